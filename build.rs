@@ -0,0 +1,30 @@
+//! Embeds the commit and the time of the build, which `GET /api/info` says.
+
+use std::{
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    // set by whoever builds it without the repository, like from a source tarball
+    let commit = std::env::var("WOL_GIT_COMMIT").ok().or_else(|| {
+        let output = Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        Some(String::from_utf8(output.stdout).ok()?.trim().to_owned())
+    });
+    if let Some(commit) = commit.filter(|commit| !commit.is_empty()) {
+        println!("cargo:rustc-env=WOL_GIT_COMMIT={commit}");
+    }
+
+    // for reproducible builds
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .or_else(|| Some(SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs()));
+    if let Some(built_at) = built_at {
+        println!("cargo:rustc-env=WOL_BUILT_AT={built_at}");
+    }
+}
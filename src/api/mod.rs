@@ -0,0 +1,7 @@
+//! The request and response bodies of the HTTP API, for clients as much as for the server.
+//!
+//! Every version has its own module, served under `/api/<version>/`. A version only ever gets new
+//! optional fields, anything else is a new version. The unversioned routes are aliases of the
+//! latest version, except that their errors aren't always JSON.
+
+pub mod v1;
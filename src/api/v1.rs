@@ -0,0 +1,1232 @@
+//! The bodies of `/api/v1/`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
+};
+
+use crate::schedule::Schedule;
+
+/// What every request under `/api/v1/` that fails answers with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// What a timed out wake was busy with when it ran out of time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<WakeStage>,
+    /// The remote site the error is from, or that couldn't be reached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
+    /// How the remote site failed to wake the host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay: Option<RelayFailure>,
+}
+
+impl ErrorResponse {
+    /// Just the error, without a stage, site or relay failure.
+    pub fn new(error: impl Into<String>) -> ErrorResponse {
+        ErrorResponse {
+            error: error.into(),
+            stage: None,
+            site: None,
+            relay: None,
+        }
+    }
+
+    pub fn with_stage(self, stage: WakeStage) -> ErrorResponse {
+        ErrorResponse {
+            stage: Some(stage),
+            ..self
+        }
+    }
+
+    pub fn with_site(self, site: String) -> ErrorResponse {
+        ErrorResponse {
+            site: Some(site),
+            ..self
+        }
+    }
+
+    pub fn with_relay(self, relay: RelayFailure) -> ErrorResponse {
+        ErrorResponse {
+            relay: Some(relay),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayFailure {
+    /// Its server (or the machine there, over ssh) couldn't be reached, or didn't answer like a
+    /// wakeonlan server.
+    Unreachable,
+    /// Its server answered with an error of its own.
+    Rejected,
+    /// ssh couldn't log in.
+    SshAuth,
+    /// Logging in and running the command took longer than the site's `timeout`.
+    SshTimeout,
+    /// The command exited with an error.
+    SshExit,
+}
+
+/// The steps of a wake, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WakeStage {
+    Starting,
+    Discovery,
+    ReverseDns,
+    NeighborRefresh,
+    /// Looking up a host that isn't found by name in DNS.
+    NameLookup,
+    Sending,
+    /// Waiting for the server of a remote site.
+    Relaying,
+    /// Waiting for the host to come up after one of its strategies.
+    Verifying,
+}
+
+/// `POST /wake`, as JSON or a form. With neither `host` nor `mac`, the default host is woken.
+/// A `mac` is used directly, a `host` is looked up in the configured and discovered hosts.
+/// Unknown fields are rejected.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WakeRequest {
+    pub host: Option<String>,
+    pub mac: Option<String>,
+    /// Resolve the host and report where the packet would go, but don't send it.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Whether to make the kernel look for a host that isn't in the neighbor table,
+    /// overriding `neighbor_refresh` from the config.
+    pub refresh: Option<bool>,
+    /// Where to post the result once the wake is done, has to be allowed by `callback_allow`.
+    pub callback_url: Option<String>,
+    /// With a callback, how many seconds to wait for the host to come up before calling back.
+    /// Without one, the response waits for it instead (at most ten minutes) and says whether
+    /// it's `online`.
+    pub wait_online: Option<u64>,
+    /// Wake during quiet hours that have `require_force`.
+    #[serde(default)]
+    pub force: bool,
+    /// Wake a host that has `require_confirmation`.
+    #[serde(default)]
+    pub confirm: bool,
+    /// Don't send anything if the host is `already_online`, overriding `skip_if_online` from the
+    /// config.
+    pub skip_if_online: Option<bool>,
+    /// How to tell that the host is up, for this wake instead of the configured `verify`. The
+    /// wake waits for the host like with `wait_online`.
+    pub verify: Option<VerifyOverride>,
+}
+
+/// The probe a wake waits for the host with, either `tcp` or `ping`. Unknown fields are rejected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyOverride {
+    /// The port to connect to.
+    pub tcp: Option<u16>,
+    #[serde(default)]
+    pub ping: bool,
+    /// How many seconds to wait for the host, at most ten minutes. Defaults to `wait_online`,
+    /// or a minute without it.
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WakeResponse {
+    /// Identifies the wake in the history and in its callback.
+    pub id: String,
+    pub host: Option<String>,
+    /// The first of `macs`.
+    pub mac: String,
+    /// Every MAC a packet was sent for (or would be, for a dry run).
+    pub macs: Vec<String>,
+    pub dry_run: bool,
+    pub destinations: Vec<Destination>,
+    /// The remote site whose server sent the packets, `None` if they were sent from here.
+    #[serde(default)]
+    pub site: Option<String>,
+    /// How the host was found by its DNS name, if it wasn't found by name otherwise.
+    #[serde(default)]
+    pub resolved: Option<ResolvedName>,
+    /// What started the wake, like `ui`, `api`, `schedule:<id>` or `sequence:<name>`.
+    #[serde(default)]
+    pub source: String,
+    /// What the host is woken for, its configured `service_url`.
+    #[serde(default)]
+    pub service_url: Option<String>,
+    /// Whether the host came up, for a wake that waited for it with `wait_online` and no
+    /// callback. `None` if it didn't wait.
+    #[serde(default)]
+    pub online: Option<bool>,
+    /// The strategy the host came up after, for a host with `strategies`.
+    #[serde(default)]
+    pub strategy: Option<String>,
+    /// Which of the host's `destinations` the packet for `mac` got out to, for a host with them.
+    #[serde(default)]
+    pub destination: Option<SocketAddr>,
+    /// How each of the host's strategies went, in the order they were tried. Empty for hosts
+    /// without any.
+    #[serde(default)]
+    pub strategies: Vec<StrategyAttempt>,
+    /// What was waited for the host with: the request's `verify`, or the configured strategy
+    /// that answered. `None` if it didn't wait, or nothing answered.
+    #[serde(default)]
+    pub probe: Option<Strategy>,
+    /// Whether the request got the result of the same wake that was in progress already, instead
+    /// of waking the host again. `id` is that wake's then.
+    #[serde(default)]
+    pub coalesced: bool,
+    /// Whether the host was up when it was last checked, at `last_probe`. Only for a check that's
+    /// no older than `online_max_age`, an older one doesn't say much.
+    #[serde(default)]
+    pub already_online: bool,
+    #[serde(default)]
+    pub last_probe: Option<DateTime<Utc>>,
+    /// Whether nothing was sent because the host is `already_online` and the wake has
+    /// `skip_if_online`.
+    #[serde(default)]
+    pub skipped: bool,
+    /// Set when discovery hasn't worked within `discovery_freshness.max_age`, so what the wake
+    /// went by may be out of date.
+    #[serde(default)]
+    pub stale_discovery: Option<StaleDiscovery>,
+}
+
+/// How long ago discovery last worked, when that's longer ago than it should be.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaleDiscovery {
+    /// `None` if it didn't work since the server started.
+    pub last_success: Option<DateTime<Utc>>,
+    /// In seconds, since the last success or since the server started.
+    pub age: u64,
+    /// Why it failed the last time, if it did.
+    pub error: Option<String>,
+}
+
+/// One of the `strategies` of a host that was tried.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyAttempt {
+    /// Like `udp to 192.168.1.255:9` or `raw on eth0`.
+    pub strategy: String,
+    /// Whether a packet for any of the MACs got out.
+    pub sent: bool,
+    /// Whether the host came up within the strategy's timeout.
+    pub online: bool,
+    /// Why it didn't wake the host.
+    pub error: Option<String>,
+    /// In seconds, sending and waiting.
+    pub elapsed: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedName {
+    /// The address of the host's name that has the MAC.
+    pub ip: IpAddr,
+    pub mac_source: MacSource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MacSource {
+    /// The address was in the neighbor table.
+    NeighborTable,
+    /// The address was in the neighbor table after asking for it.
+    NeighborRefresh,
+}
+
+/// `GET /resolve/<name>`, what a wake of the name would send its packets for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resolution {
+    /// As asked for.
+    pub name: String,
+    /// The first of `macs`.
+    pub mac: String,
+    pub macs: Vec<String>,
+    /// Where the host is at, if any of its MACs is discovered or it was found by its DNS name.
+    pub ip: Option<IpAddr>,
+    pub source: ResolveSource,
+    /// The name it was found as, like `nas.fritz.box` for `nas`.
+    pub matched: String,
+    /// How `name` was made to match, `None` if it's `matched` as it is.
+    pub normalization: Option<Normalization>,
+}
+
+/// Where a wake finds a host, in the order it looks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolveSource {
+    /// Configured in `hosts`.
+    Static,
+    /// Imported into the registry, or found in it while nothing is discovered.
+    Registry,
+    /// In the neighbor table.
+    Discovered,
+    /// Not discovered, but it has a pinned MAC.
+    Pinned,
+    /// By the addresses its name resolves to.
+    Dns,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+    IgnoringCase,
+    /// The name is a part of the one it matched.
+    Substring,
+}
+
+/// A `GET /resolve/<name>` that isn't found as any host, or as several.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotResolved {
+    pub error: String,
+    /// Names of hosts that are similar, or that it's ambiguous between.
+    pub candidates: Vec<String>,
+}
+
+/// Where a packet was sent to, and how that went.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Destination {
+    /// The MAC the packet was for.
+    pub mac: String,
+    pub address: SocketAddr,
+    /// The local address of the socket the packet left from, if it's bound.
+    pub source: Option<SocketAddr>,
+    /// The interface the packet left on, if sending was restricted to one.
+    pub interface: Option<String>,
+    /// `false` for dry runs and failed sends.
+    pub sent: bool,
+    /// How often sending was tried, `0` for dry runs.
+    pub attempts: u32,
+    /// The last error, with its causes.
+    pub error: Option<String>,
+    /// What's likely wrong, for errors that usually have the same cause.
+    #[serde(default)]
+    pub hint: Option<String>,
+    /// With `failover`, the interfaces sending failed on before `interface` was tried.
+    #[serde(default)]
+    pub skipped: Vec<SkippedInterface>,
+    /// The network was down, so it's sent again once it's back, see `GET /api/v1/queue`.
+    #[serde(default)]
+    pub queued: bool,
+    /// With `send_confirmation`, the errors the system reported for the packet after it left,
+    /// like a router saying the port is unreachable. `None` when they weren't waited for, for
+    /// broadcasts, dry runs and failed sends.
+    #[serde(default)]
+    pub reported_errors: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedInterface {
+    pub interface: String,
+    /// The last error, with its causes.
+    pub error: String,
+}
+
+/// `POST /wake/batch`, listing hosts by name, with a pattern matched against all discovered host
+/// names or with a location (or any of them together). Unknown fields are rejected.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchWakeRequest {
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    pub pattern: Option<String>,
+    /// All hosts in the location, ignoring case. Empty for the hosts without one.
+    pub location: Option<String>,
+    /// Wake during quiet hours that have `require_force`.
+    #[serde(default)]
+    pub force: bool,
+    /// Wake the hosts that have `require_confirmation` too. Otherwise the pattern and the
+    /// location leave them out, and those listed by name aren't woken.
+    #[serde(default)]
+    pub include_confirmation_required: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchWakeResponse {
+    /// `false` if at least one of the hosts didn't get its packet.
+    pub all_sent: bool,
+    /// One entry per host, in the order they were requested.
+    pub results: Vec<HostWakeResult>,
+    /// What started the wakes, like in [`WakeResponse`].
+    #[serde(default)]
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostWakeResult {
+    pub host: String,
+    /// The first of `macs`.
+    pub mac: Option<String>,
+    pub macs: Vec<String>,
+    pub destinations: Vec<Destination>,
+    /// The remote site whose server was asked to wake it, if it's in one.
+    #[serde(default)]
+    pub site: Option<String>,
+    pub sent: bool,
+    pub error: Option<String>,
+}
+
+/// An entry of `GET /hosts`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Host {
+    pub name: String,
+    /// The first of `macs`.
+    pub mac: String,
+    pub macs: Vec<String>,
+    pub source: HostSource,
+    /// The site it's in, `None` for this server's network.
+    #[serde(default)]
+    pub site: Option<String>,
+    /// Where it is, like the room, if it's configured with one.
+    #[serde(default)]
+    pub location: Option<String>,
+    /// The network interface its packets leave on, `None` if sending isn't restricted to one.
+    /// With `failover`, the one that's tried first.
+    #[serde(default)]
+    pub interface: Option<String>,
+    /// What the neighbor table says about it, `None` if it isn't in there or the discovery
+    /// backend doesn't say.
+    #[serde(default)]
+    pub state: Option<NeighborState>,
+    /// When discovery last saw it active, `None` if it hasn't since the server started.
+    #[serde(default)]
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Whether it was up when it was last checked, `None` if it wasn't since the server started.
+    #[serde(default)]
+    pub online: Option<bool>,
+    /// `None` if it hasn't been woken since the server started.
+    pub last_wake: Option<LastWake>,
+    /// `None` if it was never woken.
+    pub stats: Option<WakeStats>,
+    /// The MAC it's expected to have, if it's pinned.
+    #[serde(default)]
+    pub pinned_mac: Option<String>,
+    /// Discovery found it, but not with its pinned MAC, so wakes are refused unless forced.
+    #[serde(default)]
+    pub mac_mismatch: bool,
+    /// It's only woken with `confirm`, and the page asks before it wakes it.
+    #[serde(default)]
+    pub require_confirmation: bool,
+}
+
+/// The hosts of one site, from `GET /hosts?group=site`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SiteHosts {
+    /// `None` for this server's network.
+    pub site: Option<String>,
+    /// Whether its hosts are woken by the server there.
+    pub remote: bool,
+    pub hosts: Vec<Host>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostSource {
+    Static,
+    Discovered,
+    /// Discovered, named like it announced itself with SSDP.
+    Ssdp,
+    /// Not discovered, but it has a pinned MAC.
+    Pinned,
+}
+
+/// The most recent wake of a host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastWake {
+    /// Shared by the packets for all MACs of one wake.
+    pub id: String,
+    pub at: DateTime<Utc>,
+    /// The client that asked for the wake.
+    pub requester: Option<IpAddr>,
+    /// Who authenticated for it, if that is required.
+    pub principal: Option<String>,
+    /// What started the wake, like in [`WakeResponse`].
+    #[serde(default)]
+    pub source: String,
+    pub outcome: WakeOutcome,
+    /// How calling back went, if the wake asked for it.
+    pub callback: Option<Delivery>,
+    /// The host's `post_wake_commands` that were run after the wake, in order.
+    #[serde(default)]
+    pub hooks: Vec<HookRun>,
+    /// The strategy the host came up after, for a host with `strategies`.
+    #[serde(default)]
+    pub strategy: Option<String>,
+    /// Which of the host's `destinations` the packet got out to, for a host with them.
+    #[serde(default)]
+    pub destination: Option<SocketAddr>,
+    /// What was waited for the host with, like in [`WakeResponse`].
+    #[serde(default)]
+    pub probe: Option<Strategy>,
+    /// The requests that asked for the same wake while it was in progress, and got its result.
+    #[serde(default)]
+    pub coalesced: Vec<CoalescedRequest>,
+}
+
+/// A wake of one MAC in `GET /api/v1/history`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub mac: String,
+    /// The host it was woken by the name of, if it was.
+    pub host: Option<String>,
+    #[serde(flatten)]
+    pub wake: LastWake,
+}
+
+/// A request that was coalesced into a wake that was in progress.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoalescedRequest {
+    pub at: DateTime<Utc>,
+    pub requester: Option<IpAddr>,
+    pub principal: Option<String>,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WakeOutcome {
+    Sent,
+    Failed,
+    /// Waiting for the network in the send queue, it ends up sent, failed or cancelled.
+    Queued,
+    Cancelled,
+    /// Not sent because of the quiet hours.
+    Suppressed,
+    /// Not sent because its schedule was paused when it was due.
+    Paused,
+    /// Not sent because it was automatic, and discovery was stale.
+    StaleDiscovery,
+}
+
+/// How calling back went, kept with the wake in the history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delivery {
+    pub url: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// The last error, with its causes.
+    pub error: Option<String>,
+}
+
+/// How one of the `post_wake_commands` went. Failed ones aren't run again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookRun {
+    pub command: String,
+    /// `None` if it didn't exit by itself, like when it was killed after the timeout.
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    /// In seconds.
+    pub duration: f64,
+    /// Why it failed.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+/// `GET /hosts/<name>/status`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostStatus {
+    pub host: String,
+    pub macs: Vec<String>,
+    /// Where it was last seen, `None` if the neighbor table doesn't know it.
+    pub ip: Option<IpAddr>,
+    /// `None` if it couldn't be checked.
+    pub online: Option<bool>,
+    /// What answered, if it could be checked.
+    pub strategy: Option<Strategy>,
+}
+
+/// What `wakeonlan wake`, `check` and `list` print with `--output json`, a single document on
+/// stdout. The command exits with its `exit_code`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CliReport {
+    /// `wake`, `check` or `list`.
+    pub command: String,
+    /// 0 if it worked, otherwise the one of the `error`'s kind.
+    pub exit_code: u8,
+    /// How long the command took, in seconds.
+    pub elapsed: f64,
+    /// For `wake`. With `--wait`, `online` says whether the host came up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wake: Option<WakeResponse>,
+    /// For `check`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<HostStatus>,
+    /// For `list`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hosts: Option<Vec<Host>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<CliError>,
+}
+
+/// Why a command failed, with the error like the API answers with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CliError {
+    pub kind: CliErrorKind,
+    #[serde(flatten)]
+    pub details: ErrorResponse,
+}
+
+impl CliError {
+    pub fn new(kind: CliErrorKind, error: String) -> CliError {
+        CliError {
+            kind,
+            details: ErrorResponse::new(error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CliErrorKind {
+    /// The host is down, didn't come up in time or couldn't be checked.
+    Offline,
+    /// The host isn't found, or nothing tells which MAC it has.
+    Resolution,
+    /// No packet got out.
+    Send,
+    /// The wake isn't allowed, like during quiet hours or without `confirm`. Nothing was sent.
+    Refused,
+    /// The configuration is invalid.
+    Config,
+}
+
+impl CliErrorKind {
+    /// What a command that failed like this exits with, these never change.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            CliErrorKind::Offline => 1,
+            CliErrorKind::Resolution => 2,
+            CliErrorKind::Send | CliErrorKind::Refused => 3,
+            CliErrorKind::Config => 4,
+        }
+    }
+}
+
+/// How waking a host went so far. A wake is only verified (or timed out) if something waited for
+/// the host to come up, like `wait-online`, a callback with `wait_online` or a wake sequence.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WakeStats {
+    /// Packets sent.
+    pub attempts: u64,
+    /// Wakes after which the host came up.
+    pub verified: u64,
+    /// Wakes after which the host didn't come up in time.
+    pub timeouts: u64,
+    /// Of the wakes that were verified or timed out, how many were verified.
+    pub success_rate: Option<f64>,
+    /// Over the most recent wakes, in seconds from the wake.
+    pub average_time_to_online: Option<f64>,
+    pub median_time_to_online: Option<f64>,
+    pub p90_time_to_online: Option<f64>,
+}
+
+/// `GET /hosts/<name>/stats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostStats {
+    pub host: String,
+    /// Those of the MAC that was woken most often.
+    pub stats: WakeStats,
+    pub macs: Vec<MacStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacStats {
+    pub mac: String,
+    #[serde(flatten)]
+    pub stats: WakeStats,
+}
+
+/// `GET /hosts/<name>/wait-online`, once the host answered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaitResponse {
+    pub host: String,
+    pub online: bool,
+    /// How long it took until it answered, in seconds.
+    pub elapsed: f64,
+    pub strategy: Strategy,
+}
+
+/// A text message to `GET /ws`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchRequest {
+    pub action: WatchAction,
+    /// Chosen by the client, the events of the watch have it.
+    pub id: String,
+    /// For `wake`, what to wake like `POST /wake`. `wait_online` is how many seconds the host
+    /// is watched for coming up (60 by default, at most 600), a callback isn't supported.
+    #[serde(flatten)]
+    pub wake: WakeRequest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchAction {
+    /// Wakes the host and watches it come up.
+    Wake,
+    /// Stops the watch with the id.
+    Cancel,
+}
+
+/// A text message from `GET /ws` about a watch. Every watch ends with `online`, `timeout`,
+/// `cancelled` or `error`, or with `sent` for a dry run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WatchEvent {
+    /// The packets went out, like `POST /wake` answers.
+    Sent {
+        id: String,
+        wake: Box<WakeResponse>,
+    },
+    /// The host is probed until it answers, for at most `timeout` seconds.
+    Probing {
+        id: String,
+        timeout: u64,
+    },
+    /// `elapsed` is the seconds since the wake was asked for.
+    Online {
+        id: String,
+        elapsed: f64,
+        strategy: Strategy,
+    },
+    Timeout {
+        id: String,
+        elapsed: f64,
+    },
+    Cancelled {
+        id: String,
+    },
+    /// `status` is what `POST /wake` would have answered with. `id` is `None` if the message
+    /// didn't say.
+    Error {
+        id: Option<String>,
+        status: u16,
+        #[serde(flatten)]
+        error: ErrorResponse,
+    },
+}
+
+/// Something the server did, from `GET /api/v1/events`. Every wake starts with `wake_requested` and
+/// ends with `wake_sent` or `wake_failed`, with the same `id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Before the host is looked up. `source` is what asked, like in [`WakeResponse`].
+    WakeRequested {
+        id: String,
+        host: Option<String>,
+        mac: Option<String>,
+        source: String,
+    },
+    /// The packets went out, or would have for a dry run.
+    WakeSent {
+        id: String,
+        host: Option<String>,
+        macs: Vec<String>,
+        destinations: Vec<Destination>,
+        site: Option<String>,
+        dry_run: bool,
+    },
+    WakeFailed {
+        id: String,
+        host: Option<String>,
+        error: String,
+    },
+    /// The host is probed until it answers, for whoever waits for it.
+    VerificationStarted {
+        macs: Vec<String>,
+        strategies: Vec<Strategy>,
+    },
+    /// `seconds` since it was woken, if it was woken by this server.
+    HostOnline {
+        host: Option<String>,
+        macs: Vec<String>,
+        strategy: Strategy,
+        seconds: Option<f64>,
+    },
+    /// A woken host didn't come up before whoever waited for it gave up.
+    VerificationTimedOut { host: String, macs: Vec<String> },
+    /// The wake of it follows.
+    ScheduleFired { schedule: String, host: String },
+    /// It was due, but it's paused.
+    ScheduleSkipped { schedule: String, host: String },
+}
+
+impl Event {
+    /// Its `event`, like `wake_sent`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::WakeRequested { .. } => "wake_requested",
+            Event::WakeSent { .. } => "wake_sent",
+            Event::WakeFailed { .. } => "wake_failed",
+            Event::VerificationStarted { .. } => "verification_started",
+            Event::HostOnline { .. } => "host_online",
+            Event::VerificationTimedOut { .. } => "verification_timed_out",
+            Event::ScheduleFired { .. } => "schedule_fired",
+            Event::ScheduleSkipped { .. } => "schedule_skipped",
+        }
+    }
+}
+
+/// An [`Event`] the way `GET /api/v1/events` sends it, with when it happened. `seq` counts up from
+/// 0 since the server started, a gap is events the subscriber missed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishedEvent {
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// `GET /api/v1/network`, the interfaces of the server and where the packets it sends leave on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Network {
+    pub interfaces: Vec<NetworkInterface>,
+    /// Every destination packets are sent to, with the interface if it's a different one for
+    /// some hosts or sites. With `failover`, there's one for each of its interfaces, in order.
+    pub routes: Vec<SendRoute>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub index: u32,
+    pub mac: Option<String>,
+    /// Whether it's up, as set by `ip link set up`.
+    pub up: bool,
+    /// Whether it has a link, which it hasn't if the cable is unplugged.
+    pub running: bool,
+    pub addresses: Vec<NetworkAddress>,
+    /// Whether any of the `routes` leave on it.
+    pub used: bool,
+    /// How often sending on it failed since the server started, so the next interface of
+    /// `failover` was tried.
+    #[serde(default)]
+    pub failovers: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkAddress {
+    pub address: IpAddr,
+    pub prefix: u8,
+    pub netmask: IpAddr,
+    /// The directed broadcast of the subnet, `None` for IPv6 and `/31` or `/32`.
+    pub broadcast: Option<Ipv4Addr>,
+}
+
+/// Where packets to a destination leave, as the system routes them right now.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendRoute {
+    /// The site it's for, `None` for this server's network.
+    pub site: Option<String>,
+    /// The host it's for, if the host has an interface of its own.
+    pub host: Option<String>,
+    pub destination: SocketAddr,
+    /// The interface sending is restricted to, if it is.
+    pub interface: Option<String>,
+    /// The address the host or site has its packets sent from, one of the `addresses` of the
+    /// interfaces.
+    #[serde(default)]
+    pub bound_to: Option<IpAddr>,
+    /// The interface the packets leave on, `None` if that can't be told.
+    pub leaves_on: Option<String>,
+    /// The address they're sent from.
+    pub source: Option<IpAddr>,
+    /// Why it can't be told where they leave, which is usually why sending fails too.
+    pub error: Option<String>,
+}
+
+/// `GET /healthz`, which is answered whenever the server is up. What it finds wrong is only
+/// reported, it's not a reason to restart the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Health {
+    /// The entries discovery found right now.
+    pub discovered: usize,
+    /// The hosts in the registry.
+    pub configured: usize,
+    /// Like an empty neighbor table, failing discovery or a failed self-test.
+    pub problems: Vec<String>,
+    /// How the `self_test` at startup went, `None` if it isn't configured.
+    #[serde(default)]
+    pub self_test: Option<SelfTest>,
+}
+
+/// `GET /api/v1/info`, which build is running and how it's set up, for telling servers apart.
+/// Nothing in it is secret.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Info {
+    pub version: String,
+    /// The git commit it was built from, if that was known when it was built.
+    pub commit: Option<String>,
+    pub built_at: Option<DateTime<Utc>>,
+    /// What's built in, like `server`, and `raw-l2` where raw frames can be sent.
+    pub features: Vec<String>,
+    /// The configured `discovery`.
+    pub discovery: Vec<Backend>,
+    /// How many hosts are configured in `hosts`.
+    pub static_hosts: usize,
+    /// Seconds since the server started.
+    pub uptime: f64,
+}
+
+/// `GET /api/v1/config` and `wakeonlan --print-config`, the settings the server runs with, for
+/// telling why it does what it does. Tokens, passwords and the SNMP community are `"<redacted>"`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EffectiveConfig {
+    /// By their key, written like in the config file.
+    pub settings: BTreeMap<String, Setting>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Setting {
+    pub value: serde_json::Value,
+    /// The config file or a `WOL_*` environment variable, or neither.
+    pub source: Source,
+}
+
+/// `GET /api/v1/stats`, how many entries there are of what the server remembers in memory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryStats {
+    /// The last wake of each MAC.
+    pub last_wakes: Size,
+    /// The wakes in `GET /api/v1/history`.
+    pub history: Size,
+    /// The wakes waited to be verified, for the stats of their hosts.
+    pub unverified: Size,
+    /// The started sequences.
+    pub jobs: Size,
+    /// The IP each discovered name last had.
+    pub known_ips: Size,
+    /// When each discovered MAC was last seen active.
+    pub last_seen: Size,
+    /// Whether each MAC was up when it was last checked.
+    pub last_probes: Size,
+    /// The site each MAC was discovered in.
+    pub discovered_sites: Size,
+    /// The sources the relay rate limit counts packets of.
+    pub relay_sources: Size,
+    pub send_queue: Size,
+    pub wake_tokens: Size,
+    /// The wakes in progress that others asking for the same wake wait for.
+    pub in_flight: Size,
+    /// The hosts that are waited for.
+    pub probe_loops: Size,
+    /// The events for `/debug/logs`.
+    pub logs: Size,
+    /// The names wakes didn't find, which aren't looked for again for `not_found_ttl`.
+    pub not_found: NotFoundStats,
+    pub events: EventStats,
+}
+
+/// The subscribers of `GET /api/v1/events`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventStats {
+    pub subscribers: usize,
+    /// Since the server started.
+    pub published: u64,
+    /// How many events subscribers missed because they didn't keep up, since the server
+    /// started.
+    pub dropped: u64,
+    /// The events that wait for the slowest subscriber, limited by `limits.events`.
+    pub buffered: Size,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotFoundStats {
+    pub entries: Size,
+    /// How often a wake was answered from them since the server started.
+    pub hits: u64,
+    /// The ones asked for most often first.
+    pub names: Vec<NotFoundName>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotFoundName {
+    pub name: String,
+    /// How often a wake of it was answered without looking for it, since the server started.
+    pub hits: u64,
+    /// Who asked for it last, of those answered without looking.
+    pub last_requester: Option<IpAddr>,
+    /// Seconds until it's looked for again.
+    pub expires_in: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Size {
+    pub entries: usize,
+    /// How many entries there can be at most, `None` for what's forgotten once it's done or
+    /// expired.
+    pub limit: Option<usize>,
+}
+
+/// Whether an empty datagram could be sent to the `self_test` destination at startup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfTest {
+    pub destination: SocketAddr,
+    pub at: DateTime<Utc>,
+    pub passed: bool,
+    pub error: Option<String>,
+    /// What to do about the error, if that's known.
+    pub hint: Option<String>,
+}
+
+/// An entry of `GET /api/v1/send-stats`, what the server's sockets sent to a destination since it
+/// started, for finding out whether the packets of a wake that didn't work left at all.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendStats {
+    pub destination: SocketAddr,
+    /// The datagrams the kernel took, failed sends aren't counted.
+    pub datagrams: u64,
+    pub bytes: u64,
+    /// How often sending to it failed.
+    pub failures: u64,
+    pub last_sent: Option<DateTime<Utc>>,
+    /// The local address the last datagram was sent from, to look for in a capture.
+    pub last_source: Option<SocketAddr>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+/// `GET /api/v1/neighbors`, what each discovery backend read before it's merged, including what it
+/// skipped, for finding out why a host isn't discovered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Neighbors {
+    pub neighbors: Vec<Neighbor>,
+    /// The backends that couldn't read anything.
+    pub errors: Vec<NeighborTableError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Neighbor {
+    /// The backend that read it, like `ip-neigh`.
+    pub source: String,
+    /// The site whose backend it is, `None` for this server's network.
+    pub site: Option<String>,
+    /// The name the backend has for it, if it has one other than the address.
+    pub name: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub mac: Option<String>,
+    pub interface: Option<String>,
+    pub state: Option<NeighborState>,
+    pub vlan: Option<u16>,
+    /// Why it isn't used, `None` if it is.
+    pub skipped: Option<SkipReason>,
+    /// What's wrong with the line, for `invalid` ones.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// It has no MAC, because it's still being resolved or failed to be.
+    Incomplete,
+    /// A zero or broadcast MAC, which can't be woken.
+    UnusableMac,
+    /// The line isn't an entry the parser understands.
+    Invalid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NeighborTableError {
+    pub source: String,
+    pub site: Option<String>,
+    pub error: String,
+}
+
+/// An entry of `GET /schedules`, `POST /schedules` takes just the [`Schedule`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledWake {
+    pub id: String,
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    /// Set with `POST /schedules/<id>/pause`, then it doesn't wake the host until it's resumed.
+    #[serde(default)]
+    pub paused: bool,
+    /// When it's due next, `None` if never or while it's paused.
+    pub next: Option<DateTime<Utc>>,
+}
+
+/// An entry of `GET /api/v1/queue`, a wake waiting for the network to come back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedWake {
+    /// The id of the last wake of the MAC, wakes of a MAC that's queued already are merged.
+    pub id: String,
+    pub host: Option<String>,
+    pub mac: String,
+    pub destinations: Vec<SocketAddr>,
+    /// When the last wake of it was asked for, it's given up on `max_age` after that.
+    pub requested_at: DateTime<Utc>,
+    /// How often it was sent again so far.
+    pub attempts: u32,
+    pub next_attempt: DateTime<Utc>,
+    /// Why the wake or its last attempt failed.
+    pub error: Option<String>,
+}
+
+/// The optional body of `POST /wake-sequence/<name>`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SequenceRequest {
+    /// Overrides `continue_on_failure` of the sequence.
+    pub continue_on_failure: Option<bool>,
+}
+
+/// A wake sequence that was started with `POST /wake-sequence/<name>`, from `GET /jobs/<id>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequenceJob {
+    pub id: String,
+    /// The name of the sequence.
+    pub sequence: String,
+    pub status: JobStatus,
+    /// The index of the step that's running, `None` once the job is done.
+    pub current_step: Option<usize>,
+    pub continue_on_failure: bool,
+    pub started: DateTime<Utc>,
+    pub finished: Option<DateTime<Utc>>,
+    pub steps: Vec<StepStatus>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    /// At least one step failed.
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepStatus {
+    pub host: String,
+    pub state: StepState,
+    pub started: Option<DateTime<Utc>>,
+    pub finished: Option<DateTime<Utc>>,
+    /// Why it failed.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepState {
+    Pending,
+    Waking,
+    /// Woken, waiting for it to be ready.
+    Waiting,
+    Ready,
+    Failed,
+    /// Not started because a step before it failed.
+    Skipped,
+}
+
+/// A way of asking a host whether it's there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    /// An ARP who-has for its IPv4 address, which every NIC that's up answers. Needs `CAP_NET_RAW`.
+    Arp,
+    /// Connecting to a TCP port, where being refused still means the host is up.
+    Tcp(u16),
+    /// An ICMP echo request, from an unprivileged ICMP socket or a raw one with `CAP_NET_RAW`.
+    Icmp,
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strategy::Arp => f.write_str("arp"),
+            Strategy::Tcp(port) => write!(f, "tcp:{port}"),
+            Strategy::Icmp => f.write_str("icmp"),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    /// `arp`, `icmp` or `tcp:<port>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "arp" => Ok(Strategy::Arp),
+            "icmp" => Ok(Strategy::Icmp),
+            _ => s
+                .strip_prefix("tcp:")
+                .and_then(|port| port.parse().ok())
+                .map(Strategy::Tcp)
+                .ok_or_else(|| {
+                    format!("invalid verify strategy `{s}`, expected `arp`, `icmp` or `tcp:<port>`")
+                }),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Strategy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Strategy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// The state of an entry of the neighbor table, like `ip neigh` shows it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NeighborState {
+    /// It answered recently.
+    Reachable,
+    /// It answered once, but not recently.
+    Stale,
+    /// It's used while stale, the kernel will check on it soon.
+    Delay,
+    /// The kernel is checking on it.
+    Probe,
+    /// It stopped answering.
+    Failed,
+    /// Added by hand, it never changes.
+    Permanent,
+    /// The kernel is asking for its MAC.
+    Incomplete,
+    /// The link doesn't need neighbor discovery.
+    Noarp,
+}
+
+/// The backends that can be chosen in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Running `arp -n`.
+    Arp,
+    /// Reading `/proc/net/arp`, which only has IPv4 neighbors but needs no tools (Linux only).
+    ProcNetArp,
+    /// Running `ip neigh`, which has the IPv6 neighbors too.
+    IpNeigh,
+    /// Searching for devices that announce themselves with SSDP, named like they call themselves.
+    /// Their MACs come from the neighbor table, like the default backend reads it.
+    Ssdp,
+}
+
+/// Where the value of a setting came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Default,
+    File,
+    Env,
+}
@@ -0,0 +1,180 @@
+//! A client for the server's `/api/v1/`, with the same types the server answers with.
+//!
+//! It's blocking, built on reqwest's blocking client, so with async code create and call it in
+//! `tokio::task::spawn_blocking`. It speaks `https://` as well as `http://`.
+//!
+//! ```no_run
+//! use wakeonlan::client::WolClient;
+//!
+//! let client = WolClient::new("http://wol.lan:8090").with_token("secret");
+//! let woken = client.wake("nas")?;
+//! println!("sent to {}", woken.mac);
+//! # Ok::<(), wakeonlan::client::Error>(())
+//! ```
+
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fmt, time::Duration};
+
+use crate::{
+    api::v1::{ErrorResponse, Host, HostStatus, WaitResponse, WakeRequest, WakeResponse},
+    secret::Secret,
+    MacAddress,
+};
+
+/// How long a request may take, from connecting until the whole answer is read, unless it's
+/// changed with [`WolClient::with_timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Why a request didn't get the answer it asked for.
+#[derive(Debug)]
+pub enum Error {
+    /// The server couldn't be reached, or didn't answer with HTTP.
+    Request(reqwest::Error),
+    /// The server answered with an error, like a 404 for a host it didn't find.
+    Api { status: u16, error: ErrorResponse },
+    /// The server's answer isn't what the types of [`crate::api::v1`] say.
+    Body {
+        status: u16,
+        error: serde_json::Error,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Request(e) => write!(f, "request failed: {e}"),
+            Error::Api { status, error } => write!(f, "{status}: {}", error.error),
+            Error::Body { status, error } => write!(f, "unexpected answer with {status}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Request(error) => Some(error),
+            Error::Body { error, .. } => Some(error),
+            Error::Api { .. } => None,
+        }
+    }
+}
+
+/// Talks to a server at a base URL like `http://wol.lan:8090`.
+#[derive(Debug, Clone)]
+pub struct WolClient {
+    client: Client,
+    base: String,
+    token: Option<Secret<String>>,
+    timeout: Duration,
+}
+
+impl WolClient {
+    pub fn new(base: impl Into<String>) -> Self {
+        let base = base.into();
+        Self {
+            client: Client::new(),
+            base: base.trim_end_matches('/').to_owned(),
+            token: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sends the server's `token` with every request. It's redacted in `Debug`.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(Secret::new(token.into()));
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Wakes a host by its name, like `POST /wake` with `{"host": ...}`.
+    pub fn wake(&self, host: &str) -> Result<WakeResponse, Error> {
+        self.wake_with(&WakeRequest {
+            host: Some(host.to_owned()),
+            ..WakeRequest::default()
+        })
+    }
+
+    /// Wakes a MAC, like `POST /wake` with `{"mac": ...}`.
+    pub fn wake_mac(&self, mac: MacAddress) -> Result<WakeResponse, Error> {
+        self.wake_with(&WakeRequest {
+            mac: Some(mac.to_string()),
+            ..WakeRequest::default()
+        })
+    }
+
+    /// Wakes with every option of `POST /wake`. A wake that waits for the host (with
+    /// `wait_online` or `verify`) needs a timeout longer than that.
+    pub fn wake_with(&self, request: &WakeRequest) -> Result<WakeResponse, Error> {
+        self.post("/wake", request)
+    }
+
+    /// `GET /hosts`, the configured and discovered hosts.
+    pub fn hosts(&self) -> Result<Vec<Host>, Error> {
+        self.get("/hosts", self.timeout)
+    }
+
+    /// `GET /hosts/<host>/status`, whether it's up right now.
+    pub fn status(&self, host: &str) -> Result<HostStatus, Error> {
+        let path = format!("/hosts/{}/status", path_segment(host));
+        self.get(&path, self.timeout)
+    }
+
+    /// `GET /hosts/<host>/wait-online`, blocks until the host answers. One that doesn't within
+    /// `timeout` (at most ten minutes) is an [`Error::Api`] with a 504.
+    pub fn wait_online(&self, host: &str, timeout: Duration) -> Result<WaitResponse, Error> {
+        let path = format!(
+            "/hosts/{}/wait-online?timeout={}",
+            path_segment(host),
+            timeout.as_secs().max(1)
+        );
+        self.get(&path, self.timeout + timeout)
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str, timeout: Duration) -> Result<T, Error> {
+        let url = format!("{}/api/v1{path}", self.base);
+        self.send(self.client.get(url).timeout(timeout))
+    }
+
+    fn post<T: DeserializeOwned>(&self, path: &str, body: &impl Serialize) -> Result<T, Error> {
+        let url = format!("{}/api/v1{path}", self.base);
+        self.send(self.client.post(url).json(body).timeout(self.timeout))
+    }
+
+    /// Sends the request with the token, and reads the answer as a `T`, or an error if it's not
+    /// a success.
+    fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        let request = match &self.token {
+            Some(token) => request.bearer_auth(token.as_str()),
+            None => request,
+        };
+        let response = request.send().map_err(Error::Request)?;
+        let status = response.status().as_u16();
+        let body = response.bytes().map_err(Error::Request)?;
+        if (200..300).contains(&status) {
+            serde_json::from_slice(&body).map_err(|error| Error::Body { status, error })
+        } else {
+            match serde_json::from_slice(&body) {
+                Ok(error) => Err(Error::Api { status, error }),
+                Err(error) => Err(Error::Body { status, error }),
+            }
+        }
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters, for a host name in a path.
+fn path_segment(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("%{b:02X}"));
+        }
+    }
+    escaped
+}
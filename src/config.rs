@@ -0,0 +1,2300 @@
+//! Configuration, from a config file and `WOL_*` environment variables.
+//!
+//! Every setting can come from either, the config file wins when both set it. Which one it came
+//! from is kept, see [`Config::effective`].
+
+use chrono::NaiveTime;
+use eyre::{bail, Context};
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    api::v1::{EffectiveConfig, Setting},
+    discovery::{parse_mac_addr, Backend, DEFAULT_BACKENDS},
+    retry::RetryPolicy,
+    schedule::Schedule,
+    secret::{Secret, REDACTED},
+    verify::Strategy,
+    MacAddress, SecureOnPassword,
+};
+
+pub use crate::api::v1::Source;
+
+pub const DEFAULT_CONFIG_PATH: &str = "wakeonlan.toml";
+pub const DEFAULT_LISTEN: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 8090);
+pub const DEFAULT_BROADCAST: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), 9);
+pub const DEFAULT_WAKE_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_VERIFY: [Strategy; 2] = [Strategy::Arp, Strategy::Icmp];
+pub const DEFAULT_VERIFY_TIMEOUT: Duration = Duration::from_secs(2);
+pub const DEFAULT_NOT_FOUND_TTL: Duration = Duration::from_secs(30);
+pub const DEFAULT_ONLINE_MAX_AGE: Duration = Duration::from_secs(60);
+pub const DEFAULT_LOG_BUFFER: usize = 1000;
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 8;
+pub const DEFAULT_INDEX_PAGE: &str = "index.html";
+
+/// Settings are serialized like they'd be written in the config file, for showing them. Secrets
+/// are [`Secret`]s, so they're redacted there as well as in `Debug`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    /// The addresses the HTTP server listens on, all of them serve the same thing.
+    pub listen: Vec<SocketAddr>,
+    /// The host woken by a `POST /wake` that doesn't say which one (like the button on the
+    /// page), a name or a MAC. Without one, such a wake is rejected.
+    pub default_host: Option<String>,
+    /// Where magic packets are sent to.
+    pub broadcast: SocketAddr,
+    /// If set, magic packets only leave on this network interface, unless the host has its own.
+    pub interface: Option<String>,
+    /// Without an `interface`, the interfaces packets are sent on in this order, moving on to
+    /// the next whenever sending fails on one.
+    pub failover: Vec<String>,
+    /// If set, mutating requests need to present this token.
+    pub token: Option<Secret<String>>,
+    /// If set, signed wake links can be made, this is what they're signed with.
+    pub url_secret: Option<Secret<String>>,
+    /// Hosts that are known without having to discover them.
+    /// Only used to start the registry if there's no registry file yet.
+    #[serde(serialize_with = "serialize_hosts")]
+    pub hosts: Vec<StaticHost>,
+    /// Where the host registry is saved, it's only kept in memory without one.
+    pub registry: Option<PathBuf>,
+    /// The MAC a discovered host is expected to have, by its name. A wake is refused when
+    /// discovery finds it with another one, and sent to this one when it doesn't find it.
+    #[serde(serialize_with = "serialize_pinned_macs")]
+    pub pinned_macs: BTreeMap<String, MacAddress>,
+    /// How long a `POST /wake` may take in total before it's answered with a timeout.
+    #[serde(serialize_with = "serialize_secs")]
+    pub wake_timeout: Duration,
+    /// How failed sends are retried.
+    #[serde(serialize_with = "serialize_retry")]
+    pub retry: RetryPolicy,
+    /// How many hosts of a batch wake are woken at the same time.
+    pub batch_concurrency: usize,
+    /// How hosts are discovered, what all of them find is used.
+    pub discovery: Vec<Backend>,
+    /// How to check whether a host is up, the first strategy that can be used here is used.
+    pub verify: Vec<Strategy>,
+    /// How long a host has to answer a check.
+    #[serde(serialize_with = "serialize_secs")]
+    pub verify_timeout: Duration,
+    /// If not empty, only clients in these networks may use mutating endpoints.
+    pub allow_from: Vec<IpNet>,
+    /// If not empty, only clients in these networks may use read endpoints.
+    pub read_allow_from: Vec<IpNet>,
+    /// Refuses every mutating endpoint and every other way of waking hosts, for a dashboard
+    /// that anyone may look at.
+    pub read_only: bool,
+    /// Reverse proxies whose `X-Forwarded-For` is believed to find the real client.
+    pub trusted_proxies: Vec<IpNet>,
+    /// If set, the trusted proxies authenticate users, and say who it is in a header.
+    pub proxy_auth: Option<ProxyAuthConfig>,
+    /// If set, magic packets received over UDP are sent on to other networks.
+    pub relay: Option<RelayConfig>,
+    /// If set, a service on a host that sleeps is served through this server, which wakes it.
+    pub proxy: Option<ProxyConfig>,
+    /// Places with their own network, the configured hosts say which one they're in.
+    pub sites: Vec<Site>,
+    /// When a host isn't in the neighbor table, try to get it back in there and look again.
+    pub neighbor_refresh: bool,
+    /// How long a name that wasn't found stays not found without discovering hosts again, unless
+    /// they change or a wake says `refresh`. Zero looks for it every time.
+    #[serde(serialize_with = "serialize_secs")]
+    pub not_found_ttl: Duration,
+    /// How long the host being up when it was last checked says that it's `already_online` for a
+    /// wake. Zero never does.
+    #[serde(serialize_with = "serialize_secs")]
+    pub online_max_age: Duration,
+    /// Don't send anything for a wake of a host that's `already_online`, unless the wake says
+    /// otherwise.
+    pub skip_if_online: bool,
+    /// A network that's swept during a neighbor refresh, for hosts the server never saw before.
+    pub neighbor_sweep: Option<IpNet>,
+    /// URL prefixes that wake requests may ask to be called back at, callbacks are refused
+    /// without any.
+    pub callback_allow: Vec<String>,
+    /// If set, a Telegram bot takes wake requests too.
+    pub telegram: Option<TelegramConfig>,
+    /// Hosts that are woken on a schedule.
+    /// Only used to start the schedules if there's no schedules file yet.
+    pub schedules: Vec<Schedule>,
+    /// Where the schedules are saved, they're only kept in memory without one.
+    pub schedules_file: Option<PathBuf>,
+    /// Where the one-time wake tokens are saved, they're only kept in memory without one.
+    pub wake_tokens_file: Option<PathBuf>,
+    /// Hosts that are woken one after the other, each once the one before is ready.
+    pub sequences: Vec<WakeSequence>,
+    /// If set, every wake attempt is appended to a file.
+    pub audit: Option<AuditConfig>,
+    /// If set, discovered hosts without a reverse DNS name are asked for their NetBIOS name.
+    pub netbios: Option<NetbiosConfig>,
+    /// If set, the MACs a managed switch has seen on its ports are discovered too.
+    pub snmp: Option<SnmpConfig>,
+    /// If set, wakes that fail because the network is down are sent again once it's back.
+    pub send_queue: Option<SendQueueConfig>,
+    /// If set, the errors the system reports for packets sent to unicast addresses after they
+    /// left are waited for, and they're in the wake's destinations. Only on Linux.
+    pub send_confirmation: Option<SendConfirmationConfig>,
+    /// If set, the hosts and their last wakes are published to an MQTT broker as retained
+    /// messages.
+    pub mqtt: Option<MqttConfig>,
+    /// How the `post_wake_commands` of the configured hosts are run.
+    pub hooks: HooksConfig,
+    /// How much the server keeps in memory at most.
+    pub limits: LimitsConfig,
+    /// If set, scheduled wakes don't happen during these hours.
+    pub quiet_hours: Option<QuietHoursConfig>,
+    /// If set, automatic wakes wait for discovery to have worked recently.
+    pub discovery_freshness: Option<DiscoveryFreshnessConfig>,
+    /// If set, whether broadcasts can be sent at all is tried once at startup.
+    pub self_test: Option<SelfTestConfig>,
+    /// How many recent log events are kept for `/debug/logs`.
+    pub log_buffer: usize,
+    /// The least severe level of the events that are kept for `/debug/logs`.
+    #[serde(serialize_with = "serialize_level")]
+    pub log_buffer_level: tracing::Level,
+    /// The page served at `/` if the file exists, the built-in one is served otherwise.
+    /// Next to the config file unless it's set.
+    pub index_page: PathBuf,
+    /// Where the settings that aren't defaults came from, by their key.
+    #[serde(skip)]
+    pub sources: BTreeMap<String, Source>,
+}
+
+/// The keys of the secrets, which aren't shown in the problems with a config file either.
+const SECRETS: [&str; 4] = ["token", "url_secret", "password", "community"];
+
+impl Config {
+    /// Every setting with its value and where that came from, serialized like in the config
+    /// file. Tokens, passwords and the SNMP community are redacted.
+    pub fn effective(&self) -> EffectiveConfig {
+        let serde_json::Value::Object(values) = serde_json::to_value(self).unwrap_or_default()
+        else {
+            return EffectiveConfig::default();
+        };
+        let settings = (values.into_iter())
+            .map(|(key, value)| {
+                let source = self.sources.get(&key).copied().unwrap_or(Source::Default);
+                (key, Setting { value, source })
+            })
+            .collect();
+        EffectiveConfig { settings }
+    }
+}
+
+/// A value of a config file like it's written there, but with any secret in it redacted.
+fn shown_value(key: &str, value: &toml::Value) -> String {
+    fn redact(key: &str, value: &mut toml::Value) {
+        match value {
+            _ if SECRETS.contains(&key) => *value = REDACTED.into(),
+            toml::Value::Table(values) => {
+                for (key, value) in values {
+                    redact(key, value);
+                }
+            }
+            toml::Value::Array(values) => {
+                for value in values {
+                    redact("", value);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut value = value.clone();
+    redact(key.rsplit('.').next().unwrap_or(key), &mut value);
+    value.to_string()
+}
+
+/// Like in the config file, with what only the config file has, but with the secrets in it (the
+/// SecureOn passwords) redacted.
+fn serialize_hosts<S: Serializer>(hosts: &[StaticHost], serializer: S) -> Result<S::Ok, S::Error> {
+    fn redact(value: &mut serde_json::Value) {
+        if let serde_json::Value::Object(values) = value {
+            for (key, value) in values {
+                match value {
+                    _ if SECRETS.contains(&key.as_str()) => *value = REDACTED.into(),
+                    _ => redact(value),
+                }
+            }
+        }
+    }
+    serializer.collect_seq(hosts.iter().map(|host| {
+        let raw = RawStaticHost {
+            post_wake_commands: host.post_wake_commands.clone(),
+            strategies: host.strategies.clone(),
+            destinations: (!host.destinations.is_empty()).then(|| host.destinations.clone()),
+            ..RawStaticHost::from(host.clone())
+        };
+        let mut value = serde_json::to_value(raw).unwrap_or_default();
+        redact(&mut value);
+        value
+    }))
+}
+
+/// In seconds, like in the config file.
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+fn serialize_retry<S: Serializer>(retry: &RetryPolicy, serializer: S) -> Result<S::Ok, S::Error> {
+    RetryLayer {
+        max_attempts: Some(retry.max_attempts),
+        initial_backoff_ms: Some(retry.initial_backoff.as_millis() as u64),
+        max_backoff_ms: Some(retry.max_backoff.as_millis() as u64),
+    }
+    .serialize(serializer)
+}
+
+fn serialize_pinned_macs<S: Serializer>(
+    macs: &BTreeMap<String, MacAddress>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(macs.iter().map(|(name, mac)| (name, mac.to_string())))
+}
+
+fn serialize_level<S: Serializer>(
+    level: &tracing::Level,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&level.as_str().to_ascii_lowercase())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelayConfig {
+    /// Where the magic packets to relay are received.
+    pub listen: SocketAddr,
+    /// Where they're sent on to, the usual port 9 is used for addresses without a port.
+    #[serde(deserialize_with = "deserialize_broadcasts")]
+    pub destinations: Vec<SocketAddr>,
+    /// How many packets from one source are relayed per minute, the rest is dropped.
+    #[serde(default = "default_relay_rate_limit")]
+    pub rate_limit: u32,
+}
+
+fn default_relay_rate_limit() -> u32 {
+    10
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// Where the proxied service is served, the rest of the server stays on `listen`.
+    pub listen: SocketAddr,
+    /// Where requests are forwarded to, only `http://`.
+    pub upstream: String,
+    /// The host the upstream runs on, woken when it doesn't answer.
+    pub host: String,
+    /// Which requests wake the host.
+    #[serde(default)]
+    pub wake: ProxyWake,
+    /// How long the host gets to come up after a wake, in seconds.
+    #[serde(default = "default_proxy_max_wait")]
+    pub max_wait: u64,
+    /// Whether requests wait for the upstream instead of getting a page saying it's waking up.
+    #[serde(default)]
+    pub hold: bool,
+}
+
+fn default_proxy_max_wait() -> u64 {
+    60
+}
+
+impl ProxyConfig {
+    /// The `host:port` of the upstream, and its path without a trailing slash, which the paths
+    /// of requests are appended to. `None` if it isn't an `http://` url with a host.
+    pub fn upstream_parts(&self) -> Option<(String, &str)> {
+        let rest = self.upstream.strip_prefix("http://")?;
+        let rest = rest.split(['?', '#']).next().unwrap_or(rest);
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, ""),
+        };
+        if authority.is_empty() || authority.contains('@') {
+            return None;
+        }
+        // no port, or the end of a bracketed IPv6 address
+        let authority = if authority.ends_with(']') || !authority.contains(':') {
+            format!("{authority}:80")
+        } else {
+            authority.to_owned()
+        };
+        Some((authority, path.trim_end_matches('/')))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyWake {
+    /// Every request.
+    #[default]
+    Any,
+    /// Only a browser loading a page, not what apps or pages poll in the background.
+    Pages,
+    /// None, the host is only forwarded to when something else woke it.
+    Never,
+}
+
+/// A network of its own, either one this server can send to (with its own settings) or one
+/// that's woken by another wakeonlan server there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Site {
+    pub name: String,
+    /// Where the packets for its hosts are sent to, instead of `broadcast`.
+    #[serde(default, deserialize_with = "deserialize_broadcast")]
+    pub broadcast: Option<SocketAddr>,
+    /// The interface the packets for its hosts leave on, instead of `interface`.
+    pub interface: Option<String>,
+    /// The local address the packets for its hosts are sent from, like the one this server has
+    /// in the site's VLAN. It has to be on one of the interfaces.
+    #[serde(default)]
+    pub source: Option<IpAddr>,
+    /// More backends its hosts are discovered with, the hosts they find are in the site.
+    #[serde(default)]
+    pub discovery: Vec<Backend>,
+    /// The hosts the switch (see `snmp`) has on this VLAN are in the site.
+    pub vlan: Option<u16>,
+    /// If set, its hosts are woken by asking the server there instead.
+    pub remote: Option<RemoteSite>,
+    /// If set, its hosts are woken by running a command on a machine there over ssh instead,
+    /// for a site that can't be reached otherwise.
+    #[serde(default)]
+    pub ssh: Option<SshSite>,
+}
+
+impl Site {
+    /// Whether its packets are sent from there, not from here.
+    pub fn is_remote(&self) -> bool {
+        self.remote.is_some() || self.ssh.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteSite {
+    /// Where the other server is, which has to speak plain http, like through a VPN.
+    pub url: String,
+    /// The token of the other server, if it needs one.
+    pub token: Option<Secret<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SshSite {
+    /// The machine in the site to log in to.
+    pub host: String,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    /// The private key to log in with, ssh's own ones without it.
+    pub key: Option<PathBuf>,
+    /// What's run there for every MAC, with `{mac}` replaced by it. Nothing else of the wake
+    /// ends up in it.
+    #[serde(default = "default_ssh_command")]
+    pub command: String,
+    /// In seconds, how long logging in and running the command may take together.
+    #[serde(default = "default_ssh_timeout")]
+    pub timeout: u64,
+}
+
+fn default_ssh_command() -> String {
+    "wakeonlan wake --mac {mac}".to_owned()
+}
+
+fn default_ssh_timeout() -> u64 {
+    20
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxyAuthConfig {
+    /// The header with the name of the user, like `Remote-User` or `X-Forwarded-User`.
+    #[serde(default = "default_proxy_auth_header")]
+    pub header: String,
+    /// If not empty, only these users may use mutating endpoints.
+    #[serde(default)]
+    pub allowed_users: Vec<String>,
+}
+
+fn default_proxy_auth_header() -> String {
+    "Remote-User".to_owned()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WakeSequence {
+    pub name: String,
+    pub steps: Vec<SequenceStep>,
+    /// Whether the steps after one that failed are still started.
+    #[serde(default)]
+    pub continue_on_failure: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SequenceStep {
+    pub host: String,
+    /// How to tell that the host is ready for the next step, it's whether it's up like
+    /// `verify` says without one.
+    pub ready: Option<Strategy>,
+    /// How long waking it and waiting for it to be ready may take, in seconds.
+    #[serde(default = "default_step_timeout")]
+    pub timeout: u64,
+}
+
+fn default_step_timeout() -> u64 {
+    120
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    pub path: PathBuf,
+    /// The log is rotated before it grows beyond this.
+    #[serde(default = "default_audit_max_bytes")]
+    pub max_bytes: u64,
+    /// How many rotated logs are kept.
+    #[serde(default = "default_audit_keep")]
+    pub keep: u32,
+}
+
+fn default_audit_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_audit_keep() -> u32 {
+    5
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetbiosConfig {
+    /// How long a host gets to answer.
+    #[serde(default = "default_netbios_timeout_ms")]
+    pub timeout_ms: u64,
+    /// How many hosts are asked at the same time.
+    #[serde(default = "default_netbios_concurrency")]
+    pub concurrency: usize,
+}
+
+fn default_netbios_timeout_ms() -> u64 {
+    300
+}
+
+fn default_netbios_concurrency() -> usize {
+    8
+}
+
+/// The `[snmp]` table, with the switch whose forwarding database is read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SnmpConfig {
+    /// Port 161 unless it says.
+    #[serde(deserialize_with = "deserialize_snmp_agent")]
+    pub switch: SocketAddr,
+    /// The SNMP v2c community that may read it.
+    #[serde(default = "default_snmp_community")]
+    pub community: Secret<String>,
+    /// How long the switch gets to answer each request.
+    #[serde(default = "default_snmp_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_snmp_community() -> Secret<String> {
+    "public".into()
+}
+
+fn default_snmp_timeout_ms() -> u64 {
+    1000
+}
+
+/// The `[send_queue]` table, see `GET /api/v1/queue`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SendQueueConfig {
+    /// How many seconds after it was asked for a queued wake is given up on.
+    #[serde(default = "default_send_queue_max_age")]
+    pub max_age: u64,
+    /// How many MACs can be queued at once, wakes beyond that fail like without the queue.
+    #[serde(default = "default_send_queue_size")]
+    pub size: usize,
+}
+
+fn default_send_queue_max_age() -> u64 {
+    600
+}
+
+fn default_send_queue_size() -> usize {
+    100
+}
+
+/// The `[send_confirmation]` table, for wakes over the internet where a router on the way can
+/// say that it couldn't pass the packet on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SendConfirmationConfig {
+    /// How long after sending the errors are waited for, for each unicast destination.
+    #[serde(default = "default_send_confirmation_wait_ms")]
+    pub wait_ms: u64,
+}
+
+impl SendConfirmationConfig {
+    pub fn wait(&self) -> Duration {
+        Duration::from_millis(self.wait_ms)
+    }
+}
+
+fn default_send_confirmation_wait_ms() -> u64 {
+    200
+}
+
+const MAX_SEND_CONFIRMATION_WAIT_MS: u64 = 5000;
+
+/// The `[mqtt]` table, for dashboards that show the hosts without polling the API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    /// Like `192.168.1.5:1883`, spoken to with MQTT 3.1.1 without TLS.
+    pub broker: String,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<Secret<String>>,
+    /// What the topics start with, like `wol` for `wol/hosts/nas`.
+    #[serde(default = "default_mqtt_prefix")]
+    pub prefix: String,
+    /// How often the hosts are checked for changes the server isn't told about, like newly
+    /// discovered ones, in seconds.
+    #[serde(default = "default_mqtt_interval")]
+    pub interval: u64,
+}
+
+impl MqttConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+}
+
+fn default_mqtt_client_id() -> String {
+    "wakeonlan".to_owned()
+}
+
+fn default_mqtt_prefix() -> String {
+    "wol".to_owned()
+}
+
+fn default_mqtt_interval() -> u64 {
+    60
+}
+
+/// The `[hooks]` table, for the `post_wake_commands` of the configured hosts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    /// `false` never runs any of them, whatever the hosts say.
+    #[serde(default = "default_hooks_enabled")]
+    pub enabled: bool,
+    /// How many seconds each command may take before it's killed.
+    #[serde(default = "default_hook_timeout")]
+    pub timeout: u64,
+    /// How many seconds a woken host has to come up before its commands are run, they're not run
+    /// if it doesn't. 0 runs them right after the packet is sent.
+    #[serde(default = "default_hook_wait_online")]
+    pub wait_online: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        HooksConfig {
+            enabled: default_hooks_enabled(),
+            timeout: default_hook_timeout(),
+            wait_online: default_hook_wait_online(),
+        }
+    }
+}
+
+fn default_hooks_enabled() -> bool {
+    true
+}
+
+fn default_hook_timeout() -> u64 {
+    60
+}
+
+fn default_hook_wait_online() -> u64 {
+    300
+}
+
+/// The `[limits]` table, how many entries of what the server remembers in memory it keeps. The
+/// oldest are forgotten first, see `GET /api/v1/stats`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// How many MACs the last wake is kept for, how many wakes `GET /api/v1/history` keeps, and how
+    /// many wakes are waited to be verified.
+    #[serde(default = "default_limit_history")]
+    pub history: usize,
+    /// How many started sequences are kept, ones still running aren't forgotten.
+    #[serde(default = "default_limit_jobs")]
+    pub jobs: usize,
+    /// How many discovered hosts the IP, the site and when they were last seen are kept for.
+    #[serde(default = "default_limit_hosts")]
+    pub hosts: usize,
+    /// How many sources the relay rate limit counts packets of at once, packets from others are
+    /// dropped until the window of one runs out.
+    #[serde(default = "default_limit_relay_sources")]
+    pub relay_sources: usize,
+    /// How many events wait for a subscriber of `GET /api/v1/events` that doesn't keep up, it
+    /// misses the oldest beyond that.
+    #[serde(default = "default_limit_events")]
+    pub events: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            history: default_limit_history(),
+            jobs: default_limit_jobs(),
+            hosts: default_limit_hosts(),
+            relay_sources: default_limit_relay_sources(),
+            events: default_limit_events(),
+        }
+    }
+}
+
+fn default_limit_history() -> usize {
+    1000
+}
+
+fn default_limit_jobs() -> usize {
+    100
+}
+
+fn default_limit_hosts() -> usize {
+    4096
+}
+
+fn default_limit_relay_sources() -> usize {
+    1000
+}
+
+fn default_limit_events() -> usize {
+    256
+}
+
+/// The `[quiet_hours]` table, when wakes that nobody asked for right then (like scheduled ones)
+/// are suppressed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuietHoursConfig {
+    /// Local time, like `"22:00"`.
+    #[serde(
+        deserialize_with = "deserialize_time",
+        serialize_with = "serialize_time"
+    )]
+    pub start: NaiveTime,
+    /// Local time, it's not quiet anymore from then on. Before `start` for hours across midnight.
+    #[serde(
+        deserialize_with = "deserialize_time",
+        serialize_with = "serialize_time"
+    )]
+    pub end: NaiveTime,
+    /// Only these hosts are kept quiet, by name and ignoring case. Every host is without any
+    /// `hosts` or `locations`.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Only the hosts in these locations are kept quiet, ignoring case.
+    #[serde(default)]
+    pub locations: Vec<String>,
+    /// Wakes someone asked for need `"force": true` during them too, instead of going ahead.
+    #[serde(default)]
+    pub require_force: bool,
+}
+
+/// The `[discovery_freshness]` table, for holding back automatic wakes while discovery has been
+/// failing, since what they'd go by may be out of date. Wakes someone asks for go ahead, with a
+/// warning.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiscoveryFreshnessConfig {
+    /// Seconds since discovery last worked (or the server started) after which it's stale.
+    pub max_age: u64,
+    #[serde(default)]
+    pub when_stale: WhenStale,
+    /// The wakes that wait for discovery.
+    #[serde(default = "default_fresh_sources")]
+    pub sources: Vec<FreshSource>,
+}
+
+/// What happens to an automatic wake while discovery is stale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WhenStale {
+    /// Discover once more first, and only skip the wake if that fails too.
+    #[default]
+    Refresh,
+    Skip,
+}
+
+/// The automatic wakes that can wait for discovery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FreshSource {
+    Schedules,
+    Sequences,
+}
+
+fn default_fresh_sources() -> Vec<FreshSource> {
+    vec![FreshSource::Schedules, FreshSource::Sequences]
+}
+
+/// The `[self_test]` table, for a datagram sent at startup to find out early when the server
+/// isn't allowed to send broadcasts, like in a rootless container.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SelfTestConfig {
+    /// Where the empty datagram goes, `broadcast` without one.
+    #[serde(default)]
+    pub destination: Option<SocketAddr>,
+}
+
+impl QuietHoursConfig {
+    /// Whether it's quiet at the local time.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            // across midnight
+            self.start <= time || time < self.end
+        }
+    }
+
+    /// Whether the host (woken by its name, in the location) is kept quiet.
+    pub fn applies_to(&self, host: Option<&str>, location: Option<&str>) -> bool {
+        if self.hosts.is_empty() && self.locations.is_empty() {
+            return true;
+        }
+        let matches = |names: &[String], name: Option<&str>| {
+            name.is_some_and(|name| names.iter().any(|other| other.eq_ignore_ascii_case(name)))
+        };
+        matches(&self.hosts, host) || matches(&self.locations, location)
+    }
+}
+
+fn deserialize_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&value, "%H:%M").map_err(|_| {
+        serde::de::Error::custom(format!("invalid time `{value}`, expected one like `22:00`"))
+    })
+}
+
+fn serialize_time<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&time.format("%H:%M"))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TelegramConfig {
+    pub token: Secret<String>,
+    /// Messages from any other chat are ignored.
+    pub allowed_chats: Vec<i64>,
+    /// The Bot API server, Telegram's own unless there's a local `telegram-bot-api`.
+    #[serde(default = "default_telegram_api")]
+    pub api_url: String,
+}
+
+fn default_telegram_api() -> String {
+    "https://api.telegram.org".to_owned()
+}
+
+/// Something that's wrong with a config file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub file: PathBuf,
+    /// Where in the file it is, like `hosts[2].name`, empty if it's the file as a whole.
+    pub key: String,
+    /// What's there, written like in the file.
+    pub value: Option<String>,
+    pub message: String,
+}
+
+/// Everything that's wrong with a config file, never empty.
+#[derive(Debug, Clone)]
+pub struct Problems(pub Vec<Problem>);
+
+/// Longer values are cut off when they're shown.
+const MAX_SHOWN_VALUE: usize = 60;
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.file.display())?;
+        if !self.key.is_empty() {
+            write!(f, "{}: ", self.key)?;
+        }
+        f.write_str(&self.message)?;
+        if let Some(value) = &self.value {
+            match value.char_indices().nth(MAX_SHOWN_VALUE) {
+                Some((end, _)) => write!(f, " (found {}...)", &value[..end])?,
+                None => write!(f, " (found {value})")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Problems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [problem] => write!(f, "{problem}"),
+            problems => {
+                write!(f, "{} problems", problems.len())?;
+                for problem in problems {
+                    write!(f, "\n  {problem}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Problems {}
+
+/// Checks a config file without loading anything else, every problem it has is returned.
+pub fn check_file(path: &Path) -> Result<(), Problems> {
+    ConfigLayer::from_file(path).map(drop)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: vec![DEFAULT_LISTEN],
+            default_host: None,
+            broadcast: DEFAULT_BROADCAST,
+            interface: None,
+            failover: Vec::new(),
+            token: None,
+            url_secret: None,
+            hosts: Vec::new(),
+            registry: None,
+            pinned_macs: BTreeMap::new(),
+            wake_timeout: DEFAULT_WAKE_TIMEOUT,
+            retry: RetryPolicy::default(),
+            batch_concurrency: DEFAULT_BATCH_CONCURRENCY,
+            discovery: DEFAULT_BACKENDS.to_vec(),
+            verify: DEFAULT_VERIFY.to_vec(),
+            verify_timeout: DEFAULT_VERIFY_TIMEOUT,
+            allow_from: Vec::new(),
+            read_allow_from: Vec::new(),
+            read_only: false,
+            trusted_proxies: Vec::new(),
+            proxy_auth: None,
+            relay: None,
+            proxy: None,
+            sites: Vec::new(),
+            neighbor_refresh: false,
+            not_found_ttl: DEFAULT_NOT_FOUND_TTL,
+            online_max_age: DEFAULT_ONLINE_MAX_AGE,
+            skip_if_online: false,
+            neighbor_sweep: None,
+            callback_allow: Vec::new(),
+            telegram: None,
+            schedules: Vec::new(),
+            schedules_file: None,
+            wake_tokens_file: None,
+            sequences: Vec::new(),
+            audit: None,
+            netbios: None,
+            snmp: None,
+            send_queue: None,
+            send_confirmation: None,
+            mqtt: None,
+            hooks: HooksConfig::default(),
+            limits: LimitsConfig::default(),
+            quiet_hours: None,
+            discovery_freshness: None,
+            self_test: None,
+            log_buffer: DEFAULT_LOG_BUFFER,
+            log_buffer_level: tracing::Level::INFO,
+            index_page: PathBuf::from(DEFAULT_INDEX_PAGE),
+            sources: BTreeMap::new(),
+        }
+    }
+}
+
+/// A host with one or more network cards, every one of them gets a magic packet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawStaticHost", into = "RawStaticHost")]
+pub struct StaticHost {
+    pub name: String,
+    /// Never empty.
+    pub macs: Vec<MacAddress>,
+    /// The network interface its packets leave on, instead of the configured one.
+    pub interface: Option<String>,
+    /// The local address its packets are sent from, instead of its site's or any.
+    pub source: Option<IpAddr>,
+    /// The name of the site it's in, `None` for the network this server is in.
+    pub site: Option<String>,
+    /// Where it is, like the room. The page lists the hosts of each location together.
+    pub location: Option<String>,
+    /// What it's woken for, like its Jellyfin. An `http://` or `https://` URL the page opens
+    /// once the host is up.
+    pub service_url: Option<String>,
+    /// Sent after the MAC repetitions, for a NIC with a SecureOn password set. Left out of
+    /// `/hosts/export`.
+    pub password: Option<SecureOnPassword>,
+    /// Shell commands run one after the other once it was woken, see [`HooksConfig`]. Only
+    /// those of the config file are run, they're never exported or saved in the registry.
+    pub post_wake_commands: Vec<String>,
+    /// The ways it's woken, tried in order until it's up after one of them. Without any, the
+    /// packets go to every destination at once. Like the commands, only from the config file.
+    pub strategies: Vec<WakeStrategy>,
+    /// Where its packets go instead of the usual destinations, tried in order until one of them
+    /// gets a packet out. With `destinations_until = "online"` they're strategies instead, and
+    /// the next is tried until the host is up. Only from the config file too.
+    pub destinations: Vec<SocketAddr>,
+    /// Wakes are refused unless they `confirm` it, and batches leave it out, for a host that
+    /// shouldn't be woken by a stray tap. Schedules still wake it.
+    pub require_confirmation: bool,
+}
+
+/// One way of waking a host, see [`StaticHost::strategies`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WakeStrategy {
+    pub via: Via,
+    /// Where the UDP packets go, the usual destinations without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<SocketAddr>,
+    /// The interface the packets leave on, the host's (or the configured one) without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    /// How to tell that the host is up after it, like `verify` says without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready: Option<Strategy>,
+    /// How long the host has to come up before the next strategy is tried, in seconds.
+    #[serde(default = "default_strategy_timeout")]
+    pub timeout: u64,
+}
+
+fn default_strategy_timeout() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Via {
+    /// A magic packet in a UDP datagram, like any other wake.
+    Udp,
+    /// A magic packet in an Ethernet frame (EtherType 0x0842) to the broadcast MAC, for NICs
+    /// that ignore UDP. Needs `CAP_NET_RAW` and only works on Linux.
+    Raw,
+}
+
+/// Like `udp to 192.168.1.255:9` or `raw on eth0`, how it's named in responses and the history.
+impl fmt::Display for WakeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.via {
+            Via::Udp => "udp",
+            Via::Raw => "raw",
+        })?;
+        if let Some(destination) = self.destination {
+            write!(f, " to {destination}")?;
+        }
+        if let Some(interface) = &self.interface {
+            write!(f, " on {interface}")?;
+        }
+        Ok(())
+    }
+}
+
+/// When the next of a host's `destinations` is tried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DestinationsUntil {
+    /// Until a packet got out to one.
+    Sent,
+    /// Until the host is up, like its strategies.
+    Online,
+}
+
+/// A host as it's written down, with either a single `mac` or a list of `macs` (or both).
+/// Unknown keys are only rejected in the config file, the registry file may have them.
+#[derive(Serialize, Deserialize)]
+struct RawStaticHost {
+    name: String,
+    #[serde(default, skip_serializing)]
+    mac: Option<String>,
+    #[serde(default)]
+    macs: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    interface: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<IpAddr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    site: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    service_url: Option<String>,
+    /// Hex digits, 4 or 6 bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    /// Like the strategies and destinations, only serialized for the effective config, the
+    /// registry never has them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    post_wake_commands: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    strategies: Vec<WakeStrategy>,
+    /// `None` if it isn't set, an empty list is a mistake.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    destinations: Option<Vec<SocketAddr>>,
+    #[serde(default, skip_serializing)]
+    destinations_until: Option<DestinationsUntil>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    require_confirmation: bool,
+}
+
+/// The keys of [`RawStaticHost`].
+const RAW_HOST_KEYS: &[&str] = &[
+    "name",
+    "mac",
+    "macs",
+    "interface",
+    "source",
+    "site",
+    "location",
+    "service_url",
+    "password",
+    "post_wake_commands",
+    "strategies",
+    "destinations",
+    "destinations_until",
+    "require_confirmation",
+];
+/// The keys of a [`Schedule`], unknown ones are only rejected in the config file.
+const RAW_SCHEDULE_KEYS: &[&str] = &["host", "cron", "at"];
+
+impl StaticHost {
+    /// Validates a host, duplicate MACs are only kept once.
+    pub fn new<'a>(
+        name: &str,
+        macs: impl IntoIterator<Item = &'a str>,
+    ) -> Result<StaticHost, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("missing name".to_owned());
+        }
+        let mut parsed = Vec::new();
+        for mac in macs {
+            let mac = parse_mac_addr(mac.trim())
+                .ok_or_else(|| format!("invalid mac address `{mac}` for host `{name}`"))?;
+            if !parsed.contains(&mac) {
+                parsed.push(mac);
+            }
+        }
+        if parsed.is_empty() {
+            return Err(format!("no mac address for host `{name}`"));
+        }
+        Ok(StaticHost {
+            name: name.to_owned(),
+            macs: parsed,
+            interface: None,
+            source: None,
+            site: None,
+            location: None,
+            service_url: None,
+            password: None,
+            post_wake_commands: Vec::new(),
+            strategies: Vec::new(),
+            destinations: Vec::new(),
+            require_confirmation: false,
+        })
+    }
+}
+
+impl TryFrom<RawStaticHost> for StaticHost {
+    type Error = String;
+
+    fn try_from(raw: RawStaticHost) -> Result<Self, Self::Error> {
+        let host = StaticHost::new(
+            &raw.name,
+            raw.mac.iter().chain(&raw.macs).map(String::as_str),
+        )?;
+        let destinations = match raw.destinations {
+            Some(destinations) if destinations.is_empty() => {
+                return Err(format!("empty destinations for host `{}`", host.name));
+            }
+            destinations => destinations.unwrap_or_default(),
+        };
+        let mut strategies = raw.strategies;
+        match raw.destinations_until {
+            None | Some(DestinationsUntil::Sent) => {}
+            Some(DestinationsUntil::Online) if destinations.is_empty() => {
+                return Err(format!(
+                    "destinations_until without destinations for host `{}`",
+                    host.name
+                ));
+            }
+            Some(DestinationsUntil::Online) if !strategies.is_empty() => {
+                return Err(format!(
+                    "host `{}` has strategies, its destinations can't be strategies as well",
+                    host.name
+                ));
+            }
+            Some(DestinationsUntil::Online) => {
+                strategies = (destinations.iter())
+                    .map(|&destination| WakeStrategy {
+                        via: Via::Udp,
+                        destination: Some(destination),
+                        interface: None,
+                        ready: None,
+                        timeout: default_strategy_timeout(),
+                    })
+                    .collect();
+            }
+        }
+        Ok(StaticHost {
+            interface: non_empty_interface(raw.interface, &host.name)?,
+            source: raw.source,
+            site: raw.site.filter(|site| !site.trim().is_empty()),
+            location: non_empty_location(raw.location),
+            service_url: parse_service_url(raw.service_url, &host.name)?,
+            password: parse_password(raw.password, &host.name)?,
+            post_wake_commands: raw.post_wake_commands,
+            strategies,
+            destinations,
+            require_confirmation: raw.require_confirmation,
+            ..host
+        })
+    }
+}
+
+/// Trimmed, `None` if it's empty.
+pub(crate) fn non_empty_location(location: Option<String>) -> Option<String> {
+    location
+        .map(|location| location.trim().to_owned())
+        .filter(|location| !location.is_empty())
+}
+
+/// `None` for no URL or an empty one. Only web URLs, it ends up in a link and a redirect.
+pub(crate) fn parse_service_url(url: Option<String>, host: &str) -> Result<Option<String>, String> {
+    match url.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+            Ok(Some(url.to_owned()))
+        }
+        Some(url) => Err(format!(
+            "service url `{url}` for host `{host}` isn't an http:// or https:// url"
+        )),
+    }
+}
+
+/// `None` for no password or an empty one.
+pub(crate) fn parse_password(
+    password: Option<String>,
+    host: &str,
+) -> Result<Option<SecureOnPassword>, String> {
+    match password.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(password) => password
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("invalid password for host `{host}`: {e}")),
+    }
+}
+
+/// `None` for no interface, an empty name is a mistake.
+fn non_empty_interface(interface: Option<String>, host: &str) -> Result<Option<String>, String> {
+    match interface.as_deref().map(str::trim) {
+        Some("") => Err(format!("empty interface name for host `{host}`")),
+        Some(interface) => Ok(Some(interface.to_owned())),
+        None => Ok(None),
+    }
+}
+
+impl From<StaticHost> for RawStaticHost {
+    fn from(host: StaticHost) -> Self {
+        RawStaticHost {
+            name: host.name,
+            mac: None,
+            macs: host.macs.iter().map(MacAddress::to_string).collect(),
+            interface: host.interface,
+            source: host.source,
+            site: host.site,
+            location: host.location,
+            service_url: host.service_url,
+            password: host.password.as_ref().map(SecureOnPassword::to_hex),
+            post_wake_commands: Vec::new(),
+            strategies: Vec::new(),
+            destinations: None,
+            destinations_until: None,
+            require_confirmation: host.require_confirmation,
+        }
+    }
+}
+
+/// The settings of one source of configuration, `None` for everything it doesn't set.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigLayer {
+    #[serde(default, deserialize_with = "deserialize_listen")]
+    listen: Option<Vec<SocketAddr>>,
+    default_host: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_broadcast")]
+    broadcast: Option<SocketAddr>,
+    interface: Option<String>,
+    failover: Option<Vec<String>>,
+    token: Option<Secret<String>>,
+    url_secret: Option<Secret<String>>,
+    hosts: Option<Vec<StaticHost>>,
+    registry: Option<PathBuf>,
+    #[serde(default, deserialize_with = "deserialize_pinned_macs")]
+    pinned_macs: Option<BTreeMap<String, MacAddress>>,
+    /// In seconds.
+    wake_timeout: Option<u64>,
+    retry: Option<RetryLayer>,
+    batch_concurrency: Option<usize>,
+    discovery: Option<Vec<Backend>>,
+    verify: Option<Vec<Strategy>>,
+    /// In seconds.
+    verify_timeout: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_nets")]
+    allow_from: Option<Vec<IpNet>>,
+    #[serde(default, deserialize_with = "deserialize_nets")]
+    read_allow_from: Option<Vec<IpNet>>,
+    read_only: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_nets")]
+    trusted_proxies: Option<Vec<IpNet>>,
+    proxy_auth: Option<ProxyAuthConfig>,
+    relay: Option<RelayConfig>,
+    proxy: Option<ProxyConfig>,
+    sites: Option<Vec<Site>>,
+    neighbor_refresh: Option<bool>,
+    not_found_ttl: Option<u64>,
+    online_max_age: Option<u64>,
+    skip_if_online: Option<bool>,
+    neighbor_sweep: Option<IpNet>,
+    callback_allow: Option<Vec<String>>,
+    telegram: Option<TelegramConfig>,
+    schedules: Option<Vec<Schedule>>,
+    schedules_file: Option<PathBuf>,
+    wake_tokens_file: Option<PathBuf>,
+    sequences: Option<Vec<WakeSequence>>,
+    audit: Option<AuditConfig>,
+    netbios: Option<NetbiosConfig>,
+    snmp: Option<SnmpConfig>,
+    send_queue: Option<SendQueueConfig>,
+    send_confirmation: Option<SendConfirmationConfig>,
+    mqtt: Option<MqttConfig>,
+    hooks: Option<HooksConfig>,
+    limits: Option<LimitsConfig>,
+    quiet_hours: Option<QuietHoursConfig>,
+    discovery_freshness: Option<DiscoveryFreshnessConfig>,
+    self_test: Option<SelfTestConfig>,
+    log_buffer: Option<usize>,
+    #[serde(default, deserialize_with = "deserialize_level")]
+    log_buffer_level: Option<tracing::Level>,
+    index_page: Option<PathBuf>,
+    /// Which of the settings this layer has, the keys it was merged from keep theirs.
+    #[serde(skip)]
+    sources: BTreeMap<String, Source>,
+}
+
+/// The `[retry]` table, with the backoffs in milliseconds.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RetryLayer {
+    max_attempts: Option<u32>,
+    initial_backoff_ms: Option<u64>,
+    max_backoff_ms: Option<u64>,
+}
+
+impl RetryLayer {
+    fn into_policy(self) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: self.max_attempts.unwrap_or(default.max_attempts).max(1),
+            initial_backoff: self
+                .initial_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.initial_backoff),
+            max_backoff: self
+                .max_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.max_backoff),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file from `WOL_CONFIG` (or `wakeonlan.toml` if that exists)
+    /// on top of the `WOL_*` environment variables.
+    pub fn load() -> eyre::Result<Config> {
+        let env = ConfigLayer::from_env(|name| std::env::var(name).ok())?;
+
+        let (path, required) = match std::env::var_os("WOL_CONFIG") {
+            Some(path) => (PathBuf::from(path), true),
+            None => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+        let file = if required || path.exists() {
+            let file = ConfigLayer::from_file(&path)?;
+            tracing::debug!(path = %path.display(), "loaded config file, it takes precedence over WOL_* environment variables");
+            file
+        } else {
+            tracing::debug!(path = %path.display(), "no config file, only using WOL_* environment variables");
+            ConfigLayer::default()
+        };
+
+        let mut layer = file.over(env);
+        layer.index_page = layer
+            .index_page
+            .or_else(|| Some(path.with_file_name(DEFAULT_INDEX_PAGE)));
+        Ok(layer.into_config())
+    }
+}
+
+/// Just `key = value`, checked on its own.
+fn layer_with(key: &str, value: toml::Value) -> Result<ConfigLayer, toml::de::Error> {
+    toml::Value::Table(toml::Table::from_iter([(key.to_owned(), value)])).try_into()
+}
+
+impl ConfigLayer {
+    /// Fills everything that isn't set in `self` from `lower`.
+    fn over(self, lower: ConfigLayer) -> ConfigLayer {
+        let mut sources = lower.sources;
+        sources.extend(self.sources);
+        ConfigLayer {
+            listen: self.listen.or(lower.listen),
+            default_host: self.default_host.or(lower.default_host),
+            broadcast: self.broadcast.or(lower.broadcast),
+            interface: self.interface.or(lower.interface),
+            failover: self.failover.or(lower.failover),
+            token: self.token.or(lower.token),
+            url_secret: self.url_secret.or(lower.url_secret),
+            hosts: self.hosts.or(lower.hosts),
+            registry: self.registry.or(lower.registry),
+            pinned_macs: self.pinned_macs.or(lower.pinned_macs),
+            wake_timeout: self.wake_timeout.or(lower.wake_timeout),
+            retry: self.retry.or(lower.retry),
+            batch_concurrency: self.batch_concurrency.or(lower.batch_concurrency),
+            discovery: self.discovery.or(lower.discovery),
+            verify: self.verify.or(lower.verify),
+            verify_timeout: self.verify_timeout.or(lower.verify_timeout),
+            allow_from: self.allow_from.or(lower.allow_from),
+            read_allow_from: self.read_allow_from.or(lower.read_allow_from),
+            read_only: self.read_only.or(lower.read_only),
+            trusted_proxies: self.trusted_proxies.or(lower.trusted_proxies),
+            proxy_auth: self.proxy_auth.or(lower.proxy_auth),
+            relay: self.relay.or(lower.relay),
+            proxy: self.proxy.or(lower.proxy),
+            sites: self.sites.or(lower.sites),
+            neighbor_refresh: self.neighbor_refresh.or(lower.neighbor_refresh),
+            not_found_ttl: self.not_found_ttl.or(lower.not_found_ttl),
+            online_max_age: self.online_max_age.or(lower.online_max_age),
+            skip_if_online: self.skip_if_online.or(lower.skip_if_online),
+            neighbor_sweep: self.neighbor_sweep.or(lower.neighbor_sweep),
+            callback_allow: self.callback_allow.or(lower.callback_allow),
+            telegram: self.telegram.or(lower.telegram),
+            schedules: self.schedules.or(lower.schedules),
+            schedules_file: self.schedules_file.or(lower.schedules_file),
+            wake_tokens_file: self.wake_tokens_file.or(lower.wake_tokens_file),
+            sequences: self.sequences.or(lower.sequences),
+            audit: self.audit.or(lower.audit),
+            netbios: self.netbios.or(lower.netbios),
+            snmp: self.snmp.or(lower.snmp),
+            send_queue: self.send_queue.or(lower.send_queue),
+            send_confirmation: self.send_confirmation.or(lower.send_confirmation),
+            mqtt: self.mqtt.or(lower.mqtt),
+            hooks: self.hooks.or(lower.hooks),
+            limits: self.limits.or(lower.limits),
+            quiet_hours: self.quiet_hours.or(lower.quiet_hours),
+            discovery_freshness: self.discovery_freshness.or(lower.discovery_freshness),
+            self_test: self.self_test.or(lower.self_test),
+            log_buffer: self.log_buffer.or(lower.log_buffer),
+            log_buffer_level: self.log_buffer_level.or(lower.log_buffer_level),
+            index_page: self.index_page.or(lower.index_page),
+            sources,
+        }
+    }
+
+    fn into_config(self) -> Config {
+        let default = Config::default();
+        Config {
+            listen: self.listen.unwrap_or(default.listen),
+            default_host: self.default_host,
+            broadcast: self.broadcast.unwrap_or(default.broadcast),
+            interface: self.interface.filter(|interface| !interface.is_empty()),
+            failover: self.failover.unwrap_or_default(),
+            token: self.token,
+            url_secret: self.url_secret,
+            hosts: self.hosts.unwrap_or_default(),
+            registry: self.registry,
+            pinned_macs: self.pinned_macs.unwrap_or_default(),
+            wake_timeout: self
+                .wake_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(default.wake_timeout),
+            retry: self
+                .retry
+                .map(RetryLayer::into_policy)
+                .unwrap_or(default.retry),
+            batch_concurrency: self.batch_concurrency.unwrap_or(default.batch_concurrency),
+            discovery: self.discovery.unwrap_or(default.discovery),
+            verify: self.verify.unwrap_or(default.verify),
+            verify_timeout: self
+                .verify_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(default.verify_timeout),
+            allow_from: self.allow_from.unwrap_or_default(),
+            read_allow_from: self.read_allow_from.unwrap_or_default(),
+            read_only: self.read_only.unwrap_or(default.read_only),
+            trusted_proxies: self.trusted_proxies.unwrap_or_default(),
+            proxy_auth: self.proxy_auth,
+            relay: self.relay,
+            proxy: self.proxy,
+            sites: self.sites.unwrap_or_default(),
+            neighbor_refresh: self.neighbor_refresh.unwrap_or(default.neighbor_refresh),
+            not_found_ttl: self
+                .not_found_ttl
+                .map(Duration::from_secs)
+                .unwrap_or(default.not_found_ttl),
+            online_max_age: self
+                .online_max_age
+                .map(Duration::from_secs)
+                .unwrap_or(default.online_max_age),
+            skip_if_online: self.skip_if_online.unwrap_or(default.skip_if_online),
+            neighbor_sweep: self.neighbor_sweep,
+            callback_allow: self.callback_allow.unwrap_or_default(),
+            telegram: self.telegram,
+            schedules: self.schedules.unwrap_or_default(),
+            schedules_file: self.schedules_file,
+            wake_tokens_file: self.wake_tokens_file,
+            sequences: self.sequences.unwrap_or_default(),
+            audit: self.audit,
+            netbios: self.netbios,
+            snmp: self.snmp,
+            send_queue: self.send_queue,
+            send_confirmation: self.send_confirmation,
+            mqtt: self.mqtt,
+            hooks: self.hooks.unwrap_or_default(),
+            limits: self.limits.unwrap_or_default(),
+            quiet_hours: self.quiet_hours,
+            discovery_freshness: self.discovery_freshness,
+            self_test: self.self_test,
+            log_buffer: self.log_buffer.unwrap_or(default.log_buffer),
+            log_buffer_level: self.log_buffer_level.unwrap_or(default.log_buffer_level),
+            index_page: self.index_page.unwrap_or(default.index_page),
+            sources: self.sources,
+        }
+    }
+
+    /// Every key is checked on its own (and every entry of the lists of tables), so one problem
+    /// doesn't hide the others.
+    fn from_file(path: &Path) -> Result<ConfigLayer, Problems> {
+        let problem = |key: String, value: Option<&toml::Value>, message: String| Problem {
+            file: path.to_owned(),
+            value: value.map(|value| shown_value(&key, value)),
+            key,
+            message,
+        };
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            Problems(vec![problem(
+                String::new(),
+                None,
+                format!("can't be read: {e}"),
+            )])
+        })?;
+        let table = toml::from_str::<toml::Table>(&contents).map_err(|e| {
+            let message = match e.span() {
+                Some(span) => {
+                    let line = contents[..span.start].matches('\n').count() + 1;
+                    format!("invalid TOML on line {line}: {}", e.message())
+                }
+                None => format!("invalid TOML: {}", e.message()),
+            };
+            Problems(vec![problem(String::new(), None, message)])
+        })?;
+
+        let mut problems = Vec::new();
+        for (key, value) in &table {
+            // the tables that don't deny unknown keys themselves say which ones they know
+            let entries = match (key.as_str(), value) {
+                ("hosts", toml::Value::Array(items)) => Some((items, Some(RAW_HOST_KEYS))),
+                ("schedules", toml::Value::Array(items)) => Some((items, Some(RAW_SCHEDULE_KEYS))),
+                ("sequences", toml::Value::Array(items)) => Some((items, None)),
+                ("sites", toml::Value::Array(items)) => Some((items, None)),
+                _ => None,
+            };
+            let Some((items, known_keys)) = entries else {
+                if let Err(e) = layer_with(key, value.clone()) {
+                    let message = match e.message() {
+                        message if message.starts_with(&format!("unknown field `{key}`")) => {
+                            "unknown key".to_owned()
+                        }
+                        message => message.to_owned(),
+                    };
+                    problems.push(problem(key.clone(), Some(value), message));
+                }
+                continue;
+            };
+            for (index, item) in items.iter().enumerate() {
+                let path = format!("{key}[{index}]");
+                let unknown =
+                    item.as_table()
+                        .zip(known_keys)
+                        .into_iter()
+                        .flat_map(|(table, known_keys)| {
+                            table
+                                .iter()
+                                .filter(|(item_key, _)| !known_keys.contains(&item_key.as_str()))
+                        });
+                for (item_key, item_value) in unknown {
+                    problems.push(problem(
+                        format!("{path}.{item_key}"),
+                        Some(item_value),
+                        "unknown key".to_owned(),
+                    ));
+                }
+                if let Err(e) = layer_with(key, toml::Value::Array(vec![item.clone()])) {
+                    problems.push(problem(path, Some(item), e.message().to_owned()));
+                }
+            }
+        }
+
+        // what's wrong with the settings together is only checked for those that are fine alone
+        let broken = problems
+            .iter()
+            .map(|problem| {
+                problem
+                    .key
+                    .split(['.', '['])
+                    .next()
+                    .unwrap_or_default()
+                    .to_owned()
+            })
+            .collect::<Vec<_>>();
+        let fine = table
+            .into_iter()
+            .filter(|(key, _)| !broken.contains(key))
+            .collect::<toml::Table>();
+        let sources = (fine.keys())
+            .map(|key| (key.clone(), Source::File))
+            .collect();
+        let mut layer = toml::Value::Table(fine)
+            .try_into::<ConfigLayer>()
+            .map_err(|e| Problems(vec![problem(String::new(), None, e.message().to_owned())]))?;
+        layer.sources = sources;
+        problems.extend(
+            layer
+                .problems()
+                .into_iter()
+                .map(|(key, value, message)| Problem {
+                    file: path.to_owned(),
+                    key,
+                    value: Some(value),
+                    message,
+                }),
+        );
+        if problems.is_empty() {
+            Ok(layer)
+        } else {
+            Err(Problems(problems))
+        }
+    }
+
+    /// What's wrong with settings that parsed fine, as the key, its value and the message.
+    fn problems(&self) -> Vec<(String, String, String)> {
+        let mut problems = Vec::new();
+        let quoted = |value: &dyn fmt::Display| format!("\"{value}\"");
+
+        if self.listen.as_ref().is_some_and(Vec::is_empty) {
+            problems.push((
+                "listen".to_owned(),
+                "[]".to_owned(),
+                "no listen addresses".to_owned(),
+            ));
+        }
+        let failover = self.failover.as_deref().unwrap_or_default();
+        for (index, interface) in failover.iter().enumerate() {
+            let message = if interface.trim().is_empty() {
+                "empty interface name".to_owned()
+            } else if let Some(first) = failover[..index]
+                .iter()
+                .position(|other| other == interface)
+            {
+                format!("`failover[{first}]` is the same interface")
+            } else {
+                continue;
+            };
+            problems.push((format!("failover[{index}]"), quoted(interface), message));
+        }
+        if let Some(broadcast) = self.broadcast.filter(|addr| addr.port() == 0) {
+            problems.push((
+                "broadcast".to_owned(),
+                quoted(&broadcast),
+                "port 0 can't be sent to".to_owned(),
+            ));
+        }
+        if self.batch_concurrency == Some(0) {
+            problems.push((
+                "batch_concurrency".to_owned(),
+                "0".to_owned(),
+                "at least one host has to be woken at a time".to_owned(),
+            ));
+        }
+        if let Some(netbios) = &self.netbios {
+            if netbios.timeout_ms == 0 {
+                problems.push((
+                    "netbios.timeout_ms".to_owned(),
+                    "0".to_owned(),
+                    "no host can answer that fast".to_owned(),
+                ));
+            }
+            if netbios.concurrency == 0 {
+                problems.push((
+                    "netbios.concurrency".to_owned(),
+                    "0".to_owned(),
+                    "at least one host has to be asked at a time".to_owned(),
+                ));
+            }
+        }
+        if let Some(confirmation) = &self.send_confirmation {
+            if !(1..=MAX_SEND_CONFIRMATION_WAIT_MS).contains(&confirmation.wait_ms) {
+                problems.push((
+                    "send_confirmation.wait_ms".to_owned(),
+                    confirmation.wait_ms.to_string(),
+                    format!("has to be between 1 and {MAX_SEND_CONFIRMATION_WAIT_MS} milliseconds"),
+                ));
+            }
+        }
+        if let Some(mqtt) = &self.mqtt {
+            let port =
+                (mqtt.broker.rsplit_once(':')).and_then(|(_, port)| port.parse::<u16>().ok());
+            if port.is_none_or(|port| port == 0) {
+                problems.push((
+                    "mqtt.broker".to_owned(),
+                    quoted(&mqtt.broker),
+                    "has to be a host and a port, like `192.168.1.5:1883`".to_owned(),
+                ));
+            }
+            if mqtt.prefix.is_empty() || mqtt.prefix.contains(['+', '#']) {
+                problems.push((
+                    "mqtt.prefix".to_owned(),
+                    quoted(&mqtt.prefix),
+                    "has to be a topic without wildcards".to_owned(),
+                ));
+            }
+            if mqtt.interval == 0 {
+                problems.push((
+                    "mqtt.interval".to_owned(),
+                    "0".to_owned(),
+                    "has to be at least a second".to_owned(),
+                ));
+            }
+        }
+        if let Some(snmp) = &self.snmp {
+            if snmp.switch.port() == 0 {
+                problems.push((
+                    "snmp.switch".to_owned(),
+                    quoted(&snmp.switch),
+                    "port 0 can't be asked".to_owned(),
+                ));
+            }
+            if snmp.timeout_ms == 0 {
+                problems.push((
+                    "snmp.timeout_ms".to_owned(),
+                    "0".to_owned(),
+                    "no switch can answer that fast".to_owned(),
+                ));
+            }
+        }
+        if let Some(send_queue) = &self.send_queue {
+            if send_queue.max_age == 0 {
+                problems.push((
+                    "send_queue.max_age".to_owned(),
+                    "0".to_owned(),
+                    "queued wakes would be given up on right away".to_owned(),
+                ));
+            }
+            if send_queue.size == 0 {
+                problems.push((
+                    "send_queue.size".to_owned(),
+                    "0".to_owned(),
+                    "nothing could be queued".to_owned(),
+                ));
+            }
+        }
+        if self.hooks.as_ref().is_some_and(|hooks| hooks.timeout == 0) {
+            problems.push((
+                "hooks.timeout".to_owned(),
+                "0".to_owned(),
+                "no command finishes that fast".to_owned(),
+            ));
+        }
+        if let Some(limits) = &self.limits {
+            let zero = [
+                ("limits.history", limits.history),
+                ("limits.jobs", limits.jobs),
+                ("limits.hosts", limits.hosts),
+                ("limits.relay_sources", limits.relay_sources),
+                ("limits.events", limits.events),
+            ];
+            for (key, _) in zero.into_iter().filter(|(_, limit)| *limit == 0) {
+                problems.push((
+                    key.to_owned(),
+                    "0".to_owned(),
+                    "nothing could be remembered".to_owned(),
+                ));
+            }
+        }
+        if let Some(quiet_hours) = self
+            .quiet_hours
+            .as_ref()
+            .filter(|quiet| quiet.start == quiet.end)
+        {
+            problems.push((
+                "quiet_hours.end".to_owned(),
+                quoted(&quiet_hours.end.format("%H:%M")),
+                "the same as `start`, it would never be quiet".to_owned(),
+            ));
+        }
+        if (self.discovery_freshness.as_ref()).is_some_and(|freshness| freshness.sources.is_empty())
+        {
+            problems.push((
+                "discovery_freshness.sources".to_owned(),
+                "[]".to_owned(),
+                "empty, no wake would wait for discovery".to_owned(),
+            ));
+        }
+        for (name, mac) in self.pinned_macs.iter().flatten() {
+            if self
+                .hosts
+                .iter()
+                .flatten()
+                .any(|host| host.name.eq_ignore_ascii_case(name))
+            {
+                problems.push((
+                    format!("pinned_macs.{name}"),
+                    quoted(mac),
+                    "a configured host, its MACs are used already".to_owned(),
+                ));
+            }
+        }
+        for (index, host) in self.hosts.iter().flatten().enumerate() {
+            for (command_index, command) in host.post_wake_commands.iter().enumerate() {
+                if command.trim().is_empty() {
+                    problems.push((
+                        format!("hosts[{index}].post_wake_commands[{command_index}]"),
+                        quoted(command),
+                        "empty command".to_owned(),
+                    ));
+                }
+            }
+            for (strategy_index, strategy) in host.strategies.iter().enumerate() {
+                let key = format!("hosts[{index}].strategies[{strategy_index}]");
+                if strategy.timeout == 0 {
+                    problems.push((
+                        format!("{key}.timeout"),
+                        "0".to_owned(),
+                        "no host comes up that fast".to_owned(),
+                    ));
+                }
+                if let Some(destination) = strategy.destination.filter(|_| strategy.via == Via::Raw)
+                {
+                    problems.push((
+                        format!("{key}.destination"),
+                        quoted(&destination),
+                        "a raw frame goes to the broadcast MAC, not to an address".to_owned(),
+                    ));
+                }
+            }
+            for (destination_index, destination) in host.destinations.iter().enumerate() {
+                let key = format!("hosts[{index}].destinations[{destination_index}]");
+                if destination.port() == 0 {
+                    problems.push((
+                        key,
+                        quoted(destination),
+                        "port 0 can't be sent to".to_owned(),
+                    ));
+                } else if host.destinations[..destination_index].contains(destination) {
+                    problems.push((key, quoted(destination), "listed twice".to_owned()));
+                }
+            }
+            let unready = host
+                .strategies
+                .iter()
+                .any(|strategy| strategy.ready.is_none());
+            if unready && self.verify.as_ref().is_some_and(Vec::is_empty) {
+                problems.push((
+                    "verify".to_owned(),
+                    "[]".to_owned(),
+                    format!(
+                        "`{}` has strategies, without it (or a `ready` for each) it can't be told \
+                         whether one woke the host",
+                        host.name
+                    ),
+                ));
+            }
+        }
+        if let Some(relay) = &self.relay {
+            for (index, destination) in relay.destinations.iter().enumerate() {
+                if destination.port() == 0 {
+                    problems.push((
+                        format!("relay.destinations[{index}]"),
+                        quoted(destination),
+                        "port 0 can't be sent to".to_owned(),
+                    ));
+                }
+            }
+        }
+        if let Some(proxy) = &self.proxy {
+            if !proxy.upstream.starts_with("http://") {
+                problems.push((
+                    "proxy.upstream".to_owned(),
+                    quoted(&proxy.upstream),
+                    "only http:// urls are supported".to_owned(),
+                ));
+            } else if proxy.upstream_parts().is_none() {
+                problems.push((
+                    "proxy.upstream".to_owned(),
+                    quoted(&proxy.upstream),
+                    "not a url with a host".to_owned(),
+                ));
+            }
+            if proxy.max_wait == 0 {
+                problems.push((
+                    "proxy.max_wait".to_owned(),
+                    "0".to_owned(),
+                    "no host comes up that fast".to_owned(),
+                ));
+            }
+        }
+        if let Some(proxy_auth) = &self.proxy_auth {
+            if axum::http::HeaderName::from_bytes(proxy_auth.header.as_bytes()).is_err() {
+                problems.push((
+                    "proxy_auth.header".to_owned(),
+                    quoted(&proxy_auth.header),
+                    "not a valid header name".to_owned(),
+                ));
+            }
+        }
+        for (index, strategy) in self.verify.iter().flatten().enumerate() {
+            if *strategy == Strategy::Tcp(0) {
+                problems.push((
+                    format!("verify[{index}]"),
+                    quoted(strategy),
+                    "port 0 can't be connected to".to_owned(),
+                ));
+            }
+        }
+
+        let sites = self.sites.as_deref().unwrap_or_default();
+        for (index, site) in sites.iter().enumerate() {
+            if let Some(first) = sites[..index]
+                .iter()
+                .position(|other| other.name == site.name)
+            {
+                problems.push((
+                    format!("sites[{index}].name"),
+                    quoted(&site.name),
+                    format!("`sites[{first}]` already has that name"),
+                ));
+            }
+            if let Some(vlan) = site.vlan {
+                if let Some(first) = sites[..index]
+                    .iter()
+                    .position(|other| other.vlan == Some(vlan))
+                {
+                    problems.push((
+                        format!("sites[{index}].vlan"),
+                        vlan.to_string(),
+                        format!("`sites[{first}]` is already on that VLAN"),
+                    ));
+                }
+            }
+            if let Some(broadcast) = site.broadcast.filter(|addr| addr.port() == 0) {
+                problems.push((
+                    format!("sites[{index}].broadcast"),
+                    quoted(&broadcast),
+                    "port 0 can't be sent to".to_owned(),
+                ));
+            }
+            if let Some(ssh) = &site.ssh {
+                // ssh would take them for its own options
+                if ssh.host.is_empty() || ssh.host.starts_with('-') {
+                    problems.push((
+                        format!("sites[{index}].ssh.host"),
+                        quoted(&ssh.host),
+                        "not a host to log in to".to_owned(),
+                    ));
+                }
+                if let Some(user) = ssh
+                    .user
+                    .as_ref()
+                    .filter(|user| user.is_empty() || user.starts_with('-') || user.contains('@'))
+                {
+                    problems.push((
+                        format!("sites[{index}].ssh.user"),
+                        quoted(user),
+                        "not a user to log in as".to_owned(),
+                    ));
+                }
+                let placeholders = ssh.command.matches('{').count();
+                if !ssh.command.contains("{mac}")
+                    || placeholders != ssh.command.matches("{mac}").count()
+                {
+                    problems.push((
+                        format!("sites[{index}].ssh.command"),
+                        quoted(&ssh.command),
+                        "has to have `{mac}`, and nothing else is filled in".to_owned(),
+                    ));
+                }
+                if ssh.timeout == 0 {
+                    problems.push((
+                        format!("sites[{index}].ssh.timeout"),
+                        "0".to_owned(),
+                        "has to be at least a second".to_owned(),
+                    ));
+                }
+                if site.remote.is_some() {
+                    problems.push((
+                        format!("sites[{index}].ssh.host"),
+                        quoted(&ssh.host),
+                        "a site is woken either by its `remote` server or over `ssh`, not both"
+                            .to_owned(),
+                    ));
+                }
+                if site.broadcast.is_some()
+                    || site.interface.is_some()
+                    || !site.discovery.is_empty()
+                {
+                    problems.push((
+                        format!("sites[{index}].ssh.host"),
+                        quoted(&ssh.host),
+                        "the machine there sends the packets, a site woken over ssh can't have its own broadcast, interface or discovery".to_owned(),
+                    ));
+                }
+            }
+            let Some(remote) = &site.remote else {
+                continue;
+            };
+            if !remote.url.starts_with("http://") && !remote.url.starts_with("https://") {
+                problems.push((
+                    format!("sites[{index}].remote.url"),
+                    quoted(&remote.url),
+                    "only http:// and https:// urls are supported".to_owned(),
+                ));
+            }
+            if site.broadcast.is_some() || site.interface.is_some() || !site.discovery.is_empty() {
+                problems.push((
+                    format!("sites[{index}].remote.url"),
+                    quoted(&remote.url),
+                    "the server there sends the packets, a remote site can't have its own broadcast, interface or discovery".to_owned(),
+                ));
+            }
+        }
+
+        let hosts = self.hosts.as_deref().unwrap_or_default();
+        for (index, host) in hosts.iter().enumerate() {
+            if let Some(site) = host
+                .site
+                .as_ref()
+                .filter(|site| !sites.iter().any(|known| known.name == **site))
+            {
+                problems.push((
+                    format!("hosts[{index}].site"),
+                    quoted(site),
+                    "no site has that name".to_owned(),
+                ));
+            }
+            if let Some(first) = hosts[..index]
+                .iter()
+                .position(|other| other.name.eq_ignore_ascii_case(&host.name))
+            {
+                problems.push((
+                    format!("hosts[{index}].name"),
+                    quoted(&host.name),
+                    format!("`hosts[{first}]` already has that name"),
+                ));
+            }
+        }
+
+        let sequences = self.sequences.as_deref().unwrap_or_default();
+        for (index, sequence) in sequences.iter().enumerate() {
+            if let Some(first) = sequences[..index]
+                .iter()
+                .position(|other| other.name == sequence.name)
+            {
+                problems.push((
+                    format!("sequences[{index}].name"),
+                    quoted(&sequence.name),
+                    format!("`sequences[{first}]` already has that name"),
+                ));
+            }
+            if sequence.steps.is_empty() {
+                problems.push((
+                    format!("sequences[{index}].steps"),
+                    "[]".to_owned(),
+                    "a sequence needs at least one step".to_owned(),
+                ));
+            }
+            for (step_index, step) in sequence.steps.iter().enumerate() {
+                if step.ready == Some(Strategy::Tcp(0)) {
+                    problems.push((
+                        format!("sequences[{index}].steps[{step_index}].ready"),
+                        quoted(&Strategy::Tcp(0)),
+                        "port 0 can't be connected to".to_owned(),
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> eyre::Result<ConfigLayer> {
+        // every `WOL_*` is the setting of the same name
+        let sources = RefCell::new(BTreeMap::new());
+        let var = |name: &str| {
+            let value = var(name)?;
+            let key = name.trim_start_matches("WOL_").to_ascii_lowercase();
+            sources.borrow_mut().insert(key, Source::Env);
+            Some(value)
+        };
+        let listen = var("WOL_LISTEN")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|addr| {
+                        addr.trim()
+                            .parse()
+                            .map_err(|e| eyre::eyre!("invalid value for WOL_LISTEN `{addr}`: {e}"))
+                    })
+                    .collect::<eyre::Result<Vec<_>>>()
+            })
+            .transpose()?;
+
+        let broadcast = var("WOL_BROADCAST")
+            .map(|value| {
+                parse_broadcast(&value)
+                    .ok_or_else(|| eyre::eyre!("invalid value for WOL_BROADCAST `{value}`"))
+            })
+            .transpose()?;
+        let hosts = var("WOL_HOSTS")
+            .map(|value| parse_hosts_list(&value).wrap_err("invalid value for WOL_HOSTS"))
+            .transpose()?;
+        let read_only = var("WOL_READ_ONLY")
+            .map(|value| {
+                value.trim().parse().map_err(|_| {
+                    eyre::eyre!("invalid value for WOL_READ_ONLY `{value}`, expected true or false")
+                })
+            })
+            .transpose()?;
+        let nets = |name: &str| {
+            var(name)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .filter(|net| !net.trim().is_empty())
+                        .map(|net| {
+                            parse_net(net.trim())
+                                .ok_or_else(|| eyre::eyre!("invalid value for {name} `{net}`"))
+                        })
+                        .collect::<eyre::Result<Vec<_>>>()
+                })
+                .transpose()
+        };
+
+        Ok(ConfigLayer {
+            listen,
+            default_host: var("WOL_DEFAULT_HOST"),
+            broadcast,
+            interface: var("WOL_INTERFACE"),
+            failover: var("WOL_FAILOVER").map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|interface| !interface.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            }),
+            token: var("WOL_TOKEN").map(Secret::new),
+            url_secret: var("WOL_URL_SECRET").map(Secret::new),
+            hosts,
+            registry: var("WOL_REGISTRY").map(PathBuf::from),
+            pinned_macs: None,
+            wake_timeout: None,
+            retry: None,
+            batch_concurrency: None,
+            discovery: None,
+            verify: None,
+            verify_timeout: None,
+            allow_from: nets("WOL_ALLOW_FROM")?,
+            read_allow_from: nets("WOL_READ_ALLOW_FROM")?,
+            read_only,
+            trusted_proxies: nets("WOL_TRUSTED_PROXIES")?,
+            proxy_auth: None,
+            relay: None,
+            proxy: None,
+            sites: None,
+            neighbor_refresh: None,
+            not_found_ttl: None,
+            online_max_age: None,
+            skip_if_online: None,
+            neighbor_sweep: None,
+            callback_allow: var("WOL_CALLBACK_ALLOW").map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|prefix| !prefix.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            }),
+            telegram: None,
+            schedules: None,
+            schedules_file: var("WOL_SCHEDULES_FILE").map(PathBuf::from),
+            wake_tokens_file: var("WOL_WAKE_TOKENS_FILE").map(PathBuf::from),
+            sequences: None,
+            audit: None,
+            netbios: None,
+            snmp: None,
+            send_queue: None,
+            send_confirmation: None,
+            mqtt: None,
+            hooks: None,
+            limits: None,
+            quiet_hours: None,
+            discovery_freshness: None,
+            self_test: None,
+            log_buffer: None,
+            log_buffer_level: None,
+            index_page: var("WOL_INDEX_PAGE").map(PathBuf::from),
+            sources: sources.take(),
+        })
+    }
+}
+
+/// Parses `name=mac,name=mac|mac`.
+fn parse_hosts_list(value: &str) -> eyre::Result<Vec<StaticHost>> {
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let Some((name, macs)) = entry.split_once('=') else {
+                bail!("expected `name=mac`, found `{entry}`");
+            };
+            StaticHost::new(name, macs.split('|')).map_err(|e| eyre::eyre!(e))
+        })
+        .collect()
+}
+
+/// Either a full socket address or just an IP, which then gets the usual port 9.
+fn parse_broadcast(value: &str) -> Option<SocketAddr> {
+    value
+        .parse()
+        .ok()
+        .or_else(|| Some(SocketAddr::new(value.parse().ok()?, 9)))
+}
+
+fn deserialize_broadcast<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<SocketAddr>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_broadcast(&value)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid broadcast address `{value}`")))
+}
+
+fn deserialize_snmp_agent<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<SocketAddr, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value
+        .parse()
+        .ok()
+        .or_else(|| Some(SocketAddr::new(value.parse().ok()?, 161)))
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid switch address `{value}`")))
+}
+
+fn deserialize_broadcasts<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<SocketAddr>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|value| {
+            parse_broadcast(value).ok_or_else(|| {
+                serde::de::Error::custom(format!("invalid broadcast address `{value}`"))
+            })
+        })
+        .collect()
+}
+
+fn deserialize_pinned_macs<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<BTreeMap<String, MacAddress>>, D::Error> {
+    BTreeMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(name, mac)| match parse_mac_addr(mac.trim()) {
+            Some(parsed) => Ok((name, parsed)),
+            None => Err(serde::de::Error::custom(format!(
+                "invalid mac address `{mac}` for host `{name}`"
+            ))),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn deserialize_level<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<tracing::Level>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value
+        .parse()
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("invalid log level `{value}`")))
+}
+
+/// Either a single address or a list of them.
+fn deserialize_listen<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<SocketAddr>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(SocketAddr),
+        Many(Vec<SocketAddr>),
+    }
+    Ok(Some(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(addr) => vec![addr],
+        OneOrMany::Many(addrs) => addrs,
+    }))
+}
+
+/// A network in CIDR notation, or a single address.
+fn parse_net(value: &str) -> Option<IpNet> {
+    value
+        .parse()
+        .ok()
+        .or_else(|| Some(IpNet::from(value.parse::<IpAddr>().ok()?)))
+}
+
+fn deserialize_nets<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<IpNet>>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|value| {
+            parse_net(value)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid network `{value}`")))
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
@@ -0,0 +1,852 @@
+//! Finding out which hosts are on the network by asking the kernel's neighbor table.
+
+use eyre::{bail, Context};
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+};
+
+use crate::{MacAddress, MacParseError};
+
+pub mod snmp;
+pub mod ssdp;
+
+pub use crate::api::v1::{Backend, NeighborState};
+pub use snmp::Snmp;
+pub use ssdp::Ssdp;
+
+/// An entry of the neighbor table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostEntry {
+    /// The host name, or the IP address as a string while it's not resolved.
+    pub name: String,
+    pub ip: Option<IpAddr>,
+    pub mac: MacAddress,
+    /// The backend that found the name, for backends that know names of their own (like SSDP).
+    /// `None` for entries of the neighbor table, which are named by IP address and DNS.
+    pub named_by: Option<Backend>,
+    /// What the neighbor table says about it, `None` for backends that don't say.
+    pub state: Option<NeighborState>,
+    /// The VLAN a switch has it on, for backends that ask one (like SNMP).
+    pub vlan: Option<u16>,
+}
+
+impl NeighborState {
+    /// The state in the output of `ip neigh`, like `REACHABLE`.
+    pub fn parse(state: &str) -> Option<NeighborState> {
+        Some(match state {
+            "REACHABLE" => NeighborState::Reachable,
+            "STALE" => NeighborState::Stale,
+            "DELAY" => NeighborState::Delay,
+            "PROBE" => NeighborState::Probe,
+            "FAILED" => NeighborState::Failed,
+            "PERMANENT" => NeighborState::Permanent,
+            "NOARP" => NeighborState::Noarp,
+            "INCOMPLETE" => NeighborState::Incomplete,
+            _ => return None,
+        })
+    }
+}
+
+impl HostEntry {
+    /// Whether the host was active recently, going by the state of the entry. Entries that never
+    /// change say nothing about it, for those without a state that it's there is all there is.
+    pub fn is_seen(&self) -> bool {
+        match self.state {
+            Some(NeighborState::Reachable) | None => true,
+            Some(_) => false,
+        }
+    }
+}
+
+/// An entry of a neighbor table like a backend read it, before it's merged with what the others
+/// found. Unlike a [`HostEntry`], it can be one that's skipped, with as much as could be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbor {
+    /// The name the table has for it, if it has one other than the address.
+    pub name: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub mac: Option<MacAddress>,
+    pub interface: Option<String>,
+    pub state: Option<NeighborState>,
+    pub vlan: Option<u16>,
+    /// Why it isn't used, `None` if it is.
+    pub skipped: Option<Skipped>,
+}
+
+/// Why an entry of a neighbor table isn't used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Skipped {
+    /// It has no MAC, because it's still being resolved or failed to be.
+    Incomplete,
+    /// A zero or broadcast MAC, which can't be woken.
+    UnusableMac,
+    /// The line isn't an entry the parser understands.
+    Invalid(ParseError),
+}
+
+impl Neighbor {
+    fn skipped(skipped: Skipped) -> Neighbor {
+        Neighbor {
+            name: None,
+            ip: None,
+            mac: None,
+            interface: None,
+            state: None,
+            vlan: None,
+            skipped: Some(skipped),
+        }
+    }
+
+    /// The entry it is, `None` if it's skipped.
+    pub fn entry(&self) -> Option<HostEntry> {
+        if self.skipped.is_some() {
+            return None;
+        }
+        Some(HostEntry {
+            name: self
+                .name
+                .clone()
+                .or_else(|| self.ip.map(|ip| ip.to_string()))?,
+            ip: self.ip,
+            mac: self.mac?,
+            named_by: None,
+            state: self.state,
+            vlan: self.vlan,
+        })
+    }
+}
+
+impl From<HostEntry> for Neighbor {
+    fn from(entry: HostEntry) -> Neighbor {
+        Neighbor {
+            name: (entry.ip.map(|ip| ip.to_string()).as_ref() != Some(&entry.name))
+                .then_some(entry.name),
+            ip: entry.ip,
+            mac: Some(entry.mac),
+            interface: None,
+            state: entry.state,
+            vlan: entry.vlan,
+            skipped: None,
+        }
+    }
+}
+
+/// What one backend read, see [`HostDiscovery::neighbors`].
+#[derive(Debug)]
+pub struct NeighborTable {
+    /// The backend, like `ip-neigh`.
+    pub source: &'static str,
+    pub neighbors: eyre::Result<Vec<Neighbor>>,
+}
+
+impl NeighborTable {
+    fn found(source: &'static str, entries: eyre::Result<Vec<HostEntry>>) -> NeighborTable {
+        NeighborTable {
+            source,
+            neighbors: entries.map(|entries| entries.into_iter().map(Neighbor::from).collect()),
+        }
+    }
+}
+
+/// The entries that are used, logging how many were skipped for what.
+fn used(source: &str, neighbors: Vec<Neighbor>) -> Vec<HostEntry> {
+    let mut hosts = Vec::new();
+    let mut incomplete = 0;
+    let mut unusable_mac = 0;
+    let mut invalid = Vec::new();
+    for neighbor in neighbors {
+        match neighbor.skipped {
+            None => hosts.extend(neighbor.entry()),
+            Some(Skipped::Incomplete) => incomplete += 1,
+            Some(Skipped::UnusableMac) => unusable_mac += 1,
+            Some(Skipped::Invalid(error)) => invalid.push(error),
+        }
+    }
+    if incomplete + unusable_mac + invalid.len() > 0 {
+        tracing::debug!(
+            source,
+            incomplete,
+            unusable_mac,
+            invalid = invalid.len(),
+            ?invalid,
+            "skipped neighbor entries"
+        );
+    }
+    hosts
+}
+
+pub fn parse_mac_addr(addr: &str) -> Option<MacAddress> {
+    addr.parse().ok()
+}
+
+/// A way of finding the hosts on the network. Discovering blocks, so it's done off the async
+/// runtime like everything else that asks the system.
+pub trait HostDiscovery: Send + Sync {
+    /// The entries it found, with IP addresses as names, see [`resolve_names`].
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>>;
+
+    /// What its backends read, before it's merged, including the entries that are skipped and
+    /// why. It's for debugging discovery, backends that don't say have what they discover.
+    fn neighbors(&self) -> Vec<NeighborTable> {
+        vec![NeighborTable::found("other", self.discover())]
+    }
+}
+
+/// Reading the kernel's table directly where there is one, the `arp` tool everywhere else.
+#[cfg(target_os = "linux")]
+pub const DEFAULT_BACKENDS: [Backend; 1] = [Backend::ProcNetArp];
+#[cfg(not(target_os = "linux"))]
+pub const DEFAULT_BACKENDS: [Backend; 1] = [Backend::Arp];
+
+impl Backend {
+    pub fn discovery(self) -> Box<dyn HostDiscovery> {
+        match self {
+            Backend::Arp => Box::new(ArpCommand),
+            Backend::ProcNetArp => Box::new(ProcNetArp),
+            Backend::IpNeigh => Box::new(IpNeigh),
+            Backend::Ssdp => Box::new(Ssdp::new(Box::new(Composite::new(&DEFAULT_BACKENDS)))),
+        }
+    }
+}
+
+pub struct ArpCommand;
+
+impl HostDiscovery for ArpCommand {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        read_arp_table()
+    }
+
+    fn neighbors(&self) -> Vec<NeighborTable> {
+        vec![NeighborTable {
+            source: "arp",
+            neighbors: run_arp().map(|output| arp_neighbors(&output)),
+        }]
+    }
+}
+
+pub struct ProcNetArp;
+
+impl HostDiscovery for ProcNetArp {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        Ok(parse_proc_net_arp(&read_proc_net_arp()?))
+    }
+
+    fn neighbors(&self) -> Vec<NeighborTable> {
+        vec![NeighborTable {
+            source: "proc-net-arp",
+            neighbors: read_proc_net_arp().map(|table| proc_net_arp_neighbors(&table)),
+        }]
+    }
+}
+
+fn read_proc_net_arp() -> eyre::Result<String> {
+    std::fs::read_to_string("/proc/net/arp").wrap_err("reading /proc/net/arp")
+}
+
+pub struct IpNeigh;
+
+impl HostDiscovery for IpNeigh {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        Ok(parse_ip_neigh(&run_ip_neigh()?))
+    }
+
+    fn neighbors(&self) -> Vec<NeighborTable> {
+        vec![NeighborTable {
+            source: "ip-neigh",
+            neighbors: run_ip_neigh().map(|output| ip_neigh_neighbors(&output)),
+        }]
+    }
+}
+
+fn run_ip_neigh() -> eyre::Result<String> {
+    let ip = std::process::Command::new("ip")
+        .arg("neigh")
+        .env("LC_ALL", "C")
+        .output()
+        .wrap_err("spawning `ip neigh`")?;
+    if !ip.status.success() {
+        bail!("ip neigh failed: {}", String::from_utf8_lossy(&ip.stderr));
+    }
+    String::from_utf8(ip.stdout).wrap_err("ip returned non-utf-8 output")
+}
+
+/// Always finds the same hosts, for tests or hosts that are known some other way.
+pub struct StaticDiscovery(pub Vec<HostEntry>);
+
+impl HostDiscovery for StaticDiscovery {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        Ok(self.0.clone())
+    }
+
+    fn neighbors(&self) -> Vec<NeighborTable> {
+        vec![NeighborTable::found("static", self.discover())]
+    }
+}
+
+/// Asks all of the backends, merging what they found. An entry found by more than one (the same
+/// MAC at the same address) is only kept once, with the name a backend knows if one does and
+/// the state and VLAN of the backends that have them. An entry without an address (like from a
+/// switch) only adds its VLAN to the entries with its MAC, if there are any.
+/// Backends that fail are skipped, it only fails if all of them do.
+pub struct Composite(pub Vec<Box<dyn HostDiscovery>>);
+
+impl Composite {
+    pub fn new(backends: &[Backend]) -> Composite {
+        Composite(backends.iter().map(|backend| backend.discovery()).collect())
+    }
+}
+
+impl HostDiscovery for Composite {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        let mut hosts: Vec<HostEntry> = Vec::new();
+        let mut error = None;
+        let mut succeeded = false;
+        for backend in &self.0 {
+            match backend.discover() {
+                Ok(entries) => {
+                    succeeded = true;
+                    for entry in entries {
+                        merge(&mut hosts, entry);
+                    }
+                }
+                Err(e) => {
+                    tracing::debug!(?e, "discovery backend failed");
+                    error = Some(e);
+                }
+            }
+        }
+        match error {
+            Some(e) if !succeeded => Err(e),
+            _ => Ok(hosts),
+        }
+    }
+
+    fn neighbors(&self) -> Vec<NeighborTable> {
+        self.0
+            .iter()
+            .flat_map(|backend| backend.neighbors())
+            .collect()
+    }
+}
+
+fn merge(hosts: &mut Vec<HostEntry>, entry: HostEntry) {
+    if entry.ip.is_none() && hosts.iter().any(|host| host.mac == entry.mac) {
+        for host in hosts.iter_mut().filter(|host| host.mac == entry.mac) {
+            host.vlan = host.vlan.or(entry.vlan);
+        }
+        return;
+    }
+    // one without an address that was found first is replaced
+    let same =
+        |host: &&mut HostEntry| host.mac == entry.mac && (host.ip == entry.ip || host.ip.is_none());
+    match hosts.iter_mut().find(same) {
+        Some(host)
+            if (host.named_by.is_none() && entry.named_by.is_some()) || host.ip.is_none() =>
+        {
+            let (state, vlan) = (entry.state.or(host.state), entry.vlan.or(host.vlan));
+            *host = HostEntry {
+                state,
+                vlan,
+                ..entry
+            };
+        }
+        Some(host) => {
+            host.state = host.state.or(entry.state);
+            host.vlan = host.vlan.or(entry.vlan);
+        }
+        None => hosts.push(entry),
+    }
+}
+
+/// Reads the neighbor table from `arp`, without resolving any names.
+pub fn read_arp_table() -> eyre::Result<Vec<HostEntry>> {
+    Ok(parse_neighbor_table(&run_arp()?)?)
+}
+
+fn run_arp() -> eyre::Result<String> {
+    // TODO: It would be very cool to instead read /proc/net/arp and then call getnameinfo but that's annoying...
+    // Localized output would trip up the parser, and arp's own name resolution gives us less
+    // control than doing it ourselves, so ask for plain numeric output.
+    let arp = std::process::Command::new("arp")
+        .arg("-n")
+        .env("LC_ALL", "C")
+        .env("LANG", "C")
+        .output()
+        .wrap_err("spwaning `arp`")?;
+    if !arp.status.success() {
+        bail!("arp failed: {}", String::from_utf8_lossy(&arp.stderr));
+    }
+    String::from_utf8(arp.stdout).wrap_err("arp returned non-utf-8 output")
+}
+
+/// The MACs of the first discovered host whose name contains `host`,
+/// which are all of them if it's in the neighbor table with several NICs.
+pub fn find_host(hosts: &[HostEntry], host: &str) -> Option<Vec<MacAddress>> {
+    find_host_named(hosts, host).map(|(_, macs)| macs)
+}
+
+/// Like [`find_host`], with the name of the host that was found.
+pub fn find_host_named<'a>(
+    hosts: &'a [HostEntry],
+    host: &str,
+) -> Option<(&'a str, Vec<MacAddress>)> {
+    let found = hosts.iter().find(|entry| entry.name.contains(host))?;
+    let mut macs = Vec::new();
+    for entry in hosts.iter().filter(|entry| entry.name == found.name) {
+        // the same NIC can be there with an IPv4 and an IPv6 address
+        if !macs.contains(&entry.mac) {
+            macs.push(entry.mac);
+        }
+    }
+    Some((&found.name, macs))
+}
+
+/// The IP address the discovered hosts have for any of `macs`, if any.
+pub fn find_ip(hosts: &[HostEntry], macs: &[MacAddress]) -> Option<IpAddr> {
+    hosts
+        .iter()
+        .filter(|entry| macs.contains(&entry.mac))
+        .find_map(|entry| entry.ip)
+}
+
+/// Sends an empty UDP packet to the discard port of each address, so the kernel asks for them
+/// on the network and puts whoever answers into the neighbor table. Returns how many were sent.
+pub fn poke(ips: &[IpAddr]) -> usize {
+    let v4 = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)));
+    let v6 = UdpSocket::bind(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)));
+    ips.iter()
+        .filter(|ip| {
+            let socket = match ip {
+                IpAddr::V4(_) => &v4,
+                IpAddr::V6(_) => &v6,
+            };
+            let Ok(socket) = socket else {
+                return false;
+            };
+            socket
+                .send_to(&[], SocketAddr::new(**ip, 9))
+                .inspect_err(|e| tracing::debug!(?e, %ip, "failed to poke"))
+                .is_ok()
+        })
+        .count()
+}
+
+/// Replaces IP addresses with their host names (if they have one), using reverse DNS.
+pub fn resolve_names(hosts: Vec<HostEntry>) -> Vec<HostEntry> {
+    hosts
+        .into_iter()
+        .map(|entry| {
+            let Ok(ip) = entry.name.parse::<IpAddr>() else {
+                return entry;
+            };
+            match dns_lookup::lookup_addr(&ip) {
+                Ok(name) => HostEntry { name, ..entry },
+                Err(e) => {
+                    tracing::debug!(?e, %ip, "reverse lookup failed");
+                    entry
+                }
+            }
+        })
+        .collect()
+}
+
+/// Parses the output of `arp` into its entries.
+///
+/// Both the Linux net-tools table format and the BSD-style `name (ip) at mac` format
+/// (which busybox also uses) are understood. Instead of relying on fixed columns, the MAC is
+/// whichever field looks like one, since flags and masks are only there for some entries.
+///
+/// Entries that can't be woken (still being resolved, or with a zero or broadcast MAC)
+/// are skipped, as are lines that don't look like entries at all, see [`arp_neighbors`].
+/// It only fails if there are lines but all of them are garbage, with what's wrong with the first.
+pub fn parse_neighbor_table(output: &str) -> Result<Vec<HostEntry>, ParseError> {
+    let neighbors = arp_neighbors(output);
+    if neighbors
+        .iter()
+        .all(|neighbor| matches!(neighbor.skipped, Some(Skipped::Invalid(_))))
+    {
+        if let Some(Skipped::Invalid(error)) = neighbors.into_iter().next().and_then(|n| n.skipped)
+        {
+            return Err(error);
+        }
+        return Ok(Vec::new());
+    }
+    Ok(used("arp", neighbors))
+}
+
+/// Parses the output of `arp` like [`parse_neighbor_table`], keeping the lines it skips.
+pub fn arp_neighbors(output: &str) -> Vec<Neighbor> {
+    let mut lines = output
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .peekable();
+    // net-tools prints a header, BSD and busybox don't
+    if lines.peek().is_some_and(|(_, line)| is_header(line)) {
+        lines.next();
+    }
+
+    lines
+        .map(|(index, line)| {
+            parse_line(line).unwrap_or_else(|kind| {
+                Neighbor::skipped(Skipped::Invalid(ParseError {
+                    line: index + 1,
+                    kind,
+                    text: line.to_owned(),
+                }))
+            })
+        })
+        .collect()
+}
+
+/// A line of the neighbor table that isn't an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Counting from 1.
+    pub line: usize,
+    pub kind: ParseErrorKind,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// Not even a name, a hardware type and a MAC.
+    TooFewFields,
+    /// No field looks like a MAC.
+    MissingMac,
+    /// The field (counting from 1) looks like a MAC, but isn't one.
+    BadHex { field: usize, error: MacParseError },
+    /// The first field is a MAC, where the name or address should be.
+    MissingName,
+    /// The field where the IP address should be isn't one.
+    BadAddress,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unrecognized neighbor table line {}: ", self.line)?;
+        match &self.kind {
+            ParseErrorKind::TooFewFields => write!(f, "too few fields")?,
+            ParseErrorKind::MissingMac => write!(f, "no mac address")?,
+            ParseErrorKind::BadHex { field, error } => {
+                write!(f, "invalid mac address in field {field}: {error}")?
+            }
+            ParseErrorKind::MissingName => write!(f, "no name or address")?,
+            ParseErrorKind::BadAddress => write!(f, "invalid ip address")?,
+        }
+        write!(f, " in {:?}", self.text)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn is_incomplete(token: &str) -> bool {
+    matches!(token, "(incomplete)" | "<incomplete>")
+}
+
+fn is_header(line: &str) -> bool {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    !tokens
+        .iter()
+        .any(|token| is_incomplete(token) || parse_mac_addr(token).is_some())
+}
+
+/// Whether a field is meant to be a MAC, even if it isn't a valid one.
+fn looks_like_mac(token: &str) -> bool {
+    [':', '-']
+        .iter()
+        .any(|&sep| token.chars().filter(|&c| c == sep).count() == 5)
+}
+
+/// The field (counting from 1) that looks like a MAC but isn't one, with what's wrong with it.
+fn bad_hex(tokens: &[&str]) -> Option<ParseErrorKind> {
+    tokens.iter().enumerate().find_map(|(index, token)| {
+        let error = token.parse::<MacAddress>().err()?;
+        looks_like_mac(token).then_some(ParseErrorKind::BadHex {
+            field: index + 1,
+            error,
+        })
+    })
+}
+
+fn parse_line(line: &str) -> Result<Neighbor, ParseErrorKind> {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+
+    let (name, ip) = match tokens.as_slice() {
+        // BSD style: `name (ip) at mac ... on interface`, where the name is `?` if it's unknown
+        [name, ip, ..] if ip.starts_with('(') && ip.ends_with(')') && !is_incomplete(ip) => {
+            let ip = ip.trim_start_matches('(').trim_end_matches(')');
+            let name = if *name == "?" { ip } else { name };
+            (name, ip.parse::<IpAddr>().ok())
+        }
+        // net-tools style: `name-or-ip hwtype mac ... interface`
+        [name, ..] => (*name, name.parse().ok()),
+        [] => return Err(ParseErrorKind::TooFewFields),
+    };
+    let interface = match tokens.iter().position(|&token| token == "on") {
+        Some(on) => tokens.get(on + 1).copied(),
+        None if tokens.len() > 2 => tokens.last().copied(),
+        None => None,
+    };
+    let neighbor = Neighbor {
+        name: (ip.map(|ip| ip.to_string()).as_deref() != Some(name)).then(|| name.to_owned()),
+        ip,
+        mac: None,
+        interface: interface.map(str::to_owned),
+        state: tokens
+            .contains(&"permanent")
+            .then_some(NeighborState::Permanent),
+        vlan: None,
+        skipped: None,
+    };
+
+    if tokens.iter().any(|token| is_incomplete(token)) {
+        return Ok(Neighbor {
+            skipped: Some(Skipped::Incomplete),
+            ..neighbor
+        });
+    }
+    let Some(mac) = tokens.iter().find_map(|token| parse_mac_addr(token)) else {
+        if let Some(kind) = bad_hex(&tokens) {
+            return Err(kind);
+        }
+        return Err(if tokens.len() < 3 {
+            ParseErrorKind::TooFewFields
+        } else {
+            ParseErrorKind::MissingMac
+        });
+    };
+    if !is_usable(mac) {
+        return Ok(Neighbor {
+            mac: Some(mac),
+            skipped: Some(Skipped::UnusableMac),
+            ..neighbor
+        });
+    }
+    if parse_mac_addr(name).is_some() {
+        return Err(ParseErrorKind::MissingName);
+    }
+
+    Ok(Neighbor {
+        mac: Some(mac),
+        ..neighbor
+    })
+}
+
+fn is_usable(mac: MacAddress) -> bool {
+    mac != MacAddress([0; 6]) && mac != MacAddress([0xff; 6])
+}
+
+/// The formats of neighbor tables that can be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    /// `arp` on Linux (net-tools or busybox), macOS and the BSDs, see [`parse_neighbor_table`].
+    Arp,
+    /// `/proc/net/arp`, see [`parse_proc_net_arp`].
+    ProcNetArp,
+    /// `ip neigh`, see [`parse_ip_neigh`].
+    IpNeigh,
+    /// `arp -a` on Windows, see [`parse_windows_arp`].
+    WindowsArp,
+}
+
+impl TableFormat {
+    /// Guesses the format from what the output looks like, it's [`TableFormat::Arp`] unless it
+    /// looks like one of the others.
+    pub fn detect(output: &str) -> TableFormat {
+        let mut lines = output.lines().filter(|line| !line.trim().is_empty());
+        let first = lines.clone().next().unwrap_or_default();
+        if first.starts_with("IP address") && first.contains("HW type") {
+            return TableFormat::ProcNetArp;
+        }
+        if lines
+            .clone()
+            .any(|line| line.trim_start().starts_with("Interface:") && line.contains(" --- "))
+        {
+            return TableFormat::WindowsArp;
+        }
+        if lines.any(|line| line.split_whitespace().nth(1) == Some("dev")) {
+            return TableFormat::IpNeigh;
+        }
+        TableFormat::Arp
+    }
+}
+
+/// Parses a neighbor table in the format, or the one it looks like without one.
+/// Only [`TableFormat::Arp`] can fail, the others skip what they don't understand.
+pub fn parse_table(
+    output: &str,
+    format: Option<TableFormat>,
+) -> Result<Vec<HostEntry>, ParseError> {
+    match format.unwrap_or_else(|| TableFormat::detect(output)) {
+        TableFormat::Arp => parse_neighbor_table(output),
+        TableFormat::ProcNetArp => Ok(parse_proc_net_arp(output)),
+        TableFormat::IpNeigh => Ok(parse_ip_neigh(output)),
+        TableFormat::WindowsArp => Ok(parse_windows_arp(output)),
+    }
+}
+
+/// Parses `/proc/net/arp`: `ip hwtype flags mac mask device`, under a header.
+/// Incomplete entries (with no flags) and lines that don't parse are skipped. The flags only
+/// tell permanent entries apart, the others have no state.
+pub fn parse_proc_net_arp(table: &str) -> Vec<HostEntry> {
+    used("proc-net-arp", proc_net_arp_neighbors(table))
+}
+
+/// Parses `/proc/net/arp` like [`parse_proc_net_arp`], keeping the lines it skips.
+pub fn proc_net_arp_neighbors(table: &str) -> Vec<Neighbor> {
+    table
+        .lines()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let invalid = |kind| {
+                Neighbor::skipped(Skipped::Invalid(ParseError {
+                    line: index + 1,
+                    kind,
+                    text: line.to_owned(),
+                }))
+            };
+            let tokens = line.split_whitespace().collect::<Vec<_>>();
+            let [ip, _, flags, mac, ..] = tokens[..] else {
+                return invalid(ParseErrorKind::TooFewFields);
+            };
+            let Ok(ip) = ip.parse() else {
+                return invalid(ParseErrorKind::BadAddress);
+            };
+            // ATF_PERM
+            let permanent = u32::from_str_radix(flags.trim_start_matches("0x"), 16)
+                .is_ok_and(|flags| flags & 0x4 != 0);
+            let neighbor = Neighbor {
+                ip: Some(ip),
+                interface: tokens.get(5).map(|&device| device.to_owned()),
+                state: permanent.then_some(NeighborState::Permanent),
+                ..Neighbor::skipped(Skipped::Incomplete)
+            };
+            if flags == "0x0" {
+                return neighbor;
+            }
+            let mac = match mac.parse::<MacAddress>() {
+                Ok(mac) => mac,
+                Err(error) => return invalid(ParseErrorKind::BadHex { field: 4, error }),
+            };
+            Neighbor {
+                mac: Some(mac),
+                skipped: (!is_usable(mac)).then_some(Skipped::UnusableMac),
+                ..neighbor
+            }
+        })
+        .collect()
+}
+
+/// Parses `ip neigh`: `ip dev eth0 lladdr mac state`. Entries without a MAC (that are still being
+/// resolved, or failed to be) are skipped, failed ones that still have their MAC are kept.
+pub fn parse_ip_neigh(output: &str) -> Vec<HostEntry> {
+    used("ip-neigh", ip_neigh_neighbors(output))
+}
+
+/// Parses `ip neigh` like [`parse_ip_neigh`], keeping the lines it skips.
+pub fn ip_neigh_neighbors(output: &str) -> Vec<Neighbor> {
+    output
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let invalid = |kind| {
+                Neighbor::skipped(Skipped::Invalid(ParseError {
+                    line: index + 1,
+                    kind,
+                    text: line.to_owned(),
+                }))
+            };
+            let tokens = line.split_whitespace().collect::<Vec<_>>();
+            let Ok(ip) = tokens[0].parse::<IpAddr>() else {
+                return invalid(ParseErrorKind::BadAddress);
+            };
+            let after = |keyword| {
+                let position = tokens.iter().position(|&token| token == keyword)?;
+                tokens.get(position + 1).copied()
+            };
+            let neighbor = Neighbor {
+                ip: Some(ip),
+                interface: after("dev").map(str::to_owned),
+                state: tokens.last().and_then(|state| NeighborState::parse(state)),
+                ..Neighbor::skipped(Skipped::Incomplete)
+            };
+            if !tokens.contains(&"lladdr") {
+                return neighbor;
+            }
+            let Some(mac) = after("lladdr") else {
+                return invalid(ParseErrorKind::MissingMac);
+            };
+            let mac = match mac.parse::<MacAddress>() {
+                Ok(mac) => mac,
+                Err(error) => {
+                    let field = tokens.iter().position(|&token| token == mac).unwrap_or(0) + 1;
+                    return invalid(ParseErrorKind::BadHex { field, error });
+                }
+            };
+            Neighbor {
+                mac: Some(mac),
+                skipped: (!is_usable(mac)).then_some(Skipped::UnusableMac),
+                ..neighbor
+            }
+        })
+        .collect()
+}
+
+/// Parses `arp -a` on Windows: a table of `ip mac type` under a header for every interface, with
+/// dashes in the MACs. Lines that aren't entries are skipped.
+pub fn parse_windows_arp(output: &str) -> Vec<HostEntry> {
+    used("windows-arp", windows_arp_neighbors(output))
+}
+
+/// Parses `arp -a` on Windows like [`parse_windows_arp`], keeping the entries it skips. Lines
+/// that don't start with an address aren't entries, so they aren't kept. The interfaces are
+/// named by their address, like Windows does.
+pub fn windows_arp_neighbors(output: &str) -> Vec<Neighbor> {
+    let mut interface = None;
+    let mut neighbors = Vec::new();
+    for (index, line) in output.lines().enumerate() {
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        if let ["Interface:", address, "---", ..] = tokens[..] {
+            interface = Some(address.to_owned());
+            continue;
+        }
+        let Some(Ok(ip)) = tokens.first().map(|ip| ip.parse::<IpAddr>()) else {
+            continue;
+        };
+        let neighbor = Neighbor {
+            ip: Some(ip),
+            interface: interface.clone(),
+            state: tokens
+                .get(2)
+                .is_some_and(|&kind| kind == "static")
+                .then_some(NeighborState::Permanent),
+            ..Neighbor::skipped(Skipped::Incomplete)
+        };
+        let [_, mac, _] = tokens[..] else {
+            neighbors.push(Neighbor::skipped(Skipped::Invalid(ParseError {
+                line: index + 1,
+                kind: ParseErrorKind::TooFewFields,
+                text: line.to_owned(),
+            })));
+            continue;
+        };
+        neighbors.push(match mac.parse::<MacAddress>() {
+            Ok(mac) => Neighbor {
+                mac: Some(mac),
+                skipped: (!is_usable(mac)).then_some(Skipped::UnusableMac),
+                ..neighbor
+            },
+            Err(error) => Neighbor::skipped(Skipped::Invalid(ParseError {
+                line: index + 1,
+                kind: ParseErrorKind::BadHex { field: 2, error },
+                text: line.to_owned(),
+            })),
+        });
+    }
+    neighbors
+}
@@ -0,0 +1,390 @@
+//! Discovering the MACs a managed switch has seen on its ports, by walking its forwarding database
+//! with SNMP v2c. It knows the hosts that never talk to this server, like those on other VLANs.
+
+use eyre::{bail, ensure, Context};
+use std::{
+    collections::HashSet,
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{is_usable, HostDiscovery, HostEntry, NeighborTable};
+use crate::{config::SnmpConfig, secret::Secret, MacAddress};
+
+/// How long a walk is used for, they take a while on big switches.
+const CACHE_FOR: Duration = Duration::from_secs(60);
+/// How often a request is sent before the switch counts as not answering.
+const ATTEMPTS: u32 = 2;
+/// How many entries the switch is asked for at once.
+const MAX_REPETITIONS: i64 = 25;
+
+/// `dot1qTpFdbPort` of Q-BRIDGE-MIB, indexed by the forwarding database (which is the VLAN on
+/// most switches) and the MAC.
+pub const Q_BRIDGE_FDB_PORT: &[u32] = &[1, 3, 6, 1, 2, 1, 17, 7, 1, 2, 2, 1, 2];
+/// `dot1dTpFdbPort` of BRIDGE-MIB, indexed by the MAC, for switches that don't know VLANs.
+pub const BRIDGE_FDB_PORT: &[u32] = &[1, 3, 6, 1, 2, 1, 17, 4, 3, 1, 2];
+
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OID: u8 = 0x06;
+const SEQUENCE: u8 = 0x30;
+const COUNTER32: u8 = 0x41;
+const GAUGE32: u8 = 0x42;
+const TIMETICKS: u8 = 0x43;
+const END_OF_MIB_VIEW: u8 = 0x82;
+const RESPONSE: u8 = 0xa2;
+const GET_BULK: u8 = 0xa5;
+const VERSION_2C: i64 = 1;
+
+/// Finds the MACs in the forwarding database of the switch, named by their MAC and without an
+/// address, with the VLAN if the switch knows it. If the switch doesn't answer (which is also
+/// what it does with the wrong community), that's logged and what it said last is used.
+pub struct Snmp {
+    switch: SocketAddr,
+    community: Secret<String>,
+    timeout: Duration,
+    /// When it was last asked, with what it found.
+    found: Mutex<Option<(Instant, Vec<HostEntry>)>>,
+}
+
+impl Snmp {
+    pub fn new(config: &SnmpConfig) -> Self {
+        Self {
+            switch: config.switch,
+            community: config.community.clone(),
+            timeout: Duration::from_millis(config.timeout_ms),
+            found: Mutex::new(None),
+        }
+    }
+
+    /// Walks Q-BRIDGE-MIB, or BRIDGE-MIB if the switch has nothing there.
+    fn walk_fdb(&self) -> eyre::Result<Vec<HostEntry>> {
+        let bind = match self.switch {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(bind).wrap_err("binding SNMP socket")?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        socket.connect(self.switch)?;
+        let mut client = Client {
+            socket,
+            community: self.community.as_str().as_bytes(),
+            timeout: self.timeout,
+            request_id: fastrand::i32(1..i32::MAX / 2),
+        };
+        let rows = client.walk(Q_BRIDGE_FDB_PORT)?;
+        if !rows.is_empty() {
+            return Ok(fdb_entries(Q_BRIDGE_FDB_PORT, &rows));
+        }
+        Ok(fdb_entries(BRIDGE_FDB_PORT, &client.walk(BRIDGE_FDB_PORT)?))
+    }
+}
+
+impl HostDiscovery for Snmp {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        let mut found = self.found.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((at, entries)) = &*found {
+            if at.elapsed() < CACHE_FOR {
+                return Ok(entries.clone());
+            }
+        }
+        let entries = match self.walk_fdb() {
+            Ok(entries) => entries,
+            Err(e) => {
+                // it's not asked again until the cache would have run out
+                tracing::warn!(?e, switch = %self.switch, "failed to read the forwarding database of the switch");
+                found.take().map(|(_, entries)| entries).unwrap_or_default()
+            }
+        };
+        *found = Some((Instant::now(), entries.clone()));
+        Ok(entries)
+    }
+
+    fn neighbors(&self) -> Vec<NeighborTable> {
+        vec![NeighborTable::found("snmp", self.discover())]
+    }
+}
+
+/// The entries of the walked column, whose index is the MAC, after the forwarding database for
+/// Q-BRIDGE-MIB. Entries with port 0 are the switch's own.
+fn fdb_entries(column: &[u32], rows: &[(Vec<u32>, Value)]) -> Vec<HostEntry> {
+    let mut entries = Vec::new();
+    let mut macs = HashSet::new();
+    for (oid, value) in rows {
+        let (vlan, mac) = match &oid[column.len()..] {
+            [fdb, mac @ ..] if column == Q_BRIDGE_FDB_PORT => (u16::try_from(*fdb).ok(), mac),
+            mac => (None, mac),
+        };
+        let Ok(mac) = mac
+            .iter()
+            .map(|&octet| u8::try_from(octet))
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            continue;
+        };
+        let Ok(mac) = <[u8; 6]>::try_from(mac).map(MacAddress) else {
+            continue;
+        };
+        if !is_usable(mac) || matches!(value, Value::Integer(0)) {
+            continue;
+        }
+        // a MAC that's in several VLANs is kept with the first
+        if !macs.insert(mac) {
+            continue;
+        }
+        entries.push(HostEntry {
+            name: mac.to_string(),
+            ip: None,
+            mac,
+            named_by: None,
+            state: None,
+            vlan,
+        });
+    }
+    entries
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Integer(i64),
+    EndOfMibView,
+    /// Anything a forwarding database doesn't have.
+    Other,
+}
+
+struct Client<'a> {
+    socket: UdpSocket,
+    community: &'a [u8],
+    timeout: Duration,
+    request_id: i32,
+}
+
+impl Client<'_> {
+    /// Everything under the OID.
+    fn walk(&mut self, base: &[u32]) -> eyre::Result<Vec<(Vec<u32>, Value)>> {
+        let mut rows = Vec::new();
+        let mut next = base.to_vec();
+        loop {
+            let varbinds = self.get_bulk(&next)?;
+            if varbinds.is_empty() {
+                return Ok(rows);
+            }
+            for (oid, value) in varbinds {
+                if !oid.starts_with(base) || value == Value::EndOfMibView {
+                    return Ok(rows);
+                }
+                // it would never end otherwise
+                ensure!(oid > next, "the switch went back to {oid:?} after {next:?}");
+                next.clone_from(&oid);
+                rows.push((oid, value));
+            }
+        }
+    }
+
+    /// The entries after the OID, retrying if the switch doesn't answer in time.
+    fn get_bulk(&mut self, oid: &[u32]) -> eyre::Result<Vec<(Vec<u32>, Value)>> {
+        self.request_id = self.request_id.wrapping_add(1);
+        let request = encode_get_bulk(self.community, self.request_id, oid);
+        let mut buf = vec![0; 65535];
+        for _ in 0..ATTEMPTS {
+            self.socket
+                .send(&request)
+                .wrap_err("sending SNMP request")?;
+            loop {
+                let len = match self.socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        break
+                    }
+                    Err(e) => return Err(e).wrap_err("receiving SNMP response"),
+                };
+                let response = match decode_response(&buf[..len]) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        tracing::debug!(?e, "invalid SNMP response");
+                        continue;
+                    }
+                };
+                // one that came too late for an earlier attempt
+                if response.request_id != self.request_id {
+                    continue;
+                }
+                if response.error_status != 0 {
+                    bail!(
+                        "the switch answered with error {}",
+                        error_name(response.error_status)
+                    );
+                }
+                return Ok(response.varbinds);
+            }
+        }
+        bail!(
+            "the switch didn't answer within {:?} (is the community right?)",
+            self.timeout
+        )
+    }
+}
+
+fn error_name(status: i64) -> String {
+    match status {
+        1 => "tooBig".to_owned(),
+        2 => "noSuchName".to_owned(),
+        5 => "genErr".to_owned(),
+        6 => "noAccess".to_owned(),
+        16 => "authorizationError".to_owned(),
+        status => status.to_string(),
+    }
+}
+
+struct Response {
+    request_id: i32,
+    error_status: i64,
+    varbinds: Vec<(Vec<u32>, Value)>,
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    match content.len() {
+        len @ 0..=0x7f => encoded.push(len as u8),
+        len @ 0x80..=0xff => encoded.extend([0x81, len as u8]),
+        len => encoded.extend([0x82, (len >> 8) as u8, len as u8]),
+    }
+    encoded.extend_from_slice(content);
+    encoded
+}
+
+fn encode_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // the fewest bytes that still have the sign
+    let mut start = 0;
+    while start < 7
+        && ((bytes[start] == 0 && bytes[start + 1] & 0x80 == 0)
+            || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    tlv(INTEGER, &bytes[start..])
+}
+
+fn encode_oid(arcs: &[u32]) -> Vec<u8> {
+    let mut content = vec![(arcs[0] * 40 + arcs[1]) as u8];
+    for &arc in &arcs[2..] {
+        let mut septets = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            septets.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        content.extend(septets.iter().rev());
+    }
+    tlv(OID, &content)
+}
+
+fn encode_get_bulk(community: &[u8], request_id: i32, oid: &[u32]) -> Vec<u8> {
+    let varbind = tlv(SEQUENCE, &[encode_oid(oid), tlv(NULL, &[])].concat());
+    let pdu = [
+        encode_integer(request_id.into()),
+        // non-repeaters
+        encode_integer(0),
+        encode_integer(MAX_REPETITIONS),
+        tlv(SEQUENCE, &varbind),
+    ];
+    let message = [
+        encode_integer(VERSION_2C),
+        tlv(OCTET_STRING, community),
+        tlv(GET_BULK, &pdu.concat()),
+    ];
+    tlv(SEQUENCE, &message.concat())
+}
+
+/// The tag, the content and what comes after it.
+fn read_tlv(data: &[u8]) -> eyre::Result<(u8, &[u8], &[u8])> {
+    let [tag, first, rest @ ..] = data else {
+        bail!("truncated");
+    };
+    let (len, rest) = if first & 0x80 == 0 {
+        (usize::from(*first), rest)
+    } else {
+        let count = usize::from(first & 0x7f);
+        ensure!(count <= 4 && rest.len() >= count, "invalid length");
+        let len = rest[..count]
+            .iter()
+            .fold(0, |len, &byte| len << 8 | usize::from(byte));
+        (len, &rest[count..])
+    };
+    ensure!(rest.len() >= len, "truncated");
+    Ok((*tag, &rest[..len], &rest[len..]))
+}
+
+/// The content and what comes after it.
+fn expect(data: &[u8], tag: u8) -> eyre::Result<(&[u8], &[u8])> {
+    let (found, content, rest) = read_tlv(data)?;
+    ensure!(found == tag, "expected tag {tag:#x}, found {found:#x}");
+    Ok((content, rest))
+}
+
+fn decode_integer(content: &[u8]) -> eyre::Result<i64> {
+    ensure!(!content.is_empty() && content.len() <= 8, "invalid integer");
+    let sign = if content[0] & 0x80 != 0 { -1 } else { 0 };
+    Ok(content
+        .iter()
+        .fold(sign, |value, &byte| value << 8 | i64::from(byte)))
+}
+
+fn decode_oid(content: &[u8]) -> eyre::Result<Vec<u32>> {
+    let [first, rest @ ..] = content else {
+        bail!("empty oid");
+    };
+    let mut arcs = vec![u32::from(first / 40), u32::from(first % 40)];
+    let mut arc = 0u32;
+    for &byte in rest {
+        ensure!(arc >> 25 == 0, "oid arc too big");
+        arc = arc << 7 | u32::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+    ensure!(
+        rest.last().is_none_or(|byte| byte & 0x80 == 0),
+        "truncated oid"
+    );
+    Ok(arcs)
+}
+
+fn decode_response(data: &[u8]) -> eyre::Result<Response> {
+    let (message, _) = expect(data, SEQUENCE)?;
+    let (_version, rest) = expect(message, INTEGER)?;
+    let (_community, rest) = expect(rest, OCTET_STRING)?;
+    let (pdu, _) = expect(rest, RESPONSE)?;
+    let (request_id, rest) = expect(pdu, INTEGER)?;
+    let (error_status, rest) = expect(rest, INTEGER)?;
+    let (_error_index, rest) = expect(rest, INTEGER)?;
+    let (mut list, _) = expect(rest, SEQUENCE)?;
+    let mut varbinds = Vec::new();
+    while !list.is_empty() {
+        let (varbind, rest) = expect(list, SEQUENCE)?;
+        list = rest;
+        let (oid, value) = expect(varbind, OID)?;
+        let (tag, content, _) = read_tlv(value)?;
+        let value = match tag {
+            INTEGER | COUNTER32 | GAUGE32 | TIMETICKS => Value::Integer(decode_integer(content)?),
+            END_OF_MIB_VIEW => Value::EndOfMibView,
+            _ => Value::Other,
+        };
+        varbinds.push((decode_oid(oid)?, value));
+    }
+    Ok(Response {
+        request_id: i32::try_from(decode_integer(request_id)?).wrap_err("invalid request id")?,
+        error_status: decode_integer(error_status)?,
+        varbinds,
+    })
+}
@@ -0,0 +1,239 @@
+//! Finding devices that announce themselves with SSDP (UPnP), like TVs, consoles and NAS boxes,
+//! by the friendly name in their device description.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{Backend, HostDiscovery, HostEntry, NeighborTable};
+
+/// Where SSDP searches go.
+pub const MULTICAST: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)), 1900);
+/// How long answers to a search are collected.
+const WINDOW: Duration = Duration::from_secs(1);
+/// How long the names found are used before searching again, searching takes a while.
+const REFRESH: Duration = Duration::from_secs(60);
+/// How long each device gets to hand out its description.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(1);
+/// More devices than this answering is likely something misbehaving, the rest is ignored.
+const MAX_DEVICES: usize = 64;
+/// Longer names are cut off.
+const MAX_NAME: usize = 100;
+
+/// Names (or description URLs) by the IP of the device.
+type ByIp = Vec<(IpAddr, String)>;
+
+/// Searches for devices, fetches their descriptions and takes the MACs of those that answered
+/// from the neighbor table. Devices that answer garbage or aren't in the table are skipped.
+pub struct Ssdp {
+    target: SocketAddr,
+    neighbors: Box<dyn HostDiscovery>,
+    /// When the last search was, with the names found by IP.
+    found: Mutex<Option<(Instant, ByIp)>>,
+}
+
+impl Ssdp {
+    /// Looks up the MACs of the devices with `neighbors`.
+    pub fn new(neighbors: Box<dyn HostDiscovery>) -> Self {
+        Self {
+            target: MULTICAST,
+            neighbors,
+            found: Mutex::new(None),
+        }
+    }
+
+    /// Sends searches somewhere else than the multicast group, mostly useful for tests.
+    pub fn with_target(mut self, target: SocketAddr) -> Self {
+        self.target = target;
+        self
+    }
+
+    fn names(&self) -> io::Result<ByIp> {
+        let mut found = self.found.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((at, names)) = &*found {
+            if at.elapsed() < REFRESH {
+                return Ok(names.clone());
+            }
+        }
+        let names = fetch_names(search(self.target)?);
+        *found = Some((Instant::now(), names.clone()));
+        Ok(names)
+    }
+}
+
+impl HostDiscovery for Ssdp {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        let names = self.names()?;
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let neighbors = self.neighbors.discover()?;
+        Ok(names
+            .into_iter()
+            .filter_map(|(ip, name)| {
+                let Some(neighbor) = neighbors.iter().find(|entry| entry.ip == Some(ip)) else {
+                    tracing::debug!(%ip, %name, "SSDP device isn't in the neighbor table");
+                    return None;
+                };
+                Some(HostEntry {
+                    name,
+                    ip: Some(ip),
+                    mac: neighbor.mac,
+                    named_by: Some(Backend::Ssdp),
+                    state: neighbor.state,
+                    vlan: neighbor.vlan,
+                })
+            })
+            .collect())
+    }
+
+    fn neighbors(&self) -> Vec<NeighborTable> {
+        vec![NeighborTable::found("ssdp", self.discover())]
+    }
+}
+
+/// Sends an `M-SEARCH` for all devices, returning the description URL of each that answered.
+fn search(target: SocketAddr) -> io::Result<ByIp> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    // routers in between drop it, like they should
+    socket.set_multicast_ttl_v4(2)?;
+    let query = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {target}\r\nMAN: \"ssdp:discover\"\r\nMX: {}\r\nST: ssdp:all\r\n\r\n",
+        WINDOW.as_secs().max(1)
+    );
+    socket.send_to(query.as_bytes(), target)?;
+
+    let deadline = Instant::now() + WINDOW;
+    let mut locations: ByIp = Vec::new();
+    let mut buf = [0; 2048];
+    while locations.len() < MAX_DEVICES {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            Err(e) => return Err(e),
+        };
+        let ip = from.ip();
+        // devices answer once for every service they have
+        if locations.iter().any(|(known, _)| *known == ip) {
+            continue;
+        }
+        match location(&buf[..len], ip) {
+            Some(location) => locations.push((ip, location)),
+            None => tracing::debug!(%ip, "ignoring invalid SSDP response"),
+        }
+    }
+    Ok(locations)
+}
+
+/// The description URL in a search response. Only one on the device that answered is used,
+/// announcements aren't a reason to fetch something from anywhere else.
+fn location(response: &[u8], ip: IpAddr) -> Option<String> {
+    let response = std::str::from_utf8(response).ok()?;
+    let mut lines = response.lines();
+    if !lines.next()?.starts_with("HTTP/1.1 200") {
+        return None;
+    }
+    let location = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("location")
+            .then(|| value.trim())
+    })?;
+    let authority = location.strip_prefix("http://")?;
+    let host = authority.split(['/', '?']).next()?;
+    let host = host.rsplit_once(':').map_or(host, |(host, _)| host);
+    (host.parse::<IpAddr>().ok()? == ip).then(|| location.to_owned())
+}
+
+/// Fetches the descriptions all at once, skipping those that fail or have no name.
+fn fetch_names(locations: ByIp) -> ByIp {
+    let client = match reqwest::blocking::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!(?e, "failed to create a client for SSDP descriptions");
+            return Vec::new();
+        }
+    };
+    std::thread::scope(|scope| {
+        let fetches = locations
+            .iter()
+            .map(|(ip, location)| {
+                let client = &client;
+                let fetch = scope.spawn(move || {
+                    let description = client.get(location).send()?.error_for_status()?.text()?;
+                    Ok::<_, reqwest::Error>(friendly_name(&description))
+                });
+                (*ip, fetch)
+            })
+            .collect::<Vec<_>>();
+        fetches
+            .into_iter()
+            .filter_map(|(ip, fetch)| match fetch.join() {
+                Ok(Ok(Some(name))) => Some((ip, name)),
+                Ok(Ok(None)) => {
+                    tracing::debug!(%ip, "SSDP description without a friendlyName");
+                    None
+                }
+                Ok(Err(e)) => {
+                    tracing::debug!(?e, %ip, "failed to fetch SSDP description");
+                    None
+                }
+                Err(_) => {
+                    tracing::error!(%ip, "fetching SSDP description panicked");
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+/// The `friendlyName` of a UPnP device description. It's not parsed as XML, which cheap devices
+/// often get wrong anyway, and only a name that's plain text is taken.
+pub fn friendly_name(description: &str) -> Option<String> {
+    let start = description.find("<friendlyName>")? + "<friendlyName>".len();
+    let len = description[start..].find("</friendlyName>")?;
+    let raw = &description[start..start + len];
+    if raw.contains(['<', '>']) {
+        return None;
+    }
+    let mut name = String::new();
+    let mut rest = raw;
+    while let Some(at) = rest.find('&') {
+        name.push_str(&rest[..at]);
+        let end = rest[at..].find(';')? + at + 1;
+        name.push_str(match &rest[at..end] {
+            "&amp;" => "&",
+            "&lt;" => "<",
+            "&gt;" => ">",
+            "&quot;" => "\"",
+            "&apos;" => "'",
+            _ => return None,
+        });
+        rest = &rest[end..];
+    }
+    name.push_str(rest);
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return None;
+    }
+    Some(name.chars().take(MAX_NAME).collect())
+}
@@ -0,0 +1,174 @@
+//! The network interfaces of this machine, as sending and ARP probes see them.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use crate::MacAddress;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interface {
+    pub name: String,
+    pub index: u32,
+    /// Whether it's up, as set by `ip link set up`.
+    pub up: bool,
+    /// Whether it has a link, which it hasn't if the cable is unplugged.
+    pub running: bool,
+    /// `None` for interfaces without one (like loopback) and where it can't be told.
+    pub mac: Option<MacAddress>,
+    pub addresses: Vec<InterfaceAddress>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterfaceAddress {
+    pub ip: IpAddr,
+    /// The length of the netmask.
+    pub prefix: u8,
+}
+
+impl InterfaceAddress {
+    pub fn netmask(&self) -> IpAddr {
+        match self.ip {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(mask(u32::BITS, self.prefix) as u32)),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(mask(u128::BITS, self.prefix))),
+        }
+    }
+
+    /// The directed broadcast of its subnet, `None` for IPv6 and subnets of one or two addresses.
+    pub fn broadcast(&self) -> Option<Ipv4Addr> {
+        match self.ip {
+            IpAddr::V4(ip) if self.prefix < 31 => Some(Ipv4Addr::from(
+                u32::from(ip) | !(mask(u32::BITS, self.prefix) as u32),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Whether `ip` is in its subnet.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.ip, ip) {
+            (IpAddr::V4(own), IpAddr::V4(other)) => {
+                let mask = mask(u32::BITS, self.prefix) as u32;
+                u32::from(own) & mask == u32::from(other) & mask
+            }
+            (IpAddr::V6(own), IpAddr::V6(other)) => {
+                let mask = mask(u128::BITS, self.prefix);
+                u128::from(own) & mask == u128::from(other) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The top `prefix` of `bits` bits set.
+fn mask(bits: u32, prefix: u8) -> u128 {
+    let all = u128::MAX >> (u128::BITS - bits);
+    all & !all.checked_shr(u32::from(prefix)).unwrap_or(0)
+}
+
+impl Interface {
+    /// Whether one of its addresses is `ip`.
+    pub fn has_ip(&self, ip: IpAddr) -> bool {
+        self.addresses.iter().any(|address| address.ip == ip)
+    }
+}
+
+/// The interface with this name, `None` if there's none.
+pub fn find(name: &str) -> io::Result<Option<Interface>> {
+    Ok(list()?.into_iter().find(|interface| interface.name == name))
+}
+
+/// All interfaces, in the order the system lists them.
+#[cfg(unix)]
+pub fn list() -> io::Result<Vec<Interface>> {
+    use std::ffi::CStr;
+
+    let mut ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs fills in the pointer, which is freed below
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut current = ifaddrs;
+    while !current.is_null() {
+        // SAFETY: a non-null entry of the list returned by getifaddrs
+        let ifaddr = unsafe { &*current };
+        current = ifaddr.ifa_next;
+        // SAFETY: the name is a nul-terminated string that lives as long as the list
+        let name = unsafe { CStr::from_ptr(ifaddr.ifa_name) };
+        // every address of an interface is an entry of its own
+        let interface = match interfaces
+            .iter()
+            .position(|interface| interface.name.as_bytes() == name.to_bytes())
+        {
+            Some(position) => &mut interfaces[position],
+            None => {
+                let flags = ifaddr.ifa_flags as libc::c_int;
+                interfaces.push(Interface {
+                    name: name.to_string_lossy().into_owned(),
+                    // SAFETY: if_nametoindex only reads the nul-terminated name
+                    index: unsafe { libc::if_nametoindex(name.as_ptr()) },
+                    up: flags & libc::IFF_UP != 0,
+                    running: flags & libc::IFF_RUNNING != 0,
+                    mac: None,
+                    addresses: Vec::new(),
+                });
+                interfaces.last_mut().expect("just pushed")
+            }
+        };
+        if ifaddr.ifa_addr.is_null() {
+            continue;
+        }
+        // SAFETY: ifa_addr is non-null, and its family says what it points to
+        match i32::from(unsafe { (*ifaddr.ifa_addr).sa_family }) {
+            libc::AF_INET if !ifaddr.ifa_netmask.is_null() => {
+                // SAFETY: AF_INET addresses and masks are sockaddr_in
+                let (ip, mask) = unsafe {
+                    let ip = &*ifaddr.ifa_addr.cast::<libc::sockaddr_in>();
+                    let mask = &*ifaddr.ifa_netmask.cast::<libc::sockaddr_in>();
+                    (u32::from_be(ip.sin_addr.s_addr), mask.sin_addr.s_addr)
+                };
+                interface.addresses.push(InterfaceAddress {
+                    ip: IpAddr::V4(Ipv4Addr::from(ip)),
+                    prefix: mask.count_ones() as u8,
+                });
+            }
+            libc::AF_INET6 if !ifaddr.ifa_netmask.is_null() => {
+                // SAFETY: AF_INET6 addresses and masks are sockaddr_in6
+                let (ip, mask) = unsafe {
+                    let ip = &*ifaddr.ifa_addr.cast::<libc::sockaddr_in6>();
+                    let mask = &*ifaddr.ifa_netmask.cast::<libc::sockaddr_in6>();
+                    (ip.sin6_addr.s6_addr, mask.sin6_addr.s6_addr)
+                };
+                interface.addresses.push(InterfaceAddress {
+                    ip: IpAddr::V6(Ipv6Addr::from(ip)),
+                    prefix: u128::from_be_bytes(mask).count_ones() as u8,
+                });
+            }
+            #[cfg(target_os = "linux")]
+            libc::AF_PACKET => {
+                // SAFETY: AF_PACKET addresses are sockaddr_ll
+                let ll = unsafe { &*ifaddr.ifa_addr.cast::<libc::sockaddr_ll>() };
+                if ll.sll_halen == 6 && ll.sll_addr[..6] != [0; 6] {
+                    let mut mac = [0; 6];
+                    mac.copy_from_slice(&ll.sll_addr[..6]);
+                    interface.mac = Some(MacAddress(mac));
+                }
+            }
+            _ => {}
+        }
+    }
+    // SAFETY: the list came from getifaddrs and nothing borrowed from it is used after this
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    Ok(interfaces)
+}
+
+#[cfg(not(unix))]
+pub fn list() -> io::Result<Vec<Interface>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "listing network interfaces is only supported on Unix",
+    ))
+}
@@ -0,0 +1,480 @@
+// wake on lan code adapted from https://github.com/TeemuRemes/wake-on-lan-rust
+
+#[cfg(any(feature = "server", feature = "client"))]
+pub mod api;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "server")]
+pub mod discovery;
+#[cfg(feature = "server")]
+pub mod interfaces;
+#[cfg(feature = "server")]
+pub mod netbios;
+#[cfg(feature = "server")]
+pub mod retry;
+#[cfg(any(feature = "server", feature = "client"))]
+pub mod schedule;
+#[cfg(any(feature = "server", feature = "client"))]
+pub mod secret;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "server")]
+pub mod sign;
+#[cfg(feature = "server")]
+pub mod verify;
+
+#[cfg(feature = "socket")]
+use std::net::{Ipv4Addr, ToSocketAddrs, UdpSocket};
+use std::{fmt, str::FromStr};
+
+/// A 6-byte MAC address, displayed in the usual lowercase `aa:bb:cc:dd:ee:ff` form.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl fmt::Debug for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(feature = "macaddr")]
+impl From<macaddr::MacAddr6> for MacAddress {
+    fn from(mac: macaddr::MacAddr6) -> Self {
+        MacAddress(mac.into_array())
+    }
+}
+
+#[cfg(feature = "macaddr")]
+impl From<MacAddress> for macaddr::MacAddr6 {
+    fn from(MacAddress(bytes): MacAddress) -> Self {
+        bytes.into()
+    }
+}
+
+/// Parses `aa:bb:cc:dd:ee:ff`, `aa-bb-cc-dd-ee-ff` and `aabb.ccdd.eeff`, in either case.
+/// Leading zeros can be left out with colons or dashes, like `arp` on some systems does.
+impl FromStr for MacAddress {
+    type Err = MacParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let separator = [':', '-', '.'].into_iter().find(|&sep| s.contains(sep));
+        // the dotted form has two bytes per group
+        let (min_digits, max_digits) = match separator {
+            Some('.') => (4, 4),
+            _ => (1, 2),
+        };
+
+        let mut bytes = Vec::with_capacity(6);
+        let mut group = String::new();
+        let mut group_start = 0;
+        let chars = s.chars().map(Some).chain([None]);
+        for (position, c) in chars.enumerate() {
+            match c {
+                Some(c) if c.is_ascii_hexdigit() => group.push(c),
+                Some(c) if Some(c) != separator => {
+                    return Err(MacParseError::InvalidDigit { position, found: c })
+                }
+                // a separator or the end
+                _ => {
+                    if !(min_digits..=max_digits).contains(&group.len()) {
+                        return Err(MacParseError::InvalidGroup {
+                            position: group_start,
+                        });
+                    }
+                    let value = u16::from_str_radix(&group, 16).expect("only hex digits");
+                    if max_digits == 4 {
+                        bytes.extend(value.to_be_bytes());
+                    } else {
+                        bytes.push(value as u8);
+                    }
+                    group.clear();
+                    group_start = position + 1;
+                }
+            }
+        }
+
+        let bytes: [u8; 6] = bytes
+            .as_slice()
+            .try_into()
+            .map_err(|_| MacParseError::InvalidLength { bytes: bytes.len() })?;
+        Ok(MacAddress(bytes))
+    }
+}
+
+/// Why a string isn't a MAC address. Positions count characters, starting at 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MacParseError {
+    /// A character that's neither a hex digit nor the separator.
+    InvalidDigit { position: usize, found: char },
+    /// A group with too few or too many digits, at the position it starts at.
+    InvalidGroup { position: usize },
+    /// Not six bytes, with how many there were.
+    InvalidLength { bytes: usize },
+}
+
+impl fmt::Display for MacParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MacParseError::InvalidDigit { position, found } => {
+                write!(f, "invalid hex digit `{found}` at position {position}")
+            }
+            MacParseError::InvalidGroup { position } => {
+                write!(
+                    f,
+                    "wrong number of digits in the group at position {position}"
+                )
+            }
+            MacParseError::InvalidLength { bytes } => {
+                write!(f, "expected 6 bytes, got {bytes}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MacParseError {}
+
+/// A SecureOn password, which a NIC that has one set wants after the MAC repetitions before it
+/// wakes up. 6 bytes, or 4 for some older Intel NICs. The bytes are left out of `Debug`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SecureOnPassword {
+    Short([u8; 4]),
+    Long([u8; 6]),
+}
+
+impl SecureOnPassword {
+    /// `None` unless it's 4 or 6 bytes.
+    pub fn new(bytes: &[u8]) -> Option<SecureOnPassword> {
+        match bytes.len() {
+            4 => Some(SecureOnPassword::Short(bytes.try_into().unwrap())),
+            6 => Some(SecureOnPassword::Long(bytes.try_into().unwrap())),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            SecureOnPassword::Short(bytes) => bytes,
+            SecureOnPassword::Long(bytes) => bytes,
+        }
+    }
+
+    /// Lowercase hex digits without separators, like `a1b2c3d4`.
+    pub fn to_hex(&self) -> String {
+        self.as_bytes().iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+impl fmt::Debug for SecureOnPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecureOnPassword({} bytes)", self.as_bytes().len())
+    }
+}
+
+/// Parses hex digits, either without separators (`a1b2c3d4`) or with `:` or `-` between the bytes.
+impl FromStr for SecureOnPassword {
+    type Err = SecureOnParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut digits = Vec::with_capacity(12);
+        for (position, c) in s.chars().enumerate() {
+            match c.to_digit(16) {
+                Some(digit) => digits.push(digit as u8),
+                None if matches!(c, ':' | '-') && digits.len() % 2 == 0 => {}
+                None => return Err(SecureOnParseError::InvalidDigit { position, found: c }),
+            }
+        }
+        if digits.len() % 2 != 0 {
+            return Err(SecureOnParseError::OddDigits);
+        }
+        let bytes = digits
+            .chunks_exact(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect::<Vec<_>>();
+        SecureOnPassword::new(&bytes)
+            .ok_or(SecureOnParseError::InvalidLength { bytes: bytes.len() })
+    }
+}
+
+/// Why a string isn't a SecureOn password. Positions count characters, starting at 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecureOnParseError {
+    /// A character that's neither a hex digit nor a separator between two bytes.
+    InvalidDigit { position: usize, found: char },
+    /// Half a byte at the end.
+    OddDigits,
+    /// Neither 4 nor 6 bytes, with how many there were.
+    InvalidLength { bytes: usize },
+}
+
+impl fmt::Display for SecureOnParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecureOnParseError::InvalidDigit { position, found } => {
+                write!(f, "invalid hex digit `{found}` at position {position}")
+            }
+            SecureOnParseError::OddDigits => write!(f, "odd number of hex digits"),
+            SecureOnParseError::InvalidLength { bytes } => {
+                write!(f, "expected 4 or 6 bytes, got {bytes}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SecureOnParseError {}
+
+/// The magic bytes without a password.
+const MAGIC_BYTES_LEN: usize = 102;
+
+/// A Wake-on-LAN magic packet, optionally with a SecureOn password.
+pub struct MagicPacket {
+    /// The magic bytes, followed by the password if there is one.
+    bytes: [u8; MAGIC_BYTES_LEN + 6],
+    password: Option<SecureOnPassword>,
+}
+
+impl MagicPacket {
+    /// Creates a new `MagicPacket` intended for `mac_address` (but doesn't send it yet).
+    pub fn new(mac_address: &[u8; 6]) -> MagicPacket {
+        let mut magic_bytes: [u8; MAGIC_BYTES_LEN] = [0; MAGIC_BYTES_LEN];
+
+        // We use `unsafe` code to skip unnecessary array initialization and bounds checking.
+        unsafe {
+            // Copy the header to the beginning.
+            let mut src: *const u8 = &MAGIC_BYTES_HEADER[0];
+            let mut dst: *mut u8 = &mut magic_bytes[0];
+            dst.copy_from_nonoverlapping(src, 6);
+
+            // Copy the MAC address once from the argument.
+            src = &mac_address[0];
+            dst = dst.offset(6);
+            dst.copy_from_nonoverlapping(src, 6);
+
+            // Repeat the MAC.
+            let src: *const u8 = dst; // src points to magic_bytes[6]
+            dst = dst.offset(6);
+            dst.copy_from_nonoverlapping(src, 6);
+
+            dst = dst.offset(6);
+            dst.copy_from_nonoverlapping(src, 12);
+
+            dst = dst.offset(12);
+            dst.copy_from_nonoverlapping(src, 24);
+
+            dst = dst.offset(24);
+            dst.copy_from_nonoverlapping(src, 48);
+        }
+
+        let mut bytes = [0; MAGIC_BYTES_LEN + 6];
+        bytes[..MAGIC_BYTES_LEN].copy_from_slice(&magic_bytes);
+        MagicPacket {
+            bytes,
+            password: None,
+        }
+    }
+
+    /// The same packet with the password after the MAC repetitions, for a NIC that has one set.
+    ///
+    /// ```
+    /// use wakeonlan::{MagicPacket, SecureOnPassword};
+    ///
+    /// let password: SecureOnPassword = "a1:b2:c3:d4".parse().unwrap();
+    /// let packet = MagicPacket::new(&[0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]).with_password(password);
+    /// assert_eq!(packet.payload().len(), 106);
+    /// assert_eq!(packet.payload()[102..], [0xa1, 0xb2, 0xc3, 0xd4]);
+    /// assert_eq!(
+    ///     format!("{packet:?}"),
+    ///     "MagicPacket { mac: 00:d8:61:ca:3a:18, password: Some(SecureOnPassword(4 bytes)) }"
+    /// );
+    /// ```
+    pub fn with_password(mut self, password: SecureOnPassword) -> MagicPacket {
+        let len = password.as_bytes().len();
+        self.bytes[MAGIC_BYTES_LEN..MAGIC_BYTES_LEN + len].copy_from_slice(password.as_bytes());
+        self.password = Some(password);
+        self
+    }
+
+    /// Checks that `bytes` are a magic packet (the header followed by 16 repetitions of the same
+    /// MAC, then nothing or a 4 or 6 byte password).
+    ///
+    /// ```
+    /// use wakeonlan::{MacAddress, MagicPacket};
+    ///
+    /// let mac = [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18];
+    /// let mut bytes = MagicPacket::new(&mac).magic_bytes().to_vec();
+    /// assert_eq!(MagicPacket::parse(&bytes).unwrap().password_len(), None);
+    /// bytes.extend([0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+    /// let packet = MagicPacket::parse(&bytes).unwrap();
+    /// assert_eq!(packet.mac(), MacAddress(mac));
+    /// assert_eq!(packet.password_len(), Some(6));
+    /// // neither a 4 nor a 6 byte password
+    /// assert!(MagicPacket::parse(&bytes[..107]).is_none());
+    /// ```
+    pub fn parse(bytes: &[u8]) -> Option<MagicPacket> {
+        if bytes.len() < MAGIC_BYTES_LEN || bytes[..6] != MAGIC_BYTES_HEADER {
+            return None;
+        }
+        let (magic_bytes, password) = bytes.split_at(MAGIC_BYTES_LEN);
+        let password = match password {
+            [] => None,
+            password => Some(SecureOnPassword::new(password)?),
+        };
+        let mac: [u8; 6] = magic_bytes[6..12].try_into().unwrap();
+        if !magic_bytes[6..].chunks_exact(6).all(|chunk| chunk == mac) {
+            return None;
+        }
+        let packet = MagicPacket::new(&mac);
+        Some(match password {
+            Some(password) => packet.with_password(password),
+            None => packet,
+        })
+    }
+
+    /// The MAC address the packet is for.
+    pub fn mac(&self) -> MacAddress {
+        MacAddress(self.bytes[6..12].try_into().unwrap())
+    }
+
+    /// How many bytes the password has, if there is one.
+    pub fn password_len(&self) -> Option<usize> {
+        self.password.map(|password| password.as_bytes().len())
+    }
+
+    /// Creates a new `MagicPacket` for a MAC address in any of the forms [`MacAddress`] parses.
+    ///
+    /// ```
+    /// use wakeonlan::{MacParseError, MagicPacket};
+    ///
+    /// let packet = MagicPacket::from_mac_str("00:d8:61:ca:3a:18").unwrap();
+    /// assert_eq!(packet.magic_bytes()[6..12], [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]);
+    /// let same = MagicPacket::try_from("00-D8-61-CA-3A-18").unwrap();
+    /// assert_eq!(packet.magic_bytes(), same.magic_bytes());
+    ///
+    /// assert_eq!(
+    ///     MagicPacket::from_mac_str("00:d8:61:ca:3a").err(),
+    ///     Some(MacParseError::InvalidLength { bytes: 5 })
+    /// );
+    /// assert_eq!(
+    ///     MagicPacket::from_mac_str("00:d8:6x:ca:3a:18").err(),
+    ///     Some(MacParseError::InvalidDigit { position: 7, found: 'x' })
+    /// );
+    /// ```
+    pub fn from_mac_str(mac_address: &str) -> Result<MagicPacket, MacParseError> {
+        let MacAddress(bytes) = mac_address.parse()?;
+        Ok(MagicPacket::new(&bytes))
+    }
+
+    /// Sends the magic packet via UDP to the broadcast address `255.255.255.255:9`.
+    /// Lets the operating system choose the source port and network interface.
+    #[cfg(feature = "socket")]
+    pub fn send(&self) -> std::io::Result<()> {
+        self.send_to(
+            (Ipv4Addr::new(255, 255, 255, 255), 9),
+            (Ipv4Addr::new(0, 0, 0, 0), 0),
+        )
+    }
+
+    /// Sends the magic packet via UDP to/from an IP address and port number of your choosing.
+    #[cfg(feature = "socket")]
+    pub fn send_to<A: ToSocketAddrs>(&self, to_addr: A, from_addr: A) -> std::io::Result<()> {
+        let socket = bind_broadcast_socket(from_addr)?;
+        send_magic_packet(&socket, self, to_addr)
+    }
+
+    /// Returns the magic bytes (6 repetitions of `0xFF` and 16 repetitions of the target device's
+    /// MAC address), without the password, see [`MagicPacket::payload`] for the whole packet.
+    /// Send these bytes yourself over the network if you want to do something more advanced (like
+    /// reuse a single UDP socket when sending a large number of magic packets, see
+    /// `send_magic_packet`), or where there are no std sockets, like with the `socket` feature
+    /// turned off. They go to port 9 (or 7) of a broadcast address over UDP:
+    ///
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use wakeonlan::{parse_magic_packet, MacAddress, MagicPacket};
+    ///
+    /// let packet = MagicPacket::new(&[0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]);
+    /// // any UDP socket does, like a smoltcp one on an embedded gateway
+    /// let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// socket
+    ///     .send_to(packet.magic_bytes(), receiver.local_addr().unwrap())
+    ///     .unwrap();
+    ///
+    /// let mut buf = [0; 200];
+    /// let len = receiver.recv(&mut buf).unwrap();
+    /// assert_eq!(
+    ///     parse_magic_packet(&buf[..len]),
+    ///     Some(MacAddress([0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]))
+    /// );
+    /// ```
+    pub fn magic_bytes(&self) -> &[u8; MAGIC_BYTES_LEN] {
+        self.bytes[..MAGIC_BYTES_LEN].try_into().unwrap()
+    }
+
+    /// What's sent, the magic bytes followed by the password if there is one: 102, 106 or 108
+    /// bytes.
+    pub fn payload(&self) -> &[u8] {
+        &self.bytes[..MAGIC_BYTES_LEN + self.password_len().unwrap_or(0)]
+    }
+}
+
+impl fmt::Debug for MagicPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MagicPacket")
+            .field("mac", &self.mac())
+            .field("password", &self.password)
+            .finish()
+    }
+}
+
+impl TryFrom<&str> for MagicPacket {
+    type Error = MacParseError;
+
+    fn try_from(mac_address: &str) -> Result<Self, Self::Error> {
+        MagicPacket::from_mac_str(mac_address)
+    }
+}
+
+#[cfg(feature = "macaddr")]
+impl From<macaddr::MacAddr6> for MagicPacket {
+    fn from(mac: macaddr::MacAddr6) -> Self {
+        MagicPacket::new(&mac.into_array())
+    }
+}
+
+const MAGIC_BYTES_HEADER: [u8; 6] = [0xFF; 6];
+
+/// Checks that `bytes` are a magic packet, with or without a password, returning the MAC it's
+/// for. See [`MagicPacket::parse`].
+pub fn parse_magic_packet(bytes: &[u8]) -> Option<MacAddress> {
+    MagicPacket::parse(bytes).map(|packet| packet.mac())
+}
+
+/// Binds a UDP socket to `from_addr` and enables `SO_BROADCAST` on it, ready to be passed to
+/// [`send_magic_packet`] as many times as you like.
+#[cfg(feature = "socket")]
+pub fn bind_broadcast_socket<A: ToSocketAddrs>(from_addr: A) -> std::io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(from_addr)?;
+    socket.set_broadcast(true)?;
+    Ok(socket)
+}
+
+/// Sends `packet` to `dest` over an already bound socket.
+/// The socket needs `SO_BROADCAST` if `dest` is a broadcast address.
+#[cfg(feature = "socket")]
+pub fn send_magic_packet<A: ToSocketAddrs>(
+    socket: &UdpSocket,
+    packet: &MagicPacket,
+    dest: A,
+) -> std::io::Result<()> {
+    socket.send_to(packet.payload(), dest)?;
+    Ok(())
+}
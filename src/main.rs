@@ -1,157 +1,705 @@
-// wake on lan code adapted from https://github.com/TeemuRemes/wake-on-lan-rust
+mod tui;
 
-use axum::{
-    http::StatusCode,
-    response::{Html, IntoResponse, Response},
-    routing::{get, post},
-    Router,
+use eyre::eyre;
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    process::ExitCode,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::task::JoinSet;
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+};
+use wakeonlan::{
+    api::v1::{CliError, CliErrorKind, CliReport, Destination, HostStatus, WakeResponse},
+    config::Config,
+    discovery::parse_mac_addr,
+    server::{self, AppState, HttpListener, LogBuffer, Mqtt, Proxy, Relay, Telegram},
+    verify::Strategy,
+    MacAddress, MagicPacket,
 };
-use eyre::{bail, Context};
-use std::net::{Ipv4Addr, ToSocketAddrs, UdpSocket};
-use tracing_subscriber::EnvFilter;
 
-#[tokio::main]
-async fn main() {
-    // initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or(EnvFilter::new("info")))
-        .init();
+/// Like `EX_CONFIG` from sysexits.h, restarting won't help with these. Only the server and
+/// `--check-config` exit with it, the commands exit with [`CliErrorKind::exit_code`].
+const EXIT_CONFIG: u8 = 78;
+const EXIT_RUNTIME: u8 = 1;
+/// Like `EX_USAGE` from sysexits.h.
+const EXIT_USAGE: u8 = 64;
+/// How long `wake --wait` waits for the host to come up, like `wait-online`.
+const DEFAULT_WAIT: Duration = Duration::from_secs(60);
 
-    // build our application with a route
-    let app = Router::new()
-        .route("/", get(async || Html(include_str!("../index.html"))))
-        .route("/wake", post(wake));
+const USAGE: &str = "usage: wakeonlan [--check-config | --print-config | check <host> [--port <port>] [--timeout <seconds>] [--json] [--output <text|json>] | wake (--mac <mac> | <host> [--wait] [--timeout <seconds>]) [--output <text|json>] | list [--output <text|json>] | tui]";
 
-    // run our app with hyper, listening globally on port 8090
-    let addr = "0.0.0.0:8090";
-    tracing::info!(?addr, "Starting server");
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+/// What the command line asks for.
+enum Command {
+    Serve,
+    CheckConfig,
+    /// Prints the settings like `GET /api/v1/config` and exits.
+    PrintConfig,
+    Check(CheckArgs),
+    Wake(WakeArgs),
+    /// `list`, the hosts like `GET /hosts`.
+    List(Output),
+    /// The hosts in the terminal, to wake them from there.
+    Tui,
 }
 
-async fn wake() -> Response {
-    tracing::info!("Waking");
-    match tokio::task::spawn_blocking(|| wake_inner()).await {
-        Ok(Ok(())) => (StatusCode::ACCEPTED, "sent packet").into_response(),
-        Ok(Err(e)) => {
-            tracing::error!(?e, "failed to wake");
-            (StatusCode::INTERNAL_SERVER_ERROR, "error").into_response()
-        }
-        Err(e) => {
-            tracing::error!(?e, "join error");
-            (StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn").into_response()
+/// What `--output` asks for. With `json`, stdout only gets a [`CliReport`] and the command exits
+/// with its exit code.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Output {
+    Text,
+    Json,
+}
+
+impl FromStr for Output {
+    type Err = ();
+
+    fn from_str(output: &str) -> Result<Self, Self::Err> {
+        match output {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => Err(()),
         }
     }
 }
 
-fn wake_inner() -> eyre::Result<()> {
-    let hosts = load_possible_hosts()?;
-    let host = hosts
-        .into_iter()
-        .find(|(host, _)| host.contains("PC-Nora"))
-        .unwrap_or_else(|| ("PC-Nora".into(), parse_mac_addr("00:d8:61:ca:3a:18")));
-    let magic_packet = MagicPacket::new(&host.1);
-    magic_packet.send().wrap_err("failed to send packet")?;
-
-    tracing::info!(hostname = %host.0, mac = ?host.1, "Woke up");
+/// `check <host>`, whether a host is up.
+struct CheckArgs {
+    host: String,
+    /// Instead of `verify`, only a TCP connection to this port counts.
+    port: Option<u16>,
+    /// Instead of `verify_timeout`, in seconds.
+    timeout: Option<u64>,
+    /// Prints only the status, like `GET /hosts/<name>/status`.
+    json: bool,
+    output: Output,
+}
 
-    Ok(())
+/// `wake --mac <mac>` sends one packet to `broadcast` without the server, `wake <host>` wakes like
+/// `POST /wake` does.
+struct WakeArgs {
+    target: Target,
+    /// How long to wait for the host to come up, `None` without `--wait`.
+    wait: Option<Duration>,
+    output: Output,
 }
 
-fn parse_mac_addr(addr: &str) -> [u8; 6] {
-    addr.split(":")
-        .map(|part| u8::from_str_radix(part, 16).expect("invalid mac address"))
-        .collect::<Vec<_>>()
-        .as_slice()
-        .try_into()
-        .expect("invalid mac address")
+enum Target {
+    Mac(MacAddress),
+    Host(String),
 }
 
-fn load_possible_hosts() -> eyre::Result<Vec<(String, [u8; 6])>> {
-    // TODO: It would be very cool to instead read /proc/net/arp and then call getnameinfo but that's annoying...
-    let arp = std::process::Command::new("arp")
-        .output()
-        .wrap_err("spwaning `arp`")?;
-    if !arp.status.success() {
-        bail!("arp failed: {}", String::from_utf8_lossy(&arp.stderr));
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    match args.next().as_deref() {
+        None => Ok(Command::Serve),
+        Some("--check-config") => match args.next() {
+            None => Ok(Command::CheckConfig),
+            Some(arg) => Err(format!("unexpected argument `{arg}`")),
+        },
+        Some("--print-config") => match args.next() {
+            None => Ok(Command::PrintConfig),
+            Some(arg) => Err(format!("unexpected argument `{arg}`")),
+        },
+        Some("check") => {
+            let (mut host, mut port, mut timeout, mut json) = (None, None, None, false);
+            let mut output = Output::Text;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--json" => json = true,
+                    "--output" => output = value(&mut args, "--output")?,
+                    "--port" => port = Some(value(&mut args, "--port")?),
+                    "--timeout" => timeout = Some(value(&mut args, "--timeout")?),
+                    flag if flag.starts_with("--") => return Err(format!("unknown flag `{flag}`")),
+                    _ if host.is_none() => host = Some(arg),
+                    _ => return Err(format!("unexpected argument `{arg}`")),
+                }
+            }
+            Ok(Command::Check(CheckArgs {
+                host: host.ok_or("missing host to check")?,
+                port,
+                timeout,
+                json,
+                output,
+            }))
+        }
+        Some("wake") => {
+            let (mut mac, mut host, mut wait, mut timeout) = (None, None, false, None);
+            let mut output = Output::Text;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--mac" => {
+                        let value: String = value(&mut args, "--mac")?;
+                        let parsed = parse_mac_addr(&value)
+                            .ok_or_else(|| format!("invalid value for `--mac`: `{value}`"))?;
+                        mac = Some(parsed);
+                    }
+                    "--wait" => wait = true,
+                    "--timeout" => timeout = Some(value(&mut args, "--timeout")?),
+                    "--output" => output = value(&mut args, "--output")?,
+                    flag if flag.starts_with("--") => return Err(format!("unknown flag `{flag}`")),
+                    _ if host.is_none() => host = Some(arg),
+                    _ => return Err(format!("unexpected argument `{arg}`")),
+                }
+            }
+            let target = match (mac, host) {
+                (Some(mac), None) if !wait && timeout.is_none() => Target::Mac(mac),
+                (Some(_), None) => {
+                    return Err("`--mac` only sends a packet, it can't `--wait`".to_owned())
+                }
+                (None, Some(host)) => Target::Host(host),
+                (Some(_), Some(_)) => return Err("either `--mac` or a host to wake".to_owned()),
+                (None, None) => return Err("missing host or `--mac` to wake".to_owned()),
+            };
+            if timeout.is_some() && !wait {
+                return Err("`--timeout` is how long to `--wait`".to_owned());
+            }
+            let wait = wait.then(|| timeout.map_or(DEFAULT_WAIT, Duration::from_secs));
+            Ok(Command::Wake(WakeArgs {
+                target,
+                wait,
+                output,
+            }))
+        }
+        Some("list") => {
+            let mut output = Output::Text;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--output" => output = value(&mut args, "--output")?,
+                    flag if flag.starts_with("--") => return Err(format!("unknown flag `{flag}`")),
+                    _ => return Err(format!("unexpected argument `{arg}`")),
+                }
+            }
+            Ok(Command::List(output))
+        }
+        Some("tui") => match args.next() {
+            None => Ok(Command::Tui),
+            Some(arg) => Err(format!("unexpected argument `{arg}`")),
+        },
+        Some(arg) => Err(format!("unknown argument `{arg}`")),
     }
-    Ok(String::from_utf8(arp.stdout)
-        .wrap_err("arp returned non-utf-8 output")?
-        .lines()
-        .skip(1)
-        .map(|line| line.split_whitespace().collect::<Vec<_>>())
-        .map(|line_parts| {
-            let mac = parse_mac_addr(line_parts[2]);
-            (line_parts[0].to_owned(), mac)
-        })
-        .collect())
 }
 
-/// A Wake-on-LAN magic packet.
-pub struct MagicPacket {
-    magic_bytes: [u8; 102],
+fn value<T: FromStr>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("missing value for `{flag}`"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for `{flag}`: `{value}`"))
 }
 
-impl MagicPacket {
-    /// Creates a new `MagicPacket` intended for `mac_address` (but doesn't send it yet).
-    pub fn new(mac_address: &[u8; 6]) -> MagicPacket {
-        let mut magic_bytes: [u8; 102] = [0; 102];
+/// Why the server stopped, which decides the exit code.
+enum Failure {
+    Config(eyre::Report),
+    Runtime(eyre::Report),
+}
 
-        // We use `unsafe` code to skip unnecessary array initialization and bounds checking.
-        unsafe {
-            // Copy the header to the beginning.
-            let mut src: *const u8 = &MAGIC_BYTES_HEADER[0];
-            let mut dst: *mut u8 = &mut magic_bytes[0];
-            dst.copy_from_nonoverlapping(src, 6);
+fn config_error(e: impl Into<eyre::Report>) -> Failure {
+    Failure::Config(e.into())
+}
 
-            // Copy the MAC address once from the argument.
-            src = &mac_address[0];
-            dst = dst.offset(6);
-            dst.copy_from_nonoverlapping(src, 6);
+/// `address already in use` instead of `Address already in use (os error 98)`.
+fn io_message(e: &std::io::Error) -> String {
+    let message = e.to_string();
+    let message = match message.rfind(" (os error ") {
+        Some(end) => &message[..end],
+        None => &message,
+    };
+    let mut chars = message.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
 
-            // Repeat the MAC.
-            let src: *const u8 = dst; // src points to magic_bytes[6]
-            dst = dst.offset(6);
-            dst.copy_from_nonoverlapping(src, 6);
+#[tokio::main]
+async fn main() -> ExitCode {
+    let command = match parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    // only the server logs to stdout, what the commands print there is their result, and the tui
+    // has the terminal to itself
+    let (default_level, writer) = match command {
+        Command::Serve => ("info", BoxMakeWriter::new(std::io::stdout)),
+        Command::Tui => ("warn", BoxMakeWriter::new(std::io::sink)),
+        _ => ("warn", BoxMakeWriter::new(std::io::stderr)),
+    };
 
-            dst = dst.offset(6);
-            dst.copy_from_nonoverlapping(src, 12);
+    // initialize tracing, the buffer for /debug/logs only starts keeping events once configured
+    let logs = Arc::new(LogBuffer::default());
+    let filter = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(directives) => match EnvFilter::try_new(&directives) {
+            Ok(filter) => filter,
+            Err(e) => {
+                eprintln!("invalid {}: {e}", EnvFilter::DEFAULT_ENV);
+                return ExitCode::from(EXIT_CONFIG);
+            }
+        },
+        Err(_) => EnvFilter::new(default_level),
+    };
+    if let Err(e) = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(writer))
+        .with(logs.layer())
+        .try_init()
+    {
+        eprintln!("failed to set up logging: {e}");
+        return ExitCode::from(EXIT_RUNTIME);
+    }
 
-            dst = dst.offset(12);
-            dst.copy_from_nonoverlapping(src, 24);
+    match command {
+        Command::Serve => {}
+        Command::CheckConfig => {
+            return match Config::load() {
+                Ok(_) => {
+                    println!("configuration is valid");
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("invalid configuration: {e:#}");
+                    ExitCode::from(EXIT_CONFIG)
+                }
+            }
+        }
+        Command::PrintConfig => {
+            let printed = Config::load()
+                .and_then(|config| Ok(serde_json::to_string_pretty(&config.effective())?));
+            return match printed {
+                Ok(printed) => {
+                    println!("{printed}");
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("invalid configuration: {e:#}");
+                    ExitCode::from(EXIT_CONFIG)
+                }
+            };
+        }
+        Command::Check(args) => return check(args),
+        Command::Wake(args) => return wake(args).await,
+        Command::List(output) => return list(output).await,
+        Command::Tui => return tui().await,
+    }
 
-            dst = dst.offset(24);
-            dst.copy_from_nonoverlapping(src, 48);
+    match run(logs).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Config(e)) => {
+            tracing::error!("invalid configuration: {e:#}");
+            ExitCode::from(EXIT_CONFIG)
+        }
+        Err(Failure::Runtime(e)) => {
+            tracing::error!("{e:#}");
+            ExitCode::from(EXIT_RUNTIME)
         }
+    }
+}
 
-        MagicPacket { magic_bytes }
+/// What a command exits with, 0 without an error.
+fn exit_code(error: Option<&CliError>) -> u8 {
+    error.map_or(0, |error| error.kind.exit_code())
+}
+
+/// Prints the report as the only thing on stdout, and exits with the code of its error.
+fn report(started: Instant, mut report: CliReport) -> ExitCode {
+    report.exit_code = exit_code(report.error.as_ref());
+    report.elapsed = started.elapsed().as_secs_f64();
+    match serde_json::to_string(&report) {
+        Ok(json) => println!("{json}"),
+        Err(e) => {
+            eprintln!("failed to serialize the report: {e}");
+            return ExitCode::from(EXIT_RUNTIME);
+        }
     }
+    ExitCode::from(report.exit_code)
+}
+
+fn load_config() -> Result<Config, CliError> {
+    Config::load().map_err(|e| {
+        CliError::new(
+            CliErrorKind::Config,
+            format!("invalid configuration: {e:#}"),
+        )
+    })
+}
 
-    /// Sends the magic packet via UDP to the broadcast address `255.255.255.255:9`.
-    /// Lets the operating system choose the source port and network interface.
-    pub fn send(&self) -> std::io::Result<()> {
-        self.send_to(
-            (Ipv4Addr::new(255, 255, 255, 255), 9),
-            (Ipv4Addr::new(0, 0, 0, 0), 0),
+fn load_state(config: Config) -> Result<AppState, CliError> {
+    AppState::new(config).map_err(|e| {
+        CliError::new(
+            CliErrorKind::Config,
+            format!("invalid configuration: {e:#}"),
         )
+    })
+}
+
+/// Finds and checks the host like `GET /hosts/<name>/status` does.
+fn check(args: CheckArgs) -> ExitCode {
+    let started = Instant::now();
+    let result = check_host(&args);
+    let error = match &result {
+        Ok(status) if status.online == Some(true) => None,
+        Ok(status) => Some(CliError::new(
+            CliErrorKind::Offline,
+            describe_status(status),
+        )),
+        Err(e) => Some(e.clone()),
+    };
+    if args.output == Output::Json {
+        return report(
+            started,
+            CliReport {
+                command: "check".to_owned(),
+                status: result.ok(),
+                error,
+                ..CliReport::default()
+            },
+        );
+    }
+    // a host that's down is a result too, only failing to check it goes to stderr
+    match &result {
+        Ok(status) if args.json => match serde_json::to_string(status) {
+            Ok(json) => println!("{json}"),
+            Err(e) => eprintln!("failed to serialize status: {e}"),
+        },
+        Ok(status) => println!("{}", describe_status(status)),
+        Err(e) => eprintln!("{}", e.details.error),
+    }
+    ExitCode::from(exit_code(error.as_ref()))
+}
+
+fn check_host(args: &CheckArgs) -> Result<HostStatus, CliError> {
+    let mut config = load_config()?;
+    if let Some(port) = args.port {
+        config.verify = vec![Strategy::Tcp(port)];
+    }
+    if let Some(timeout) = args.timeout {
+        config.verify_timeout = Duration::from_secs(timeout);
+    }
+    let state = load_state(config)?;
+    match state.host_status(&args.host) {
+        Ok(Some(status)) => Ok(status),
+        Ok(None) => Err(CliError::new(
+            CliErrorKind::Resolution,
+            format!("host `{}` not found", args.host),
+        )),
+        Err(e) => Err(CliError::new(
+            CliErrorKind::Resolution,
+            format!("failed to check `{}`: {e:#}", args.host),
+        )),
+    }
+}
+
+/// `wake --mac` sends the packet itself, `wake <host>` wakes like the server does.
+async fn wake(args: WakeArgs) -> ExitCode {
+    let started = Instant::now();
+    let result = match &args.target {
+        Target::Mac(mac) => load_config().and_then(|config| wake_mac(&config, *mac)),
+        Target::Host(host) => match load_config().and_then(load_state) {
+            Ok(state) => Arc::new(state).wake_from_cli(host.clone(), args.wait).await,
+            Err(e) => Err(e),
+        },
+    };
+    // a host that didn't come up is a failure too
+    let (wake, error) = match result {
+        Ok(response) if response.online == Some(false) => {
+            let within = (args.wait)
+                .map(|wait| format!(" within {}s", wait.as_secs()))
+                .unwrap_or_default();
+            let message = format!("{}, it didn't come up{within}", response.summary());
+            let error = CliError::new(CliErrorKind::Offline, message);
+            (Some(response), Some(error))
+        }
+        Ok(response) => (Some(response), None),
+        Err(e) => (None, Some(e)),
+    };
+    if args.output == Output::Json {
+        return report(
+            started,
+            CliReport {
+                command: "wake".to_owned(),
+                wake,
+                error,
+                ..CliReport::default()
+            },
+        );
+    }
+    if let Some(error) = error {
+        eprintln!("{}", error.details.error);
+        return ExitCode::from(exit_code(Some(&error)));
+    }
+    let Some(response) = wake else {
+        unreachable!("either woken or an error");
+    };
+    match (&args.target, response.probe) {
+        (Target::Mac(mac), _) => {
+            let address = response.destinations[0].address;
+            println!("sent magic packet to {mac} via {address}");
+        }
+        (Target::Host(_), Some(probe)) if response.online == Some(true) => {
+            println!("{}, it came up ({probe})", response.summary());
+        }
+        (Target::Host(_), _) => println!("{}", response.summary()),
     }
+    ExitCode::SUCCESS
+}
 
-    /// Sends the magic packet via UDP to/from an IP address and port number of your choosing.
-    pub fn send_to<A: ToSocketAddrs>(&self, to_addr: A, from_addr: A) -> std::io::Result<()> {
-        let socket = UdpSocket::bind(from_addr)?;
-        socket.set_broadcast(true)?;
-        socket.send_to(&self.magic_bytes, to_addr)?;
+/// Sends the packet to the configured `broadcast`, what an `ssh` site runs where it is.
+fn wake_mac(config: &Config, mac: MacAddress) -> Result<WakeResponse, CliError> {
+    let from = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0));
+    if let Err(e) = MagicPacket::new(&mac.0).send_to(config.broadcast, from) {
+        return Err(CliError::new(
+            CliErrorKind::Send,
+            format!("failed to send magic packet to {mac}: {}", io_message(&e)),
+        ));
+    }
+    Ok(WakeResponse {
+        mac: mac.to_string(),
+        macs: vec![mac.to_string()],
+        destinations: vec![Destination {
+            mac: mac.to_string(),
+            address: config.broadcast,
+            source: None,
+            interface: None,
+            sent: true,
+            attempts: 1,
+            error: None,
+            hint: None,
+            skipped: Vec::new(),
+            queued: false,
+            reported_errors: None,
+        }],
+        source: "cli".to_owned(),
+        ..Default::default()
+    })
+}
 
-        Ok(())
+/// Lists the hosts like `GET /hosts` does, without the server.
+async fn list(output: Output) -> ExitCode {
+    let started = Instant::now();
+    let result = match load_config().and_then(load_state) {
+        Ok(state) => Ok(Arc::new(state).hosts().await),
+        Err(e) => Err(e),
+    };
+    if output == Output::Json {
+        let (hosts, error) = match result {
+            Ok(hosts) => (Some(hosts), None),
+            Err(e) => (None, Some(e)),
+        };
+        return report(
+            started,
+            CliReport {
+                command: "list".to_owned(),
+                hosts,
+                error,
+                ..CliReport::default()
+            },
+        );
     }
+    let hosts = match result {
+        Ok(hosts) => hosts,
+        Err(e) => {
+            eprintln!("{}", e.details.error);
+            return ExitCode::from(exit_code(Some(&e)));
+        }
+    };
+    let width = hosts.iter().map(|host| host.name.len()).max().unwrap_or(0);
+    for host in &hosts {
+        let status = match host.online {
+            Some(true) => "up",
+            Some(false) => "down",
+            None => "unknown",
+        };
+        println!("{:width$}  {}  {status}", host.name, host.macs.join(","));
+    }
+    ExitCode::SUCCESS
+}
 
-    /// Returns the magic packet's payload (6 repetitions of `0xFF` and 16 repetitions of the
-    /// target device's MAC address). Send these bytes yourself over the network if you want to do
-    /// something more advanced (like reuse a single UDP socket when sending a large number of
-    /// magic packets).
-    pub fn magic_bytes(&self) -> &[u8; 102] {
-        &self.magic_bytes
+async fn tui() -> ExitCode {
+    let state = match load_config().and_then(load_state) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{}", e.details.error);
+            return ExitCode::from(exit_code(Some(&e)));
+        }
+    };
+    match tui::run(state).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", io_message(&e));
+            ExitCode::from(EXIT_RUNTIME)
+        }
     }
 }
 
-const MAGIC_BYTES_HEADER: [u8; 6] = [0xFF; 6];
+fn describe_status(status: &HostStatus) -> String {
+    let host = &status.host;
+    match (status.ip, status.online, status.strategy) {
+        (None, _, _) => format!("{host} is down, it's not in the neighbor table"),
+        (Some(ip), Some(true), Some(strategy)) => format!("{host} is up at {ip} ({strategy})"),
+        (Some(ip), Some(false), Some(strategy)) => {
+            format!("{host} is down, {ip} didn't answer {strategy}")
+        }
+        (Some(ip), _, _) => format!("{host} at {ip} couldn't be checked"),
+    }
+}
+
+async fn run(logs: Arc<LogBuffer>) -> Result<(), Failure> {
+    let config = Config::load().map_err(config_error)?;
+    let addrs = config.listen.clone();
+    let relay_config = config.relay.clone();
+    let proxy_config = config.proxy.clone();
+    let telegram = config.telegram.clone().map(Telegram::new);
+    let mqtt = config.mqtt.clone().map(Mqtt::new);
+    if addrs.is_empty() {
+        return Err(config_error(eyre!("no listen addresses")));
+    }
+    // a registry or schedules file that can't be loaded is a configuration problem too
+    let state = Arc::new(AppState::new(config).map_err(config_error)?.with_logs(logs));
+
+    // build our application with a route
+    let app = server::router(state.clone());
+
+    // bind everything before serving anything, so a bad address doesn't leave us half-running
+    let listeners = server::bind_http(&addrs).map_err(|(addr, e)| {
+        Failure::Runtime(eyre!("failed to bind {addr}: {}", io_message(&e)))
+    })?;
+
+    let relay = match relay_config {
+        Some(relay_config) => {
+            let listen = relay_config.listen;
+            match Relay::bind(relay_config).await {
+                Ok(relay) => Some(relay),
+                Err(e) => {
+                    return Err(Failure::Runtime(eyre!(
+                        "failed to bind relay to {listen}: {}",
+                        io_message(&e)
+                    )))
+                }
+            }
+        }
+        None => None,
+    };
+    let proxy = match proxy_config {
+        Some(proxy_config) => {
+            let listen = proxy_config.listen;
+            match Proxy::bind(proxy_config).await {
+                Ok(proxy) => Some(proxy),
+                Err(e) => {
+                    return Err(Failure::Runtime(eyre!(
+                        "failed to bind proxy to {listen}: {}",
+                        io_message(&e)
+                    )))
+                }
+            }
+        }
+        None => None,
+    };
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(());
+    let mut servers = JoinSet::new();
+    for HttpListener {
+        addr,
+        dual_stack,
+        listener,
+    } in listeners
+    {
+        tracing::info!(%addr, dual_stack, "Starting server");
+        let app = app.clone();
+        let mut shutdown_rx = shutdown_rx.clone();
+        servers.spawn(async move {
+            let result = axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(async move {
+                let _ = shutdown_rx.changed().await;
+            })
+            .await;
+            (addr, result)
+        });
+    }
+
+    tokio::spawn(server::run_scheduler(state.clone()));
+    tokio::spawn(server::run_send_queue(state.clone()));
+    tokio::spawn(server::run_hooks(state.clone()));
+    tokio::spawn(server::run_janitor(state.clone()));
+    if let Some(telegram) = telegram {
+        tracing::info!("Starting telegram bot");
+        tokio::spawn(telegram.run(state.clone()));
+    }
+    if let Some(mqtt) = mqtt {
+        tracing::info!("Starting mqtt publisher");
+        tokio::spawn(mqtt.run(state.clone()));
+    }
+
+    let relay = async {
+        match relay {
+            Some(relay) => {
+                match relay.local_addr() {
+                    Ok(addr) => tracing::info!(%addr, "Starting relay"),
+                    Err(e) => tracing::warn!(?e, "Starting relay on unknown address"),
+                }
+                relay.run(state.clone()).await
+            }
+            None => std::future::pending().await,
+        }
+    };
+    let proxy = async {
+        match proxy {
+            Some(proxy) => {
+                match proxy.local_addr() {
+                    Ok(addr) => tracing::info!(%addr, "Starting proxy"),
+                    Err(e) => tracing::warn!(?e, "Starting proxy on unknown address"),
+                }
+                proxy.run(state.clone()).await
+            }
+            None => std::future::pending().await,
+        }
+    };
+
+    let mut result = tokio::select! {
+        _ = tokio::signal::ctrl_c() => {
+            tracing::info!("Shutting down");
+            Ok(())
+        }
+        Some(joined) = servers.join_next() => {
+            let stopped = match joined {
+                Ok((addr, Err(e))) => eyre!("server on {addr} failed: {}", io_message(&e)),
+                Ok((addr, Ok(()))) => eyre!("server on {addr} stopped unexpectedly"),
+                Err(e) => eyre!("server task failed: {e}"),
+            };
+            Err(Failure::Runtime(stopped))
+        }
+        result = relay => {
+            let stopped = match result {
+                Err(e) => e.wrap_err("relay failed"),
+                Ok(()) => eyre!("relay stopped unexpectedly"),
+            };
+            Err(Failure::Runtime(stopped))
+        }
+        result = proxy => {
+            let stopped = match result {
+                Err(e) => eyre!("proxy failed: {}", io_message(&e)),
+                Ok(()) => eyre!("proxy stopped unexpectedly"),
+            };
+            Err(Failure::Runtime(stopped))
+        }
+    };
+    state.shut_down().await;
+    let _ = shutdown_tx.send(());
+    while let Some(joined) = servers.join_next().await {
+        if let Ok((addr, Err(e))) = joined {
+            tracing::error!(%addr, "server failed: {}", io_message(&e));
+            if result.is_ok() {
+                result = Err(Failure::Runtime(eyre!("server on {addr} failed")));
+            }
+        }
+    }
+    result
+}
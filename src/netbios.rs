@@ -0,0 +1,133 @@
+//! Asking Windows machines for their name with a NetBIOS node status query, for those that
+//! don't have a reverse DNS entry.
+
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+/// Where NetBIOS name services listen.
+pub const PORT: u16 = 137;
+
+/// The node status query for the wildcard name `*`, which every machine answers. The
+/// transaction id is filled in before it's sent.
+const QUERY: [u8; 50] = {
+    let mut query = [0; 50];
+    // one question
+    query[5] = 1;
+    // the first level encoding of `*` padded with nuls, each nibble as a letter
+    query[12] = 32;
+    let mut index = 13;
+    while index < 45 {
+        query[index] = b'A';
+        index += 1;
+    }
+    query[13] = b'C';
+    query[14] = b'K';
+    // the end of the name, then NBSTAT in class IN
+    query[47] = 0x21;
+    query[49] = 1;
+    query
+};
+
+/// Asks the machine at `addr` (usually on [`PORT`]) for its name, `None` if it didn't answer in
+/// time or has none.
+pub fn node_status(addr: SocketAddr, timeout: Duration) -> io::Result<Option<String>> {
+    let bind = match addr {
+        SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+        SocketAddr::V6(_) => SocketAddr::from(([0u16; 8], 0)),
+    };
+    let socket = UdpSocket::bind(bind)?;
+    let id = transaction_id();
+    let mut query = QUERY;
+    query[..2].copy_from_slice(&id.to_be_bytes());
+    socket.send_to(&query, addr)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut response = [0; 1024];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let (len, from) = match socket.recv_from(&mut response) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(None)
+            }
+            // an ICMP port unreachable from an earlier send
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        // anything else on the port is ignored
+        if from == addr {
+            if let Some(name) = parse_node_status(&response[..len], id) {
+                return Ok(name);
+            }
+        }
+    }
+}
+
+/// Something different for every query, there's no need for it to be unpredictable.
+fn transaction_id() -> u16 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    (nanos ^ (nanos >> 16)) as u16
+}
+
+/// The machine name in a node status response to the query with the transaction id: the first
+/// unique name for the workstation service. `None` if it isn't such a response, `Some(None)` if
+/// it is but has no such name.
+pub fn parse_node_status(response: &[u8], id: u16) -> Option<Option<String>> {
+    // a response, without an error
+    if response.len() < 12
+        || response[..2] != id.to_be_bytes()
+        || response[2] & 0x80 == 0
+        || response[3] & 0x0f != 0
+    {
+        return None;
+    }
+    let mut position = 12;
+    // the name the answer is for, which is the one from the query but might be compressed
+    loop {
+        let len = *response.get(position)?;
+        match len {
+            0 => {
+                position += 1;
+                break;
+            }
+            len if len & 0xc0 == 0xc0 => {
+                position += 2;
+                break;
+            }
+            len => position += 1 + usize::from(len),
+        }
+    }
+    // type, class, ttl and the length of the data
+    let header = response.get(position..position + 10)?;
+    if header[..2] != [0, 0x21] {
+        return None;
+    }
+    let data = response.get(position + 10..)?;
+    let count = usize::from(*data.first()?);
+    let names = data.get(1..1 + count * 18)?;
+    let name = names.chunks_exact(18).find_map(|entry| {
+        let (name, suffix, flags) = (&entry[..15], entry[15], entry[16]);
+        // the workstation service, and not a group name
+        (suffix == 0 && flags & 0x80 == 0).then(|| {
+            String::from_utf8_lossy(name)
+                .trim_end_matches([' ', '\0'])
+                .to_owned()
+        })
+    });
+    Some(name.filter(|name| !name.is_empty()))
+}
@@ -0,0 +1,73 @@
+//! Retrying sends that failed for reasons that tend to go away on their own.
+
+use std::{io, time::Duration};
+
+/// How often and how patiently a failed send is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Including the first attempt, so `1` never retries.
+    pub max_attempts: u32,
+    /// The wait before the first retry, doubled for every one after it.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(4),
+        }
+    }
+}
+
+/// What a retried operation ended with.
+#[derive(Debug)]
+pub struct Attempts<T> {
+    pub result: io::Result<T>,
+    pub attempts: u32,
+}
+
+impl RetryPolicy {
+    /// Runs `op` until it succeeds, fails with an error that isn't transient, or runs out of
+    /// attempts. Blocks while backing off, so this belongs on a blocking thread.
+    pub fn run<T>(&self, mut op: impl FnMut() -> io::Result<T>) -> Attempts<T> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let result = op();
+            let error = match &result {
+                Err(e) if attempts < self.max_attempts && is_transient(e) => e,
+                _ => return Attempts { result, attempts },
+            };
+            let backoff = self.backoff(attempts);
+            tracing::warn!(?error, attempts, ?backoff, "send failed, retrying");
+            std::thread::sleep(backoff);
+        }
+    }
+
+    /// The wait after the `attempt`th attempt, somewhere between half and all of the
+    /// exponential backoff so retries of several sends don't all line up.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_backoff);
+        exponential / 2 + exponential.mul_f64(fastrand::f64() / 2.0)
+    }
+}
+
+/// Errors from a network that's briefly unavailable, like right after an interface came up.
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkDown
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+    )
+}
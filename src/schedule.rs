@@ -0,0 +1,254 @@
+//! When scheduled wakes happen, from cron expressions or at a fixed time.
+
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeDelta, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+/// A host that's woken on a schedule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawSchedule", into = "RawSchedule")]
+pub struct Schedule {
+    pub host: String,
+    pub when: When,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum When {
+    /// Again and again.
+    Cron(Cron),
+    /// Once.
+    At(DateTime<Utc>),
+}
+
+/// A schedule as it's written down, with exactly one of `cron` and `at`.
+#[derive(Serialize, Deserialize)]
+struct RawSchedule {
+    host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cron: Option<Cron>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<RawSchedule> for Schedule {
+    type Error = String;
+
+    fn try_from(raw: RawSchedule) -> Result<Self, Self::Error> {
+        let host = raw.host.trim();
+        if host.is_empty() {
+            return Err("missing host".to_owned());
+        }
+        let when = match (raw.cron, raw.at) {
+            (Some(cron), None) => When::Cron(cron),
+            (None, Some(at)) => When::At(at),
+            _ => return Err("expected either `cron` or `at`".to_owned()),
+        };
+        Ok(Schedule {
+            host: host.to_owned(),
+            when,
+        })
+    }
+}
+
+impl From<Schedule> for RawSchedule {
+    fn from(schedule: Schedule) -> Self {
+        let (cron, at) = match schedule.when {
+            When::Cron(cron) => (Some(cron), None),
+            When::At(at) => (None, Some(at)),
+        };
+        RawSchedule {
+            host: schedule.host,
+            cron,
+            at,
+        }
+    }
+}
+
+impl Schedule {
+    /// The first time it's due after `after`, `None` if it never is again.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match &self.when {
+            When::Cron(cron) => cron.next_after(after),
+            When::At(at) => (*at > after).then_some(*at),
+        }
+    }
+}
+
+/// A cron expression with the usual five fields (minute, hour, day of month, month and day of
+/// week), in the server's local time.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Cron {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Like in every cron, restricting both days and weekdays means either of them has to match.
+    days_and_weekdays: bool,
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+/// Far enough to find `0 0 29 2 *`.
+const MAX_SEARCH_YEARS: i32 = 8;
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "expected 5 fields in cron expression `{s}`, found {}",
+                fields.len()
+            ));
+        };
+        let mut weekday_bits = parse_field(weekdays, 0, 7, 0, &WEEKDAYS)?;
+        // both 0 and 7 are sunday
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits = (weekday_bits | 1) & !(1 << 7);
+        }
+        Ok(Cron {
+            source: fields.join(" "),
+            minutes: parse_field(minutes, 0, 59, 0, &[])?,
+            hours: parse_field(hours, 0, 23, 0, &[])?,
+            days: parse_field(days, 1, 31, 0, &[])?,
+            months: parse_field(months, 1, 12, 1, &MONTHS)?,
+            weekdays: weekday_bits,
+            days_and_weekdays: days != "*" && weekdays != "*",
+        })
+    }
+}
+
+/// Parses a comma separated list of `*`, `n` and `a-b`, each optionally with a `/step`,
+/// into the set of values it allows. `names` are the names of the values from `first_name` on.
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    first_name: u32,
+    names: &[&str],
+) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let value = match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            Some(i) => i as u32 + first_name,
+            None => s
+                .parse()
+                .map_err(|_| format!("invalid value `{s}` in cron field `{field}`"))?,
+        };
+        if !(min..=max).contains(&value) {
+            return Err(format!(
+                "`{s}` is out of range {min}-{max} in cron field `{field}`"
+            ));
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("invalid step `{step}` in cron field `{field}`"))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/10` means from 5 on
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("empty range `{range}` in cron field `{field}`"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl Cron {
+    /// The first minute after `after` that matches. Local times that happen twice when clocks go
+    /// back are due the first time, ones that don't happen when clocks go forward are skipped.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = after.with_timezone(&Local).naive_local();
+        let mut t = local.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        let limit = t.with_year(t.year() + MAX_SEARCH_YEARS)?;
+        while t < limit {
+            if !has(self.months, t.month()) {
+                t = start_of_next_month(t)?;
+            } else if !self.day_matches(t) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !has(self.hours, t.hour()) {
+                t = t.with_minute(0)? + TimeDelta::hours(1);
+            } else if !has(self.minutes, t.minute()) {
+                t += TimeDelta::minutes(1);
+            } else if let Some(at) = Local.from_local_datetime(&t).earliest() {
+                return Some(at.with_timezone(&Utc));
+            } else {
+                t += TimeDelta::minutes(1);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, t: NaiveDateTime) -> bool {
+        let day = has(self.days, t.day());
+        let weekday = has(self.weekdays, t.weekday().num_days_from_sunday());
+        if self.days_and_weekdays {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+}
+
+fn has(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+fn start_of_next_month(t: NaiveDateTime) -> Option<NaiveDateTime> {
+    let (year, month) = match t.month() {
+        12 => (t.year() + 1, 1),
+        month => (t.year(), month + 1),
+    };
+    chrono::NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl fmt::Debug for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cron({:?})", self.source)
+    }
+}
+
+impl Serialize for Cron {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cron {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
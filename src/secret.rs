@@ -0,0 +1,61 @@
+//! Values like tokens and passwords, which have to stay out of logs, errors and debug output.
+
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+/// What a secret is shown as, wherever it's formatted or serialized, like in `GET /api/v1/config`
+/// and `wakeonlan --print-config`.
+pub const REDACTED: &str = "<redacted>";
+
+/// A secret that's [`REDACTED`] when it's formatted, with `Debug` as well as `Display`, and when
+/// it's serialized. It's deserialized like the value itself, and only [`Secret::expose`] gives it
+/// back, for where it's actually used.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Secret<T> {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl Secret<String> {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret<String> {
+    fn from(value: String) -> Self {
+        Secret(value)
+    }
+}
+
+impl From<&str> for Secret<String> {
+    fn from(value: &str) -> Self {
+        Secret(value.to_owned())
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
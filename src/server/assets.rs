@@ -0,0 +1,56 @@
+//! The icons and the web app manifest, so the page can be added to a phone's home screen.
+//!
+//! Every URL in them is relative, they work the same when a reverse proxy serves the page under
+//! a path of its own.
+
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::sync::Arc;
+
+use super::AppState;
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/favicon.ico", get(|| asset("image/x-icon", FAVICON)))
+        .route("/icon-192.png", get(|| asset("image/png", ICON_192)))
+        .route("/icon-512.png", get(|| asset("image/png", ICON_512)))
+        .route(
+            "/manifest.json",
+            get(|| asset("application/manifest+json", MANIFEST.as_bytes())),
+        )
+}
+
+const FAVICON: &[u8] = include_bytes!("../../assets/favicon.ico");
+const ICON_192: &[u8] = include_bytes!("../../assets/icon-192.png");
+const ICON_512: &[u8] = include_bytes!("../../assets/icon-512.png");
+
+const MANIFEST: &str = r##"{
+  "name": "Wake on LAN",
+  "short_name": "Wake",
+  "start_url": "./",
+  "scope": "./",
+  "display": "standalone",
+  "background_color": "#24292f",
+  "theme_color": "#24292f",
+  "icons": [
+    { "src": "icon-192.png", "sizes": "192x192", "type": "image/png" },
+    { "src": "icon-512.png", "sizes": "512x512", "type": "image/png" }
+  ]
+}
+"##;
+
+/// They only change with the server, so a week is fine.
+async fn asset(content_type: &'static str, body: &'static [u8]) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, "public, max-age=604800"),
+        ],
+        body,
+    )
+        .into_response()
+}
@@ -0,0 +1,214 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    api::v1::{StrategyAttempt, WakeOutcome},
+    config::AuditConfig,
+};
+
+/// How many entries can wait for the writer before new ones are dropped.
+const QUEUE: usize = 1024;
+/// Failures are only logged once in this long, with how many there were since the last time.
+const ERROR_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A line in the audit log.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(super) enum AuditEvent {
+    Wake(AuditEntry),
+    LinkMinted(LinkEntry),
+    Strategies(StrategyEntry),
+}
+
+/// One wake attempt of one MAC.
+#[derive(Debug, Serialize)]
+pub(super) struct AuditEntry {
+    pub(super) at: DateTime<Utc>,
+    /// The id of the wake, shared by all its MACs.
+    pub(super) id: String,
+    pub(super) host: Option<String>,
+    pub(super) mac: String,
+    pub(super) requester: Option<IpAddr>,
+    pub(super) principal: Option<String>,
+    /// What started the wake, see [`WakeSource`](super::WakeSource).
+    pub(super) source: String,
+    pub(super) outcome: WakeOutcome,
+    pub(super) destinations: Vec<AuditDestination>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct AuditDestination {
+    pub(super) address: SocketAddr,
+    pub(super) sent: bool,
+}
+
+/// How the strategies of a host went, after the entries of its wake.
+#[derive(Debug, Serialize)]
+pub(super) struct StrategyEntry {
+    pub(super) at: DateTime<Utc>,
+    /// The id of the wake.
+    pub(super) id: String,
+    pub(super) host: String,
+    /// The one the host came up after, `None` if it didn't come up.
+    pub(super) worked: Option<String>,
+    pub(super) attempts: Vec<StrategyAttempt>,
+}
+
+/// A signed wake link that was handed out.
+#[derive(Debug, Serialize)]
+pub(super) struct LinkEntry {
+    pub(super) at: DateTime<Utc>,
+    pub(super) host: String,
+    pub(super) expires: DateTime<Utc>,
+    pub(super) requester: Option<IpAddr>,
+    pub(super) principal: Option<String>,
+}
+
+/// Appends wake attempts to a file as JSON lines, from a thread of its own so nothing that logs
+/// has to wait for the disk.
+pub(super) struct AuditLog {
+    sender: SyncSender<AuditEvent>,
+    dropped: Mutex<Throttle>,
+}
+
+impl AuditLog {
+    pub(super) fn start(config: &AuditConfig) -> AuditLog {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE);
+        let writer = Writer {
+            path: config.path.clone(),
+            max_bytes: config.max_bytes,
+            keep: config.keep,
+            file: None,
+            errors: Throttle::default(),
+        };
+        std::thread::spawn(move || writer.run(receiver));
+        AuditLog {
+            sender,
+            dropped: Mutex::new(Throttle::default()),
+        }
+    }
+
+    /// Never blocks, if the writer can't keep up the entry is dropped.
+    pub(super) fn log(&self, event: AuditEvent) {
+        if let Err(TrySendError::Full(event) | TrySendError::Disconnected(event)) =
+            self.sender.try_send(event)
+        {
+            let mut dropped = self.dropped.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(suppressed) = dropped.failed() {
+                tracing::error!(
+                    ?event,
+                    suppressed,
+                    "audit log can't keep up, dropping entry"
+                );
+            }
+        }
+    }
+}
+
+/// Counts failures, saying when it's time to log one.
+#[derive(Default)]
+struct Throttle {
+    last_logged: Option<Instant>,
+    suppressed: u64,
+}
+
+impl Throttle {
+    /// With how many failures weren't logged before this one if it should be logged.
+    fn failed(&mut self) -> Option<u64> {
+        if self
+            .last_logged
+            .is_some_and(|last| last.elapsed() < ERROR_INTERVAL)
+        {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last_logged = Some(Instant::now());
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
+
+struct Writer {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: u32,
+    /// The open log and how large it is.
+    file: Option<(File, u64)>,
+    errors: Throttle,
+}
+
+impl Writer {
+    fn run(mut self, receiver: Receiver<AuditEvent>) {
+        for event in receiver {
+            let mut line = serde_json::to_vec(&event).expect("audit event serializes");
+            line.push(b'\n');
+            if let Err(e) = self.write(&line) {
+                // reopened for the next entry
+                self.file = None;
+                if let Some(suppressed) = self.errors.failed() {
+                    tracing::error!(?e, path = %self.path.display(), suppressed, "failed to write audit log");
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, line: &[u8]) -> std::io::Result<()> {
+        let size = match &self.file {
+            Some((_, size)) => *size,
+            None => {
+                let (file, size) = self.open()?;
+                self.file = Some((file, size));
+                size
+            }
+        };
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            self.file = None;
+            self.rotate()?;
+            self.file = Some(self.open()?);
+        }
+        let (file, size) = self.file.as_mut().expect("opened above");
+        file.write_all(line)?;
+        *size += line.len() as u64;
+        Ok(())
+    }
+
+    fn open(&self) -> std::io::Result<(File, u64)> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let size = file.metadata()?.len();
+        Ok((file, size))
+    }
+
+    /// Moves `audit.log` to `audit.log.1`, `audit.log.1` to `audit.log.2` and so on,
+    /// dropping the oldest.
+    fn rotate(&self) -> std::io::Result<()> {
+        if self.keep == 0 {
+            return std::fs::remove_file(&self.path);
+        }
+        for n in (1..self.keep).rev() {
+            let from = rotated(&self.path, n);
+            if from.exists() {
+                std::fs::rename(&from, rotated(&self.path, n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated(&self.path, 1))
+    }
+}
+
+fn rotated(path: &Path, n: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{n}"));
+    PathBuf::from(rotated)
+}
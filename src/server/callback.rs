@@ -0,0 +1,172 @@
+use eyre::bail;
+use serde::Serialize;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::{wait, AppState};
+use crate::{
+    api::v1::{Delivery, DeliveryStatus},
+    verify::Strategy,
+    MacAddress,
+};
+
+/// Including the first attempt.
+const ATTEMPTS: u32 = 3;
+/// The wait before the first retry, doubled for the one after it.
+const BACKOFF: Duration = Duration::from_secs(2);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A callback that's due once a wake is done.
+pub(super) struct Callback {
+    pub(super) url: String,
+    /// The id of the wake, to find it in the history.
+    pub(super) id: String,
+    pub(super) host: Option<String>,
+    pub(super) macs: Vec<MacAddress>,
+    pub(super) outcome: Outcome,
+    /// With a packet sent, wait this long for the host to come up before calling back.
+    pub(super) wait_online: Option<Duration>,
+    /// What to wait for it with instead of the configured `verify`.
+    pub(super) probe: Option<Strategy>,
+    /// When the wake was asked for.
+    pub(super) started: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Outcome {
+    Sent,
+    Failed,
+    TimedOut,
+    /// Sent, and the host came up in time.
+    Online,
+    /// Sent, but the host didn't come up in time.
+    Offline,
+}
+
+/// What's posted to the callback URL.
+#[derive(Serialize)]
+struct CallbackBody<'a> {
+    id: &'a str,
+    host: Option<&'a str>,
+    macs: Vec<String>,
+    outcome: Outcome,
+    /// Since the wake was asked for, in seconds.
+    elapsed: f64,
+}
+
+/// How a delivery starts out, before the wake is done.
+pub(super) fn pending(url: &str) -> Delivery {
+    Delivery {
+        url: url.to_owned(),
+        status: DeliveryStatus::Pending,
+        attempts: 0,
+        error: None,
+    }
+}
+
+/// Checks that a callback URL is `http://` or `https://` and starts with one of the allowed prefixes,
+/// where the prefix has to end at a path boundary (`http://ci/hooks` doesn't allow
+/// `http://ci/hooks-admin` or `http://ci/hooks.evil.example`).
+pub(super) fn check_url(allowed: &[String], url: &str) -> Result<(), String> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err("only http:// and https:// callback urls are supported".to_owned());
+    }
+    if url.split(['/', '?', '#']).any(|segment| segment == "..") {
+        return Err("callback url must not contain `..`".to_owned());
+    }
+    let allowed = allowed.iter().any(|prefix| {
+        url.strip_prefix(prefix.as_str()).is_some_and(|rest| {
+            prefix.ends_with('/') || rest.is_empty() || rest.starts_with(['/', '?', '#'])
+        })
+    });
+    if !allowed {
+        return Err("callback url is not allowed".to_owned());
+    }
+    Ok(())
+}
+
+/// Waits for the host if asked to, then calls back, recording how that went in the history.
+pub(super) async fn run(state: Arc<AppState>, callback: Callback) {
+    let outcome = match (callback.outcome, callback.wait_online) {
+        (Outcome::Sent, Some(timeout)) => {
+            let strategies = state.probes(callback.probe);
+            let verified =
+                wait::wait_with(&state, callback.macs.clone(), strategies, timeout).await;
+            let probe = verified
+                .map(|verified| verified.strategy)
+                .or(callback.probe);
+            if let Some(probe) = probe {
+                state.record_probe(&callback.macs, &callback.id, probe);
+            }
+            match verified {
+                Some(_) => Outcome::Online,
+                None => Outcome::Offline,
+            }
+        }
+        (outcome, _) => outcome,
+    };
+    let body = CallbackBody {
+        id: &callback.id,
+        host: callback.host.as_deref(),
+        macs: callback.macs.iter().map(MacAddress::to_string).collect(),
+        outcome,
+        elapsed: callback.started.elapsed().as_secs_f64(),
+    };
+    let body = serde_json::to_vec(&body).expect("callback body serializes");
+
+    let delivery = deliver(&state.http, &callback.url, body).await;
+    match delivery.status {
+        DeliveryStatus::Delivered => {
+            tracing::info!(url = %callback.url, id = %callback.id, ?outcome, "Called back")
+        }
+        _ => {
+            tracing::error!(url = %callback.url, id = %callback.id, ?delivery, "failed to call back")
+        }
+    }
+    state.record_callback(&callback.macs, &callback.id, delivery);
+}
+
+/// Posts the body, retrying a couple of times.
+async fn deliver(client: &reqwest::Client, url: &str, body: Vec<u8>) -> Delivery {
+    let mut backoff = BACKOFF;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let error = match post(client, url, body.clone()).await {
+            Ok(()) => None,
+            Err(e) => Some(format!("{e:#}")),
+        };
+        if error.is_none() || attempts == ATTEMPTS {
+            return Delivery {
+                url: url.to_owned(),
+                status: match error {
+                    None => DeliveryStatus::Delivered,
+                    Some(_) => DeliveryStatus::Failed,
+                },
+                attempts,
+                error,
+            };
+        }
+        tracing::warn!(%url, ?error, attempts, ?backoff, "callback failed, retrying");
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+/// Succeeds on any 2xx answer.
+async fn post(client: &reqwest::Client, url: &str, body: Vec<u8>) -> eyre::Result<()> {
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .timeout(TIMEOUT)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        bail!("answered with status {}", response.status().as_u16());
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! Wakes that are asked for again while they're in progress, like from several phones at once,
+//! which get the result of the one in progress instead of waking the host again.
+
+use axum::http::StatusCode;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::watch;
+
+use super::{wake::Finished, AppState};
+use crate::{
+    api::v1::{VerifyOverride, WakeRequest},
+    MacAddress,
+};
+
+/// The wakes in progress, each publishing how it went once it's done.
+pub(super) type InFlight = Mutex<HashMap<Key, watch::Receiver<Option<Finished>>>>;
+
+/// What makes two wakes the same: the MACs they wake, however they were named, and all options
+/// that change how.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct Key {
+    /// Sorted, without duplicates.
+    macs: Vec<MacAddress>,
+    dry_run: bool,
+    refresh: Option<bool>,
+    force: bool,
+    confirm: bool,
+    skip_if_online: Option<bool>,
+    wait_online: Option<u64>,
+    verify: Option<VerifyOverride>,
+}
+
+impl Key {
+    /// For a wake of these MACs, as they were found for it.
+    pub(super) fn new(params: &WakeRequest, macs: &[MacAddress]) -> Self {
+        let mut macs = macs.to_vec();
+        macs.sort_by_key(|mac| mac.0);
+        macs.dedup();
+        Key {
+            macs,
+            dry_run: params.dry_run,
+            refresh: params.refresh,
+            force: params.force,
+            confirm: params.confirm,
+            skip_if_online: params.skip_if_online,
+            wait_online: params.wait_online,
+            verify: params.verify,
+        }
+    }
+}
+
+/// Runs the wake, unless the same one is in progress already, then it waits for that one
+/// instead. Returns how it went and whether it was another one's.
+pub(super) async fn join(
+    state: &Arc<AppState>,
+    key: Key,
+    wake: impl Future<Output = Finished> + Send + 'static,
+) -> (Finished, bool) {
+    let (mut finished, coalesced) = {
+        let mut in_flight = state.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        match in_flight.get(&key) {
+            Some(running) => (running.clone(), true),
+            None => {
+                let (sender, receiver) = watch::channel(None);
+                in_flight.insert(key.clone(), receiver.clone());
+                // on its own, so the others still get the result if this request goes away
+                tokio::spawn(run(state.clone(), key, wake, sender));
+                (receiver, false)
+            }
+        }
+    };
+    let finished = match finished.wait_for(Option::is_some).await {
+        Ok(finished) => (*finished).clone().expect("waited until it's there"),
+        Err(e) => {
+            tracing::error!(?e, "wake in progress went away");
+            Finished::failed(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "the wake this one is the same as failed".to_owned(),
+            )
+        }
+    };
+    (finished, coalesced)
+}
+
+/// Removes the wake from those in progress once it's done, even if it panicked.
+struct Done {
+    state: Arc<AppState>,
+    key: Key,
+}
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        let mut in_flight = (self.state.in_flight.lock()).unwrap_or_else(|e| e.into_inner());
+        in_flight.remove(&self.key);
+    }
+}
+
+async fn run(
+    state: Arc<AppState>,
+    key: Key,
+    wake: impl Future<Output = Finished>,
+    sender: watch::Sender<Option<Finished>>,
+) {
+    let done = Done { state, key };
+    let finished = wake.await;
+    // removed first, so a wake asked for right after this one wakes the host again
+    drop(done);
+    sender.send_replace(Some(finished));
+}
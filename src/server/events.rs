@@ -0,0 +1,179 @@
+//! `GET /api/v1/events`, a stream of everything the server does for automation to react to, as
+//! server-sent events.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::Utc;
+use http_body_util::{channel::Sender, Channel};
+use serde::Deserialize;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use super::AppState;
+use crate::api::v1::{ErrorResponse, Event, EventStats, PublishedEvent, Size, WakeResponse};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/events", get(subscribe))
+}
+
+/// How often a subscriber gets a comment when nothing happens, so proxies don't close the
+/// connection.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// What's waiting to be written to a subscriber's connection, beyond that the events wait in
+/// the channel.
+const BUFFERED: usize = 16;
+
+/// Every event goes to every subscriber. Publishing never waits for one, a subscriber that
+/// doesn't keep up misses the oldest events instead.
+pub(super) struct Events {
+    sender: broadcast::Sender<Arc<PublishedEvent>>,
+    capacity: usize,
+    published: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Events {
+    pub(super) fn new(capacity: usize) -> Self {
+        // a channel without room panics
+        let capacity = capacity.max(1);
+        Events {
+            sender: broadcast::Sender::new(capacity),
+            capacity,
+            published: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub(super) fn publish(&self, event: Event) {
+        let seq = self.published.fetch_add(1, Ordering::Relaxed);
+        // without subscribers, nobody gets it
+        let _ = self.sender.send(Arc::new(PublishedEvent {
+            seq,
+            at: Utc::now(),
+            event,
+        }));
+    }
+
+    pub(super) fn wake_sent(&self, response: &WakeResponse) {
+        self.publish(Event::WakeSent {
+            id: response.id.clone(),
+            host: response.host.clone(),
+            macs: response.macs.clone(),
+            destinations: response.destinations.clone(),
+            site: response.site.clone(),
+            dry_run: response.dry_run,
+        });
+    }
+
+    pub(super) fn wake_failed(&self, id: &str, host: Option<&str>, error: &ErrorResponse) {
+        self.publish(Event::WakeFailed {
+            id: id.to_owned(),
+            host: host.map(str::to_owned),
+            error: error.error.clone(),
+        });
+    }
+
+    pub(super) fn stats(&self) -> EventStats {
+        EventStats {
+            subscribers: self.sender.receiver_count(),
+            published: self.published.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            buffered: Size {
+                entries: self.sender.len(),
+                limit: Some(self.capacity),
+            },
+        }
+    }
+}
+
+impl AppState {
+    /// Every event from now on, like `GET /api/v1/events` streams them.
+    pub fn events(&self) -> broadcast::Receiver<Arc<PublishedEvent>> {
+        self.events.sender.subscribe()
+    }
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// Only these events, comma separated like `wake_sent,host_online`.
+    #[serde(default)]
+    events: Option<String>,
+}
+
+async fn subscribe(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<EventsQuery>,
+) -> Response {
+    let wanted = query.events.map(|events| {
+        (events.split(','))
+            .map(|event| event.trim().to_owned())
+            .filter(|event| !event.is_empty())
+            .collect::<Vec<_>>()
+    });
+    let receiver = state.events.sender.subscribe();
+    let (sender, channel) = Channel::<Bytes, io::Error>::new(BUFFERED);
+    tokio::spawn(stream(state, receiver, wanted, sender));
+    (
+        [
+            (header::CONTENT_TYPE, "text/event-stream"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        Body::new(channel),
+    )
+        .into_response()
+}
+
+/// Writes the events to the subscriber until it goes away or the server shuts down.
+async fn stream(
+    state: Arc<AppState>,
+    mut receiver: broadcast::Receiver<Arc<PublishedEvent>>,
+    wanted: Option<Vec<String>>,
+    mut sender: Sender<Bytes, io::Error>,
+) {
+    let mut shutdown = state.shutdown.subscribe();
+    let mut keep_alive =
+        tokio::time::interval_at(tokio::time::Instant::now() + KEEP_ALIVE, KEEP_ALIVE);
+    loop {
+        let message = tokio::select! {
+            received = receiver.recv() => match received {
+                Ok(event) => {
+                    let name = event.event.name();
+                    if wanted.as_ref().is_some_and(|wanted| !wanted.iter().any(|wanted| wanted == name)) {
+                        continue;
+                    }
+                    match serde_json::to_string(&*event) {
+                        Ok(data) => format!("id: {}\nevent: {name}\ndata: {data}\n\n", event.seq),
+                        Err(e) => {
+                            tracing::error!(?e, "failed to serialize event");
+                            continue;
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "events subscriber doesn't keep up, it missed some");
+                    state.events.dropped.fetch_add(missed, Ordering::Relaxed);
+                    format!(": missed {missed} events\n\n")
+                }
+                Err(RecvError::Closed) => return,
+            },
+            _ = keep_alive.tick() => ": keep-alive\n\n".to_owned(),
+            _ = shutdown.wait_for(|&down| down) => return,
+        };
+        if sender.send_data(Bytes::from(message)).await.is_err() {
+            return;
+        }
+    }
+}
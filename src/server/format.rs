@@ -0,0 +1,75 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+
+/// Whether to answer with a page or with JSON. `?format=json|html` decides if it's there,
+/// otherwise `Accept` does: `text/html` gets a page if it's preferred over `application/json`,
+/// everything else gets JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ResponseFormat {
+    Html,
+    Json,
+}
+
+impl ResponseFormat {
+    /// `None` if the request doesn't say, the endpoint picks then.
+    pub(super) fn negotiate(headers: &HeaderMap, uri: &Uri) -> Result<Option<Self>, String> {
+        let format = uri
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("format="));
+        match format {
+            Some("json") => return Ok(Some(ResponseFormat::Json)),
+            Some("html") => return Ok(Some(ResponseFormat::Html)),
+            Some(other) => {
+                return Err(format!(
+                    "unsupported format `{other}`, expected `json` or `html`"
+                ))
+            }
+            None => {}
+        }
+
+        let accept = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        let (mut html, mut json) = (None, None);
+        for range in accept {
+            let mut params = range.split(';');
+            let media_type = params.next().unwrap_or_default().trim();
+            let quality = params
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let best = match media_type {
+                "text/html" => &mut html,
+                "application/json" => &mut json,
+                _ => continue,
+            };
+            *best = Some(best.unwrap_or(0.0f32).max(quality));
+        }
+        Ok(match (html, json) {
+            (None, None) => None,
+            (Some(html), json) if html > 0.0 && html > json.unwrap_or(0.0) => {
+                Some(ResponseFormat::Html)
+            }
+            _ => Some(ResponseFormat::Json),
+        })
+    }
+}
+
+/// For endpoints that answer with JSON unless a page is asked for.
+impl<S: Send + Sync> FromRequestParts<S> for ResponseFormat {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match ResponseFormat::negotiate(&parts.headers, &parts.uri) {
+            Ok(format) => Ok(format.unwrap_or(ResponseFormat::Json)),
+            Err(e) => Err((StatusCode::BAD_REQUEST, e).into_response()),
+        }
+    }
+}
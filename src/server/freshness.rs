@@ -0,0 +1,72 @@
+//! When discovery last worked, for `discovery_freshness`, so that automatic wakes don't go by
+//! what it found long ago while it has been failing since.
+
+use chrono::{DateTime, Utc};
+use std::sync::Mutex;
+
+use super::AppState;
+use crate::{api::v1::StaleDiscovery, discovery::HostEntry};
+
+pub(super) struct Freshness(Mutex<Entries>);
+
+struct Entries {
+    started: DateTime<Utc>,
+    last_success: Option<DateTime<Utc>>,
+    /// Why discovery failed the last time, `None` if it worked.
+    error: Option<String>,
+}
+
+impl Default for Freshness {
+    fn default() -> Self {
+        Freshness(Mutex::new(Entries {
+            started: Utc::now(),
+            last_success: None,
+            error: None,
+        }))
+    }
+}
+
+impl Freshness {
+    /// Discovery was tried, and this is what came of it.
+    pub(super) fn discovered(&self, result: &eyre::Result<Vec<HostEntry>>) {
+        let mut entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Ok(_) => {
+                entries.last_success = Some(Utc::now());
+                entries.error = None;
+            }
+            Err(e) => entries.error = Some(format!("{e:#}")),
+        }
+    }
+
+    /// How long ago discovery last worked (or the server started, if it didn't yet), in
+    /// seconds.
+    pub(super) fn age(&self) -> u64 {
+        let entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let since = entries.last_success.unwrap_or(entries.started);
+        (Utc::now() - since)
+            .num_seconds()
+            .try_into()
+            .unwrap_or_default()
+    }
+
+    /// Stale after `max_age` seconds, or right away if it failed without ever having worked.
+    fn stale(&self, max_age: u64) -> Option<StaleDiscovery> {
+        let age = self.age();
+        let entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let never_worked = entries.last_success.is_none() && entries.error.is_some();
+        (age > max_age || never_worked).then(|| StaleDiscovery {
+            last_success: entries.last_success,
+            age,
+            error: entries.error.clone(),
+        })
+    }
+}
+
+impl AppState {
+    /// How long ago discovery last worked, if that's longer than `discovery_freshness.max_age`.
+    pub(super) fn stale_discovery(&self) -> Option<StaleDiscovery> {
+        let freshness = self.config.discovery_freshness.as_ref()?;
+        self.freshness.stale(freshness.max_age)
+    }
+}
@@ -0,0 +1,41 @@
+//! `GET /healthz`, for container health checks and for finding out why wakes find nothing.
+
+use axum::{extract::State, routing::get, Json, Router};
+use std::sync::Arc;
+
+use super::{wake::NOTHING_DISCOVERED, AppState};
+use crate::api::v1::{Health, SelfTest};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/healthz", get(health))
+}
+
+async fn health(State(state): State<Arc<AppState>>) -> Json<Health> {
+    let configured = state.registry.all().len();
+    let discovery = tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || state.discover()
+    })
+    .await;
+    let (discovered, mut problems) = match discovery {
+        Ok(Ok(entries)) if entries.is_empty() => (0, vec![NOTHING_DISCOVERED.to_owned()]),
+        Ok(Ok(entries)) => (entries.len(), Vec::new()),
+        Ok(Err(e)) => (0, vec![format!("discovery failed: {e:#}")]),
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            (0, vec!["failed to spawn discovery".to_owned()])
+        }
+    };
+    if let Some(SelfTest {
+        error: Some(error), ..
+    }) = &state.self_test
+    {
+        problems.push(format!("send self-test failed: {error}"));
+    }
+    Json(Health {
+        discovered,
+        configured,
+        problems,
+        self_test: state.self_test.clone(),
+    })
+}
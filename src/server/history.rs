@@ -0,0 +1,70 @@
+//! `GET /api/v1/history`, the wakes of every MAC in the order they happened, not only the last one
+//! of each like in `GET /hosts`. Only the most recent `limits.history` are kept.
+
+use axum::{extract::State, routing::get, Json, Router};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use super::AppState;
+use crate::{
+    api::v1::{HistoryEntry, LastWake},
+    MacAddress,
+};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/history", get(history))
+}
+
+/// The recorded wakes, the oldest first.
+#[derive(Default)]
+pub(super) struct History(Mutex<VecDeque<(MacAddress, HistoryEntry)>>);
+
+impl History {
+    /// Adds the wake, forgetting the oldest ones over `limit`.
+    pub(super) fn push(&self, mac: MacAddress, host: Option<&str>, wake: LastWake, limit: usize) {
+        let mut wakes = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        wakes.push_back((
+            mac,
+            HistoryEntry {
+                mac: mac.to_string(),
+                host: host.map(str::to_owned),
+                wake,
+            },
+        ));
+        while wakes.len() > limit {
+            wakes.pop_front();
+        }
+    }
+
+    /// Changes the wake with the id for each of the MACs, if it's still kept.
+    pub(super) fn update(
+        &self,
+        macs: &[MacAddress],
+        id: &str,
+        mut change: impl FnMut(&mut LastWake),
+    ) {
+        let mut wakes = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for (_, entry) in wakes
+            .iter_mut()
+            .filter(|(mac, entry)| entry.wake.id == id && macs.contains(mac))
+        {
+            change(&mut entry.wake);
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// The newest first.
+    fn entries(&self) -> Vec<HistoryEntry> {
+        let wakes = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        wakes.iter().rev().map(|(_, entry)| entry.clone()).collect()
+    }
+}
+
+async fn history(State(state): State<Arc<AppState>>) -> Json<Vec<HistoryEntry>> {
+    Json(state.history.entries())
+}
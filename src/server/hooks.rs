@@ -0,0 +1,148 @@
+//! The `post_wake_commands` of the configured hosts, run once they were woken.
+
+use std::{
+    collections::VecDeque,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{process::Command, sync::Notify};
+
+use super::{wait, AppState};
+use crate::{api::v1::HookRun, MacAddress};
+
+/// The wakes whose commands are yet to be started by [`run_hooks`].
+#[derive(Default)]
+pub(super) struct Hooks {
+    due: Mutex<VecDeque<Due>>,
+    added: Notify,
+}
+
+struct Due {
+    /// Of the wake, the runs are recorded with it.
+    id: String,
+    host: String,
+    macs: Vec<MacAddress>,
+    commands: Vec<String>,
+}
+
+impl AppState {
+    /// Has the commands of the configured host run once it's up, if it has any and hooks aren't
+    /// turned off. The host is found by its name or any of the MACs.
+    pub(super) fn run_post_wake(&self, id: &str, host: Option<&str>, macs: &[MacAddress]) {
+        if !self.config.hooks.enabled {
+            return;
+        }
+        let Some(configured) = self.config.hosts.iter().find(|configured| {
+            host.is_some_and(|host| configured.name.eq_ignore_ascii_case(host))
+                || configured.macs.iter().any(|mac| macs.contains(mac))
+        }) else {
+            return;
+        };
+        if configured.post_wake_commands.is_empty() {
+            return;
+        }
+        self.hooks
+            .due
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(Due {
+                id: id.to_owned(),
+                host: configured.name.clone(),
+                macs: macs.to_vec(),
+                commands: configured.post_wake_commands.clone(),
+            });
+        self.hooks.added.notify_one();
+    }
+}
+
+/// Runs the commands of every woken host that has some, each host on its own.
+pub async fn run_hooks(state: Arc<AppState>) {
+    loop {
+        let due = state
+            .hooks
+            .due
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front();
+        match due {
+            Some(due) => {
+                tokio::spawn(run(state.clone(), due));
+            }
+            None => state.hooks.added.notified().await,
+        }
+    }
+}
+
+/// Waits for the host if that's configured, then runs its commands one after the other, even
+/// if one of them fails.
+async fn run(state: Arc<AppState>, due: Due) {
+    let config = &state.config.hooks;
+    if config.wait_online > 0 {
+        let timeout = Duration::from_secs(config.wait_online);
+        if wait::wait_until_online(&state, due.macs.clone(), timeout)
+            .await
+            .is_none()
+        {
+            tracing::warn!(host = %due.host, id = %due.id, ?timeout, "host didn't come up, not running its post wake commands");
+            return;
+        }
+    }
+    let timeout = Duration::from_secs(config.timeout);
+    for command in &due.commands {
+        let run = run_command(command, &due, timeout).await;
+        state.record_hook(&due.macs, &due.id, run);
+    }
+}
+
+/// Runs it with `sh -c`, with the host in `WOL_HOST` and the id of the wake in `WOL_WAKE_ID`.
+async fn run_command(command: &str, due: &Due, timeout: Duration) -> HookRun {
+    let started = Instant::now();
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("WOL_HOST", &due.host)
+        .env("WOL_WAKE_ID", &due.id)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        // so the timeout kills it
+        .kill_on_drop(true)
+        .spawn();
+    let output = match child {
+        Ok(child) => match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(Ok(output)) => Ok(output),
+            Ok(Err(e)) => Err(format!("failed to wait for it: {e}")),
+            Err(_) => Err(format!("timed out after {timeout:?}")),
+        },
+        Err(e) => Err(format!("failed to start: {e}")),
+    };
+    let duration = started.elapsed().as_secs_f64();
+
+    let (exit_code, error) = match output {
+        Ok(output) if output.status.success() => {
+            tracing::info!(host = %due.host, id = %due.id, %command, duration, "Ran post wake command");
+            (output.status.code(), None)
+        }
+        Ok(output) => {
+            let error = match output.status.code() {
+                Some(code) => format!("exited with status {code}"),
+                None => "killed by a signal".to_owned(),
+            };
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            tracing::warn!(host = %due.host, id = %due.id, %command, %error, stderr = %stderr.trim(), "post wake command failed");
+            (output.status.code(), Some(error))
+        }
+        Err(error) => {
+            tracing::warn!(host = %due.host, id = %due.id, %command, %error, "post wake command failed");
+            (None, Some(error))
+        }
+    };
+    HookRun {
+        command: command.to_owned(),
+        exit_code,
+        success: error.is_none(),
+        duration,
+        error,
+    }
+}
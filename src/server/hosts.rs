@@ -0,0 +1,584 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+
+use super::{
+    audit::{AuditDestination, AuditEntry, AuditEvent},
+    format::ResponseFormat,
+    html::{hosts_page, html_escape, html_page, refreshing_page},
+    janitor,
+    wake::used_destination,
+    AppState, RequestContext,
+};
+use crate::{
+    api::v1::{
+        CoalescedRequest, Delivery, Destination, HookRun, Host, HostSource, HostStatus, LastWake,
+        SiteHosts, WakeOutcome, WakeResponse,
+    },
+    discovery::{self, Backend, HostEntry, NeighborState},
+    verify::{self, Strategy, Verified},
+    MacAddress,
+};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/hosts", get(hosts))
+        .route("/hosts/{name}/status", get(status))
+        .route("/hosts/{name}/open", get(open))
+}
+
+/// How often the page of a wake checks whether the host is up, in seconds.
+pub(super) const OPEN_INTERVAL: u64 = 2;
+/// How often it checks before giving up, a few minutes with the checks themselves.
+const OPEN_ATTEMPTS: u32 = 90;
+/// A wake remembers at most this many of the requests that were coalesced into it.
+const MAX_COALESCED: usize = 100;
+
+/// A random id for a wake, to find it in the history later.
+pub(super) fn new_wake_id() -> String {
+    format!("{:016x}", fastrand::u64(..))
+}
+
+impl AppState {
+    /// Remembers the wake as the last one of the MAC, and puts it in the history, the audit log
+    /// and the metrics.
+    pub(super) fn record_wake(
+        &self,
+        mac: MacAddress,
+        id: &str,
+        host: Option<&str>,
+        context: &RequestContext,
+        outcome: WakeOutcome,
+        destinations: Vec<AuditDestination>,
+    ) {
+        let at = Utc::now();
+        let source = context.source.to_string();
+        self.metrics.attempted(&source, outcome);
+        if let Some(audit) = &self.audit {
+            audit.log(AuditEvent::Wake(AuditEntry {
+                at,
+                id: id.to_owned(),
+                host: host.map(str::to_owned),
+                mac: mac.to_string(),
+                requester: context.client,
+                principal: context.principal.clone(),
+                source: source.clone(),
+                outcome,
+                destinations,
+            }));
+        }
+        if outcome == WakeOutcome::Sent {
+            self.stats.attempted(mac, host, at);
+        }
+        let wake = LastWake {
+            id: id.to_owned(),
+            at,
+            requester: context.client,
+            principal: context.principal.clone(),
+            source,
+            outcome,
+            callback: None,
+            hooks: Vec::new(),
+            strategy: None,
+            destination: None,
+            probe: None,
+            coalesced: Vec::new(),
+        };
+        self.history
+            .push(mac, host, wake.clone(), self.config.limits.history);
+        let mut last_wakes = self.last_wakes.lock().unwrap_or_else(|e| e.into_inner());
+        last_wakes.insert(mac, wake);
+        janitor::trim(&mut last_wakes, self.config.limits.history, |wake| wake.at);
+        self.mqtt.changed();
+    }
+
+    /// Notes how calling back went with the wake, in the history and as the last wake unless the
+    /// MACs were woken again since.
+    pub(super) fn record_callback(&self, macs: &[MacAddress], id: &str, delivery: Delivery) {
+        self.history
+            .update(macs, id, |wake| wake.callback = Some(delivery.clone()));
+        let mut last_wakes = self.last_wakes.lock().unwrap_or_else(|e| e.into_inner());
+        for mac in macs {
+            if let Some(wake) = last_wakes.get_mut(mac).filter(|wake| wake.id == id) {
+                wake.callback = Some(delivery.clone());
+            }
+        }
+        self.mqtt.changed();
+    }
+
+    /// Notes what was waited for the host with, in the history and as the last wake unless the
+    /// MACs were woken again since.
+    pub(super) fn record_probe(&self, macs: &[MacAddress], id: &str, probe: Strategy) {
+        self.history
+            .update(macs, id, |wake| wake.probe = Some(probe));
+        let mut last_wakes = self.last_wakes.lock().unwrap_or_else(|e| e.into_inner());
+        for mac in macs {
+            if let Some(wake) = last_wakes.get_mut(mac).filter(|wake| wake.id == id) {
+                wake.probe = Some(probe);
+            }
+        }
+        self.mqtt.changed();
+    }
+
+    /// Notes which of the host's `destinations` the packet for each of the MACs got out to, in
+    /// the history and as the last wake unless the MACs were woken again since.
+    pub(super) fn record_destination(&self, macs: &[MacAddress], id: &str, sent: &[Destination]) {
+        for mac in macs {
+            self.history.update(&[*mac], id, |wake| {
+                wake.destination = used_destination(sent, *mac);
+            });
+        }
+        let mut last_wakes = self.last_wakes.lock().unwrap_or_else(|e| e.into_inner());
+        for mac in macs {
+            if let Some(wake) = last_wakes.get_mut(mac).filter(|wake| wake.id == id) {
+                wake.destination = used_destination(sent, *mac);
+            }
+        }
+        self.mqtt.changed();
+    }
+
+    /// Adds a request that got the wake's result to it, in the history and as the last wake
+    /// unless the MACs were woken again since.
+    pub(super) fn record_coalesced(&self, woken: &WakeResponse, context: &RequestContext) {
+        let request = CoalescedRequest {
+            at: Utc::now(),
+            requester: context.client,
+            principal: context.principal.clone(),
+            source: context.source.to_string(),
+        };
+        let macs = (woken.macs.iter())
+            .filter_map(|mac| discovery::parse_mac_addr(mac))
+            .collect::<Vec<_>>();
+        self.history.update(&macs, &woken.id, |wake| {
+            if wake.coalesced.len() < MAX_COALESCED {
+                wake.coalesced.push(request.clone());
+            }
+        });
+        let mut last_wakes = self.last_wakes.lock().unwrap_or_else(|e| e.into_inner());
+        for mac in macs {
+            if let Some(wake) = last_wakes.get_mut(&mac).filter(|wake| wake.id == woken.id) {
+                if wake.coalesced.len() < MAX_COALESCED {
+                    wake.coalesced.push(request.clone());
+                }
+            }
+        }
+        self.mqtt.changed();
+    }
+
+    /// Adds how the command went to the wake, in the history and as the last wake unless the
+    /// MACs were woken again since.
+    pub(super) fn record_hook(&self, macs: &[MacAddress], id: &str, run: HookRun) {
+        self.history
+            .update(macs, id, |wake| wake.hooks.push(run.clone()));
+        let mut last_wakes = self.last_wakes.lock().unwrap_or_else(|e| e.into_inner());
+        for mac in macs {
+            if let Some(wake) = last_wakes.get_mut(mac).filter(|wake| wake.id == id) {
+                wake.hooks.push(run.clone());
+            }
+        }
+        self.mqtt.changed();
+    }
+
+    /// When any of the MACs was last seen active.
+    fn last_seen(&self, macs: &[MacAddress]) -> Option<DateTime<Utc>> {
+        let last_seen = self.last_seen.lock().unwrap_or_else(|e| e.into_inner());
+        macs.iter()
+            .filter_map(|mac| last_seen.get(mac))
+            .max()
+            .copied()
+    }
+
+    /// When any of the MACs was last checked, if it was up then and that's no longer ago than
+    /// `online_max_age`. Older checks say nothing.
+    pub(super) fn already_online(&self, macs: &[MacAddress]) -> Option<DateTime<Utc>> {
+        let last_probes = self.last_probes.lock().unwrap_or_else(|e| e.into_inner());
+        let (online, at) = macs
+            .iter()
+            .filter_map(|mac| last_probes.get(mac))
+            .max_by_key(|(_, at)| *at)
+            .copied()?;
+        let age = (Utc::now() - at).to_std().unwrap_or_default();
+        (online && age < self.config.online_max_age).then_some(at)
+    }
+
+    /// Whether the most recently checked of the MACs was up then.
+    fn last_online(&self, macs: &[MacAddress]) -> Option<bool> {
+        let last_probes = self.last_probes.lock().unwrap_or_else(|e| e.into_inner());
+        macs.iter()
+            .filter_map(|mac| last_probes.get(mac))
+            .max_by_key(|(_, at)| *at)
+            .map(|(online, _)| *online)
+    }
+
+    /// The most recent wake of any of the MACs.
+    fn last_wake(&self, macs: &[MacAddress]) -> Option<LastWake> {
+        let last_wakes = self.last_wakes.lock().unwrap_or_else(|e| e.into_inner());
+        macs.iter()
+            .filter_map(|mac| last_wakes.get(mac))
+            .max_by_key(|wake| wake.at)
+            .cloned()
+    }
+}
+
+#[derive(Deserialize)]
+struct HostsQuery {
+    /// Only `site` for now.
+    group: Option<String>,
+    /// Only the hosts in it, see [`in_location`].
+    location: Option<String>,
+}
+
+async fn hosts(
+    State(state): State<Arc<AppState>>,
+    format: ResponseFormat,
+    Query(query): Query<HostsQuery>,
+) -> Response {
+    let grouped = match query.group.as_deref() {
+        None => false,
+        Some("site") => true,
+        Some(other) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("can't group by `{other}`, only by `site`"),
+            )
+                .into_response()
+        }
+    };
+    let mut hosts = known_hosts(&state).await;
+    if let Some(location) = &query.location {
+        hosts.retain(|host| in_location(host.location.as_deref(), location));
+    }
+    match format {
+        ResponseFormat::Json if grouped => Json(group_by_site(&state, hosts)).into_response(),
+        ResponseFormat::Json => Json(hosts).into_response(),
+        ResponseFormat::Html => hosts_page(&group_by_site(&state, hosts)).into_response(),
+    }
+}
+
+/// The MACs of a configured, discovered or pinned host, `None` if there's no such host.
+pub(super) fn find_macs(state: &AppState, name: &str) -> eyre::Result<Option<Vec<MacAddress>>> {
+    match state.static_host(name) {
+        Some(macs) => Ok(Some(macs)),
+        None => Ok(discovery::find_host(&state.discover_hosts()?, name)
+            .or_else(|| Some(vec![state.pinned_mac(name)?]))),
+    }
+}
+
+/// Like [`find_macs`], off the async runtime, with the response for a host that isn't found.
+pub(super) async fn lookup_host(
+    state: &Arc<AppState>,
+    name: &str,
+) -> Result<Vec<MacAddress>, Response> {
+    let macs = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let name = name.to_owned();
+        move || find_macs(&state, &name)
+    })
+    .await;
+    match macs {
+        Ok(Ok(Some(macs))) => Ok(macs),
+        Ok(Ok(None)) => Err((StatusCode::NOT_FOUND, "host not found").into_response()),
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to find host");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "error").into_response())
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn").into_response())
+        }
+    }
+}
+
+/// Checks whether a host is up at the address the neighbor table has for it.
+/// Without an address or any verify strategy that can be used, it's unknown.
+pub(super) fn check_host(
+    state: &AppState,
+    macs: &[MacAddress],
+) -> eyre::Result<(Option<IpAddr>, Option<Verified>)> {
+    check_host_with(state, macs, &state.config.verify)
+}
+
+/// Like [`check_host`], with these strategies instead of the configured ones.
+pub(super) fn check_host_with(
+    state: &AppState,
+    macs: &[MacAddress],
+    strategies: &[Strategy],
+) -> eyre::Result<(Option<IpAddr>, Option<Verified>)> {
+    let ip = discovery::find_ip(&state.discover()?, macs);
+    let verified = ip.and_then(|ip| {
+        verify::check(strategies, ip, state.config.verify_timeout)
+            .inspect_err(|e| tracing::warn!(?e, %ip, "failed to check host"))
+            .ok()
+    });
+    if let Some(verified) = verified {
+        let now = Utc::now();
+        let mut last_probes = state.last_probes.lock().unwrap_or_else(|e| e.into_inner());
+        let mut flipped = false;
+        for mac in macs {
+            let before = last_probes.insert(*mac, (verified.online, now));
+            flipped |= before.is_none_or(|(online, _)| online != verified.online);
+        }
+        drop(last_probes);
+        state.trim_hosts();
+        if flipped {
+            state.mqtt.changed();
+        }
+    }
+    Ok((ip, verified))
+}
+
+impl AppState {
+    /// The hosts like `GET /hosts` lists them.
+    pub async fn hosts(self: &Arc<Self>) -> Vec<Host> {
+        known_hosts(self).await
+    }
+
+    /// The MAC the discovered host is expected to have, if it's pinned. Configured hosts
+    /// aren't, their MACs are used anyway.
+    pub(super) fn pinned_mac(&self, name: &str) -> Option<MacAddress> {
+        if self.static_host(name).is_some() {
+            return None;
+        }
+        self.config
+            .pinned_macs
+            .iter()
+            .find(|(pinned, _)| same_host(pinned, name))
+            .map(|(_, mac)| *mac)
+    }
+
+    /// Finds the host like a wake does and checks whether it's up, `None` if there's no such
+    /// host. This blocks until the check is done.
+    pub fn host_status(&self, name: &str) -> eyre::Result<Option<HostStatus>> {
+        let Some(macs) = find_macs(self, name)? else {
+            return Ok(None);
+        };
+        let (ip, verified) = check_host(self, &macs)?;
+        Ok(Some(HostStatus {
+            host: name.to_owned(),
+            macs: macs.iter().map(MacAddress::to_string).collect(),
+            ip,
+            online: verified.map(|Verified { online, .. }| online),
+            strategy: verified.map(|Verified { strategy, .. }| strategy),
+        }))
+    }
+}
+
+async fn status(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Response {
+    let result = tokio::task::spawn_blocking(move || state.host_status(&name)).await;
+
+    match result {
+        Ok(Ok(Some(status))) => Json(status).into_response(),
+        Ok(Ok(None)) => (StatusCode::NOT_FOUND, "host not found").into_response(),
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to get host status");
+            (StatusCode::INTERNAL_SERVER_ERROR, "error").into_response()
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn").into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenQuery {
+    /// How often it was checked already.
+    #[serde(default)]
+    attempt: u32,
+}
+
+/// Redirects to the host's `service_url` once it's up, until then it's a page that checks again
+/// every [`OPEN_INTERVAL`].
+async fn open(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<OpenQuery>,
+) -> Response {
+    let Some(url) = state.registry.service_url(&name) else {
+        return (StatusCode::NOT_FOUND, "host has no service_url").into_response();
+    };
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let name = name.clone();
+        move || state.host_status(&name)
+    })
+    .await;
+    let online = match result {
+        Ok(Ok(status)) => status.and_then(|status| status.online) == Some(true),
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to get host status");
+            false
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            false
+        }
+    };
+    if online {
+        return Redirect::to(&url).into_response();
+    }
+
+    let link = format!(r#"<a href="{0}">{0}</a>"#, html_escape(&url));
+    let attempt = query.attempt + 1;
+    if attempt >= OPEN_ATTEMPTS {
+        let body = format!(
+            "<p>{} didn't come up. Open {link} anyway.</p>",
+            html_escape(&name)
+        );
+        return (StatusCode::GATEWAY_TIMEOUT, html_page("Not up", &body)).into_response();
+    }
+    let body = format!("<p>Opening {link} once {} is up.</p>", html_escape(&name));
+    let again = format!("open?attempt={attempt}");
+    refreshing_page("Waiting", &body, OPEN_INTERVAL, &again).into_response()
+}
+
+/// All configured hosts, followed by the discovered ones that aren't configured.
+/// If discovery fails, that's logged and only the configured hosts are returned.
+///
+/// A discovered MAC that isn't configured, but whose name is that of a configured host (or of
+/// another discovered entry), is another NIC of that host and listed with it.
+pub(super) async fn known_hosts(state: &Arc<AppState>) -> Vec<Host> {
+    let discovered = match tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || state.discover_hosts()
+    })
+    .await
+    {
+        Ok(Ok(discovered)) => discovered,
+        Ok(Err(e)) => {
+            tracing::warn!(?e, "failed to discover hosts");
+            Vec::new()
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            Vec::new()
+        }
+    };
+
+    // the state of each MAC, a reachable entry wins over another one for the same MAC
+    let mut states = HashMap::new();
+    for entry in &discovered {
+        if let Some(state) = entry.state {
+            let known = states.entry(entry.mac).or_insert(state);
+            if state == NeighborState::Reachable {
+                *known = state;
+            }
+        }
+    }
+
+    let mut hosts = state
+        .registry
+        .all()
+        .into_iter()
+        .map(|host| (host.name, host.macs, HostSource::Static))
+        .collect::<Vec<_>>();
+    for HostEntry {
+        name,
+        mac,
+        named_by,
+        ..
+    } in discovered
+    {
+        if state.registry.contains_mac(mac) {
+            continue;
+        }
+        let source = match named_by {
+            Some(Backend::Ssdp) => HostSource::Ssdp,
+            _ => HostSource::Discovered,
+        };
+        match hosts.iter_mut().find(|(host, _, _)| same_host(host, &name)) {
+            Some((_, macs, _)) if !macs.contains(&mac) => macs.push(mac),
+            Some(_) => {}
+            None => hosts.push((name, vec![mac], source)),
+        }
+    }
+    for (name, mac) in &state.config.pinned_macs {
+        if !hosts.iter().any(|(host, _, _)| same_host(name, host)) {
+            hosts.push((name.clone(), vec![*mac], HostSource::Pinned));
+        }
+    }
+
+    hosts
+        .into_iter()
+        .map(|(name, macs, source)| {
+            let site = state.site(Some(&name), &macs);
+            let pinned_mac = state.pinned_mac(&name);
+            Host {
+                interface: state.interfaces(Some(&name), site).into_iter().next(),
+                site: site.map(|site| site.name.clone()),
+                location: state.registry.location(&name),
+                mac: macs[0].to_string(),
+                state: neighbor_state(&states, &macs),
+                last_seen: state.last_seen(&macs),
+                online: state.last_online(&macs),
+                last_wake: state.last_wake(&macs),
+                stats: state.stats.host(&macs).0,
+                pinned_mac: pinned_mac.as_ref().map(MacAddress::to_string),
+                mac_mismatch: pinned_mac.is_some_and(|pinned| !macs.contains(&pinned)),
+                require_confirmation: state.registry.requires_confirmation(Some(&name), &macs),
+                macs: macs.iter().map(MacAddress::to_string).collect(),
+                name,
+                source,
+            }
+        })
+        .collect()
+}
+
+/// The state of the host in the neighbor table, which is reachable if any of its MACs is.
+fn neighbor_state(
+    states: &HashMap<MacAddress, NeighborState>,
+    macs: &[MacAddress],
+) -> Option<NeighborState> {
+    let found = macs
+        .iter()
+        .filter_map(|mac| states.get(mac).copied())
+        .collect::<Vec<_>>();
+    found
+        .iter()
+        .copied()
+        .find(|&state| state == NeighborState::Reachable)
+        .or(found.first().copied())
+}
+
+/// The hosts by site, this server's network first and then the sites like they're configured.
+/// Sites without any hosts are left out.
+fn group_by_site(state: &AppState, hosts: Vec<Host>) -> Vec<SiteHosts> {
+    let sites = std::iter::once(None).chain(state.config.sites.iter().map(Some));
+    let mut groups = sites
+        .map(|site| SiteHosts {
+            site: site.map(|site| site.name.clone()),
+            remote: site.is_some_and(|site| site.is_remote()),
+            hosts: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    for host in hosts {
+        if let Some(group) = groups.iter_mut().find(|group| group.site == host.site) {
+            group.hosts.push(host);
+        }
+    }
+    groups.retain(|group| !group.hosts.is_empty());
+    groups
+}
+
+/// Whether a host is in the location, ignoring case. The empty one has the hosts without any.
+pub(super) fn in_location(host: Option<&str>, location: &str) -> bool {
+    let location = location.trim();
+    match host {
+        Some(host) => host.eq_ignore_ascii_case(location),
+        None => location.is_empty(),
+    }
+}
+
+/// Whether a discovered name is the host's, either exactly or as the first label of a
+/// fully qualified name (`pc.fritz.box` for `pc`).
+fn same_host(host: &str, discovered: &str) -> bool {
+    let first_label = discovered.split('.').next().unwrap_or(discovered);
+    host.eq_ignore_ascii_case(discovered) || host.eq_ignore_ascii_case(first_label)
+}
@@ -0,0 +1,367 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use super::{hosts::known_hosts, AppState};
+use crate::{
+    api::v1::{Host, HostSource, LastWake, SiteHosts, WakeOutcome},
+    discovery::NeighborState,
+};
+
+pub(super) fn html_page(title: &str, body: &str) -> Html<String> {
+    page(title, "", body)
+}
+
+/// A page that loads `url` (relative to it) after `seconds`, without any script.
+pub(super) fn refreshing_page(title: &str, body: &str, seconds: u64, url: &str) -> Html<String> {
+    let head = format!(
+        r#"
+    <meta http-equiv="refresh" content="{seconds};url={}" />"#,
+        html_escape(url)
+    );
+    page(title, &head, body)
+}
+
+fn page(title: &str, head: &str, body: &str) -> Html<String> {
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1.0" />{head}
+    <title>{title} - Wake on LAN</title>
+  </head>
+  <body>
+    {body}
+    <a href="/">Back</a>
+  </body>
+</html>
+"#
+    ))
+}
+
+/// The page for a wake that was asked for by opening a link.
+pub(super) fn wake_page(result: Result<String, String>) -> Response {
+    match result {
+        Ok(summary) => {
+            let body = format!("<p>{}.</p>", html_escape(&summary));
+            (StatusCode::ACCEPTED, html_page("Sent", &body)).into_response()
+        }
+        Err(e) => {
+            let body = format!("<p>Failed to wake: {}</p>", html_escape(&e));
+            (StatusCode::INTERNAL_SERVER_ERROR, html_page("Error", &body)).into_response()
+        }
+    }
+}
+
+pub(super) fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Served when there's no page on disk.
+const BUILT_IN_INDEX: &str = include_str!("../../index.html");
+
+/// The page at `/`, read again whenever the file on disk changes.
+pub(super) struct IndexPage {
+    path: PathBuf,
+    /// The page as it was read, with when the file was last modified then.
+    cached: Mutex<Option<(SystemTime, String)>>,
+}
+
+impl IndexPage {
+    pub(super) fn new(path: PathBuf) -> Self {
+        IndexPage {
+            path,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// The page with the `{{...}}` still in it. A file that exists but can't be read is only
+    /// logged, the built-in page is served then.
+    fn template(&self) -> String {
+        let modified = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata.modified().ok(),
+            Err(e) if e.kind() == ErrorKind::NotFound => return BUILT_IN_INDEX.to_owned(),
+            Err(e) => {
+                tracing::warn!(?e, path = %self.path.display(), "failed to check index page, serving the built-in one");
+                return BUILT_IN_INDEX.to_owned();
+            }
+        };
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        if let (Some(modified), Some((cached_modified, page))) = (modified, &*cached) {
+            if modified == *cached_modified {
+                return page.clone();
+            }
+        }
+        match std::fs::read_to_string(&self.path) {
+            Ok(page) => {
+                *cached = modified.map(|modified| (modified, page.clone()));
+                page
+            }
+            Err(e) => {
+                tracing::warn!(?e, path = %self.path.display(), "failed to read index page, serving the built-in one");
+                BUILT_IN_INDEX.to_owned()
+            }
+        }
+    }
+}
+
+/// Like `last woken 5 minutes ago by alice (10.8.0.2)`, escaped.
+fn describe_last_wake(last_wake: Option<&LastWake>) -> String {
+    let Some(wake) = last_wake else {
+        return "never woken".to_owned();
+    };
+    let by = match (&wake.principal, wake.requester) {
+        (Some(principal), Some(requester)) => {
+            format!(" by {} ({requester})", html_escape(principal))
+        }
+        (Some(principal), None) => format!(" by {}", html_escape(principal)),
+        (None, Some(requester)) => format!(" by {requester}"),
+        (None, None) => String::new(),
+    };
+    let failed = match wake.outcome {
+        WakeOutcome::Sent => "",
+        WakeOutcome::Failed => " (failed)",
+        WakeOutcome::Queued => " (queued)",
+        WakeOutcome::Cancelled => " (cancelled)",
+        WakeOutcome::Suppressed => " (suppressed by quiet hours)",
+        WakeOutcome::Paused => " (skipped, its schedule is paused)",
+        WakeOutcome::StaleDiscovery => " (skipped: stale discovery)",
+    };
+    format!("last woken {}{by}{failed}", format_ago(wake.at))
+}
+
+/// Like `stale, seen 3 days ago`, empty if discovery knows nothing about it.
+fn describe_seen(host: &Host) -> String {
+    let state = host.state.map(|state| {
+        match state {
+            NeighborState::Reachable => "reachable",
+            NeighborState::Stale => "stale",
+            NeighborState::Delay => "delay",
+            NeighborState::Probe => "probe",
+            NeighborState::Failed => "failed",
+            NeighborState::Permanent => "permanent",
+            NeighborState::Noarp => "noarp",
+            NeighborState::Incomplete => "incomplete",
+        }
+        .to_owned()
+    });
+    let seen = host.last_seen.map(|at| format!("seen {}", format_ago(at)));
+    [state, seen]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `GET /hosts` for browsers, with a table for each site if there are any besides this
+/// server's network.
+pub(super) fn hosts_page(sites: &[SiteHosts]) -> Html<String> {
+    let body = match sites {
+        [] => hosts_table(&[]),
+        [SiteHosts {
+            site: None, hosts, ..
+        }] => hosts_table(hosts),
+        sites => sites
+            .iter()
+            .map(|group| {
+                let heading = match (&group.site, group.remote) {
+                    (None, _) => "Here".to_owned(),
+                    (Some(site), false) => html_escape(site),
+                    (Some(site), true) => format!("{} (remote)", html_escape(site)),
+                };
+                format!("<h2>{heading}</h2>{}", hosts_table(&group.hosts))
+            })
+            .collect(),
+    };
+    html_page("Hosts", &body)
+}
+
+fn hosts_table(hosts: &[Host]) -> String {
+    let rows = hosts
+        .iter()
+        .map(|host| {
+            let source = match host.source {
+                _ if host.mac_mismatch => "discovered, not with the pinned MAC",
+                HostSource::Static => "configured",
+                HostSource::Discovered => "discovered",
+                HostSource::Ssdp => "discovered (SSDP)",
+                HostSource::Pinned => "pinned",
+            };
+            let success = match host.stats.as_ref().and_then(|stats| stats.success_rate) {
+                Some(rate) => format!("{:.0}%", rate * 100.0),
+                None => String::new(),
+            };
+            format!(
+                "<tr><td><b>{}</b></td><td><code>{}</code></td><td>{source}</td><td>{}</td><td>{}</td><td>{}</td><td>{success}</td></tr>",
+                html_escape(&host.name),
+                host.macs.join(", "),
+                html_escape(host.interface.as_deref().unwrap_or_default()),
+                describe_seen(host),
+                describe_last_wake(host.last_wake.as_ref()),
+            )
+        })
+        .collect::<String>();
+    format!(
+        "<table><tr><th>Host</th><th>MAC</th><th>Source</th><th>Interface</th><th>Seen</th><th>Last wake</th><th>Came up</th></tr>{rows}</table>"
+    )
+}
+
+/// The page, with an `ETag` of what's on it. A browser or proxy that has that already gets a
+/// `304` instead, and `HEAD` only gets the headers.
+pub(super) async fn index(State(state): State<Arc<AppState>>, request: HeaderMap) -> Response {
+    let page = render_index(&state).await;
+    let mut hasher = DefaultHasher::new();
+    page.hash(&mut hasher);
+    let etag = format!(r#""{:016x}""#, hasher.finish());
+    let headers = [
+        (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+        // the hosts on it change, so it's always asked for again
+        (header::CACHE_CONTROL, "no-cache"),
+        (header::ETAG, &etag),
+    ];
+    let cached = (request.get(header::IF_NONE_MATCH))
+        .and_then(|tags| tags.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| same_etag(tag.trim(), &etag)));
+    if cached {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    (headers, page).into_response()
+}
+
+/// Compared like `If-None-Match` does, a weak tag is the same as the strong one.
+fn same_etag(tag: &str, etag: &str) -> bool {
+    tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag
+}
+
+async fn render_index(state: &Arc<AppState>) -> String {
+    let hosts = known_hosts(state).await;
+    let item = |host: &Host| {
+        let seen = match describe_seen(host) {
+            seen if seen.is_empty() => seen,
+            seen => format!("{seen} &mdash; "),
+        };
+        let wake = if state.config.read_only {
+            String::new()
+        } else {
+            // the box has to be ticked before the form can be sent
+            let confirm = if host.require_confirmation {
+                r#"<label><input type="checkbox" name="confirm" value="true" required /> confirm</label> "#
+            } else {
+                ""
+            };
+            format!(
+                r#" <form class="host-wake" method="post" action="/wake"><input type="hidden" name="host" value="{}" />{confirm}<button type="submit">Wake</button></form>"#,
+                html_escape(&host.name),
+            )
+        };
+        format!(
+            "<li><b>{}</b> <code>{}</code> &mdash; {seen}{}{wake}</li>",
+            html_escape(&host.name),
+            host.macs.join(", "),
+            describe_last_wake(host.last_wake.as_ref()),
+        )
+    };
+    // without any locations, they're just listed
+    let hosts = if hosts.iter().all(|host| host.location.is_none()) {
+        hosts.iter().map(item).collect::<String>()
+    } else {
+        by_location(&hosts)
+            .into_iter()
+            .map(|(location, hosts)| {
+                let heading = location.map_or_else(|| "Unsorted".to_owned(), html_escape);
+                let wake_all = if state.config.read_only {
+                    String::new()
+                } else {
+                    format!(
+                        r#"<form method="post" action="/wake/batch"><input type="hidden" name="location" value="{}" /><button type="submit">Wake all</button></form>"#,
+                        html_escape(location.unwrap_or_default()),
+                    )
+                };
+                format!(
+                    r#"<li class="location"><h2>{heading}</h2>{wake_all}<ul>{}</ul></li>"#,
+                    hosts.into_iter().map(item).collect::<String>(),
+                )
+            })
+            .collect()
+    };
+
+    let default_host = match &state.config.default_host {
+        _ if state.config.read_only => "read-only, nothing can be woken from here".to_owned(),
+        Some(host) => format!("wakes <b>{}</b> unless told otherwise", html_escape(host)),
+        None => "no default host is configured, enter a host name or mac address".to_owned(),
+    };
+
+    let template = tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || state.index_page.template()
+    })
+    .await
+    .unwrap_or_else(|e| {
+        tracing::error!(?e, "join error");
+        BUILT_IN_INDEX.to_owned()
+    });
+    template
+        .replace("{{default_host}}", &default_host)
+        .replace("{{hosts}}", &hosts)
+        .replace(
+            "{{disabled}}",
+            if state.config.read_only {
+                "disabled"
+            } else {
+                ""
+            },
+        )
+}
+
+/// The hosts of each location, in the order the locations first come up (ignoring case) and
+/// then those without one.
+fn by_location(hosts: &[Host]) -> Vec<(Option<&str>, Vec<&Host>)> {
+    let mut groups: Vec<(Option<&str>, Vec<&Host>)> = Vec::new();
+    for host in hosts {
+        let location = host.location.as_deref();
+        let same = |(group, _): &&mut (Option<&str>, Vec<&Host>)| match (group, location) {
+            (Some(group), Some(location)) => group.eq_ignore_ascii_case(location),
+            (group, location) => *group == location,
+        };
+        match groups.iter_mut().find(same) {
+            Some((_, hosts)) => hosts.push(host),
+            None => groups.push((location, vec![host])),
+        }
+    }
+    groups.sort_by_key(|(location, _)| location.is_none());
+    groups
+}
+
+pub(super) fn format_ago(at: DateTime<Utc>) -> String {
+    let seconds = (Utc::now() - at).num_seconds().max(0);
+    let (amount, unit) = match seconds {
+        0..60 => return "just now".to_owned(),
+        60..3600 => (seconds / 60, "minute"),
+        3600..86400 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{amount} {unit}{plural} ago")
+}
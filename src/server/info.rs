@@ -0,0 +1,39 @@
+//! `GET /api/v1/info`, for telling which build a server runs and how it's set up.
+
+use axum::{extract::State, routing::get, Json, Router};
+use chrono::DateTime;
+use std::sync::Arc;
+
+use super::AppState;
+use crate::api::v1::Info;
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/info", get(info))
+}
+
+/// What's built in, the crate's features and what the platform can do.
+const FEATURES: [(&str, bool); 5] = [
+    ("server", true),
+    ("socket", cfg!(feature = "socket")),
+    ("macaddr", cfg!(feature = "macaddr")),
+    ("telegram", true),
+    ("raw-l2", cfg!(target_os = "linux")),
+];
+
+async fn info(State(state): State<Arc<AppState>>) -> Json<Info> {
+    let built_at = option_env!("WOL_BUILT_AT")
+        .and_then(|built_at| built_at.parse().ok())
+        .and_then(|built_at| DateTime::from_timestamp(built_at, 0));
+    Json(Info {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        commit: option_env!("WOL_GIT_COMMIT").map(str::to_owned),
+        built_at,
+        features: (FEATURES.iter())
+            .filter(|(_, built_in)| *built_in)
+            .map(|(feature, _)| (*feature).to_owned())
+            .collect(),
+        discovery: state.config.discovery.clone(),
+        static_hosts: state.config.hosts.len(),
+        uptime: state.started.elapsed().as_secs_f64(),
+    })
+}
@@ -0,0 +1,174 @@
+//! Keeps what the server remembers in memory from growing forever, and says how much of it there
+//! is.
+
+use axum::{extract::State, routing::get, Json, Router};
+use std::{collections::HashMap, hash::Hash, sync::Arc, time::Duration};
+
+use super::AppState;
+use crate::api::v1::{MemoryStats, Size};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/stats", get(stats))
+}
+
+/// How often what expired is forgotten.
+const INTERVAL: Duration = Duration::from_secs(60);
+
+/// Forgets what expired and what's over the `[limits]`, forever. Most of what's over them is
+/// forgotten right when something new is remembered already, this catches the rest.
+pub async fn run_janitor(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(INTERVAL);
+    loop {
+        interval.tick().await;
+        let state = state.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || state.prune()).await {
+            tracing::error!(?e, "join error");
+        }
+    }
+}
+
+async fn stats(State(state): State<Arc<AppState>>) -> Json<MemoryStats> {
+    Json(state.sizes())
+}
+
+/// Forgets the oldest entries, by when `at` says they're from, until there are `limit` left.
+pub(super) fn trim<K: Hash + Eq + Clone, V, T: Ord>(
+    map: &mut HashMap<K, V>,
+    limit: usize,
+    at: impl Fn(&V) -> T,
+) {
+    let Some(over) = map.len().checked_sub(limit).filter(|over| *over > 0) else {
+        return;
+    };
+    let mut oldest = (map.iter())
+        .map(|(key, value)| (at(value), key.clone()))
+        .collect::<Vec<_>>();
+    oldest.sort_by(|a, b| a.0.cmp(&b.0));
+    for (_, key) in oldest.into_iter().take(over) {
+        map.remove(&key);
+    }
+}
+
+impl AppState {
+    /// Forgets what expired and the oldest of what's over the limits. This blocks while the
+    /// wake tokens are saved without the expired ones.
+    fn prune(&self) {
+        let limits = &self.config.limits;
+        trim(
+            &mut self.last_wakes.lock().unwrap_or_else(|e| e.into_inner()),
+            limits.history,
+            |wake| wake.at,
+        );
+        self.stats.prune();
+        self.not_found.prune(self.config.not_found_ttl);
+        self.jobs.prune();
+        self.trim_hosts();
+        self.relay_sources.prune();
+        self.sender.prune();
+        if let Some(netbios) = &self.netbios {
+            netbios.prune();
+        }
+        if let Err(e) = self.wake_tokens.prune() {
+            tracing::warn!(
+                ?e,
+                "failed to save the wake tokens without the expired ones"
+            );
+        }
+    }
+
+    /// Forgets the discovered hosts that were discovered longest ago, beyond `limits.hosts`.
+    pub(super) fn trim_hosts(&self) {
+        let limit = self.config.limits.hosts;
+        trim(
+            &mut self.known_ips.lock().unwrap_or_else(|e| e.into_inner()),
+            limit,
+            |(_, at)| *at,
+        );
+        trim(
+            &mut self.last_seen.lock().unwrap_or_else(|e| e.into_inner()),
+            limit,
+            |at| *at,
+        );
+        trim(
+            &mut self.last_probes.lock().unwrap_or_else(|e| e.into_inner()),
+            limit,
+            |(_, at)| *at,
+        );
+        trim(
+            &mut self
+                .discovered_sites
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+            limit,
+            |(_, at)| *at,
+        );
+    }
+
+    fn sizes(&self) -> MemoryStats {
+        let limits = &self.config.limits;
+        let size = |entries, limit| Size { entries, limit };
+        MemoryStats {
+            last_wakes: size(
+                self.last_wakes
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .len(),
+                Some(limits.history),
+            ),
+            history: size(self.history.len(), Some(limits.history)),
+            unverified: size(self.stats.unverified(), Some(limits.history)),
+            jobs: size(self.jobs.len(), Some(limits.jobs)),
+            known_ips: size(
+                self.known_ips
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .len(),
+                Some(limits.hosts),
+            ),
+            last_seen: size(
+                self.last_seen
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .len(),
+                Some(limits.hosts),
+            ),
+            last_probes: size(
+                self.last_probes
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .len(),
+                Some(limits.hosts),
+            ),
+            discovered_sites: size(
+                self.discovered_sites
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .len(),
+                Some(limits.hosts),
+            ),
+            relay_sources: size(self.relay_sources.len(), Some(limits.relay_sources)),
+            send_queue: size(
+                self.send_queue.len(),
+                self.config.send_queue.as_ref().map(|queue| queue.size),
+            ),
+            wake_tokens: size(self.wake_tokens.len(), None),
+            in_flight: size(
+                self.in_flight
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .len(),
+                None,
+            ),
+            probe_loops: size(
+                self.probe_loops
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .len(),
+                None,
+            ),
+            logs: size(self.logs.len(), Some(self.logs.capacity())),
+            not_found: (self.not_found).stats(self.config.not_found_ttl, limits.hosts),
+            events: self.events.stats(),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::{
+    audit::{AuditEvent, LinkEntry},
+    hosts::lookup_host,
+    html::wake_page,
+    wake::wake_by_name,
+    AppState, ErrorResponse, Principal, RequestContext, WakeSource,
+};
+use crate::sign::{sign_link, verify_link};
+
+pub(super) fn mint_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/wake/links", post(mint))
+}
+
+/// Only for requests that passed [`require_signature`].
+pub(super) fn link_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/wake", get(wake_link))
+}
+
+#[derive(Deserialize)]
+struct MintRequest {
+    host: String,
+    /// In seconds.
+    expires_in: u64,
+}
+
+#[derive(Serialize)]
+struct MintResponse {
+    /// Only if the request said which host it was sent to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    path: String,
+    host: String,
+    expires: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct LinkQuery {
+    host: String,
+    /// In seconds since the epoch.
+    exp: i64,
+    sig: String,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(ErrorResponse::new(message))).into_response()
+}
+
+async fn mint(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    headers: HeaderMap,
+    Json(request): Json<MintRequest>,
+) -> Response {
+    let Some(secret) = &state.config.url_secret else {
+        return error(StatusCode::BAD_REQUEST, "no url_secret is configured");
+    };
+
+    if let Err(response) = lookup_host(&state, &request.host).await {
+        return response;
+    }
+
+    let now = Utc::now();
+    let Some(expires) = i64::try_from(request.expires_in)
+        .ok()
+        .and_then(|secs| now.checked_add_signed(chrono::Duration::try_seconds(secs)?))
+    else {
+        return error(StatusCode::BAD_REQUEST, "expires_in is too large");
+    };
+    let exp = expires.timestamp();
+    let Some(sig) = sign_link(secret.as_str().as_bytes(), &request.host, exp) else {
+        return error(StatusCode::BAD_REQUEST, "the host can't have a line break");
+    };
+    let path = format!(
+        "/wake?host={}&exp={exp}&sig={sig}",
+        query_escape(&request.host)
+    );
+    // only the one who made it sees it, so it doesn't matter if the headers are made up
+    let url = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .map(|authority| {
+            let scheme = match headers
+                .get("x-forwarded-proto")
+                .and_then(|value| value.to_str().ok())
+            {
+                Some("https") => "https",
+                _ => "http",
+            };
+            format!("{scheme}://{authority}{path}")
+        });
+
+    tracing::info!(host = %request.host, %expires, client = ?context.client, principal = ?context.principal, "Minted wake link");
+    if let Some(audit) = &state.audit {
+        audit.log(AuditEvent::LinkMinted(LinkEntry {
+            at: now,
+            host: request.host.clone(),
+            expires,
+            requester: context.client,
+            principal: context.principal,
+        }));
+    }
+    (
+        StatusCode::CREATED,
+        Json(MintResponse {
+            url,
+            path,
+            host: request.host,
+            expires,
+        }),
+    )
+        .into_response()
+}
+
+/// Everything but the unreserved characters is percent-encoded.
+pub(crate) fn query_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("%{b:02X}"));
+        }
+    }
+    escaped
+}
+
+/// Only lets requests through with a signed link that hasn't expired, and takes `link` as the
+/// principal for them.
+pub(super) async fn require_signature(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let valid = state.config.url_secret.as_ref().is_some_and(|secret| {
+        Query::<LinkQuery>::try_from_uri(request.uri()).is_ok_and(|Query(link)| {
+            verify_link(
+                secret.as_str().as_bytes(),
+                &link.host,
+                link.exp,
+                &link.sig,
+                Utc::now().timestamp(),
+            )
+        })
+    });
+    if !valid {
+        tracing::warn!(path = %request.uri().path(), "rejected invalid or expired wake link");
+        return error(StatusCode::FORBIDDEN, "invalid or expired link");
+    }
+    request
+        .extensions_mut()
+        .insert(Principal("link".to_owned()));
+    next.run(request).await
+}
+
+async fn wake_link(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Query(link): Query<LinkQuery>,
+) -> Response {
+    let context = RequestContext {
+        source: WakeSource::Link,
+        ..context
+    };
+    wake_page(wake_by_name(&state, link.host, context).await)
+}
@@ -0,0 +1,94 @@
+//! Binding the HTTP listeners, with IPv6 wildcards taking IPv4 clients too where they can.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+};
+use tokio::net::{TcpListener, TcpSocket};
+
+/// How many connections may wait to be accepted.
+const BACKLOG: u32 = 1024;
+
+/// A listener of the HTTP server.
+pub struct HttpListener {
+    /// The address it's bound to, with the port the system picked for port 0.
+    pub addr: SocketAddr,
+    /// Whether IPv4 clients can connect to it too, which they do as `::ffff:a.b.c.d`.
+    pub dual_stack: bool,
+    pub listener: TcpListener,
+}
+
+/// Binds a listener for every address, failing with the address that couldn't be bound.
+/// Has to be called within the runtime.
+///
+/// An IPv6 wildcard like `[::]:8090` takes IPv4 clients too, unless IPv4 is bound on the same
+/// port as well (that would fail otherwise). Where the system can't do that, `0.0.0.0` is bound
+/// next to it.
+pub fn bind_http(addrs: &[SocketAddr]) -> Result<Vec<HttpListener>, (SocketAddr, io::Error)> {
+    let mut listeners = Vec::new();
+    for &addr in addrs {
+        let ipv4_on_port = addrs
+            .iter()
+            .any(|other| other.is_ipv4() && other.port() == addr.port());
+        let wants_ipv4 = addr.is_ipv6() && addr.ip().is_unspecified() && !ipv4_on_port;
+        let (listener, dual_stack) = bind(addr, wants_ipv4).map_err(|e| (addr, e))?;
+        let local_addr = listener.local_addr().map_err(|e| (addr, e))?;
+        if wants_ipv4 && !dual_stack {
+            // the same port, even if the system picked it
+            let ipv4 = SocketAddr::from((Ipv4Addr::UNSPECIFIED, local_addr.port()));
+            tracing::info!(%addr, %ipv4, "IPv6 listener can't take IPv4 clients, binding IPv4 too");
+            let (ipv4_listener, _) = bind(ipv4, false).map_err(|e| (ipv4, e))?;
+            listeners.push(HttpListener {
+                addr: ipv4,
+                dual_stack: false,
+                listener: ipv4_listener,
+            });
+        }
+        listeners.push(HttpListener {
+            addr: local_addr,
+            dual_stack,
+            listener,
+        });
+    }
+    Ok(listeners)
+}
+
+/// Returns whether IPv4 clients can connect too, which is only tried with `dual_stack`.
+fn bind(addr: SocketAddr, dual_stack: bool) -> io::Result<(TcpListener, bool)> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.set_reuseaddr(true)?;
+    let dual_stack = addr.is_ipv6() && set_v6_only(&socket, !dual_stack) && dual_stack;
+    socket.bind(addr)?;
+    Ok((socket.listen(BACKLOG)?, dual_stack))
+}
+
+/// Sets `IPV6_V6ONLY`, returning whether that worked.
+#[cfg(unix)]
+fn set_v6_only(socket: &TcpSocket, v6_only: bool) -> bool {
+    use std::os::fd::AsRawFd;
+
+    let value = libc::c_int::from(v6_only);
+    // SAFETY: the socket is open for as long as it's borrowed, and the value is a c_int
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_V6ONLY,
+            (&value as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        let e = io::Error::last_os_error();
+        tracing::debug!(?e, v6_only, "failed to set IPV6_V6ONLY");
+    }
+    result == 0
+}
+
+#[cfg(not(unix))]
+fn set_v6_only(_socket: &TcpSocket, v6_only: bool) -> bool {
+    v6_only
+}
@@ -0,0 +1,202 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::{self, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::layer::{Context, Layer};
+
+use super::AppState;
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/debug/logs", get(logs))
+}
+
+/// Fields with these in their name are never kept, they might hold credentials.
+const SENSITIVE: [&str; 6] = [
+    "token",
+    "password",
+    "secret",
+    "authorization",
+    "cookie",
+    "sig",
+];
+const DEFAULT_LIMIT: usize = 100;
+
+/// The most recent log events, for looking at them over HTTP.
+/// Keeps nothing until it's configured, which happens once the config is loaded.
+#[derive(Default)]
+pub struct LogBuffer {
+    capacity: AtomicUsize,
+    /// The least severe level that's kept, as from [`level_index`].
+    level: AtomicUsize,
+    events: Mutex<VecDeque<LogEvent>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct LogEvent {
+    at: DateTime<Utc>,
+    #[serde(serialize_with = "serialize_level")]
+    level: Level,
+    target: String,
+    message: String,
+    fields: BTreeMap<String, String>,
+}
+
+/// `ERROR` is 0, `TRACE` is 4.
+fn level_index(level: Level) -> usize {
+    match level {
+        Level::ERROR => 0,
+        Level::WARN => 1,
+        Level::INFO => 2,
+        Level::DEBUG => 3,
+        Level::TRACE => 4,
+    }
+}
+
+fn serialize_level<S: Serializer>(level: &Level, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&level.as_str().to_ascii_lowercase())
+}
+
+impl LogBuffer {
+    /// Keeps the last `capacity` events that are at least as severe as `level`.
+    pub fn configure(&self, capacity: usize, level: Level) {
+        self.level.store(level_index(level), Ordering::Relaxed);
+        self.capacity.store(capacity, Ordering::Relaxed);
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        while events.len() > capacity {
+            events.pop_front();
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub(super) fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// The layer that fills this buffer.
+    pub fn layer(self: &Arc<Self>) -> LogLayer {
+        LogLayer(self.clone())
+    }
+
+    fn push(&self, event: LogEvent) {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        while events.len() >= capacity.max(1) {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// The last `limit` events at least as severe as `level`, oldest first.
+    fn recent(&self, level: Level, limit: usize) -> Vec<LogEvent> {
+        let events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        let mut recent = events
+            .iter()
+            .rev()
+            .filter(|event| event.level <= level)
+            .take(limit)
+            .cloned()
+            .collect::<Vec<_>>();
+        recent.reverse();
+        recent
+    }
+}
+
+pub struct LogLayer(Arc<LogBuffer>);
+
+impl<S: Subscriber> Layer<S> for LogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let buffer = &self.0;
+        if buffer.capacity.load(Ordering::Relaxed) == 0
+            || level_index(*event.metadata().level()) > buffer.level.load(Ordering::Relaxed)
+        {
+            return;
+        }
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        buffer.push(LogEvent {
+            at: Utc::now(),
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_owned(),
+            message: visitor.message,
+            fields: visitor.fields,
+        });
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: BTreeMap<String, String>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let mut formatted = String::new();
+        let _ = write!(formatted, "{value:?}");
+        self.record(field, formatted);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.to_owned());
+    }
+}
+
+impl FieldVisitor {
+    fn record(&mut self, field: &Field, value: String) {
+        let name = field.name();
+        if name == "message" {
+            self.message = value;
+        } else if !SENSITIVE
+            .iter()
+            .any(|sensitive| name.to_ascii_lowercase().contains(sensitive))
+        {
+            self.fields.insert(name.to_owned(), value);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LogsQuery {
+    /// The least severe level to return, everything that's kept by default.
+    #[serde(default, deserialize_with = "deserialize_level")]
+    level: Option<Level>,
+    limit: Option<usize>,
+}
+
+fn deserialize_level<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Level>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value
+        .parse()
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("invalid level `{value}`")))
+}
+
+async fn logs(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<LogsQuery>,
+) -> Json<Vec<LogEvent>> {
+    Json(state.logs.recent(
+        query.level.unwrap_or(Level::TRACE),
+        query.limit.unwrap_or(DEFAULT_LIMIT),
+    ))
+}
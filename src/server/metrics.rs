@@ -0,0 +1,161 @@
+//! `GET /metrics`, in the Prometheus text format.
+
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+use super::AppState;
+use crate::api::v1::WakeOutcome;
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/metrics", get(metrics))
+}
+
+/// The upper bounds of the time to online buckets in seconds, from a quick boot to a slow one.
+const BUCKETS: [f64; 10] = [
+    10.0, 20.0, 30.0, 45.0, 60.0, 90.0, 120.0, 180.0, 240.0, 300.0,
+];
+
+/// How the wakes that were waited for went, by the host they were for, and what started the wakes.
+/// Only kept in memory, unlike the stats.
+#[derive(Default)]
+pub(super) struct Metrics {
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    time_to_online: BTreeMap<String, Histogram>,
+    verify_timeouts: BTreeMap<String, u64>,
+    /// By the source and the outcome, one for each MAC like in the audit log.
+    wake_attempts: BTreeMap<(String, &'static str), u64>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Not cumulative, the last one is for everything slower than the last bucket.
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Metrics {
+    /// The host answered this many seconds after it was woken.
+    pub(super) fn online(&self, host: &str, seconds: f64) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let histogram = entries.time_to_online.entry(host.to_owned()).or_default();
+        let bucket = BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(BUCKETS.len());
+        histogram.counts[bucket] += 1;
+        histogram.sum += seconds;
+    }
+
+    /// Waiting for the woken host to answer was given up on.
+    pub(super) fn timed_out(&self, host: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        *entries.verify_timeouts.entry(host.to_owned()).or_default() += 1;
+    }
+
+    /// A MAC was woken (or not) for a wake started by the source.
+    pub(super) fn attempted(&self, source: &str, outcome: WakeOutcome) {
+        let outcome = match outcome {
+            WakeOutcome::Sent => "sent",
+            WakeOutcome::Failed => "failed",
+            WakeOutcome::Queued => "queued",
+            WakeOutcome::Cancelled => "cancelled",
+            WakeOutcome::Suppressed => "suppressed",
+            WakeOutcome::Paused => "paused",
+            WakeOutcome::StaleDiscovery => "stale_discovery",
+        };
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        *entries
+            .wake_attempts
+            .entry((source.to_owned(), outcome))
+            .or_default() += 1;
+    }
+
+    /// With how long ago discovery last worked, in seconds.
+    fn render(&self, discovery_age: u64) -> String {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+        out.push_str(
+            "# HELP wol_time_to_online_seconds How long woken hosts took to answer.\n\
+             # TYPE wol_time_to_online_seconds histogram\n",
+        );
+        for (host, histogram) in &entries.time_to_online {
+            let host = escape(host);
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.counts) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "wol_time_to_online_seconds_bucket{{host=\"{host}\",le=\"{bound}\"}} {cumulative}"
+                );
+            }
+            let total = histogram.counts.iter().sum::<u64>();
+            let _ = writeln!(
+                out,
+                "wol_time_to_online_seconds_bucket{{host=\"{host}\",le=\"+Inf\"}} {total}\n\
+                 wol_time_to_online_seconds_sum{{host=\"{host}\"}} {}\n\
+                 wol_time_to_online_seconds_count{{host=\"{host}\"}} {total}",
+                histogram.sum
+            );
+        }
+        out.push_str(
+            "# HELP wol_verify_timeouts_total Woken hosts that didn't answer in time.\n\
+             # TYPE wol_verify_timeouts_total counter\n",
+        );
+        for (host, timeouts) in &entries.verify_timeouts {
+            let _ = writeln!(
+                out,
+                "wol_verify_timeouts_total{{host=\"{}\"}} {timeouts}",
+                escape(host)
+            );
+        }
+        out.push_str(
+            "# HELP wol_wake_attempts_total Wakes of a MAC, by what started them and how they went.\n\
+             # TYPE wol_wake_attempts_total counter\n",
+        );
+        for ((source, outcome), attempts) in &entries.wake_attempts {
+            let _ = writeln!(
+                out,
+                "wol_wake_attempts_total{{source=\"{}\",outcome=\"{outcome}\"}} {attempts}",
+                escape(source)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP wol_discovery_age_seconds How long ago discovery last worked, or the server \
+             started if it didn't yet.\n\
+             # TYPE wol_discovery_age_seconds gauge\n\
+             wol_discovery_age_seconds {discovery_age}"
+        );
+        out
+    }
+}
+
+/// Label values have backslashes, quotes and newlines escaped.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+async fn metrics(State(state): State<Arc<AppState>>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(state.freshness.age()),
+    )
+        .into_response()
+}
@@ -0,0 +1,855 @@
+mod assets;
+mod audit;
+mod callback;
+mod coalesce;
+mod events;
+mod format;
+mod freshness;
+mod health;
+mod history;
+mod hooks;
+mod hosts;
+mod html;
+mod info;
+mod janitor;
+pub(crate) mod links;
+mod listen;
+mod logs;
+mod metrics;
+mod mqtt;
+mod names;
+mod neighbors;
+mod network;
+mod not_found;
+mod proxy;
+mod queue;
+mod registry;
+mod relay;
+mod resolve;
+mod schedules;
+mod sender;
+mod sequences;
+mod settings;
+mod sites;
+mod state_file;
+mod stats;
+mod strategies;
+mod telegram;
+mod tokens;
+mod wait;
+mod wake;
+mod ws;
+
+pub use hooks::run_hooks;
+pub use janitor::run_janitor;
+pub use listen::{bind_http, HttpListener};
+pub use logs::{LogBuffer, LogLayer};
+pub use mqtt::Mqtt;
+pub use proxy::Proxy;
+pub use queue::run_send_queue;
+pub use relay::Relay;
+pub use schedules::run_scheduler;
+pub use sender::PacketSender;
+pub use telegram::Telegram;
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::request::Parts,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{
+    api::v1::{ErrorResponse, LastWake, SelfTest},
+    config::{Config, SendConfirmationConfig, Site},
+    discovery::{resolve_names, Composite, HostDiscovery, HostEntry, Snmp},
+    secret::Secret,
+    sign::constant_time_eq,
+    MacAddress,
+};
+use audit::AuditLog;
+use html::IndexPage;
+use names::NetbiosNames;
+use registry::Registry;
+use schedules::Schedules;
+use sender::{Sender, UdpSender};
+use sequences::Jobs;
+use stats::Stats;
+use tokens::WakeTokens;
+
+pub const SEND_BIND_ADDR: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
+
+pub struct AppState {
+    config: Config,
+    registry: Registry,
+    discovery: Box<dyn HostDiscovery>,
+    /// The sites with backends of their own, by their name.
+    site_discovery: Vec<(String, Box<dyn HostDiscovery>)>,
+    /// The site that found each MAC discovered in a site, and when.
+    discovered_sites: Mutex<HashMap<MacAddress, (String, Instant)>>,
+    freshness: freshness::Freshness,
+    schedules: Schedules,
+    wake_tokens: WakeTokens,
+    /// The wake sequences that were started.
+    jobs: Jobs,
+    sender: Sender,
+    /// How the configured self-test at startup went.
+    self_test: Option<SelfTest>,
+    /// Where magic packets are sent to, every packet goes to all of them.
+    destinations: Vec<SocketAddr>,
+    last_wakes: Mutex<HashMap<MacAddress, LastWake>>,
+    history: history::History,
+    /// When discovery last saw each MAC active, see [`HostEntry::is_seen`].
+    last_seen: Mutex<HashMap<MacAddress, DateTime<Utc>>>,
+    /// Whether each MAC was up when it was last checked, and when that was.
+    last_probes: Mutex<HashMap<MacAddress, (bool, DateTime<Utc>)>>,
+    /// How often sending failed on each interface and moved on to the next one.
+    failovers: Mutex<HashMap<String, u64>>,
+    stats: Stats,
+    metrics: metrics::Metrics,
+    netbios: Option<NetbiosNames>,
+    /// The IP each discovered name last had, for finding it again once it's not discovered.
+    known_ips: Mutex<HashMap<String, (IpAddr, Instant)>>,
+    probe_loops: wait::ProbeLoops,
+    in_flight: coalesce::InFlight,
+    not_found: not_found::NotFound,
+    send_queue: queue::SendQueue,
+    /// The sources of the packets the relay counted in the last window.
+    relay_sources: relay::RateLimiter,
+    hooks: hooks::Hooks,
+    /// For the callbacks and the remote sites.
+    http: reqwest::Client,
+    /// What `GET /api/v1/events` streams.
+    events: events::Events,
+    mqtt: mqtt::Changes,
+    /// Set once the server shuts down, every WebSocket watches it.
+    shutdown: tokio::sync::watch::Sender<bool>,
+    started: Instant,
+    audit: Option<AuditLog>,
+    logs: Arc<LogBuffer>,
+    index_page: IndexPage,
+}
+
+impl AppState {
+    /// Fails if the registry, its stats, the schedules or the wake tokens file can't be loaded.
+    pub fn new(config: Config) -> eyre::Result<Self> {
+        let registry = Registry::load(&config)?;
+        for host in registry.all() {
+            if let Some(site) = host
+                .site
+                .filter(|site| !config.sites.iter().any(|known| known.name == *site))
+            {
+                tracing::warn!(host = %host.name, %site, "unknown site, treating the host as local");
+            }
+        }
+        if config.default_host.is_none() && registry.all().is_empty() {
+            tracing::warn!(
+                "no default_host and no hosts configured, wakes will have to say what to wake"
+            );
+        }
+        let mut discovery = Composite::new(&config.discovery);
+        if let Some(snmp) = &config.snmp {
+            discovery.0.push(Box::new(Snmp::new(snmp)));
+        }
+        Ok(Self {
+            registry,
+            discovery: Box::new(discovery),
+            site_discovery: config
+                .sites
+                .iter()
+                .filter(|site| !site.discovery.is_empty())
+                .map(|site| {
+                    let discovery: Box<dyn HostDiscovery> =
+                        Box::new(Composite::new(&site.discovery));
+                    (site.name.clone(), discovery)
+                })
+                .collect(),
+            discovered_sites: Mutex::new(HashMap::new()),
+            freshness: freshness::Freshness::default(),
+            schedules: Schedules::load(&config)?,
+            wake_tokens: WakeTokens::load(&config)?,
+            jobs: Jobs::new(config.limits.jobs),
+            sender: Sender::new(
+                Box::new(UdpSender::new(SEND_BIND_ADDR)),
+                config
+                    .send_confirmation
+                    .as_ref()
+                    .map(SendConfirmationConfig::wait),
+            ),
+            self_test: config.self_test.as_ref().map(|self_test| {
+                let destination = self_test.destination.unwrap_or(config.broadcast);
+                sender::self_test(SEND_BIND_ADDR, destination)
+            }),
+            destinations: vec![config.broadcast],
+            last_wakes: Mutex::new(HashMap::new()),
+            history: history::History::default(),
+            last_seen: Mutex::new(HashMap::new()),
+            last_probes: Mutex::new(HashMap::new()),
+            failovers: Mutex::new(HashMap::new()),
+            stats: Stats::load(&config)?,
+            metrics: metrics::Metrics::default(),
+            netbios: config.netbios.as_ref().map(NetbiosNames::new),
+            known_ips: Mutex::new(HashMap::new()),
+            probe_loops: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+            not_found: not_found::NotFound::default(),
+            send_queue: queue::SendQueue::default(),
+            relay_sources: relay::RateLimiter::default(),
+            hooks: hooks::Hooks::default(),
+            http: reqwest::Client::new(),
+            events: events::Events::new(config.limits.events),
+            mqtt: mqtt::Changes::default(),
+            shutdown: tokio::sync::watch::Sender::new(false),
+            started: Instant::now(),
+            audit: config.audit.as_ref().map(AuditLog::start),
+            logs: Arc::default(),
+            index_page: IndexPage::new(config.index_page.clone()),
+            config,
+        })
+    }
+
+    /// Serves `/debug/logs` from these logs, configuring them from the config.
+    pub fn with_logs(mut self, logs: Arc<LogBuffer>) -> Self {
+        logs.configure(self.config.log_buffer, self.config.log_buffer_level);
+        self.logs = logs;
+        self
+    }
+
+    /// Sends magic packets with this instead of UDP sockets.
+    pub fn with_sender(mut self, sender: impl PacketSender + 'static) -> Self {
+        let confirm = self.config.send_confirmation.as_ref();
+        self.sender = Sender::new(Box::new(sender), confirm.map(SendConfirmationConfig::wait));
+        self
+    }
+
+    /// Discovers hosts with this instead of the configured backends.
+    pub fn with_discovery(mut self, discovery: impl HostDiscovery + 'static) -> Self {
+        self.discovery = Box::new(discovery);
+        self
+    }
+
+    /// What the configured backends and those of the sites find, remembering which site found
+    /// (or has the VLAN of) which MAC and when each was last seen, and when discovery last worked.
+    /// Like for the backends themselves, it only fails if all of them do.
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        let mut result = self.discovery.discover();
+        for (site, discovery) in &self.site_discovery {
+            let entries = match discovery.discover() {
+                Ok(entries) => entries,
+                Err(e) => {
+                    tracing::debug!(?e, %site, "site discovery failed");
+                    continue;
+                }
+            };
+            let mut discovered_sites = self
+                .discovered_sites
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            for entry in &entries {
+                discovered_sites.insert(entry.mac, (site.clone(), Instant::now()));
+            }
+            drop(discovered_sites);
+            match &mut result {
+                Ok(hosts) => {
+                    for entry in entries {
+                        if !hosts
+                            .iter()
+                            .any(|host| host.mac == entry.mac && host.ip == entry.ip)
+                        {
+                            hosts.push(entry);
+                        }
+                    }
+                }
+                Err(_) => result = Ok(entries),
+            }
+        }
+        if let Ok(hosts) = &result {
+            let mut discovered_sites = self
+                .discovered_sites
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            for entry in hosts {
+                let Some(vlan) = entry.vlan else {
+                    continue;
+                };
+                if let Some(site) = self
+                    .config
+                    .sites
+                    .iter()
+                    .find(|site| site.vlan == Some(vlan))
+                {
+                    discovered_sites.insert(entry.mac, (site.name.clone(), Instant::now()));
+                }
+            }
+            drop(discovered_sites);
+            let now = Utc::now();
+            let mut last_seen = self.last_seen.lock().unwrap_or_else(|e| e.into_inner());
+            for entry in hosts.iter().filter(|entry| entry.is_seen()) {
+                last_seen.insert(entry.mac, now);
+            }
+            drop(last_seen);
+            self.trim_hosts();
+            self.not_found.discovered(hosts);
+        }
+        self.freshness.discovered(&result);
+        result
+    }
+
+    /// The discovered hosts, with their names resolved.
+    fn discover_hosts(&self) -> eyre::Result<Vec<HostEntry>> {
+        Ok(self.resolve_names(self.discover()?))
+    }
+
+    /// Names hosts by reverse DNS, and by NetBIOS if that's enabled and DNS doesn't know them.
+    fn resolve_names(&self, hosts: Vec<HostEntry>) -> Vec<HostEntry> {
+        let hosts = resolve_names(hosts);
+        match &self.netbios {
+            Some(netbios) => netbios.resolve(hosts),
+            None => hosts,
+        }
+    }
+
+    /// The site of a host, configured for it or the one it was discovered in.
+    /// `None` for hosts in this server's network.
+    fn site(&self, host: Option<&str>, macs: &[MacAddress]) -> Option<&Site> {
+        let name = match host.and_then(|host| self.registry.site(host)) {
+            Some(name) => name,
+            None => {
+                let discovered_sites = self
+                    .discovered_sites
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                macs.iter()
+                    .find_map(|mac| discovered_sites.get(mac))?
+                    .0
+                    .clone()
+            }
+        };
+        self.config.sites.iter().find(|site| site.name == name)
+    }
+
+    fn static_host(&self, name: &str) -> Option<Vec<MacAddress>> {
+        self.registry.get(name)
+    }
+
+    /// The interfaces packets for the host are sent on, in the order they're tried: its own, its
+    /// site's or the configured one, otherwise those of `failover`. Empty if sending isn't
+    /// restricted to any, and for hosts in remote sites, which get their packets from there.
+    fn interfaces(&self, host: Option<&str>, site: Option<&Site>) -> Vec<String> {
+        if site.is_some_and(|site| site.is_remote()) {
+            return Vec::new();
+        }
+        let interface = host
+            .and_then(|host| self.registry.interface(host))
+            .or_else(|| site?.interface.clone())
+            .or_else(|| self.config.interface.clone());
+        match interface {
+            Some(interface) => vec![interface],
+            None => self.config.failover.clone(),
+        }
+    }
+
+    /// The local address packets for the host are sent from: its own or its site's. `None` if
+    /// they're sent from any, and for hosts in remote sites.
+    fn source(&self, host: Option<&str>, site: Option<&Site>) -> Option<IpAddr> {
+        if site.is_some_and(|site| site.is_remote()) {
+            return None;
+        }
+        host.and_then(|host| self.registry.source(host))
+            .or_else(|| site?.source)
+    }
+
+    /// Counts that sending on the interface failed, and the next one was tried.
+    fn count_failover(&self, interface: &str) {
+        let mut failovers = self.failovers.lock().unwrap_or_else(|e| e.into_inner());
+        *failovers.entry(interface.to_owned()).or_default() += 1;
+    }
+
+    /// Where packets for hosts in the site are sent to.
+    fn destinations(&self, site: Option<&Site>) -> Vec<SocketAddr> {
+        match site.and_then(|site| site.broadcast) {
+            Some(broadcast) => vec![broadcast],
+            None => self.destinations.clone(),
+        }
+    }
+}
+
+pub fn router(state: Arc<AppState>) -> Router {
+    let api = api_routes(&state);
+    let pages = Router::new()
+        .route("/", get(html::index))
+        .merge(assets::routes())
+        .merge(health::routes())
+        .merge(metrics::routes())
+        .route_layer(middleware::from_fn_with_state(state.clone(), allow_read));
+    let public = links::link_routes()
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            links::require_signature,
+        ))
+        .merge(tokens::redeem_routes())
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            allow_mutating,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            refuse_read_only,
+        ));
+
+    Router::new()
+        .merge(pages)
+        .merge(authorized(&state, logs::routes()))
+        .merge(public)
+        .merge(api.clone())
+        .nest(
+            "/api/v1",
+            api.merge(v1_routes(&state))
+                .layer(middleware::from_fn(v1_errors)),
+        )
+        .with_state(state)
+}
+
+/// Everything that's served under `/api/v1/` too.
+fn api_routes(state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    let mutating = Router::new()
+        .merge(wake::routes())
+        .merge(registry::write_routes())
+        .merge(schedules::write_routes())
+        .merge(links::mint_routes())
+        .merge(tokens::write_routes())
+        .merge(sequences::write_routes())
+        .merge(ws::routes());
+    let read = Router::new()
+        .merge(hosts::routes())
+        .merge(registry::read_routes())
+        .merge(resolve::routes())
+        .merge(schedules::read_routes())
+        .merge(wait::routes())
+        .merge(sequences::read_routes())
+        .merge(stats::routes())
+        .route_layer(middleware::from_fn_with_state(state.clone(), allow_read));
+
+    Router::new()
+        .merge(read)
+        .merge(
+            authorized(state, mutating).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                refuse_read_only,
+            )),
+        )
+}
+
+/// Everything that's only served under `/api/v1/`.
+fn v1_routes(state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    let read = Router::new()
+        .merge(info::routes())
+        .merge(history::routes())
+        .merge(queue::read_routes())
+        .merge(janitor::routes())
+        .merge(events::routes())
+        .route_layer(middleware::from_fn_with_state(state.clone(), allow_read));
+
+    Router::new()
+        .merge(read)
+        .merge(authorized(state, queue::write_routes()).route_layer(
+            middleware::from_fn_with_state(state.clone(), refuse_read_only),
+        ))
+        .merge(authorized(state, settings::routes()))
+        .merge(authorized(state, network::routes()))
+        .merge(authorized(state, neighbors::routes()))
+}
+
+/// Makes the routes need the token and one of the `allow_from` addresses.
+fn authorized(state: &Arc<AppState>, routes: Router<Arc<AppState>>) -> Router<Arc<AppState>> {
+    routes
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            allow_mutating,
+        ))
+}
+
+/// Error messages longer than this are cut off when they're turned into JSON.
+const MAX_ERROR_MESSAGE: usize = 16 * 1024;
+
+/// Under `/api/v1/`, errors are always an [`ErrorResponse`], where the unversioned routes
+/// sometimes answer with just text. Pages for submitted forms are left alone.
+async fn v1_errors(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !(status.is_client_error() || status.is_server_error())
+        || content_type.starts_with("application/json")
+        || content_type.starts_with("text/html")
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let error = match axum::body::to_bytes(body, MAX_ERROR_MESSAGE).await {
+        Ok(bytes) if !bytes.is_empty() => String::from_utf8_lossy(&bytes).into_owned(),
+        _ => status.canonical_reason().unwrap_or("error").to_lowercase(),
+    };
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let mut response = (status, Json(ErrorResponse::new(error))).into_response();
+    response.headers_mut().extend(parts.headers);
+    response
+}
+
+/// The IP address of the client, if the server was started with connect info.
+/// Behind a trusted proxy, that's the address it forwarded the request for.
+struct ClientIp(Option<IpAddr>);
+
+impl FromRequestParts<Arc<AppState>> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(ClientIp(peer.map(|peer| {
+            forwarded_client(peer, &parts.headers, &state.config.trusted_proxies)
+        })))
+    }
+}
+
+/// Who a request came from, passed down into wakes so the logs and the wake history can say
+/// who asked for it, and why.
+#[derive(Debug, Clone, Default)]
+pub(super) struct RequestContext {
+    pub(super) client: Option<IpAddr>,
+    /// Who authenticated, if that is required.
+    pub(super) principal: Option<String>,
+    pub(super) source: WakeSource,
+}
+
+impl RequestContext {
+    /// Nobody asked for it right then, like for a schedule, a relayed packet or a proxied
+    /// request. Quiet hours suppress these.
+    pub(super) fn automatic(&self) -> bool {
+        !matches!(
+            self.source,
+            WakeSource::Ui
+                | WakeSource::Api
+                | WakeSource::WebSocket
+                | WakeSource::Telegram
+                | WakeSource::Tui
+                | WakeSource::Cli
+        )
+    }
+}
+
+/// For wakes that aren't started by a request, like those of schedules or Telegram.
+impl From<WakeSource> for RequestContext {
+    fn from(source: WakeSource) -> Self {
+        RequestContext {
+            client: None,
+            principal: None,
+            source,
+        }
+    }
+}
+
+/// What started a wake, set by the entry point and kept with the wake in the history, the audit
+/// log and the metrics.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) enum WakeSource {
+    /// The forms of the page.
+    Ui,
+    /// Everything else that sends requests, which is also what requests are until their handler
+    /// says otherwise.
+    #[default]
+    Api,
+    /// `GET /ws`.
+    WebSocket,
+    /// By the id of the schedule.
+    Schedule(String),
+    /// By the name of the sequence.
+    Sequence(String),
+    Telegram,
+    /// A magic packet that came in on the relay port.
+    Relay,
+    /// A request for the proxied service of a sleeping host.
+    Proxy,
+    /// A signed wake link.
+    Link,
+    /// A single use wake token.
+    Token,
+    /// `wakeonlan tui`.
+    Tui,
+    /// `wakeonlan wake <host>`.
+    Cli,
+}
+
+impl fmt::Display for WakeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WakeSource::Ui => f.write_str("ui"),
+            WakeSource::Api => f.write_str("api"),
+            WakeSource::WebSocket => f.write_str("ws"),
+            WakeSource::Schedule(id) => write!(f, "schedule:{id}"),
+            WakeSource::Sequence(name) => write!(f, "sequence:{name}"),
+            WakeSource::Telegram => f.write_str("telegram"),
+            WakeSource::Relay => f.write_str("relay"),
+            WakeSource::Proxy => f.write_str("proxy"),
+            WakeSource::Link => f.write_str("link"),
+            WakeSource::Token => f.write_str("token"),
+            WakeSource::Tui => f.write_str("tui"),
+            WakeSource::Cli => f.write_str("cli"),
+        }
+    }
+}
+
+/// Put into the request extensions by [`require_token`].
+#[derive(Clone)]
+struct Principal(String);
+
+impl FromRequestParts<Arc<AppState>> for RequestContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let ClientIp(client) = ClientIp::from_request_parts(parts, state).await?;
+        Ok(RequestContext {
+            client,
+            principal: parts
+                .extensions
+                .get::<Principal>()
+                .map(|Principal(name)| name.clone()),
+            source: WakeSource::Api,
+        })
+    }
+}
+
+/// Walks `X-Forwarded-For` from the right for as long as the hops are trusted proxies,
+/// the first one that isn't is the client. Anything a client sent itself is never looked at.
+fn forwarded_client(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|net| net.contains(&ip));
+
+    let mut client = peer.to_canonical();
+    let hops = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+    for hop in hops.into_iter().rev() {
+        if !is_trusted(client) {
+            break;
+        }
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => client = ip.to_canonical(),
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+async fn allow_mutating(
+    State(state): State<Arc<AppState>>,
+    ClientIp(client): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_allowed(&state.config.allow_from, client, request, next).await
+}
+
+async fn allow_read(
+    State(state): State<Arc<AppState>>,
+    ClientIp(client): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_allowed(&state.config.read_allow_from, client, request, next).await
+}
+
+pub(super) const READ_ONLY: &str = "server is in read-only mode";
+
+/// The path of a request like it's logged, without the wake token in it.
+fn logged_path(uri: &Uri) -> String {
+    match uri.path().split_once("/wake-token/") {
+        Some((before, token)) => format!("{before}/wake-token/{}", Secret::new(token)),
+        None => uri.path().to_owned(),
+    }
+}
+
+/// With `read_only`, refuses the request before anything else looks at it, even without the
+/// token. Everyone gets the same JSON error, and every attempt is logged.
+async fn refuse_read_only(
+    State(state): State<Arc<AppState>>,
+    ClientIp(client): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.config.read_only {
+        return next.run(request).await;
+    }
+    tracing::warn!(?client, method = %request.method(), path = %logged_path(request.uri()), "refused mutation, the server is in read-only mode");
+    (StatusCode::FORBIDDEN, Json(ErrorResponse::new(READ_ONLY))).into_response()
+}
+
+/// Lets everyone through if `allowed` is empty, otherwise only clients in one of the networks.
+/// Without a known client address, nothing can be checked and the request is denied.
+async fn require_allowed(
+    allowed: &[IpNet],
+    client: Option<IpAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if allowed.is_empty() || client.is_some_and(|ip| allowed.iter().any(|net| net.contains(&ip))) {
+        return next.run(request).await;
+    }
+
+    match client {
+        Some(client) => {
+            tracing::warn!(%client, path = %logged_path(request.uri()), "rejected request from address that isn't allowed")
+        }
+        None => {
+            tracing::warn!(path = %logged_path(request.uri()), "rejected request without a known client address")
+        }
+    }
+    (
+        StatusCode::FORBIDDEN,
+        Json(ErrorResponse::new(
+            "requests from this address are not allowed",
+        )),
+    )
+        .into_response()
+}
+
+/// Only lets requests through that carry the configured token, either as a bearer token
+/// or as the password of basic auth (which browsers will prompt for).
+/// The basic auth user name is taken as the principal, it's just `token` otherwise.
+///
+/// With `proxy_auth`, a request straight from a trusted proxy is let through as the user in the
+/// header instead (if that user is allowed). Anyone else needs the token, and there's no way in
+/// without one.
+async fn require_token(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(proxy_auth) = &state.config.proxy_auth {
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_canonical());
+        let from_proxy = peer.is_some_and(|peer| {
+            state
+                .config
+                .trusted_proxies
+                .iter()
+                .any(|net| net.contains(&peer))
+        });
+        if from_proxy {
+            let user = request
+                .headers()
+                .get(proxy_auth.header.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|user| !user.is_empty())
+                .map(str::to_owned);
+            return match user {
+                Some(user)
+                    if proxy_auth.allowed_users.is_empty()
+                        || proxy_auth.allowed_users.contains(&user) =>
+                {
+                    request.extensions_mut().insert(Principal(user));
+                    next.run(request).await
+                }
+                Some(user) => {
+                    tracing::warn!(%user, path = %logged_path(request.uri()), "rejected request from user that isn't allowed");
+                    auth_error(
+                        StatusCode::FORBIDDEN,
+                        format!("user `{user}` is not allowed to do this"),
+                    )
+                }
+                None => {
+                    tracing::warn!(header = %proxy_auth.header, path = %logged_path(request.uri()), "rejected request from proxy without user");
+                    auth_error(
+                        StatusCode::UNAUTHORIZED,
+                        format!("missing {} header", proxy_auth.header),
+                    )
+                }
+            };
+        }
+    }
+    let Some(token) = &state.config.token else {
+        if state.config.proxy_auth.is_some() {
+            tracing::warn!(path = %logged_path(request.uri()), "rejected request that didn't come through the proxy");
+            return auth_error(StatusCode::UNAUTHORIZED, "not authenticated".to_owned());
+        }
+        return next.run(request).await;
+    };
+
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            if let Some(bearer) = value.strip_prefix("Bearer ") {
+                return Some(("token".to_owned(), bearer.as_bytes().to_vec()));
+            }
+            let basic = value.strip_prefix("Basic ")?;
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(basic)
+                .ok()?;
+            let colon = decoded.iter().position(|&b| b == b':')?;
+            let user = String::from_utf8_lossy(&decoded[..colon]);
+            let user = if user.is_empty() {
+                "token".into()
+            } else {
+                user
+            };
+            Some((user.into_owned(), decoded[colon + 1..].to_vec()))
+        });
+
+    match presented {
+        Some((principal, presented)) if constant_time_eq(&presented, token.as_str().as_bytes()) => {
+            request.extensions_mut().insert(Principal(principal));
+            next.run(request).await
+        }
+        _ => {
+            tracing::warn!(path = %logged_path(request.uri()), "rejected request without valid token");
+            let mut response = auth_error(
+                StatusCode::UNAUTHORIZED,
+                "missing or invalid token".to_owned(),
+            );
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Basic realm="wakeonlan""#),
+            );
+            response
+        }
+    }
+}
+
+fn auth_error(status: StatusCode, error: String) -> Response {
+    (status, Json(ErrorResponse::new(error))).into_response()
+}
@@ -0,0 +1,187 @@
+//! Publishing the hosts and their last wakes to an MQTT broker as retained messages, for
+//! dashboards that show them without polling the API, with rumqttc.
+
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::sync::{watch, Notify};
+
+use super::{hosts::known_hosts, AppState};
+use crate::config::MqttConfig;
+
+/// How long connecting, and the broker accepting the connection, may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The broker drops the connection when it doesn't hear from the server for one and a half of
+/// these.
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+const RETRY_AFTER: Duration = Duration::from_secs(5);
+/// Publishes waiting to go out before publishing waits for them.
+const QUEUED: usize = 64;
+/// Hosts with a lot of history are big, but not this big.
+const MAX_PUBLISH: usize = 1024 * 1024;
+
+/// Publishes `<prefix>/hosts/<host>` with each host like in `GET /hosts`,
+/// `<prefix>/last_wake/<host>` with its last wake once it has one, and `<prefix>/status` with
+/// whether the server is connected. Everything is retained.
+pub struct Mqtt {
+    config: MqttConfig,
+}
+
+/// Tells the publisher that the hosts may have changed, so it doesn't wait for its `interval`.
+#[derive(Default)]
+pub(super) struct Changes(Notify);
+
+impl Changes {
+    pub(super) fn changed(&self) {
+        self.0.notify_one();
+    }
+}
+
+impl Mqtt {
+    pub fn new(config: MqttConfig) -> Mqtt {
+        Mqtt { config }
+    }
+
+    /// Publishes forever, reconnecting after a while when the connection fails.
+    pub async fn run(self, state: Arc<AppState>) {
+        let Some(options) = self.options() else {
+            tracing::error!(broker = %self.config.broker, "invalid mqtt broker, not publishing");
+            return;
+        };
+        let (client, mut events) = AsyncClient::new(options, QUEUED);
+        events
+            .network_options
+            .set_connection_timeout(CONNECT_TIMEOUT.as_secs());
+        let (connected_tx, mut connected) = watch::channel(false);
+        tokio::spawn(poll(events, self.config.broker.clone(), connected_tx));
+
+        // what the broker retains from this server, by topic
+        let mut retained = BTreeMap::new();
+        let mut resync = tokio::time::interval(self.config.interval());
+        let mut online = false;
+        loop {
+            let everything = tokio::select! {
+                changed = connected.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    online = *connected.borrow_and_update();
+                    // the broker may have lost what it retained, or something changed while the
+                    // server wasn't connected, so after connecting everything is published again
+                    if online {
+                        let status = client.publish(self.topic("status"), QoS::AtMostOnce, true, "online");
+                        if let Err(e) = status.await {
+                            tracing::error!(?e, "failed to publish to the mqtt broker");
+                            return;
+                        }
+                    }
+                    true
+                }
+                _ = state.mqtt.0.notified() => false,
+                _ = resync.tick() => false,
+            };
+            if !online {
+                continue;
+            }
+            for (topic, payload) in self.changes(&state, &retained, everything).await {
+                let published = client.publish(&topic, QoS::AtMostOnce, true, payload.as_bytes());
+                if let Err(e) = published.await {
+                    tracing::error!(?e, "failed to publish to the mqtt broker");
+                    return;
+                }
+                // an empty message clears the retained one
+                if payload.is_empty() {
+                    retained.remove(&topic);
+                } else {
+                    retained.insert(topic, payload);
+                }
+            }
+        }
+    }
+
+    /// Logs in with a clean session, and has the broker set `status` to `offline` when the
+    /// connection is lost. `None` if the broker isn't a host and a port.
+    fn options(&self) -> Option<MqttOptions> {
+        let (host, port) = self.config.broker.rsplit_once(':')?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let mut options = MqttOptions::new(&self.config.client_id, host, port.parse().ok()?);
+        options
+            .set_keep_alive(KEEP_ALIVE)
+            .set_clean_session(true)
+            .set_max_packet_size(10 * 1024, MAX_PUBLISH)
+            .set_last_will(LastWill::new(
+                self.topic("status"),
+                "offline",
+                QoS::AtMostOnce,
+                true,
+            ));
+        if let Some(username) = &self.config.username {
+            let password = self.config.password.as_ref();
+            options.set_credentials(username, password.map_or("", |password| password.as_str()));
+        }
+        Some(options)
+    }
+
+    /// What has to be published so the broker retains what's current, unless that's the same
+    /// as what it already retains. Hosts that are gone get an empty message.
+    async fn changes(
+        &self,
+        state: &Arc<AppState>,
+        retained: &BTreeMap<String, String>,
+        everything: bool,
+    ) -> Vec<(String, String)> {
+        let mut current = BTreeMap::new();
+        for host in &known_hosts(state).await {
+            let name = topic_level(&host.name);
+            let last_wake = host.last_wake.as_ref().map(serde_json::to_string);
+            for (topic, payload) in [
+                (format!("hosts/{name}"), Some(serde_json::to_string(host))),
+                (format!("last_wake/{name}"), last_wake),
+            ] {
+                match payload {
+                    Some(Ok(payload)) => {
+                        current.insert(self.topic(&topic), payload);
+                    }
+                    Some(Err(e)) => tracing::error!(?e, %topic, "failed to serialize"),
+                    None => {}
+                }
+            }
+        }
+        let gone = (retained.keys())
+            .filter(|topic| !current.contains_key(*topic))
+            .map(|topic| (topic.clone(), String::new()))
+            .collect::<Vec<_>>();
+        (current.into_iter())
+            .filter(|(topic, payload)| everything || retained.get(topic) != Some(payload))
+            .chain(gone)
+            .collect()
+    }
+
+    fn topic(&self, topic: &str) -> String {
+        format!("{}/{topic}", self.config.prefix)
+    }
+}
+
+/// Drives the connection, telling whether it's up. Polling again after it failed reconnects.
+async fn poll(mut events: EventLoop, broker: String, connected: watch::Sender<bool>) {
+    loop {
+        match events.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                tracing::info!(%broker, "Connected to the mqtt broker");
+                connected.send_replace(true);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                if connected.send(false).is_err() {
+                    return;
+                }
+                tracing::warn!(?e, %broker, "mqtt connection failed, reconnecting");
+                tokio::time::sleep(RETRY_AFTER).await;
+            }
+        }
+    }
+}
+
+/// A host name as one level of a topic, without what separates levels or matches them.
+fn topic_level(name: &str) -> String {
+    name.replace(['/', '+', '#'], "_")
+}
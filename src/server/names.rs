@@ -0,0 +1,111 @@
+//! NetBIOS names for discovered hosts that don't have a reverse DNS name.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{config::NetbiosConfig, discovery::HostEntry, netbios, MacAddress};
+
+/// How long an answer (or that there was none) is kept, hosts that don't answer would slow down
+/// every discovery otherwise.
+const KEEP: Duration = Duration::from_secs(5 * 60);
+
+/// By IP and MAC, so a new host getting the IP of another doesn't get its name.
+type Host = (Ipv4Addr, MacAddress);
+
+pub(super) struct NetbiosNames {
+    timeout: Duration,
+    concurrency: usize,
+    /// When each host was asked, with its answer.
+    cache: Mutex<HashMap<Host, (Instant, Option<String>)>>,
+}
+
+impl NetbiosNames {
+    pub(super) fn new(config: &NetbiosConfig) -> Self {
+        Self {
+            timeout: Duration::from_millis(config.timeout_ms),
+            concurrency: config.concurrency,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Forgets the answers that are too old to be used.
+    pub(super) fn prune(&self) {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.retain(|_, (at, _)| at.elapsed() < KEEP);
+    }
+
+    /// Names the hosts that are still only known by their IPv4 address, if they answer.
+    pub(super) fn resolve(&self, hosts: Vec<HostEntry>) -> Vec<HostEntry> {
+        let unnamed = |entry: &HostEntry| match entry.name.parse() {
+            Ok(IpAddr::V4(ip)) => Some((ip, entry.mac)),
+            _ => None,
+        };
+        let mut names = HashMap::new();
+        let mut missing = Vec::new();
+        {
+            let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            cache.retain(|_, (at, _)| at.elapsed() < KEEP);
+            for key in hosts.iter().filter_map(unnamed) {
+                match cache.get(&key) {
+                    Some((_, name)) => {
+                        names.insert(key, name.clone());
+                    }
+                    None if !missing.contains(&key) => missing.push(key),
+                    None => {}
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            let found = self.query(&missing);
+            let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            for (key, name) in found {
+                cache.insert(key, (Instant::now(), name.clone()));
+                names.insert(key, name);
+            }
+        }
+
+        hosts
+            .into_iter()
+            .map(
+                |entry| match unnamed(&entry).and_then(|key| names.get(&key)?.clone()) {
+                    Some(name) => HostEntry { name, ..entry },
+                    None => entry,
+                },
+            )
+            .collect()
+    }
+
+    /// Asks all of them, `concurrency` at a time.
+    fn query(&self, hosts: &[Host]) -> Vec<(Host, Option<String>)> {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for _ in 0..self.concurrency.min(hosts.len()) {
+                scope.spawn(|| {
+                    while let Some(&(ip, mac)) = hosts.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let addr = SocketAddr::from((ip, netbios::PORT));
+                        let name = match netbios::node_status(addr, self.timeout) {
+                            // like names from DNS
+                            Ok(name) => name.map(|name| name.to_lowercase()),
+                            Err(e) => {
+                                tracing::debug!(?e, %ip, "NetBIOS lookup failed");
+                                None
+                            }
+                        };
+                        let mut results = results.lock().unwrap_or_else(|e| e.into_inner());
+                        results.push(((ip, mac), name));
+                    }
+                });
+            }
+        });
+        results.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,96 @@
+//! What the discovery backends read, for finding out why a host isn't discovered.
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use std::sync::Arc;
+
+use super::AppState;
+use crate::{
+    api::v1::{self, NeighborTableError, Neighbors, SkipReason},
+    discovery::{NeighborTable, Skipped},
+};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/neighbors", get(neighbors))
+}
+
+async fn neighbors(State(state): State<Arc<AppState>>) -> Response {
+    match tokio::task::spawn_blocking(move || state.neighbors()).await {
+        Ok(neighbors) => Json(neighbors).into_response(),
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "error").into_response()
+        }
+    }
+}
+
+impl AppState {
+    /// What the configured backends and those of the sites read, in that order.
+    fn neighbors(&self) -> Neighbors {
+        let tables = self
+            .discovery
+            .neighbors()
+            .into_iter()
+            .map(|table| (None, table))
+            .chain(self.site_discovery.iter().flat_map(|(site, discovery)| {
+                discovery
+                    .neighbors()
+                    .into_iter()
+                    .map(|table| (Some(site.clone()), table))
+            }));
+        let mut neighbors = Neighbors {
+            neighbors: Vec::new(),
+            errors: Vec::new(),
+        };
+        for (
+            site,
+            NeighborTable {
+                source,
+                neighbors: table,
+            },
+        ) in tables
+        {
+            let table = match table {
+                Ok(table) => table,
+                Err(e) => {
+                    neighbors.errors.push(NeighborTableError {
+                        source: source.to_owned(),
+                        site,
+                        error: format!("{e:#}"),
+                    });
+                    continue;
+                }
+            };
+            neighbors
+                .neighbors
+                .extend(table.into_iter().map(|neighbor| {
+                    let (skipped, error) = match neighbor.skipped {
+                        None => (None, None),
+                        Some(Skipped::Incomplete) => (Some(SkipReason::Incomplete), None),
+                        Some(Skipped::UnusableMac) => (Some(SkipReason::UnusableMac), None),
+                        Some(Skipped::Invalid(error)) => {
+                            (Some(SkipReason::Invalid), Some(error.to_string()))
+                        }
+                    };
+                    v1::Neighbor {
+                        source: source.to_owned(),
+                        site: site.clone(),
+                        name: neighbor.name,
+                        ip: neighbor.ip,
+                        mac: neighbor.mac.map(|mac| mac.to_string()),
+                        interface: neighbor.interface,
+                        state: neighbor.state,
+                        vlan: neighbor.vlan,
+                        skipped,
+                        error,
+                    }
+                }));
+        }
+        neighbors
+    }
+}
@@ -0,0 +1,156 @@
+//! What the server knows about its network, for finding out why packets don't arrive.
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use std::sync::Arc;
+
+use super::{sender, AppState, SEND_BIND_ADDR};
+use crate::{
+    api::v1::{Network, NetworkAddress, NetworkInterface, SendRoute, SendStats},
+    config::Site,
+    interfaces::{self, Interface},
+};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/network", get(network))
+        .route("/send-stats", get(send_stats))
+}
+
+async fn network(State(state): State<Arc<AppState>>) -> Response {
+    match tokio::task::spawn_blocking(move || inspect(&state)).await {
+        Ok(Ok(network)) => Json(network).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to list network interfaces");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "error").into_response()
+        }
+    }
+}
+
+/// What was sent to each destination since the server started, ordered by the destination.
+async fn send_stats(State(state): State<Arc<AppState>>) -> Json<Vec<SendStats>> {
+    Json(state.sender.stats())
+}
+
+fn inspect(state: &AppState) -> std::io::Result<Network> {
+    let interfaces = interfaces::list()?;
+    let mut routes = send_routes(state);
+    for route in &mut routes {
+        resolve(&interfaces, route);
+    }
+    let failovers = state
+        .failovers
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    let interfaces = interfaces
+        .into_iter()
+        .map(|interface| NetworkInterface {
+            failovers: failovers.get(&interface.name).copied().unwrap_or_default(),
+            used: routes
+                .iter()
+                .any(|route| route.leaves_on.as_ref() == Some(&interface.name)),
+            name: interface.name,
+            index: interface.index,
+            mac: interface.mac.map(|mac| mac.to_string()),
+            up: interface.up,
+            running: interface.running,
+            addresses: interface
+                .addresses
+                .into_iter()
+                .map(|address| NetworkAddress {
+                    address: address.ip,
+                    prefix: address.prefix,
+                    netmask: address.netmask(),
+                    broadcast: address.broadcast(),
+                })
+                .collect(),
+        })
+        .collect();
+    Ok(Network { interfaces, routes })
+}
+
+/// Everywhere packets are sent to, not resolved yet: the configured destinations, those of the
+/// sites this server sends for, and those of the hosts with an interface or source address of
+/// their own.
+fn send_routes(state: &AppState) -> Vec<SendRoute> {
+    let mut routes = Vec::new();
+    let mut add = |site: Option<&Site>, host: Option<&str>| {
+        let interfaces = state.interfaces(host, site);
+        let bound_to = state.source(host, site);
+        // without any, sending isn't restricted to an interface
+        let interfaces = if interfaces.is_empty() {
+            vec![None]
+        } else {
+            interfaces.into_iter().map(Some).collect()
+        };
+        for destination in state.destinations(site) {
+            for interface in &interfaces {
+                routes.push(SendRoute {
+                    site: site.map(|site| site.name.clone()),
+                    host: host.map(str::to_owned),
+                    destination,
+                    interface: interface.clone(),
+                    bound_to,
+                    leaves_on: None,
+                    source: None,
+                    error: None,
+                });
+            }
+        }
+    };
+    let local_sites = state.config.sites.iter().filter(|site| !site.is_remote());
+    for site in [None].into_iter().chain(local_sites.map(Some)) {
+        add(site, None);
+    }
+    for host in state.registry.all() {
+        let site = state.site(Some(&host.name), &host.macs);
+        let own = host.interface.is_some() || host.source.is_some();
+        if !own || site.is_some_and(|site| site.is_remote()) {
+            continue;
+        }
+        add(site, Some(&host.name));
+    }
+    routes
+}
+
+/// Asks the system where packets to the destination would leave, like sending them would.
+fn resolve(interfaces: &[Interface], route: &mut SendRoute) {
+    let destination = route.destination;
+    let checked = match &route.interface {
+        Some(interface) => sender::check_interface(interface),
+        None => Ok(()),
+    }
+    .and_then(|()| route.bound_to.map_or(Ok(()), sender::check_source));
+    let result = checked.and_then(|()| {
+        let interface = route.interface.as_deref();
+        sender::route(SEND_BIND_ADDR, destination, interface, route.bound_to).map_err(|e| {
+            match sender::hint(&e, destination, None) {
+                Some(hint) => format!("{e}; {hint}"),
+                None => e.to_string(),
+            }
+        })
+    });
+    match result {
+        Ok(source) => {
+            route.source = Some(source.ip());
+            // a restricted socket leaves on its interface whatever the address
+            route.leaves_on = route.interface.clone().or_else(|| {
+                interfaces
+                    .iter()
+                    .find(|interface| interface.has_ip(source.ip()))
+                    .map(|interface| interface.name.clone())
+            });
+        }
+        Err(error) => route.error = Some(error),
+    }
+}
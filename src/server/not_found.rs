@@ -0,0 +1,134 @@
+//! The names a wake didn't find, for `not_found_ttl`, so a client asking for a host that's gone
+//! again and again doesn't have the hosts discovered every time.
+
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::janitor;
+use crate::{
+    api::v1::{NotFoundName, NotFoundStats, Size},
+    discovery::HostEntry,
+};
+
+/// Why a name wasn't found, like the wake said it.
+#[derive(Debug, Clone)]
+pub(super) enum Missing {
+    Host(String),
+    NameNotResolved { host: String, error: String },
+    NoMacForName { host: String, ips: Vec<IpAddr> },
+}
+
+#[derive(Default)]
+pub(super) struct NotFound(Mutex<Entries>);
+
+#[derive(Default)]
+struct Entries {
+    /// The discovered hosts the names weren't found in, see [`fingerprint`].
+    table: Option<u64>,
+    names: HashMap<String, Entry>,
+    /// How often a wake was answered from here since the server started.
+    hits: u64,
+}
+
+struct Entry {
+    missing: Missing,
+    at: Instant,
+    /// Since the server started, not just since it was last looked for.
+    hits: u64,
+    last_requester: Option<IpAddr>,
+}
+
+impl NotFound {
+    /// Why the name wasn't found, if it was looked for less than `ttl` ago.
+    pub(super) fn get(
+        &self,
+        name: &str,
+        ttl: Duration,
+        requester: Option<IpAddr>,
+    ) -> Option<Missing> {
+        let mut entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let entries = &mut *entries;
+        let entry = (entries.names.get_mut(name)).filter(|entry| entry.at.elapsed() < ttl)?;
+        entry.hits += 1;
+        entry.last_requester = requester;
+        entries.hits += 1;
+        Some(entry.missing.clone())
+    }
+
+    /// Remembers that the name wasn't found just now, forgetting the oldest beyond `limit`.
+    pub(super) fn insert(&self, name: &str, missing: Missing, limit: usize) {
+        let mut entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let (hits, last_requester) = (entries.names.get(name))
+            .map(|entry| (entry.hits, entry.last_requester))
+            .unwrap_or_default();
+        entries.names.insert(
+            name.to_owned(),
+            Entry {
+                missing,
+                at: Instant::now(),
+                hits,
+                last_requester,
+            },
+        );
+        janitor::trim(&mut entries.names, limit, |entry| entry.at);
+    }
+
+    /// Forgets every name if these discovered hosts aren't the ones they weren't found in, one of
+    /// them might be there now.
+    pub(super) fn discovered(&self, table: &[HostEntry]) {
+        let fingerprint = fingerprint(table);
+        let mut entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.table != Some(fingerprint) {
+            entries.table = Some(fingerprint);
+            entries.names.clear();
+        }
+    }
+
+    /// Forgets the names that were looked for `ttl` ago.
+    pub(super) fn prune(&self, ttl: Duration) {
+        let mut entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        entries.names.retain(|_, entry| entry.at.elapsed() < ttl);
+    }
+
+    /// The names that are still not found, the ones asked for most often first.
+    pub(super) fn stats(&self, ttl: Duration, limit: usize) -> NotFoundStats {
+        let entries = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let mut names = (entries.names.iter())
+            .filter_map(|(name, entry)| {
+                let expires_in = ttl.checked_sub(entry.at.elapsed())?;
+                Some(NotFoundName {
+                    name: name.clone(),
+                    hits: entry.hits,
+                    last_requester: entry.last_requester,
+                    expires_in: expires_in.as_secs_f64(),
+                })
+            })
+            .collect::<Vec<_>>();
+        names.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.name.cmp(&b.name)));
+        NotFoundStats {
+            entries: Size {
+                entries: entries.names.len(),
+                limit: Some(limit),
+            },
+            hits: entries.hits,
+            names,
+        }
+    }
+}
+
+/// Tells discovered hosts apart by their MACs and IPs, in any order.
+fn fingerprint(table: &[HostEntry]) -> u64 {
+    let mut entries = (table.iter())
+        .map(|entry| (entry.mac.0, entry.ip))
+        .collect::<Vec<_>>();
+    entries.sort();
+    entries.dedup();
+    let mut hasher = DefaultHasher::new();
+    entries.hash(&mut hasher);
+    hasher.finish()
+}
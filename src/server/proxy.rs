@@ -0,0 +1,510 @@
+//! Serving a service on a host that sleeps: requests are forwarded to it, and wake the host when
+//! it doesn't answer.
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri},
+    response::{Html, IntoResponse, Response},
+    Router,
+};
+use http_body_util::{channel::Sender, BodyExt, Channel};
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{tcp::OwnedReadHalf, TcpListener, TcpStream},
+};
+
+use super::{
+    format::ResponseFormat, html::html_escape, wait::PROBE_INTERVAL, wake::wake_by_name, AppState,
+    RequestContext, WakeSource,
+};
+use crate::config::{ProxyConfig, ProxyWake};
+
+/// Longer response heads are refused.
+const MAX_HEAD: u64 = 64 * 1024;
+/// How much of a body is read from the upstream at a time.
+const READ_SIZE: usize = 16 * 1024;
+/// How many pieces of a body are read ahead of a client that's slower than the upstream.
+const BUFFERED: usize = 8;
+/// Headers about the connection rather than the request, which aren't forwarded either way.
+const HOP_BY_HOP: [HeaderName; 8] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Forwards HTTP requests to the upstream on its own listener, waking the host it runs on when
+/// it doesn't answer. Upgrades like WebSockets aren't forwarded.
+pub struct Proxy {
+    listener: TcpListener,
+    config: ProxyConfig,
+}
+
+impl Proxy {
+    pub async fn bind(config: ProxyConfig) -> io::Result<Proxy> {
+        let listener = TcpListener::bind(config.listen).await?;
+        Ok(Proxy { listener, config })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves until accepting fails.
+    pub async fn run(self, state: Arc<AppState>) -> io::Result<()> {
+        let (authority, prefix) = self
+            .config
+            .upstream_parts()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid upstream url"))?;
+        let proxied = Proxied {
+            authority,
+            prefix: prefix.to_owned(),
+            max_wait: Duration::from_secs(self.config.max_wait),
+            config: self.config,
+            state,
+            woken: Mutex::new(None),
+        };
+        let app = Router::new().fallback(proxy).with_state(Arc::new(proxied));
+        axum::serve(
+            self.listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+    }
+}
+
+struct Proxied {
+    config: ProxyConfig,
+    /// `host:port` of the upstream.
+    authority: String,
+    /// The path of the upstream url without a trailing slash, the paths of requests are
+    /// appended to it.
+    prefix: String,
+    max_wait: Duration,
+    state: Arc<AppState>,
+    /// When the host was last woken, requests while it comes up don't wake it again.
+    woken: Mutex<Option<Instant>>,
+}
+
+async fn proxy(
+    State(proxied): State<Arc<Proxied>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+) -> Response {
+    let upstream = match proxied.connect().await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            tracing::debug!(?e, upstream = %proxied.authority, "upstream doesn't answer");
+            // a request isn't Sync, so it can't be borrowed while waiting
+            let triggers = proxied.triggers(&request);
+            match proxied.wait_for_upstream(triggers, peer).await {
+                Ok(upstream) => upstream,
+                Err(response) => return response,
+            }
+        }
+    };
+    match proxied.forward(upstream, request, peer).await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!(?e, upstream = %proxied.authority, "failed to forward request");
+            page(
+                StatusCode::BAD_GATEWAY,
+                "Bad gateway",
+                &format!("{} didn't answer like an HTTP server.", proxied.config.host),
+                None,
+            )
+        }
+    }
+}
+
+impl Proxied {
+    /// Connecting is how the upstream is checked, the host answering doesn't mean the service on
+    /// it is up yet. It gets `verify_timeout` like the checks of hosts do.
+    async fn connect(&self) -> io::Result<TcpStream> {
+        let timeout = self.state.config.verify_timeout;
+        match tokio::time::timeout(timeout, TcpStream::connect(&self.authority)).await {
+            Ok(result) => result,
+            Err(_) => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+
+    /// Wakes the host if the request `triggers` a wake and it wasn't woken already, then either
+    /// waits for the upstream to come up (with `hold`) or answers with a page saying it's waking
+    /// up.
+    async fn wait_for_upstream(
+        &self,
+        triggers: bool,
+        peer: SocketAddr,
+    ) -> Result<TcpStream, Response> {
+        let host = &self.config.host;
+        let woken = {
+            let mut woken = self.woken.lock().unwrap_or_else(|e| e.into_inner());
+            match *woken {
+                Some(at) if at.elapsed() < self.max_wait => Some((at, false)),
+                _ if triggers => {
+                    let now = Instant::now();
+                    *woken = Some(now);
+                    Some((now, true))
+                }
+                _ => None,
+            }
+        };
+        let Some((woken_at, wake)) = woken else {
+            return Err(page(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Asleep",
+                &format!("{host} is asleep."),
+                None,
+            ));
+        };
+        if wake {
+            let context = RequestContext {
+                client: Some(peer.ip().to_canonical()),
+                principal: None,
+                source: WakeSource::Proxy,
+            };
+            if let Err(e) = wake_by_name(&self.state, host.clone(), context).await {
+                tracing::warn!(%host, %e, "failed to wake the host of the proxied service");
+                *self.woken.lock().unwrap_or_else(|e| e.into_inner()) = None;
+                return Err(page(
+                    StatusCode::BAD_GATEWAY,
+                    "Error",
+                    &format!("Failed to wake {host}: {e}"),
+                    None,
+                ));
+            }
+            tracing::info!(%host, client = %peer.ip().to_canonical(), "Woke the host of the proxied service");
+        }
+
+        let deadline = woken_at + self.max_wait;
+        if !self.config.hold {
+            let retry = deadline
+                .saturating_duration_since(Instant::now())
+                .as_secs()
+                .max(1);
+            let mut response = page(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Waking up",
+                &format!("Waking up {host}, retry in ~{retry}s."),
+                Some(5),
+            );
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry));
+            return Err(response);
+        }
+        while Instant::now() < deadline {
+            tokio::time::sleep(PROBE_INTERVAL).await;
+            if let Ok(upstream) = self.connect().await {
+                tracing::info!(%host, elapsed = ?woken_at.elapsed(), "Proxied service is up");
+                return Ok(upstream);
+            }
+        }
+        Err(page(
+            StatusCode::GATEWAY_TIMEOUT,
+            "Timed out",
+            &format!("{host} didn't come up in {}s.", self.max_wait.as_secs()),
+            None,
+        ))
+    }
+
+    fn triggers(&self, request: &Request) -> bool {
+        match self.config.wake {
+            ProxyWake::Any => true,
+            // what browsers ask for when loading a page, the query is the service's own
+            ProxyWake::Pages => {
+                request.method() == Method::GET
+                    && ResponseFormat::negotiate(request.headers(), &Uri::from_static("/"))
+                        == Ok(Some(ResponseFormat::Html))
+            }
+            ProxyWake::Never => false,
+        }
+    }
+
+    /// Sends the request over a connection of its own and streams the response back.
+    async fn forward(
+        &self,
+        upstream: TcpStream,
+        request: Request,
+        peer: SocketAddr,
+    ) -> io::Result<Response> {
+        let (parts, mut body) = request.into_parts();
+        let path = parts.uri.path_and_query().map_or("/", |path| path.as_str());
+        let mut head = format!(
+            "{} {}{path} HTTP/1.1\r\nHost: {}\r\n",
+            parts.method, self.prefix, self.authority
+        )
+        .into_bytes();
+        let mut forwarded_for = Vec::new();
+        for (name, value) in &parts.headers {
+            if name == header::HOST || name == header::CONTENT_LENGTH || HOP_BY_HOP.contains(name) {
+                continue;
+            }
+            if name == "x-forwarded-for" {
+                forwarded_for.extend_from_slice(value.as_bytes());
+                forwarded_for.extend_from_slice(b", ");
+                continue;
+            }
+            head.extend_from_slice(name.as_str().as_bytes());
+            head.extend_from_slice(b": ");
+            head.extend_from_slice(value.as_bytes());
+            head.extend_from_slice(b"\r\n");
+        }
+        head.extend_from_slice(b"X-Forwarded-For: ");
+        head.extend_from_slice(&forwarded_for);
+        head.extend_from_slice(format!("{}\r\n", peer.ip().to_canonical()).as_bytes());
+        if let Some(host) = parts.headers.get(header::HOST) {
+            if !parts.headers.contains_key("x-forwarded-host") {
+                head.extend_from_slice(b"X-Forwarded-Host: ");
+                head.extend_from_slice(host.as_bytes());
+                head.extend_from_slice(b"\r\n");
+            }
+        }
+        if !parts.headers.contains_key("x-forwarded-proto") {
+            head.extend_from_slice(b"X-Forwarded-Proto: http\r\n");
+        }
+        let length = parts
+            .headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok()?.parse::<u64>().ok());
+        let chunked = length.is_none() && !body.is_end_stream();
+        match length {
+            Some(length) => {
+                head.extend_from_slice(format!("Content-Length: {length}\r\n").as_bytes())
+            }
+            None if chunked => head.extend_from_slice(b"Transfer-Encoding: chunked\r\n"),
+            None => {}
+        }
+        head.extend_from_slice(b"Connection: close\r\n\r\n");
+
+        let (reader, mut writer) = upstream.into_split();
+        writer.write_all(&head).await?;
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(io::Error::other)?;
+            let Ok(data) = frame.into_data() else {
+                continue;
+            };
+            // an empty chunk would end the body
+            if data.is_empty() {
+                continue;
+            }
+            if chunked {
+                writer
+                    .write_all(format!("{:x}\r\n", data.len()).as_bytes())
+                    .await?;
+                writer.write_all(&data).await?;
+                writer.write_all(b"\r\n").await?;
+            } else {
+                writer.write_all(&data).await?;
+            }
+        }
+        if chunked {
+            writer.write_all(b"0\r\n\r\n").await?;
+        }
+
+        let mut reader = BufReader::new(reader);
+        // interim responses like 100 Continue are for us
+        let (status, headers) = loop {
+            let (status, headers) = read_head(&mut reader).await?;
+            if !status.is_informational() {
+                break (status, headers);
+            }
+        };
+        let framing = if parts.method == Method::HEAD
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+        {
+            Framing::Empty
+        } else if headers
+            .get(header::TRANSFER_ENCODING)
+            .and_then(|encoding| encoding.to_str().ok())
+            .is_some_and(|encoding| encoding.to_ascii_lowercase().ends_with("chunked"))
+        {
+            Framing::Chunked
+        } else {
+            match headers.get(header::CONTENT_LENGTH) {
+                Some(length) => Framing::Length(
+                    length
+                        .to_str()
+                        .ok()
+                        .and_then(|length| length.parse().ok())
+                        .ok_or_else(|| invalid("invalid content length"))?,
+                ),
+                None => Framing::Close,
+            }
+        };
+
+        let (mut sender, channel) = Channel::<Bytes, io::Error>::new(BUFFERED);
+        tokio::spawn(async move {
+            if let Err(e) = copy_body(&mut reader, framing, &mut sender).await {
+                tracing::debug!(?e, "failed to read the body from the upstream");
+                sender.abort(e);
+            }
+        });
+        let mut response = Response::new(Body::new(channel));
+        *response.status_mut() = status;
+        for (name, value) in &headers {
+            if framing == Framing::Chunked && name == header::CONTENT_LENGTH {
+                continue;
+            }
+            if !HOP_BY_HOP.contains(name) {
+                response.headers_mut().append(name, value.clone());
+            }
+        }
+        Ok(response)
+    }
+}
+
+/// How the end of a response body is told.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    Empty,
+    Length(u64),
+    Chunked,
+    /// It ends when the upstream closes the connection.
+    Close,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The status line and headers of a response, headers that aren't valid are left out.
+async fn read_head(reader: &mut BufReader<OwnedReadHalf>) -> io::Result<(StatusCode, HeaderMap)> {
+    let mut head = (&mut *reader).take(MAX_HEAD);
+    let mut line = Vec::new();
+    head.read_until(b'\n', &mut line).await?;
+    let status = std::str::from_utf8(&line)
+        .ok()
+        .filter(|line| line.starts_with("HTTP/1."))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .ok_or_else(|| invalid("invalid status line"))?;
+
+    let mut headers = HeaderMap::new();
+    loop {
+        line.clear();
+        if head.read_until(b'\n', &mut line).await? == 0 {
+            return Err(invalid("response head too long or cut off"));
+        }
+        let line = line.trim_ascii();
+        if line.is_empty() {
+            return Ok((status, headers));
+        }
+        let Some(colon) = line.iter().position(|&byte| byte == b':') else {
+            continue;
+        };
+        let (name, value) = (&line[..colon], line[colon + 1..].trim_ascii());
+        if let (Ok(name), Ok(value)) =
+            (HeaderName::from_bytes(name), HeaderValue::from_bytes(value))
+        {
+            headers.append(name, value);
+        }
+    }
+}
+
+/// Sends the body on to the client until it's done, or the client went away.
+async fn copy_body(
+    reader: &mut BufReader<OwnedReadHalf>,
+    framing: Framing,
+    sender: &mut Sender<Bytes, io::Error>,
+) -> io::Result<()> {
+    match framing {
+        Framing::Empty => Ok(()),
+        Framing::Length(length) => copy_exactly(reader, length, sender).await,
+        Framing::Close => loop {
+            let data = read_some(reader, READ_SIZE).await?;
+            if data.is_empty() || sender.send_data(data).await.is_err() {
+                return Ok(());
+            }
+        },
+        Framing::Chunked => loop {
+            let mut line = Vec::new();
+            (&mut *reader)
+                .take(MAX_HEAD)
+                .read_until(b'\n', &mut line)
+                .await?;
+            let size = std::str::from_utf8(&line)
+                .ok()
+                .and_then(|line| line.trim().split(';').next())
+                .and_then(|size| u64::from_str_radix(size.trim(), 16).ok())
+                .ok_or_else(|| invalid("invalid chunk size"))?;
+            if size == 0 {
+                // trailers aren't passed on
+                loop {
+                    line.clear();
+                    let read = (&mut *reader)
+                        .take(MAX_HEAD)
+                        .read_until(b'\n', &mut line)
+                        .await?;
+                    if read == 0 || line.trim_ascii().is_empty() {
+                        return Ok(());
+                    }
+                }
+            }
+            copy_exactly(reader, size, sender).await?;
+            // the line break after every chunk
+            (&mut *reader).take(2).read_until(b'\n', &mut line).await?;
+        },
+    }
+}
+
+async fn copy_exactly(
+    reader: &mut BufReader<OwnedReadHalf>,
+    mut remaining: u64,
+    sender: &mut Sender<Bytes, io::Error>,
+) -> io::Result<()> {
+    while remaining > 0 {
+        let data = read_some(reader, READ_SIZE.min(remaining as usize)).await?;
+        if data.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        remaining -= data.len() as u64;
+        if sender.send_data(data).await.is_err() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+async fn read_some(reader: &mut (impl AsyncRead + Unpin), max: usize) -> io::Result<Bytes> {
+    let mut buf = vec![0; max];
+    let len = reader.read(&mut buf).await?;
+    buf.truncate(len);
+    Ok(Bytes::from(buf))
+}
+
+/// The pages the proxy answers with itself, reloading every `refresh` seconds if set.
+fn page(status: StatusCode, title: &str, message: &str, refresh: Option<u64>) -> Response {
+    let refresh = refresh
+        .map(|seconds| format!("\n    <meta http-equiv=\"refresh\" content=\"{seconds}\" />"))
+        .unwrap_or_default();
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="UTF-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1.0" />{refresh}
+    <title>{title}</title>
+  </head>
+  <body>
+    <p>{}</p>
+  </body>
+</html>
+"#,
+        html_escape(message)
+    );
+    (status, Html(page)).into_response()
+}
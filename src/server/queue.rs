@@ -0,0 +1,293 @@
+//! Wakes that failed because the network was down, sent again once it's back.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+use super::{audit::AuditDestination, wake, AppState, RequestContext};
+use crate::{
+    api::v1::{QueuedWake, WakeOutcome},
+    config::SendQueueConfig,
+    discovery::parse_mac_addr,
+    MacAddress,
+};
+
+/// The wait before a queued wake is sent again the first time, doubled for every time after.
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(30);
+
+pub(super) fn read_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/queue", get(list))
+}
+
+pub(super) fn write_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/queue/{mac}", delete(cancel))
+}
+
+/// At most one entry for each MAC, a wake of a MAC that's queued already is merged into it.
+#[derive(Default)]
+pub(super) struct SendQueue {
+    entries: Mutex<Vec<Queued>>,
+    /// Tells the retries to look at the queue again.
+    changed: Notify,
+}
+
+/// A wake of a MAC that's waiting for the network.
+#[derive(Debug, Clone)]
+pub(super) struct Queued {
+    /// Of the last wake of the MAC.
+    pub(super) id: String,
+    pub(super) host: Option<String>,
+    pub(super) mac: MacAddress,
+    pub(super) addresses: Vec<SocketAddr>,
+    /// Where it's sent on, like the wake would have.
+    pub(super) interfaces: Vec<String>,
+    pub(super) context: RequestContext,
+    pub(super) requested_at: DateTime<Utc>,
+    pub(super) error: Option<String>,
+    attempts: u32,
+    next_attempt: DateTime<Utc>,
+}
+
+impl Queued {
+    pub(super) fn new(
+        id: &str,
+        host: Option<&str>,
+        mac: MacAddress,
+        addresses: Vec<SocketAddr>,
+        interfaces: &[String],
+        context: &RequestContext,
+        error: Option<String>,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id: id.to_owned(),
+            host: host.map(str::to_owned),
+            mac,
+            addresses,
+            interfaces: interfaces.to_vec(),
+            context: context.clone(),
+            requested_at: now,
+            error,
+            attempts: 0,
+            next_attempt: now + FIRST_RETRY,
+        }
+    }
+
+    fn to_api(&self) -> QueuedWake {
+        QueuedWake {
+            id: self.id.clone(),
+            host: self.host.clone(),
+            mac: self.mac.to_string(),
+            destinations: self.addresses.clone(),
+            requested_at: self.requested_at,
+            attempts: self.attempts,
+            next_attempt: self.next_attempt,
+            error: self.error.clone(),
+        }
+    }
+}
+
+impl SendQueue {
+    /// Queues the wake, or merges it into the one of its MAC. Returns `false` if the queue is full.
+    pub(super) fn push(&self, size: usize, queued: Queued) -> bool {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.mac == queued.mac) {
+            for address in queued.addresses {
+                if !entry.addresses.contains(&address) {
+                    entry.addresses.push(address);
+                }
+            }
+            // the last wake is the one it's about now
+            entry.id = queued.id;
+            entry.host = queued.host.or(entry.host.take());
+            entry.interfaces = queued.interfaces;
+            entry.context = queued.context;
+            entry.requested_at = queued.requested_at;
+            entry.error = queued.error;
+            entry.next_attempt = entry.next_attempt.min(queued.next_attempt);
+        } else if entries.len() < size {
+            entries.push(queued);
+        } else {
+            return false;
+        }
+        drop(entries);
+        self.changed.notify_one();
+        true
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    fn all(&self) -> Vec<Queued> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn remove(&self, mac: MacAddress) -> Option<Queued> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let index = entries.iter().position(|entry| entry.mac == mac)?;
+        Some(entries.remove(index))
+    }
+
+    /// Notes the failed attempt and when the next one is, unless it was cancelled meanwhile.
+    fn failed(&self, mac: MacAddress, error: Option<String>) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.mac == mac) {
+            entry.attempts += 1;
+            let wait = FIRST_RETRY
+                .saturating_mul(2u32.saturating_pow(entry.attempts - 1))
+                .min(MAX_RETRY);
+            entry.next_attempt = Utc::now() + wait;
+            entry.error = error;
+        }
+    }
+}
+
+async fn list(State(state): State<Arc<AppState>>) -> Json<Vec<QueuedWake>> {
+    Json(state.send_queue.all().iter().map(Queued::to_api).collect())
+}
+
+async fn cancel(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(mac): Path<String>,
+) -> Response {
+    let Some(mac) = parse_mac_addr(&mac) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("invalid mac address `{mac}`"),
+        )
+            .into_response();
+    };
+    let Some(queued) = state.send_queue.remove(mac) else {
+        return (StatusCode::NOT_FOUND, "nothing queued for that mac").into_response();
+    };
+    tracing::info!(%mac, id = %queued.id, client = ?context.client, principal = ?context.principal, "Cancelled queued wake");
+    record(&state, &queued, WakeOutcome::Cancelled, Vec::new());
+    StatusCode::NO_CONTENT.into_response()
+}
+
+fn record(
+    state: &AppState,
+    queued: &Queued,
+    outcome: WakeOutcome,
+    destinations: Vec<AuditDestination>,
+) {
+    state.record_wake(
+        queued.mac,
+        &queued.id,
+        queued.host.as_deref(),
+        &queued.context,
+        outcome,
+        destinations,
+    );
+}
+
+/// Sends the queued wakes again whenever they're due, until they're sent or too old.
+pub async fn run_send_queue(state: Arc<AppState>) {
+    let Some(config) = state.config.send_queue.clone() else {
+        return;
+    };
+    loop {
+        let next = state
+            .send_queue
+            .all()
+            .iter()
+            .map(|entry| entry.next_attempt)
+            .min();
+        if let Some(next) = next {
+            let wait = (next - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = state.send_queue.changed.notified() => continue,
+            }
+        } else {
+            state.send_queue.changed.notified().await;
+            continue;
+        }
+
+        let now = Utc::now();
+        let due = state
+            .send_queue
+            .all()
+            .into_iter()
+            .filter(|entry| entry.next_attempt <= now)
+            .collect::<Vec<_>>();
+        let result = tokio::task::spawn_blocking({
+            let state = state.clone();
+            let config = config.clone();
+            move || {
+                for queued in due {
+                    retry(&state, &config, queued);
+                }
+            }
+        })
+        .await;
+        if let Err(e) = result {
+            tracing::error!(?e, "join error");
+        }
+    }
+}
+
+fn retry(state: &AppState, config: &SendQueueConfig, queued: Queued) {
+    let host = queued.host.as_deref();
+    let source = state.source(host, state.site(host, &[queued.mac]));
+    let destinations = wake::send_wake_one(
+        state,
+        queued.mac,
+        &queued.addresses,
+        &queued.interfaces,
+        source,
+        false,
+    );
+    let sent = destinations.iter().any(|destination| destination.sent);
+    let still_down = destinations.iter().any(|destination| destination.queued);
+    let age = Utc::now() - queued.requested_at;
+    let too_old = age.to_std().unwrap_or_default() >= Duration::from_secs(config.max_age);
+    if !sent && still_down && !too_old {
+        let error = destinations
+            .iter()
+            .find_map(|destination| destination.error.clone());
+        tracing::debug!(mac = %queued.mac, ?error, "network is still down");
+        state.send_queue.failed(queued.mac, error);
+        return;
+    }
+
+    // the latest of it, another wake might have been merged into it since
+    let Some(queued) = state.send_queue.remove(queued.mac) else {
+        if sent {
+            tracing::info!(mac = %queued.mac, "Sent queued wake that was cancelled meanwhile");
+        }
+        return;
+    };
+    let audit = destinations
+        .iter()
+        .map(|destination| AuditDestination {
+            address: destination.address,
+            sent: destination.sent,
+        })
+        .collect();
+    if sent {
+        tracing::info!(hostname = ?queued.host, mac = %queued.mac, ?destinations, "Woke up from the send queue");
+        record(state, &queued, WakeOutcome::Sent, audit);
+        state.run_post_wake(&queued.id, queued.host.as_deref(), &[queued.mac]);
+    } else {
+        tracing::warn!(hostname = ?queued.host, mac = %queued.mac, ?destinations, too_old, "gave up on queued wake");
+        record(state, &queued, WakeOutcome::Failed, audit);
+    }
+}
@@ -0,0 +1,456 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use super::{
+    state_file::{self, Format},
+    AppState, RequestContext,
+};
+use crate::{
+    config::{non_empty_location, parse_password, parse_service_url, Config, Site, StaticHost},
+    MacAddress, SecureOnPassword,
+};
+
+pub(super) fn read_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/hosts/export", get(export))
+}
+
+pub(super) fn write_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/hosts/import", post(import))
+}
+
+/// The hosts that are known without discovering them, saved to the registry file if there is one.
+pub(super) struct Registry {
+    path: Option<PathBuf>,
+    hosts: RwLock<Vec<StaticHost>>,
+}
+
+/// The registry file, and what `/hosts/export` returns and `/hosts/import` takes.
+#[derive(Serialize, Deserialize)]
+struct HostsDocument<T> {
+    hosts: Vec<T>,
+}
+
+const FORMAT: Format = Format {
+    what: "registry",
+    migrations: &[macs],
+};
+
+/// The first registries had one MAC for every host, in `mac`. The config file still takes that,
+/// the registry has them all in `macs` since version 2.
+fn macs(document: &mut Map<String, Value>) -> eyre::Result<()> {
+    let Some(Value::Array(hosts)) = document.get_mut("hosts") else {
+        return Ok(());
+    };
+    for host in hosts.iter_mut().filter_map(Value::as_object_mut) {
+        let Some(mac) = host.remove("mac") else {
+            continue;
+        };
+        match host
+            .entry("macs")
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            Value::Array(macs) => macs.insert(0, mac),
+            _ => eyre::bail!("`macs` isn't a list"),
+        }
+    }
+    Ok(())
+}
+
+impl Registry {
+    /// Loads the registry file, or starts with the configured hosts if there is none yet.
+    pub(super) fn load(config: &Config) -> eyre::Result<Registry> {
+        let document = (config.registry.as_deref())
+            .map(|path| state_file::load::<HostsDocument<StaticHost>>(path, &FORMAT))
+            .transpose()?
+            .flatten();
+        let hosts = match document {
+            Some(document) => {
+                tracing::debug!(hosts = document.hosts.len(), "loaded registry");
+                document.hosts
+            }
+            None => config.hosts.clone(),
+        };
+        Ok(Registry {
+            path: config.registry.clone(),
+            hosts: RwLock::new(hosts),
+        })
+    }
+
+    pub(super) fn all(&self) -> Vec<StaticHost> {
+        self.hosts.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Looks up the MACs of a host by its name, ignoring case.
+    pub(super) fn get(&self, name: &str) -> Option<Vec<MacAddress>> {
+        self.hosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|host| host.name.eq_ignore_ascii_case(name))
+            .map(|host| host.macs.clone())
+    }
+
+    /// The name the host is registered with, which can differ from `name` in case.
+    pub(super) fn name(&self, name: &str) -> Option<String> {
+        self.hosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|host| host.name.eq_ignore_ascii_case(name))
+            .map(|host| host.name.clone())
+    }
+
+    /// The interface the host's packets leave on, if it has its own.
+    pub(super) fn interface(&self, name: &str) -> Option<String> {
+        self.hosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|host| host.name.eq_ignore_ascii_case(name))
+            .and_then(|host| host.interface.clone())
+    }
+
+    /// The local address the host's packets are sent from, if it has its own.
+    pub(super) fn source(&self, name: &str) -> Option<IpAddr> {
+        self.hosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|host| host.name.eq_ignore_ascii_case(name))
+            .and_then(|host| host.source)
+    }
+
+    /// The name of the site the host is in, if it's not in this server's network.
+    pub(super) fn site(&self, name: &str) -> Option<String> {
+        self.hosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|host| host.name.eq_ignore_ascii_case(name))
+            .and_then(|host| host.site.clone())
+    }
+
+    /// Where the host is, if that's said.
+    pub(super) fn location(&self, name: &str) -> Option<String> {
+        self.hosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|host| host.name.eq_ignore_ascii_case(name))
+            .and_then(|host| host.location.clone())
+    }
+
+    /// What the host is woken for, if that's configured.
+    pub(super) fn service_url(&self, name: &str) -> Option<String> {
+        self.hosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|host| host.name.eq_ignore_ascii_case(name))
+            .and_then(|host| host.service_url.clone())
+    }
+
+    /// Whether the host, or the one with any of the MACs, is only woken with `confirm`.
+    pub(super) fn requires_confirmation(&self, name: Option<&str>, macs: &[MacAddress]) -> bool {
+        self.hosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|host| host.require_confirmation)
+            .any(|host| {
+                name.is_some_and(|name| host.name.eq_ignore_ascii_case(name))
+                    || host.macs.iter().any(|mac| macs.contains(mac))
+            })
+    }
+
+    /// The SecureOn password of the host with the MAC, if it has one.
+    pub(super) fn password(&self, mac: MacAddress) -> Option<SecureOnPassword> {
+        self.hosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|host| host.macs.contains(&mac))
+            .and_then(|host| host.password)
+    }
+
+    pub(super) fn contains_mac(&self, mac: MacAddress) -> bool {
+        self.hosts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(|host| host.macs.contains(&mac))
+    }
+
+    /// Applies an import unless any of its entries is invalid, returning whether it was applied.
+    /// The hosts are saved before they're changed, so a failed save doesn't change anything.
+    fn import(
+        &self,
+        entries: Vec<ImportEntry>,
+        mode: ImportMode,
+        sites: &[Site],
+    ) -> eyre::Result<(bool, Vec<ImportResult>)> {
+        let mut current = self.hosts.write().unwrap_or_else(|e| e.into_inner());
+        let (hosts, results) = plan_import(&current, entries, mode, sites);
+        if results
+            .iter()
+            .any(|result| result.status == ImportStatus::Invalid)
+        {
+            return Ok((false, results));
+        }
+        if let Some(path) = &self.path {
+            let document = HostsDocument {
+                hosts: hosts.clone(),
+            };
+            state_file::save(path, &FORMAT, &document)?;
+        }
+        *current = hosts;
+        Ok((true, results))
+    }
+}
+
+/// Everything but the passwords.
+async fn export(State(state): State<Arc<AppState>>) -> Json<HostsDocument<StaticHost>> {
+    let hosts = state.registry.all().into_iter();
+    Json(HostsDocument {
+        hosts: hosts
+            .map(|host| StaticHost {
+                password: None,
+                ..host
+            })
+            .collect(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ImportMode {
+    /// Add the imported hosts and update the ones with the same name, keep the others.
+    #[default]
+    Merge,
+    /// The imported hosts are all hosts afterwards.
+    Replace,
+}
+
+#[derive(Deserialize)]
+struct ImportQuery {
+    #[serde(default)]
+    mode: ImportMode,
+}
+
+/// An imported host before it's validated, so one bad entry can be reported along with the rest.
+#[derive(Deserialize)]
+struct ImportEntry {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    mac: Option<String>,
+    #[serde(default)]
+    macs: Vec<String>,
+    #[serde(default)]
+    interface: Option<String>,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    site: Option<String>,
+    #[serde(default)]
+    location: Option<String>,
+    #[serde(default)]
+    service_url: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    require_confirmation: bool,
+}
+
+#[derive(Serialize)]
+struct ImportResponse {
+    /// `false` if any entry was invalid, then nothing was changed.
+    applied: bool,
+    mode: ImportMode,
+    results: Vec<ImportResult>,
+}
+
+#[derive(Serialize)]
+struct ImportResult {
+    name: String,
+    status: ImportStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ImportStatus {
+    Added,
+    Updated,
+    Unchanged,
+    /// Only for `replace`, a host that wasn't in the import.
+    Removed,
+    Invalid,
+}
+
+async fn import(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Query(query): Query<ImportQuery>,
+    Json(document): Json<HostsDocument<ImportEntry>>,
+) -> Response {
+    let mode = query.mode;
+    let (applied, results) = match state
+        .registry
+        .import(document.hosts, mode, &state.config.sites)
+    {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::error!(?e, "failed to save registry");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "failed to save registry").into_response();
+        }
+    };
+
+    if !applied {
+        tracing::warn!(?mode, client = ?context.client, principal = ?context.principal, "rejected invalid hosts import");
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ImportResponse {
+                applied: false,
+                mode,
+                results,
+            }),
+        )
+            .into_response();
+    }
+
+    tracing::info!(?mode, entries = results.len(), client = ?context.client, principal = ?context.principal, "Imported hosts");
+    state.mqtt.changed();
+    Json(ImportResponse {
+        applied: true,
+        mode,
+        results,
+    })
+    .into_response()
+}
+
+/// Works out what the registry looks like after the import, and what happens to every entry.
+fn plan_import(
+    current: &[StaticHost],
+    entries: Vec<ImportEntry>,
+    mode: ImportMode,
+    sites: &[Site],
+) -> (Vec<StaticHost>, Vec<ImportResult>) {
+    let mut hosts = match mode {
+        ImportMode::Merge => current.to_vec(),
+        ImportMode::Replace => Vec::new(),
+    };
+    let mut results = Vec::new();
+
+    for entry in entries {
+        let name = entry.name.trim().to_owned();
+        let invalid = |name: String, error: String| ImportResult {
+            name,
+            status: ImportStatus::Invalid,
+            error: Some(error),
+        };
+
+        let site = entry.site.filter(|site| !site.trim().is_empty());
+        if let Some(site) = site
+            .as_ref()
+            .filter(|site| !sites.iter().any(|known| known.name == **site))
+        {
+            results.push(invalid(name, format!("no site named `{site}`")));
+            continue;
+        }
+        let macs = entry.mac.iter().chain(&entry.macs).map(String::as_str);
+        let host = StaticHost::new(&name, macs).and_then(|host| {
+            let password = parse_password(entry.password, &host.name)?;
+            let source = parse_source(entry.source, &host.name)?;
+            let service_url = parse_service_url(entry.service_url, &host.name)?;
+            Ok((host, password, source, service_url))
+        });
+        let host = match host {
+            Ok((host, password, source, service_url)) => StaticHost {
+                interface: entry
+                    .interface
+                    .filter(|interface| !interface.trim().is_empty()),
+                source,
+                site,
+                location: non_empty_location(entry.location),
+                service_url,
+                password,
+                require_confirmation: entry.require_confirmation,
+                ..host
+            },
+            Err(error) => {
+                results.push(invalid(name, error));
+                continue;
+            }
+        };
+        if results
+            .iter()
+            .any(|result: &ImportResult| result.name.eq_ignore_ascii_case(&name))
+        {
+            results.push(invalid(name, "duplicate name".to_owned()));
+            continue;
+        }
+
+        let previous = current
+            .iter()
+            .find(|host| host.name.eq_ignore_ascii_case(&name));
+        let status = match previous {
+            None => ImportStatus::Added,
+            Some(previous) if *previous == host => ImportStatus::Unchanged,
+            Some(_) => ImportStatus::Updated,
+        };
+        match hosts
+            .iter_mut()
+            .find(|existing| existing.name.eq_ignore_ascii_case(&name))
+        {
+            Some(existing) => *existing = host,
+            None => hosts.push(host),
+        }
+        results.push(ImportResult {
+            name,
+            status,
+            error: None,
+        });
+    }
+
+    if let ImportMode::Replace = mode {
+        for host in current {
+            if !results
+                .iter()
+                .any(|result| result.name.eq_ignore_ascii_case(&host.name))
+            {
+                results.push(ImportResult {
+                    name: host.name.clone(),
+                    status: ImportStatus::Removed,
+                    error: None,
+                });
+            }
+        }
+    }
+
+    (hosts, results)
+}
+
+/// `None` for no source address or an empty one.
+fn parse_source(source: Option<String>, host: &str) -> Result<Option<IpAddr>, String> {
+    match source.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(source) => source
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("invalid source address `{source}` for host `{host}`")),
+    }
+}
@@ -0,0 +1,154 @@
+use eyre::Context;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{
+    audit::AuditDestination, hosts::new_wake_id, sender, wake::suppressed, AppState,
+    RequestContext, WakeSource,
+};
+use crate::{api::v1::WakeOutcome, config::RelayConfig, retry::Attempts, MagicPacket};
+
+/// Packets for a MAC we sent a packet for this recently are ours coming back (possibly through
+/// another relay), relaying them again would make two relays send them back and forth forever.
+const LOOP_GUARD: Duration = Duration::from_secs(2);
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Receives magic packets over UDP and sends them on to other networks,
+/// for clients that can reach this server but not the network of the host they want to wake.
+pub struct Relay {
+    socket: tokio::net::UdpSocket,
+    config: RelayConfig,
+}
+
+impl Relay {
+    pub async fn bind(config: RelayConfig) -> std::io::Result<Relay> {
+        let socket = tokio::net::UdpSocket::bind(config.listen).await?;
+        Ok(Relay { socket, config })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Relays packets until receiving fails.
+    pub async fn run(self, state: Arc<AppState>) -> eyre::Result<()> {
+        let mut buf = [0; 1500];
+        loop {
+            let (len, source) = self
+                .socket
+                .recv_from(&mut buf)
+                .await
+                .wrap_err("receiving packet to relay")?;
+            let Some(packet) = MagicPacket::parse(&buf[..len]) else {
+                tracing::debug!(%source, len, "ignoring packet that isn't a magic packet");
+                continue;
+            };
+            let mac = packet.mac();
+            if state.config.read_only {
+                tracing::warn!(%source, %mac, "not relaying packet, the server is in read-only mode");
+                continue;
+            }
+            if state.sender.sent_recently(mac, LOOP_GUARD) {
+                tracing::debug!(%source, %mac, "not relaying packet that we just sent ourselves");
+                continue;
+            }
+            if !state.relay_sources.allow(
+                source.ip(),
+                self.config.rate_limit,
+                state.config.limits.relay_sources,
+            ) {
+                tracing::warn!(%source, %mac, "source is over the relay rate limit, dropping packet");
+                continue;
+            }
+
+            let state = state.clone();
+            let destinations = self.config.destinations.clone();
+            tokio::task::spawn_blocking(move || relay(&state, &packet, source, &destinations));
+        }
+    }
+}
+
+/// Sends the packet on like it came, with its password if it has one, unless it's quiet hours.
+fn relay(state: &AppState, packet: &MagicPacket, source: SocketAddr, destinations: &[SocketAddr]) {
+    let mac = packet.mac();
+    let id = new_wake_id();
+    let context = RequestContext {
+        client: Some(source.ip()),
+        principal: None,
+        source: WakeSource::Relay,
+    };
+    if suppressed(state, None, &[mac], &id, &context) {
+        return;
+    }
+    let mut sent_to = Vec::new();
+    for &destination in destinations {
+        let Attempts { result, attempts } = state
+            .config
+            .retry
+            .run(|| state.sender.send(packet, destination, None, None));
+        match result {
+            Ok(_) => {
+                sent_to.push(AuditDestination {
+                    address: destination,
+                    sent: true,
+                });
+                tracing::info!(%source, %mac, %destination, "Relayed magic packet");
+            }
+            Err(e) => {
+                sent_to.push(AuditDestination {
+                    address: destination,
+                    sent: false,
+                });
+                let hint = sender::hint(&e, destination, None);
+                tracing::error!(%source, %mac, %destination, attempts, ?e, ?hint, "failed to relay magic packet")
+            }
+        }
+    }
+
+    let outcome = if sent_to.iter().any(|destination| destination.sent) {
+        WakeOutcome::Sent
+    } else {
+        WakeOutcome::Failed
+    };
+    state.record_wake(mac, &id, None, &context, outcome, sent_to);
+}
+
+/// Allows a number of packets per source in every window, for at most `limits.relay_sources`
+/// sources at once.
+#[derive(Default)]
+pub(super) struct RateLimiter(Mutex<HashMap<IpAddr, (Instant, u32)>>);
+
+impl RateLimiter {
+    fn allow(&self, source: IpAddr, per_window: u32, max_sources: usize) -> bool {
+        let now = Instant::now();
+        let mut sources = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if !sources.contains_key(&source) {
+            // only forget sources when new ones show up, so this can't grow forever
+            sources.retain(|_, (start, _)| now.duration_since(*start) < RATE_LIMIT_WINDOW);
+            if sources.len() >= max_sources {
+                return false;
+            }
+        }
+        let (start, count) = sources.entry(source).or_insert((now, 0));
+        if now.duration_since(*start) >= RATE_LIMIT_WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count <= per_window
+    }
+
+    /// Forgets the sources whose window ran out.
+    pub(super) fn prune(&self) {
+        let mut sources = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        sources.retain(|_, (start, _)| start.elapsed() < RATE_LIMIT_WINDOW);
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
@@ -0,0 +1,143 @@
+//! What a wake of a name would send its packets for, without waking anything.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use std::sync::Arc;
+
+use super::{wake, AppState};
+use crate::{
+    api::v1::{Normalization, NotResolved, Resolution, ResolveSource},
+    discovery::{self, HostEntry},
+    MacAddress,
+};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/resolve/{name}", get(resolve))
+}
+
+/// At most this many similar names are suggested.
+const MAX_CANDIDATES: usize = 10;
+/// How many edits a name may be away from a host's to suggest it.
+const MAX_DISTANCE: usize = 2;
+
+enum Unresolved {
+    NotFound(NotResolved),
+    /// Like a wake would have failed.
+    Failed(StatusCode, String),
+}
+
+async fn resolve(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Response {
+    match tokio::task::spawn_blocking(move || state.resolve(&name)).await {
+        Ok(Ok(resolution)) => Json(resolution).into_response(),
+        Ok(Err(Unresolved::NotFound(not_resolved))) => {
+            (StatusCode::NOT_FOUND, Json(not_resolved)).into_response()
+        }
+        Ok(Err(Unresolved::Failed(status, error))) => (status, error).into_response(),
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn").into_response()
+        }
+    }
+}
+
+impl AppState {
+    /// Finds the host the way a wake does. A name that's part of several discovered names is
+    /// ambiguous, even though a wake takes the first of them. This blocks until it's found.
+    fn resolve(&self, name: &str) -> Result<Resolution, Unresolved> {
+        let found = wake::resolve(self, name);
+        let table = self.discover_hosts().unwrap_or_else(|e| {
+            tracing::warn!(?e, "failed to discover hosts");
+            Vec::new()
+        });
+        let found = match found {
+            Ok(found) => found,
+            Err((StatusCode::NOT_FOUND, error)) => {
+                return Err(Unresolved::NotFound(NotResolved {
+                    error,
+                    candidates: self.candidates(name, &table),
+                }))
+            }
+            Err((status, error)) => return Err(Unresolved::Failed(status, error)),
+        };
+
+        let normalization = if found.name == name {
+            None
+        } else if found.name.eq_ignore_ascii_case(name) {
+            Some(Normalization::IgnoringCase)
+        } else {
+            Some(Normalization::Substring)
+        };
+        if found.source == ResolveSource::Discovered
+            && normalization == Some(Normalization::Substring)
+        {
+            let mut containing = (table.iter())
+                .filter(|entry| entry.name.contains(name))
+                .map(|entry| entry.name.clone())
+                .collect::<Vec<_>>();
+            containing.sort();
+            containing.dedup();
+            if containing.len() > 1 {
+                return Err(Unresolved::NotFound(NotResolved {
+                    error: format!(
+                        "host `{name}` is ambiguous, a wake would wake `{}`",
+                        found.name
+                    ),
+                    candidates: containing,
+                }));
+            }
+        }
+
+        let ip = (found.resolved.as_ref().map(|resolved| resolved.ip))
+            .or_else(|| discovery::find_ip(&table, &found.macs));
+        Ok(Resolution {
+            name: name.to_owned(),
+            mac: found.macs[0].to_string(),
+            macs: found.macs.iter().map(MacAddress::to_string).collect(),
+            ip,
+            source: found.source,
+            matched: found.name,
+            normalization,
+        })
+    }
+
+    /// The names of the configured and discovered hosts that look like `name`: that contain it
+    /// ignoring case, or are a typo or two away from it.
+    fn candidates(&self, name: &str, table: &[HostEntry]) -> Vec<String> {
+        let name = name.to_ascii_lowercase();
+        let mut candidates = (self.registry.all().into_iter())
+            .map(|host| host.name)
+            .chain(table.iter().map(|entry| entry.name.clone()))
+            .filter(|candidate| {
+                let candidate = candidate.to_ascii_lowercase();
+                let first_label = candidate.split('.').next().unwrap_or(&candidate);
+                let distance = distance(first_label, &name);
+                // for short names, that's every other one
+                candidate.contains(&name) || (distance <= MAX_DISTANCE && distance < name.len())
+            })
+            .collect::<Vec<_>>();
+        candidates.sort();
+        candidates.dedup();
+        candidates.truncate(MAX_CANDIDATES);
+        candidates
+    }
+}
+
+/// How many characters have to be inserted, removed or replaced to get from one to the other.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let replaced = previous[j] + usize::from(a != *b);
+            current.push(replaced.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
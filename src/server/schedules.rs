@@ -0,0 +1,406 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::Notify;
+
+use super::{
+    hosts::{find_macs, new_wake_id},
+    state_file::{self, Format},
+    wake::wake_by_name,
+    AppState, ErrorResponse, RequestContext, WakeSource,
+};
+use crate::{
+    api::v1::{Event, ScheduledWake, WakeOutcome},
+    config::Config,
+    discovery::parse_mac_addr,
+    schedule::{Schedule, When},
+};
+
+pub(super) fn read_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/schedules", get(list))
+}
+
+pub(super) fn write_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/schedules", post(create))
+        .route("/schedules/{id}", delete(remove))
+        .route("/schedules/{id}/pause", post(pause))
+        .route("/schedules/{id}/resume", post(resume))
+}
+
+/// The scheduled wakes, saved to the schedules file if there is one.
+pub(super) struct Schedules {
+    path: Option<PathBuf>,
+    entries: RwLock<Vec<ScheduleEntry>>,
+    /// Tells the scheduler to look at the schedules again.
+    changed: Notify,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ScheduleEntry {
+    id: String,
+    #[serde(flatten)]
+    schedule: Schedule,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    paused: bool,
+}
+
+/// The schedules file.
+#[derive(Serialize, Deserialize)]
+struct SchedulesDocument {
+    schedules: Vec<ScheduleEntry>,
+}
+
+const FORMAT: Format = Format {
+    what: "schedules",
+    migrations: &[state_file::versioned],
+};
+
+fn new_schedule_id() -> String {
+    format!("{:08x}", fastrand::u32(..))
+}
+
+impl Schedules {
+    /// Loads the schedules file, or starts with the configured schedules if there is none yet.
+    pub(super) fn load(config: &Config) -> eyre::Result<Schedules> {
+        let document = (config.schedules_file.as_deref())
+            .map(|path| state_file::load::<SchedulesDocument>(path, &FORMAT))
+            .transpose()?
+            .flatten();
+        let entries = match document {
+            Some(document) => {
+                tracing::debug!(schedules = document.schedules.len(), "loaded schedules");
+                document.schedules
+            }
+            None => config
+                .schedules
+                .iter()
+                .map(|schedule| ScheduleEntry {
+                    id: new_schedule_id(),
+                    schedule: schedule.clone(),
+                    paused: false,
+                })
+                .collect(),
+        };
+        Ok(Schedules {
+            path: config.schedules_file.clone(),
+            entries: RwLock::new(entries),
+            changed: Notify::new(),
+        })
+    }
+
+    fn all(&self) -> Vec<ScheduleEntry> {
+        self.entries
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Saves the changed schedules before they're swapped in, so a failed save doesn't change
+    /// anything, then tells the scheduler.
+    fn update<T>(&self, change: impl FnOnce(&mut Vec<ScheduleEntry>) -> T) -> eyre::Result<T> {
+        let mut current = self.entries.write().unwrap_or_else(|e| e.into_inner());
+        let mut entries = current.clone();
+        let result = change(&mut entries);
+        if let Some(path) = &self.path {
+            let document = SchedulesDocument {
+                schedules: entries.clone(),
+            };
+            state_file::save(path, &FORMAT, &document)?;
+        }
+        *current = entries;
+        drop(current);
+        self.changed.notify_one();
+        Ok(result)
+    }
+}
+
+impl ScheduledWake {
+    fn new(entry: ScheduleEntry) -> Self {
+        ScheduledWake {
+            next: (!entry.paused)
+                .then(|| entry.schedule.next_after(Utc::now()))
+                .flatten(),
+            id: entry.id,
+            schedule: entry.schedule,
+            paused: entry.paused,
+        }
+    }
+}
+
+async fn list(State(state): State<Arc<AppState>>) -> Json<Vec<ScheduledWake>> {
+    Json(
+        state
+            .schedules
+            .all()
+            .into_iter()
+            .map(ScheduledWake::new)
+            .collect(),
+    )
+}
+
+async fn create(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Json(schedule): Json<Schedule>,
+) -> Response {
+    if schedule.next_after(Utc::now()).is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("the schedule is never due")),
+        )
+            .into_response();
+    }
+
+    match add(&state, schedule, &context).await {
+        Ok(scheduled) => (StatusCode::CREATED, Json(scheduled)).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Adds a schedule that's due at some point, answering with what went wrong if it couldn't be
+/// saved.
+pub(super) async fn add(
+    state: &Arc<AppState>,
+    schedule: Schedule,
+    context: &RequestContext,
+) -> Result<ScheduledWake, Response> {
+    let entry = ScheduleEntry {
+        id: new_schedule_id(),
+        schedule,
+        paused: false,
+    };
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let entry = entry.clone();
+        move || state.schedules.update(|entries| entries.push(entry))
+    })
+    .await;
+    match result {
+        Ok(Ok(())) => {
+            tracing::info!(id = %entry.id, host = %entry.schedule.host, when = ?entry.schedule.when, client = ?context.client, principal = ?context.principal, "Added schedule");
+            Ok(ScheduledWake::new(entry))
+        }
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to save schedules");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "error").into_response())
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn").into_response())
+        }
+    }
+}
+
+async fn remove(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(id): Path<String>,
+) -> Response {
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let id = id.clone();
+        move || state.schedules.update(|entries| remove_entry(entries, &id))
+    })
+    .await;
+    match result {
+        Ok(Ok(true)) => {
+            tracing::info!(%id, client = ?context.client, principal = ?context.principal, "Removed schedule");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(Ok(false)) => (StatusCode::NOT_FOUND, "schedule not found").into_response(),
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to save schedules");
+            (StatusCode::INTERNAL_SERVER_ERROR, "error").into_response()
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn").into_response()
+        }
+    }
+}
+
+async fn pause(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(id): Path<String>,
+) -> Response {
+    set_paused(&state, &context, id, true).await
+}
+
+async fn resume(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(id): Path<String>,
+) -> Response {
+    set_paused(&state, &context, id, false).await
+}
+
+/// Pauses or resumes the schedule, answering with it. A wake it started already isn't
+/// cancelled.
+async fn set_paused(
+    state: &Arc<AppState>,
+    context: &RequestContext,
+    id: String,
+    paused: bool,
+) -> Response {
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let id = id.clone();
+        move || {
+            state.schedules.update(|entries| {
+                let entry = entries.iter_mut().find(|entry| entry.id == id)?;
+                entry.paused = paused;
+                Some(entry.clone())
+            })
+        }
+    })
+    .await;
+    match result {
+        Ok(Ok(Some(entry))) => {
+            tracing::info!(%id, paused, client = ?context.client, principal = ?context.principal, "Changed schedule");
+            Json(ScheduledWake::new(entry)).into_response()
+        }
+        Ok(Ok(None)) => (StatusCode::NOT_FOUND, "schedule not found").into_response(),
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to save schedules");
+            (StatusCode::INTERNAL_SERVER_ERROR, "error").into_response()
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn").into_response()
+        }
+    }
+}
+
+fn remove_entry(entries: &mut Vec<ScheduleEntry>, id: &str) -> bool {
+    let before = entries.len();
+    entries.retain(|entry| entry.id != id);
+    entries.len() != before
+}
+
+/// Wakes the scheduled hosts when they're due, forever. Wakes are recorded with `scheduled` as
+/// who asked for them, one-shot schedules are removed once they fired. Paused ones are due like
+/// the others, so that they're skipped in the history of their host.
+pub async fn run_scheduler(state: Arc<AppState>) {
+    let mut since = Utc::now();
+    loop {
+        let next = state
+            .schedules
+            .all()
+            .iter()
+            .filter_map(|entry| entry.schedule.next_after(since))
+            .min();
+        if let Some(next) = next {
+            let wait = (next - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = state.schedules.changed.notified() => continue,
+            }
+        } else {
+            state.schedules.changed.notified().await;
+            continue;
+        }
+
+        let now = Utc::now();
+        let due = state
+            .schedules
+            .all()
+            .into_iter()
+            .filter(|entry| entry.schedule.next_after(since).is_some_and(|at| at <= now))
+            .collect::<Vec<_>>();
+        since = now;
+        for entry in due {
+            tokio::spawn(fire(state.clone(), entry));
+        }
+    }
+}
+
+async fn fire(state: Arc<AppState>, entry: ScheduleEntry) {
+    if entry.paused {
+        tracing::info!(id = %entry.id, host = %entry.schedule.host, "Schedule is due, but paused");
+    } else {
+        tracing::info!(id = %entry.id, host = %entry.schedule.host, "Schedule is due");
+    }
+    if let When::At(_) = entry.schedule.when {
+        let result = tokio::task::spawn_blocking({
+            let state = state.clone();
+            let id = entry.id.clone();
+            move || state.schedules.update(|entries| remove_entry(entries, &id))
+        })
+        .await;
+        match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => tracing::error!(?e, "failed to save schedules"),
+            Err(e) => tracing::error!(?e, "join error"),
+        }
+    }
+
+    if entry.paused {
+        skip(&state, &entry).await;
+        return;
+    }
+    state.events.publish(Event::ScheduleFired {
+        schedule: entry.id.clone(),
+        host: entry.schedule.host.clone(),
+    });
+    match wake_by_name(&state, entry.schedule.host.clone(), context(&entry)).await {
+        Ok(summary) => tracing::info!(id = %entry.id, %summary, "Scheduled wake done"),
+        Err(e) => {
+            tracing::error!(id = %entry.id, host = %entry.schedule.host, error = %e, "scheduled wake failed")
+        }
+    }
+}
+
+fn context(entry: &ScheduleEntry) -> RequestContext {
+    RequestContext {
+        principal: Some("scheduled".to_owned()),
+        ..RequestContext::from(WakeSource::Schedule(entry.id.clone()))
+    }
+}
+
+/// Notes in the history of the host that it wasn't woken, because its schedule is paused.
+async fn skip(state: &Arc<AppState>, entry: &ScheduleEntry) {
+    state.events.publish(Event::ScheduleSkipped {
+        schedule: entry.id.clone(),
+        host: entry.schedule.host.clone(),
+    });
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let host = entry.schedule.host.clone();
+        let context = context(entry);
+        move || {
+            // like in a wake, it can be a MAC
+            let (name, macs) = match parse_mac_addr(&host) {
+                Some(mac) => (None, vec![mac]),
+                None => (
+                    Some(host.as_str()),
+                    find_macs(&state, &host)?.unwrap_or_default(),
+                ),
+            };
+            let id = new_wake_id();
+            for mac in macs {
+                state.record_wake(mac, &id, name, &context, WakeOutcome::Paused, Vec::new());
+            }
+            eyre::Ok(())
+        }
+    })
+    .await;
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            tracing::warn!(?e, host = %entry.schedule.host, "failed to find the host of a paused schedule")
+        }
+        Err(e) => tracing::error!(?e, "join error"),
+    }
+}
@@ -0,0 +1,695 @@
+use chrono::Utc;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    api::v1::{SelfTest, SendStats},
+    interfaces, MacAddress, MagicPacket,
+};
+
+/// How long a sent packet is remembered, so relays can recognize it coming back.
+const REMEMBER_SENT: Duration = Duration::from_secs(5);
+
+/// Sends magic packets, which the server does with UDP sockets. Anything else is mostly useful
+/// for tests, like one that fails in a certain way.
+pub trait PacketSender: Send + Sync {
+    /// Sends the packet, only on the interface if there is one, returning the local address it
+    /// was sent from.
+    fn send(
+        &self,
+        packet: &MagicPacket,
+        dest: SocketAddr,
+        interface: Option<&str>,
+    ) -> io::Result<SocketAddr>;
+
+    /// Like [`send`](Self::send), but from a socket bound to the local address. Senders that
+    /// can't do that fail.
+    fn send_from(
+        &self,
+        _packet: &MagicPacket,
+        _dest: SocketAddr,
+        _interface: Option<&str>,
+        source: IpAddr,
+    ) -> io::Result<SocketAddr> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("can't send from {source}"),
+        ))
+    }
+
+    /// Like [`send`](Self::send) (or [`send_from`](Self::send_from) with a source), then waits
+    /// for `wait` and returns the errors the system reported for the datagram after it left, like
+    /// an ICMP port unreachable from a router on the way. Senders that can't tell don't wait and
+    /// return `None` for them.
+    fn send_confirmed(
+        &self,
+        packet: &MagicPacket,
+        dest: SocketAddr,
+        interface: Option<&str>,
+        source: Option<IpAddr>,
+        _wait: Duration,
+    ) -> io::Result<(SocketAddr, Option<Vec<String>>)> {
+        let sent = match source {
+            Some(source) => self.send_from(packet, dest, interface, source)?,
+            None => self.send(packet, dest, interface)?,
+        };
+        Ok((sent, None))
+    }
+
+    /// The local address packets on the interface are sent from, if that's known before sending.
+    fn local_addr(&self, _interface: Option<&str>) -> Option<SocketAddr> {
+        None
+    }
+
+    /// Sends the packet in an Ethernet frame to the broadcast MAC on the interface, instead of
+    /// in a UDP datagram. Senders that can't do that fail.
+    fn send_frame(&self, _packet: &MagicPacket, interface: &str) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("can't send raw frames on `{interface}`"),
+        ))
+    }
+}
+
+/// How sending a packet went.
+pub(super) struct Sent {
+    /// The local address it was sent from.
+    pub(super) source: SocketAddr,
+    /// The errors reported for it after it left, `None` if they weren't waited for.
+    pub(super) reported: Option<Vec<String>>,
+}
+
+/// Sends the packets, remembering which MACs it sent packets for and what was sent to each
+/// destination.
+pub(super) struct Sender {
+    inner: Box<dyn PacketSender>,
+    /// How long the errors for packets to unicast addresses are waited for, if they are.
+    confirm: Option<Duration>,
+    /// When a packet for a MAC was last sent.
+    recently_sent: Mutex<HashMap<MacAddress, Instant>>,
+    /// By the destination, since the server started.
+    stats: Mutex<BTreeMap<SocketAddr, SendStats>>,
+}
+
+impl Sender {
+    pub(super) fn new(inner: Box<dyn PacketSender>, confirm: Option<Duration>) -> Self {
+        Self {
+            inner,
+            confirm,
+            recently_sent: Mutex::new(HashMap::new()),
+            stats: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// What was sent to each destination, ordered by it.
+    pub(super) fn stats(&self) -> Vec<SendStats> {
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        stats.values().cloned().collect()
+    }
+
+    /// Packets from a source address of their own are sent from a socket of their own, that
+    /// one's port is only known once it's sent.
+    pub(super) fn local_addr(
+        &self,
+        interface: Option<&str>,
+        source: Option<IpAddr>,
+    ) -> Option<SocketAddr> {
+        match source {
+            Some(_) => None,
+            None => self.inner.local_addr(interface),
+        }
+    }
+
+    /// Whether a packet for `mac` was sent in the last `within` (which is at most a few seconds).
+    pub(super) fn sent_recently(&self, mac: MacAddress, within: Duration) -> bool {
+        let recently_sent = self.recently_sent.lock().unwrap_or_else(|e| e.into_inner());
+        recently_sent
+            .get(&mac)
+            .is_some_and(|sent| sent.elapsed() < within)
+    }
+
+    /// Forgets the packets that weren't sent recently.
+    pub(super) fn prune(&self) {
+        let mut recently_sent = self.recently_sent.lock().unwrap_or_else(|e| e.into_inner());
+        recently_sent.retain(|_, sent| sent.elapsed() < REMEMBER_SENT);
+    }
+
+    pub(super) fn send(
+        &self,
+        packet: &MagicPacket,
+        dest: SocketAddr,
+        interface: Option<&str>,
+        source: Option<IpAddr>,
+    ) -> io::Result<Sent> {
+        // remembered before it's sent, it might come back before sending even returns
+        {
+            let mut recently_sent = self.recently_sent.lock().unwrap_or_else(|e| e.into_inner());
+            recently_sent.retain(|_, sent| sent.elapsed() < REMEMBER_SENT);
+            recently_sent.insert(packet.mac(), Instant::now());
+        }
+        let result =
+            match (self.confirm.filter(|_| is_unicast(dest)), source) {
+                (Some(wait), source) => (self.inner)
+                    .send_confirmed(packet, dest, interface, source, wait)
+                    .map(|(source, reported)| Sent { source, reported }),
+                (None, Some(source)) => (self.inner)
+                    .send_from(packet, dest, interface, source)
+                    .map(|source| Sent {
+                        source,
+                        reported: None,
+                    }),
+                (None, None) => self.inner.send(packet, dest, interface).map(|source| Sent {
+                    source,
+                    reported: None,
+                }),
+            };
+
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let stats = stats.entry(dest).or_insert_with(|| SendStats {
+            destination: dest,
+            datagrams: 0,
+            bytes: 0,
+            failures: 0,
+            last_sent: None,
+            last_source: None,
+            last_error: None,
+            last_error_at: None,
+        });
+        match &result {
+            Ok(Sent { source, reported }) => {
+                let bytes = packet.payload().len();
+                tracing::debug!(%dest, %source, ?interface, bytes, "Sent magic packet");
+                for error in reported.iter().flatten() {
+                    tracing::warn!(%dest, %source, error, "an error was reported for a sent magic packet");
+                }
+                stats.datagrams += 1;
+                stats.bytes += bytes as u64;
+                stats.last_sent = Some(Utc::now());
+                stats.last_source = Some(*source);
+            }
+            Err(e) => {
+                stats.failures += 1;
+                stats.last_error = Some(e.to_string());
+                stats.last_error_at = Some(Utc::now());
+            }
+        }
+        result
+    }
+
+    /// Sends the packet in a raw frame, see [`PacketSender::send_frame`]. Frames have no
+    /// destination address, so they're not in the stats.
+    pub(super) fn send_frame(&self, packet: &MagicPacket, interface: &str) -> io::Result<()> {
+        let result = self.inner.send_frame(packet, interface);
+        if result.is_ok() {
+            let bytes = packet.payload().len();
+            tracing::debug!(interface, bytes, "Sent magic packet in a raw frame");
+        }
+        result
+    }
+}
+
+/// The UDP sockets magic packets are sent from, one for each interface sending is restricted to
+/// and source address packets are sent from, and one for when neither is.
+///
+/// Each is bound once and reused, but thrown away and bound again when a send fails,
+/// so a socket that broke (e.g. because the interface went away) doesn't break all future wakes.
+pub(super) struct UdpSender {
+    bind_addr: SocketAddr,
+    sockets: Mutex<HashMap<SocketKey, UdpSocket>>,
+}
+
+/// The interface a socket is restricted to and the address it's bound to.
+type SocketKey = (Option<String>, Option<IpAddr>);
+
+impl UdpSender {
+    pub(super) fn new(bind_addr: SocketAddr) -> Self {
+        let mut sockets = HashMap::new();
+        match crate::bind_broadcast_socket(bind_addr) {
+            Ok(socket) => {
+                sockets.insert((None, None), socket);
+            }
+            Err(e) => {
+                tracing::warn!(?e, %bind_addr, "failed to bind send socket, will retry on first send");
+            }
+        }
+        Self {
+            bind_addr,
+            sockets: Mutex::new(sockets),
+        }
+    }
+}
+
+impl UdpSender {
+    fn send_on(
+        &self,
+        packet: &MagicPacket,
+        dest: SocketAddr,
+        interface: Option<&str>,
+        source: Option<IpAddr>,
+    ) -> io::Result<SocketAddr> {
+        let mut sockets = self.sockets.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (interface.map(str::to_owned), source);
+        let bind_addr = match source {
+            Some(source) => SocketAddr::new(source, 0),
+            None => self.bind_addr,
+        };
+
+        if let Some(existing) = sockets.get(&key) {
+            match crate::send_magic_packet(existing, packet, dest) {
+                Ok(()) => return existing.local_addr(),
+                Err(e) => {
+                    tracing::warn!(?e, %bind_addr, ?interface, "send failed, rebinding socket");
+                    sockets.remove(&key);
+                }
+            }
+        }
+
+        let new = bind(bind_addr, interface).map_err(|e| match source {
+            Some(source) => io::Error::new(e.kind(), format!("failed to bind to {source}: {e}")),
+            None => e,
+        })?;
+        let result = crate::send_magic_packet(&new, packet, dest).and_then(|()| new.local_addr());
+        sockets.insert(key, new);
+        result
+    }
+}
+
+impl PacketSender for UdpSender {
+    fn send(
+        &self,
+        packet: &MagicPacket,
+        dest: SocketAddr,
+        interface: Option<&str>,
+    ) -> io::Result<SocketAddr> {
+        self.send_on(packet, dest, interface, None)
+    }
+
+    fn send_from(
+        &self,
+        packet: &MagicPacket,
+        dest: SocketAddr,
+        interface: Option<&str>,
+        source: IpAddr,
+    ) -> io::Result<SocketAddr> {
+        self.send_on(packet, dest, interface, Some(source))
+    }
+
+    /// From a socket of its own each time, so everything in its error queue is about this
+    /// packet.
+    #[cfg(target_os = "linux")]
+    fn send_confirmed(
+        &self,
+        packet: &MagicPacket,
+        dest: SocketAddr,
+        interface: Option<&str>,
+        source: Option<IpAddr>,
+        wait: Duration,
+    ) -> io::Result<(SocketAddr, Option<Vec<String>>)> {
+        let bind_addr = source.map_or(self.bind_addr, |source| SocketAddr::new(source, 0));
+        let socket = bind(bind_addr, interface).map_err(|e| match source {
+            Some(source) => io::Error::new(e.kind(), format!("failed to bind to {source}: {e}")),
+            None => e,
+        })?;
+        keep_errors(&socket)?;
+        crate::send_magic_packet(&socket, packet, dest)?;
+        let local = socket.local_addr()?;
+        std::thread::sleep(wait);
+        Ok((local, Some(queued_errors(&socket)?)))
+    }
+
+    /// The local address of the socket for the interface, if it's currently bound.
+    fn local_addr(&self, interface: Option<&str>) -> Option<SocketAddr> {
+        let sockets = self.sockets.lock().unwrap_or_else(|e| e.into_inner());
+        sockets
+            .get(&(interface.map(str::to_owned), None))?
+            .local_addr()
+            .ok()
+    }
+
+    /// From a packet socket of its own each time, raw frames are rare.
+    fn send_frame(&self, packet: &MagicPacket, interface: &str) -> io::Result<()> {
+        send_frame(packet, interface)
+    }
+}
+
+/// A socket to send magic packets from, restricted to the interface if there is one.
+fn bind(bind_addr: SocketAddr, interface: Option<&str>) -> io::Result<UdpSocket> {
+    let socket = crate::bind_broadcast_socket(bind_addr)?;
+    if let Some(interface) = interface {
+        bind_to_interface(&socket, interface)?;
+    }
+    Ok(socket)
+}
+
+/// The local address packets to `dest` would be sent from by a [`UdpSender`] bound to
+/// `bind_addr` (or the source address), as the system routes them right now. Nothing is sent.
+pub(super) fn route(
+    bind_addr: SocketAddr,
+    dest: SocketAddr,
+    interface: Option<&str>,
+    source: Option<IpAddr>,
+) -> io::Result<SocketAddr> {
+    let bind_addr = source.map_or(bind_addr, |source| SocketAddr::new(source, 0));
+    let socket = bind(bind_addr, interface)?;
+    socket.connect(dest)?;
+    socket.local_addr()
+}
+
+/// Whether packets to `dest` go to a single host, only those can be confirmed. Where the
+/// interfaces can't be listed, a directed broadcast can't be told apart, so it's taken for one.
+fn is_unicast(dest: SocketAddr) -> bool {
+    // the send sockets are IPv4 ones
+    let IpAddr::V4(ip) = dest.ip() else {
+        return false;
+    };
+    if ip.is_broadcast() || ip.is_multicast() || ip.is_unspecified() {
+        return false;
+    }
+    match interfaces::list() {
+        Ok(interfaces) => !(interfaces.iter())
+            .flat_map(|interface| &interface.addresses)
+            .any(|address| address.broadcast() == Some(ip)),
+        Err(_) => false,
+    }
+}
+
+/// What to do about the usual reasons sending fails, `None` for anything else.
+pub(super) fn hint(e: &io::Error, dest: SocketAddr, source: Option<SocketAddr>) -> Option<String> {
+    match e.raw_os_error()? {
+        libc::EPERM | libc::EACCES => Some(
+            "broadcast not permitted, check the container's NET_RAW capability and network mode"
+                .to_owned(),
+        ),
+        libc::ENETUNREACH => Some(format!(
+            "no route to {dest}, check the broadcast address and interface"
+        )),
+        libc::EADDRNOTAVAIL => Some(match source {
+            Some(source) => format!("source address {} is not on any interface", source.ip()),
+            None => "the source address is not on any interface".to_owned(),
+        }),
+        _ => None,
+    }
+}
+
+/// Binds a socket like [`UdpSender`] does, allows broadcasts on it and sends an empty datagram
+/// to `dest`, which fails like a magic packet would without waking anything. Logged either way.
+pub(super) fn self_test(bind_addr: SocketAddr, dest: SocketAddr) -> SelfTest {
+    let result = UdpSocket::bind(bind_addr)
+        .map_err(|e| {
+            (
+                format!("failed to bind the send socket to {bind_addr}: {e}"),
+                e,
+            )
+        })
+        .and_then(|socket| {
+            socket
+                .set_broadcast(true)
+                .map_err(|e| (format!("failed to allow broadcasts: {e}"), e))?;
+            socket
+                .send_to(&[], dest)
+                .map_err(|e| (format!("failed to send to {dest}: {e}"), e))
+        });
+    let (error, hint) = match result {
+        Ok(_) => {
+            tracing::info!(%dest, "Send self-test passed");
+            (None, None)
+        }
+        Err((error, e)) => {
+            let hint = self_test_hint(&e, dest);
+            tracing::error!(%dest, %error, ?hint, "send self-test failed, wakes will probably fail too");
+            (Some(error), hint)
+        }
+    };
+    SelfTest {
+        destination: dest,
+        at: Utc::now(),
+        passed: error.is_none(),
+        error,
+        hint,
+    }
+}
+
+/// Like [`hint`], with more on what to do when it's not permitted, nothing could be sent yet then.
+fn self_test_hint(e: &io::Error, dest: SocketAddr) -> Option<String> {
+    match e.raw_os_error()? {
+        libc::EPERM | libc::EACCES => Some(
+            "broadcasts aren't permitted here. in a container, run it with host networking \
+             (`--network host`) or with the NET_RAW capability, and check the firewall rules and \
+             sysctls (like `net.ipv4.conf.all.bc_forwarding` for a directed broadcast)"
+                .to_owned(),
+        ),
+        _ => hint(e, dest, None),
+    }
+}
+
+/// Whether sending failed because there's no network right now, which the send queue waits out.
+pub(super) fn is_network_down(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(libc::ENETUNREACH | libc::ENETDOWN | libc::ENODEV)
+    )
+}
+
+/// Fails with a message naming the interface if there's no interface with that name.
+/// Where interfaces can't be listed that can't be told, sending fails anyway then.
+pub(super) fn check_interface(interface: &str) -> Result<(), String> {
+    match interfaces::find(interface) {
+        Ok(None) => Err(format!("no network interface named `{interface}`")),
+        Ok(Some(_)) | Err(_) => Ok(()),
+    }
+}
+
+/// Fails with a message naming the address if no interface has it, packets can't be sent from
+/// it then. Where interfaces can't be listed that can't be told, binding to it fails anyway then.
+pub(super) fn check_source(source: IpAddr) -> Result<(), String> {
+    match interfaces::list() {
+        Ok(interfaces) if !interfaces.iter().any(|interface| interface.has_ip(source)) => Err(
+            format!("no network interface has the source address {source}"),
+        ),
+        Ok(_) | Err(_) => Ok(()),
+    }
+}
+
+/// Makes the socket send only on the interface, with `SO_BINDTODEVICE`.
+#[cfg(target_os = "linux")]
+fn bind_to_interface(socket: &UdpSocket, interface: &str) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    check_interface(interface).map_err(|e| io::Error::new(io::ErrorKind::NotFound, e))?;
+    // SAFETY: the option value is the name, with its length
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            interface.as_ptr().cast(),
+            interface.len() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        let e = io::Error::last_os_error();
+        return Err(io::Error::new(
+            e.kind(),
+            format!("failed to restrict sending to interface `{interface}`: {e}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Makes the system keep the errors reported for what's sent from the socket in its error
+/// queue, with `IP_RECVERR`, like the ICMP errors routers send back.
+#[cfg(target_os = "linux")]
+fn keep_errors(socket: &UdpSocket) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let on: libc::c_int = 1;
+    // SAFETY: the option value is an int, with its size
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_IP,
+            libc::IP_RECVERR,
+            (&on as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Everything in the socket's error queue right now, without waiting for more.
+#[cfg(target_os = "linux")]
+fn queued_errors(socket: &UdpSocket) -> io::Result<Vec<String>> {
+    use std::{mem, net::Ipv4Addr, os::fd::AsRawFd};
+
+    let mut errors = Vec::new();
+    loop {
+        // the packet the error is about comes back too, it's known already
+        let mut data = [0u8; 128];
+        // u64s for the alignment the control messages need
+        let mut control = [0u64; 64];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+        // SAFETY: msghdr is plain old data
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+        // SAFETY: the buffers are valid for the lengths in msg
+        let received = unsafe {
+            libc::recvmsg(
+                socket.as_raw_fd(),
+                &mut msg,
+                libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT,
+            )
+        };
+        if received < 0 {
+            let e = io::Error::last_os_error();
+            return match e.kind() {
+                io::ErrorKind::WouldBlock => Ok(errors),
+                _ => Err(e),
+            };
+        }
+        let with_offender =
+            mem::size_of::<libc::sock_extended_err>() + mem::size_of::<libc::sockaddr_in>();
+        // SAFETY: recvmsg filled in the control messages within msg_controllen, and each is only
+        // read as far as its length says
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_IP && (*cmsg).cmsg_type == libc::IP_RECVERR {
+                    let err = libc::CMSG_DATA(cmsg) as *const libc::sock_extended_err;
+                    let offender = ((*cmsg).cmsg_len as usize
+                        >= libc::CMSG_LEN(with_offender as u32) as usize)
+                        .then(|| {
+                            let offender = libc::SO_EE_OFFENDER(err) as *const libc::sockaddr_in;
+                            offender.read_unaligned()
+                        })
+                        .filter(|offender| {
+                            offender.sin_family == libc::AF_INET as libc::sa_family_t
+                        })
+                        .map(|offender| Ipv4Addr::from(u32::from_be(offender.sin_addr.s_addr)));
+                    errors.push(reported_error(&err.read_unaligned(), offender));
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+    }
+}
+
+/// Like `port unreachable (from 203.0.113.1)`, with the router (or host) that said so.
+#[cfg(target_os = "linux")]
+fn reported_error(err: &libc::sock_extended_err, offender: Option<std::net::Ipv4Addr>) -> String {
+    const ICMP: u8 = libc::SO_EE_ORIGIN_ICMP;
+    // 3 is destination unreachable, 11 time exceeded
+    let icmp = match (err.ee_origin, err.ee_type, err.ee_code) {
+        (ICMP, 3, 0) => Some("network unreachable"),
+        (ICMP, 3, 1) => Some("host unreachable"),
+        (ICMP, 3, 3) => Some("port unreachable"),
+        (ICMP, 3, 4) => Some("fragmentation needed"),
+        (ICMP, 3, 9 | 10 | 13) => Some("administratively prohibited"),
+        (ICMP, 11, _) => Some("time to live exceeded"),
+        _ => None,
+    };
+    let message = icmp.map_or_else(
+        || io::Error::from_raw_os_error(err.ee_errno as i32).to_string(),
+        str::to_owned,
+    );
+    match offender {
+        Some(offender) if !offender.is_unspecified() => format!("{message} (from {offender})"),
+        _ => message,
+    }
+}
+
+/// The EtherType of magic packets in raw frames.
+#[cfg(target_os = "linux")]
+const ETH_P_WOL: u16 = 0x0842;
+
+/// Sends the packet in an Ethernet frame from the interface's MAC to the broadcast MAC.
+#[cfg(target_os = "linux")]
+fn send_frame(packet: &MagicPacket, interface: &str) -> io::Result<()> {
+    use std::{
+        mem,
+        os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    };
+
+    let Some(found) = interfaces::find(interface)? else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no network interface named `{interface}`"),
+        ));
+    };
+    // SAFETY: plain socket(2), the result is checked before it's owned
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_PACKET,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            i32::from(ETH_P_WOL.to_be()),
+        )
+    };
+    if fd < 0 {
+        let e = io::Error::last_os_error();
+        return Err(match e.raw_os_error() {
+            Some(libc::EPERM | libc::EACCES) => {
+                io::Error::new(e.kind(), format!("raw frames need CAP_NET_RAW: {e}"))
+            }
+            _ => e,
+        });
+    }
+    // SAFETY: the fd was just created and isn't owned by anything else
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut frame = vec![0xff; 6];
+    frame.extend_from_slice(&found.mac.map_or([0; 6], |mac| mac.0));
+    frame.extend_from_slice(&ETH_P_WOL.to_be_bytes());
+    frame.extend_from_slice(packet.payload());
+
+    // SAFETY: sockaddr_ll is plain old data
+    let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as u16;
+    addr.sll_protocol = ETH_P_WOL.to_be();
+    addr.sll_ifindex = found.index as i32;
+    addr.sll_halen = 6;
+    addr.sll_addr[..6].copy_from_slice(&[0xff; 6]);
+    // SAFETY: the buffer and address are valid for their given lengths
+    let sent = unsafe {
+        libc::sendto(
+            socket.as_raw_fd(),
+            frame.as_ptr().cast(),
+            frame.len(),
+            0,
+            (&addr as *const libc::sockaddr_ll).cast(),
+            mem::size_of::<libc::sockaddr_ll>() as u32,
+        )
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_frame(_packet: &MagicPacket, interface: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("can't send raw frames on `{interface}`, that only works on Linux"),
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_to_interface(_socket: &UdpSocket, interface: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("can't restrict sending to interface `{interface}`, that only works on Linux"),
+    ))
+}
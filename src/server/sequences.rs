@@ -0,0 +1,326 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use std::{
+    collections::VecDeque,
+    net::{SocketAddr, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{
+    hosts::find_macs,
+    wait::{wait_until_online, PROBE_INTERVAL},
+    wake::wake_by_name,
+    AppState, RequestContext, WakeSource,
+};
+use crate::{
+    api::v1::{JobStatus, SequenceJob, SequenceRequest, StepState, StepStatus},
+    config::{SequenceStep, WakeSequence},
+    discovery,
+    verify::{self, ProbeError, Strategy},
+    MacAddress,
+};
+
+pub(super) fn read_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/jobs", get(list))
+        .route("/jobs/{id}", get(job))
+}
+
+pub(super) fn write_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/wake-sequence/{name}", post(start))
+}
+
+/// The sequences that were started, oldest first. Jobs that are done are forgotten once there
+/// are more than `limits.jobs`.
+pub(super) struct Jobs {
+    limit: usize,
+    jobs: Mutex<VecDeque<SequenceJob>>,
+}
+
+impl Jobs {
+    pub(super) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            jobs: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn add(&self, job: SequenceJob) {
+        self.jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(job);
+        self.prune();
+    }
+
+    /// Forgets the oldest jobs that are done, beyond the limit.
+    pub(super) fn prune(&self) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        while jobs.len() > self.limit {
+            match jobs.iter().position(|job| job.status != JobStatus::Running) {
+                Some(done) => jobs.remove(done),
+                None => break,
+            };
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    fn get(&self, id: &str) -> Option<SequenceJob> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    fn all(&self) -> Vec<SequenceJob> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter().cloned().collect()
+    }
+
+    fn update(&self, id: &str, change: impl FnOnce(&mut SequenceJob)) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+            change(job);
+        }
+    }
+
+    fn update_step(&self, id: &str, step: usize, change: impl FnOnce(&mut StepStatus)) {
+        self.update(id, |job| change(&mut job.steps[step]));
+    }
+}
+
+async fn list(State(state): State<Arc<AppState>>) -> Json<Vec<SequenceJob>> {
+    Json(state.jobs.all())
+}
+
+async fn job(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Response {
+    match state.jobs.get(&id) {
+        Some(job) => Json(job).into_response(),
+        None => (StatusCode::NOT_FOUND, "job not found").into_response(),
+    }
+}
+
+/// Starts the sequence in the background, the job it answers with says how it's going.
+async fn start(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(name): Path<String>,
+    request: Option<Json<SequenceRequest>>,
+) -> Response {
+    let Some(sequence) = state
+        .config
+        .sequences
+        .iter()
+        .find(|sequence| sequence.name == name)
+        .cloned()
+    else {
+        return (StatusCode::NOT_FOUND, "sequence not found").into_response();
+    };
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+
+    let job = SequenceJob {
+        id: format!("{:08x}", fastrand::u32(..)),
+        sequence: sequence.name.clone(),
+        status: JobStatus::Running,
+        current_step: None,
+        continue_on_failure: request
+            .continue_on_failure
+            .unwrap_or(sequence.continue_on_failure),
+        started: Utc::now(),
+        finished: None,
+        steps: sequence
+            .steps
+            .iter()
+            .map(|step| StepStatus {
+                host: step.host.clone(),
+                state: StepState::Pending,
+                started: None,
+                finished: None,
+                error: None,
+            })
+            .collect(),
+    };
+    tracing::info!(id = %job.id, sequence = %name, client = ?context.client, principal = ?context.principal, "Starting wake sequence");
+    let context = RequestContext {
+        source: WakeSource::Sequence(sequence.name.clone()),
+        ..context
+    };
+    state.jobs.add(job.clone());
+    tokio::spawn(run(
+        state.clone(),
+        job.id.clone(),
+        sequence,
+        job.continue_on_failure,
+        context,
+    ));
+    (StatusCode::ACCEPTED, Json(job)).into_response()
+}
+
+async fn run(
+    state: Arc<AppState>,
+    id: String,
+    sequence: WakeSequence,
+    continue_on_failure: bool,
+    context: RequestContext,
+) {
+    let mut failed = false;
+    for (index, step) in sequence.steps.iter().enumerate() {
+        if failed && !continue_on_failure {
+            state
+                .jobs
+                .update_step(&id, index, |status| status.state = StepState::Skipped);
+            continue;
+        }
+        state.jobs.update(&id, |job| {
+            job.current_step = Some(index);
+            job.steps[index].state = StepState::Waking;
+            job.steps[index].started = Some(Utc::now());
+        });
+
+        let result = run_step(&state, &id, index, step, &context).await;
+        if let Err(error) = &result {
+            tracing::warn!(%id, sequence = %sequence.name, host = %step.host, %error, "wake sequence step failed");
+            failed = true;
+        }
+        state.jobs.update_step(&id, index, |status| {
+            status.finished = Some(Utc::now());
+            match result {
+                Ok(()) => status.state = StepState::Ready,
+                Err(error) => {
+                    status.state = StepState::Failed;
+                    status.error = Some(error);
+                }
+            }
+        });
+    }
+
+    let status = if failed {
+        JobStatus::Failed
+    } else {
+        JobStatus::Succeeded
+    };
+    tracing::info!(%id, sequence = %sequence.name, ?status, "Wake sequence done");
+    state.jobs.update(&id, |job| {
+        job.status = status;
+        job.current_step = None;
+        job.finished = Some(Utc::now());
+    });
+}
+
+/// Wakes the host of the step and waits for it to be ready, all within the step's timeout.
+async fn run_step(
+    state: &Arc<AppState>,
+    id: &str,
+    index: usize,
+    step: &SequenceStep,
+    context: &RequestContext,
+) -> Result<(), String> {
+    let timeout = Duration::from_secs(step.timeout);
+    let woken_and_ready = async {
+        wake_by_name(state, step.host.clone(), context.clone()).await?;
+        state
+            .jobs
+            .update_step(id, index, |status| status.state = StepState::Waiting);
+        wait_until_ready(state, step, timeout).await
+    };
+    tokio::time::timeout(timeout, woken_and_ready)
+        .await
+        .unwrap_or_else(|_| Err(format!("not ready after {timeout:?}")))
+}
+
+async fn wait_until_ready(
+    state: &Arc<AppState>,
+    step: &SequenceStep,
+    timeout: Duration,
+) -> Result<(), String> {
+    let Some(strategy) = step.ready else {
+        let macs = host_macs(state, &step.host).await?;
+        return match wait_until_online(state, macs, timeout).await {
+            Some(_) => Ok(()),
+            None => Err(format!("not online after {timeout:?}")),
+        };
+    };
+    loop {
+        let result = tokio::task::spawn_blocking({
+            let state = state.clone();
+            let host = step.host.clone();
+            move || check_ready(&state, &host, strategy)
+        })
+        .await;
+        match result {
+            Ok(Ok(true)) => return Ok(()),
+            Ok(Ok(false)) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(e) => {
+                tracing::error!(?e, "join error");
+                return Err("failed to spawn".to_owned());
+            }
+        }
+        tokio::time::sleep(PROBE_INTERVAL).await;
+    }
+}
+
+async fn host_macs(state: &Arc<AppState>, host: &str) -> Result<Vec<MacAddress>, String> {
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let host = host.to_owned();
+        move || find_macs(&state, &host)
+    })
+    .await;
+    match result {
+        Ok(Ok(Some(macs))) => Ok(macs),
+        Ok(Ok(None)) => Err(format!("host `{host}` not found")),
+        Ok(Err(e)) => Err(format!("{e:#}")),
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            Err("failed to spawn".to_owned())
+        }
+    }
+}
+
+/// Whether the host is at an address the neighbor table knows and answers there. Unlike when
+/// checking whether a host is up, a port only counts once it accepts connections.
+pub(super) fn check_ready(
+    state: &AppState,
+    host: &str,
+    strategy: Strategy,
+) -> Result<bool, String> {
+    let macs = find_macs(state, host)
+        .map_err(|e| format!("{e:#}"))?
+        .ok_or_else(|| format!("host `{host}` not found"))?;
+    let table = state.discover().map_err(|e| format!("{e:#}"))?;
+    let Some(ip) = discovery::find_ip(&table, &macs) else {
+        return Ok(false);
+    };
+    let timeout = state.config.verify_timeout;
+    let answered = match strategy {
+        Strategy::Tcp(port) => {
+            Ok(TcpStream::connect_timeout(&SocketAddr::new(ip, port), timeout).is_ok())
+        }
+        strategy => verify::probe(strategy, ip, timeout),
+    };
+    match answered {
+        Ok(true) => {
+            if let Some((host, seconds)) = state.stats.online(&macs) {
+                state.metrics.online(&host, seconds);
+            }
+            Ok(true)
+        }
+        Ok(false) => Ok(false),
+        Err(ProbeError::Unavailable(reason)) => {
+            Err(format!("can't check with {strategy}: {reason}"))
+        }
+        Err(ProbeError::Io(e)) => {
+            tracing::debug!(?e, %host, %ip, %strategy, "readiness probe failed");
+            Ok(false)
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! `GET /api/v1/config`, the settings the server runs with and where each of them came from.
+
+use axum::{extract::State, routing::get, Json, Router};
+use std::sync::Arc;
+
+use super::AppState;
+use crate::api::v1::EffectiveConfig;
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/config", get(effective))
+}
+
+async fn effective(State(state): State<Arc<AppState>>) -> Json<EffectiveConfig> {
+    Json(state.config.effective())
+}
@@ -0,0 +1,207 @@
+//! Waking hosts in remote sites by asking the wakeonlan server there, or by running a command
+//! there over ssh.
+
+use axum::http::StatusCode;
+use eyre::Context;
+use std::{process::Stdio, time::Duration};
+use tokio::process::Command;
+
+use crate::{
+    api::v1::{ErrorResponse, RelayFailure, WakeRequest, WakeResponse},
+    config::{RemoteSite, Site, SshSite},
+    MacAddress,
+};
+
+/// How the hosts of a site are woken from there.
+#[derive(Clone, Copy)]
+pub(super) enum Relay<'a> {
+    Server(&'a RemoteSite),
+    Ssh(&'a SshSite),
+}
+
+impl<'a> Relay<'a> {
+    /// `None` for a site that gets its packets from here.
+    pub(super) fn of(site: &'a Site) -> Option<Self> {
+        (site.ssh.as_ref().map(Relay::Ssh)).or(site.remote.as_ref().map(Relay::Server))
+    }
+}
+
+/// How long the server of a remote site gets to answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Longer errors that aren't JSON are cut off.
+const MAX_ERROR: usize = 200;
+
+/// Why the server of a remote site didn't wake the host.
+pub(super) enum RelayError {
+    /// It answered, with an error of its own.
+    Rejected { status: StatusCode, error: String },
+    /// It couldn't be reached, or didn't answer like a wakeonlan server.
+    Unreachable(eyre::Report),
+    /// ssh couldn't log in there, with what it said.
+    SshAuth(String),
+    /// ssh didn't finish within the site's `timeout`.
+    SshTimeout(Duration),
+    /// The command there failed, with its exit code (`None` if it was killed) and what it
+    /// printed.
+    SshExit { code: Option<i32>, output: String },
+}
+
+impl RelayError {
+    pub(super) fn failure(&self) -> RelayFailure {
+        match self {
+            RelayError::Rejected { .. } => RelayFailure::Rejected,
+            RelayError::Unreachable(_) => RelayFailure::Unreachable,
+            RelayError::SshAuth(_) => RelayFailure::SshAuth,
+            RelayError::SshTimeout(_) => RelayFailure::SshTimeout,
+            RelayError::SshExit { .. } => RelayFailure::SshExit,
+        }
+    }
+}
+
+/// Asks the server of the site to wake the host (or MAC), forwarding what the wake asked for.
+/// This blocks until it answered.
+pub(super) fn wake(
+    client: &reqwest::Client,
+    remote: &RemoteSite,
+    request: &WakeRequest,
+) -> Result<WakeResponse, RelayError> {
+    tokio::runtime::Handle::current().block_on(ask(client, remote, request))
+}
+
+async fn ask(
+    client: &reqwest::Client,
+    remote: &RemoteSite,
+    request: &WakeRequest,
+) -> Result<WakeResponse, RelayError> {
+    let url = format!("{}/api/v1/wake", remote.url.trim_end_matches('/'));
+    let mut post = client.post(url).json(request).timeout(TIMEOUT);
+    if let Some(token) = &remote.token {
+        post = post.bearer_auth(token.as_str());
+    }
+    let response = post
+        .send()
+        .await
+        .wrap_err("sending request")
+        .map_err(RelayError::Unreachable)?;
+    let status = response.status();
+    let body = response
+        .bytes()
+        .await
+        .wrap_err("reading response")
+        .map_err(RelayError::Unreachable)?;
+
+    if status.is_success() {
+        return serde_json::from_slice(&body)
+            .wrap_err("invalid response")
+            .map_err(RelayError::Unreachable);
+    }
+    let status = StatusCode::from_u16(status.as_u16())
+        .map_err(|_| RelayError::Unreachable(eyre::eyre!("invalid status {status}")))?;
+    let error = match serde_json::from_slice::<ErrorResponse>(&body) {
+        Ok(ErrorResponse { error, .. }) => error,
+        Err(_) => {
+            let text = String::from_utf8_lossy(&body);
+            if text.trim().is_empty() {
+                format!("status {status}")
+            } else {
+                cut(&text)
+            }
+        }
+    };
+    Err(RelayError::Rejected { status, error })
+}
+
+fn cut(text: &str) -> String {
+    match text.char_indices().nth(MAX_ERROR) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_owned(),
+    }
+}
+
+/// Runs the site's command for each of the MACs over ssh, one after the other, unless it's a dry
+/// run. The response has no destinations, the machine there knows where it sent the packets.
+/// This blocks until they're done.
+pub(super) fn wake_ssh(
+    ssh: &SshSite,
+    request: &WakeRequest,
+    macs: &[MacAddress],
+) -> Result<WakeResponse, RelayError> {
+    if !request.dry_run {
+        let runtime = tokio::runtime::Handle::current();
+        for mac in macs {
+            let output = runtime.block_on(run_ssh(ssh, *mac))?;
+            tracing::info!(host = %ssh.host, %mac, %output, "Ran wake command over ssh");
+        }
+    }
+    Ok(WakeResponse {
+        host: request.host.clone(),
+        mac: macs[0].to_string(),
+        macs: macs.iter().map(MacAddress::to_string).collect(),
+        dry_run: request.dry_run,
+        ..Default::default()
+    })
+}
+
+/// Only the MAC ends up in the command, which is only hex digits and colons.
+async fn run_ssh(ssh: &SshSite, mac: MacAddress) -> Result<String, RelayError> {
+    let timeout = Duration::from_secs(ssh.timeout);
+    let mut command = Command::new("ssh");
+    // never ask for a password or whether to trust the host, there's nobody to answer
+    command
+        .args(["-o", "BatchMode=yes", "-o"])
+        .arg(format!("ConnectTimeout={}", ssh.timeout));
+    if let Some(port) = ssh.port {
+        command.arg("-p").arg(port.to_string());
+    }
+    if let Some(user) = &ssh.user {
+        command.arg("-l").arg(user);
+    }
+    if let Some(key) = &ssh.key {
+        command
+            .arg("-i")
+            .arg(key)
+            .args(["-o", "IdentitiesOnly=yes"]);
+    }
+    let child = command
+        .arg("--")
+        .arg(&ssh.host)
+        .arg(ssh.command.replace("{mac}", &mac.to_string()))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // so the timeout kills it
+        .kill_on_drop(true)
+        .spawn()
+        .wrap_err("failed to start ssh")
+        .map_err(RelayError::Unreachable)?;
+    let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(output) => output
+            .wrap_err("failed to wait for ssh")
+            .map_err(RelayError::Unreachable)?,
+        Err(_) => return Err(RelayError::SshTimeout(timeout)),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+    match output.status.code() {
+        Some(0) => Ok(stdout),
+        // ssh's own errors, the command's would need to exit with that too
+        Some(255)
+            if stderr.contains("Permission denied") || stderr.contains("verification failed") =>
+        {
+            Err(RelayError::SshAuth(cut(&stderr)))
+        }
+        Some(255) if stderr.contains("timed out") => Err(RelayError::SshTimeout(timeout)),
+        Some(255) => Err(RelayError::Unreachable(eyre::eyre!("{}", cut(&stderr)))),
+        code => {
+            let output = [stdout, stderr]
+                .into_iter()
+                .filter(|output| !output.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+            Err(RelayError::SshExit {
+                code,
+                output: cut(&output),
+            })
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! The files the server keeps its state in: the registry, its stats, the schedules and the wake
+//! tokens. Each of them has a `version`, an older file is migrated when it's loaded (with a copy
+//! of it kept next to it) and a newer one isn't loaded at all, so an upgrade can't lose anything
+//! and a downgrade can't either.
+
+use eyre::{bail, Context};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Brings a file from one version to the next.
+type Migration = fn(&mut Map<String, Value>) -> eyre::Result<()>;
+
+/// How one kind of file is versioned.
+pub(super) struct Format {
+    /// What the file is, for errors.
+    pub(super) what: &'static str,
+    /// The first migration is from version 1 to 2, the version written is the one after the
+    /// last.
+    pub(super) migrations: &'static [Migration],
+}
+
+impl Format {
+    pub(super) fn current(&self) -> u64 {
+        self.migrations.len() as u64 + 1
+    }
+}
+
+/// Files from before there were versions don't have one, they're all version 1. The ones that
+/// didn't change otherwise only need the version that's then written.
+pub(super) fn versioned(_: &mut Map<String, Value>) -> eyre::Result<()> {
+    Ok(())
+}
+
+/// Where the file is copied to before it's migrated from `version`, like `hosts.json.v1.bak`.
+fn backup_path(path: &Path, version: u64) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{version}.bak"));
+    backup.into()
+}
+
+/// Loads the file, `None` if there is none. A file from an older version is migrated and saved
+/// again, after it was copied to its [`backup_path`].
+pub(super) fn load<T: Serialize + DeserializeOwned>(
+    path: &Path,
+    format: &Format,
+) -> eyre::Result<Option<T>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let what = format.what;
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("reading {what} {}", path.display()))?;
+    let value: Value = serde_json::from_str(&contents)
+        .wrap_err_with(|| format!("parsing {what} {}", path.display()))?;
+    let Value::Object(mut document) = value else {
+        bail!("parsing {what} {}: not a JSON object", path.display());
+    };
+    let version = match document.remove("version") {
+        None => 1,
+        Some(version) => match version.as_u64() {
+            Some(version) if version > 0 => version,
+            _ => bail!(
+                "parsing {what} {}: invalid version {version}, expected a number from 1",
+                path.display()
+            ),
+        },
+    };
+    let current = format.current();
+    if version > current {
+        bail!(
+            "{what} {} is version {version}, newer than the version {current} this wakeonlan \
+             knows. it was written by a newer wakeonlan, use that one or restore the file from \
+             before the upgrade",
+            path.display()
+        );
+    }
+
+    for (from, migration) in (version..).zip(&format.migrations[version as usize - 1..]) {
+        migration(&mut document)
+            .wrap_err_with(|| format!("migrating {what} {} from version {from}", path.display()))?;
+    }
+    let document = serde_json::from_value(Value::Object(document))
+        .wrap_err_with(|| format!("parsing {what} {}", path.display()))?;
+    if version < current {
+        let backup = backup_path(path, version);
+        std::fs::copy(path, &backup)
+            .wrap_err_with(|| format!("backing up {what} to {}", backup.display()))?;
+        save(path, format, &document)?;
+        tracing::info!(what, path = %path.display(), backup = %backup.display(), from = version, to = current, "Migrated state file");
+    }
+    Ok(Some(document))
+}
+
+/// Writes to a temporary file next to it first, so a crash can't leave half a file.
+pub(super) fn save(path: &Path, format: &Format, document: &impl Serialize) -> eyre::Result<()> {
+    let mut value = serde_json::to_value(document)?;
+    if let Value::Object(document) = &mut value {
+        document.insert("version".to_owned(), format.current().into());
+    }
+    let json = serde_json::to_string_pretty(&value)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, json)
+        .wrap_err_with(|| format!("writing {}", Path::new(&tmp).display()))?;
+    std::fs::rename(&tmp, path).wrap_err_with(|| format!("replacing {}", path.display()))?;
+    Ok(())
+}
@@ -0,0 +1,246 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path as FsPath, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    hosts::lookup_host,
+    janitor,
+    state_file::{self, Format},
+    AppState,
+};
+use crate::{
+    api::v1::{HostStats, MacStats, WakeStats},
+    config::Config,
+    MacAddress,
+};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/hosts/{name}/stats", get(stats))
+}
+
+/// How many of the most recent times to online the averages are over.
+const RECENT_BOOTS: usize = 50;
+
+/// How waking each MAC went so far, saved next to the registry if there is one.
+pub(super) struct Stats {
+    path: Option<PathBuf>,
+    /// How many wakes are waited to be verified at most, `limits.history`.
+    history: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    counts: HashMap<MacAddress, Counts>,
+    /// When each MAC was last sent a packet, until it's seen online or given up on.
+    unverified: HashMap<MacAddress, Unverified>,
+}
+
+struct Unverified {
+    at: DateTime<Utc>,
+    /// What the wake was for, the MAC if it was woken by MAC.
+    host: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Counts {
+    attempts: u64,
+    verified: u64,
+    timeouts: u64,
+    /// In seconds, oldest first.
+    boot_times: Vec<f64>,
+}
+
+/// The stats file, with the MACs in their usual form.
+#[derive(Serialize, Deserialize)]
+struct StatsDocument {
+    macs: BTreeMap<String, Counts>,
+}
+
+const FORMAT: Format = Format {
+    what: "stats",
+    migrations: &[state_file::versioned],
+};
+
+/// `hosts.json` has its stats in `hosts.stats.json`.
+fn stats_path(registry: &FsPath) -> PathBuf {
+    registry.with_extension("stats.json")
+}
+
+impl Stats {
+    pub(super) fn load(config: &Config) -> eyre::Result<Stats> {
+        let path = config.registry.as_deref().map(stats_path);
+        let document = (path.as_deref())
+            .map(|path| state_file::load::<StatsDocument>(path, &FORMAT))
+            .transpose()?
+            .flatten();
+        let counts = match document {
+            Some(document) => document
+                .macs
+                .into_iter()
+                .filter_map(|(mac, counts)| Some((mac.parse().ok()?, counts)))
+                .collect(),
+            None => HashMap::new(),
+        };
+        Ok(Stats {
+            path,
+            history: config.limits.history,
+            entries: Mutex::new(Entries {
+                counts,
+                unverified: HashMap::new(),
+            }),
+        })
+    }
+
+    /// A packet was sent for the MAC, for the host if it was woken by name.
+    pub(super) fn attempted(&self, mac: MacAddress, host: Option<&str>, at: DateTime<Utc>) {
+        self.update(|entries| {
+            entries.counts.entry(mac).or_default().attempts += 1;
+            let host = host.map_or_else(|| mac.to_string(), str::to_owned);
+            entries.unverified.insert(mac, Unverified { at, host });
+            janitor::trim(&mut entries.unverified, self.history, |woken| woken.at);
+        });
+    }
+
+    /// Forgets the oldest wakes waited to be verified, beyond the limit.
+    pub(super) fn prune(&self) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        janitor::trim(&mut entries.unverified, self.history, |woken| woken.at);
+    }
+
+    /// How many wakes are waited to be verified.
+    pub(super) fn unverified(&self) -> usize {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.unverified.len()
+    }
+
+    /// The host with these MACs answered, which verifies the last wake of each of them.
+    /// Returns what the first of those wakes was for and how many seconds ago it was, `None` if
+    /// none of them were woken since they were last verified.
+    pub(super) fn online(&self, macs: &[MacAddress]) -> Option<(String, f64)> {
+        let now = Utc::now();
+        self.update(|entries| {
+            let mut first: Option<(String, f64)> = None;
+            for mac in macs {
+                let Some(woken) = entries.unverified.remove(mac) else {
+                    continue;
+                };
+                let seconds = (now - woken.at).num_milliseconds() as f64 / 1000.0;
+                let counts = entries.counts.entry(*mac).or_default();
+                counts.verified += 1;
+                counts.boot_times.push(seconds);
+                if counts.boot_times.len() > RECENT_BOOTS {
+                    counts.boot_times.remove(0);
+                }
+                if first.as_ref().is_none_or(|(_, longest)| seconds > *longest) {
+                    first = Some((woken.host, seconds));
+                }
+            }
+            first
+        })
+    }
+
+    /// Waiting for the host with these MACs to answer was given up on. Returns what the wakes
+    /// were for, `None` if none of them were woken since they were last verified.
+    pub(super) fn timed_out(&self, macs: &[MacAddress]) -> Option<String> {
+        self.update(|entries| {
+            let mut host = None;
+            for mac in macs {
+                if let Some(woken) = entries.unverified.remove(mac) {
+                    entries.counts.entry(*mac).or_default().timeouts += 1;
+                    host = Some(woken.host);
+                }
+            }
+            host
+        })
+    }
+
+    /// The stats of the MACs, and those of the one woken most often for the host as a whole.
+    pub(super) fn host(&self, macs: &[MacAddress]) -> (Option<WakeStats>, Vec<MacStats>) {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let per_mac = macs
+            .iter()
+            .filter_map(|mac| Some((*mac, entries.counts.get(mac)?)))
+            .collect::<Vec<_>>();
+        let overall = per_mac
+            .iter()
+            .max_by_key(|(_, counts)| counts.attempts)
+            .map(|(_, counts)| counts.summary());
+        let per_mac = per_mac
+            .into_iter()
+            .map(|(mac, counts)| MacStats {
+                mac: mac.to_string(),
+                stats: counts.summary(),
+            })
+            .collect();
+        (overall, per_mac)
+    }
+
+    /// Changes the counts and saves them. Failing to save is only logged, it never fails a wake.
+    fn update<T>(&self, change: impl FnOnce(&mut Entries) -> T) -> T {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let changed = change(&mut entries);
+        if let Some(path) = &self.path {
+            if let Err(e) = save(path, &entries.counts) {
+                tracing::warn!(?e, "failed to save stats");
+            }
+        }
+        changed
+    }
+}
+
+impl Counts {
+    fn summary(&self) -> WakeStats {
+        let checked = self.verified + self.timeouts;
+        let mut sorted = self.boot_times.clone();
+        sorted.sort_by(f64::total_cmp);
+        let percentile = |p: f64| {
+            let last = sorted.len().checked_sub(1)?;
+            Some(sorted[(last as f64 * p).round() as usize])
+        };
+        WakeStats {
+            attempts: self.attempts,
+            verified: self.verified,
+            timeouts: self.timeouts,
+            success_rate: (checked > 0).then(|| self.verified as f64 / checked as f64),
+            average_time_to_online: (!sorted.is_empty())
+                .then(|| sorted.iter().sum::<f64>() / sorted.len() as f64),
+            median_time_to_online: percentile(0.5),
+            p90_time_to_online: percentile(0.9),
+        }
+    }
+}
+
+fn save(path: &FsPath, counts: &HashMap<MacAddress, Counts>) -> eyre::Result<()> {
+    let document = StatsDocument {
+        macs: counts
+            .iter()
+            .map(|(mac, counts)| (mac.to_string(), counts.clone()))
+            .collect(),
+    };
+    state_file::save(path, &FORMAT, &document)
+}
+
+async fn stats(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Response {
+    let macs = match lookup_host(&state, &name).await {
+        Ok(macs) => macs,
+        Err(response) => return response,
+    };
+    let (overall, macs) = state.stats.host(&macs);
+    let response = HostStats {
+        host: name,
+        stats: overall.unwrap_or_default(),
+        macs,
+    };
+    Json(response).into_response()
+}
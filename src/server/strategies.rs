@@ -0,0 +1,290 @@
+//! The `strategies` of the configured hosts, the ways of waking them that are tried one after
+//! the other until the host is up after one of them.
+
+use chrono::Utc;
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use super::{
+    audit::{AuditDestination, AuditEvent, StrategyEntry},
+    hosts::check_host,
+    sender,
+    sequences::check_ready,
+    wait::PROBE_INTERVAL,
+    wake::{magic_packet, send_wake_one},
+    AppState, RequestContext,
+};
+use crate::{
+    api::v1::{Destination, StrategyAttempt, WakeOutcome, WakeStage},
+    config::{Site, Via, WakeStrategy},
+    retry::Attempts,
+    MacAddress,
+};
+
+/// How the strategies went, with the UDP packets they sent.
+pub(super) struct Tried {
+    pub(super) destinations: Vec<Destination>,
+    pub(super) attempts: Vec<StrategyAttempt>,
+    /// The one the host came up after, `None` if it didn't come up at all.
+    pub(super) worked: Option<String>,
+}
+
+impl Tried {
+    /// Whether any of them got a packet out.
+    pub(super) fn sent(&self) -> bool {
+        self.attempts.iter().any(|attempt| attempt.sent)
+    }
+}
+
+impl AppState {
+    /// The strategies of the configured host, empty for a host that's configured without any
+    /// (or isn't configured at all).
+    pub(super) fn strategies(&self, host: &str) -> &[WakeStrategy] {
+        self.config
+            .hosts
+            .iter()
+            .find(|configured| configured.name.eq_ignore_ascii_case(host))
+            .map_or(&[], |configured| configured.strategies.as_slice())
+    }
+
+    /// The `destinations` of the configured host, like [`AppState::strategies`].
+    pub(super) fn host_destinations(&self, host: &str) -> &[SocketAddr] {
+        self.config
+            .hosts
+            .iter()
+            .find(|configured| configured.name.eq_ignore_ascii_case(host))
+            .map_or(&[], |configured| configured.destinations.as_slice())
+    }
+
+    /// How long a wake of the host (or `default_host`) may take, `wake_timeout` and for a host
+    /// with strategies their timeouts on top.
+    pub(super) fn wake_budget(&self, host: Option<&str>) -> Duration {
+        let host = (host.filter(|host| !host.is_empty())).or(self.config.default_host.as_deref());
+        let waiting = host.map_or(0, |host| {
+            self.strategies(host)
+                .iter()
+                .map(|strategy| strategy.timeout)
+                .sum()
+        });
+        self.config.wake_timeout + Duration::from_secs(waiting)
+    }
+
+    /// Notes the strategy the host came up after with the wake, unless the MACs were woken again
+    /// since, and puts how each of them went in the audit log.
+    fn record_strategy(&self, macs: &[MacAddress], id: &str, host: &str, tried: &Tried) {
+        let mut last_wakes = self.last_wakes.lock().unwrap_or_else(|e| e.into_inner());
+        for mac in macs {
+            if let Some(wake) = last_wakes.get_mut(mac).filter(|wake| wake.id == id) {
+                wake.strategy.clone_from(&tried.worked);
+            }
+        }
+        drop(last_wakes);
+        if let Some(audit) = &self.audit {
+            audit.log(AuditEvent::Strategies(StrategyEntry {
+                at: Utc::now(),
+                id: id.to_owned(),
+                host: host.to_owned(),
+                worked: tried.worked.clone(),
+                attempts: tried.attempts.clone(),
+            }));
+        }
+    }
+}
+
+/// Tries the strategies in order, each until the host is up or its timeout passes, and stops at
+/// the first one the host came up after. This blocks until then.
+pub(super) fn run(
+    state: &AppState,
+    host: &str,
+    macs: &[MacAddress],
+    site: Option<&Site>,
+    source: Option<IpAddr>,
+    set_stage: impl Fn(WakeStage),
+) -> Tried {
+    let mut tried = Tried {
+        destinations: Vec::new(),
+        attempts: Vec::new(),
+        worked: None,
+    };
+    for strategy in state.strategies(host) {
+        let started = Instant::now();
+        set_stage(WakeStage::Sending);
+        let sent = send(
+            state,
+            host,
+            macs,
+            site,
+            source,
+            strategy,
+            &mut tried.destinations,
+        );
+        let was_sent = sent.is_ok();
+        let result = sent.and_then(|()| {
+            set_stage(WakeStage::Verifying);
+            wait_until_up(state, host, macs, strategy)
+        });
+        let name = strategy.to_string();
+        match &result {
+            Ok(()) => tracing::info!(%host, strategy = %name, "Host came up after the strategy"),
+            Err(error) => {
+                tracing::warn!(%host, strategy = %name, %error, "strategy didn't wake the host")
+            }
+        }
+        tried.attempts.push(StrategyAttempt {
+            strategy: name.clone(),
+            sent: was_sent,
+            online: result.is_ok(),
+            error: result.err(),
+            elapsed: started.elapsed().as_secs_f64(),
+        });
+        if tried.attempts.last().is_some_and(|attempt| attempt.online) {
+            tried.worked = Some(name);
+            break;
+        }
+    }
+    tried
+}
+
+/// Records the outcome for each of the MACs like any other wake, with the strategy that worked,
+/// returning whether any packet got out. With one, the host's post wake commands are run.
+pub(super) fn record(
+    state: &AppState,
+    host: &str,
+    macs: &[MacAddress],
+    id: &str,
+    tried: &Tried,
+    context: &RequestContext,
+) -> bool {
+    let sent = tried.sent();
+    let outcome = if sent {
+        WakeOutcome::Sent
+    } else {
+        WakeOutcome::Failed
+    };
+    for mac in macs {
+        let mac_string = mac.to_string();
+        let sent_to = (tried.destinations.iter())
+            .filter(|report| report.mac == mac_string)
+            .map(|report| AuditDestination {
+                address: report.address,
+                sent: report.sent,
+            })
+            .collect();
+        state.record_wake(*mac, id, Some(host), context, outcome, sent_to);
+    }
+    state.record_strategy(macs, id, host, tried);
+    if sent {
+        state.run_post_wake(id, Some(host), macs);
+    }
+    sent
+}
+
+/// Sends the packets of one strategy, failing with why if none for any MAC got out. The UDP
+/// ones are added to `destinations`.
+fn send(
+    state: &AppState,
+    host: &str,
+    macs: &[MacAddress],
+    site: Option<&Site>,
+    source: Option<IpAddr>,
+    strategy: &WakeStrategy,
+    destinations: &mut Vec<Destination>,
+) -> Result<(), String> {
+    let interfaces = match &strategy.interface {
+        Some(interface) => vec![interface.clone()],
+        None => state.interfaces(Some(host), site),
+    };
+    match strategy.via {
+        Via::Udp => {
+            if let [interface] = interfaces.as_slice() {
+                sender::check_interface(interface)?;
+            }
+            let addresses = match strategy.destination {
+                Some(destination) => vec![destination],
+                None => state.destinations(site),
+            };
+            let sent = macs
+                .iter()
+                .flat_map(|mac| send_wake_one(state, *mac, &addresses, &interfaces, source, false))
+                .collect::<Vec<_>>();
+            let result = if sent.iter().any(|report| report.sent) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "failed to send packet: {}",
+                    Destination::summary(&sent)
+                ))
+            };
+            destinations.extend(sent);
+            result
+        }
+        Via::Raw => {
+            let Some(interface) = interfaces.into_iter().next() else {
+                return Err(
+                    "no interface to send the raw frame on, the strategy or the host needs one"
+                        .to_owned(),
+                );
+            };
+            let mut errors = Vec::new();
+            for mac in macs {
+                let packet = magic_packet(state, *mac);
+                let Attempts { result, .. } =
+                    (state.config.retry).run(|| state.sender.send_frame(&packet, &interface));
+                if let Err(e) = result {
+                    errors.push(format!("{mac}: {:#}", eyre::Report::new(e)));
+                }
+            }
+            if errors.len() == macs.len() {
+                Err(format!("failed to send raw frame: {}", errors.join(", ")))
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Checks whether the host is up every [`PROBE_INTERVAL`] until it is, failing once the
+/// strategy's timeout passed or if it can't be checked at all.
+fn wait_until_up(
+    state: &AppState,
+    host: &str,
+    macs: &[MacAddress],
+    strategy: &WakeStrategy,
+) -> Result<(), String> {
+    let timeout = Duration::from_secs(strategy.timeout);
+    let deadline = Instant::now() + timeout;
+    loop {
+        let up = match strategy.ready {
+            Some(ready) => check_ready(state, host, ready)?,
+            None => match check_host(state, macs) {
+                Ok((_, verified)) => verified.is_some_and(|verified| verified.online),
+                Err(e) => {
+                    tracing::warn!(?e, ?macs, "failed to probe host");
+                    false
+                }
+            },
+        };
+        if up {
+            return Ok(());
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(format!("not online after {timeout:?}"));
+        }
+        std::thread::sleep(remaining.min(PROBE_INTERVAL));
+    }
+}
+
+/// Like `udp to 192.168.1.255:9: not online after 30s`, for each strategy.
+pub(super) fn summary(attempts: &[StrategyAttempt]) -> String {
+    attempts
+        .iter()
+        .map(|attempt| match &attempt.error {
+            Some(error) => format!("{}: {error}", attempt.strategy),
+            None => attempt.strategy.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
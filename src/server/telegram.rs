@@ -0,0 +1,215 @@
+use eyre::{bail, Context};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+
+use super::{
+    hosts::{check_host, known_hosts},
+    wake::wake_by_name,
+    AppState, RequestContext, WakeSource,
+};
+use crate::{config::TelegramConfig, MacAddress};
+
+/// How long a poll for new messages waits on the Bot API server.
+const POLL_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a request that isn't a poll may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_AFTER: Duration = Duration::from_secs(5);
+
+const USAGE: &str = "/list - the known hosts and whether they're up\n/wake <host> - wake a host";
+
+/// Takes wake requests from Telegram chats, polling the Bot API for messages.
+pub struct Telegram {
+    config: TelegramConfig,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Update {
+    update_id: i64,
+    message: Option<Message>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    chat: Chat,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    id: i64,
+}
+
+impl Telegram {
+    pub fn new(config: TelegramConfig) -> Telegram {
+        Telegram {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Answers messages forever, failed polls are retried after a while.
+    pub async fn run(self, state: Arc<AppState>) {
+        let mut offset = None;
+        loop {
+            let updates = self
+                .call::<Vec<Update>>(
+                    "getUpdates",
+                    json!({
+                        "offset": offset,
+                        "timeout": POLL_TIMEOUT.as_secs(),
+                        "allowed_updates": ["message"],
+                    }),
+                    POLL_TIMEOUT + REQUEST_TIMEOUT,
+                )
+                .await;
+            let updates = match updates {
+                Ok(updates) => updates,
+                Err(e) => {
+                    tracing::warn!(?e, "failed to get telegram updates, retrying");
+                    tokio::time::sleep(RETRY_AFTER).await;
+                    continue;
+                }
+            };
+
+            for update in updates {
+                offset = Some(update.update_id + 1);
+                let Some(Message {
+                    chat,
+                    text: Some(text),
+                }) = update.message
+                else {
+                    continue;
+                };
+                if !self.config.allowed_chats.contains(&chat.id) {
+                    tracing::warn!(
+                        chat = chat.id,
+                        "ignoring telegram message from a chat that isn't allowed"
+                    );
+                    continue;
+                }
+                let Some(reply) = self.answer(&state, chat.id, &text).await else {
+                    continue;
+                };
+                let sent = self
+                    .call::<serde_json::Value>(
+                        "sendMessage",
+                        json!({ "chat_id": chat.id, "text": reply }),
+                        REQUEST_TIMEOUT,
+                    )
+                    .await;
+                if let Err(e) = sent {
+                    tracing::error!(?e, chat = chat.id, "failed to reply on telegram");
+                }
+            }
+        }
+    }
+
+    /// The reply to a message, `None` for messages that aren't commands.
+    async fn answer(&self, state: &Arc<AppState>, chat: i64, text: &str) -> Option<String> {
+        let mut words = text.split_whitespace();
+        // in groups, commands can be addressed to a bot with `/wake@bot`
+        let command = words.next()?.split('@').next().unwrap_or_default();
+        match (command, words.next()) {
+            ("/list", _) => Some(list(state).await),
+            ("/wake", Some(host)) => {
+                let context = RequestContext {
+                    principal: Some(format!("telegram:{chat}")),
+                    ..RequestContext::from(WakeSource::Telegram)
+                };
+                Some(match wake_by_name(state, host.to_owned(), context).await {
+                    Ok(summary) => format!("{summary}."),
+                    Err(e) => format!("Failed to wake: {e}"),
+                })
+            }
+            (command, _) if command.starts_with('/') => Some(USAGE.to_owned()),
+            _ => None,
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        body: serde_json::Value,
+        timeout: Duration,
+    ) -> eyre::Result<T> {
+        // the token is part of the url, so it has to stay out of errors
+        let url = format!(
+            "{}/bot{}/{method}",
+            self.config.api_url.trim_end_matches('/'),
+            self.config.token.expose()
+        );
+        let response: ApiResponse<T> = self
+            .client
+            .post(url)
+            .json(&body)
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .wrap_err_with(|| format!("calling {method}"))?
+            .json()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .wrap_err_with(|| format!("invalid response to {method}"))?;
+        match response {
+            ApiResponse {
+                ok: true,
+                result: Some(result),
+                ..
+            } => Ok(result),
+            ApiResponse { description, .. } => bail!(
+                "{method} failed: {}",
+                description.as_deref().unwrap_or("no description")
+            ),
+        }
+    }
+}
+
+/// Every known host and whether it's up, checked all at once.
+async fn list(state: &Arc<AppState>) -> String {
+    let hosts = known_hosts(state).await;
+    if hosts.is_empty() {
+        return "No hosts known.".to_owned();
+    }
+    let checks = hosts
+        .iter()
+        .map(|host| {
+            let state = state.clone();
+            // the MACs were written from parsed ones, so they parse again
+            let macs = host
+                .macs
+                .iter()
+                .filter_map(|mac| mac.parse().ok())
+                .collect::<Vec<MacAddress>>();
+            tokio::task::spawn_blocking(move || check_host(&state, &macs))
+        })
+        .collect::<Vec<_>>();
+
+    let mut lines = Vec::new();
+    for (host, check) in hosts.iter().zip(checks) {
+        let status = match check.await {
+            Ok(Ok((_, Some(verified)))) if verified.online => "online",
+            Ok(Ok((_, Some(_)))) => "offline",
+            Ok(Ok((_, None))) => "unknown",
+            Ok(Err(e)) => {
+                tracing::warn!(?e, host = %host.name, "failed to check host");
+                "unknown"
+            }
+            Err(e) => {
+                tracing::error!(?e, "join error");
+                "unknown"
+            }
+        };
+        lines.push(format!("{}: {status}", host.name));
+    }
+    lines.join("\n")
+}
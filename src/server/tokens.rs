@@ -0,0 +1,372 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Read,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    hosts::lookup_host,
+    html::wake_page,
+    state_file::{self, Format},
+    wake::wake_by_name,
+    AppState, ErrorResponse, RequestContext, WakeSource,
+};
+use crate::{config::Config, sign::sha256};
+
+pub(super) fn write_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/tokens", post(create).get(list))
+        .route("/tokens/{id}", delete(revoke))
+}
+
+/// Anyone with a token may use these.
+pub(super) fn redeem_routes() -> Router<Arc<AppState>> {
+    Router::new().route("/wake-token/{token}", get(redeem))
+}
+
+/// One-time wake tokens, saved to the tokens file if there is one.
+/// Only their hashes are kept, so the file can't be used to wake anything.
+pub(super) struct WakeTokens {
+    path: Option<PathBuf>,
+    entries: Mutex<Vec<WakeToken>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WakeToken {
+    /// For listing and revoking, it can't be used to wake.
+    id: String,
+    /// The SHA-256 of the token, in hex.
+    hash: String,
+    host: String,
+    created: DateTime<Utc>,
+    expires: DateTime<Utc>,
+    /// Consumed tokens are kept until [`GRACE`] after they expire, to tell them apart from ones
+    /// that never existed.
+    consumed: Option<DateTime<Utc>>,
+}
+
+/// The tokens file.
+#[derive(Serialize, Deserialize)]
+struct TokensDocument {
+    tokens: Vec<WakeToken>,
+}
+
+const FORMAT: Format = Format {
+    what: "wake tokens",
+    migrations: &[state_file::versioned],
+};
+
+/// What became of redeeming a token.
+enum Redeemed {
+    Host {
+        id: String,
+        host: String,
+    },
+    /// Consumed or expired.
+    Gone,
+    NotFound,
+}
+
+fn hash(token: &str) -> String {
+    sha256(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// How long expired tokens are kept, so redeeming one answers that it's gone rather than that
+/// it never existed.
+const GRACE: chrono::TimeDelta = chrono::TimeDelta::days(7);
+
+/// 128 bits from the OS, for the tokens (which have to be impossible to guess) and their ids.
+fn random_hex() -> eyre::Result<String> {
+    let mut bytes = [0; 16];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(&mut bytes))
+        .wrap_err("reading /dev/urandom")?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+impl WakeTokens {
+    pub(super) fn load(config: &Config) -> eyre::Result<WakeTokens> {
+        let document = (config.wake_tokens_file.as_deref())
+            .map(|path| state_file::load::<TokensDocument>(path, &FORMAT))
+            .transpose()?
+            .flatten();
+        let entries = document.map_or_else(Vec::new, |document| document.tokens);
+        Ok(WakeTokens {
+            path: config.wake_tokens_file.clone(),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// The tokens that can still be used.
+    fn outstanding(&self) -> Vec<WakeToken> {
+        let now = Utc::now();
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|token| token.consumed.is_none() && token.expires >= now)
+            .cloned()
+            .collect()
+    }
+
+    /// Prunes the tokens that expired more than [`GRACE`] ago and saves the rest before they're swapped in, so a failed save
+    /// doesn't change anything. The lock is held throughout, so no two changes can see the same
+    /// token unconsumed.
+    fn update<T>(&self, change: impl FnOnce(&mut Vec<WakeToken>) -> T) -> eyre::Result<T> {
+        let mut current = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries = current.clone();
+        let now = Utc::now();
+        entries.retain(|token| token.expires + GRACE >= now);
+        let result = change(&mut entries);
+        if let Some(path) = &self.path {
+            let document = TokensDocument {
+                tokens: entries.clone(),
+            };
+            state_file::save(path, &FORMAT, &document)?;
+        }
+        *current = entries;
+        Ok(result)
+    }
+
+    /// Saves the tokens without the ones past their [`GRACE`], if there are any.
+    pub(super) fn prune(&self) -> eyre::Result<()> {
+        let now = Utc::now();
+        let expired = (self.entries.lock().unwrap_or_else(|e| e.into_inner()))
+            .iter()
+            .any(|token| token.expires + GRACE < now);
+        if expired {
+            self.update(|_| ())?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    fn redeem(&self, token: &str) -> eyre::Result<Redeemed> {
+        let hash = hash(token);
+        let now = Utc::now();
+        self.update(
+            |entries| match entries.iter_mut().find(|entry| entry.hash == hash) {
+                None => Redeemed::NotFound,
+                Some(entry) if entry.consumed.is_some() || entry.expires < now => Redeemed::Gone,
+                Some(entry) => {
+                    entry.consumed = Some(now);
+                    Redeemed::Host {
+                        id: entry.id.clone(),
+                        host: entry.host.clone(),
+                    }
+                }
+            },
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct CreateRequest {
+    host: String,
+    /// In seconds.
+    expires_in: u64,
+}
+
+#[derive(Serialize)]
+struct TokenInfo {
+    id: String,
+    host: String,
+    created: DateTime<Utc>,
+    expires: DateTime<Utc>,
+}
+
+impl From<WakeToken> for TokenInfo {
+    fn from(token: WakeToken) -> Self {
+        TokenInfo {
+            id: token.id,
+            host: token.host,
+            created: token.created,
+            expires: token.expires,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CreateResponse {
+    #[serde(flatten)]
+    info: TokenInfo,
+    /// Only ever shown here.
+    token: String,
+    path: String,
+}
+
+async fn create(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Json(request): Json<CreateRequest>,
+) -> Response {
+    if let Err(response) = lookup_host(&state, &request.host).await {
+        return response;
+    }
+    let now = Utc::now();
+    let Some(expires) = i64::try_from(request.expires_in)
+        .ok()
+        .and_then(|secs| now.checked_add_signed(chrono::Duration::try_seconds(secs)?))
+    else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("expires_in is too large")),
+        )
+            .into_response();
+    };
+
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || -> eyre::Result<(WakeToken, String)> {
+            let token = random_hex()?;
+            let entry = WakeToken {
+                id: random_hex()?,
+                hash: hash(&token),
+                host: request.host,
+                created: now,
+                expires,
+                consumed: None,
+            };
+            let added = state.wake_tokens.update(|entries| {
+                let duplicate = entries
+                    .iter()
+                    .any(|other| other.id == entry.id || other.hash == entry.hash);
+                if !duplicate {
+                    entries.push(entry.clone());
+                }
+                !duplicate
+            })?;
+            if !added {
+                eyre::bail!("the new token or its id is already taken");
+            }
+            Ok((entry, token))
+        }
+    })
+    .await;
+    match result {
+        Ok(Ok((entry, token))) => {
+            tracing::info!(id = %entry.id, host = %entry.host, %expires, client = ?context.client, principal = ?context.principal, "Created wake token");
+            (
+                StatusCode::CREATED,
+                Json(CreateResponse {
+                    path: format!("/wake-token/{token}"),
+                    info: entry.into(),
+                    token,
+                }),
+            )
+                .into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to create wake token");
+            (StatusCode::INTERNAL_SERVER_ERROR, "error").into_response()
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn").into_response()
+        }
+    }
+}
+
+async fn list(State(state): State<Arc<AppState>>) -> Json<Vec<TokenInfo>> {
+    Json(
+        state
+            .wake_tokens
+            .outstanding()
+            .into_iter()
+            .map(TokenInfo::from)
+            .collect(),
+    )
+}
+
+async fn revoke(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(id): Path<String>,
+) -> Response {
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        let id = id.clone();
+        move || {
+            state.wake_tokens.update(|entries| {
+                let before = entries.len();
+                entries.retain(|entry| entry.id != id);
+                entries.len() != before
+            })
+        }
+    })
+    .await;
+    match result {
+        Ok(Ok(true)) => {
+            tracing::info!(%id, client = ?context.client, principal = ?context.principal, "Revoked wake token");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(Ok(false)) => (StatusCode::NOT_FOUND, "token not found").into_response(),
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to save wake tokens");
+            (StatusCode::INTERNAL_SERVER_ERROR, "error").into_response()
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn").into_response()
+        }
+    }
+}
+
+/// Consumes the token before waking, so it's used up even if the wake fails.
+async fn redeem(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Path(token): Path<String>,
+) -> Response {
+    let result = tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || state.wake_tokens.redeem(&token)
+    })
+    .await;
+    match result {
+        Ok(Ok(Redeemed::Host { id, host })) => {
+            let context = RequestContext {
+                principal: Some(format!("token {id}")),
+                source: WakeSource::Token,
+                ..context
+            };
+            wake_page(wake_by_name(&state, host, context).await)
+        }
+        Ok(Ok(Redeemed::Gone)) => {
+            tracing::warn!(client = ?context.client, "rejected used or expired wake token");
+            (
+                StatusCode::GONE,
+                "this token was already used or has expired",
+            )
+                .into_response()
+        }
+        Ok(Ok(Redeemed::NotFound)) => {
+            tracing::warn!(client = ?context.client, "rejected unknown wake token");
+            (StatusCode::NOT_FOUND, "token not found").into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to save wake tokens");
+            (StatusCode::INTERNAL_SERVER_ERROR, "error").into_response()
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn").into_response()
+        }
+    }
+}
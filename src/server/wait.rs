@@ -0,0 +1,212 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+use super::{
+    hosts::{check_host_with, lookup_host},
+    AppState, ErrorResponse,
+};
+use crate::{
+    api::v1::{Event, WaitResponse},
+    verify::{Strategy, Verified},
+    MacAddress,
+};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/hosts/{name}/wait-online", get(wait_online))
+}
+
+pub(super) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+pub(super) const MAX_TIMEOUT: Duration = Duration::from_secs(600);
+pub(super) const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The probe loops that are running, by the MACs of the host they're probing and the strategies
+/// they probe it with. Each one publishes how the host answered once it's online.
+pub(super) type ProbeLoops =
+    Mutex<HashMap<(Vec<MacAddress>, Vec<Strategy>), watch::Sender<Option<Verified>>>>;
+
+#[derive(Deserialize)]
+struct WaitQuery {
+    /// In seconds.
+    timeout: Option<u64>,
+}
+
+/// Blocks until the host answers or the timeout (at most ten minutes) passes.
+async fn wait_online(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<WaitQuery>,
+) -> Response {
+    let start = Instant::now();
+    let timeout = query
+        .timeout
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT)
+        .min(MAX_TIMEOUT);
+
+    let macs = match lookup_host(&state, &name).await {
+        Ok(macs) => macs,
+        Err(response) => return response,
+    };
+
+    match wait_until_online(&state, macs, timeout).await {
+        Some(verified) => {
+            let elapsed = start.elapsed();
+            tracing::info!(host = %name, ?elapsed, "Host is online");
+            Json(WaitResponse {
+                host: name,
+                online: verified.online,
+                elapsed: elapsed.as_secs_f64(),
+                strategy: verified.strategy,
+            })
+            .into_response()
+        }
+        None => (
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(ErrorResponse::new(format!(
+                "host `{name}` not online after {timeout:?}"
+            ))),
+        )
+            .into_response(),
+    }
+}
+
+/// How the host answered once it's online, `None` if it didn't come up in time.
+pub(super) async fn wait_until_online(
+    state: &Arc<AppState>,
+    macs: Vec<MacAddress>,
+    timeout: Duration,
+) -> Option<Verified> {
+    wait_with(state, macs, state.config.verify.clone(), timeout).await
+}
+
+/// Like [`wait_until_online`], asking the host with these strategies instead of the configured
+/// ones.
+pub(super) async fn wait_with(
+    state: &Arc<AppState>,
+    macs: Vec<MacAddress>,
+    strategies: Vec<Strategy>,
+    timeout: Duration,
+) -> Option<Verified> {
+    let mut online = subscribe(state, (macs.clone(), strategies));
+    let waited = tokio::time::timeout(timeout, online.wait_for(Option::is_some))
+        .await
+        .map(|result| result.map(|verified| *verified));
+    match waited {
+        Ok(Ok(verified)) => verified,
+        Ok(Err(e)) => {
+            tracing::error!(?e, "probe loop went away");
+            None
+        }
+        Err(_) => {
+            let timed_out = {
+                let state = state.clone();
+                let macs = macs.clone();
+                tokio::task::spawn_blocking(move || state.stats.timed_out(&macs))
+            };
+            match timed_out.await {
+                Ok(Some(host)) => {
+                    state.metrics.timed_out(&host);
+                    let macs = macs.iter().map(MacAddress::to_string).collect();
+                    state
+                        .events
+                        .publish(Event::VerificationTimedOut { host, macs });
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!(?e, "join error"),
+            }
+            None
+        }
+    }
+}
+
+impl AppState {
+    /// What to ask the host with, the strategy of a request's `verify` or the configured ones.
+    pub(super) fn probes(&self, verify: Option<Strategy>) -> Vec<Strategy> {
+        verify.map_or_else(|| self.config.verify.clone(), |strategy| vec![strategy])
+    }
+}
+
+/// Joins the probe loop for the host, starting it if nobody is waiting for that host yet.
+fn subscribe(
+    state: &Arc<AppState>,
+    key: (Vec<MacAddress>, Vec<Strategy>),
+) -> watch::Receiver<Option<Verified>> {
+    let mut loops = state.probe_loops.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(running) = loops.get(&key) {
+        return running.subscribe();
+    }
+    let (sender, receiver) = watch::channel(None);
+    loops.insert(key.clone(), sender.clone());
+    state.events.publish(Event::VerificationStarted {
+        macs: key.0.iter().map(MacAddress::to_string).collect(),
+        strategies: key.1.clone(),
+    });
+    tokio::spawn(probe_until_online(state.clone(), key, sender));
+    receiver
+}
+
+/// Probes the host until it's online or nobody is waiting for it anymore.
+async fn probe_until_online(
+    state: Arc<AppState>,
+    key: (Vec<MacAddress>, Vec<Strategy>),
+    sender: watch::Sender<Option<Verified>>,
+) {
+    let macs = &key.0;
+    loop {
+        let result = tokio::task::spawn_blocking({
+            let state = state.clone();
+            let (macs, strategies) = key.clone();
+            move || check_host_with(&state, &macs, &strategies)
+        })
+        .await;
+        match result {
+            Ok(Ok((_, Some(verified)))) if verified.online => {
+                let stats = {
+                    let state = state.clone();
+                    let macs = macs.clone();
+                    tokio::task::spawn_blocking(move || state.stats.online(&macs))
+                };
+                let woken = stats.await.unwrap_or_else(|e| {
+                    tracing::error!(?e, "join error");
+                    None
+                });
+                if let Some((host, seconds)) = &woken {
+                    state.metrics.online(host, *seconds);
+                }
+                state.events.publish(Event::HostOnline {
+                    host: woken.as_ref().map(|(host, _)| host.clone()),
+                    macs: macs.iter().map(MacAddress::to_string).collect(),
+                    strategy: verified.strategy,
+                    seconds: woken.map(|(_, seconds)| seconds),
+                });
+                sender.send_replace(Some(verified));
+                break;
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => tracing::warn!(?e, ?macs, "failed to probe host"),
+            Err(e) => tracing::error!(?e, "join error"),
+        }
+
+        tokio::time::sleep(PROBE_INTERVAL).await;
+        // checked with the lock held, so nobody can join a loop that's about to stop
+        let mut loops = state.probe_loops.lock().unwrap_or_else(|e| e.into_inner());
+        if sender.receiver_count() == 0 {
+            loops.remove(&key);
+            return;
+        }
+    }
+    let mut loops = state.probe_loops.lock().unwrap_or_else(|e| e.into_inner());
+    loops.remove(&key);
+}
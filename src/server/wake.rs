@@ -0,0 +1,2518 @@
+use axum::{
+    extract::{DefaultBodyLimit, FromRequest, Query, Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Form, Json, Router,
+};
+use chrono::{DateTime, Local, NaiveTime, TimeDelta, Utc};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use super::{
+    audit::AuditDestination,
+    callback::{self, Callback},
+    coalesce,
+    format::ResponseFormat,
+    hosts::{in_location, new_wake_id, OPEN_INTERVAL},
+    html::{format_ago, html_escape, html_page, refreshing_page},
+    links::query_escape,
+    not_found::Missing,
+    queue::Queued,
+    schedules,
+    sender::{self, Sent},
+    sites::{self, Relay, RelayError},
+    strategies, wait, AppState, RequestContext, WakeSource, READ_ONLY,
+};
+use crate::{
+    api::v1::{
+        BatchWakeRequest, BatchWakeResponse, CliError, CliErrorKind, Destination, ErrorResponse,
+        Event, HostWakeResult, MacSource, ResolveSource, ResolvedName, SkippedInterface,
+        StaleDiscovery, VerifyOverride, WakeOutcome, WakeRequest, WakeResponse, WakeStage,
+    },
+    config::{FreshSource, Site, WhenStale},
+    discovery::{self, parse_mac_addr, HostEntry},
+    retry::Attempts,
+    schedule::{Schedule, When},
+    verify::Strategy,
+    MacAddress, MagicPacket,
+};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/wake", post(wake))
+        .route("/wake/batch", post(wake_batch))
+        .layer(DefaultBodyLimit::max(MAX_BODY))
+}
+
+/// Longer bodies are refused with a 413. A wake is a few hundred bytes, a batch of a hundred hosts
+/// still fits.
+const MAX_BODY: usize = 8 * 1024;
+
+/// The body of `POST /wake` (or that of `POST /wake/batch`), which can be JSON, a submitted form,
+/// or empty. Unless the request says how it wants to be answered, a submitted form gets a page
+/// back and everything else JSON. Bodies that don't fit the request, like those with unknown
+/// fields, are a 400 like those that can't be parsed at all.
+struct WakeBody<T = WakeRequest> {
+    params: T,
+    format: ResponseFormat,
+}
+
+impl<S: Send + Sync, T: DeserializeOwned + Default> FromRequest<S> for WakeBody<T> {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let negotiated = ResponseFormat::negotiate(req.headers(), req.uri())
+            .map_err(|e| ResponseFormat::Json.error(StatusCode::BAD_REQUEST, e))?;
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| {
+                value
+                    .split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_owned()
+            });
+
+        match content_type.as_deref() {
+            None => Ok(WakeBody {
+                params: T::default(),
+                format: negotiated.unwrap_or(ResponseFormat::Json),
+            }),
+            Some("application/json") => {
+                let format = negotiated.unwrap_or(ResponseFormat::Json);
+                match Json::<T>::from_request(req, state).await {
+                    Ok(Json(params)) => Ok(WakeBody { params, format }),
+                    Err(e) => Err(format.error(bad_request(e.status()), e.body_text())),
+                }
+            }
+            Some("application/x-www-form-urlencoded") => {
+                let format = negotiated.unwrap_or(ResponseFormat::Html);
+                match Form::<T>::from_request(req, state).await {
+                    Ok(Form(params)) => Ok(WakeBody { params, format }),
+                    Err(e) => Err(format.error(bad_request(e.status()), e.body_text())),
+                }
+            }
+            Some(other) => Err(negotiated.unwrap_or(ResponseFormat::Json).error(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("unsupported content type `{other}`"),
+            )),
+        }
+    }
+}
+
+/// The status of a rejected body, without the 422 axum has for bodies that parse but don't fit.
+fn bad_request(status: StatusCode) -> StatusCode {
+    match status {
+        StatusCode::UNPROCESSABLE_ENTITY => StatusCode::BAD_REQUEST,
+        status => status,
+    }
+}
+
+/// A wake that got its packets out (or would have, for a dry run), with the MACs it was for.
+#[derive(Clone)]
+struct Woken {
+    response: WakeResponse,
+    macs: Vec<MacAddress>,
+}
+
+impl WakeResponse {
+    /// What happened, like the ways of asking for a wake that aren't HTTP answer with.
+    pub fn summary(&self) -> String {
+        let target = match &self.host {
+            Some(host) => format!("{host} ({})", self.macs.join(", ")),
+            None => self.macs.join(", "),
+        };
+        let checked = self.last_probe.map(format_ago).unwrap_or_default();
+        if self.skipped {
+            return format!("Not sent, {target} was up already when it was checked {checked}");
+        }
+        let summary = self.summary_sent(&target);
+        if self.already_online {
+            format!("{summary}; it was up already when it was checked {checked}")
+        } else {
+            summary
+        }
+    }
+
+    fn summary_sent(&self, target: &str) -> String {
+        if !self.strategies.is_empty() {
+            return match &self.strategy {
+                Some(strategy) => {
+                    format!("Sent magic packet to {target}, it came up after {strategy}")
+                }
+                None => format!(
+                    "Sent magic packet to {target}, it didn't come up after any strategy ({})",
+                    strategies::summary(&self.strategies)
+                ),
+            };
+        }
+        let queued = self.destinations.iter().any(|report| report.queued)
+            && !self.destinations.iter().any(|report| report.sent);
+        let verb = if self.dry_run {
+            "Would send"
+        } else if queued {
+            "Queued"
+        } else {
+            "Sent"
+        };
+        match &self.site {
+            // woken over ssh, which doesn't say where it sent them
+            Some(site) if self.destinations.is_empty() => {
+                format!("{verb} magic packet to {target} in site {site}")
+            }
+            _ => {
+                let destinations = Destination::summary(&self.destinations);
+                format!("{verb} magic packet to {target} via {destinations}")
+            }
+        }
+    }
+}
+
+impl Destination {
+    /// With packets for several MACs, every destination says which one it's about.
+    pub(super) fn summary(reports: &[Destination]) -> String {
+        let several_macs = reports.iter().any(|report| report.mac != reports[0].mac);
+        reports
+            .iter()
+            .map(|report| {
+                let summary = Self::summary_one(report);
+                if several_macs {
+                    format!("{} via {summary}", report.mac)
+                } else {
+                    summary
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn summary_one(report: &Destination) -> String {
+        let summary = match &report.error {
+            Some(error) if report.attempts > 1 => {
+                format!(
+                    "{}: {error} (after {} attempts)",
+                    report.address, report.attempts
+                )
+            }
+            Some(error) => format!("{}: {error}", report.address),
+            None => report.address.to_string(),
+        };
+        let summary = match (&report.interface, report.skipped.as_slice()) {
+            (Some(interface), [_, ..]) if report.sent => {
+                let skipped = report
+                    .skipped
+                    .iter()
+                    .map(|skipped| skipped.interface.as_str())
+                    .collect::<Vec<_>>();
+                format!(
+                    "{summary} on {interface} (failed on {})",
+                    skipped.join(", ")
+                )
+            }
+            _ => summary,
+        };
+        let summary = match report.reported_errors.as_deref() {
+            Some(errors @ [_, ..]) => format!("{summary}, reported back: {}", errors.join(", ")),
+            _ => summary,
+        };
+        match &report.hint {
+            Some(hint) => format!("{summary}; {hint}"),
+            None => summary,
+        }
+    }
+}
+
+impl WakeStage {
+    fn name(self) -> &'static str {
+        match self {
+            WakeStage::Starting => "starting",
+            WakeStage::Discovery => "discovery",
+            WakeStage::ReverseDns => "reverse_dns",
+            WakeStage::NeighborRefresh => "neighbor_refresh",
+            WakeStage::NameLookup => "name_lookup",
+            WakeStage::Sending => "sending",
+            WakeStage::Relaying => "relaying",
+            WakeStage::Verifying => "verifying",
+        }
+    }
+}
+
+/// Shared between the handler and the blocking wake, so the handler can tell
+/// where the wake got stuck when it gives up on it.
+#[derive(Clone)]
+struct StageTracker(Arc<Mutex<WakeStage>>);
+
+impl StageTracker {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(WakeStage::Starting)))
+    }
+
+    fn set(&self, stage: WakeStage) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = stage;
+    }
+
+    fn get(&self) -> WakeStage {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// What a wake starts with: the target if it was found before it was coalesced, and its budget.
+struct Prepared {
+    target: Option<Result<Target, WakeError>>,
+    budget: Budget,
+}
+
+/// The time a wake has, from when it was asked for, and what it's busy with. Finding what to
+/// wake before it's coalesced and the wake itself share it.
+#[derive(Clone)]
+struct Budget {
+    duration: Duration,
+    deadline: tokio::time::Instant,
+    stage: StageTracker,
+}
+
+impl Budget {
+    fn start(state: &AppState, host: Option<&str>) -> Self {
+        let duration = state.wake_budget(host);
+        Budget {
+            duration,
+            deadline: tokio::time::Instant::now() + duration,
+            stage: StageTracker::new(),
+        }
+    }
+
+    /// Runs `f` on a blocking thread, failing if it doesn't finish before the deadline.
+    async fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&StageTracker) -> T + Send + 'static,
+    ) -> Result<T, WakeFailure> {
+        let task = tokio::task::spawn_blocking({
+            let stage = self.stage.clone();
+            move || f(&stage)
+        });
+        match tokio::time::timeout_at(self.deadline, task).await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(e)) => {
+                tracing::error!(?e, "join error");
+                Err(WakeFailure::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorResponse::new("failed to spawn"),
+                ))
+            }
+            Err(_) => {
+                let (budget, stage) = (self.duration, self.stage.get());
+                tracing::error!(?budget, ?stage, "wake timed out");
+                let message = format!("timed out after {budget:?} during {}", stage.name());
+                Err(WakeFailure {
+                    timed_out: true,
+                    ..WakeFailure::new(
+                        StatusCode::GATEWAY_TIMEOUT,
+                        ErrorResponse::new(message).with_stage(stage),
+                    )
+                })
+            }
+        }
+    }
+}
+
+impl ResponseFormat {
+    /// The page links to the host's service, and with `watch` it goes there once the host is up.
+    fn success(self, response: &WakeResponse, watch: bool) -> Response {
+        match self {
+            // nothing was sent, so there's nothing still to happen
+            ResponseFormat::Json if response.skipped => Json(response).into_response(),
+            ResponseFormat::Json => (StatusCode::ACCEPTED, Json(response)).into_response(),
+            ResponseFormat::Html if response.skipped => {
+                let body = format!("<p>{}.</p>", html_escape(&response.summary()));
+                html_page("Not sent", &body).into_response()
+            }
+            ResponseFormat::Html => {
+                let mut body = format!("<p>{}.</p>", html_escape(&response.summary()));
+                let service = (response.service_url.as_ref())
+                    .zip(response.host.as_ref())
+                    .filter(|_| !response.dry_run);
+                let Some((url, host)) = service else {
+                    return (StatusCode::ACCEPTED, html_page("Sent", &body)).into_response();
+                };
+                let link = format!(r#"<a href="{0}">{0}</a>"#, html_escape(url));
+                if !watch {
+                    body.push_str(&format!("<p>Open {link}.</p>"));
+                    return (StatusCode::ACCEPTED, html_page("Sent", &body)).into_response();
+                }
+                body.push_str(&format!(
+                    "<p>Opening {link} once {} is up.</p>",
+                    html_escape(host)
+                ));
+                let open = format!("hosts/{}/open", query_escape(host));
+                let page = refreshing_page("Sent", &body, OPEN_INTERVAL, &open);
+                (StatusCode::ACCEPTED, page).into_response()
+            }
+        }
+    }
+
+    fn error(self, status: StatusCode, message: String) -> Response {
+        match self {
+            ResponseFormat::Json => (status, Json(ErrorResponse::new(message))).into_response(),
+            ResponseFormat::Html => {
+                let body = format!("<p>Failed to wake: {}</p>", html_escape(&message));
+                (status, html_page("Error", &body)).into_response()
+            }
+        }
+    }
+}
+
+/// Why a wake didn't happen.
+enum WakeError {
+    InvalidMac(String),
+    HostNotFound(String),
+    /// The host isn't configured, and nothing is discovered at all (yet).
+    NothingDiscovered(String),
+    /// The host isn't found by name, and its name doesn't resolve either.
+    NameNotResolved {
+        host: String,
+        error: String,
+    },
+    /// The host's name resolves, but none of its addresses are in the neighbor table.
+    NoMacForName {
+        host: String,
+        ips: Vec<IpAddr>,
+    },
+    /// Nothing to wake was given and there's no `default_host`.
+    NoDefaultHost,
+    /// The interface the packets are supposed to leave on doesn't exist.
+    UnknownInterface(String),
+    /// No interface has the address the packets are supposed to be sent from.
+    UnknownSource(String),
+    /// The packet didn't make it to any of the destinations, with the error of the self-test at
+    /// startup if that failed too.
+    SendFailed {
+        woken: Box<Woken>,
+        self_test: Option<String>,
+    },
+    /// The host is in a remote site, and its server didn't wake it.
+    Relay {
+        site: String,
+        error: RelayError,
+    },
+    /// Discovery found the host with other MACs than the pinned one, and the wake isn't forced.
+    PinnedMacMismatch {
+        host: String,
+        pinned: MacAddress,
+        discovered: Vec<MacAddress>,
+    },
+    /// It's quiet until then, and the wake wasn't forced (or can't be).
+    QuietHours {
+        until: NaiveTime,
+        forceable: bool,
+    },
+    /// The wake is automatic, and discovery hasn't worked for longer than
+    /// `discovery_freshness` allows.
+    StaleDiscovery(StaleDiscovery),
+    /// The host has `require_confirmation`, and the wake didn't `confirm` it.
+    ConfirmationRequired(String),
+    /// The server is `read_only`, nothing is ever woken.
+    ReadOnly,
+    Other(eyre::Report),
+}
+
+/// `POST /wake?delay=<seconds>` or `?at=<time>`, which schedules the wake for later instead.
+#[derive(Debug, Deserialize)]
+struct Later {
+    delay: Option<u64>,
+    at: Option<DateTime<Utc>>,
+}
+
+/// The strategy of the request's `verify` and how long it waits, failing with why for anything
+/// but a port or a ping.
+fn check_verify(verify: &VerifyOverride) -> Result<(Strategy, Option<Duration>), String> {
+    let strategy = match (verify.tcp, verify.ping) {
+        (Some(_), true) => return Err("`verify` has either `tcp` or `ping`, not both".to_owned()),
+        (None, false) => return Err("`verify` needs `tcp` or `ping`".to_owned()),
+        (Some(0), false) => return Err("`verify.tcp` has to be a port, not 0".to_owned()),
+        (Some(port), false) => Strategy::Tcp(port),
+        (None, true) => Strategy::Icmp,
+    };
+    let timeout = verify.timeout.map(Duration::from_secs);
+    if timeout.is_some_and(|timeout| timeout.is_zero() || timeout > wait::MAX_TIMEOUT) {
+        return Err(format!(
+            "`verify.timeout` has to be between 1 and {} seconds",
+            wait::MAX_TIMEOUT.as_secs()
+        ));
+    }
+    Ok((strategy, timeout))
+}
+
+async fn wake(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    Query(later): Query<Later>,
+    body: WakeBody,
+) -> Response {
+    let WakeBody { params, format } = body;
+    let context = from_page(context, format);
+    if later.delay.is_some() || later.at.is_some() {
+        return wake_later(&state, context, later, params, format).await;
+    }
+    let id = new_wake_id();
+    tracing::info!(%id, host = ?params.host, mac = ?params.mac, client = ?context.client, principal = ?context.principal, source = %context.source, "Waking");
+
+    let callback_url = params.callback_url.as_deref().filter(|url| !url.is_empty());
+    if let Some(url) = callback_url {
+        if let Err(e) = callback::check_url(&state.config.callback_allow, url) {
+            tracing::warn!(%url, client = ?context.client, "refusing callback url");
+            return format.error(StatusCode::BAD_REQUEST, e);
+        }
+    }
+    let verify = match params.verify.as_ref().map(check_verify).transpose() {
+        Ok(verify) => verify,
+        Err(e) => return format.error(StatusCode::BAD_REQUEST, e),
+    };
+    let probe = verify.map(|(strategy, _)| strategy);
+    // a `verify` waits like `wait_online` does
+    let waiting = (verify.and_then(|(_, timeout)| timeout))
+        .or(params.wait_online.map(Duration::from_secs))
+        .or(verify.map(|_| wait::DEFAULT_TIMEOUT));
+    let watch = !state.config.verify.is_empty();
+
+    let budget = Budget::start(&state, params.host.as_deref());
+    // wakes with a callback are their own
+    let target = if params.callback_url.as_deref().is_none_or(str::is_empty) {
+        match resolve_ahead(&state, &params, &context, &budget).await {
+            Ok(target) => Some(target),
+            Err(failure) => {
+                let requested = params.host.as_deref().filter(|host| !host.is_empty());
+                state.events.wake_failed(&id, requested, &failure.error);
+                return Finished(Err((failure.status, failure.error))).render(format, watch);
+            }
+        }
+    } else {
+        None
+    };
+    let key = match &target {
+        Some(Ok(target)) => coalesce::Key::new(&params, &target.macs),
+        _ => {
+            let prepared = Prepared { target, budget };
+            let finished = run_wake(state, params, prepared, id, context, probe, waiting).await;
+            return finished.render(format, watch);
+        }
+    };
+    let wake = run_wake(
+        state.clone(),
+        params,
+        Prepared { target, budget },
+        id.clone(),
+        context.clone(),
+        probe,
+        waiting,
+    );
+    let (finished, coalesced) = coalesce::join(&state, key, wake).await;
+    match (coalesced, finished) {
+        (true, Finished(Ok(mut response))) => {
+            tracing::info!(%id, into = %response.id, client = ?context.client, principal = ?context.principal, "Coalesced into the wake in progress");
+            state.record_coalesced(&response, &context);
+            response.coalesced = true;
+            Finished(Ok(response)).render(format, watch)
+        }
+        (_, finished) => finished.render(format, watch),
+    }
+}
+
+/// Finds what the wake is for before it's coalesced with the others, so it's the same wake
+/// however the host is named. It's within the wake's budget, which is what's left of it for the
+/// wake, and a lookup that doesn't finish in time fails the wake with its stage.
+async fn resolve_ahead(
+    state: &Arc<AppState>,
+    params: &WakeRequest,
+    context: &RequestContext,
+    budget: &Budget,
+) -> Result<Result<Target, WakeError>, WakeFailure> {
+    let state = state.clone();
+    let params = params.clone();
+    let context = context.clone();
+    budget
+        .run(move |stage| resolve_target(&state, &params, &context, stage))
+        .await
+}
+
+/// How a wake went, what every request coalesced into it answers with.
+#[derive(Debug, Clone)]
+pub(super) struct Finished(Result<WakeResponse, (StatusCode, ErrorResponse)>);
+
+impl Finished {
+    pub(super) fn failed(status: StatusCode, error: String) -> Self {
+        Finished(Err((status, ErrorResponse::new(error))))
+    }
+
+    fn render(self, format: ResponseFormat, watch: bool) -> Response {
+        match (self.0, format) {
+            (Ok(response), format) => format.success(&response, watch),
+            (Err((status, error)), ResponseFormat::Json) => (status, Json(error)).into_response(),
+            (Err((status, error)), ResponseFormat::Html) => format.error(status, error.error),
+        }
+    }
+}
+
+/// Wakes the host like `POST /wake` asked, calling back or waiting for it to come up if it asked
+/// for that too.
+async fn run_wake(
+    state: Arc<AppState>,
+    params: WakeRequest,
+    prepared: Prepared,
+    id: String,
+    context: RequestContext,
+    probe: Option<Strategy>,
+    waiting: Option<Duration>,
+) -> Finished {
+    let started = Instant::now();
+    let callback_url = params.callback_url.clone().filter(|url| !url.is_empty());
+    let call_back = {
+        let state = state.clone();
+        let id = id.clone();
+        let requested_host = params.host.clone().filter(|host| !host.is_empty());
+        move |outcome, woken: Option<&Woken>| {
+            let Some(url) = callback_url else {
+                return;
+            };
+            let (host, macs) = match woken {
+                Some(woken) => (woken.response.host.clone(), woken.macs.clone()),
+                None => (requested_host, Vec::new()),
+            };
+            if !macs.is_empty() {
+                state.record_callback(&macs, &id, callback::pending(&url));
+            }
+            tokio::spawn(callback::run(
+                state,
+                Callback {
+                    url,
+                    id,
+                    host,
+                    macs,
+                    outcome,
+                    wait_online: waiting.filter(|_| woken.is_some_and(|w| !w.response.dry_run)),
+                    probe,
+                    started,
+                },
+            ));
+        }
+    };
+
+    // with a callback, that waits instead
+    let wait_online = waiting
+        .filter(|_| params.callback_url.as_deref().is_none_or(str::is_empty))
+        .map(|timeout| timeout.min(wait::MAX_TIMEOUT));
+    match attempt_wake(&state, params, prepared, &id, context).await {
+        Ok(mut woken) => {
+            call_back(callback::Outcome::Sent, Some(&woken));
+            // the hosts of remote sites can't be watched from here, and strategies watched already
+            let local = !woken.response.dry_run
+                && !woken.response.skipped
+                && woken.response.site.is_none()
+                && woken.response.online.is_none();
+            if let Some(timeout) = wait_online.filter(|_| local) {
+                let strategies = state.probes(probe);
+                let online = wait::wait_with(&state, woken.macs.clone(), strategies, timeout).await;
+                woken.response.online = Some(online.is_some());
+                woken.response.probe = online.map(|verified| verified.strategy).or(probe);
+                if let Some(probe) = woken.response.probe {
+                    state.record_probe(&woken.macs, &woken.response.id, probe);
+                }
+            }
+            Finished(Ok(woken.response))
+        }
+        Err(failure) => {
+            let outcome = if failure.timed_out {
+                callback::Outcome::TimedOut
+            } else {
+                callback::Outcome::Failed
+            };
+            call_back(outcome, failure.woken.as_ref());
+            Finished(Err((failure.status, failure.error)))
+        }
+    }
+}
+
+/// How a wake failed, and what it answers with.
+struct WakeFailure {
+    status: StatusCode,
+    error: ErrorResponse,
+    /// Whether it ran out of its budget, rather than failing.
+    timed_out: bool,
+    /// What the packets were for, if sending them failed.
+    woken: Option<Woken>,
+}
+
+impl WakeFailure {
+    fn new(status: StatusCode, error: ErrorResponse) -> Self {
+        WakeFailure {
+            status,
+            error,
+            timed_out: false,
+            woken: None,
+        }
+    }
+}
+
+/// The answer for the errors that happen before anything is sent, or while it is.
+impl From<WakeError> for WakeFailure {
+    fn from(error: WakeError) -> Self {
+        let mut woken = None;
+        let mut details = None;
+        match &error {
+            WakeError::Relay { site, error } => details = Some((site.clone(), error.failure())),
+            WakeError::SendFailed { woken: sent, .. } => woken = Some(Woken::clone(sent)),
+            _ => {}
+        }
+        let (status, message) = error.status_and_message();
+        let mut error = ErrorResponse::new(message);
+        if let Some((site, relay)) = details {
+            error = error.with_site(site).with_relay(relay);
+        }
+        WakeFailure {
+            woken,
+            ..WakeFailure::new(status, error)
+        }
+    }
+}
+
+/// Runs the wake in the background within the budget of the host and tells the events how it
+/// went. A timed out wake's error has the stage it was busy with.
+async fn attempt_wake(
+    state: &Arc<AppState>,
+    params: WakeRequest,
+    prepared: Prepared,
+    id: &str,
+    context: RequestContext,
+) -> Result<Woken, WakeFailure> {
+    let requested = params.host.clone().filter(|host| !host.is_empty());
+    let Prepared { target, budget } = prepared;
+    let task = budget.run({
+        let state = state.clone();
+        let id = id.to_owned();
+        move |stage| wake_inner(&state, params, target, &id, &context, stage)
+    });
+    let result = match task.await {
+        Ok(result) => result.map_err(WakeFailure::from),
+        Err(failure) => Err(failure),
+    };
+    match &result {
+        Ok(woken) => state.events.wake_sent(&woken.response),
+        Err(failure) => state
+            .events
+            .wake_failed(id, requested.as_deref(), &failure.error),
+    }
+    result
+}
+
+/// Wakes answered with a page are those of the page's forms, everything else is from the API.
+fn from_page(context: RequestContext, format: ResponseFormat) -> RequestContext {
+    match format {
+        ResponseFormat::Html => RequestContext {
+            source: WakeSource::Ui,
+            ..context
+        },
+        ResponseFormat::Json => context,
+    }
+}
+
+impl From<Missing> for WakeError {
+    fn from(missing: Missing) -> Self {
+        match missing {
+            Missing::Host(host) => WakeError::HostNotFound(host),
+            Missing::NameNotResolved { host, error } => WakeError::NameNotResolved { host, error },
+            Missing::NoMacForName { host, ips } => WakeError::NoMacForName { host, ips },
+        }
+    }
+}
+
+impl WakeError {
+    /// How the host wasn't found, `None` for every other error and when nothing is discovered at
+    /// all (that won't stay that way).
+    fn missing(&self) -> Option<Missing> {
+        match self {
+            WakeError::HostNotFound(host) => Some(Missing::Host(host.clone())),
+            WakeError::NameNotResolved { host, error } => Some(Missing::NameNotResolved {
+                host: host.clone(),
+                error: error.clone(),
+            }),
+            WakeError::NoMacForName { host, ips } => Some(Missing::NoMacForName {
+                host: host.clone(),
+                ips: ips.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn status_and_message(self) -> (StatusCode, String) {
+        match self {
+            WakeError::InvalidMac(mac) => (
+                StatusCode::BAD_REQUEST,
+                format!("invalid mac address `{mac}`"),
+            ),
+            WakeError::HostNotFound(host) => {
+                (StatusCode::NOT_FOUND, format!("host `{host}` not found"))
+            }
+            WakeError::NothingDiscovered(host) => (
+                StatusCode::NOT_FOUND,
+                format!("host `{host}` not found, {NOTHING_DISCOVERED}"),
+            ),
+            WakeError::NameNotResolved { host, error } => (
+                StatusCode::NOT_FOUND,
+                format!("host `{host}` not found, and its name doesn't resolve: {error}"),
+            ),
+            WakeError::NoMacForName { host, ips } => {
+                let ips = ips.iter().map(IpAddr::to_string).collect::<Vec<_>>();
+                (
+                    StatusCode::NOT_FOUND,
+                    format!(
+                        "host `{host}` resolves to {}, but no MAC is known for it, \
+                         not even after asking the network",
+                        ips.join(", ")
+                    ),
+                )
+            }
+            WakeError::NoDefaultHost => (
+                StatusCode::BAD_REQUEST,
+                "no host or mac given and no default_host configured".to_owned(),
+            ),
+            WakeError::UnknownInterface(message) | WakeError::UnknownSource(message) => {
+                tracing::error!(%message, "failed to wake");
+                (StatusCode::INTERNAL_SERVER_ERROR, message)
+            }
+            WakeError::SendFailed { woken, self_test } => {
+                let response = woken.response;
+                tracing::error!(destinations = ?response.destinations, "failed to wake");
+                let mut message = if response.strategies.is_empty() {
+                    format!(
+                        "failed to send packet: {}",
+                        Destination::summary(&response.destinations)
+                    )
+                } else {
+                    format!(
+                        "no strategy got a packet out: {}",
+                        strategies::summary(&response.strategies)
+                    )
+                };
+                if let Some(error) = self_test {
+                    message.push_str(&format!(
+                        "; the send self-test at startup failed already ({error}), see /healthz"
+                    ));
+                }
+                (StatusCode::INTERNAL_SERVER_ERROR, message)
+            }
+            WakeError::Relay {
+                site,
+                error: RelayError::Rejected { status, error },
+            } => {
+                tracing::error!(%site, %status, %error, "remote site failed to wake");
+                (status, format!("site `{site}`: {error}"))
+            }
+            WakeError::Relay {
+                site,
+                error: RelayError::Unreachable(e),
+            } => {
+                tracing::error!(%site, ?e, "failed to reach remote site");
+                (
+                    StatusCode::BAD_GATEWAY,
+                    format!("can't reach the server of site `{site}`: {e:#}"),
+                )
+            }
+            WakeError::Relay {
+                site,
+                error: RelayError::SshAuth(error),
+            } => {
+                tracing::error!(%site, %error, "failed to log in to site over ssh");
+                (
+                    StatusCode::BAD_GATEWAY,
+                    format!("can't log in to site `{site}` over ssh: {error}"),
+                )
+            }
+            WakeError::Relay {
+                site,
+                error: RelayError::SshTimeout(timeout),
+            } => {
+                tracing::error!(%site, ?timeout, "ssh to site timed out");
+                (
+                    StatusCode::GATEWAY_TIMEOUT,
+                    format!("ssh to site `{site}` didn't finish within {timeout:?}"),
+                )
+            }
+            WakeError::Relay {
+                site,
+                error: RelayError::SshExit { code, output },
+            } => {
+                tracing::error!(%site, ?code, %output, "command of site failed");
+                let status = match code {
+                    Some(code) => format!("exited with status {code}"),
+                    None => "was killed by a signal".to_owned(),
+                };
+                let output = if output.is_empty() {
+                    output
+                } else {
+                    format!(": {output}")
+                };
+                (
+                    StatusCode::BAD_GATEWAY,
+                    format!("the command of site `{site}` {status}{output}"),
+                )
+            }
+            WakeError::PinnedMacMismatch {
+                host,
+                pinned,
+                discovered,
+            } => {
+                let discovered = discovered
+                    .iter()
+                    .map(MacAddress::to_string)
+                    .collect::<Vec<_>>();
+                (
+                    StatusCode::CONFLICT,
+                    format!(
+                        "host `{host}` is discovered with {}, but its MAC is pinned to {pinned}, \
+                         wake with `force` to wake it anyway",
+                        discovered.join(", ")
+                    ),
+                )
+            }
+            WakeError::QuietHours { until, forceable } => {
+                let until = until.format("%H:%M");
+                let message = if forceable {
+                    format!("quiet hours until {until}, wake with `force` to wake anyway")
+                } else {
+                    format!("suppressed by quiet hours until {until}")
+                };
+                (StatusCode::CONFLICT, message)
+            }
+            WakeError::StaleDiscovery(stale) => {
+                let mut message = match stale.last_success {
+                    Some(_) => format!(
+                        "skipped: stale discovery, it last worked {}s ago",
+                        stale.age
+                    ),
+                    None => format!(
+                        "skipped: stale discovery, it didn't work in the {}s since the server \
+                         started",
+                        stale.age
+                    ),
+                };
+                if let Some(error) = stale.error {
+                    message = format!("{message}: {error}");
+                }
+                (StatusCode::SERVICE_UNAVAILABLE, message)
+            }
+            WakeError::ConfirmationRequired(host) => (
+                StatusCode::PRECONDITION_REQUIRED,
+                format!("host `{host}` requires confirmation, wake with `confirm` to wake it"),
+            ),
+            WakeError::ReadOnly => (StatusCode::FORBIDDEN, READ_ONLY.to_owned()),
+            WakeError::Other(e) => {
+                tracing::error!(?e, "failed to wake");
+                (StatusCode::INTERNAL_SERVER_ERROR, "error".to_owned())
+            }
+        }
+    }
+}
+
+/// Wakes a host by name like `POST /wake` does, for the ways of asking for a wake that aren't
+/// HTTP. Either way, the message says what happened.
+pub(super) async fn wake_by_name(
+    state: &Arc<AppState>,
+    host: String,
+    context: RequestContext,
+) -> Result<String, String> {
+    match wake_request(state, by_name(host), context).await {
+        Ok((response, _)) => Ok(response.summary()),
+        Err((_, e)) => Err(e.error),
+    }
+}
+
+fn by_name(host: String) -> WakeRequest {
+    // like `default_host`, it can be a MAC
+    match parse_mac_addr(&host) {
+        Some(_) => WakeRequest {
+            mac: Some(host),
+            ..WakeRequest::default()
+        },
+        None => WakeRequest {
+            host: Some(host),
+            ..WakeRequest::default()
+        },
+    }
+}
+
+impl AppState {
+    /// Wakes a host by name (or MAC) like `POST /wake` with `wait_online` does, for programs
+    /// that run the server's code themselves, like `wakeonlan tui`. How waiting for it goes is
+    /// in the [`events`](AppState::events), the message says what happened in the end.
+    pub async fn wake_and_wait(self: &Arc<Self>, host: String) -> Result<String, String> {
+        let context = RequestContext::from(WakeSource::Tui);
+        let (response, macs) =
+            (wake_request(self, by_name(host), context).await).map_err(|(_, e)| e.error)?;
+        let summary = response.summary();
+        let timeout = wait::DEFAULT_TIMEOUT;
+        match self.wait_after(&response, macs, timeout).await {
+            None => Ok(summary),
+            Some(Some(strategy)) => Ok(format!("{summary}, it came up ({strategy})")),
+            Some(None) => Ok(format!(
+                "{summary}, it didn't come up within {}s",
+                timeout.as_secs()
+            )),
+        }
+    }
+
+    /// Wakes a host by name (or MAC) like `POST /wake` does, for `wakeonlan wake`. With a
+    /// `wait`, it waits at most that long for the host to come up and says whether it did as
+    /// `online`, with what answered as `probe`.
+    pub async fn wake_from_cli(
+        self: &Arc<Self>,
+        host: String,
+        wait: Option<Duration>,
+    ) -> Result<WakeResponse, CliError> {
+        let context = RequestContext::from(WakeSource::Cli);
+        let (mut response, macs) =
+            (wake_request(self, by_name(host), context).await).map_err(|(status, details)| {
+                CliError {
+                    kind: cli_error_kind(status, &details),
+                    details,
+                }
+            })?;
+        if let Some(timeout) = wait {
+            if let Some(answered) = self.wait_after(&response, macs, timeout).await {
+                response.online = Some(answered.is_some());
+                response.probe = answered;
+            }
+        }
+        Ok(response)
+    }
+
+    /// Waits for the woken host to come up like `wait_online` does, noting what answered with
+    /// the wake. `None` if it can't be watched from here, `Some(None)` if it didn't come up.
+    async fn wait_after(
+        self: &Arc<Self>,
+        response: &WakeResponse,
+        macs: Vec<MacAddress>,
+        timeout: Duration,
+    ) -> Option<Option<Strategy>> {
+        // like for `POST /wake`, the hosts of remote sites can't be watched from here
+        let local = !response.skipped
+            && response.site.is_none()
+            && response.online.is_none()
+            && !self.config.verify.is_empty();
+        if !local {
+            return None;
+        }
+        let verified = wait::wait_with(self, macs.clone(), self.probes(None), timeout).await;
+        if let Some(verified) = &verified {
+            self.record_probe(&macs, &response.id, verified.strategy);
+        }
+        Some(verified.map(|verified| verified.strategy))
+    }
+}
+
+/// How a wake that failed with the status failed, for `wakeonlan wake --output json`.
+fn cli_error_kind(status: StatusCode, error: &ErrorResponse) -> CliErrorKind {
+    match status {
+        StatusCode::NOT_FOUND | StatusCode::BAD_REQUEST => CliErrorKind::Resolution,
+        StatusCode::CONFLICT | StatusCode::FORBIDDEN | StatusCode::PRECONDITION_REQUIRED => {
+            CliErrorKind::Refused
+        }
+        // it ran out of time before it got to sending
+        StatusCode::GATEWAY_TIMEOUT
+            if matches!(
+                error.stage,
+                Some(
+                    WakeStage::Starting
+                        | WakeStage::Discovery
+                        | WakeStage::ReverseDns
+                        | WakeStage::NeighborRefresh
+                        | WakeStage::NameLookup
+                )
+            ) =>
+        {
+            CliErrorKind::Resolution
+        }
+        _ => CliErrorKind::Send,
+    }
+}
+
+/// Adds a one-shot schedule for the host (or MAC, or `default_host`), answering with when it's
+/// due and the ID it can be removed with. Scheduled wakes are woken by name, so the other options
+/// of a wake can't be used with it.
+async fn wake_later(
+    state: &Arc<AppState>,
+    context: RequestContext,
+    later: Later,
+    params: WakeRequest,
+    format: ResponseFormat,
+) -> Response {
+    let at = match later {
+        Later {
+            delay: Some(delay),
+            at: None,
+        } => match i64::try_from(delay)
+            .ok()
+            .and_then(TimeDelta::try_seconds)
+            .and_then(|delay| Utc::now().checked_add_signed(delay))
+        {
+            Some(at) => at,
+            None => return format.error(StatusCode::BAD_REQUEST, "delay is too long".to_owned()),
+        },
+        Later {
+            delay: None,
+            at: Some(at),
+        } => at,
+        _ => {
+            return format.error(
+                StatusCode::BAD_REQUEST,
+                "expected either `delay` or `at`".to_owned(),
+            )
+        }
+    };
+    let options = [
+        ("dry_run", params.dry_run),
+        ("refresh", params.refresh.is_some()),
+        ("callback_url", params.callback_url.is_some()),
+        ("wait_online", params.wait_online.is_some()),
+        ("verify", params.verify.is_some()),
+        ("force", params.force),
+        ("confirm", params.confirm),
+        ("skip_if_online", params.skip_if_online.is_some()),
+    ];
+    if let Some((option, _)) = options.iter().find(|(_, given)| *given) {
+        return format.error(
+            StatusCode::BAD_REQUEST,
+            format!("`{option}` can't be used with a wake that's scheduled"),
+        );
+    }
+    let host = [params.host, params.mac, state.config.default_host.clone()]
+        .into_iter()
+        .flatten()
+        .find(|host| !host.is_empty());
+    let Some(host) = host else {
+        return format.error(
+            StatusCode::BAD_REQUEST,
+            "no host given and no default_host configured".to_owned(),
+        );
+    };
+    let schedule = Schedule {
+        host,
+        when: When::At(at),
+    };
+    if schedule.next_after(Utc::now()).is_none() {
+        return format.error(
+            StatusCode::BAD_REQUEST,
+            "the schedule is never due".to_owned(),
+        );
+    }
+    match schedules::add(state, schedule, &context).await {
+        Ok(scheduled) => match format {
+            ResponseFormat::Json => (StatusCode::CREATED, Json(scheduled)).into_response(),
+            ResponseFormat::Html => {
+                let body = format!(
+                    "<p>Waking {} at {}.</p>",
+                    html_escape(&scheduled.schedule.host),
+                    at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S")
+                );
+                (StatusCode::CREATED, html_page("Scheduled", &body)).into_response()
+            }
+        },
+        Err(response) => response,
+    }
+}
+
+/// Wakes like `POST /wake` does (without a callback), returning the response with the MACs the
+/// packets were for, or the status and error it would answer with.
+pub(super) async fn wake_request(
+    state: &Arc<AppState>,
+    params: WakeRequest,
+    context: RequestContext,
+) -> Result<(WakeResponse, Vec<MacAddress>), (StatusCode, ErrorResponse)> {
+    let id = new_wake_id();
+    tracing::info!(%id, host = ?params.host, mac = ?params.mac, client = ?context.client, principal = ?context.principal, source = %context.source, "Waking");
+    let prepared = Prepared {
+        target: None,
+        budget: Budget::start(state, params.host.as_deref()),
+    };
+    match attempt_wake(state, params, prepared, &id, context).await {
+        Ok(Woken { response, macs }) => Ok((response, macs)),
+        Err(failure) => Err((failure.status, failure.error)),
+    }
+}
+
+/// What a wake is for, the host it was asked for (if any) and the MACs it goes to.
+#[derive(Debug)]
+struct Target {
+    host: Option<String>,
+    macs: Vec<MacAddress>,
+    resolved: Option<ResolvedName>,
+}
+
+/// Finds the MACs a wake goes to, by the MAC it was given or by looking up its host (or the
+/// default host).
+fn resolve_target(
+    state: &AppState,
+    params: &WakeRequest,
+    context: &RequestContext,
+    stage: &StageTracker,
+) -> Result<Target, WakeError> {
+    // empty form fields are sent as empty strings
+    let host = params.host.clone().filter(|host| !host.is_empty());
+    let mac = params.mac.clone().filter(|mac| !mac.is_empty());
+    let refresh = params.refresh.unwrap_or(state.config.neighbor_refresh);
+    // asking for a refresh looks again, even for a name that wasn't found a moment ago
+    let cached = (params.refresh != Some(true)).then_some(context.client);
+
+    let (host, (macs, resolved)) = match (host, mac) {
+        (host, Some(mac)) => {
+            let mac = parse_mac_addr(&mac).ok_or(WakeError::InvalidMac(mac))?;
+            (host, (vec![mac], None))
+        }
+        (Some(host), None) => {
+            let found = lookup(state, &host, refresh, stage, params.force, cached)?;
+            (Some(host), (found.macs, found.resolved))
+        }
+        (None, None) => {
+            let host = state
+                .config
+                .default_host
+                .clone()
+                .ok_or(WakeError::NoDefaultHost)?;
+            match parse_mac_addr(&host) {
+                Some(mac) => (None, (vec![mac], None)),
+                None => {
+                    let found = lookup(state, &host, refresh, stage, params.force, cached)?;
+                    (Some(host), (found.macs, found.resolved))
+                }
+            }
+        }
+    };
+    Ok(Target {
+        host,
+        macs,
+        resolved,
+    })
+}
+
+fn wake_inner(
+    state: &AppState,
+    params: WakeRequest,
+    target: Option<Result<Target, WakeError>>,
+    id: &str,
+    context: &RequestContext,
+    stage: &StageTracker,
+) -> Result<Woken, WakeError> {
+    state.events.publish(Event::WakeRequested {
+        id: id.to_owned(),
+        host: params.host.clone().filter(|host| !host.is_empty()),
+        mac: params.mac.clone().filter(|mac| !mac.is_empty()),
+        source: context.source.to_string(),
+    });
+    // the endpoints refuse it already, this is for everything else, like schedules
+    if state.config.read_only {
+        tracing::warn!(client = ?context.client, principal = ?context.principal, source = %context.source, "refused wake, the server is in read-only mode");
+        return Err(WakeError::ReadOnly);
+    }
+    let mac_given = params.mac.as_deref().is_some_and(|mac| !mac.is_empty());
+    let Target {
+        host,
+        macs,
+        resolved,
+    } = match target {
+        Some(target) => target?,
+        None => resolve_target(state, &params, context, stage)?,
+    };
+
+    let already_online = state.already_online(&macs);
+    let skip = params.skip_if_online.unwrap_or(state.config.skip_if_online);
+    if let Some(checked) = already_online.filter(|_| skip) {
+        tracing::info!(hostname = ?host, ?macs, %checked, client = ?context.client, principal = ?context.principal, "Already online, not sending");
+        let service_url = (host.as_deref()).and_then(|host| state.registry.service_url(host));
+        let response = WakeResponse {
+            id: id.to_owned(),
+            host,
+            mac: macs[0].to_string(),
+            macs: macs.iter().map(MacAddress::to_string).collect(),
+            dry_run: params.dry_run,
+            destinations: Vec::new(),
+            site: None,
+            resolved,
+            source: context.source.to_string(),
+            service_url,
+            online: None,
+            strategy: None,
+            destination: None,
+            strategies: Vec::new(),
+            probe: None,
+            coalesced: false,
+            already_online: true,
+            last_probe: Some(checked),
+            skipped: true,
+            stale_discovery: state.stale_discovery(),
+        };
+        return Ok(Woken { response, macs });
+    }
+
+    if !params.dry_run {
+        confirmed(state, host.as_deref(), &macs, context, params.confirm)?;
+        quiet_hours(state, host.as_deref(), &macs, id, context, params.force)?;
+        fresh_discovery(state, host.as_deref(), &macs, id, context)?;
+    }
+
+    let site = state.site(host.as_deref(), &macs);
+    if let Some((site, relay)) = site.and_then(|site| Some((site, Relay::of(site)?))) {
+        stage.set(WakeStage::Relaying);
+        let request = WakeRequest {
+            host: host.clone(),
+            // the other server might not resolve the name the same way
+            mac: (mac_given || resolved.is_some()).then(|| macs[0].to_string()),
+            dry_run: params.dry_run,
+            refresh: params.refresh,
+            confirm: params.confirm,
+            skip_if_online: params.skip_if_online,
+            ..WakeRequest::default()
+        };
+        let response = relay_wake(state, site, relay, &request, &macs, id, context)?;
+        tracing::info!(hostname = ?host, ?macs, site = %site.name, destinations = ?response.destinations, client = ?context.client, principal = ?context.principal, "Woken by remote site");
+        return Ok(Woken { response, macs });
+    }
+
+    let interfaces = state.interfaces(host.as_deref(), site);
+    // with failover, the next one is tried instead
+    if let [interface] = interfaces.as_slice() {
+        sender::check_interface(interface).map_err(WakeError::UnknownInterface)?;
+    }
+    let source = state.source(host.as_deref(), site);
+    if let Some(source) = source {
+        sender::check_source(source).map_err(WakeError::UnknownSource)?;
+    }
+    let with_strategies = host
+        .as_deref()
+        .filter(|host| !params.dry_run && !state.strategies(host).is_empty());
+    if let Some(host) = with_strategies {
+        let tried = strategies::run(state, host, &macs, site, source, |next| stage.set(next));
+        let sent = strategies::record(state, host, &macs, id, &tried, context);
+        let destination = record_destination(state, host, &macs, id, &tried.destinations);
+        let response = WakeResponse {
+            id: id.to_owned(),
+            host: Some(host.to_owned()),
+            mac: macs[0].to_string(),
+            macs: macs.iter().map(MacAddress::to_string).collect(),
+            dry_run: false,
+            destinations: tried.destinations,
+            site: None,
+            resolved,
+            source: context.source.to_string(),
+            service_url: state.registry.service_url(host),
+            online: Some(tried.worked.is_some()),
+            strategy: tried.worked,
+            destination,
+            strategies: tried.attempts,
+            probe: None,
+            coalesced: false,
+            already_online: already_online.is_some(),
+            last_probe: already_online,
+            skipped: false,
+            stale_discovery: state.stale_discovery(),
+        };
+        let woken = Woken { response, macs };
+        if !sent {
+            return Err(WakeError::SendFailed {
+                woken: Box::new(woken),
+                self_test: state
+                    .self_test
+                    .as_ref()
+                    .and_then(|self_test| self_test.error.clone()),
+            });
+        }
+        tracing::info!(hostname = %host, macs = ?woken.macs, strategy = ?woken.response.strategy, client = ?context.client, principal = ?context.principal, "Woke up with strategies");
+        return Ok(woken);
+    }
+
+    stage.set(WakeStage::Sending);
+    let mut destinations = send_wake(
+        state,
+        host.as_deref(),
+        &macs,
+        site,
+        &interfaces,
+        source,
+        params.dry_run,
+    );
+    if !params.dry_run {
+        queue_unsent(
+            state,
+            host.as_deref(),
+            &macs,
+            id,
+            &interfaces,
+            &mut destinations,
+            context,
+        );
+    }
+    let sent =
+        params.dry_run || record_wakes(state, host.as_deref(), &macs, id, &destinations, context);
+    let destination = (host.as_deref())
+        .filter(|_| !params.dry_run)
+        .and_then(|host| record_destination(state, host, &macs, id, &destinations));
+    let service_url = (host.as_deref()).and_then(|host| state.registry.service_url(host));
+    let response = WakeResponse {
+        id: id.to_owned(),
+        host,
+        mac: macs[0].to_string(),
+        macs: macs.iter().map(MacAddress::to_string).collect(),
+        dry_run: params.dry_run,
+        destinations,
+        site: None,
+        resolved,
+        source: context.source.to_string(),
+        service_url,
+        online: None,
+        strategy: None,
+        destination,
+        strategies: Vec::new(),
+        probe: None,
+        coalesced: false,
+        already_online: already_online.is_some(),
+        last_probe: already_online,
+        skipped: false,
+        stale_discovery: state.stale_discovery(),
+    };
+    let woken = Woken { response, macs };
+    if !sent {
+        let self_test = (state.self_test.as_ref()).and_then(|self_test| self_test.error.clone());
+        return Err(WakeError::SendFailed {
+            woken: Box::new(woken),
+            self_test,
+        });
+    }
+
+    let Woken {
+        response: WakeResponse {
+            host, destinations, ..
+        },
+        macs,
+    } = &woken;
+    if params.dry_run {
+        tracing::info!(hostname = ?host, ?macs, ?destinations, client = ?context.client, principal = ?context.principal, "Dry run, not sending");
+    } else {
+        tracing::info!(hostname = ?host, ?macs, ?destinations, client = ?context.client, principal = ?context.principal, "Woke up");
+    }
+    Ok(woken)
+}
+
+/// The discovered MACs of the host, unless it's pinned to another one and the wake isn't forced.
+fn check_pinned(
+    state: &AppState,
+    host: &str,
+    discovered: Vec<MacAddress>,
+    force: bool,
+) -> Result<Vec<MacAddress>, WakeError> {
+    match state.pinned_mac(host) {
+        Some(pinned) if !discovered.contains(&pinned) => {
+            tracing::warn!(%host, %pinned, ?discovered, force, "host is discovered with another MAC than its pinned one");
+            if force {
+                Ok(discovered)
+            } else {
+                Err(WakeError::PinnedMacMismatch {
+                    host: host.to_owned(),
+                    pinned,
+                    discovered,
+                })
+            }
+        }
+        _ => Ok(discovered),
+    }
+}
+
+/// Refuses the wake of a host with `require_confirmation` unless it's confirmed. Automatic wakes,
+/// like those of schedules, were set up on purpose, they don't need to.
+fn confirmed(
+    state: &AppState,
+    host: Option<&str>,
+    macs: &[MacAddress],
+    context: &RequestContext,
+    confirm: bool,
+) -> Result<(), WakeError> {
+    if confirm || context.automatic() || !state.registry.requires_confirmation(host, macs) {
+        return Ok(());
+    }
+    tracing::warn!(hostname = ?host, ?macs, client = ?context.client, principal = ?context.principal, source = %context.source, "refused unconfirmed wake");
+    let host = host.map_or_else(|| macs[0].to_string(), str::to_owned);
+    Err(WakeError::ConfirmationRequired(host))
+}
+
+/// Suppresses the wake if it's quiet for the host and the wake is automatic, or isn't forced
+/// when that's required, recording it as suppressed.
+fn quiet_hours(
+    state: &AppState,
+    host: Option<&str>,
+    macs: &[MacAddress],
+    id: &str,
+    context: &RequestContext,
+    force: bool,
+) -> Result<(), WakeError> {
+    let Some(quiet) = &state.config.quiet_hours else {
+        return Ok(());
+    };
+    let held = context.automatic() || (quiet.require_force && !force);
+    let location = host.and_then(|host| state.registry.location(host));
+    if !held || !quiet.applies_to(host, location.as_deref()) || !quiet.contains(Local::now().time())
+    {
+        return Ok(());
+    }
+    tracing::info!(hostname = ?host, ?macs, until = %quiet.end, client = ?context.client, principal = ?context.principal, "Quiet hours, not waking");
+    for mac in macs {
+        state.record_wake(*mac, id, host, context, WakeOutcome::Suppressed, Vec::new());
+    }
+    Err(WakeError::QuietHours {
+        until: quiet.end,
+        forceable: !context.automatic(),
+    })
+}
+
+/// Whether quiet hours suppress a wake that doesn't go through [`wake_inner`], like a relayed
+/// packet, recording it as suppressed if they do.
+pub(super) fn suppressed(
+    state: &AppState,
+    host: Option<&str>,
+    macs: &[MacAddress],
+    id: &str,
+    context: &RequestContext,
+) -> bool {
+    quiet_hours(state, host, macs, id, context, false).is_err()
+}
+
+/// Skips the wake if `discovery_freshness` applies to where it came from and discovery is stale,
+/// recording it as skipped. With `when_stale = "refresh"`, it's discovered once more first.
+fn fresh_discovery(
+    state: &AppState,
+    host: Option<&str>,
+    macs: &[MacAddress],
+    id: &str,
+    context: &RequestContext,
+) -> Result<(), WakeError> {
+    let Some(freshness) = &state.config.discovery_freshness else {
+        return Ok(());
+    };
+    let source = match context.source {
+        WakeSource::Schedule(_) => FreshSource::Schedules,
+        WakeSource::Sequence(_) => FreshSource::Sequences,
+        _ => return Ok(()),
+    };
+    if !freshness.sources.contains(&source) || state.stale_discovery().is_none() {
+        return Ok(());
+    }
+    if freshness.when_stale == WhenStale::Refresh {
+        tracing::info!(hostname = ?host, source = %context.source, "Discovery is stale, discovering again before waking");
+        if let Err(e) = state.discover() {
+            tracing::debug!(?e, "discovery failed again");
+        }
+    }
+    let Some(stale) = state.stale_discovery() else {
+        return Ok(());
+    };
+    tracing::warn!(hostname = ?host, ?macs, age = stale.age, error = ?stale.error, source = %context.source, "Discovery is stale, not waking");
+    for mac in macs {
+        state.record_wake(
+            *mac,
+            id,
+            host,
+            context,
+            WakeOutcome::StaleDiscovery,
+            Vec::new(),
+        );
+    }
+    Err(WakeError::StaleDiscovery(stale))
+}
+
+/// Has the server of the remote site (or the machine there, over ssh) wake the host, recording how
+/// that went like a wake from here. The response is the one from there, with the id of the wake
+/// here.
+fn relay_wake(
+    state: &AppState,
+    site: &Site,
+    relay: Relay,
+    request: &WakeRequest,
+    macs: &[MacAddress],
+    id: &str,
+    context: &RequestContext,
+) -> Result<WakeResponse, WakeError> {
+    let result = match relay {
+        Relay::Server(remote) => sites::wake(&state.http, remote, request),
+        Relay::Ssh(ssh) => sites::wake_ssh(ssh, request, macs),
+    };
+    let host = request.host.as_deref();
+    match relay {
+        _ if request.dry_run => {}
+        // nothing says where the packets went
+        Relay::Ssh(_) => {
+            let outcome = match result {
+                Ok(_) => WakeOutcome::Sent,
+                Err(_) => WakeOutcome::Failed,
+            };
+            for mac in macs {
+                state.record_wake(*mac, id, host, context, outcome, Vec::new());
+            }
+            if outcome == WakeOutcome::Sent {
+                state.run_post_wake(id, host, macs);
+            }
+        }
+        Relay::Server(_) => {
+            let destinations = match &result {
+                Ok(response) => response.destinations.as_slice(),
+                Err(_) => &[],
+            };
+            // hosts there are only known by name, the MACs it sent to are the ones it says
+            let recorded = match &result {
+                Ok(response) => response
+                    .macs
+                    .iter()
+                    .filter_map(|mac| parse_mac_addr(mac))
+                    .collect(),
+                Err(_) => macs.to_vec(),
+            };
+            record_wakes(state, host, &recorded, id, destinations, context);
+        }
+    }
+    match result {
+        Ok(response) => Ok(WakeResponse {
+            id: id.to_owned(),
+            site: Some(site.name.clone()),
+            source: context.source.to_string(),
+            ..response
+        }),
+        Err(error) => Err(WakeError::Relay {
+            site: site.name.clone(),
+            error,
+        }),
+    }
+}
+
+/// Queues the wakes of the MACs that got no packet because the network is down, see `GET
+/// /api/v1/queue`. The destinations of the MACs that aren't queued after all, because they got a
+/// packet elsewhere or the queue is full, aren't `queued` then.
+fn queue_unsent(
+    state: &AppState,
+    host: Option<&str>,
+    macs: &[MacAddress],
+    id: &str,
+    interfaces: &[String],
+    destinations: &mut [Destination],
+    context: &RequestContext,
+) {
+    let Some(config) = &state.config.send_queue else {
+        return;
+    };
+    for mac in macs {
+        let mac_string = mac.to_string();
+        let of_mac = || {
+            destinations
+                .iter()
+                .filter(|destination| destination.mac == mac_string)
+        };
+        let addresses = of_mac()
+            .filter(|destination| destination.queued)
+            .map(|destination| destination.address)
+            .collect::<Vec<_>>();
+        if addresses.is_empty() {
+            continue;
+        }
+        let queued = !of_mac().any(|destination| destination.sent)
+            && {
+                let error = of_mac().find_map(|destination| destination.error.clone());
+                let entry = Queued::new(id, host, *mac, addresses, interfaces, context, error);
+                let queued = state.send_queue.push(config.size, entry);
+                if queued {
+                    tracing::info!(%mac, "network is down, queued the wake");
+                } else {
+                    tracing::warn!(%mac, size = config.size, "send queue is full, not queueing the wake");
+                }
+                queued
+            };
+        if !queued {
+            for destination in destinations
+                .iter_mut()
+                .filter(|destination| destination.mac == mac_string)
+            {
+                destination.queued = false;
+            }
+        }
+    }
+}
+
+/// Records the outcome for each of the MACs, returning whether any of them got a packet or is
+/// queued to get one. With a packet sent, the host's post wake commands are run.
+fn record_wakes(
+    state: &AppState,
+    host: Option<&str>,
+    macs: &[MacAddress],
+    id: &str,
+    destinations: &[Destination],
+    context: &RequestContext,
+) -> bool {
+    let mut any_sent = false;
+    let mut any_packet = false;
+    for mac in macs {
+        let mac_string = mac.to_string();
+        let sent_to = destinations
+            .iter()
+            .filter(|report| report.mac == mac_string)
+            .map(|report| AuditDestination {
+                address: report.address,
+                sent: report.sent,
+            })
+            .collect::<Vec<_>>();
+        let queued = destinations
+            .iter()
+            .any(|report| report.mac == mac_string && report.queued);
+        let outcome = if sent_to.iter().any(|destination| destination.sent) {
+            WakeOutcome::Sent
+        } else if queued {
+            WakeOutcome::Queued
+        } else {
+            WakeOutcome::Failed
+        };
+        state.record_wake(*mac, id, host, context, outcome, sent_to);
+        any_sent |= outcome != WakeOutcome::Failed;
+        any_packet |= outcome == WakeOutcome::Sent;
+    }
+    if any_packet {
+        state.run_post_wake(id, host, macs);
+    }
+    any_sent
+}
+
+/// A host like a wake finds it.
+pub(super) struct Found {
+    pub(super) macs: Vec<MacAddress>,
+    /// How it was found by its DNS name, for `source` [`ResolveSource::Dns`].
+    pub(super) resolved: Option<ResolvedName>,
+    pub(super) source: ResolveSource,
+    /// The name it was found as, like `nas.fritz.box` for `nas`.
+    pub(super) name: String,
+}
+
+/// Finds a host by name like a wake does, without waking it, and without forcing past a pinned
+/// MAC or answering with a name that wasn't found before. Fails with what a wake would answer
+/// with. This blocks until it's found.
+pub(super) fn resolve(state: &AppState, host: &str) -> Result<Found, (StatusCode, String)> {
+    let refresh = state.config.neighbor_refresh;
+    lookup(state, host, refresh, &StageTracker::new(), false, None)
+        .map_err(WakeError::status_and_message)
+}
+
+/// [`resolve_host`] with the discovery of a wake, which remembers the IPs it finds and the names
+/// it doesn't find. With `cached` (who asked for it), a name that wasn't found less than
+/// `not_found_ttl` ago isn't looked for again.
+fn lookup(
+    state: &AppState,
+    host: &str,
+    refresh: bool,
+    stage: &StageTracker,
+    force: bool,
+    cached: Option<Option<IpAddr>>,
+) -> Result<Found, WakeError> {
+    let ttl = state.config.not_found_ttl;
+    if let Some(requester) = cached.filter(|_| state.static_host(host).is_none()) {
+        if let Some(missing) = state.not_found.get(host, ttl, requester) {
+            tracing::debug!(%host, ?requester, "host wasn't found a moment ago, not looking again");
+            return Err(missing.into());
+        }
+    }
+
+    let discover = || {
+        stage.set(WakeStage::Discovery);
+        let table = state.discover().map_err(WakeError::Other)?;
+        stage.set(WakeStage::ReverseDns);
+        let hosts = state.resolve_names(table);
+        state.remember_ips(&hosts);
+        Ok(hosts)
+    };
+    let refresh = || {
+        if refresh {
+            stage.set(WakeStage::NeighborRefresh);
+        }
+        refresh
+    };
+    let result = resolve_host(state, host, discover, refresh, stage, force);
+    if let Some(missing) = result.as_ref().err().and_then(WakeError::missing) {
+        if !ttl.is_zero() {
+            (state.not_found).insert(host, missing, state.config.limits.hosts);
+        }
+    }
+    result
+}
+
+/// Finds the MACs of a host, preferring the configured hosts over discovered ones, which have to
+/// have the pinned MAC if there is one (see [`check_pinned`]). A pinned host that isn't
+/// discovered gets its packets at the pinned MAC. Otherwise, if it's not discovered and
+/// `refresh` says so, the neighbors are refreshed and it's looked for once more. With nothing
+/// discovered at all, a registered host whose name contains it is used instead. Names with a
+/// dot in them that still aren't found are looked up in DNS, see [`resolve_name`].
+fn resolve_host(
+    state: &AppState,
+    host: &str,
+    discover: impl Fn() -> Result<Vec<HostEntry>, WakeError>,
+    refresh: impl FnOnce() -> bool,
+    stage: &StageTracker,
+    force: bool,
+) -> Result<Found, WakeError> {
+    let found = |macs, source, name: &str| Found {
+        macs,
+        resolved: None,
+        source,
+        name: name.to_owned(),
+    };
+    if let Some(configured) = state.static_host(host) {
+        let name = state.registry.name(host).unwrap_or_else(|| host.to_owned());
+        let in_config = (state.config.hosts.iter()).any(|static_host| static_host.name == name);
+        let source = if in_config {
+            ResolveSource::Static
+        } else {
+            ResolveSource::Registry
+        };
+        return Ok(found(configured, source, &name));
+    }
+    let mut table = discover()?;
+    if table.is_empty() {
+        // like after the server booted, only the registry is there to find it in
+        if let Some(registered) = state
+            .registry
+            .all()
+            .into_iter()
+            .find(|registered| registered.name.contains(host))
+        {
+            let source = ResolveSource::Registry;
+            return Ok(found(registered.macs, source, &registered.name));
+        }
+        refresh_empty_table(state);
+    }
+    if let Some((name, macs)) = discovery::find_host_named(&table, host) {
+        let macs = check_pinned(state, host, macs, force)?;
+        return Ok(found(macs, ResolveSource::Discovered, name));
+    }
+    if let Some(pinned) = state.pinned_mac(host) {
+        return Ok(found(vec![pinned], ResolveSource::Pinned, host));
+    }
+    if refresh() && refresh_neighbors(state, host) {
+        table = discover()?;
+        if let Some((name, macs)) = discovery::find_host_named(&table, host) {
+            return Ok(found(macs, ResolveSource::Discovered, name));
+        }
+    }
+    if !host.contains('.') {
+        return Err(if table.is_empty() {
+            WakeError::NothingDiscovered(host.to_owned())
+        } else {
+            WakeError::HostNotFound(host.to_owned())
+        });
+    }
+    let (macs, resolved) = resolve_name(host, &table, discover, stage)?;
+    Ok(Found {
+        resolved: Some(resolved),
+        ..found(macs, ResolveSource::Dns, host)
+    })
+}
+
+/// Finds a host by the addresses its name resolves to, in the neighbor table or, if it's not in
+/// there, after poking them.
+fn resolve_name(
+    host: &str,
+    table: &[HostEntry],
+    discover: impl Fn() -> Result<Vec<HostEntry>, WakeError>,
+    stage: &StageTracker,
+) -> Result<(Vec<MacAddress>, ResolvedName), WakeError> {
+    stage.set(WakeStage::NameLookup);
+    let ips = match dns_lookup::lookup_host(host) {
+        Ok(ips) => ips.collect::<Vec<_>>(),
+        Err(e) => {
+            return Err(WakeError::NameNotResolved {
+                host: host.to_owned(),
+                error: e.to_string(),
+            })
+        }
+    };
+    let find = |table: &[HostEntry], mac_source| {
+        ips.iter().find_map(|&ip| {
+            let mut macs = Vec::new();
+            for entry in table.iter().filter(|entry| entry.ip == Some(ip)) {
+                if !macs.contains(&entry.mac) {
+                    macs.push(entry.mac);
+                }
+            }
+            (!macs.is_empty()).then_some((macs, ResolvedName { ip, mac_source }))
+        })
+    };
+    if let Some(found) = find(table, MacSource::NeighborTable) {
+        tracing::debug!(%host, ip = %found.1.ip, "found host by the address of its name");
+        return Ok(found);
+    }
+
+    stage.set(WakeStage::NeighborRefresh);
+    let poked = discovery::poke(&ips);
+    tracing::info!(%host, ?ips, poked, "Address of host not in neighbor table, refreshing");
+    if poked > 0 {
+        std::thread::sleep(NEIGHBOR_REFRESH_WAIT);
+        if let Some(found) = find(&discover()?, MacSource::NeighborRefresh) {
+            return Ok(found);
+        }
+    }
+    Err(WakeError::NoMacForName {
+        host: host.to_owned(),
+        ips,
+    })
+}
+
+/// How long the kernel gets to hear back from hosts after a refresh.
+const NEIGHBOR_REFRESH_WAIT: Duration = Duration::from_secs(1);
+/// Sweeps larger than this are cut off, a refresh is supposed to be quick.
+const MAX_SWEEP: usize = 1024;
+
+/// Sends a packet to every address the host might have (the one it last had, what its name
+/// resolves to, and the whole sweep network if configured), which makes the kernel look for it
+/// in the neighbor table, and gives it a moment to answer.
+/// Returns `false` without waiting if there was nowhere to send anything.
+fn refresh_neighbors(state: &AppState, host: &str) -> bool {
+    let mut targets = state.known_ips(host);
+    match dns_lookup::lookup_host(host) {
+        Ok(ips) => targets.extend(ips),
+        Err(e) => tracing::debug!(?e, %host, "host name doesn't resolve"),
+    }
+    if let Some(sweep) = state.config.neighbor_sweep {
+        targets.extend(sweep.hosts().take(MAX_SWEEP));
+    }
+    targets.sort();
+    targets.dedup();
+
+    let poked = discovery::poke(&targets);
+    tracing::info!(%host, targets = targets.len(), poked, "Host not in neighbor table, refreshing");
+    if poked == 0 {
+        return false;
+    }
+    std::thread::sleep(NEIGHBOR_REFRESH_WAIT);
+    true
+}
+
+/// What's wrong when discovery finds nothing, as a wake and `/healthz` say it.
+pub(super) const NOTHING_DISCOVERED: &str = "no hosts are discovered yet, the neighbor table is \
+     empty. configure the host in `hosts` or wait for discovery";
+
+/// Pokes every address a host was seen at and the sweep network in the background, so the
+/// neighbor table that's empty has something in it for the next wake.
+fn refresh_empty_table(state: &AppState) {
+    let mut targets = state
+        .known_ips
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .map(|(ip, _)| *ip)
+        .collect::<Vec<_>>();
+    if let Some(sweep) = state.config.neighbor_sweep {
+        targets.extend(sweep.hosts().take(MAX_SWEEP));
+    }
+    targets.sort();
+    targets.dedup();
+    tracing::warn!(
+        targets = targets.len(),
+        "neighbor table is empty, refreshing it"
+    );
+    if !targets.is_empty() {
+        std::thread::spawn(move || discovery::poke(&targets));
+    }
+}
+
+impl AppState {
+    /// Remembers the IP every discovered name had, `table` is what `hosts` was resolved from.
+    fn remember_ips(&self, hosts: &[HostEntry]) {
+        let mut known_ips = self.known_ips.lock().unwrap_or_else(|e| e.into_inner());
+        for entry in hosts {
+            if let Some(ip) = entry.ip {
+                known_ips.insert(entry.name.clone(), (ip, Instant::now()));
+            }
+        }
+        drop(known_ips);
+        self.trim_hosts();
+    }
+
+    /// The IPs of the hosts with a name containing `host` when they were last discovered.
+    fn known_ips(&self, host: &str) -> Vec<IpAddr> {
+        let known_ips = self.known_ips.lock().unwrap_or_else(|e| e.into_inner());
+        known_ips
+            .iter()
+            .filter(|(name, _)| name.contains(host))
+            .map(|(_, (ip, _))| *ip)
+            .collect()
+    }
+}
+
+/// Sends a magic packet for each of the MACs to every destination, only on the interface if
+/// there is one (or just figures out where they would go for a dry run). For a host with
+/// `destinations`, those are tried in order instead.
+fn send_wake(
+    state: &AppState,
+    host: Option<&str>,
+    macs: &[MacAddress],
+    site: Option<&Site>,
+    interfaces: &[String],
+    source: Option<IpAddr>,
+    dry_run: bool,
+) -> Vec<Destination> {
+    let fallback = host.map_or(&[][..], |host| state.host_destinations(host));
+    if !fallback.is_empty() && !dry_run {
+        return macs
+            .iter()
+            .flat_map(|mac| send_in_order(state, *mac, fallback, interfaces, source))
+            .collect();
+    }
+    let destinations = match fallback {
+        [] => state.destinations(site),
+        // a dry run shows all of them, in the order they'd be tried
+        fallback => fallback.to_vec(),
+    };
+    macs.iter()
+        .flat_map(|mac| send_wake_one(state, *mac, &destinations, interfaces, source, dry_run))
+        .collect()
+}
+
+/// Sends to the first of the destinations, and to the next if that fails, until a packet got
+/// out. Only the ones that were tried are returned.
+fn send_in_order(
+    state: &AppState,
+    mac: MacAddress,
+    destinations: &[SocketAddr],
+    interfaces: &[String],
+    source: Option<IpAddr>,
+) -> Vec<Destination> {
+    let mut tried = Vec::new();
+    for (index, &address) in destinations.iter().enumerate() {
+        let sent = send_wake_one(state, mac, &[address], interfaces, source, false);
+        let worked = sent.iter().any(|report| report.sent);
+        tried.extend(sent);
+        if worked {
+            break;
+        }
+        if let Some(next) = destinations.get(index + 1) {
+            tracing::warn!(%mac, %address, %next, "send failed, falling back to the next destination");
+        }
+    }
+    tried
+}
+
+/// The address the last packet for the MAC got out to.
+pub(super) fn used_destination(sent: &[Destination], mac: MacAddress) -> Option<SocketAddr> {
+    let mac = mac.to_string();
+    (sent.iter().rev())
+        .find(|report| report.sent && report.mac == mac)
+        .map(|report| report.address)
+}
+
+/// For a host with `destinations`, notes which of them each of the MACs was woken by with the
+/// wake, returning the one of the first MAC.
+fn record_destination(
+    state: &AppState,
+    host: &str,
+    macs: &[MacAddress],
+    id: &str,
+    sent: &[Destination],
+) -> Option<SocketAddr> {
+    if state.host_destinations(host).is_empty() {
+        return None;
+    }
+    state.record_destination(macs, id, sent);
+    used_destination(sent, macs[0])
+}
+
+/// The packet for the MAC, with its host's SecureOn password if it has one.
+pub(super) fn magic_packet(state: &AppState, mac: MacAddress) -> MagicPacket {
+    match state.registry.password(mac) {
+        Some(password) => MagicPacket::new(&mac.0).with_password(password),
+        None => MagicPacket::new(&mac.0),
+    }
+}
+
+/// Sends to each destination on the first of the interfaces, and on the next if that fails.
+/// Failures because the network is down are `queued` if there's a send queue, that's only
+/// true once [`queue_unsent`] queued them though.
+pub(super) fn send_wake_one(
+    state: &AppState,
+    mac: MacAddress,
+    destinations: &[SocketAddr],
+    interfaces: &[String],
+    source: Option<IpAddr>,
+    dry_run: bool,
+) -> Vec<Destination> {
+    // without any, sending isn't restricted to an interface
+    let interfaces = match interfaces {
+        [] => vec![None],
+        interfaces => interfaces
+            .iter()
+            .map(|interface| Some(interface.as_str()))
+            .collect(),
+    };
+    let magic_packet = magic_packet(state, mac);
+    destinations
+        .iter()
+        .map(|&address| {
+            if dry_run {
+                let interface = interfaces[0];
+                return Destination {
+                    mac: mac.to_string(),
+                    address,
+                    source: state.sender.local_addr(interface, source),
+                    interface: interface.map(str::to_owned),
+                    sent: false,
+                    attempts: 0,
+                    error: None,
+                    hint: None,
+                    skipped: Vec::new(),
+                    queued: false,
+                    reported_errors: None,
+                };
+            }
+            let mut skipped = Vec::new();
+            let mut attempts = 0;
+            let mut remaining = interfaces.iter().peekable();
+            let (interface, result) = loop {
+                let &interface = remaining.next().expect("at least one interface");
+                let Attempts {
+                    result,
+                    attempts: tried,
+                } = state
+                    .config
+                    .retry
+                    .run(|| state.sender.send(&magic_packet, address, interface, source));
+                attempts += tried;
+                match (result, remaining.peek()) {
+                    (Err(e), Some(&&next)) => {
+                        let interface = interface.unwrap_or_default();
+                        let error = format!("{:#}", eyre::Report::new(e));
+                        tracing::warn!(%mac, %address, interface, next, %error, "send failed, failing over to the next interface");
+                        state.count_failover(interface);
+                        skipped.push(SkippedInterface {
+                            interface: interface.to_owned(),
+                            error,
+                        });
+                    }
+                    (result, _) => break (interface, result),
+                }
+            };
+            match result {
+                Ok(Sent { source, reported }) => Destination {
+                    mac: mac.to_string(),
+                    address,
+                    source: Some(source),
+                    interface: interface.map(str::to_owned),
+                    sent: true,
+                    attempts,
+                    error: None,
+                    hint: None,
+                    skipped,
+                    queued: false,
+                    reported_errors: reported,
+                },
+                Err(e) => {
+                    let source = state.sender.local_addr(interface, source);
+                    Destination {
+                        mac: mac.to_string(),
+                        address,
+                        source,
+                        interface: interface.map(str::to_owned),
+                        sent: false,
+                        attempts,
+                        hint: sender::hint(&e, address, source),
+                        queued: state.config.send_queue.is_some() && sender::is_network_down(&e),
+                        error: Some(format!("{:#}", eyre::Report::new(e))),
+                        skipped,
+                        reported_errors: None,
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+async fn wake_batch(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    body: WakeBody<BatchWakeRequest>,
+) -> Response {
+    let WakeBody {
+        params: request,
+        format,
+    } = body;
+    let context = from_page(context, format);
+    let error = |status: StatusCode, message: &str| match format {
+        ResponseFormat::Json => (status, message.to_owned()).into_response(),
+        ResponseFormat::Html => format.error(status, message.to_owned()),
+    };
+    if request.hosts.is_empty() && request.pattern.is_none() && request.location.is_none() {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "no hosts, pattern or location given",
+        );
+    }
+
+    tracing::info!(hosts = ?request.hosts, pattern = ?request.pattern, location = ?request.location, client = ?context.client, principal = ?context.principal, source = %context.source, "Waking batch");
+    let (request_force, confirm) = (request.force, request.include_confirmation_required);
+    let targets = match tokio::task::spawn_blocking({
+        let state = state.clone();
+        move || batch_targets(&state, &request)
+    })
+    .await
+    {
+        Ok(Ok(targets)) => targets,
+        Ok(Err(e)) => {
+            tracing::error!(?e, "failed to wake batch");
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "error");
+        }
+        Err(e) => {
+            tracing::error!(?e, "join error");
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "failed to spawn");
+        }
+    };
+
+    if targets.is_empty() {
+        return error(StatusCode::NOT_FOUND, "no hosts matched");
+    }
+    let results = wake_all(&state, targets, &context, request_force, confirm).await;
+
+    let sent = results.iter().filter(|result| result.sent).count();
+    let status = if sent == results.len() {
+        StatusCode::OK
+    } else if sent == 0 {
+        StatusCode::BAD_GATEWAY
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+    let response = BatchWakeResponse {
+        all_sent: sent == results.len(),
+        results,
+        source: context.source.to_string(),
+    };
+    match format {
+        ResponseFormat::Json => (status, Json(response)).into_response(),
+        ResponseFormat::Html => {
+            let results = response
+                .results
+                .iter()
+                .map(|result| {
+                    let outcome = match &result.error {
+                        Some(error) => html_escape(error),
+                        None => "sent".to_owned(),
+                    };
+                    format!("<li><b>{}</b>: {outcome}</li>", html_escape(&result.host))
+                })
+                .collect::<String>();
+            (status, html_page("Sent", &format!("<ul>{results}</ul>"))).into_response()
+        }
+    }
+}
+
+/// The hosts of a batch, in the order they were asked for and then those the pattern and the
+/// location matched, with their MACs if they were found. Only configured hosts have a location,
+/// the discovered ones are in the empty one. Those with `require_confirmation` are only matched
+/// when the request includes them.
+fn batch_targets(state: &AppState, request: &BatchWakeRequest) -> eyre::Result<Vec<BatchTarget>> {
+    let hosts = state.discover_hosts()?;
+    let included = |name: &str, macs: &[MacAddress]| {
+        request.include_confirmation_required
+            || !state.registry.requires_confirmation(Some(name), macs)
+    };
+
+    let mut targets = request
+        .hosts
+        .iter()
+        .map(|name| {
+            let macs = state
+                .static_host(name)
+                .or_else(|| discovery::find_host(&hosts, name));
+            (name.clone(), macs)
+        })
+        .collect::<Vec<BatchTarget>>();
+    if let Some(pattern) = &request.pattern {
+        for entry in hosts
+            .iter()
+            .filter(|entry| entry.name.contains(pattern.as_str()))
+            .filter(|entry| included(&entry.name, &[entry.mac]))
+        {
+            add_discovered(&mut targets, entry);
+        }
+    }
+    if let Some(location) = &request.location {
+        for host in state
+            .registry
+            .all()
+            .into_iter()
+            .filter(|host| in_location(host.location.as_deref(), location))
+            .filter(|host| included(&host.name, &host.macs))
+        {
+            if !targets.iter().any(|(name, _)| *name == host.name) {
+                targets.push((host.name, Some(host.macs)));
+            }
+        }
+        if in_location(None, location) {
+            for entry in hosts
+                .iter()
+                .filter(|entry| !state.registry.contains_mac(entry.mac))
+            {
+                add_discovered(&mut targets, entry);
+            }
+        }
+    }
+
+    Ok(targets)
+}
+
+/// A host with several NICs is only woken once, with all of them.
+fn add_discovered(targets: &mut Vec<BatchTarget>, entry: &HostEntry) {
+    match targets.iter_mut().find(|(name, _)| *name == entry.name) {
+        Some((_, Some(macs))) if !macs.contains(&entry.mac) => macs.push(entry.mac),
+        Some(_) => {}
+        None => targets.push((entry.name.clone(), Some(vec![entry.mac]))),
+    }
+}
+
+/// A host of a batch, with its MACs if it was found.
+type BatchTarget = (String, Option<Vec<MacAddress>>);
+
+/// Wakes the hosts, `batch_concurrency` at a time, with the results in the order of the targets.
+/// Those with `require_confirmation` are only woken if the batch `confirm`s them.
+/// If the request is dropped (like when the client goes away) the wakes that didn't start yet
+/// are aborted, when the server shuts down those that aren't done are given up on.
+async fn wake_all(
+    state: &Arc<AppState>,
+    targets: Vec<BatchTarget>,
+    context: &RequestContext,
+    force: bool,
+    confirm: bool,
+) -> Vec<HostWakeResult> {
+    let hosts = targets
+        .iter()
+        .map(|(host, _)| host.clone())
+        .collect::<Vec<_>>();
+    let permits = Arc::new(Semaphore::new(state.config.batch_concurrency));
+    let mut tasks = JoinSet::new();
+    for (index, (host, macs)) in targets.into_iter().enumerate() {
+        let state = state.clone();
+        let context = context.clone();
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            // it's never closed
+            let _permit = permits.acquire_owned().await;
+            let result = tokio::task::spawn_blocking(move || {
+                let id = new_wake_id();
+                state.events.publish(Event::WakeRequested {
+                    id: id.clone(),
+                    host: Some(host.clone()),
+                    mac: None,
+                    source: context.source.to_string(),
+                });
+                let result = wake_batch_host(&state, &id, host, macs, &context, force, confirm);
+                state.events.publish(match &result.error {
+                    None if result.sent => Event::WakeSent {
+                        id,
+                        host: Some(result.host.clone()),
+                        macs: result.macs.clone(),
+                        destinations: result.destinations.clone(),
+                        site: result.site.clone(),
+                        dry_run: false,
+                    },
+                    error => Event::WakeFailed {
+                        id,
+                        host: Some(result.host.clone()),
+                        error: error.clone().unwrap_or_else(|| "not sent".to_owned()),
+                    },
+                });
+                result
+            })
+            .await;
+            (index, result)
+        });
+    }
+
+    let mut results = hosts.iter().map(|_| None).collect::<Vec<_>>();
+    let mut shutdown = state.shutdown.subscribe();
+    loop {
+        tokio::select! {
+            joined = tasks.join_next() => match joined {
+                Some(Ok((index, Ok(result)))) => results[index] = Some(result),
+                Some(Ok((index, Err(e)))) => {
+                    tracing::error!(?e, host = %hosts[index], "join error");
+                    results[index] = Some(not_woken(hosts[index].clone(), "failed to spawn"));
+                }
+                Some(Err(e)) => tracing::error!(?e, "join error"),
+                None => break,
+            },
+            () = async { let _ = shutdown.wait_for(|&down| down).await; } => {
+                let left = tasks.len();
+                tracing::warn!(left, "server is shutting down, giving up on the rest of the batch");
+                tasks.abort_all();
+                break;
+            }
+        }
+    }
+    results
+        .into_iter()
+        .zip(hosts)
+        .map(|(result, host)| {
+            result.unwrap_or_else(|| not_woken(host, "not woken, the server is shutting down"))
+        })
+        .collect()
+}
+
+/// The result of a host of a batch that can't be woken.
+fn not_woken(host: String, error: &str) -> HostWakeResult {
+    HostWakeResult {
+        host,
+        mac: None,
+        macs: Vec::new(),
+        destinations: Vec::new(),
+        site: None,
+        sent: false,
+        error: Some(error.to_owned()),
+    }
+}
+
+/// Wakes one host of a batch, like a wake of it alone would. This blocks until it's sent.
+fn wake_batch_host(
+    state: &AppState,
+    id: &str,
+    host: String,
+    macs: Option<Vec<MacAddress>>,
+    context: &RequestContext,
+    force: bool,
+    confirm: bool,
+) -> HostWakeResult {
+    let macs = match macs.map(|macs| check_pinned(state, &host, macs, force)) {
+        Some(Ok(macs)) => macs,
+        Some(Err(e)) => {
+            let error = e.status_and_message().1;
+            return not_woken(host, &error);
+        }
+        None => match state.pinned_mac(&host) {
+            Some(pinned) => vec![pinned],
+            None => {
+                tracing::warn!(%host, "host not found");
+                return not_woken(host, "host not found");
+            }
+        },
+    };
+    let allowed = confirmed(state, Some(&host), &macs, context, confirm)
+        .and_then(|()| quiet_hours(state, Some(&host), &macs, id, context, force));
+    if let Err(e) = allowed {
+        return HostWakeResult {
+            host,
+            mac: Some(macs[0].to_string()),
+            macs: macs.iter().map(MacAddress::to_string).collect(),
+            destinations: Vec::new(),
+            site: None,
+            sent: false,
+            error: Some(e.status_and_message().1),
+        };
+    }
+    let site = state.site(Some(&host), &macs);
+    if let Some((site, relay)) = site.and_then(|site| Some((site, Relay::of(site)?))) {
+        let request = WakeRequest {
+            host: Some(host.clone()),
+            confirm,
+            ..WakeRequest::default()
+        };
+        let result = relay_wake(state, site, relay, &request, &macs, id, context);
+        let (destinations, error) = match result {
+            Ok(response) => {
+                tracing::info!(hostname = %host, ?macs, site = %site.name, destinations = ?response.destinations, client = ?context.client, principal = ?context.principal, "Woken by remote site");
+                (response.destinations, None)
+            }
+            Err(e) => (Vec::new(), Some(e.status_and_message().1)),
+        };
+        return HostWakeResult {
+            host,
+            mac: Some(macs[0].to_string()),
+            macs: macs.iter().map(MacAddress::to_string).collect(),
+            destinations,
+            site: Some(site.name.clone()),
+            sent: error.is_none(),
+            error,
+        };
+    }
+    let interfaces = state.interfaces(Some(&host), site);
+    let source = state.source(Some(&host), site);
+    let checked = match interfaces.as_slice() {
+        [interface] => sender::check_interface(interface),
+        _ => Ok(()),
+    }
+    .and_then(|()| source.map_or(Ok(()), sender::check_source));
+    if let Err(error) = checked {
+        tracing::error!(%host, %error, "failed to wake");
+        return HostWakeResult {
+            host,
+            mac: Some(macs[0].to_string()),
+            macs: macs.iter().map(MacAddress::to_string).collect(),
+            destinations: Vec::new(),
+            site: site.map(|site| site.name.clone()),
+            sent: false,
+            error: Some(error),
+        };
+    }
+    if !state.strategies(&host).is_empty() {
+        let tried = strategies::run(state, &host, &macs, site, source, |_| {});
+        let sent = strategies::record(state, &host, &macs, id, &tried, context);
+        record_destination(state, &host, &macs, id, &tried.destinations);
+        tracing::info!(hostname = %host, ?macs, strategy = ?tried.worked, client = ?context.client, principal = ?context.principal, "Woke up with strategies");
+        let error = (!sent).then(|| {
+            format!(
+                "no strategy got a packet out: {}",
+                strategies::summary(&tried.attempts)
+            )
+        });
+        return HostWakeResult {
+            host,
+            mac: Some(macs[0].to_string()),
+            macs: macs.iter().map(MacAddress::to_string).collect(),
+            destinations: tried.destinations,
+            site: site.map(|site| site.name.clone()),
+            sent,
+            error,
+        };
+    }
+    let mut destinations = send_wake(state, Some(&host), &macs, site, &interfaces, source, false);
+    queue_unsent(
+        state,
+        Some(&host),
+        &macs,
+        id,
+        &interfaces,
+        &mut destinations,
+        context,
+    );
+    let sent = record_wakes(state, Some(&host), &macs, id, &destinations, context);
+    record_destination(state, &host, &macs, id, &destinations);
+    if sent {
+        tracing::info!(hostname = %host, ?macs, ?destinations, client = ?context.client, principal = ?context.principal, "Woke up");
+    } else {
+        tracing::error!(hostname = %host, ?macs, ?destinations, client = ?context.client, principal = ?context.principal, "failed to wake");
+    }
+    let error = (!sent).then(|| {
+        format!(
+            "failed to send packet: {}",
+            Destination::summary(&destinations)
+        )
+    });
+    HostWakeResult {
+        host,
+        mac: Some(macs[0].to_string()),
+        macs: macs.iter().map(MacAddress::to_string).collect(),
+        destinations,
+        site: site.map(|site| site.name.clone()),
+        sent,
+        error,
+    }
+}
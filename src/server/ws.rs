@@ -0,0 +1,335 @@
+//! `GET /ws`, a WebSocket that wakes hosts and says how they come up, so pages don't have to
+//! poll for it.
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{sync::mpsc, task::AbortHandle};
+
+use super::{
+    wait::{self, wait_until_online},
+    wake::wake_request,
+    AppState, RequestContext, WakeSource,
+};
+use crate::api::v1::{ErrorResponse, WakeRequest, WatchAction, WatchEvent, WatchRequest};
+
+pub(super) fn routes() -> Router<Arc<AppState>> {
+    Router::new().route("/ws", get(upgrade))
+}
+
+/// How long clients get to answer the close frame when the server shuts down.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Events that are waiting for a slow client, watches wait for it beyond that.
+const BUFFERED_EVENTS: usize = 16;
+/// Longer messages close the socket.
+const MAX_MESSAGE: usize = 64 * 1024;
+
+impl AppState {
+    /// Closes every WebSocket, waiting a little for their clients to say goodbye.
+    pub async fn shut_down(&self) {
+        self.shutdown.send_replace(true);
+        let closed = tokio::time::timeout(CLOSE_TIMEOUT * 2, self.shutdown.closed()).await;
+        if closed.is_err() {
+            let open = self.shutdown.receiver_count();
+            tracing::warn!(open, "WebSockets didn't close in time");
+        }
+    }
+}
+
+async fn upgrade(
+    State(state): State<Arc<AppState>>,
+    context: RequestContext,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let context = RequestContext {
+        source: WakeSource::WebSocket,
+        ..context
+    };
+    // browsers send their credentials along to WebSockets of any site
+    if !same_origin(&headers) {
+        tracing::warn!(origin = ?headers.get(header::ORIGIN), client = ?context.client, "rejected cross-origin WebSocket");
+        return (
+            StatusCode::FORBIDDEN,
+            "cross-origin WebSockets aren't allowed",
+        )
+            .into_response();
+    }
+    upgrade
+        .max_message_size(MAX_MESSAGE)
+        .on_failed_upgrade(|e| tracing::warn!(?e, "WebSocket upgrade failed"))
+        .on_upgrade(move |socket| serve(state, context, socket))
+}
+
+/// Whether the page that opened the socket is served by us, clients that aren't browsers don't
+/// say where they're from.
+fn same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN) else {
+        return true;
+    };
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    let origin = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.split_once("://"))
+        .map(|(_, authority)| authority);
+    matches!((origin, host), (Some(origin), Some(host)) if origin.eq_ignore_ascii_case(host))
+}
+
+/// How the connection ends.
+enum Closing {
+    /// The client sent a close frame, which is answered.
+    ByClient,
+    Shutdown,
+    /// The client sent something binary, only text is spoken.
+    Binary,
+    /// The connection is gone, there's nobody to tell.
+    Gone,
+}
+
+async fn serve(state: Arc<AppState>, context: RequestContext, mut socket: WebSocket) {
+    let mut shutdown = state.shutdown.subscribe();
+    tracing::debug!(client = ?context.client, "WebSocket opened");
+
+    let mut watches = Watches::default();
+    let (events_tx, mut events) = mpsc::channel(BUFFERED_EVENTS);
+    let closing = loop {
+        let event = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => watches.handle(&state, &context, &text, &events_tx),
+                // pings are answered while reading
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => None,
+                Some(Ok(Message::Binary(_))) => break Closing::Binary,
+                Some(Ok(Message::Close(_))) => break Closing::ByClient,
+                Some(Err(e)) => {
+                    tracing::debug!(?e, "WebSocket read failed");
+                    break Closing::Gone;
+                }
+                None => break Closing::Gone,
+            },
+            Some((serial, event)) = events.recv() => watches.current(serial, &event).then_some(event),
+            // the guard it returns isn't Send
+            () = async { let _ = shutdown.wait_for(|&down| down).await; } => break Closing::Shutdown,
+        };
+        let Some(event) = event else {
+            continue;
+        };
+        let text = serde_json::to_string(&event).expect("events serialize");
+        if let Err(e) = socket.send(Message::Text(text.into())).await {
+            tracing::debug!(?e, "WebSocket write failed");
+            break Closing::Gone;
+        }
+    };
+    watches.cancel_all();
+
+    let close = match closing {
+        // the answer to it goes out with the next read
+        Closing::ByClient => None,
+        Closing::Binary => Some((close_code::UNSUPPORTED, "only text messages are supported")),
+        Closing::Shutdown => Some((close_code::AWAY, "server shutting down")),
+        Closing::Gone => {
+            tracing::debug!(client = ?context.client, "WebSocket closed");
+            return;
+        }
+    };
+    if let Some((code, reason)) = close {
+        let frame = CloseFrame {
+            code,
+            reason: reason.into(),
+        };
+        if let Err(e) = socket.send(Message::Close(Some(frame))).await {
+            tracing::debug!(?e, "failed to close WebSocket");
+        }
+    }
+    // whatever else the client sends before its close frame doesn't matter anymore
+    let _ = tokio::time::timeout(CLOSE_TIMEOUT, async {
+        while let Some(Ok(_)) = socket.recv().await {}
+    })
+    .await;
+    tracing::debug!(client = ?context.client, "WebSocket closed");
+}
+
+/// The watches of one connection by their id, each with a serial so the events of a cancelled
+/// watch are told apart from those of a new one with the same id.
+#[derive(Default)]
+struct Watches {
+    running: HashMap<String, (u64, AbortHandle)>,
+    next_serial: u64,
+}
+
+type Events = mpsc::Sender<(u64, WatchEvent)>;
+
+impl Watches {
+    /// Starts or cancels a watch, returning the event to answer with right away if there is one.
+    fn handle(
+        &mut self,
+        state: &Arc<AppState>,
+        context: &RequestContext,
+        text: &str,
+        events: &Events,
+    ) -> Option<WatchEvent> {
+        let request: WatchRequest = match serde_json::from_str(text) {
+            Ok(request) => request,
+            Err(e) => {
+                let id = serde_json::from_str::<serde_json::Value>(text)
+                    .ok()
+                    .and_then(|value| Some(value.get("id")?.as_str()?.to_owned()));
+                return Some(error(
+                    id,
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid message: {e}"),
+                ));
+            }
+        };
+        let id = request.id;
+        match request.action {
+            WatchAction::Cancel => match self.running.remove(&id) {
+                Some((_, task)) => {
+                    task.abort();
+                    Some(WatchEvent::Cancelled { id })
+                }
+                None => {
+                    let message = format!("no watch with id `{id}` is running");
+                    Some(error(Some(id), StatusCode::NOT_FOUND, message))
+                }
+            },
+            WatchAction::Wake if self.running.contains_key(&id) => {
+                let message = format!("a watch with id `{id}` is running already");
+                Some(error(Some(id), StatusCode::CONFLICT, message))
+            }
+            WatchAction::Wake if request.wake.callback_url.is_some() => Some(error(
+                Some(id),
+                StatusCode::BAD_REQUEST,
+                "callbacks aren't supported over the WebSocket".to_owned(),
+            )),
+            WatchAction::Wake => {
+                let serial = self.next_serial;
+                self.next_serial += 1;
+                let task = tokio::spawn(watch(
+                    state.clone(),
+                    context.clone(),
+                    id.clone(),
+                    request.wake,
+                    (serial, events.clone()),
+                ));
+                self.running.insert(id, (serial, task.abort_handle()));
+                None
+            }
+        }
+    }
+
+    /// Whether the event is from a watch that's still running, forgetting the watch if it's
+    /// the last event.
+    fn current(&mut self, serial: u64, event: &WatchEvent) -> bool {
+        let id = match event {
+            WatchEvent::Sent { id, .. }
+            | WatchEvent::Probing { id, .. }
+            | WatchEvent::Online { id, .. }
+            | WatchEvent::Timeout { id, .. }
+            | WatchEvent::Cancelled { id } => id,
+            WatchEvent::Error { id: Some(id), .. } => id,
+            WatchEvent::Error { id: None, .. } => return true,
+        };
+        if self
+            .running
+            .get(id)
+            .is_none_or(|&(running, _)| running != serial)
+        {
+            return false;
+        }
+        let last = match event {
+            WatchEvent::Sent { wake, .. } => wake.dry_run,
+            WatchEvent::Probing { .. } => false,
+            _ => true,
+        };
+        if last {
+            self.running.remove(id);
+        }
+        true
+    }
+
+    fn cancel_all(&mut self) {
+        for (_, (_, task)) in self.running.drain() {
+            task.abort();
+        }
+    }
+}
+
+fn error(id: Option<String>, status: StatusCode, error: String) -> WatchEvent {
+    WatchEvent::Error {
+        id,
+        status: status.as_u16(),
+        error: ErrorResponse::new(error),
+    }
+}
+
+/// Wakes like `POST /wake` does, then waits for the host like `wait-online` does.
+async fn watch(
+    state: Arc<AppState>,
+    context: RequestContext,
+    id: String,
+    wake: WakeRequest,
+    (serial, events): (u64, Events),
+) {
+    let started = Instant::now();
+    let timeout = wake
+        .wait_online
+        .map(Duration::from_secs)
+        .unwrap_or(wait::DEFAULT_TIMEOUT)
+        .min(wait::MAX_TIMEOUT);
+    let send = |event| events.send((serial, event));
+
+    let (response, macs) = match wake_request(&state, wake, context).await {
+        Ok(woken) => woken,
+        Err((status, error)) => {
+            let _ = send(WatchEvent::Error {
+                id: Some(id),
+                status: status.as_u16(),
+                error,
+            })
+            .await;
+            return;
+        }
+    };
+    let dry_run = response.dry_run;
+    let sent = WatchEvent::Sent {
+        id: id.clone(),
+        wake: Box::new(response),
+    };
+    if send(sent).await.is_err() || dry_run {
+        return;
+    }
+    let probing = WatchEvent::Probing {
+        id: id.clone(),
+        timeout: timeout.as_secs(),
+    };
+    if send(probing).await.is_err() {
+        return;
+    }
+    let event = match wait_until_online(&state, macs, timeout).await {
+        Some(verified) => WatchEvent::Online {
+            id,
+            elapsed: started.elapsed().as_secs_f64(),
+            strategy: verified.strategy,
+        },
+        None => WatchEvent::Timeout {
+            id,
+            elapsed: started.elapsed().as_secs_f64(),
+        },
+    };
+    let _ = send(event).await;
+}
@@ -0,0 +1,331 @@
+//! `wakeonlan tui`, the hosts in the terminal to wake them from there. It runs the server's code
+//! without the server, and draws with ratatui on crossterm.
+
+use chrono::Local;
+use ratatui::{
+    crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::Style,
+    widgets::{Block, Borders, Paragraph, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+use std::{
+    collections::{HashSet, VecDeque},
+    io::{self, IsTerminal},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use wakeonlan::{
+    api::v1::{Event, Host, HostSource},
+    server::AppState,
+};
+
+/// How often the hosts are looked up again.
+const REFRESH: Duration = Duration::from_secs(5);
+/// How often the screen is drawn when nothing happens, so it follows the terminal's size.
+const REDRAW: Duration = Duration::from_millis(250);
+/// What the status pane keeps, it shows as many of the last ones as fit.
+const MESSAGES: usize = 100;
+const HELP: &str = "wakeonlan: up/down selects, enter wakes, r refreshes, q quits";
+
+/// The terminal in raw mode and on the alternate screen, until it's dropped. That happens when
+/// quitting and when `run` fails, wakes that are still running don't keep it. A panic restores it
+/// in ratatui's panic hook, before the message is printed.
+struct Terminal(DefaultTerminal);
+
+impl Terminal {
+    fn enter() -> io::Result<Terminal> {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return Err(io::Error::other("wakeonlan tui needs a terminal"));
+        }
+        ratatui::try_init().map(Terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Enter,
+    Refresh,
+    Quit,
+}
+
+/// The key of a key press, everything else is ignored.
+fn key(event: TermEvent) -> Option<Key> {
+    let TermEvent::Key(key) = event else {
+        return None;
+    };
+    if key.kind != KeyEventKind::Press {
+        return None;
+    }
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    Some(match key.code {
+        KeyCode::Up | KeyCode::Char('k') => Key::Up,
+        KeyCode::Down | KeyCode::Char('j') => Key::Down,
+        KeyCode::Enter => Key::Enter,
+        // ctrl-c and ctrl-d don't send signals in raw mode
+        KeyCode::Char('c' | 'd') if ctrl => Key::Quit,
+        KeyCode::Char('r') => Key::Refresh,
+        KeyCode::Char('q') | KeyCode::Esc => Key::Quit,
+        _ => return None,
+    })
+}
+
+/// Reads the keys on a thread of its own, which blocks in reading until the process exits.
+fn read_keys(keys: mpsc::UnboundedSender<Key>) {
+    while let Ok(event) = event::read() {
+        if let Some(key) = key(event) {
+            if keys.send(key).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+struct Screen {
+    hosts: Vec<Host>,
+    selected: usize,
+    /// The hosts that are being woken, or waited for.
+    waking: HashSet<String>,
+    messages: VecDeque<String>,
+}
+
+impl Screen {
+    fn message(&mut self, message: String) {
+        if self.messages.len() == MESSAGES {
+            self.messages.pop_front();
+        }
+        let at = Local::now().format("%H:%M:%S");
+        self.messages.push_back(format!("{at} {message}"));
+    }
+
+    /// Looks up the hosts again, keeping the one that's selected.
+    async fn refresh(&mut self, state: &Arc<AppState>) {
+        let selected = self.hosts.get(self.selected).map(|host| host.name.clone());
+        self.hosts = state.hosts().await;
+        self.selected = (self.hosts.iter())
+            .position(|host| Some(&host.name) == selected.as_ref())
+            .unwrap_or(self.selected)
+            .min(self.hosts.len().saturating_sub(1));
+    }
+
+    /// The host the MAC is one of, or the MAC.
+    fn name_of(&self, mac: &str) -> String {
+        (self.hosts.iter())
+            .find(|host| host.macs.iter().any(|known| known == mac))
+            .map_or_else(|| mac.to_owned(), |host| host.name.clone())
+    }
+
+    /// The status pane's line for the event, `None` for those the outcome of the wake says
+    /// enough about.
+    fn describe(&self, event: &Event) -> Option<String> {
+        match event {
+            Event::WakeSent {
+                host,
+                macs,
+                destinations,
+                ..
+            } => {
+                let host = host.clone().unwrap_or_else(|| macs.join(", "));
+                let mut addresses = Vec::new();
+                for destination in destinations {
+                    let address = destination.address.to_string();
+                    if !addresses.contains(&address) {
+                        addresses.push(address);
+                    }
+                }
+                let addresses = addresses.join(", ");
+                Some(format!("{host}: sent to {addresses}"))
+            }
+            Event::VerificationStarted { macs, strategies } => {
+                let host = self.name_of(macs.first()?);
+                let strategies = (strategies.iter())
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Some(format!("{host}: waiting for it to come up ({strategies})"))
+            }
+            Event::HostOnline {
+                host,
+                macs,
+                strategy,
+                seconds,
+            } => {
+                let host = host.clone().or_else(|| Some(self.name_of(macs.first()?)))?;
+                Some(match seconds {
+                    Some(seconds) => format!("{host}: up after {seconds:.1}s ({strategy})"),
+                    None => format!("{host}: up ({strategy})"),
+                })
+            }
+            Event::VerificationTimedOut { host, .. } => Some(format!("{host}: didn't come up")),
+            _ => None,
+        }
+    }
+
+    /// Draws what fits the terminal. The columns it's too narrow for are left out, and so is
+    /// the status pane when it's too short.
+    fn render(&self, frame: &mut Frame) {
+        let area = frame.area();
+        if area.width < 16 || area.height < 3 {
+            frame.render_widget(Paragraph::new("too small"), area);
+            return;
+        }
+        let status_rows = match area.height {
+            12.. => 5,
+            8.. => 2,
+            _ => 0,
+        };
+        let pane = if status_rows > 0 { status_rows + 1 } else { 0 };
+        let [help, hosts, status] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(1),
+            Constraint::Length(pane),
+        ])
+        .areas(area);
+        frame.render_widget(Paragraph::new(HELP), help);
+
+        let name_width = (self.hosts.iter())
+            .map(|host| host.name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .clamp(4, 24) as u16;
+        // the ones on the right are left out first
+        let mut columns = vec![("NAME", name_width), ("STATUS", 7)];
+        for column in [("LAST WAKE", 16), ("SOURCE", 10)] {
+            let used = columns.iter().map(|(_, width)| width + 2).sum::<u16>();
+            if used + column.1 > area.width {
+                break;
+            }
+            columns.push(column);
+        }
+
+        let rows = self.hosts.iter().map(|host| {
+            let status = match host.online {
+                _ if self.waking.contains(&host.name) => "waking",
+                Some(true) => "up",
+                Some(false) => "down",
+                None => "unknown",
+            };
+            let last_wake = host.last_wake.as_ref().map_or_else(
+                || "-".to_owned(),
+                |wake| {
+                    wake.at
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                },
+            );
+            let source = match host.source {
+                HostSource::Static => "static",
+                HostSource::Discovered => "discovered",
+                HostSource::Ssdp => "ssdp",
+                HostSource::Pinned => "pinned",
+            };
+            let cells = [
+                host.name.clone(),
+                status.to_owned(),
+                last_wake,
+                source.to_owned(),
+            ];
+            Row::new(cells.into_iter().take(columns.len()))
+        });
+        let header = Row::new(columns.iter().map(|(title, _)| *title));
+        let widths = columns.iter().map(|&(_, width)| Constraint::Length(width));
+        let table = Table::new(rows, widths)
+            .header(header)
+            .column_spacing(2)
+            .row_highlight_style(Style::new().reversed());
+        // it scrolls so that the selected host is shown
+        let mut state = TableState::new().with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, hosts, &mut state);
+        if self.hosts.is_empty() {
+            let [_, empty] =
+                Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(hosts);
+            frame.render_widget(Paragraph::new("no hosts"), empty);
+        }
+
+        if status_rows > 0 {
+            let skip = self.messages.len().saturating_sub(usize::from(status_rows));
+            let messages = self.messages.iter().skip(skip).map(String::as_str);
+            let messages = Paragraph::new(messages.collect::<Vec<_>>().join("\n"))
+                .block(Block::new().borders(Borders::TOP));
+            frame.render_widget(messages, status);
+        }
+    }
+}
+
+/// Shows the hosts until `q` is pressed. Only fails if the terminal can't be used.
+pub async fn run(state: AppState) -> io::Result<()> {
+    let state = Arc::new(state);
+    let mut events = state.events();
+    let mut screen = Screen {
+        hosts: state.hosts().await,
+        selected: 0,
+        waking: HashSet::new(),
+        messages: VecDeque::new(),
+    };
+    let mut terminal = Terminal::enter()?;
+    let (keys_sender, mut keys) = mpsc::unbounded_channel();
+    std::thread::spawn(move || read_keys(keys_sender));
+    let (woken_sender, mut woken) = mpsc::unbounded_channel();
+    let mut refresh = tokio::time::interval_at(tokio::time::Instant::now() + REFRESH, REFRESH);
+    let mut redraw = tokio::time::interval(REDRAW);
+
+    loop {
+        terminal.0.draw(|frame| screen.render(frame))?;
+        tokio::select! {
+            key = keys.recv() => match key {
+                None | Some(Key::Quit) => break,
+                Some(Key::Up) => screen.selected = screen.selected.saturating_sub(1),
+                Some(Key::Down) => {
+                    screen.selected = (screen.selected + 1).min(screen.hosts.len().saturating_sub(1));
+                }
+                Some(Key::Refresh) => screen.refresh(&state).await,
+                Some(Key::Enter) => {
+                    let Some(host) = screen.hosts.get(screen.selected).map(|host| host.name.clone()) else {
+                        continue;
+                    };
+                    if !screen.waking.insert(host.clone()) {
+                        continue;
+                    }
+                    screen.message(format!("{host}: waking"));
+                    let state = state.clone();
+                    let woken = woken_sender.clone();
+                    tokio::spawn(async move {
+                        let result = state.wake_and_wait(host.clone()).await;
+                        let _ = woken.send((host, result));
+                    });
+                }
+            },
+            Some((host, result)) = woken.recv() => {
+                screen.waking.remove(&host);
+                screen.message(match result {
+                    Ok(summary) => format!("{host}: {summary}"),
+                    Err(e) => format!("{host}: failed to wake: {e}"),
+                });
+                screen.refresh(&state).await;
+            }
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if let Some(message) = screen.describe(&event.event) {
+                        screen.message(message);
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => screen.message(format!("missed {missed} events")),
+                Err(RecvError::Closed) => {}
+            },
+            _ = refresh.tick() => screen.refresh(&state).await,
+            _ = redraw.tick() => {}
+        }
+    }
+    drop(terminal);
+    Ok(())
+}
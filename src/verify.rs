@@ -0,0 +1,276 @@
+//! Checking whether a host is up, for after it was sent a magic packet.
+
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+    net::{IpAddr, SocketAddr, TcpStream},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+pub mod icmp;
+
+pub use crate::api::v1::Strategy;
+
+#[derive(Debug)]
+pub enum ProbeError {
+    /// The strategy can't be used here at all (missing capability or tool, wrong address family),
+    /// so the next one should be tried.
+    Unavailable(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::Unavailable(reason) => write!(f, "unavailable: {reason}"),
+            ProbeError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl From<io::Error> for ProbeError {
+    fn from(e: io::Error) -> Self {
+        ProbeError::Io(e)
+    }
+}
+
+/// What [`check`] asks with when ICMP can't be used, SSH being the port that's most likely open
+/// (or at least refused) on a host that's up.
+pub const FALLBACK: Strategy = Strategy::Tcp(22);
+
+/// What a check found out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Verified {
+    pub online: bool,
+    /// The strategy that gave the answer.
+    pub strategy: Strategy,
+}
+
+/// Asks the host with each strategy in turn, the first one that's available gives the answer.
+/// Unavailable strategies are skipped, with a warning the first time for each kind of strategy.
+/// If ICMP can't be used and there's no TCP strategy to fall back to, [`FALLBACK`] is tried
+/// last. Returns an error if none of them could be used.
+pub fn check(strategies: &[Strategy], ip: IpAddr, timeout: Duration) -> eyre::Result<Verified> {
+    let no_tcp = !strategies.iter().any(|s| matches!(s, Strategy::Tcp(_)));
+    let mut fallback = None;
+    for &strategy in strategies {
+        match probe(strategy, ip, timeout) {
+            Ok(online) => return Ok(Verified { online, strategy }),
+            Err(ProbeError::Unavailable(reason)) => {
+                if strategy == Strategy::Icmp && no_tcp {
+                    fallback = Some(FALLBACK);
+                }
+                static WARNED: [AtomicBool; 3] = [const { AtomicBool::new(false) }; 3];
+                let kind = match strategy {
+                    Strategy::Arp => 0,
+                    Strategy::Tcp(_) => 1,
+                    Strategy::Icmp => 2,
+                };
+                if !WARNED[kind].swap(true, Ordering::Relaxed) {
+                    tracing::warn!(%strategy, %reason, "verify strategy unavailable, falling back");
+                } else {
+                    tracing::debug!(%strategy, %reason, "verify strategy unavailable, falling back");
+                }
+            }
+            Err(ProbeError::Io(e)) => {
+                tracing::warn!(%strategy, ?e, %ip, "verify probe failed, falling back");
+            }
+        }
+    }
+    if let Some(strategy) = fallback {
+        match probe(strategy, ip, timeout) {
+            Ok(online) => return Ok(Verified { online, strategy }),
+            Err(e) => tracing::warn!(%strategy, %e, %ip, "verify fallback failed"),
+        }
+    }
+    eyre::bail!("none of the verify strategies ({strategies:?}) could be used for {ip}")
+}
+
+/// Asks the host once, returning whether it answered within `timeout`.
+pub fn probe(strategy: Strategy, ip: IpAddr, timeout: Duration) -> Result<bool, ProbeError> {
+    match strategy {
+        Strategy::Arp => match ip {
+            IpAddr::V4(ip) => arp::probe(ip, timeout),
+            IpAddr::V6(_) => Err(ProbeError::Unavailable(
+                "ARP only works for IPv4 addresses".to_owned(),
+            )),
+        },
+        Strategy::Tcp(port) => {
+            match TcpStream::connect_timeout(&SocketAddr::new(ip, port), timeout) {
+                Ok(_) => Ok(true),
+                // something sent back a RST, so the host is up
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => Ok(true),
+                Err(e) if e.kind() == ErrorKind::TimedOut => Ok(false),
+                Err(e) if e.kind() == ErrorKind::HostUnreachable => Ok(false),
+                Err(e) => Err(e.into()),
+            }
+        }
+        Strategy::Icmp => icmp::ping(ip, timeout),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod arp {
+    use std::{
+        io, mem,
+        net::{IpAddr, Ipv4Addr},
+        os::fd::{AsRawFd, FromRawFd, OwnedFd},
+        time::{Duration, Instant},
+    };
+
+    use super::ProbeError;
+    use crate::interfaces;
+
+    const ETH_P_ARP: u16 = 0x0806;
+    const ETH_P_IP: u16 = 0x0800;
+    const ARP_REQUEST: u16 = 1;
+    const ARP_REPLY: u16 = 2;
+
+    /// The interface a target is on, which is the one with a subnet containing it.
+    struct Interface {
+        index: u32,
+        mac: [u8; 6],
+        ip: Ipv4Addr,
+    }
+
+    pub(super) fn probe(target: Ipv4Addr, timeout: Duration) -> Result<bool, ProbeError> {
+        // SAFETY: plain socket(2), the result is checked before it's owned
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                i32::from(ETH_P_ARP.to_be()),
+            )
+        };
+        if fd < 0 {
+            let e = io::Error::last_os_error();
+            return Err(match e.raw_os_error() {
+                Some(libc::EPERM | libc::EACCES) => {
+                    ProbeError::Unavailable("ARP probes need CAP_NET_RAW".to_owned())
+                }
+                _ => ProbeError::Io(e),
+            });
+        }
+        // SAFETY: the fd was just created and isn't owned by anything else
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let Some(interface) = find_interface(target)? else {
+            return Err(ProbeError::Unavailable(format!(
+                "no interface is on the same network as {target}"
+            )));
+        };
+
+        let mut request = [0u8; 28];
+        request[0..2].copy_from_slice(&1u16.to_be_bytes()); // ethernet
+        request[2..4].copy_from_slice(&ETH_P_IP.to_be_bytes());
+        request[4] = 6;
+        request[5] = 4;
+        request[6..8].copy_from_slice(&ARP_REQUEST.to_be_bytes());
+        request[8..14].copy_from_slice(&interface.mac);
+        request[14..18].copy_from_slice(&interface.ip.octets());
+        request[24..28].copy_from_slice(&target.octets());
+
+        // SAFETY: sockaddr_ll is plain old data
+        let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = ETH_P_ARP.to_be();
+        addr.sll_ifindex = interface.index as i32;
+        addr.sll_halen = 6;
+        addr.sll_addr[..6].copy_from_slice(&[0xff; 6]);
+        // SAFETY: the buffer and address are valid for their given lengths
+        let sent = unsafe {
+            libc::sendto(
+                socket.as_raw_fd(),
+                request.as_ptr().cast(),
+                request.len(),
+                0,
+                (&addr as *const libc::sockaddr_ll).cast(),
+                mem::size_of::<libc::sockaddr_ll>() as u32,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut reply = [0u8; 64];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            let mut pollfd = libc::pollfd {
+                fd: socket.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: one valid pollfd
+            let ready = unsafe { libc::poll(&mut pollfd, 1, remaining.as_millis().max(1) as i32) };
+            if ready < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e.into());
+            }
+            if ready == 0 {
+                return Ok(false);
+            }
+            // SAFETY: the buffer is valid for its length
+            let len = unsafe {
+                libc::recv(
+                    socket.as_raw_fd(),
+                    reply.as_mut_ptr().cast(),
+                    reply.len(),
+                    0,
+                )
+            };
+            if len < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            let reply = &reply[..len as usize];
+            // every ARP packet on the interface ends up here, only the answer from the target counts
+            if reply.len() >= 28
+                && reply[6..8] == ARP_REPLY.to_be_bytes()
+                && reply[14..18] == target.octets()
+            {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn find_interface(target: Ipv4Addr) -> io::Result<Option<Interface>> {
+        let target = IpAddr::V4(target);
+        Ok(interfaces::list()?.into_iter().find_map(|interface| {
+            let ip = interface
+                .addresses
+                .iter()
+                .find_map(|address| match address.ip {
+                    IpAddr::V4(ip) if !ip.is_loopback() && address.contains(target) => Some(ip),
+                    _ => None,
+                })?;
+            Some(Interface {
+                index: interface.index,
+                mac: interface.mac?.0,
+                ip,
+            })
+        }))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod arp {
+    use std::{net::Ipv4Addr, time::Duration};
+
+    use super::ProbeError;
+
+    pub(super) fn probe(_: Ipv4Addr, _: Duration) -> Result<bool, ProbeError> {
+        Err(ProbeError::Unavailable(
+            "ARP probes are only supported on Linux".to_owned(),
+        ))
+    }
+}
@@ -0,0 +1,324 @@
+//! ICMP echo probes without `ping`. They're sent from an unprivileged ICMP datagram socket where
+//! `net.ipv4.ping_group_range` allows those, otherwise from a raw socket, which needs
+//! `CAP_NET_RAW`. Every probe has a socket of its own and only counts the reply with its
+//! identifier and sequence number from the host it asked, so probes of different hosts at the
+//! same time don't take each other's replies.
+
+use std::{
+    net::IpAddr,
+    sync::atomic::{AtomicU16, Ordering},
+    time::Duration,
+};
+
+use super::ProbeError;
+
+pub const ECHO_REPLY_V4: u8 = 0;
+pub const ECHO_REQUEST_V4: u8 = 8;
+pub const ECHO_REQUEST_V6: u8 = 128;
+pub const ECHO_REPLY_V6: u8 = 129;
+
+/// What the echo requests carry, hosts send it back.
+const PAYLOAD: &[u8; 16] = b"wakeonlan probe\0";
+
+/// The internet checksum (RFC 1071). Over a message with its checksum filled in, it's 0 if the
+/// checksum is right.
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// An echo request. Only ICMPv4 ones have their checksum filled in, the one of ICMPv6 covers the
+/// addresses too, so the kernel fills it in.
+pub fn echo_request(v6: bool, identifier: u16, sequence: u16) -> Vec<u8> {
+    let kind = if v6 { ECHO_REQUEST_V6 } else { ECHO_REQUEST_V4 };
+    let mut packet = vec![kind, 0, 0, 0];
+    packet.extend(identifier.to_be_bytes());
+    packet.extend(sequence.to_be_bytes());
+    packet.extend(PAYLOAD);
+    if !v6 {
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+    packet
+}
+
+/// What identifies the request an echo reply is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EchoReply {
+    pub identifier: u16,
+    pub sequence: u16,
+}
+
+impl EchoReply {
+    /// Parses what an ICMP socket received, `None` for anything but an intact echo reply. Raw
+    /// ICMPv4 sockets receive the IPv4 header in front of it, which is skipped with `ip_header`.
+    pub fn parse(packet: &[u8], v6: bool, ip_header: bool) -> Option<EchoReply> {
+        let message = if ip_header {
+            let header_len = usize::from(packet.first()? & 0x0f) * 4;
+            packet.get(header_len..)?
+        } else {
+            packet
+        };
+        let kind = if v6 { ECHO_REPLY_V6 } else { ECHO_REPLY_V4 };
+        if message.len() < 8 || message[0] != kind || message[1] != 0 {
+            return None;
+        }
+        // the kernel already checked the one of ICMPv6
+        if !v6 && checksum(message) != 0 {
+            return None;
+        }
+        Some(EchoReply {
+            identifier: u16::from_be_bytes([message[4], message[5]]),
+            sequence: u16::from_be_bytes([message[6], message[7]]),
+        })
+    }
+
+    pub fn answers(&self, identifier: u16, sequence: u16) -> bool {
+        self.identifier == identifier && self.sequence == sequence
+    }
+}
+
+/// Different for every probe, so even the probes that share the identifier of the process on
+/// raw sockets are told apart.
+fn next_sequence() -> u16 {
+    static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+    SEQUENCE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Sends one echo request, returning whether the reply came within `timeout`.
+pub fn ping(target: IpAddr, timeout: Duration) -> Result<bool, ProbeError> {
+    sys::ping(target, timeout)
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{
+        io, mem,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr},
+        os::fd::{AsRawFd, FromRawFd, OwnedFd},
+        time::{Duration, Instant},
+    };
+
+    use super::{echo_request, next_sequence, EchoReply};
+    use crate::verify::ProbeError;
+
+    pub(super) fn ping(target: IpAddr, timeout: Duration) -> Result<bool, ProbeError> {
+        let v6 = target.is_ipv6();
+        let (socket, datagram) = open(v6)?;
+        let sequence = next_sequence();
+        // the kernel replaces the identifier of datagram sockets with their port
+        let request = echo_request(v6, std::process::id() as u16, sequence);
+        send_to(&socket, &request, target)?;
+        let identifier = if datagram {
+            local_port(&socket)?
+        } else {
+            std::process::id() as u16
+        };
+
+        let deadline = Instant::now() + timeout;
+        let mut reply = [0u8; 1500];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            let mut pollfd = libc::pollfd {
+                fd: socket.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // SAFETY: one valid pollfd
+            let ready = unsafe { libc::poll(&mut pollfd, 1, remaining.as_millis().max(1) as i32) };
+            if ready < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e.into());
+            }
+            if ready == 0 {
+                return Ok(false);
+            }
+            let (len, from) = recv_from(&socket, &mut reply)?;
+            // raw sockets get every echo reply that arrives, not just the ones to this probe
+            let ip_header = !datagram && !v6;
+            if from == Some(target)
+                && EchoReply::parse(&reply[..len], v6, ip_header)
+                    .is_some_and(|reply| reply.answers(identifier, sequence))
+            {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// A datagram socket if that's allowed, a raw one otherwise, with whether it's a datagram one.
+    fn open(v6: bool) -> Result<(OwnedFd, bool), ProbeError> {
+        let (domain, protocol) = if v6 {
+            (libc::AF_INET6, libc::IPPROTO_ICMPV6)
+        } else {
+            (libc::AF_INET, libc::IPPROTO_ICMP)
+        };
+        let denied = |e: &io::Error| {
+            matches!(
+                e.raw_os_error(),
+                Some(libc::EACCES | libc::EPERM | libc::EPROTONOSUPPORT)
+            )
+        };
+        match socket(domain, libc::SOCK_DGRAM, protocol) {
+            Ok(socket) => return Ok((socket, true)),
+            Err(e) if denied(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+        match socket(domain, libc::SOCK_RAW, protocol) {
+            Ok(socket) => Ok((socket, false)),
+            Err(e) if denied(&e) => Err(ProbeError::Unavailable(
+                "unprivileged ICMP sockets aren't allowed (see net.ipv4.ping_group_range) and raw \
+                 ones need CAP_NET_RAW"
+                    .to_owned(),
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn socket(domain: i32, kind: i32, protocol: i32) -> io::Result<OwnedFd> {
+        // SAFETY: plain socket(2), the result is checked before it's owned
+        let fd = unsafe { libc::socket(domain, kind | libc::SOCK_CLOEXEC, protocol) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the fd was just created and isn't owned by anything else
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    fn send_to(socket: &OwnedFd, packet: &[u8], target: IpAddr) -> io::Result<()> {
+        // SAFETY: sockaddr_storage is plain old data
+        let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let len = match target {
+            IpAddr::V4(ip) => {
+                // SAFETY: sockaddr_storage is large and aligned enough for any address
+                let addr = unsafe {
+                    &mut *(&mut addr as *mut libc::sockaddr_storage).cast::<libc::sockaddr_in>()
+                };
+                addr.sin_family = libc::AF_INET as u16;
+                addr.sin_addr.s_addr = u32::from(ip).to_be();
+                mem::size_of::<libc::sockaddr_in>()
+            }
+            IpAddr::V6(ip) => {
+                // SAFETY: sockaddr_storage is large and aligned enough for any address
+                let addr = unsafe {
+                    &mut *(&mut addr as *mut libc::sockaddr_storage).cast::<libc::sockaddr_in6>()
+                };
+                addr.sin6_family = libc::AF_INET6 as u16;
+                addr.sin6_addr.s6_addr = ip.octets();
+                mem::size_of::<libc::sockaddr_in6>()
+            }
+        };
+        // SAFETY: the buffer and address are valid for their given lengths
+        let sent = unsafe {
+            libc::sendto(
+                socket.as_raw_fd(),
+                packet.as_ptr().cast(),
+                packet.len(),
+                0,
+                (&addr as *const libc::sockaddr_storage).cast(),
+                len as u32,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// What was received and who sent it.
+    fn recv_from(socket: &OwnedFd, buf: &mut [u8]) -> io::Result<(usize, Option<IpAddr>)> {
+        // SAFETY: sockaddr_storage is plain old data
+        let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<libc::sockaddr_storage>() as u32;
+        // SAFETY: the buffer and address are valid for their given lengths
+        let len = unsafe {
+            libc::recvfrom(
+                socket.as_raw_fd(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                0,
+                (&mut addr as *mut libc::sockaddr_storage).cast(),
+                &mut addr_len,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((len as usize, address(&addr)))
+    }
+
+    /// The port the kernel bound a datagram socket to, which is the identifier of its echo
+    /// requests.
+    fn local_port(socket: &OwnedFd) -> io::Result<u16> {
+        // SAFETY: sockaddr_storage is plain old data
+        let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<libc::sockaddr_storage>() as u32;
+        // SAFETY: the address is valid for its given length
+        let result = unsafe {
+            libc::getsockname(
+                socket.as_raw_fd(),
+                (&mut addr as *mut libc::sockaddr_storage).cast(),
+                &mut addr_len,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let port = match i32::from(addr.ss_family) {
+            // SAFETY: the family says which address it is
+            libc::AF_INET => unsafe {
+                (*(&addr as *const libc::sockaddr_storage).cast::<libc::sockaddr_in>()).sin_port
+            },
+            // SAFETY: the family says which address it is
+            libc::AF_INET6 => unsafe {
+                (*(&addr as *const libc::sockaddr_storage).cast::<libc::sockaddr_in6>()).sin6_port
+            },
+            _ => 0,
+        };
+        Ok(u16::from_be(port))
+    }
+
+    fn address(addr: &libc::sockaddr_storage) -> Option<IpAddr> {
+        match i32::from(addr.ss_family) {
+            libc::AF_INET => {
+                // SAFETY: the family says which address it is
+                let addr = unsafe {
+                    &*(addr as *const libc::sockaddr_storage).cast::<libc::sockaddr_in>()
+                };
+                Some(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).into())
+            }
+            libc::AF_INET6 => {
+                // SAFETY: the family says which address it is
+                let addr = unsafe {
+                    &*(addr as *const libc::sockaddr_storage).cast::<libc::sockaddr_in6>()
+                };
+                Some(Ipv6Addr::from(addr.sin6_addr.s6_addr).into())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::{net::IpAddr, time::Duration};
+
+    use crate::verify::ProbeError;
+
+    pub(super) fn ping(_: IpAddr, _: Duration) -> Result<bool, ProbeError> {
+        Err(ProbeError::Unavailable(
+            "ICMP probes are only supported on Linux".to_owned(),
+        ))
+    }
+}
@@ -0,0 +1,103 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use std::{net::SocketAddr, sync::Arc};
+use tower::ServiceExt;
+use wakeonlan::{
+    config::Config,
+    server::{self, AppState},
+};
+
+fn app(config: Config) -> Router {
+    server::router(Arc::new(
+        AppState::new(Config {
+            broadcast: "127.0.0.1:9".parse().unwrap(),
+            allow_from: vec![
+                "10.8.0.0/24".parse().unwrap(),
+                "fd00:8::/64".parse().unwrap(),
+            ],
+            ..config
+        })
+        .unwrap(),
+    ))
+}
+
+/// A dry run, so nothing is actually sent when it's let through.
+async fn wake_from(app: Router, peer: &str, forwarded_for: Option<&str>) -> StatusCode {
+    let mut request = Request::post("/wake").header(header::CONTENT_TYPE, "application/json");
+    if let Some(forwarded_for) = forwarded_for {
+        request = request.header("x-forwarded-for", forwarded_for);
+    }
+    let mut request = request
+        .body(Body::from(
+            r#"{"mac": "00:d8:61:ca:3a:18", "dry_run": true}"#,
+        ))
+        .unwrap();
+    request
+        .extensions_mut()
+        .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+    app.oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn allowed_networks() {
+    let app = app(Config::default());
+    assert_eq!(
+        wake_from(app.clone(), "10.8.0.5:1234", None).await,
+        StatusCode::ACCEPTED
+    );
+    assert_eq!(
+        wake_from(app.clone(), "[fd00:8::5]:1234", None).await,
+        StatusCode::ACCEPTED
+    );
+    assert_eq!(
+        wake_from(app.clone(), "[::ffff:10.8.0.5]:1234", None).await,
+        StatusCode::ACCEPTED
+    );
+    assert_eq!(
+        wake_from(app.clone(), "192.168.1.5:1234", None).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        wake_from(app, "[fd00:9::5]:1234", None).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn forwarded_for_is_only_believed_from_trusted_proxies() {
+    let untrusted = app(Config::default());
+    assert_eq!(
+        wake_from(untrusted, "192.168.1.5:1234", Some("10.8.0.5")).await,
+        StatusCode::FORBIDDEN
+    );
+
+    let trusted = app(Config {
+        trusted_proxies: vec!["127.0.0.1/32".parse().unwrap()],
+        ..Config::default()
+    });
+    assert_eq!(
+        wake_from(trusted.clone(), "127.0.0.1:1234", Some("10.8.0.5")).await,
+        StatusCode::ACCEPTED
+    );
+    // the client can put anything in front, only what the proxy appended counts
+    assert_eq!(
+        wake_from(trusted, "127.0.0.1:1234", Some("10.8.0.5, 192.168.1.5")).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn reads_are_unrestricted_by_default() {
+    let mut request = Request::get("/").body(Body::empty()).unwrap();
+    request.extensions_mut().insert(ConnectInfo(
+        "192.168.1.5:1234".parse::<SocketAddr>().unwrap(),
+    ));
+    let response = app(Config::default()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,134 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{test_app, NoSend};
+use http_body_util::BodyExt;
+use std::time::Duration;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{HostStatus, WakeResponse},
+    config::Config,
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    verify::Strategy,
+    MacAddress,
+};
+
+/// Knows `pc` at 127.0.0.1, which is up whenever something listens on `port`.
+fn state(port: u16, config: Config) -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        verify: vec![Strategy::Tcp(port)],
+        ..config
+    })
+    .unwrap()
+    .with_sender(NoSend)
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "pc".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0x02, 0, 0, 0, 0, 0x01]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]))
+}
+
+async fn check(app: &Router) -> HostStatus {
+    let request = Request::get("/api/v1/hosts/pc/status")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn wake(app: &Router, body: &str) -> (StatusCode, WakeResponse) {
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn says_when_it_was_up_already() {
+    let up = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let app = test_app(state(up.local_addr().unwrap().port(), Config::default()));
+
+    // nothing checked it yet
+    let (status, woken) = wake(&app, r#"{"host": "pc"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(!woken.already_online);
+    assert_eq!(woken.last_probe, None);
+
+    assert_eq!(check(&app).await.online, Some(true));
+    let (status, woken) = wake(&app, r#"{"host": "pc"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(woken.already_online && !woken.skipped);
+    assert!(woken.last_probe.is_some());
+    assert!(!woken.destinations.is_empty());
+
+    let (status, skipped) = wake(&app, r#"{"host": "pc", "skip_if_online": true}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(skipped.already_online && skipped.skipped);
+    assert_eq!(skipped.last_probe, woken.last_probe);
+    assert!(skipped.destinations.is_empty());
+
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("host=pc"))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let page = response.into_body().collect().await.unwrap().to_bytes();
+    let page = String::from_utf8(page.to_vec()).unwrap();
+    assert!(
+        page.contains("it was up already when it was checked just now"),
+        "{page}"
+    );
+}
+
+#[tokio::test]
+async fn skips_by_default_with_skip_if_online() {
+    let up = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let app = test_app(state(
+        up.local_addr().unwrap().port(),
+        Config {
+            skip_if_online: true,
+            ..Config::default()
+        },
+    ));
+    check(&app).await;
+
+    let (status, skipped) = wake(&app, r#"{"host": "pc"}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(skipped.skipped);
+    let (status, woken) = wake(&app, r#"{"host": "pc", "skip_if_online": false}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(woken.already_online && !woken.skipped);
+}
+
+#[tokio::test]
+async fn old_checks_say_nothing() {
+    let up = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let app = test_app(state(
+        up.local_addr().unwrap().port(),
+        Config {
+            online_max_age: Duration::from_millis(100),
+            ..Config::default()
+        },
+    ));
+    check(&app).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let (status, woken) = wake(&app, r#"{"host": "pc", "skip_if_online": true}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(!woken.already_online && woken.last_probe.is_none());
+}
@@ -0,0 +1,214 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{receiving, send, test_app};
+use http_body_util::BodyExt;
+use serde::de::DeserializeOwned;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1,
+    config::{Config, StaticHost},
+};
+
+fn config() -> Config {
+    Config {
+        hosts: vec![StaticHost::new("pc", ["00:11:22:33:44:55"]).unwrap()],
+        ..Config::default()
+    }
+}
+
+async fn get<T: DeserializeOwned>(app: &Router, path: &str) -> (StatusCode, T) {
+    let (status, body) = send(app, Request::get(path).body(Body::empty()).unwrap()).await;
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+async fn post<T: DeserializeOwned>(app: &Router, path: &str, body: String) -> (StatusCode, T) {
+    let request = Request::post(path)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let (status, body) = send(app, request).await;
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn typed_bodies() {
+    let (state, receiver) = receiving(config());
+    let app = test_app(state);
+
+    let request = v1::WakeRequest {
+        host: Some("pc".to_owned()),
+        ..v1::WakeRequest::default()
+    };
+    let (status, woken): (_, v1::WakeResponse) = post(
+        &app,
+        "/api/v1/wake",
+        serde_json::to_string(&request).unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(woken.host.as_deref(), Some("pc"));
+    assert_eq!(woken.macs, ["00:11:22:33:44:55"]);
+    assert!(woken.destinations[0].sent);
+    let mut buf = [0; 200];
+    assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+
+    let (status, hosts): (_, Vec<v1::Host>) = get(&app, "/api/v1/hosts").await;
+    assert_eq!(status, StatusCode::OK);
+    let pc = hosts.iter().find(|host| host.name == "pc").unwrap();
+    assert_eq!(pc.source, v1::HostSource::Static);
+    let last_wake = pc.last_wake.as_ref().unwrap();
+    assert_eq!(last_wake.id, woken.id);
+    assert_eq!(last_wake.outcome, v1::WakeOutcome::Sent);
+
+    // the unversioned routes are the same
+    let (status, unversioned): (_, Vec<v1::Host>) = get(&app, "/hosts").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(unversioned.len(), hosts.len());
+
+    let (status, schedules): (_, Vec<v1::ScheduledWake>) = get(&app, "/api/v1/schedules").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(schedules.is_empty());
+}
+
+#[tokio::test]
+async fn errors_are_json() {
+    let (state, _receiver) = receiving(config());
+    let app = test_app(state);
+
+    let (status, error): (_, v1::ErrorResponse) = get(&app, "/api/v1/hosts/nas/status").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error.error, "host not found");
+    assert_eq!(error.stage, None);
+    // without a version, it stays as it was
+    let (status, body) = send(
+        &app,
+        Request::get("/hosts/nas/status")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, b"host not found");
+
+    let (status, error): (_, v1::ErrorResponse) =
+        post(&app, "/api/v1/wake/batch", "{}".to_owned()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error.error, "no hosts, pattern or location given");
+
+    let (status, error): (_, v1::ErrorResponse) =
+        post(&app, "/api/v1/wake/batch", "[".to_owned()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(!error.error.is_empty());
+
+    let (status, error): (_, v1::ErrorResponse) =
+        post(&app, "/api/v1/wake", r#"{"host": "nas"}"#.to_owned()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error.error, "host `nas` not found");
+}
+
+#[tokio::test]
+async fn bodies_are_checked() {
+    let (state, _receiver) = receiving(config());
+    let app = test_app(state);
+
+    let big = format!(r#"{{"host": "{}"}}"#, "a".repeat(100_000));
+    for path in ["/api/v1/wake", "/wake", "/api/v1/wake/batch"] {
+        let (status, error): (_, v1::ErrorResponse) = post(&app, path, big.clone()).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE, "{path}");
+        assert!(error.error.contains("length limit exceeded"), "{error:?}");
+    }
+
+    let (status, error): (_, v1::ErrorResponse) = post(
+        &app,
+        "/api/v1/wake",
+        r#"{"host": "pc", "dryrun": true}"#.to_owned(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error.error.contains("unknown field `dryrun`"), "{error:?}");
+    let (status, error): (_, v1::ErrorResponse) =
+        post(&app, "/api/v1/wake/batch", r#"{"host": ["pc"]}"#.to_owned()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error.error.contains("unknown field `host`"), "{error:?}");
+
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from("pc"))
+        .unwrap();
+    let (status, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let error: v1::ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error.error, "unsupported content type `text/plain`");
+}
+
+fn content_type(response: &axum::response::Response) -> &str {
+    response.headers()[header::CONTENT_TYPE].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn negotiated() {
+    let (state, _receiver) = receiving(config());
+    let app = test_app(state);
+    let hosts = |accept: &str, path: &str| {
+        Request::get(path)
+            .header(header::ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+    let response = app.clone().oneshot(hosts(browser, "/hosts")).await.unwrap();
+    assert!(content_type(&response).starts_with("text/html"));
+    let page = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8(page.to_vec())
+        .unwrap()
+        .contains("<td><b>pc</b></td>"));
+
+    for (accept, path) in [
+        ("*/*", "/hosts"),
+        ("application/json, text/html;q=0.5", "/hosts"),
+        (browser, "/hosts?format=json"),
+    ] {
+        let response = app.clone().oneshot(hosts(accept, path)).await.unwrap();
+        assert_eq!(
+            content_type(&response),
+            "application/json",
+            "{accept} {path}"
+        );
+    }
+    let response = app
+        .clone()
+        .oneshot(hosts("*/*", "/hosts?format=html"))
+        .await
+        .unwrap();
+    assert!(content_type(&response).starts_with("text/html"));
+    let response = app
+        .clone()
+        .oneshot(hosts("*/*", "/hosts?format=xml"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // a wake is answered with a page when it's asked for, whatever the body was
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::ACCEPT, browser)
+        .body(Body::from(r#"{"host": "pc", "dry_run": true}"#))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert!(content_type(&response).starts_with("text/html"));
+    let request = Request::post("/wake?format=json")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("host=pc&dry_run=true"))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(content_type(&response), "application/json");
+}
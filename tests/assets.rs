@@ -0,0 +1,95 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+use wakeonlan::{config::Config, discovery::StaticDiscovery, server::AppState};
+
+fn state() -> AppState {
+    AppState::new(Config {
+        index_page: "/nonexistent/index.html".into(),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(Vec::new()))
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, String, String, Vec<u8>) {
+    let response = app
+        .clone()
+        .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_owned())
+            .unwrap_or_default()
+    };
+    let (content_type, cache_control) =
+        (header(header::CONTENT_TYPE), header(header::CACHE_CONTROL));
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, content_type, cache_control, body.to_vec())
+}
+
+#[tokio::test]
+async fn icons_and_manifest() {
+    let app = test_app(state());
+
+    for (uri, expected) in [
+        ("/favicon.ico", "image/x-icon"),
+        ("/icon-192.png", "image/png"),
+        ("/icon-512.png", "image/png"),
+        ("/manifest.json", "application/manifest+json"),
+    ] {
+        let (status, content_type, cache_control, body) = get(&app, uri).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+        assert_eq!(content_type, expected, "{uri}");
+        assert_eq!(cache_control, "public, max-age=604800", "{uri}");
+        assert!(!body.is_empty(), "{uri}");
+    }
+    let (_, _, _, icon) = get(&app, "/icon-192.png").await;
+    assert!(icon.starts_with(b"\x89PNG"));
+    let (_, _, _, favicon) = get(&app, "/favicon.ico").await;
+    assert!(favicon.starts_with(&[0, 0, 1, 0]));
+}
+
+/// Behind a reverse proxy that serves it under `/wol/`, everything still points there.
+#[tokio::test]
+async fn relative_to_the_base_path() {
+    let app = Router::new().nest("/wol", test_app(state()));
+
+    let (status, _, _, manifest) = get(&app, "/wol/manifest.json").await;
+    assert_eq!(status, StatusCode::OK);
+    let manifest: serde_json::Value = serde_json::from_slice(&manifest).unwrap();
+    assert_eq!(manifest["name"], "Wake on LAN");
+    assert_eq!(manifest["display"], "standalone");
+    // relative to the manifest, so `/wol/`
+    assert_eq!(manifest["start_url"], "./");
+    for icon in manifest["icons"].as_array().unwrap() {
+        let src = icon["src"].as_str().unwrap();
+        assert!(!src.starts_with('/'), "{src}");
+        let (status, content_type, _, _) = get(&app, &format!("/wol/{src}")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, icon["type"]);
+    }
+
+    let (status, _, _, page) = get(&app, "/wol").await;
+    assert_eq!(status, StatusCode::OK);
+    let page = String::from_utf8(page).unwrap();
+    for link in [
+        r#"<link rel="icon" href="favicon.ico" />"#,
+        r#"<link rel="manifest" href="manifest.json" />"#,
+    ] {
+        assert!(page.contains(link), "{page}");
+    }
+}
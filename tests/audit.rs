@@ -0,0 +1,139 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use serde_json::Value;
+use std::{net::UdpSocket, path::PathBuf, sync::Arc, time::Duration};
+use tower::ServiceExt;
+use wakeonlan::{
+    config::{AuditConfig, Config, StaticHost},
+    server::{self, AppState},
+};
+
+fn read_lines(path: &PathBuf) -> Vec<Value> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn logs_and_rotates() {
+    let dir = std::env::temp_dir().join(format!("wakeonlan-audit-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir(&dir).unwrap();
+    let path = dir.join("audit.log");
+
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let app = server::router(Arc::new(
+        AppState::new(Config {
+            broadcast: receiver.local_addr().unwrap(),
+            hosts: ["pc", "nas", "laptop"]
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    StaticHost::new(name, [format!("00:11:22:33:44:0{i}").as_str()]).unwrap()
+                })
+                .collect(),
+            audit: Some(AuditConfig {
+                path: path.clone(),
+                // every entry ends up in a file of its own
+                max_bytes: 1,
+                keep: 2,
+            }),
+            ..Config::default()
+        })
+        .unwrap(),
+    ));
+
+    for host in ["pc", "nas", "laptop"] {
+        let request = Request::post("/wake")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(format!(r#"{{"host": "{host}"}}"#)))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        // the writer has to be done with one before the next is sent, for the order of the files
+        for _ in 0..50 {
+            if read_lines(&path)
+                .first()
+                .is_some_and(|entry| entry["host"] == host)
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    let rotated = |n: u32| dir.join(format!("audit.log.{n}"));
+    assert!(!rotated(3).exists());
+    let entries = [rotated(2), rotated(1), path.clone()]
+        .iter()
+        .map(read_lines)
+        .collect::<Vec<_>>();
+    let hosts = entries
+        .iter()
+        .map(|lines| {
+            assert_eq!(lines.len(), 1);
+            lines[0]["host"].as_str().unwrap().to_owned()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(hosts, ["pc", "nas", "laptop"]);
+
+    let entry = &entries[2][0];
+    assert_eq!(entry["event"], "wake");
+    assert_eq!(entry["mac"], "00:11:22:33:44:02");
+    assert_eq!(entry["outcome"], "sent");
+    assert_eq!(
+        entry["destinations"][0]["address"],
+        receiver.local_addr().unwrap().to_string()
+    );
+    assert_eq!(entry["destinations"][0]["sent"], true);
+    assert!(entry["at"].is_string());
+    assert!(entry["id"].is_string());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn logs_minted_links() {
+    let path =
+        std::env::temp_dir().join(format!("wakeonlan-audit-links-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let app = server::router(Arc::new(
+        AppState::new(Config {
+            url_secret: Some("secret".into()),
+            hosts: vec![StaticHost::new("pc", ["00:11:22:33:44:55"]).unwrap()],
+            audit: Some(AuditConfig {
+                path: path.clone(),
+                max_bytes: 1 << 20,
+                keep: 1,
+            }),
+            ..Config::default()
+        })
+        .unwrap(),
+    ));
+
+    let request = Request::post("/wake/links")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"host": "pc", "expires_in": 60}"#))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let mut lines = Vec::new();
+    for _ in 0..50 {
+        lines = read_lines(&path);
+        if !lines.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["event"], "link_minted");
+    assert_eq!(lines[0]["host"], "pc");
+    assert!(lines[0]["expires"].is_string());
+    std::fs::remove_file(&path).unwrap();
+}
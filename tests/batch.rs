@@ -0,0 +1,163 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::BatchWakeResponse,
+    config::{Config, StaticHost},
+    discovery::StaticDiscovery,
+    server::{AppState, PacketSender},
+    MagicPacket,
+};
+
+const HOSTS: usize = 10;
+const SEND_TIME: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+struct Sends {
+    sending: AtomicUsize,
+    most: AtomicUsize,
+    sent: AtomicUsize,
+}
+
+/// Takes its time with every packet, counting how many it's sending at once.
+struct SlowSender(Arc<Sends>);
+
+impl PacketSender for SlowSender {
+    fn send(&self, _: &MagicPacket, _: SocketAddr, _: Option<&str>) -> io::Result<SocketAddr> {
+        let sending = self.0.sending.fetch_add(1, Ordering::SeqCst) + 1;
+        self.0.most.fetch_max(sending, Ordering::SeqCst);
+        std::thread::sleep(SEND_TIME);
+        self.0.sending.fetch_sub(1, Ordering::SeqCst);
+        self.0.sent.fetch_add(1, Ordering::SeqCst);
+        Ok("127.0.0.1:40000".parse().unwrap())
+    }
+}
+
+/// `h0` to `h9`, woken `concurrency` at a time.
+fn state(concurrency: usize) -> (Arc<AppState>, Arc<Sends>) {
+    let sends = Arc::new(Sends::default());
+    let hosts = (0..HOSTS)
+        .map(|i| {
+            StaticHost::new(&format!("h{i}"), [format!("02:00:00:00:00:0{i}").as_str()]).unwrap()
+        })
+        .collect();
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts,
+        batch_concurrency: concurrency,
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(SlowSender(sends.clone()))
+    .with_discovery(StaticDiscovery(Vec::new()));
+    (Arc::new(state), sends)
+}
+
+/// All of them, last first, and one that doesn't exist in between.
+fn batch() -> Request<Body> {
+    let mut hosts = (0..HOSTS)
+        .rev()
+        .map(|i| format!("h{i}"))
+        .collect::<Vec<_>>();
+    hosts.insert(3, "nope".to_owned());
+    let body = serde_json::json!({ "hosts": hosts }).to_string();
+    Request::post("/wake/batch")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn wake_batch(app: &Router) -> (StatusCode, BatchWakeResponse) {
+    let response = app.clone().oneshot(batch()).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn concurrency_is_limited() {
+    let (state, sender) = state(3);
+    let app = test_app(state);
+    let start = Instant::now();
+    let (status, response) = wake_batch(&app).await;
+    let elapsed = start.elapsed();
+
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+    let hosts = response
+        .results
+        .iter()
+        .map(|result| result.host.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        hosts,
+        ["h9", "h8", "h7", "nope", "h6", "h5", "h4", "h3", "h2", "h1", "h0"]
+    );
+    assert!(response
+        .results
+        .iter()
+        .all(|result| result.sent == (result.host != "nope")));
+    assert_eq!(response.results[3].error.as_deref(), Some("host not found"));
+    assert_eq!(
+        response.results[0].mac.as_deref(),
+        Some("02:00:00:00:00:09")
+    );
+
+    assert_eq!(sender.sent.load(Ordering::SeqCst), HOSTS);
+    assert_eq!(sender.most.load(Ordering::SeqCst), 3);
+    // one after the other would take a second
+    assert!(elapsed < SEND_TIME * 8, "{elapsed:?}");
+}
+
+#[tokio::test]
+async fn client_going_away_aborts_the_rest() {
+    let (state, sender) = state(2);
+    let app = test_app(state);
+    let request = app.clone().oneshot(batch());
+    // the first two are sent, the next two started
+    assert!(tokio::time::timeout(SEND_TIME * 3 / 2, request)
+        .await
+        .is_err());
+
+    tokio::time::sleep(SEND_TIME * 3).await;
+    assert_eq!(sender.sent.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn shutdown_gives_up_on_the_rest() {
+    let (state, sender) = state(2);
+    let app = test_app(state.clone());
+    let request = tokio::spawn(async move { wake_batch(&app).await });
+    tokio::time::sleep(SEND_TIME * 3 / 2).await;
+    tokio::time::timeout(Duration::from_millis(500), state.shut_down())
+        .await
+        .unwrap();
+
+    let (status, response) = request.await.unwrap();
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+    let sent = response.results.iter().filter(|result| result.sent).count();
+    assert_eq!(sent, 2);
+    assert_eq!(
+        response.results.last().unwrap().error.as_deref(),
+        Some("not woken, the server is shutting down")
+    );
+    tokio::time::sleep(SEND_TIME * 3).await;
+    assert_eq!(sender.sent.load(Ordering::SeqCst), 4);
+}
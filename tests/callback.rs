@@ -0,0 +1,137 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, UdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    config::{Config, StaticHost},
+    server::{self, AppState},
+};
+
+fn app(callback_allow: Vec<String>) -> Router {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let state = AppState::new(Config {
+        broadcast: receiver.local_addr().unwrap(),
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        callback_allow,
+        ..Config::default()
+    })
+    .unwrap();
+    server::router(Arc::new(state))
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+async fn wake(app: &Router, body: Value) -> (StatusCode, Value) {
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    send(app, request).await
+}
+
+/// Accepts one request and answers it with 204, returning the request line and body.
+fn receive_callback(listener: TcpListener) -> (String, Value) {
+    let (stream, _) = listener.accept().unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+            content_length = value.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+    reader
+        .into_inner()
+        .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+        .unwrap();
+    (
+        request_line.trim().to_owned(),
+        serde_json::from_slice(&body).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn calls_back_after_wake() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}/hooks", listener.local_addr().unwrap());
+    let app = app(vec![base.clone()]);
+    let callback = tokio::task::spawn_blocking(move || receive_callback(listener));
+
+    let (status, response) = wake(
+        &app,
+        json!({"host": "nas", "callback_url": format!("{base}/run?step=wake")}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let id = response["id"].as_str().unwrap().to_owned();
+
+    let (request_line, body) = callback.await.unwrap();
+    assert_eq!(request_line, "POST /hooks/run?step=wake HTTP/1.1");
+    assert_eq!(body["id"], id.as_str());
+    assert_eq!(body["host"], "nas");
+    assert_eq!(body["macs"], json!(["a8:a1:59:0e:7b:02"]));
+    assert_eq!(body["outcome"], "sent");
+
+    let mut delivery = Value::Null;
+    for _ in 0..50 {
+        let (_, hosts) = send(&app, Request::get("/hosts").body(Body::empty()).unwrap()).await;
+        delivery = hosts[0]["last_wake"]["callback"].clone();
+        if delivery["status"] != "pending" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(delivery["status"], "delivered");
+    assert_eq!(delivery["attempts"], 1);
+
+    let (_, history) = send(
+        &app,
+        Request::get("/api/v1/history").body(Body::empty()).unwrap(),
+    )
+    .await;
+    assert_eq!(history[0]["host"], "nas");
+    assert_eq!(history[0]["callback"]["status"], "delivered");
+}
+
+#[tokio::test]
+async fn refuses_callbacks_that_are_not_allowed() {
+    let app = app(vec!["http://ci.example/hooks".to_owned()]);
+    for url in [
+        "http://ci.example/hooks-admin",
+        "http://ci.example/hooks.evil.example/",
+        "http://ci.example/hooks/../admin",
+        "https://ci.example/hooks",
+        "http://169.254.169.254/latest",
+    ] {
+        let (status, _) = wake(&app, json!({"host": "nas", "callback_url": url})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{url}");
+    }
+}
@@ -0,0 +1,391 @@
+#![cfg(feature = "server")]
+
+use std::{
+    net::{TcpListener, UdpSocket},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+use wakeonlan::{
+    api::v1::{CliErrorKind, CliReport, EffectiveConfig},
+    config::{Config, Source, StaticHost},
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    verify::Strategy,
+    MacAddress,
+};
+
+fn config_file(name: &str, contents: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("wakeonlan-cli-{name}-{}.toml", std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Runs `wakeonlan check` with the config, returning the exit code and what it printed.
+fn check(config: &PathBuf, args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_wakeonlan"))
+        .arg("check")
+        .args(args)
+        .env("WOL_CONFIG", config)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn exit_codes() {
+    let config = config_file(
+        "hosts",
+        r#"
+[[hosts]]
+name = "ghost"
+mac = "02:00:00:00:00:01"
+"#,
+    );
+    let (code, output) = check(&config, &["ghost", "--port", "22", "--timeout", "1"]);
+    assert_eq!(code, 1);
+    assert_eq!(output, "ghost is down, it's not in the neighbor table\n");
+
+    let (code, output) = check(&config, &["ghost", "--json"]);
+    assert_eq!(code, 1);
+    let status: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(status["macs"][0], "02:00:00:00:00:01");
+    assert_eq!(status["online"], serde_json::Value::Null);
+
+    let (code, _) = check(&config, &["no-such-host-anywhere"]);
+    assert_eq!(code, i32::from(CliErrorKind::Resolution.exit_code()));
+    std::fs::write(&config, "broadcast = 9").unwrap();
+    let (code, _) = check(&config, &["ghost"]);
+    assert_eq!(code, i32::from(CliErrorKind::Config.exit_code()));
+    let output = Command::new(env!("CARGO_BIN_EXE_wakeonlan"))
+        .args(["wake", "--mac", "02:00:00:00:00:01"])
+        .env("WOL_CONFIG", &config)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+    assert_eq!(
+        output.status.code(),
+        Some(i32::from(CliErrorKind::Config.exit_code()))
+    );
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[test]
+fn host_status() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let state = AppState::new(Config {
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        verify: vec![Strategy::Tcp(port)],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]));
+
+    let status = state.host_status("nas").unwrap().unwrap();
+    assert_eq!(status.ip, Some("127.0.0.1".parse().unwrap()));
+    assert_eq!(status.online, Some(true));
+    assert_eq!(status.strategy, Some(Strategy::Tcp(port)));
+    assert!(state.host_status("pc").unwrap().is_none());
+}
+
+#[test]
+fn wake_mac() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let config = config_file(
+        "wake",
+        &format!("broadcast = \"{}\"\n", receiver.local_addr().unwrap()),
+    );
+    let wake = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_wakeonlan"))
+            .arg("wake")
+            .args(args)
+            .env("WOL_CONFIG", &config)
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap()
+    };
+
+    let output = wake(&["--mac", "a8-a1-59-0e-7b-02"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "sent magic packet to a8:a1:59:0e:7b:02 via {}\n",
+            receiver.local_addr().unwrap()
+        )
+    );
+    let mut buf = [0; 200];
+    assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+    assert_eq!(buf[6..12], [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+
+    let args: [&[&str]; 4] = [
+        &["--mac", "nas"],
+        &[],
+        &["--mac", "a8-a1-59-0e-7b-02", "--wait"],
+        &["nas", "--timeout", "5"],
+    ];
+    for args in args {
+        assert_eq!(wake(args).status.code(), Some(64), "{args:?}");
+    }
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[test]
+fn print_config() {
+    let config = config_file(
+        "print",
+        r#"
+broadcast = "192.168.1.255"
+token = "hunter2"
+
+[[hosts]]
+name = "pc"
+mac = "02:00:00:00:00:01"
+password = "aabbccddeeff"
+"#,
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_wakeonlan"))
+        .arg("--print-config")
+        .env("WOL_CONFIG", &config)
+        .env("WOL_BROADCAST", "10.0.0.255")
+        .env("WOL_LISTEN", "127.0.0.1:8091")
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let printed: EffectiveConfig = serde_json::from_slice(&output.stdout).unwrap();
+    let setting = |key: &str| printed.settings[key].clone();
+
+    // the file wins over the environment
+    assert_eq!(setting("broadcast").value, "192.168.1.255:9");
+    assert_eq!(setting("broadcast").source, Source::File);
+    assert_eq!(
+        setting("listen").value,
+        serde_json::json!(["127.0.0.1:8091"])
+    );
+    assert_eq!(setting("listen").source, Source::Env);
+    assert_eq!(setting("wake_timeout").value, 10);
+    assert_eq!(setting("wake_timeout").source, Source::Default);
+    assert_eq!(setting("token").value, "<redacted>");
+    assert_eq!(setting("hosts").value[0]["password"], "<redacted>");
+    assert_eq!(setting("hosts").value[0]["name"], "pc");
+    assert!(!String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("hunter2"));
+
+    std::fs::write(&config, "broadcast = 9").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_wakeonlan"))
+        .arg("--print-config")
+        .env("WOL_CONFIG", &config)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(78));
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[tokio::test]
+async fn wake_and_wait() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        verify: vec![Strategy::Tcp(port)],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]));
+    let state = Arc::new(state);
+    let mut events = state.events();
+
+    let summary = state.wake_and_wait("nas".to_owned()).await.unwrap();
+    assert!(
+        summary.ends_with(&format!("it came up (tcp:{port})")),
+        "{summary}"
+    );
+    let mut names = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        names.push(event.event.name());
+    }
+    assert_eq!(
+        names,
+        [
+            "wake_requested",
+            "wake_sent",
+            "verification_started",
+            "host_online"
+        ]
+    );
+
+    let hosts = state.hosts().await;
+    assert_eq!(hosts[0].name, "nas");
+    assert_eq!(hosts[0].online, Some(true));
+    assert_eq!(hosts[0].last_wake.as_ref().unwrap().source, "tui");
+    let error = state.wake_and_wait("pc".to_owned()).await.unwrap_err();
+    assert_eq!(error, "host `pc` not found");
+}
+
+/// Runs the command with `--output json`, returning the exit code and what it printed, which has
+/// to be one JSON document with the same exit code.
+fn json_report(config: &PathBuf, args: &[&str]) -> (i32, CliReport) {
+    let output = Command::new(env!("CARGO_BIN_EXE_wakeonlan"))
+        .args(args)
+        .args(["--output", "json"])
+        .env("WOL_CONFIG", config)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap();
+    let code = output.status.code().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let report: CliReport = serde_json::from_str(&stdout).expect(&stdout);
+    assert_eq!(i32::from(report.exit_code), code, "{stdout}");
+    assert_eq!(stdout.lines().count(), 1, "{stdout}");
+    (code, report)
+}
+
+#[test]
+fn json_reports() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let config = config_file(
+        "json",
+        &format!(
+            r#"
+broadcast = "{}"
+verify = ["tcp:1"]
+
+[[hosts]]
+name = "ghost"
+mac = "02:00:00:00:00:01"
+"#,
+            receiver.local_addr().unwrap()
+        ),
+    );
+
+    let (code, report) = json_report(&config, &["wake", "ghost"]);
+    assert_eq!(code, 0);
+    assert_eq!(report.command, "wake");
+    let wake = report.wake.unwrap();
+    assert_eq!(wake.host.as_deref(), Some("ghost"));
+    assert_eq!(wake.source, "cli");
+    assert!(wake.destinations[0].sent);
+    assert_eq!(wake.destinations[0].address, receiver.local_addr().unwrap());
+
+    // it's not in the neighbor table, so it never comes up
+    let (code, report) = json_report(&config, &["wake", "ghost", "--wait", "--timeout", "1"]);
+    assert_eq!(code, 1);
+    assert_eq!(report.wake.unwrap().online, Some(false));
+    assert_eq!(report.error.unwrap().kind, CliErrorKind::Offline);
+
+    let (code, report) = json_report(&config, &["wake", "no-such-host-anywhere"]);
+    assert_eq!(code, 2);
+    let error = report.error.unwrap();
+    assert_eq!(error.kind, CliErrorKind::Resolution);
+    assert_eq!(
+        error.details.error,
+        "host `no-such-host-anywhere` not found"
+    );
+    assert!(report.wake.is_none());
+
+    let (code, report) = json_report(&config, &["wake", "--mac", "02:00:00:00:00:01"]);
+    assert_eq!(code, 0);
+    assert_eq!(report.wake.unwrap().macs, ["02:00:00:00:00:01"]);
+
+    let (code, report) = json_report(&config, &["check", "ghost"]);
+    assert_eq!(code, 1);
+    assert_eq!(report.status.unwrap().host, "ghost");
+    assert_eq!(report.error.unwrap().kind, CliErrorKind::Offline);
+    let (code, _) = json_report(&config, &["check", "no-such-host-anywhere"]);
+    assert_eq!(code, 2);
+
+    let (code, report) = json_report(&config, &["list"]);
+    assert_eq!(code, 0);
+    assert_eq!(report.hosts.unwrap()[0].name, "ghost");
+
+    // an IPv4 socket can't send to an IPv6 address
+    std::fs::write(&config, "broadcast = \"[::1]:9\"\n").unwrap();
+    let (code, report) = json_report(&config, &["wake", "--mac", "02:00:00:00:00:01"]);
+    assert_eq!(code, 3);
+    assert_eq!(report.error.unwrap().kind, CliErrorKind::Send);
+
+    std::fs::write(&config, "broadcast = 9").unwrap();
+    for args in [&["wake", "ghost"][..], &["check", "ghost"], &["list"]] {
+        let (code, report) = json_report(&config, args);
+        assert_eq!(code, 4, "{args:?}");
+        assert_eq!(report.error.unwrap().kind, CliErrorKind::Config);
+    }
+    std::fs::remove_file(&config).unwrap();
+}
+
+#[tokio::test]
+async fn wake_from_cli() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        verify: vec![Strategy::Tcp(port)],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]));
+    let state = Arc::new(state);
+
+    let response = (state
+        .wake_from_cli("nas".to_owned(), Some(Duration::from_secs(5)))
+        .await)
+        .unwrap();
+    assert_eq!(response.online, Some(true));
+    assert_eq!(response.probe, Some(Strategy::Tcp(port)));
+    // without waiting, it isn't known
+    let response = state.wake_from_cli("nas".to_owned(), None).await.unwrap();
+    assert_eq!(response.online, None);
+}
+
+#[test]
+fn tui_needs_a_terminal() {
+    let config = config_file("tui", "");
+    let output = Command::new(env!("CARGO_BIN_EXE_wakeonlan"))
+        .arg("tui")
+        .env("WOL_CONFIG", &config)
+        .env_remove("RUST_LOG")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "wakeonlan tui needs a terminal\n"
+    );
+    std::fs::remove_file(&config).unwrap();
+}
@@ -0,0 +1,116 @@
+#![cfg(all(feature = "client", feature = "server"))]
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use wakeonlan::{
+    api::v1::WakeRequest,
+    client::{Error, WolClient},
+    config::Config,
+    discovery::{HostEntry, StaticDiscovery},
+    secret::REDACTED,
+    server::{self, AppState},
+    verify::Strategy,
+    MacAddress,
+};
+
+/// A server that knows `pc` at 127.0.0.1, which is up whenever something listens on `port`.
+async fn server(port: u16) -> String {
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        token: Some("secret".into()),
+        verify: vec![Strategy::Tcp(port)],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "pc".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0x02, 0, 0, 0, 0, 0x01]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let app = server::router(Arc::new(state));
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+    url
+}
+
+#[tokio::test]
+async fn drives_the_server() {
+    let up = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = server(up.local_addr().unwrap().port()).await;
+
+    tokio::task::spawn_blocking(move || {
+        let client = WolClient::new(url).with_token("secret");
+
+        let woken = client
+            .wake_with(&WakeRequest {
+                host: Some("pc".to_owned()),
+                dry_run: true,
+                ..WakeRequest::default()
+            })
+            .unwrap();
+        assert_eq!(woken.mac, "02:00:00:00:00:01");
+        assert!(woken.dry_run);
+        let woken = client
+            .wake_mac(MacAddress([0x02, 0, 0, 0, 0, 0x02]))
+            .unwrap();
+        assert_eq!(woken.macs, ["02:00:00:00:00:02"]);
+        assert!(!woken.destinations.is_empty());
+
+        let hosts = client.hosts().unwrap();
+        assert_eq!(hosts.len(), 1);
+        assert_eq!(hosts[0].name, "pc");
+
+        let status = client.status("pc").unwrap();
+        assert_eq!(status.online, Some(true));
+        let waited = client.wait_online("pc", Duration::from_secs(5)).unwrap();
+        assert!(waited.online);
+        assert_eq!(
+            waited.strategy,
+            Strategy::Tcp(up.local_addr().unwrap().port())
+        );
+
+        match client.wake("gone") {
+            Err(Error::Api { status: 404, error }) => {
+                assert_eq!(error.error, "host `gone` not found");
+            }
+            other => panic!("{other:?}"),
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn needs_the_token() {
+    let url = server(1).await;
+    tokio::task::spawn_blocking(move || {
+        match WolClient::new(url).wake("pc") {
+            Err(Error::Api { status: 401, .. }) => {}
+            other => panic!("{other:?}"),
+        }
+        assert!(matches!(
+            WolClient::new("http://127.0.0.1:1").hosts(),
+            Err(Error::Request(_))
+        ));
+    })
+    .await
+    .unwrap();
+}
+
+#[test]
+fn token_is_redacted() {
+    let client = WolClient::new("http://127.0.0.1:1").with_token("sentinel-token");
+    let formatted = format!("{client:?}");
+    assert!(!formatted.contains("sentinel-token"), "{formatted}");
+    assert!(formatted.contains(REDACTED));
+}
@@ -0,0 +1,196 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{ErrorResponse, HistoryEntry, Host, WakeResponse, WakeStage},
+    config::Config,
+    discovery::{HostDiscovery, HostEntry},
+    server::{AppState, PacketSender},
+    MacAddress, MagicPacket,
+};
+
+/// Takes its time discovering the TV and longer sending to it, and counts how often it's asked and
+/// sent to.
+#[derive(Clone, Default)]
+struct Slow {
+    discovered: Arc<AtomicUsize>,
+    sent: Arc<AtomicUsize>,
+}
+
+impl HostDiscovery for Slow {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        self.discovered.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(300));
+        Ok(vec![HostEntry {
+            name: "tv-pc".to_owned(),
+            ip: Some("192.168.1.40".parse().unwrap()),
+            mac: MacAddress([0x02, 0, 0, 0, 0, 0x40]),
+            named_by: None,
+            state: None,
+            vlan: None,
+        }])
+    }
+}
+
+impl PacketSender for Slow {
+    fn send(&self, _: &MagicPacket, _: SocketAddr, _: Option<&str>) -> io::Result<SocketAddr> {
+        self.sent.fetch_add(1, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(600));
+        Ok("127.0.0.1:40000".parse().unwrap())
+    }
+}
+
+fn state(slow: &Slow) -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(slow.clone())
+    .with_discovery(slow.clone())
+}
+
+async fn wake(app: &Router, body: &'static str) -> WakeResponse {
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn one_send() {
+    let slow = Slow::default();
+    let app = test_app(state(&slow));
+
+    let (first, second, third) = tokio::join!(
+        wake(&app, r#"{"host": "tv-pc"}"#),
+        wake(&app, r#"{"mac": "02-00-00-00-00-40"}"#),
+        wake(&app, r#"{"host": "tv-pc"}"#),
+    );
+    let mut woken = [&first, &second, &third];
+    woken.sort_by_key(|response| response.coalesced);
+    assert_eq!(
+        woken.map(|response| response.coalesced),
+        [false, true, true]
+    );
+    assert!(woken.iter().all(|response| response.id == first.id));
+    // each finds the host for itself, only one of them wakes it
+    assert_eq!(slow.discovered.load(Ordering::SeqCst), 2);
+    assert_eq!(slow.sent.load(Ordering::SeqCst), 1);
+
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let hosts: Vec<Host> = serde_json::from_slice(&body).unwrap();
+    let last_wake = hosts[0].last_wake.clone().unwrap();
+    assert_eq!(last_wake.id, first.id);
+    assert_eq!(last_wake.coalesced.len(), 2);
+    let request = Request::get("/api/v1/history").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let history: Vec<HistoryEntry> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].wake.id, first.id);
+    assert_eq!(history[0].wake.coalesced.len(), 2);
+
+    // once it's done, it's woken again
+    let again = wake(&app, r#"{"host": "tv-pc"}"#).await;
+    assert!(!again.coalesced);
+    assert_ne!(again.id, first.id);
+    assert_eq!(slow.sent.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn different_options() {
+    let slow = Slow::default();
+    let app = test_app(state(&slow));
+
+    let (sent, dry_run) = tokio::join!(
+        wake(&app, r#"{"host": "tv-pc"}"#),
+        wake(&app, r#"{"host": "tv-pc", "dry_run": true}"#),
+    );
+    assert!(!sent.coalesced);
+    assert!(!dry_run.coalesced);
+    assert_ne!(sent.id, dry_run.id);
+    assert_eq!(slow.sent.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn host_and_its_mac() {
+    let slow = Slow::default();
+    let app = test_app(state(&slow));
+
+    let (by_name, by_mac) = tokio::join!(
+        wake(&app, r#"{"host": "tv-pc"}"#),
+        wake(&app, r#"{"mac": "02:00:00:00:00:40"}"#),
+    );
+    assert_ne!(by_name.coalesced, by_mac.coalesced);
+    assert_eq!(by_name.id, by_mac.id);
+    assert_eq!(slow.sent.load(Ordering::SeqCst), 1);
+
+    // another MAC is another wake
+    let (tv, other) = tokio::join!(
+        wake(&app, r#"{"host": "tv-pc"}"#),
+        wake(&app, r#"{"mac": "02:00:00:00:00:41"}"#),
+    );
+    assert!(!tv.coalesced);
+    assert!(!other.coalesced);
+    assert_eq!(slow.sent.load(Ordering::SeqCst), 3);
+}
+
+/// Takes longer to discover anything than a wake has.
+struct Stuck;
+
+impl HostDiscovery for Stuck {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        std::thread::sleep(Duration::from_secs(3));
+        Ok(Vec::new())
+    }
+}
+
+#[tokio::test]
+async fn slow_lookup_times_out_within_one_budget() {
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        wake_timeout: Duration::from_secs(1),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(Stuck);
+    let app = test_app(state);
+
+    let started = Instant::now();
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"host": "tv-pc"}"#))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert!(started.elapsed() < Duration::from_millis(1500));
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error.stage, Some(WakeStage::Discovery));
+    assert_eq!(error.error, "timed out after 1s during discovery");
+}
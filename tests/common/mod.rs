@@ -0,0 +1,83 @@
+//! What the server tests share, each of them has it with `mod common;`.
+
+// not every test uses all of it
+#![allow(dead_code)]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    config::Config,
+    server::{self, AppState, PacketSender},
+    MacAddress, MagicPacket,
+};
+
+/// Sends nothing, but says it did.
+pub struct NoSend;
+
+impl PacketSender for NoSend {
+    fn send(&self, _: &MagicPacket, _: SocketAddr, _: Option<&str>) -> io::Result<SocketAddr> {
+        Ok("127.0.0.1:40000".parse().unwrap())
+    }
+}
+
+/// Sends nothing either, but keeps the MACs it was asked to wake.
+#[derive(Clone, Default)]
+pub struct Recorder(pub Arc<Mutex<Vec<MacAddress>>>);
+
+impl PacketSender for Recorder {
+    fn send(&self, packet: &MagicPacket, _: SocketAddr, _: Option<&str>) -> io::Result<SocketAddr> {
+        self.0.lock().unwrap().push(packet.mac());
+        Ok("127.0.0.1:40000".parse().unwrap())
+    }
+}
+
+pub fn test_app(state: impl Into<Arc<AppState>>) -> Router {
+    server::router(state.into())
+}
+
+/// A server with the config, which sends to the socket instead of the `broadcast` of the config.
+pub fn receiving(config: Config) -> (AppState, UdpSocket) {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let state = AppState::new(Config {
+        broadcast: receiver.local_addr().unwrap(),
+        ..config
+    })
+    .unwrap();
+    (state, receiver)
+}
+
+pub async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+/// Posts the JSON body.
+pub async fn post(app: &Router, uri: &str, body: &str) -> (StatusCode, Vec<u8>) {
+    let request = Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    send(app, request).await
+}
+
+/// Posts the JSON body and reads the JSON response.
+pub async fn post_json(app: &Router, uri: &str, body: &str) -> (StatusCode, serde_json::Value) {
+    let (status, body) = post(app, uri, body).await;
+    (status, serde_json::from_slice(&body).unwrap())
+}
@@ -0,0 +1,314 @@
+#![cfg(feature = "server")]
+
+use std::path::PathBuf;
+use wakeonlan::config::{self, Problem};
+
+fn config_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("wakeonlan-{name}-{}.toml", std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn every_problem() {
+    let path = config_file(
+        "problems",
+        r#"
+broadcast = "10.0.0.255:0"
+allow_from = ["10.0.0.0/33"]
+colour = "blue"
+batch_concurrency = 0
+
+[[hosts]]
+name = "pc"
+mac = "zz:00"
+[[hosts]]
+name = "nas"
+mac = "a8:a1:59:0e:7b:02"
+nick = "storage"
+[[hosts]]
+name = "NAS"
+mac = "a8:a1:59:0e:7b:03"
+
+[[schedules]]
+host = "pc"
+cron = "61 * * * *"
+
+[relay]
+listen = "0.0.0.0:9"
+destinations = ["10.0.0.255"]
+rate = 3
+"#,
+    );
+    let problems = config::check_file(&path).unwrap_err().0;
+    let keys = problems
+        .iter()
+        .map(|problem| problem.key.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        keys,
+        [
+            "allow_from",
+            "colour",
+            "hosts[0]",
+            "hosts[1].nick",
+            "relay",
+            "schedules[0]",
+            "broadcast",
+            "batch_concurrency",
+        ]
+    );
+    assert_eq!(
+        problems[2],
+        Problem {
+            file: path.clone(),
+            key: "hosts[0]".to_owned(),
+            value: Some(r#"{ mac = "zz:00", name = "pc" }"#.to_owned()),
+            message: "invalid mac address `zz:00` for host `pc`".to_owned(),
+        }
+    );
+    assert_eq!(problems[1].message, "unknown key");
+    assert!(problems[4].message.starts_with("unknown field `rate`"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn duplicates() {
+    let path = config_file(
+        "duplicates",
+        r#"
+[[hosts]]
+name = "nas"
+mac = "a8:a1:59:0e:7b:02"
+[[hosts]]
+name = "NAS"
+mac = "a8:a1:59:0e:7b:03"
+
+[[sequences]]
+name = "lab"
+steps = [{ host = "nas", ready = "tcp:0" }]
+[[sequences]]
+name = "lab"
+steps = []
+"#,
+    );
+    let problems = config::check_file(&path).unwrap_err();
+    let shown = problems.to_string();
+    let lines = shown.lines().collect::<Vec<_>>();
+    let file = path.display();
+    assert_eq!(
+        lines,
+        [
+            "4 problems".to_owned(),
+            format!("  {file}: hosts[1].name: `hosts[0]` already has that name (found \"NAS\")"),
+            format!("  {file}: sequences[0].steps[0].ready: port 0 can't be connected to (found \"tcp:0\")"),
+            format!("  {file}: sequences[1].name: `sequences[0]` already has that name (found \"lab\")"),
+            format!("  {file}: sequences[1].steps: a sequence needs at least one step (found [])"),
+        ]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn valid() {
+    let path = config_file(
+        "valid",
+        r#"
+listen = ["0.0.0.0:8090", "[::]:8090"]
+allow_from = ["10.8.0.0/24"]
+
+[[hosts]]
+name = "pc"
+macs = ["00:d8:61:ca:3a:18", "00:d8:61:ca:3a:19"]
+
+[[schedules]]
+host = "pc"
+cron = "0 7 * * 1-5"
+"#,
+    );
+    config::check_file(&path).unwrap();
+
+    std::fs::write(&path, "listen = [\n").unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].message.starts_with("invalid TOML on line 1"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn failover() {
+    let path = config_file("failover", r#"failover = ["eth0", "", "wlan0", "eth0"]"#);
+    let problems = config::check_file(&path).unwrap_err();
+    let shown = problems.to_string();
+    let lines = shown.lines().collect::<Vec<_>>();
+    let file = path.display();
+    assert_eq!(
+        lines,
+        [
+            "2 problems".to_owned(),
+            format!("  {file}: failover[1]: empty interface name (found \"\")"),
+            format!("  {file}: failover[3]: `failover[0]` is the same interface (found \"eth0\")"),
+        ]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn snmp() {
+    let path = config_file(
+        "snmp",
+        r#"
+[snmp]
+switch = "192.168.1.2"
+timeout_ms = 0
+
+[[sites]]
+name = "lab"
+vlan = 10
+[[sites]]
+name = "office"
+vlan = 10
+"#,
+    );
+    let problems = config::check_file(&path).unwrap_err();
+    let shown = problems.to_string();
+    let lines = shown.lines().collect::<Vec<_>>();
+    let file = path.display();
+    assert_eq!(
+        lines,
+        [
+            "2 problems".to_owned(),
+            format!("  {file}: snmp.timeout_ms: no switch can answer that fast (found 0)"),
+            format!("  {file}: sites[1].vlan: `sites[0]` is already on that VLAN (found 10)"),
+        ]
+    );
+
+    std::fs::remove_file(&path).unwrap();
+
+    let path = config_file("snmp-port", "[snmp]\nswitch = \"192.168.1.2:1161\"\n");
+    config::check_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let path = config_file("snmp-invalid", "[snmp]\nswitch = \"switch.lan\"\n");
+    let problems = config::check_file(&path).unwrap_err().0;
+    assert_eq!(problems[0].key, "snmp");
+    assert!(problems[0]
+        .message
+        .starts_with("invalid switch address `switch.lan`"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn send_confirmation() {
+    let path = config_file(
+        "send-confirmation",
+        "[send_confirmation]
+",
+    );
+    config::check_file(&path).unwrap();
+    std::fs::write(
+        &path,
+        "[send_confirmation]
+wait_ms = 60000
+",
+    )
+    .unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].key, "send_confirmation.wait_ms");
+    assert_eq!(
+        problems[0].message,
+        "has to be between 1 and 5000 milliseconds"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn mqtt() {
+    let path = config_file(
+        "mqtt",
+        "[mqtt]
+broker = \"192.168.1.5:1883\"
+",
+    );
+    config::check_file(&path).unwrap();
+    std::fs::write(
+        &path,
+        "[mqtt]
+broker = \"192.168.1.5\"
+prefix = \"wol/#\"
+",
+    )
+    .unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    let keys = problems.iter().map(|problem| problem.key.as_str());
+    assert_eq!(keys.collect::<Vec<_>>(), ["mqtt.broker", "mqtt.prefix"]);
+    assert_eq!(problems[1].message, "has to be a topic without wildcards");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn proxy() {
+    let path = config_file(
+        "proxy",
+        r#"
+[proxy]
+listen = "0.0.0.0:8096"
+upstream = "http://@/"
+host = "media"
+wake = "pages"
+max_wait = 0
+"#,
+    );
+    let problems = config::check_file(&path).unwrap_err();
+    let shown = problems.to_string();
+    let lines = shown.lines().collect::<Vec<_>>();
+    let file = path.display();
+    assert_eq!(
+        lines,
+        [
+            "2 problems".to_owned(),
+            format!("  {file}: proxy.upstream: not a url with a host (found \"http://@/\")"),
+            format!("  {file}: proxy.max_wait: no host comes up that fast (found 0)"),
+        ]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn sites() {
+    let path = config_file(
+        "sites",
+        r#"
+[[sites]]
+name = "parents"
+remote = { url = "ftp://wol.example", token = "secret" }
+[[sites]]
+name = "parents"
+broadcast = "192.168.2.255"
+[[sites]]
+name = "cabin"
+interface = "wg0"
+remote = { url = "http://10.9.0.1:8090" }
+
+[[hosts]]
+name = "nas"
+mac = "a8:a1:59:0e:7b:02"
+site = "parnets"
+"#,
+    );
+    let problems = config::check_file(&path).unwrap_err();
+    let shown = problems.to_string();
+    let lines = shown.lines().collect::<Vec<_>>();
+    let file = path.display();
+    assert_eq!(
+        lines,
+        [
+            "4 problems".to_owned(),
+            format!("  {file}: sites[0].remote.url: only http:// and https:// urls are supported (found \"ftp://wol.example\")"),
+            format!("  {file}: sites[1].name: `sites[0]` already has that name (found \"parents\")"),
+            format!("  {file}: sites[2].remote.url: the server there sends the packets, a remote site can't have its own broadcast, interface or discovery (found \"http://10.9.0.1:8090\")"),
+            format!("  {file}: hosts[0].site: no site has that name (found \"parnets\")"),
+        ]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,160 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{post, test_app, NoSend};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{BatchWakeResponse, ErrorResponse, Host},
+    config::{Config, StaticHost},
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    MacAddress,
+};
+
+/// `heater` has to be confirmed, `pc` doesn't, both in the basement and discovered.
+fn state() -> AppState {
+    let discovered = |name: &str, last| HostEntry {
+        name: name.to_owned(),
+        ip: None,
+        mac: MacAddress([0x02, 0, 0, 0, 0, last]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    };
+    let host = |name: &str, mac: &str, require_confirmation| StaticHost {
+        location: Some("basement".to_owned()),
+        require_confirmation,
+        ..StaticHost::new(name, [mac]).unwrap()
+    };
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![
+            host("heater", "02:00:00:00:00:01", true),
+            host("pc", "02:00:00:00:00:02", false),
+        ],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(NoSend)
+    .with_discovery(StaticDiscovery(vec![
+        discovered("heater", 1),
+        discovered("pc", 2),
+    ]))
+}
+
+async fn get(app: &Router, path: &str) -> Vec<u8> {
+    let request = Request::get(path).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    body.to_vec()
+}
+
+#[tokio::test]
+async fn wakes_only_when_confirmed() {
+    let app = test_app(state());
+
+    for body in [
+        r#"{"host": "heater"}"#,
+        r#"{"host": "HEATER", "force": true}"#,
+        r#"{"mac": "02:00:00:00:00:01"}"#,
+    ] {
+        let (status, error) = post(&app, "/api/v1/wake", body).await;
+        assert_eq!(status, StatusCode::PRECONDITION_REQUIRED, "{body}");
+        let error: ErrorResponse = serde_json::from_slice(&error).unwrap();
+        assert!(
+            error.error.contains("requires confirmation"),
+            "{}",
+            error.error
+        );
+    }
+    let (status, _) = post(
+        &app,
+        "/api/v1/wake",
+        r#"{"host": "heater", "confirm": true}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (status, _) = post(&app, "/api/v1/wake", r#"{"host": "pc"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let hosts: Vec<Host> = serde_json::from_slice(&get(&app, "/api/v1/hosts").await).unwrap();
+    let confirmed = |name: &str| {
+        let host = hosts.iter().find(|host| host.name == name).unwrap();
+        host.require_confirmation
+    };
+    assert!(confirmed("heater"));
+    assert!(!confirmed("pc"));
+}
+
+#[tokio::test]
+async fn batches_leave_it_out() {
+    let app = test_app(state());
+    let batch = |body: &'static str| {
+        let app = app.clone();
+        async move {
+            let (status, body) = post(&app, "/api/v1/wake/batch", body).await;
+            let response: BatchWakeResponse = serde_json::from_slice(&body).unwrap();
+            let results = (response.results.into_iter())
+                .map(|result| (result.host, result.sent))
+                .collect::<Vec<_>>();
+            (status, results)
+        }
+    };
+
+    let (status, results) = batch(r#"{"location": "basement"}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(results, [("pc".to_owned(), true)]);
+    let (status, results) = batch(r#"{"pattern": ""}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(results, [("pc".to_owned(), true)]);
+
+    // named, it's in the results but isn't woken
+    let (status, results) = batch(r#"{"hosts": ["heater", "pc"]}"#).await;
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+    assert_eq!(
+        results,
+        [("heater".to_owned(), false), ("pc".to_owned(), true)]
+    );
+
+    let (status, results) =
+        batch(r#"{"location": "basement", "include_confirmation_required": true}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        results,
+        [("heater".to_owned(), true), ("pc".to_owned(), true)]
+    );
+}
+
+#[tokio::test]
+async fn page_asks_first() {
+    let app = test_app(state());
+    let page = String::from_utf8(get(&app, "/").await).unwrap();
+    let heater = page
+        .split("<li>")
+        .find(|item| item.contains("heater"))
+        .unwrap();
+    assert!(
+        heater.contains(r#"name="confirm" value="true" required"#),
+        "{heater}"
+    );
+    let pc = page
+        .split("<li>")
+        .find(|item| item.contains("<b>pc</b>"))
+        .unwrap();
+    assert!(pc.contains("Wake") && !pc.contains("confirm"), "{pc}");
+
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("host=heater&confirm=true"))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert!(response.status().is_success(), "{}", response.status());
+}
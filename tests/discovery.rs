@@ -0,0 +1,590 @@
+#![cfg(feature = "server")]
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, UdpSocket},
+};
+use wakeonlan::{
+    discovery::{
+        arp_neighbors, find_host, ip_neigh_neighbors, parse_ip_neigh, parse_neighbor_table,
+        parse_proc_net_arp, parse_table, proc_net_arp_neighbors, ssdp::friendly_name,
+        windows_arp_neighbors, Backend, Composite, HostDiscovery, HostEntry, Neighbor,
+        NeighborState, ParseError, ParseErrorKind, Skipped, Ssdp, StaticDiscovery, TableFormat,
+    },
+    MacAddress, MacParseError,
+};
+
+fn fixture(name: &str) -> String {
+    fixture_in("arp", name)
+}
+
+fn fixture_in(dir: &str, name: &str) -> String {
+    std::fs::read_to_string(format!(
+        "{}/tests/fixtures/{dir}/{name}",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap()
+}
+
+fn entry(ip: &str, mac: [u8; 6]) -> HostEntry {
+    HostEntry {
+        name: ip.to_owned(),
+        ip: Some(ip.parse().unwrap()),
+        mac: MacAddress(mac),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }
+}
+
+fn host(name: &str, mac: [u8; 6]) -> (String, MacAddress) {
+    (name.to_owned(), MacAddress(mac))
+}
+
+/// The names and MACs of the entries in a fixture.
+fn parse_arp_output(output: &str) -> Result<Vec<(String, MacAddress)>, ParseError> {
+    Ok(parse_neighbor_table(output)?
+        .into_iter()
+        .map(|entry| (entry.name, entry.mac))
+        .collect())
+}
+
+fn error(output: &str) -> ParseError {
+    parse_neighbor_table(output).unwrap_err()
+}
+
+#[test]
+fn skips_junk_entries() {
+    let hosts = parse_arp_output(&fixture("mixed.txt")).unwrap();
+    assert_eq!(
+        hosts,
+        [
+            host("192.168.1.1", [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+            host("PC-Nora.fritz.box", [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]),
+            host("nas.fritz.box", [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        ]
+    );
+}
+
+#[test]
+fn only_junk_entries_is_empty() {
+    let hosts = parse_arp_output(&fixture("only_junk.txt")).unwrap();
+    assert_eq!(hosts, []);
+}
+
+#[test]
+fn header_only_is_empty() {
+    let hosts = parse_arp_output(
+        "Address                  HWtype  HWaddress           Flags Mask            Iface\n",
+    )
+    .unwrap();
+    assert_eq!(hosts, []);
+}
+
+#[test]
+fn garbage_is_an_error() {
+    // the first line is taken for a header
+    let error = error(&fixture("garbage.txt"));
+    assert_eq!(error.line, 2);
+    assert_eq!(error.kind, ParseErrorKind::MissingMac);
+    assert_eq!(error.text, "something went horribly wrong");
+}
+
+#[test]
+fn bad_hex() {
+    let error = error(&fixture("bad_hex.txt"));
+    assert_eq!(error.line, 2);
+    assert_eq!(
+        error.kind,
+        ParseErrorKind::BadHex {
+            field: 3,
+            error: MacParseError::InvalidDigit {
+                position: 4,
+                found: 'g'
+            }
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "unrecognized neighbor table line 2: invalid mac address in field 3: invalid hex digit `g` \
+         at position 4 in \"192.168.1.1              ether   00:1g:22:33:44:55   C                     eth0\""
+    );
+}
+
+#[test]
+fn too_few_fields() {
+    let error = error(&fixture("too_few_fields.txt"));
+    assert_eq!(error.line, 2);
+    assert_eq!(error.kind, ParseErrorKind::TooFewFields);
+}
+
+#[test]
+fn missing_name() {
+    let error = error("00:11:22:33:44:55 ether eth0\n");
+    assert_eq!(error.kind, ParseErrorKind::MissingName);
+}
+
+/// A bad line doesn't hide the good ones.
+#[test]
+fn bad_lines_are_skipped() {
+    let hosts = parse_arp_output(&format!(
+        "{}{}",
+        fixture("bad_hex.txt"),
+        fixture("debian_de_c_locale.txt")
+    ))
+    .unwrap();
+    assert_eq!(hosts.len(), 2);
+}
+
+#[test]
+fn addresses() {
+    let entries = parse_neighbor_table(&fixture("bsd.txt")).unwrap();
+    assert_eq!(
+        entries[1],
+        HostEntry {
+            name: "nas.local".to_owned(),
+            ip: Some("192.168.1.20".parse().unwrap()),
+            mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+            named_by: None,
+            state: None,
+            vlan: None,
+        }
+    );
+    let entries = parse_neighbor_table(&fixture("debian.txt")).unwrap();
+    assert_eq!(entries[0].ip, None);
+    assert_eq!(entries[1].ip, Some("192.168.1.50".parse().unwrap()));
+}
+
+#[test]
+fn debian_flags_and_masks() {
+    let hosts = parse_arp_output(&fixture("debian.txt")).unwrap();
+    assert_eq!(
+        hosts,
+        [
+            host("_gateway", [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+            host("192.168.1.50", [0x11, 0x22, 0x33, 0x44, 0x55, 0x66]),
+            host("192.168.1.60", [0x22, 0x33, 0x44, 0x55, 0x66, 0x77]),
+            host("nas.fritz.box", [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        ]
+    );
+}
+
+#[test]
+fn busybox() {
+    let hosts = parse_arp_output(&fixture("busybox.txt")).unwrap();
+    assert_eq!(
+        hosts,
+        [
+            host("192.168.1.1", [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+            host("nas.lan", [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+            host("192.168.1.30", [0x3c, 0x7c, 0x3f, 0x1d, 0xaa, 0x09]),
+        ]
+    );
+}
+
+#[test]
+fn bsd() {
+    let hosts = parse_arp_output(&fixture("bsd.txt")).unwrap();
+    assert_eq!(
+        hosts,
+        [
+            host("192.168.1.1", [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+            host("nas.local", [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+            host("224.0.0.251", [0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb]),
+        ]
+    );
+}
+
+/// With a German locale, net-tools translates the header and the incomplete marker, which used to
+/// make the `(unvollständig)` line crash the parser (its third column is the interface name).
+#[test]
+fn localized_output_does_not_fail() {
+    let hosts = parse_arp_output(&fixture("debian_de.txt")).unwrap();
+    assert_eq!(
+        hosts,
+        [
+            host("fritz.box", [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+            host("PC-Nora.fritz.box", [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]),
+        ]
+    );
+}
+
+/// The same table as `debian_de.txt`, but from `LC_ALL=C arp -n`, which is what discovery runs.
+#[test]
+fn c_locale_numeric_output() {
+    let hosts = parse_arp_output(&fixture("debian_de_c_locale.txt")).unwrap();
+    assert_eq!(
+        hosts,
+        [
+            host("192.168.1.1", [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+            host("192.168.1.23", [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]),
+        ]
+    );
+}
+
+#[test]
+fn proc_net_arp() {
+    assert_eq!(
+        parse_proc_net_arp(&fixture_in("proc", "arp.txt")),
+        [
+            entry("192.168.1.1", [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]),
+            entry("192.168.1.20", [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+            HostEntry {
+                state: Some(NeighborState::Permanent),
+                ..entry("192.168.1.30", [0x3c, 0x7c, 0x3f, 0x1d, 0xaa, 0x09])
+            },
+        ]
+    );
+}
+
+#[test]
+fn ip_neigh() {
+    assert_eq!(
+        parse_ip_neigh(&fixture_in("ip", "neigh.txt")),
+        [
+            (
+                "192.168.1.1",
+                [0x00, 0x11, 0x22, 0x33, 0x44, 0x55],
+                NeighborState::Reachable
+            ),
+            // it stopped answering, but that's still its MAC
+            (
+                "192.168.1.30",
+                [0x3c, 0x7c, 0x3f, 0x1d, 0xaa, 0x09],
+                NeighborState::Failed
+            ),
+            (
+                "192.168.1.20",
+                [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02],
+                NeighborState::Stale
+            ),
+            (
+                "fe80::aaa1:59ff:fe0e:7b02",
+                [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02],
+                NeighborState::Stale
+            ),
+        ]
+        .map(|(ip, mac, state)| HostEntry {
+            state: Some(state),
+            vlan: None,
+            ..entry(ip, mac)
+        })
+    );
+}
+
+/// What the entries are skipped for, in order.
+fn skipped(neighbors: &[Neighbor]) -> Vec<Option<&Skipped>> {
+    neighbors
+        .iter()
+        .map(|neighbor| neighbor.skipped.as_ref())
+        .collect()
+}
+
+#[test]
+fn skipped_with_reasons() {
+    let neighbors = arp_neighbors(&fixture("mixed.txt"));
+    assert_eq!(
+        skipped(&neighbors),
+        [
+            None,
+            Some(&Skipped::Incomplete),
+            None,
+            Some(&Skipped::UnusableMac),
+            Some(&Skipped::UnusableMac),
+            None,
+            Some(&Skipped::Incomplete),
+        ]
+    );
+    assert_eq!(
+        neighbors[1],
+        Neighbor {
+            name: None,
+            ip: Some("192.168.1.17".parse().unwrap()),
+            mac: None,
+            interface: Some("eth0".to_owned()),
+            state: None,
+            vlan: None,
+            skipped: Some(Skipped::Incomplete),
+        }
+    );
+    assert_eq!(neighbors[2].name.as_deref(), Some("PC-Nora.fritz.box"));
+    assert_eq!(neighbors[3].mac, Some(MacAddress([0; 6])));
+
+    let neighbors = arp_neighbors(&format!(
+        "{}{}",
+        fixture("bad_hex.txt"),
+        fixture("busybox.txt")
+    ));
+    let Some(Skipped::Invalid(error)) = &neighbors[0].skipped else {
+        panic!("expected the bad line first, found {neighbors:?}");
+    };
+    assert_eq!(error.line, 2);
+    assert!(matches!(
+        error.kind,
+        ParseErrorKind::BadHex { field: 3, .. }
+    ));
+    assert_eq!(neighbors[1].interface.as_deref(), Some("eth0"));
+    assert_eq!(neighbors[3].skipped, Some(Skipped::Incomplete));
+
+    let neighbors = ip_neigh_neighbors(&fixture_in("ip", "neigh.txt"));
+    assert_eq!(
+        skipped(&neighbors),
+        [
+            None,
+            Some(&Skipped::Incomplete),
+            None,
+            None,
+            Some(&Skipped::Incomplete),
+            None
+        ]
+    );
+    assert_eq!(neighbors[1].state, Some(NeighborState::Failed));
+    assert_eq!(neighbors[4].state, Some(NeighborState::Incomplete));
+    assert!(neighbors
+        .iter()
+        .all(|neighbor| neighbor.interface.as_deref() == Some("eth0")));
+
+    let neighbors = proc_net_arp_neighbors(&format!(
+        "{}192.168.1.50     0x1         0x2         00:1g:22:33:44:55     *        eth0\n",
+        fixture_in("proc", "arp.txt")
+    ));
+    assert_eq!(neighbors[1].skipped, Some(Skipped::Incomplete));
+    let Some(Skipped::Invalid(error)) = &neighbors[4].skipped else {
+        panic!("expected the bad line last, found {neighbors:?}");
+    };
+    assert_eq!(error.line, 6);
+    assert!(matches!(
+        error.kind,
+        ParseErrorKind::BadHex { field: 4, .. }
+    ));
+}
+
+#[test]
+fn windows_interfaces() {
+    let neighbors = windows_arp_neighbors(&fixture_in("corpus", "windows_arp.txt"));
+    let interfaces = neighbors
+        .iter()
+        .map(|neighbor| {
+            (
+                neighbor.interface.as_deref().unwrap(),
+                neighbor.skipped.is_some(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        interfaces,
+        [
+            ("192.168.1.23", false),
+            ("192.168.1.23", false),
+            ("192.168.1.23", true),
+            ("192.168.1.23", false),
+            ("172.20.16.1", false),
+            ("172.20.16.1", true),
+        ]
+    );
+}
+
+/// Every table in `fixtures/corpus` with the format it's in. Each `name.txt` has a
+/// `name.expected` next to it, with a `name ip mac` line for each entry (`-` for no ip), followed
+/// by the state if it has one.
+const CORPUS: &[(&str, TableFormat)] = &[
+    ("debian_arp", TableFormat::Arp),
+    ("busybox_arp", TableFormat::Arp),
+    ("macos_arp", TableFormat::Arp),
+    ("ip_neigh", TableFormat::IpNeigh),
+    ("proc_net_arp", TableFormat::ProcNetArp),
+    ("windows_arp", TableFormat::WindowsArp),
+];
+
+fn expected_entries(expected: &str) -> Vec<HostEntry> {
+    expected
+        .lines()
+        .map(|line| {
+            let (name, ip, mac, state) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [name, ip, mac] => (name, ip, mac, None),
+                [name, ip, mac, state] => {
+                    (name, ip, mac, Some(NeighborState::parse(state).unwrap()))
+                }
+                _ => panic!("expected `name ip mac [state]`, found {line:?}"),
+            };
+            HostEntry {
+                name: name.to_owned(),
+                ip: (ip != "-").then(|| ip.parse().unwrap()),
+                mac: mac.parse().unwrap(),
+                named_by: None,
+                state,
+                vlan: None,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn corpus() {
+    for &(name, format) in CORPUS {
+        let output = fixture_in("corpus", &format!("{name}.txt"));
+        let expected = expected_entries(&fixture_in("corpus", &format!("{name}.expected")));
+        assert_eq!(TableFormat::detect(&output), format, "{name}");
+        assert_eq!(
+            parse_table(&output, Some(format)).unwrap(),
+            expected,
+            "{name}"
+        );
+        assert_eq!(parse_table(&output, None).unwrap(), expected, "{name}");
+    }
+
+    // a table that's added without a line above would never be checked
+    let dir = format!("{}/tests/fixtures/corpus", env!("CARGO_MANIFEST_DIR"));
+    for file in std::fs::read_dir(dir).unwrap() {
+        let file = file.unwrap().file_name().into_string().unwrap();
+        let name = file.split('.').next().unwrap();
+        assert!(
+            CORPUS.iter().any(|(known, _)| *known == name),
+            "{file} isn't in CORPUS"
+        );
+    }
+}
+
+struct Failing;
+
+impl HostDiscovery for Failing {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        eyre::bail!("no neighbors here")
+    }
+}
+
+#[test]
+fn composite_merges() {
+    let router = entry("192.168.1.1", [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+    let nas = entry("192.168.1.20", [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+    let nas_v6 = entry(
+        "fe80::aaa1:59ff:fe0e:7b02",
+        [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02],
+    );
+    let composite = Composite(vec![
+        Box::new(Failing),
+        Box::new(StaticDiscovery(vec![router.clone(), nas.clone()])),
+        Box::new(StaticDiscovery(vec![nas.clone(), nas_v6.clone()])),
+    ]);
+    assert_eq!(
+        composite.discover().unwrap(),
+        [router, nas.clone(), nas_v6.clone()]
+    );
+
+    // the state is that of the backend that knows it
+    let stale = HostEntry {
+        state: Some(NeighborState::Stale),
+        ..nas.clone()
+    };
+    let composite = Composite(vec![
+        Box::new(StaticDiscovery(vec![nas.clone()])),
+        Box::new(StaticDiscovery(vec![stale.clone()])),
+    ]);
+    assert_eq!(composite.discover().unwrap(), [stale]);
+
+    // once both have the same name, the NIC is only there once
+    let named = [nas, nas_v6].map(|entry| HostEntry {
+        name: "nas".to_owned(),
+        ..entry
+    });
+    assert_eq!(
+        find_host(&named, "nas"),
+        Some(vec![MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02])])
+    );
+
+    let failing = Composite(vec![Box::new(Failing), Box::new(Failing)]);
+    assert!(failing.discover().is_err());
+}
+
+/// Serves one request with the document, on the port it returns.
+fn serve_once(ip: &str, document: &'static str) -> u16 {
+    let listener = TcpListener::bind((ip, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        write!(
+            reader.get_mut(),
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{document}",
+            document.len()
+        )
+        .unwrap();
+    });
+    port
+}
+
+#[test]
+fn ssdp() {
+    let tv = serve_once(
+        "127.0.0.1",
+        "<root><device><friendlyName>Living Room TV &amp; Co</friendlyName></device></root>",
+    );
+    let plug = serve_once("127.0.0.2", "<root><device><friendlyName>Plug<");
+    // the search goes to the TV, the others answer it like they would a multicast one
+    let search = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let target = search.local_addr().unwrap();
+    let devices = [
+        (
+            "127.0.0.1",
+            format!("http://127.0.0.1:{tv}/description.xml"),
+        ),
+        ("127.0.0.2", format!("http://127.0.0.2:{plug}/")),
+        // pointing somewhere else
+        (
+            "127.0.0.3",
+            format!("http://127.0.0.1:{tv}/description.xml"),
+        ),
+    ];
+    std::thread::spawn(move || {
+        let mut query = [0; 1024];
+        let (len, from) = search.recv_from(&mut query).unwrap();
+        assert!(query[..len].starts_with(b"M-SEARCH * HTTP/1.1\r\n"));
+        for (ip, location) in devices {
+            let socket = UdpSocket::bind((ip, 0)).unwrap();
+            let response =
+                format!("HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\nLOCATION: {location}\r\n\r\n");
+            socket.send_to(response.as_bytes(), from).unwrap();
+        }
+        search.send_to(b"garbage", from).unwrap();
+    });
+
+    let tv_entry = entry("127.0.0.1", [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+    let plug_entry = entry("127.0.0.2", [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]);
+    let neighbors = || StaticDiscovery(vec![tv_entry.clone(), plug_entry.clone()]);
+    let ssdp = Ssdp::new(Box::new(neighbors())).with_target(target);
+    let named_tv = HostEntry {
+        name: "Living Room TV & Co".to_owned(),
+        named_by: Some(Backend::Ssdp),
+        ..tv_entry
+    };
+    assert_eq!(ssdp.discover().unwrap(), std::slice::from_ref(&named_tv));
+
+    // merged with the neighbor table, where it gets the name (and the found names are reused)
+    let composite = Composite(vec![Box::new(neighbors()), Box::new(ssdp)]);
+    assert_eq!(composite.discover().unwrap(), [named_tv, plug_entry]);
+}
+
+#[test]
+fn ssdp_friendly_names() {
+    let description = |name: &str| format!("<root><friendlyName>{name}</friendlyName></root>");
+    assert_eq!(
+        friendly_name(&description("  NAS\n  box ")).as_deref(),
+        Some("NAS box")
+    );
+    assert_eq!(
+        friendly_name(&description("&quot;Kitchen&quot;")).as_deref(),
+        Some("\"Kitchen\"")
+    );
+    assert_eq!(friendly_name(&description("")), None);
+    assert_eq!(friendly_name(&description("&nbsp;")), None);
+    assert_eq!(friendly_name("<friendlyName>Cut off"), None);
+    assert_eq!(friendly_name(&description("<b>nested</b>")), None);
+    assert_eq!(
+        friendly_name(&description(&"x".repeat(500))).unwrap().len(),
+        100
+    );
+}
@@ -0,0 +1,260 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use chrono::{TimeDelta, Utc};
+use common::{receiving, test_app};
+use http_body_util::BodyExt;
+use serde::de::DeserializeOwned;
+use std::{
+    net::{TcpListener, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{Host, JobStatus, SequenceJob, WakeOutcome, WakeResponse},
+    config::{
+        self, Config, DiscoveryFreshnessConfig, SequenceStep, StaticHost, WakeSequence, WhenStale,
+    },
+    discovery::{HostDiscovery, HostEntry},
+    server,
+    verify::Strategy,
+    MacAddress,
+};
+
+const NAS: MacAddress = MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+
+/// Fails until it's told to work, then finds `nas` at 127.0.0.1.
+#[derive(Clone, Default)]
+struct Discovery {
+    works: Arc<AtomicBool>,
+}
+
+impl HostDiscovery for Discovery {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        if !self.works.load(Ordering::SeqCst) {
+            eyre::bail!("the switch is unreachable");
+        }
+        Ok(vec![HostEntry {
+            name: "localhost".to_owned(),
+            ip: Some("127.0.0.1".parse().unwrap()),
+            mac: NAS,
+            named_by: None,
+            state: None,
+            vlan: None,
+        }])
+    }
+}
+
+/// Starts the server with its scheduler. `nas` is ready once `ready` takes connections, the packets
+/// for it arrive at the socket.
+fn start(when_stale: WhenStale, discovery: &Discovery, ready: &TcpListener) -> (Router, UdpSocket) {
+    let (state, receiver) = receiving(Config {
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        sequences: vec![WakeSequence {
+            name: "lab".to_owned(),
+            steps: vec![SequenceStep {
+                host: "nas".to_owned(),
+                ready: Some(Strategy::Tcp(ready.local_addr().unwrap().port())),
+                timeout: 5,
+            }],
+            continue_on_failure: false,
+        }],
+        discovery_freshness: Some(
+            serde_json::from_value(serde_json::json!({
+                "max_age": 3600,
+                "when_stale": when_stale,
+            }))
+            .unwrap(),
+        ),
+        ..Config::default()
+    });
+    receiver
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let state = Arc::new(state.with_discovery(discovery.clone()));
+    tokio::spawn(server::run_scheduler(state.clone()));
+    (test_app(state), receiver)
+}
+
+async fn send<T: DeserializeOwned>(app: &Router, request: Request<Body>) -> (StatusCode, T) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+fn post(path: &str, body: String) -> Request<Body> {
+    Request::post(path)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// Has the server discover, which fails unless discovery was told to work.
+async fn health_check(app: &Router) {
+    let request = Request::get("/healthz").body(Body::empty()).unwrap();
+    let (status, _): (_, serde_json::Value) = send(app, request).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn run_sequence(app: &Router) -> SequenceJob {
+    let request = Request::post("/wake-sequence/lab")
+        .body(Body::empty())
+        .unwrap();
+    let (status, job): (_, SequenceJob) = send(app, request).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    for _ in 0..100 {
+        let request = Request::get(format!("/jobs/{}", job.id))
+            .body(Body::empty())
+            .unwrap();
+        let (_, job): (_, SequenceJob) = send(app, request).await;
+        if job.status != JobStatus::Running {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("sequence didn't finish");
+}
+
+async fn last_outcome(app: &Router) -> Option<WakeOutcome> {
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    let (_, hosts): (_, Vec<Host>) = send(app, request).await;
+    Some(hosts[0].last_wake.as_ref()?.outcome)
+}
+
+fn received(receiver: &UdpSocket) -> usize {
+    let mut buf = [0; 200];
+    std::iter::from_fn(|| receiver.recv(&mut buf).ok()).count()
+}
+
+#[tokio::test]
+async fn manual_wakes_go_ahead_with_a_warning() {
+    let discovery = Discovery::default();
+    let ready = TcpListener::bind("127.0.0.1:0").unwrap();
+    let (app, receiver) = start(WhenStale::Skip, &discovery, &ready);
+    health_check(&app).await;
+
+    let wake = || post("/api/v1/wake", r#"{"host": "nas"}"#.to_owned());
+    let (status, response): (_, WakeResponse) = send(&app, wake()).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let stale = response.stale_discovery.unwrap();
+    assert_eq!(stale.last_success, None);
+    assert!(stale.error.unwrap().contains("the switch is unreachable"));
+    assert_eq!(received(&receiver), 1);
+
+    discovery.works.store(true, Ordering::SeqCst);
+    health_check(&app).await;
+    let (_, response): (_, WakeResponse) = send(&app, wake()).await;
+    assert_eq!(response.stale_discovery, None);
+}
+
+#[tokio::test]
+async fn skipped_while_stale() {
+    let discovery = Discovery::default();
+    let ready = TcpListener::bind("127.0.0.1:0").unwrap();
+    let (app, receiver) = start(WhenStale::Skip, &discovery, &ready);
+    health_check(&app).await;
+    // it would work, but nothing discovered again since
+    discovery.works.store(true, Ordering::SeqCst);
+
+    let job = run_sequence(&app).await;
+    assert_eq!(job.status, JobStatus::Failed);
+    let error = job.steps[0].error.as_deref().unwrap();
+    assert!(
+        error.starts_with("skipped: stale discovery, it didn't work in the "),
+        "{error}"
+    );
+    assert!(error.ends_with("the switch is unreachable"), "{error}");
+    assert_eq!(last_outcome(&app).await, Some(WakeOutcome::StaleDiscovery));
+    assert_eq!(received(&receiver), 0);
+
+    let request = Request::get("/metrics").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let metrics = String::from_utf8(body.to_vec()).unwrap();
+    assert!(
+        metrics.contains("outcome=\"stale_discovery\"} 1\n"),
+        "{metrics}"
+    );
+    assert!(
+        metrics.contains("\nwol_discovery_age_seconds "),
+        "{metrics}"
+    );
+}
+
+#[tokio::test]
+async fn refreshed_first_while_stale() {
+    let discovery = Discovery::default();
+    let ready = TcpListener::bind("127.0.0.1:0").unwrap();
+    let (app, receiver) = start(WhenStale::Refresh, &discovery, &ready);
+    health_check(&app).await;
+    discovery.works.store(true, Ordering::SeqCst);
+
+    let job = run_sequence(&app).await;
+    assert_eq!(job.status, JobStatus::Succeeded, "{:?}", job.steps);
+    assert_eq!(last_outcome(&app).await, Some(WakeOutcome::Sent));
+    assert_eq!(received(&receiver), 1);
+}
+
+#[tokio::test]
+async fn scheduled_wakes_too() {
+    let discovery = Discovery::default();
+    let ready = TcpListener::bind("127.0.0.1:0").unwrap();
+    let (app, receiver) = start(WhenStale::Refresh, &discovery, &ready);
+    health_check(&app).await;
+
+    let at = Utc::now() + TimeDelta::seconds(1);
+    let body = serde_json::json!({"host": "nas", "at": at}).to_string();
+    let (status, _): (_, serde_json::Value) = send(&app, post("/schedules", body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let mut outcome = None;
+    for _ in 0..100 {
+        outcome = last_outcome(&app).await;
+        if outcome.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    // discovering again didn't help
+    assert_eq!(outcome, Some(WakeOutcome::StaleDiscovery));
+    assert_eq!(received(&receiver), 0);
+}
+
+#[test]
+fn defaults() {
+    let freshness: DiscoveryFreshnessConfig =
+        serde_json::from_value(serde_json::json!({"max_age": 600})).unwrap();
+    assert_eq!(freshness.when_stale, WhenStale::Refresh);
+    assert_eq!(freshness.sources.len(), 2);
+}
+
+#[test]
+fn problems() {
+    let path = std::env::temp_dir().join(format!(
+        "wakeonlan-discovery-freshness-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        "[discovery_freshness]\nmax_age = 600\nsources = []\n",
+    )
+    .unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].key, "discovery_freshness.sources");
+    assert_eq!(
+        problems[0].message,
+        "empty, no wake would wait for discovery"
+    );
+}
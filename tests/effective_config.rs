@@ -0,0 +1,153 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use std::collections::BTreeMap;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::EffectiveConfig,
+    config::{Config, RemoteSite, Site, SnmpConfig, Source, StaticHost, Via, WakeStrategy},
+    server::AppState,
+};
+
+fn state() -> AppState {
+    AppState::new(Config {
+        broadcast: "192.168.1.255:9".parse().unwrap(),
+        token: Some("hunter2".into()),
+        hosts: vec![StaticHost {
+            password: Some("a1b2c3d4".parse().unwrap()),
+            post_wake_commands: vec!["ssh nas mount -a".to_owned()],
+            strategies: vec![WakeStrategy {
+                via: Via::Raw,
+                destination: None,
+                interface: Some("eth0".to_owned()),
+                ready: None,
+                timeout: 30,
+            }],
+            destinations: vec!["192.168.1.255:9".parse().unwrap()],
+            ..StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()
+        }],
+        url_secret: Some("signing secret".into()),
+        sites: vec![Site {
+            name: "parents".to_owned(),
+            broadcast: None,
+            interface: None,
+            source: None,
+            discovery: Vec::new(),
+            vlan: None,
+            remote: Some(RemoteSite {
+                url: "http://10.9.0.2:8090".to_owned(),
+                token: Some("their token".into()),
+            }),
+            ssh: None,
+        }],
+        snmp: Some(SnmpConfig {
+            switch: "192.0.2.1:161".parse().unwrap(),
+            community: "private".into(),
+            timeout_ms: 1000,
+        }),
+        sources: BTreeMap::from([
+            ("broadcast".to_owned(), Source::Env),
+            ("sites".to_owned(), Source::File),
+        ]),
+        ..Config::default()
+    })
+    .unwrap()
+}
+
+async fn get(app: &Router, token: Option<&str>) -> (StatusCode, Vec<u8>) {
+    let mut request = Request::get("/api/v1/config");
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+#[tokio::test]
+async fn needs_the_token() {
+    let app = test_app(state());
+    assert_eq!(get(&app, None).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(get(&app, Some("wrong")).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(get(&app, Some("hunter2")).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn says_where_settings_came_from() {
+    let (_, body) = get(&test_app(state()), Some("hunter2")).await;
+    let config: EffectiveConfig = serde_json::from_slice(&body).unwrap();
+    let setting = |key: &str| config.settings[key].clone();
+
+    assert_eq!(setting("broadcast").value, "192.168.1.255:9");
+    assert_eq!(setting("broadcast").source, Source::Env);
+    assert_eq!(setting("sites").source, Source::File);
+    assert_eq!(
+        setting("sites").value[0]["remote"]["url"],
+        "http://10.9.0.2:8090"
+    );
+    assert_eq!(setting("verify_timeout").value, 2);
+    assert_eq!(setting("verify_timeout").source, Source::Default);
+    assert_eq!(setting("log_buffer_level").value, "info");
+    assert_eq!(
+        setting("retry").value,
+        serde_json::json!({"max_attempts": 3, "initial_backoff_ms": 500, "max_backoff_ms": 4000})
+    );
+    assert!(!config.settings.contains_key("sources"));
+}
+
+#[tokio::test]
+async fn hosts_have_what_only_the_config_file_has() {
+    let (_, body) = get(&test_app(state()), Some("hunter2")).await;
+    let config: EffectiveConfig = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        config.settings["hosts"].value,
+        serde_json::json!([{
+            "name": "nas",
+            "macs": ["a8:a1:59:0e:7b:02"],
+            "password": "<redacted>",
+            "post_wake_commands": ["ssh nas mount -a"],
+            "strategies": [{"via": "raw", "interface": "eth0", "timeout": 30}],
+            "destinations": ["192.168.1.255:9"],
+        }])
+    );
+}
+
+#[tokio::test]
+async fn redacts_secrets() {
+    let (_, body) = get(&test_app(state()), Some("hunter2")).await;
+    let body = String::from_utf8(body).unwrap();
+    for secret in [
+        "hunter2",
+        "signing secret",
+        "their token",
+        "private",
+        "a1b2c3d4",
+    ] {
+        assert!(!body.contains(secret), "{secret} in {body}");
+    }
+    let config: EffectiveConfig = serde_json::from_str(&body).unwrap();
+    assert_eq!(config.settings["token"].value, "<redacted>");
+    assert_eq!(
+        config.settings["sites"].value[0]["remote"]["token"],
+        "<redacted>"
+    );
+    assert_eq!(config.settings["snmp"].value["community"], "<redacted>");
+    // nothing set isn't redacted
+    assert_eq!(
+        config.settings["default_host"].value,
+        serde_json::Value::Null
+    );
+}
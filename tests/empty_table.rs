@@ -0,0 +1,92 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::Health,
+    config::{Config, StaticHost},
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    MacAddress,
+};
+
+const NOTHING_DISCOVERED: &str = "no hosts are discovered yet, the neighbor table is empty. \
+                                  configure the host in `hosts` or wait for discovery";
+
+fn state(discovered: Vec<HostEntry>) -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![StaticHost::new("nas-01", ["02:00:00:00:00:01"]).unwrap()],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(discovered))
+}
+
+async fn wake(app: &Router, host: &str) -> (StatusCode, serde_json::Value) {
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(r#"{{"host": "{host}"}}"#)))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+async fn health(app: &Router) -> Health {
+    let request = Request::get("/healthz").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn nothing_discovered() {
+    let app = test_app(state(Vec::new()));
+
+    let (status, body) = wake(&app, "pc").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        body["error"],
+        format!("host `pc` not found, {NOTHING_DISCOVERED}")
+    );
+
+    // found in the registry like discovery would have found it
+    let (status, body) = wake(&app, "nas").await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(body["mac"], "02:00:00:00:00:01");
+
+    let health = health(&app).await;
+    assert_eq!((health.discovered, health.configured), (0, 1));
+    assert_eq!(health.problems, [NOTHING_DISCOVERED]);
+}
+
+#[tokio::test]
+async fn something_discovered() {
+    let app = test_app(state(vec![HostEntry {
+        name: "tv".to_owned(),
+        ip: Some("192.168.1.20".parse().unwrap()),
+        mac: MacAddress([0x02, 0, 0, 0, 0, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]));
+
+    let (status, body) = wake(&app, "pc").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"], "host `pc` not found");
+
+    let health = health(&app).await;
+    assert_eq!((health.discovered, health.configured), (1, 1));
+    assert!(health.problems.is_empty());
+}
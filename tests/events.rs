@@ -0,0 +1,186 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{test_app, NoSend};
+use http_body_util::BodyExt;
+use std::time::Duration;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{Event, MemoryStats, PublishedEvent},
+    config::{Config, LimitsConfig},
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    verify::Strategy,
+    MacAddress,
+};
+
+/// Knows `pc` at 127.0.0.1, which is up whenever something listens on `port`.
+fn state(port: u16, events: usize) -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        verify: vec![Strategy::Tcp(port)],
+        limits: LimitsConfig {
+            events,
+            ..LimitsConfig::default()
+        },
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(NoSend)
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "pc".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0x02, 0, 0, 0, 0, 0x01]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]))
+}
+
+async fn subscribe(app: &Router, query: &str) -> Body {
+    let request = Request::get(format!("/api/v1/events{query}"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/event-stream"
+    );
+    response.into_body()
+}
+
+async fn wake(app: &Router, body: &str) -> StatusCode {
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+/// Reads messages from the stream until `done` says so, the events and the comments.
+async fn read_until(
+    body: &mut Body,
+    mut done: impl FnMut(&[PublishedEvent], &[String]) -> bool,
+) -> (Vec<PublishedEvent>, Vec<String>) {
+    let (mut events, mut comments, mut buffer) = (Vec::new(), Vec::new(), String::new());
+    while !done(&events, &comments) {
+        let frame = tokio::time::timeout(Duration::from_secs(5), body.frame())
+            .await
+            .expect("no event in time")
+            .unwrap()
+            .unwrap();
+        buffer.push_str(std::str::from_utf8(&frame.into_data().unwrap()).unwrap());
+        while let Some(end) = buffer.find("\n\n") {
+            let message = buffer[..end].to_owned();
+            buffer.drain(..end + 2);
+            match message.strip_prefix(": ") {
+                Some(comment) => comments.push(comment.to_owned()),
+                None => {
+                    let data = message
+                        .lines()
+                        .find_map(|line| line.strip_prefix("data: "))
+                        .unwrap();
+                    events.push(serde_json::from_str(data).unwrap());
+                }
+            }
+        }
+    }
+    (events, comments)
+}
+
+#[tokio::test]
+async fn follows_a_wake_until_the_host_is_up() {
+    let up = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let app = test_app(state(up.local_addr().unwrap().port(), 256));
+    let mut body = subscribe(&app, "").await;
+
+    let status = wake(&app, r#"{"host": "pc", "wait_online": 5}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (events, _) = read_until(&mut body, |events, _| {
+        events
+            .iter()
+            .any(|event| matches!(event.event, Event::HostOnline { .. }))
+    })
+    .await;
+
+    let names = events
+        .iter()
+        .map(|event| event.event.name())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "wake_requested",
+            "wake_sent",
+            "verification_started",
+            "host_online"
+        ]
+    );
+    assert!(events.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+    let (Event::WakeRequested { id, .. }, Event::WakeSent { id: sent, macs, .. }) =
+        (&events[0].event, &events[1].event)
+    else {
+        panic!("{events:?}");
+    };
+    assert_eq!(id, sent);
+    assert_eq!(macs, &["02:00:00:00:00:01"]);
+    match &events[3].event {
+        Event::HostOnline { host, strategy, .. } => {
+            assert_eq!(host.as_deref(), Some("pc"));
+            assert_eq!(*strategy, Strategy::Tcp(up.local_addr().unwrap().port()));
+        }
+        other => panic!("{other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn only_the_events_asked_for() {
+    let app = test_app(state(1, 256));
+    let mut body = subscribe(&app, "?events=wake_failed").await;
+
+    assert_eq!(
+        wake(&app, r#"{"host": "gone"}"#).await,
+        StatusCode::NOT_FOUND
+    );
+    let (events, _) = read_until(&mut body, |events, _| !events.is_empty()).await;
+    match &events[0].event {
+        Event::WakeFailed { host, error, .. } => {
+            assert_eq!(host.as_deref(), Some("gone"));
+            assert_eq!(error, "host `gone` not found");
+        }
+        other => panic!("{other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn slow_subscribers_miss_events() {
+    let app = test_app(state(1, 2));
+    let mut body = subscribe(&app, "").await;
+
+    // more than fit into the stream and the channel
+    for _ in 0..50 {
+        let status = wake(&app, r#"{"host": "pc", "dry_run": true}"#).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+    let (_, comments) = read_until(&mut body, |_, comments| {
+        comments.iter().any(|comment| comment.starts_with("missed"))
+    })
+    .await;
+    assert!(!comments.is_empty());
+
+    let request = Request::get("/api/v1/stats").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let stats = response.into_body().collect().await.unwrap().to_bytes();
+    let stats: MemoryStats = serde_json::from_slice(&stats).unwrap();
+    assert_eq!(stats.events.subscribers, 1);
+    assert_eq!(stats.events.published, 100);
+    assert!(stats.events.dropped > 0, "{:?}", stats.events);
+    assert_eq!(stats.events.buffered.limit, Some(2));
+}
@@ -0,0 +1,19 @@
+use std::{path::Path, process::Command};
+
+/// The packets can be built and parsed without std's sockets (or anything of the server), for
+/// platforms that send them some other way.
+#[test]
+fn builds_without_default_features() {
+    let output = Command::new(env!("CARGO"))
+        .args(["build", "--lib", "--no-default-features", "--manifest-path"])
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(Path::new(env!("CARGO_TARGET_TMPDIR")).join("no-default-features"))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
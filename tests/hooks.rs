@@ -0,0 +1,187 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{post, test_app};
+use http_body_util::BodyExt;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{Host, LastWake},
+    config::{self, Config, HooksConfig, StaticHost},
+    discovery::StaticDiscovery,
+    server::{self, AppState},
+};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("wakeonlan-hooks-{name}-{}", std::process::id()))
+}
+
+/// Starts the server with its hooks.
+fn start(commands: &[String], hooks: HooksConfig) -> Router {
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![StaticHost {
+            post_wake_commands: commands.to_vec(),
+            ..StaticHost::new("build", ["02:00:00:00:00:01"]).unwrap()
+        }],
+        hooks,
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(Vec::new()));
+    let state = Arc::new(state);
+    tokio::spawn(server::run_hooks(state.clone()));
+    test_app(state)
+}
+
+async fn wake(app: &Router) {
+    let (status, _) = post(app, "/api/v1/wake", r#"{"host": "build"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+}
+
+async fn last_wake(app: &Router) -> LastWake {
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let hosts: Vec<Host> = serde_json::from_slice(&body).unwrap();
+    hosts.into_iter().next().unwrap().last_wake.unwrap()
+}
+
+/// Polls until `count` commands ran, for at most five seconds.
+async fn hooks_ran(app: &Router, count: usize) -> LastWake {
+    for _ in 0..50 {
+        let wake = last_wake(app).await;
+        if wake.hooks.len() >= count {
+            return wake;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("the commands didn't run");
+}
+
+#[tokio::test]
+async fn commands_run_after_the_wake() {
+    let marker = temp_path("marker");
+    let _ = std::fs::remove_file(&marker);
+    let commands = [
+        format!("echo \"$WOL_HOST $WOL_WAKE_ID\" > {}", marker.display()),
+        "exit 3".to_owned(),
+        "sleep 10".to_owned(),
+    ];
+    let app = start(
+        &commands,
+        HooksConfig {
+            enabled: true,
+            timeout: 1,
+            wait_online: 0,
+        },
+    );
+
+    wake(&app).await;
+    let wake = hooks_ran(&app, 3).await;
+    let summary = wake
+        .hooks
+        .iter()
+        .map(|run| (run.exit_code, run.success, run.error.as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (Some(0), true, None),
+            (Some(3), false, Some("exited with status 3")),
+            (None, false, Some("timed out after 1s")),
+        ]
+    );
+    assert_eq!(wake.hooks[1].command, "exit 3");
+    assert!(wake.hooks[2].duration >= 1.0);
+    assert!(wake.hooks[2].duration < 5.0);
+    let written = std::fs::read_to_string(&marker).unwrap();
+    assert_eq!(written.trim(), format!("build {}", wake.id));
+    std::fs::remove_file(&marker).unwrap();
+}
+
+#[tokio::test]
+async fn turned_off() {
+    let marker = temp_path("off");
+    let _ = std::fs::remove_file(&marker);
+    let app = start(
+        &[format!("touch {}", marker.display())],
+        HooksConfig {
+            enabled: false,
+            wait_online: 0,
+            ..HooksConfig::default()
+        },
+    );
+
+    wake(&app).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(last_wake(&app).await.hooks.is_empty());
+    assert!(!marker.exists());
+}
+
+#[tokio::test]
+async fn only_from_the_config() {
+    let app = start(&["true".to_owned()], HooksConfig::default());
+    let export = || async {
+        let request = Request::get("/hosts/export").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    };
+
+    // neither exported nor taken from an import
+    assert_eq!(
+        export().await,
+        serde_json::json!({"hosts": [{"name": "build", "macs": ["02:00:00:00:00:01"]}]})
+    );
+    let document = serde_json::json!({"hosts": [
+        {"name": "laptop", "macs": ["02:00:00:00:00:02"], "post_wake_commands": ["true"]},
+    ]});
+    let request = Request::post("/hosts/import?mode=merge")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(document.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        export().await["hosts"][1],
+        serde_json::json!({"name": "laptop", "macs": ["02:00:00:00:00:02"]})
+    );
+}
+
+#[test]
+fn problems() {
+    let path = temp_path("config.toml");
+    std::fs::write(
+        &path,
+        r#"
+[[hosts]]
+name = "build"
+mac = "02:00:00:00:00:01"
+post_wake_commands = ["mount /mnt/share", " "]
+
+[hooks]
+timeout = 0
+"#,
+    )
+    .unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    let keys = problems
+        .iter()
+        .map(|problem| (problem.key.as_str(), problem.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        keys,
+        [
+            ("hooks.timeout", "no command finishes that fast"),
+            ("hosts[0].post_wake_commands[1]", "empty command"),
+        ]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,278 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use serde::de::DeserializeOwned;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{Host, WakeResponse},
+    config::{self, Config, StaticHost},
+    discovery::{HostDiscovery, HostEntry},
+    retry::RetryPolicy,
+    server::{AppState, PacketSender},
+    verify::Strategy,
+    MacAddress, MagicPacket,
+};
+
+const MAC: MacAddress = MacAddress([0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]);
+
+/// Can't send to `10.0.0.255`, and the host only comes up (at 127.0.0.1) once a packet got out.
+#[derive(Clone, Default)]
+struct Network {
+    sent: Arc<Mutex<Vec<SocketAddr>>>,
+    up: Arc<AtomicBool>,
+}
+
+impl PacketSender for Network {
+    fn send(&self, _: &MagicPacket, to: SocketAddr, _: Option<&str>) -> io::Result<SocketAddr> {
+        if to.ip().to_string() == "10.0.0.255" {
+            return Err(io::Error::from_raw_os_error(libc::EHOSTUNREACH));
+        }
+        self.sent.lock().unwrap().push(to);
+        self.up.store(true, Ordering::SeqCst);
+        Ok("127.0.0.1:40000".parse().unwrap())
+    }
+}
+
+impl HostDiscovery for Network {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        if !self.up.load(Ordering::SeqCst) {
+            return Ok(Vec::new());
+        }
+        Ok(vec![HostEntry {
+            name: "127.0.0.1".to_owned(),
+            ip: Some("127.0.0.1".parse().unwrap()),
+            mac: MAC,
+            named_by: None,
+            state: None,
+            vlan: None,
+        }])
+    }
+}
+
+fn addresses(addresses: &[&str]) -> Vec<SocketAddr> {
+    addresses
+        .iter()
+        .map(|address| address.parse().unwrap())
+        .collect()
+}
+
+fn state(host: StaticHost, network: &Network) -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![host],
+        // refused still means it's up
+        verify: vec![Strategy::Tcp(1)],
+        retry: RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        },
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(network.clone())
+    .with_discovery(network.clone())
+}
+
+/// Sends to the destinations instead of the broadcast address.
+fn workstation(destinations: &[&str]) -> StaticHost {
+    StaticHost {
+        destinations: addresses(destinations),
+        ..StaticHost::new("workstation", ["00:d8:61:ca:3a:18"]).unwrap()
+    }
+}
+
+async fn send<T: DeserializeOwned>(app: &Router, request: Request<Body>) -> (StatusCode, T) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+fn wake(body: &'static str) -> Request<Body> {
+    Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn tried(response: &WakeResponse) -> Vec<(SocketAddr, bool)> {
+    (response.destinations.iter())
+        .map(|destination| (destination.address, destination.sent))
+        .collect()
+}
+
+#[tokio::test]
+async fn tried_in_order_until_one_works() {
+    let network = Network::default();
+    let host = workstation(&["10.0.0.255:9", "10.0.1.255:9", "10.0.2.255:9"]);
+    let app = test_app(state(host, &network));
+
+    let (status, response): (_, WakeResponse) =
+        send(&app, wake(r#"{"host": "workstation"}"#)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let [first, second] = addresses(&["10.0.0.255:9", "10.0.1.255:9"])[..] else {
+        unreachable!()
+    };
+    assert_eq!(tried(&response), [(first, false), (second, true)]);
+    assert_eq!(response.destination, Some(second));
+    assert_eq!(*network.sent.lock().unwrap(), [second]);
+
+    let (_, hosts): (_, Vec<Host>) = send(
+        &app,
+        Request::get("/api/v1/hosts").body(Body::empty()).unwrap(),
+    )
+    .await;
+    let host = hosts
+        .iter()
+        .find(|host| host.name == "workstation")
+        .unwrap();
+    assert_eq!(host.last_wake.as_ref().unwrap().destination, Some(second));
+}
+
+#[tokio::test]
+async fn fails_if_none_works() {
+    let network = Network::default();
+    let app = test_app(state(
+        workstation(&["10.0.0.255:9", "10.0.0.255:7"]),
+        &network,
+    ));
+
+    let (status, response): (_, serde_json::Value) =
+        send(&app, wake(r#"{"host": "workstation"}"#)).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{response}");
+    assert!(network.sent.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn dry_run_shows_every_destination_in_order() {
+    let network = Network::default();
+    let host = workstation(&["10.0.0.255:9", "10.0.1.255:9", "10.0.2.255:9"]);
+    let app = test_app(state(host, &network));
+
+    let (status, response): (_, WakeResponse) =
+        send(&app, wake(r#"{"host": "workstation", "dry_run": true}"#)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let planned = (response.destinations.iter())
+        .map(|destination| destination.address)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        planned,
+        addresses(&["10.0.0.255:9", "10.0.1.255:9", "10.0.2.255:9"])
+    );
+    assert_eq!(response.destination, None);
+    assert!(network.sent.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn until_online() {
+    let network = Network::default();
+    let host: StaticHost = serde_json::from_str(
+        r#"{
+            "name": "workstation",
+            "mac": "00:d8:61:ca:3a:18",
+            "destinations": ["10.0.0.255:9", "10.0.1.255:9", "10.0.2.255:9"],
+            "destinations_until": "online"
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(host.strategies.len(), 3);
+    let app = test_app(state(host, &network));
+
+    let (status, response): (_, WakeResponse) =
+        send(&app, wake(r#"{"host": "workstation"}"#)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(response.strategy.as_deref(), Some("udp to 10.0.1.255:9"));
+    assert_eq!(response.online, Some(true));
+    assert_eq!(response.destination, Some("10.0.1.255:9".parse().unwrap()));
+}
+
+/// The keys and messages of the problems with the config file.
+fn problems(name: &str, config: &str) -> Vec<(String, String)> {
+    let path = std::env::temp_dir().join(format!(
+        "wakeonlan-host-destinations-{name}-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(&path, config).unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    std::fs::remove_file(&path).unwrap();
+    (problems.into_iter())
+        .map(|problem| (problem.key, problem.message))
+        .collect()
+}
+
+#[test]
+fn invalid_hosts() {
+    let problems = problems(
+        "hosts",
+        r#"
+[[hosts]]
+name = "pc"
+mac = "00:d8:61:ca:3a:18"
+destinations = []
+
+[[hosts]]
+name = "tv"
+mac = "a8:a1:59:0e:7b:03"
+destinations_until = "online"
+
+[[hosts]]
+name = "nas"
+mac = "a8:a1:59:0e:7b:02"
+destinations = ["10.0.0.255:9"]
+destinations_until = "online"
+strategies = [{ via = "raw", interface = "eth0" }]
+"#,
+    );
+    assert_eq!(
+        problems,
+        [
+            ("hosts[0]", "empty destinations for host `pc`"),
+            (
+                "hosts[1]",
+                "destinations_until without destinations for host `tv`"
+            ),
+            (
+                "hosts[2]",
+                "host `nas` has strategies, its destinations can't be strategies as well"
+            ),
+        ]
+        .map(|(key, message)| (key.to_owned(), message.to_owned()))
+    );
+}
+
+#[test]
+fn invalid_destinations() {
+    let problems = problems(
+        "destinations",
+        r#"
+[[hosts]]
+name = "nas"
+mac = "a8:a1:59:0e:7b:02"
+destinations = ["10.0.0.255:9", "10.0.1.255:0", "10.0.0.255:9"]
+"#,
+    );
+    assert_eq!(
+        problems,
+        [
+            ("hosts[0].destinations[1]", "port 0 can't be sent to"),
+            ("hosts[0].destinations[2]", "listed twice"),
+        ]
+        .map(|(key, message)| (key.to_owned(), message.to_owned()))
+    );
+}
@@ -0,0 +1,106 @@
+#![cfg(feature = "server")]
+
+use std::time::Duration;
+use wakeonlan::verify::{
+    self,
+    icmp::{self, EchoReply},
+    ProbeError, Strategy,
+};
+
+fn bytes(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// A reply to `127.0.0.1` from a raw socket, with the IPv4 header in front.
+const RAW_REPLY: &str =
+    "4500002cabcd4000400191017f0000017f00000100007c381234000777616b656f6e6c616e2070726f626500";
+/// A reply from a datagram socket, only the ICMP message.
+const DATAGRAM_REPLY: &str = "0000cf59beef002a77616b656f6e6c616e2070726f626500";
+
+#[test]
+fn checksum() {
+    // the example of RFC 1071
+    assert_eq!(
+        icmp::checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]),
+        !0xddf2
+    );
+    // odd lengths are padded with a zero
+    assert_eq!(icmp::checksum(&[0x01]), !0x0100);
+    assert_eq!(icmp::checksum(&[]), 0xffff);
+}
+
+#[test]
+fn echo_request() {
+    let request = icmp::echo_request(false, 0x1234, 7);
+    assert_eq!(
+        request,
+        bytes("080074381234000777616b656f6e6c616e2070726f626500")
+    );
+    assert_eq!(icmp::checksum(&request), 0);
+
+    // the kernel fills in the checksum of ICMPv6
+    let request = icmp::echo_request(true, 0x1234, 7);
+    assert_eq!(&request[..8], [128, 0, 0, 0, 0x12, 0x34, 0, 7]);
+}
+
+#[test]
+fn replies() {
+    let reply = EchoReply::parse(&bytes(RAW_REPLY), false, true).unwrap();
+    assert_eq!(
+        reply,
+        EchoReply {
+            identifier: 0x1234,
+            sequence: 7
+        }
+    );
+    assert!(reply.answers(0x1234, 7));
+    assert!(!reply.answers(0x1234, 8));
+    assert!(!reply.answers(0x4321, 7));
+
+    let reply = EchoReply::parse(&bytes(DATAGRAM_REPLY), false, false).unwrap();
+    assert!(reply.answers(0xbeef, 42));
+
+    let mut v6 = bytes(DATAGRAM_REPLY);
+    v6[0] = icmp::ECHO_REPLY_V6;
+    assert!(EchoReply::parse(&v6, true, false)
+        .unwrap()
+        .answers(0xbeef, 42));
+}
+
+#[test]
+fn not_replies() {
+    // an echo request, like the ones raw sockets see going out to loopback
+    let request = icmp::echo_request(false, 0x1234, 7);
+    assert_eq!(EchoReply::parse(&request, false, false), None);
+    // the IPv4 header wasn't skipped
+    assert_eq!(EchoReply::parse(&bytes(RAW_REPLY), false, false), None);
+    // broken on the way
+    let mut reply = bytes(DATAGRAM_REPLY);
+    reply[12] ^= 0xff;
+    assert_eq!(EchoReply::parse(&reply, false, false), None);
+    assert_eq!(EchoReply::parse(&reply[..6], false, false), None);
+    assert_eq!(EchoReply::parse(&[], false, true), None);
+    // an ICMPv4 reply isn't an ICMPv6 one
+    assert_eq!(EchoReply::parse(&bytes(DATAGRAM_REPLY), true, false), None);
+}
+
+#[test]
+fn loopback() {
+    let ip = "127.0.0.1".parse().unwrap();
+    match icmp::ping(ip, Duration::from_secs(1)) {
+        Ok(answered) => assert!(answered),
+        // neither unprivileged nor raw ICMP sockets where the tests run
+        Err(ProbeError::Unavailable(_)) => {}
+        Err(e) => panic!("{e}"),
+    }
+    // without ICMP it falls back to connecting to SSH, which is refused if nothing listens
+    let verified = verify::check(&[Strategy::Icmp], ip, Duration::from_secs(1)).unwrap();
+    assert!(verified.online);
+    assert!(
+        [Strategy::Icmp, verify::FALLBACK].contains(&verified.strategy),
+        "{verified:?}"
+    );
+}
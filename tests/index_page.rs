@@ -0,0 +1,118 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use common::{test_app, NoSend};
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+use wakeonlan::{
+    config::{Config, StaticHost},
+    discovery::StaticDiscovery,
+    server::AppState,
+};
+
+fn state() -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        index_page: "/nonexistent/index.html".into(),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(NoSend)
+    .with_discovery(StaticDiscovery(Vec::new()))
+}
+
+async fn index(
+    app: &Router,
+    method: Method,
+    etag: Option<&str>,
+) -> (StatusCode, HeaderMap, Vec<u8>) {
+    let mut request = Request::builder().method(method).uri("/");
+    if let Some(etag) = etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let (status, headers) = (response.status(), response.headers().clone());
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, headers, body.to_vec())
+}
+
+#[tokio::test]
+async fn page_with_etag() {
+    let app = test_app(state());
+
+    let (status, headers, body) = index(&app, Method::GET, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "text/html; charset=utf-8");
+    assert_eq!(headers[header::CACHE_CONTROL], "no-cache");
+    assert_eq!(headers[header::CONTENT_LENGTH], body.len().to_string());
+    assert!(String::from_utf8(body).unwrap().contains("<b>nas</b>"));
+    let etag = headers[header::ETAG].to_str().unwrap().to_owned();
+    assert!(etag.starts_with('"') && etag.ends_with('"'), "{etag}");
+
+    // the same page has the same tag
+    let (_, again, _) = index(&app, Method::GET, None).await;
+    assert_eq!(again[header::ETAG], etag);
+}
+
+#[tokio::test]
+async fn not_modified() {
+    let app = test_app(state());
+    let (_, headers, _) = index(&app, Method::GET, None).await;
+    let etag = headers[header::ETAG].to_str().unwrap().to_owned();
+
+    for tags in [
+        etag.clone(),
+        format!("W/{etag}"),
+        format!(r#""other", {etag}"#),
+        "*".to_owned(),
+    ] {
+        let (status, headers, body) = index(&app, Method::GET, Some(&tags)).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED, "{tags}");
+        assert_eq!(headers[header::ETAG], etag, "{tags}");
+        assert!(body.is_empty(), "{tags}");
+    }
+    let (status, _, body) = index(&app, Method::GET, Some(r#""other""#)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.is_empty());
+
+    // waking a host changes the page, and its tag
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("host=nas"))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let (status, headers, body) = index(&app, Method::GET, Some(&etag)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(headers[header::ETAG], etag);
+    assert!(String::from_utf8(body).unwrap().contains("woken"));
+}
+
+#[tokio::test]
+async fn head() {
+    let app = test_app(state());
+    let (_, get, page) = index(&app, Method::GET, None).await;
+
+    let (status, headers, body) = index(&app, Method::HEAD, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.is_empty());
+    assert_eq!(headers[header::CONTENT_TYPE], "text/html; charset=utf-8");
+    assert_eq!(headers[header::ETAG], get[header::ETAG]);
+    assert_eq!(headers[header::CONTENT_LENGTH], page.len().to_string());
+
+    let etag = get[header::ETAG].to_str().unwrap();
+    let (status, _, body) = index(&app, Method::HEAD, Some(etag)).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert!(body.is_empty());
+}
@@ -0,0 +1,46 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use std::sync::Arc;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::Info,
+    config::{Config, StaticHost},
+    discovery::{Backend, StaticDiscovery},
+    server::{self, AppState},
+};
+
+#[tokio::test]
+async fn nothing_secret() {
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        token: Some("secret".into()),
+        discovery: vec![Backend::IpNeigh, Backend::Ssdp],
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(Vec::new()));
+    let app = server::router(Arc::new(state));
+
+    let request = Request::get("/api/v1/info").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    assert!(!text.contains("secret"), "{text}");
+    assert!(!text.contains("a8:a1:59"), "{text}");
+    assert!(!text.contains("nas"), "{text}");
+
+    let info: Info = serde_json::from_str(&text).unwrap();
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert!(info.built_at.is_some());
+    assert!(info.features.iter().any(|feature| feature == "server"));
+    assert_eq!(info.discovery, [Backend::IpNeigh, Backend::Ssdp]);
+    assert_eq!(info.static_hosts, 1);
+    assert!(info.uptime >= 0.0);
+}
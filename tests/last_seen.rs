@@ -0,0 +1,136 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::Host,
+    config::{Config, StaticHost},
+    discovery::{HostDiscovery, HostEntry, NeighborState},
+    server::AppState,
+    MacAddress,
+};
+
+const NAS: MacAddress = MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+const PC: MacAddress = MacAddress([0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]);
+
+/// Finds whatever the test put in there last.
+#[derive(Clone, Default)]
+struct Table(Arc<Mutex<Vec<HostEntry>>>);
+
+impl Table {
+    fn set(&self, entries: &[(&str, MacAddress, NeighborState)]) {
+        *self.0.lock().unwrap() = entries
+            .iter()
+            .map(|&(ip, mac, state)| HostEntry {
+                name: ip.to_owned(),
+                ip: Some(ip.parse().unwrap()),
+                mac,
+                named_by: None,
+                state: Some(state),
+                vlan: None,
+            })
+            .collect();
+    }
+}
+
+impl HostDiscovery for Table {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+}
+
+fn state(table: &Table) -> AppState {
+    AppState::new(Config {
+        hosts: vec![
+            StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap(),
+            StaticHost::new("pc", ["00:d8:61:ca:3a:18"]).unwrap(),
+        ],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(table.clone())
+}
+
+async fn get(app: &Router, accept: &str) -> Vec<u8> {
+    let request = Request::get("/hosts")
+        .header(header::ACCEPT, accept)
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    response
+        .into_body()
+        .collect()
+        .await
+        .unwrap()
+        .to_bytes()
+        .to_vec()
+}
+
+async fn hosts(app: &Router) -> Vec<Host> {
+    let mut hosts: Vec<Host> = serde_json::from_slice(&get(app, "application/json").await).unwrap();
+    hosts.sort_by(|a, b| a.name.cmp(&b.name));
+    hosts
+}
+
+#[tokio::test]
+async fn kept_across_refreshes() {
+    let table = Table::default();
+    let app = test_app(state(&table));
+    table.set(&[
+        ("192.168.1.20", NAS, NeighborState::Reachable),
+        ("192.168.1.23", PC, NeighborState::Stale),
+    ]);
+
+    let [nas, pc] = &hosts(&app).await[..] else {
+        panic!("expected nas and pc");
+    };
+    assert_eq!(nas.state, Some(NeighborState::Reachable));
+    let seen = nas.last_seen.unwrap();
+    // being stale doesn't mean it was seen
+    assert_eq!(pc.state, Some(NeighborState::Stale));
+    assert_eq!(pc.last_seen, None);
+
+    table.set(&[
+        ("192.168.1.20", NAS, NeighborState::Failed),
+        ("192.168.1.23", PC, NeighborState::Stale),
+    ]);
+    let [nas, _] = &hosts(&app).await[..] else {
+        panic!("expected nas and pc");
+    };
+    assert_eq!(nas.state, Some(NeighborState::Failed));
+    assert_eq!(nas.last_seen, Some(seen));
+
+    // gone from the table entirely, it's still known when it was there
+    table.set(&[]);
+    let [nas, pc] = &hosts(&app).await[..] else {
+        panic!("expected nas and pc");
+    };
+    assert_eq!((nas.state, nas.last_seen), (None, Some(seen)));
+    assert_eq!((pc.state, pc.last_seen), (None, None));
+
+    table.set(&[("192.168.1.20", NAS, NeighborState::Reachable)]);
+    assert!(hosts(&app).await[0].last_seen.unwrap() >= seen);
+}
+
+#[tokio::test]
+async fn shown_on_the_page() {
+    let table = Table::default();
+    let app = test_app(state(&table));
+    table.set(&[
+        ("192.168.1.20", NAS, NeighborState::Reachable),
+        ("192.168.1.23", PC, NeighborState::Stale),
+    ]);
+    let page = String::from_utf8(get(&app, "text/html").await).unwrap();
+    assert!(page.contains("<th>Seen</th>"), "{page}");
+    assert!(page.contains("<td>reachable, seen just now</td>"), "{page}");
+    assert!(page.contains("<td>stale</td>"), "{page}");
+}
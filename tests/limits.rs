@@ -0,0 +1,165 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use common::{post, test_app, NoSend};
+use http_body_util::BodyExt;
+use std::{
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{Host, MemoryStats, Size},
+    config::{Config, LimitsConfig, RelayConfig},
+    discovery::{HostDiscovery, HostEntry},
+    server::{AppState, Relay},
+    MacAddress, MagicPacket,
+};
+
+/// Discovers five new hosts in every round, like a network where they keep changing.
+#[derive(Clone, Default)]
+struct Churning(Arc<AtomicUsize>);
+
+impl HostDiscovery for Churning {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        let round = self.0.load(Ordering::SeqCst);
+        Ok((0..5)
+            .map(|i| {
+                let n = (round * 5 + i) as u32;
+                let [_, a, b, c] = n.to_be_bytes();
+                HostEntry {
+                    name: format!("h{n:05}-pc"),
+                    ip: Some(format!("10.{a}.{b}.{c}").parse().unwrap()),
+                    mac: MacAddress([0x02, 0, 0, a, b, c]),
+                    named_by: None,
+                    state: None,
+                    vlan: None,
+                }
+            })
+            .collect())
+    }
+}
+
+fn limits() -> LimitsConfig {
+    LimitsConfig {
+        history: 100,
+        hosts: 200,
+        relay_sources: 2,
+        ..LimitsConfig::default()
+    }
+}
+
+async fn memory_stats(app: &Router) -> MemoryStats {
+    let request = Request::get("/api/v1/stats").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+async fn wake(app: &Router, body: String) {
+    let (status, _) = post(app, "/api/v1/wake", &body).await;
+    assert!(status.is_success(), "{status}");
+}
+
+#[tokio::test]
+async fn thousands_of_wakes_stay_bounded() {
+    let round = Arc::new(AtomicUsize::new(0));
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        limits: limits(),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(NoSend)
+    .with_discovery(Churning(round.clone()));
+    let app = test_app(state);
+
+    for i in 0..3000u32 {
+        let [_, a, b, c] = i.to_be_bytes();
+        let mac = format!("02:00:01:{a:02x}:{b:02x}:{c:02x}");
+        wake(&app, format!(r#"{{"mac": "{mac}"}}"#)).await;
+    }
+    for i in 0..500 {
+        round.store(i, Ordering::SeqCst);
+        wake(&app, format!(r#"{{"host": "h{:05}-pc"}}"#, i * 5)).await;
+    }
+
+    let stats = memory_stats(&app).await;
+    let limited = |entries| Size {
+        entries,
+        limit: Some(entries),
+    };
+    assert_eq!(stats.last_wakes, limited(100));
+    assert_eq!(stats.unverified, limited(100));
+    assert_eq!(stats.known_ips, limited(200));
+    assert_eq!(stats.last_seen, limited(200));
+    assert_eq!(stats.in_flight.entries, 0);
+
+    // the newest are the ones that are kept
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let hosts: Vec<Host> = serde_json::from_slice(&body).unwrap();
+    let host = hosts.iter().find(|host| host.name == "h02495-pc").unwrap();
+    assert!(host.last_wake.is_some());
+}
+
+#[tokio::test]
+async fn relay_counts_few_sources() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let relay = Relay::bind(RelayConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        destinations: vec![receiver.local_addr().unwrap()],
+        rate_limit: 10,
+    })
+    .await
+    .unwrap();
+    let relay_addr = relay.local_addr().unwrap();
+    let state = Arc::new(
+        AppState::new(Config {
+            limits: limits(),
+            ..Config::default()
+        })
+        .unwrap(),
+    );
+    tokio::spawn(relay.run(state.clone()));
+    let app = test_app(state);
+
+    let received = tokio::task::spawn_blocking(move || {
+        for source in 2..6u8 {
+            let client = UdpSocket::bind((format!("127.0.0.{source}"), 0)).unwrap();
+            let packet = MagicPacket::new(&[0x02, 0, 0, 0, 0x99, source]);
+            client.send_to(packet.magic_bytes(), relay_addr).unwrap();
+        }
+        let mut buf = [0; 200];
+        let mut received = 0;
+        while receiver.recv(&mut buf).is_ok() {
+            received += 1;
+        }
+        received
+    });
+    assert_eq!(received.await.unwrap(), 2);
+    let stats = memory_stats(&app).await;
+    assert_eq!(
+        stats.relay_sources,
+        Size {
+            entries: 2,
+            limit: Some(2)
+        }
+    );
+}
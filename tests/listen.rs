@@ -0,0 +1,83 @@
+#![cfg(feature = "server")]
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::Arc,
+};
+use wakeonlan::{
+    config::Config,
+    discovery::StaticDiscovery,
+    server::{self, bind_http, AppState},
+};
+
+/// The status line of a `GET` over plain TCP.
+fn get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: wol\r\nConnection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.lines().next().unwrap().to_owned()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ipv6_wildcard_takes_ipv4_clients() {
+    let listeners = bind_http(&["[::]:0".parse().unwrap()]).unwrap();
+    assert_eq!(listeners.len(), 1);
+    let listener = listeners.into_iter().next().unwrap();
+    assert!(listener.dual_stack);
+    let port = listener.addr.port();
+
+    // the IPv4 client comes in as ::ffff:127.0.0.1, which has to match like 127.0.0.1
+    let state = AppState::new(Config {
+        read_allow_from: vec!["127.0.0.1/32".parse().unwrap()],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(Vec::new()));
+    let app = server::router(Arc::new(state));
+    tokio::spawn(async move {
+        axum::serve(
+            listener.listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .unwrap();
+    });
+
+    let status = tokio::task::spawn_blocking(move || {
+        (
+            get(SocketAddr::from(([127, 0, 0, 1], port)), "/hosts"),
+            get(SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], port)), "/hosts"),
+        )
+    })
+    .await
+    .unwrap();
+    assert_eq!(status.0, "HTTP/1.1 200 OK");
+    assert_eq!(status.1, "HTTP/1.1 403 Forbidden");
+}
+
+#[tokio::test]
+async fn both_wildcards() {
+    let port = {
+        let listeners = bind_http(&["0.0.0.0:0".parse().unwrap()]).unwrap();
+        listeners[0].addr.port()
+    };
+    let addrs = [
+        SocketAddr::from(([0, 0, 0, 0], port)),
+        SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 0], port)),
+    ];
+    let listeners = bind_http(&addrs).unwrap();
+    let bound = listeners
+        .iter()
+        .map(|listener| (listener.addr, listener.dual_stack))
+        .collect::<Vec<_>>();
+    assert_eq!(bound, [(addrs[0], false), (addrs[1], false)]);
+
+    let taken = bind_http(&addrs[..1]).err().unwrap();
+    assert_eq!(taken.0, addrs[0]);
+}
@@ -0,0 +1,136 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{post, send, test_app, Recorder};
+use wakeonlan::{
+    api::v1::{BatchWakeResponse, Host},
+    config::{Config, StaticHost},
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    MacAddress,
+};
+
+fn located(name: &str, mac: &str, location: Option<&str>) -> StaticHost {
+    StaticHost {
+        location: location.map(str::to_owned),
+        ..StaticHost::new(name, [mac]).unwrap()
+    }
+}
+
+/// `pc` and `nas` in the office, `tv` in the living room, `printer` nowhere and `phone` only
+/// discovered.
+fn state(sender: Recorder) -> AppState {
+    let phone = HostEntry {
+        name: "phone".to_owned(),
+        ip: Some("192.168.1.30".parse().unwrap()),
+        mac: MacAddress([0x02, 0, 0, 0, 0, 0x05]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    };
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![
+            located("pc", "02:00:00:00:00:01", Some("Office")),
+            located("tv", "02:00:00:00:00:02", Some("Living room")),
+            located("nas", "02:00:00:00:00:03", Some("office")),
+            located("printer", "02:00:00:00:00:04", None),
+        ],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(sender)
+    .with_discovery(StaticDiscovery(vec![phone]))
+}
+
+async fn hosts(app: &Router, uri: &str) -> Vec<Host> {
+    let (status, body) = send(app, Request::get(uri).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn names(hosts: &[Host]) -> Vec<&str> {
+    hosts.iter().map(|host| host.name.as_str()).collect()
+}
+
+#[tokio::test]
+async fn hosts_filtered_by_location() {
+    let app = test_app(state(Recorder::default()));
+
+    let all = hosts(&app, "/api/v1/hosts").await;
+    let pc = all.iter().find(|host| host.name == "pc").unwrap();
+    assert_eq!(pc.location.as_deref(), Some("Office"));
+    let phone = all.iter().find(|host| host.name == "phone").unwrap();
+    assert_eq!(phone.location, None);
+
+    let office = hosts(&app, "/api/v1/hosts?location=OFFICE").await;
+    assert_eq!(names(&office), ["pc", "nas"]);
+    let unsorted = hosts(&app, "/api/v1/hosts?location=").await;
+    assert_eq!(names(&unsorted), ["printer", "phone"]);
+    assert!(hosts(&app, "/api/v1/hosts?location=attic").await.is_empty());
+}
+
+#[tokio::test]
+async fn wake_all_in_a_location() {
+    let sent = Recorder::default();
+    let app = test_app(state(sent.clone()));
+
+    let (status, response) = post(&app, "/api/v1/wake/batch", r#"{"location": "office"}"#).await;
+    assert!(status.is_success(), "{status}");
+    let response: BatchWakeResponse = serde_json::from_slice(&response).unwrap();
+    assert!(response.all_sent);
+    let mut woken = response
+        .results
+        .iter()
+        .map(|result| result.host.as_str())
+        .collect::<Vec<_>>();
+    woken.sort_unstable();
+    assert_eq!(woken, ["nas", "pc"]);
+    assert_eq!(sent.0.lock().unwrap().len(), 2);
+
+    // the button of the unsorted ones on the page
+    sent.0.lock().unwrap().clear();
+    let request = Request::post("/wake/batch")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("location="))
+        .unwrap();
+    let (status, page) = send(&app, request).await;
+    assert!(status.is_success(), "{status}");
+    let page = String::from_utf8(page).unwrap();
+    assert!(page.contains("<b>printer</b>"), "{page}");
+    assert!(page.contains("<b>phone</b>"), "{page}");
+    let mut sent = sent.0.lock().unwrap().clone();
+    sent.sort_unstable_by_key(|mac| mac.0);
+    assert_eq!(
+        sent,
+        [
+            MacAddress([0x02, 0, 0, 0, 0, 0x04]),
+            MacAddress([0x02, 0, 0, 0, 0, 0x05]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn page_grouped_by_location() {
+    let app = test_app(state(Recorder::default()));
+
+    let (status, page) = send(&app, Request::get("/").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    let page = String::from_utf8(page).unwrap();
+    let headings = page
+        .match_indices("<h2>")
+        .map(|(start, _)| {
+            let heading = &page[start + 4..];
+            &heading[..heading.find("</h2>").unwrap()]
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(headings, ["Office", "Living room", "Unsorted"]);
+    assert!(page.contains(r#"name="location" value="Living room""#));
+    assert!(page.contains(r#"name="location" value="""#));
+}
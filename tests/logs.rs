@@ -0,0 +1,78 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use std::sync::Arc;
+use tower::ServiceExt;
+use tracing_subscriber::layer::SubscriberExt;
+use wakeonlan::{
+    config::Config,
+    server::{self, AppState, LogBuffer},
+};
+
+#[tokio::test]
+async fn recent_logs() {
+    let logs = Arc::new(LogBuffer::default());
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(logs.layer()));
+    let state = AppState::new(Config {
+        token: Some("hunter2".into()),
+        log_buffer: 10,
+        ..Config::default()
+    })
+    .unwrap()
+    .with_logs(logs);
+    let app = server::router(Arc::new(state));
+
+    tracing::debug!("too verbose to be kept");
+    tracing::info!(user = "nora", token = "hunter2", "something happened");
+    let response = app
+        .clone()
+        .oneshot(Request::post("/wake").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let get = |query: &str| {
+        let request = Request::get(format!("/debug/logs{query}"))
+            .header(header::AUTHORIZATION, "Bearer hunter2")
+            .body(Body::empty())
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            serde_json::from_slice::<Vec<Value>>(&body).unwrap()
+        }
+    };
+
+    let all = get("").await;
+    let messages = all
+        .iter()
+        .map(|event| event["message"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        ["something happened", "rejected request without valid token"]
+    );
+    assert_eq!(all[0]["level"], "info");
+    assert_eq!(all[0]["fields"]["user"], "nora");
+    assert!(all[0]["fields"].get("token").is_none());
+    assert!(!serde_json::to_string(&all).unwrap().contains("hunter2"));
+
+    let warnings = get("?level=warn").await;
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0]["level"], "warn");
+    assert_eq!(get("?limit=1").await, warnings);
+
+    let response = app
+        .oneshot(Request::get("/debug/logs").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
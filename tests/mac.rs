@@ -0,0 +1,132 @@
+#![cfg(feature = "server")]
+
+use wakeonlan::{
+    discovery::parse_mac_addr, MacAddress, MacParseError, MagicPacket, SecureOnParseError,
+    SecureOnPassword,
+};
+
+const MAC: MacAddress = MacAddress([0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]);
+
+#[test]
+fn separators() {
+    for s in [
+        "00:d8:61:ca:3a:18",
+        "00-D8-61-CA-3A-18",
+        "00d8.61ca.3a18",
+        "0:d8:61:ca:3a:18",
+    ] {
+        assert_eq!(s.parse::<MacAddress>(), Ok(MAC), "{s}");
+        assert_eq!(parse_mac_addr(s), Some(MAC), "{s}");
+    }
+}
+
+#[test]
+fn errors() {
+    let parse = |s: &str| s.parse::<MacAddress>().unwrap_err();
+    assert_eq!(parse(""), MacParseError::InvalidGroup { position: 0 });
+    assert_eq!(
+        parse("nas"),
+        MacParseError::InvalidDigit {
+            position: 0,
+            found: 'n'
+        }
+    );
+    assert_eq!(
+        parse("00:d8-61:ca:3a:18"),
+        MacParseError::InvalidDigit {
+            position: 5,
+            found: '-'
+        }
+    );
+    assert_eq!(
+        parse("00:d8::ca:3a:18"),
+        MacParseError::InvalidGroup { position: 6 }
+    );
+    assert_eq!(
+        parse("00:d8:611:ca:3a"),
+        MacParseError::InvalidGroup { position: 6 }
+    );
+    assert_eq!(
+        parse("00d8.61ca.3a"),
+        MacParseError::InvalidGroup { position: 10 }
+    );
+    assert_eq!(
+        parse("00:d8:61:ca:3a:18:00"),
+        MacParseError::InvalidLength { bytes: 7 }
+    );
+    assert_eq!(
+        parse("00:d8:61:ca:3a:18:00").to_string(),
+        "expected 6 bytes, got 7"
+    );
+    assert_eq!(parse_mac_addr("00:d8:61:ca:3a"), None);
+}
+
+#[test]
+fn magic_packet() {
+    let packet = MagicPacket::try_from("00:d8:61:ca:3a:18").unwrap();
+    assert_eq!(packet.magic_bytes(), MagicPacket::new(&MAC.0).magic_bytes());
+    assert!(MagicPacket::from_mac_str("00:d8:61:ca:3a:1g").is_err());
+}
+
+#[test]
+fn secure_on_passwords() {
+    let plain = MagicPacket::new(&MAC.0);
+    let short = MagicPacket::new(&MAC.0).with_password("a1b2c3d4".parse().unwrap());
+    let long = MagicPacket::new(&MAC.0).with_password("a1-b2-c3-d4-e5-f6".parse().unwrap());
+    assert_eq!(plain.payload().len(), 102);
+    assert_eq!(short.payload().len(), 106);
+    assert_eq!(long.payload().len(), 108);
+    assert_eq!(short.payload()[..102], plain.payload()[..]);
+    assert_eq!(long.payload()[102..], [0xa1, 0xb2, 0xc3, 0xd4, 0xe5, 0xf6]);
+    assert_eq!(long.magic_bytes(), plain.magic_bytes());
+
+    for (packet, password_len) in [(&plain, None), (&short, Some(4)), (&long, Some(6))] {
+        let parsed = MagicPacket::parse(packet.payload()).unwrap();
+        assert_eq!(parsed.mac(), MAC);
+        assert_eq!(parsed.password_len(), password_len);
+        assert_eq!(parsed.payload(), packet.payload());
+    }
+    // trailing bytes that are no password
+    let mut bytes = long.payload().to_vec();
+    bytes.push(0);
+    for len in [96, 101, 103, 104, 105, 107, 109] {
+        assert!(MagicPacket::parse(&bytes[..len]).is_none(), "{len}");
+    }
+    let mut broken = short.payload().to_vec();
+    broken[101] ^= 1;
+    assert!(MagicPacket::parse(&broken).is_none());
+
+    let shown = format!("{long:?}");
+    assert_eq!(
+        shown,
+        "MagicPacket { mac: 00:d8:61:ca:3a:18, password: Some(SecureOnPassword(6 bytes)) }"
+    );
+    assert!(!shown.to_lowercase().contains("a1"));
+}
+
+#[test]
+fn secure_on_password_errors() {
+    let parse = |s: &str| s.parse::<SecureOnPassword>().unwrap_err();
+    assert_eq!(
+        parse("a1b2c3d4e5").to_string(),
+        "expected 4 or 6 bytes, got 5"
+    );
+    assert_eq!(parse("a1b2c3d"), SecureOnParseError::OddDigits);
+    assert_eq!(
+        parse("a1:b2:c3:zz"),
+        SecureOnParseError::InvalidDigit {
+            position: 9,
+            found: 'z'
+        }
+    );
+    assert_eq!(
+        parse("a:1b2c3d4"),
+        SecureOnParseError::InvalidDigit {
+            position: 1,
+            found: ':'
+        }
+    );
+    let password: SecureOnPassword = "A1:B2:C3:D4".parse().unwrap();
+    assert_eq!(password.to_hex(), "a1b2c3d4");
+    assert_eq!(password.as_bytes(), [0xa1, 0xb2, 0xc3, 0xd4]);
+}
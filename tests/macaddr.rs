@@ -0,0 +1,21 @@
+#![cfg(feature = "macaddr")]
+
+use macaddr::MacAddr6;
+use wakeonlan::{MacAddress, MagicPacket};
+
+#[test]
+fn round_trip() {
+    let mac = MacAddr6::new(0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18);
+    let converted = MacAddress::from(mac);
+    assert_eq!(converted, MacAddress([0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]));
+    assert_eq!(MacAddr6::from(converted), mac);
+}
+
+#[test]
+fn magic_packet() {
+    let mac = MacAddr6::new(0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18);
+    assert_eq!(
+        MagicPacket::from(mac).magic_bytes(),
+        MagicPacket::new(mac.as_bytes().try_into().unwrap()).magic_bytes()
+    );
+}
@@ -0,0 +1,197 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{post, test_app, NoSend};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use wakeonlan::{
+    api::v1::{Host, LastWake, WakeOutcome},
+    config::{Config, MqttConfig, StaticHost},
+    discovery::StaticDiscovery,
+    server::{AppState, Mqtt},
+};
+
+fn mqtt_config(broker: SocketAddr) -> MqttConfig {
+    MqttConfig {
+        broker: broker.to_string(),
+        client_id: "wol-test".to_owned(),
+        username: Some("dashboard".to_owned()),
+        password: Some("secret".into()),
+        prefix: "home/wol".to_owned(),
+        interval: 3600,
+    }
+}
+
+/// Knows `nas` and `pc`, and starts publishing to a broker at `broker`.
+fn test_state(broker: SocketAddr) -> Arc<AppState> {
+    let state = Arc::new(
+        AppState::new(Config {
+            broadcast: "127.0.0.1:9".parse().unwrap(),
+            hosts: vec![
+                StaticHost::new("nas", ["02:00:00:00:00:01"]).unwrap(),
+                StaticHost::new("pc", ["02:00:00:00:00:02"]).unwrap(),
+            ],
+            mqtt: Some(mqtt_config(broker)),
+            ..Config::default()
+        })
+        .unwrap()
+        .with_sender(NoSend)
+        .with_discovery(StaticDiscovery(Vec::new())),
+    );
+    tokio::spawn(Mqtt::new(mqtt_config(broker)).run(state.clone()));
+    state
+}
+
+/// The kind of the packet with its flags, and what follows its fixed header.
+async fn read_packet(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let read = async {
+        let kind = stream.read_u8().await.unwrap();
+        let (mut len, mut shift) = (0, 0);
+        loop {
+            let byte = stream.read_u8().await.unwrap();
+            len |= usize::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        stream.read_exact(&mut body).await.unwrap();
+        (kind, body)
+    };
+    tokio::time::timeout(Duration::from_secs(5), read)
+        .await
+        .expect("nothing from the server in time")
+}
+
+/// Splits off a string with its length in front.
+fn string(body: &[u8]) -> (String, &[u8]) {
+    let len = usize::from(u16::from_be_bytes([body[0], body[1]]));
+    let string = String::from_utf8(body[2..2 + len].to_vec()).unwrap();
+    (string, &body[2 + len..])
+}
+
+/// Accepts the server's connection, checking how it logs in.
+async fn accept(broker: &TcpListener) -> TcpStream {
+    let (mut stream, _) = tokio::time::timeout(Duration::from_secs(10), broker.accept())
+        .await
+        .expect("the server didn't connect")
+        .unwrap();
+    let (kind, body) = read_packet(&mut stream).await;
+    assert_eq!(kind, 0x10);
+    let (protocol, rest) = string(&body);
+    assert_eq!(protocol, "MQTT");
+    // 3.1.1, then username, password, a retained will and a clean session
+    assert_eq!(rest[..2], [4, 0xe6]);
+    let mut fields = Vec::new();
+    let mut rest = &rest[4..];
+    while !rest.is_empty() {
+        let (field, after) = string(rest);
+        fields.push(field);
+        rest = after;
+    }
+    assert_eq!(
+        fields,
+        [
+            "wol-test",
+            "home/wol/status",
+            "offline",
+            "dashboard",
+            "secret"
+        ]
+    );
+    stream.write_all(&[0x20, 2, 0, 0]).await.unwrap();
+    stream
+}
+
+/// Reads retained publishes until `done` has what it waits for, ignoring pings.
+async fn read_until(
+    stream: &mut TcpStream,
+    mut done: impl FnMut(&[(String, String)]) -> bool,
+) -> Vec<(String, String)> {
+    let mut published = Vec::new();
+    while !done(&published) {
+        let (kind, body) = read_packet(stream).await;
+        if kind == 0xc0 {
+            continue;
+        }
+        assert_eq!(kind, 0x31, "not a retained publish with QoS 0");
+        let (topic, payload) = string(&body);
+        published.push((topic, String::from_utf8(payload.to_vec()).unwrap()));
+    }
+    published
+}
+
+fn payload<'a>(published: &'a [(String, String)], topic: &str) -> Option<&'a str> {
+    (published.iter().rev())
+        .find(|(published, _)| published == topic)
+        .map(|(_, payload)| payload.as_str())
+}
+
+#[tokio::test]
+async fn retains_the_hosts_and_their_wakes() {
+    let broker = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let state = test_state(broker.local_addr().unwrap());
+    let app = test_app(state);
+
+    let mut stream = accept(&broker).await;
+    let published = read_until(&mut stream, |published| published.len() == 3).await;
+    assert_eq!(published[0], ("home/wol/status".into(), "online".into()));
+    let nas: Host =
+        serde_json::from_str(payload(&published, "home/wol/hosts/nas").unwrap()).unwrap();
+    assert_eq!(nas.macs, ["02:00:00:00:00:01"]);
+    assert!(nas.last_wake.is_none());
+    assert!(payload(&published, "home/wol/hosts/pc").is_some());
+
+    assert_eq!(
+        post(&app, "/api/v1/wake", r#"{"host": "nas"}"#).await.0,
+        StatusCode::ACCEPTED
+    );
+    let published = read_until(&mut stream, |published| {
+        payload(published, "home/wol/last_wake/nas").is_some()
+    })
+    .await;
+    let wake: LastWake =
+        serde_json::from_str(payload(&published, "home/wol/last_wake/nas").unwrap()).unwrap();
+    assert_eq!(wake.outcome, WakeOutcome::Sent);
+    // pc didn't change, so it isn't published again
+    assert!(payload(&published, "home/wol/hosts/pc").is_none());
+
+    // nas is gone, so what's retained for it is cleared
+    let (status, _) = post(
+        &app,
+        "/api/v1/hosts/import?mode=replace",
+        r#"{"hosts": [{"name": "pc", "mac": "02:00:00:00:00:02"}]}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let published = read_until(&mut stream, |published| {
+        payload(published, "home/wol/hosts/nas").is_some()
+            && payload(published, "home/wol/last_wake/nas").is_some()
+    })
+    .await;
+    assert_eq!(payload(&published, "home/wol/hosts/nas"), Some(""));
+    assert_eq!(payload(&published, "home/wol/last_wake/nas"), Some(""));
+}
+
+#[tokio::test]
+async fn publishes_everything_again_after_reconnecting() {
+    let broker = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let _state = test_state(broker.local_addr().unwrap());
+
+    let mut stream = accept(&broker).await;
+    read_until(&mut stream, |published| published.len() == 3).await;
+    drop(stream);
+
+    // like a broker that restarted and lost what it retained
+    let mut stream = accept(&broker).await;
+    let published = read_until(&mut stream, |published| published.len() == 3).await;
+    assert_eq!(payload(&published, "home/wol/status"), Some("online"));
+    assert!(payload(&published, "home/wol/hosts/nas").is_some());
+    assert!(payload(&published, "home/wol/hosts/pc").is_some());
+}
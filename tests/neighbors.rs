@@ -0,0 +1,137 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use std::sync::Arc;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{Neighbors, SkipReason},
+    config::Config,
+    discovery::{
+        ip_neigh_neighbors, parse_ip_neigh, Composite, HostDiscovery, HostEntry, NeighborState,
+        NeighborTable, StaticDiscovery,
+    },
+    server::{self, AppState},
+    MacAddress,
+};
+
+const TABLE: &str = "192.168.1.1 dev eth0 lladdr 00:11:22:33:44:55 REACHABLE
+192.168.1.40 dev eth0 INCOMPLETE
+192.168.1.42 dev eth0 lladdr 00:00:00:00:00:00 STALE
+192.168.1.43 dev eth1 lladdr 00:1g:22:33:44:55 STALE
+";
+
+/// Reads `TABLE` like `ip neigh` would have printed it.
+struct IpNeighOutput;
+
+impl HostDiscovery for IpNeighOutput {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        Ok(parse_ip_neigh(TABLE))
+    }
+
+    fn neighbors(&self) -> Vec<NeighborTable> {
+        vec![NeighborTable {
+            source: "ip-neigh",
+            neighbors: Ok(ip_neigh_neighbors(TABLE)),
+        }]
+    }
+}
+
+struct Failing;
+
+impl HostDiscovery for Failing {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        eyre::bail!("no neighbors here")
+    }
+}
+
+#[tokio::test]
+async fn raw_entries_of_every_backend() {
+    let nas = HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("192.168.1.20".parse().unwrap()),
+        mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    };
+    let state = AppState::new(Config {
+        token: Some("hunter2".into()),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(Composite(vec![
+        Box::new(IpNeighOutput),
+        Box::new(StaticDiscovery(vec![nas])),
+        Box::new(Failing),
+    ]));
+    let app = server::router(Arc::new(state));
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/api/v1/neighbors")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = Request::get("/api/v1/neighbors")
+        .header(header::AUTHORIZATION, "Bearer hunter2")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let Neighbors { neighbors, errors } = serde_json::from_slice(&body).unwrap();
+
+    let rows = neighbors
+        .iter()
+        .map(|neighbor| {
+            (
+                neighbor.source.as_str(),
+                neighbor.ip.map(|ip| ip.to_string()),
+                neighbor.skipped,
+            )
+        })
+        .collect::<Vec<_>>();
+    let ip = |ip: &str| Some(ip.to_owned());
+    assert_eq!(
+        rows,
+        [
+            ("ip-neigh", ip("192.168.1.1"), None),
+            ("ip-neigh", ip("192.168.1.40"), Some(SkipReason::Incomplete)),
+            (
+                "ip-neigh",
+                ip("192.168.1.42"),
+                Some(SkipReason::UnusableMac)
+            ),
+            ("ip-neigh", None, Some(SkipReason::Invalid)),
+            ("static", ip("192.168.1.20"), None),
+        ]
+    );
+    assert_eq!(neighbors[0].mac.as_deref(), Some("00:11:22:33:44:55"));
+    assert_eq!(neighbors[0].interface.as_deref(), Some("eth0"));
+    assert_eq!(neighbors[0].state, Some(NeighborState::Reachable));
+    assert_eq!(neighbors[1].state, Some(NeighborState::Incomplete));
+    assert_eq!(
+        neighbors[3].error.as_deref(),
+        Some(
+            "unrecognized neighbor table line 4: invalid mac address in field 5: invalid hex \
+             digit `g` at position 4 in \"192.168.1.43 dev eth1 lladdr 00:1g:22:33:44:55 STALE\""
+        )
+    );
+    assert_eq!(neighbors[4].name.as_deref(), Some("nas"));
+    assert!(neighbors.iter().all(|neighbor| neighbor.site.is_none()));
+
+    let [error] = &errors[..] else {
+        panic!("expected the failing backend, found {errors:?}");
+    };
+    assert_eq!(error.source, "other");
+    assert_eq!(error.error, "no neighbors here");
+}
@@ -0,0 +1,76 @@
+#![cfg(feature = "server")]
+
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+use wakeonlan::netbios::{node_status, parse_node_status};
+
+/// A node status response to the query with `id`, listing the names with their suffix and flags.
+fn response(id: u16, names: &[(&str, u8, u8)]) -> Vec<u8> {
+    let mut response = id.to_be_bytes().to_vec();
+    response.extend([0x84, 0x00, 0, 0, 0, 1, 0, 0, 0, 0]);
+    response.push(32);
+    response.extend(b"CKAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA");
+    response.push(0);
+    response.extend([0, 0x21, 0, 1, 0, 0, 0, 0]);
+    let len = 1 + names.len() * 18 + 46;
+    response.extend((len as u16).to_be_bytes());
+    response.push(names.len() as u8);
+    for (name, suffix, flags) in names {
+        response.extend(format!("{name:<15}").as_bytes());
+        response.extend([*suffix, *flags, 0]);
+    }
+    // the statistics, including the MAC
+    response.extend([0; 46]);
+    response
+}
+
+#[test]
+fn parse() {
+    let names = [
+        ("WORKGROUP", 0x00, 0x84),
+        ("DESKTOP-4F2K", 0x20, 0x04),
+        ("DESKTOP-4F2K", 0x00, 0x04),
+    ];
+    assert_eq!(
+        parse_node_status(&response(7, &names), 7),
+        Some(Some("DESKTOP-4F2K".to_owned()))
+    );
+    // just a group name
+    assert_eq!(parse_node_status(&response(7, &names[..1]), 7), Some(None));
+    // an answer to something else, or cut off
+    assert_eq!(parse_node_status(&response(8, &names), 7), None);
+    let response = response(7, &names);
+    assert_eq!(parse_node_status(&response[..60], 7), None);
+}
+
+#[test]
+fn query() {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    let answering = std::thread::spawn(move || {
+        let mut query = [0; 100];
+        let (len, from) = server.recv_from(&mut query).unwrap();
+        assert_eq!(len, 50);
+        // NBSTAT for `*`
+        assert_eq!(&query[13..15], b"CK");
+        assert_eq!(&query[46..50], [0, 0x21, 0, 1]);
+        let id = u16::from_be_bytes([query[0], query[1]]);
+        // something that isn't the answer first, which is ignored
+        server.send_to(&response(id ^ 1, &[]), from).unwrap();
+        server
+            .send_to(&response(id, &[("NAS", 0x00, 0x04)]), from)
+            .unwrap();
+    });
+    let name = node_status(addr, Duration::from_secs(5)).unwrap();
+    assert_eq!(name.as_deref(), Some("NAS"));
+    answering.join().unwrap();
+
+    // nothing listening, or not answering
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let start = Instant::now();
+    let addr: SocketAddr = silent.local_addr().unwrap();
+    assert_eq!(node_status(addr, Duration::from_millis(200)).unwrap(), None);
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
@@ -0,0 +1,225 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{Network, SendStats},
+    config::{Config, StaticHost},
+    retry::RetryPolicy,
+    server::{self, AppState, PacketSender},
+    MagicPacket,
+};
+
+struct Unreachable;
+
+impl PacketSender for Unreachable {
+    fn send(&self, _: &MagicPacket, _: SocketAddr, _: Option<&str>) -> io::Result<SocketAddr> {
+        Err(io::Error::from_raw_os_error(libc::ENETUNREACH))
+    }
+}
+
+async fn wake_and_get_stats(state: AppState) -> Vec<SendStats> {
+    let app = server::router(Arc::new(state));
+    for _ in 0..2 {
+        let request = Request::post("/api/v1/wake")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"host": "nas"}"#))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap();
+    }
+    let request = Request::get("/api/v1/send-stats")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap()
+}
+
+fn nas_config() -> Config {
+    Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        retry: RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        },
+        ..Config::default()
+    }
+}
+
+#[tokio::test]
+async fn send_stats() {
+    let stats = wake_and_get_stats(AppState::new(nas_config()).unwrap()).await;
+
+    let [stats] = &stats[..] else {
+        panic!("expected one destination: {stats:?}");
+    };
+    assert_eq!(stats.destination, "127.0.0.1:9".parse().unwrap());
+    assert_eq!((stats.datagrams, stats.bytes, stats.failures), (2, 204, 0));
+    assert!(stats.last_sent.is_some());
+    // the socket isn't connected, so just the port it's bound to
+    assert_ne!(stats.last_source.unwrap().port(), 0);
+    assert_eq!(stats.last_error, None);
+}
+
+#[tokio::test]
+async fn send_stats_of_failures() {
+    let state = AppState::new(nas_config())
+        .unwrap()
+        .with_sender(Unreachable);
+    let stats = wake_and_get_stats(state).await;
+
+    let [stats] = &stats[..] else {
+        panic!("expected one destination: {stats:?}");
+    };
+    assert_eq!((stats.datagrams, stats.bytes, stats.failures), (0, 0, 2));
+    assert_eq!(stats.last_sent, None);
+    assert!(stats.last_error.as_deref().unwrap().contains("unreachable"));
+    assert!(stats.last_error_at.is_some());
+}
+
+#[tokio::test]
+async fn interfaces_and_routes() {
+    let mut looped = StaticHost::new("looped", ["00:d8:61:ca:3a:18"]).unwrap();
+    looped.interface = Some("lo".to_owned());
+    let mut gone = StaticHost::new("gone", ["a8:a1:59:0e:7b:02"]).unwrap();
+    gone.interface = Some("nope0".to_owned());
+    let state = AppState::new(Config {
+        token: Some("hunter2".into()),
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![looped, gone],
+        ..Config::default()
+    })
+    .unwrap();
+    let app = server::router(Arc::new(state));
+
+    let response = app
+        .clone()
+        .oneshot(Request::get("/api/v1/network").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = Request::get("/api/v1/network")
+        .header(header::AUTHORIZATION, "Bearer hunter2")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let network: Network = serde_json::from_slice(&body).unwrap();
+
+    let lo = network
+        .interfaces
+        .iter()
+        .find(|interface| interface.name == "lo")
+        .unwrap();
+    assert!(lo.up && lo.used);
+    let localhost: IpAddr = "127.0.0.1".parse().unwrap();
+    let address = lo
+        .addresses
+        .iter()
+        .find(|address| address.address == localhost)
+        .unwrap();
+    assert_eq!(address.prefix, 8);
+    assert_eq!(address.netmask, "255.0.0.0".parse::<IpAddr>().unwrap());
+    assert_eq!(address.broadcast, Some("127.255.255.255".parse().unwrap()));
+
+    let routes = network
+        .routes
+        .iter()
+        .map(|route| {
+            (
+                route.host.as_deref(),
+                route.interface.as_deref(),
+                route.leaves_on.as_deref(),
+                route.source,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        routes,
+        [
+            (None, None, Some("lo"), Some(localhost)),
+            (Some("looped"), Some("lo"), Some("lo"), Some(localhost)),
+            (Some("gone"), Some("nope0"), None, None),
+        ]
+    );
+    assert_eq!(
+        network.routes[2].error.as_deref(),
+        Some("no network interface named `nope0`")
+    );
+}
+
+#[tokio::test]
+async fn sent_from_the_source_address() {
+    let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+    listener
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut nas = StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap();
+    nas.source = Some("127.0.0.1".parse().unwrap());
+    let state = AppState::new(Config {
+        broadcast: listener.local_addr().unwrap(),
+        hosts: vec![nas],
+        ..nas_config()
+    })
+    .unwrap();
+    let stats = wake_and_get_stats(state).await;
+
+    let mut buf = [0; 256];
+    let (_, from) = listener.recv_from(&mut buf).unwrap();
+    assert_eq!(from.ip(), "127.0.0.1".parse::<IpAddr>().unwrap());
+    // bound to it, unlike the socket for everything else
+    assert_eq!(stats[0].last_source.unwrap().ip(), from.ip());
+}
+
+#[tokio::test]
+async fn source_address_of_no_interface() {
+    let mut nas = StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap();
+    nas.source = Some("192.0.2.1".parse().unwrap());
+    let state = AppState::new(Config {
+        hosts: vec![nas],
+        ..nas_config()
+    })
+    .unwrap();
+    let app = server::router(Arc::new(state));
+
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"host": "nas"}"#))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8_lossy(&body)
+        .contains("no network interface has the source address 192.0.2.1"));
+
+    let response = app
+        .oneshot(Request::get("/api/v1/network").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let network: Network = serde_json::from_slice(&body).unwrap();
+    let route = network
+        .routes
+        .iter()
+        .find(|route| route.host.as_deref() == Some("nas"))
+        .unwrap();
+    assert_eq!(route.bound_to, Some("192.0.2.1".parse().unwrap()));
+    assert_eq!(
+        route.error.as_deref(),
+        Some("no network interface has the source address 192.0.2.1")
+    );
+}
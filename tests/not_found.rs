@@ -0,0 +1,132 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{ErrorResponse, MemoryStats},
+    config::Config,
+    discovery::{HostDiscovery, HostEntry},
+    server::AppState,
+    MacAddress,
+};
+
+/// Counts how often it's asked, and finds whatever is in it right then.
+#[derive(Clone, Default)]
+struct Counting {
+    discovered: Arc<AtomicUsize>,
+    table: Arc<Mutex<Vec<HostEntry>>>,
+}
+
+impl HostDiscovery for Counting {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        self.discovered.fetch_add(1, Ordering::SeqCst);
+        Ok(self.table.lock().unwrap().clone())
+    }
+}
+
+fn entry(name: &str, last: u8) -> HostEntry {
+    HostEntry {
+        name: name.to_owned(),
+        ip: Some(format!("192.168.1.{last}").parse().unwrap()),
+        mac: MacAddress([0x02, 0, 0, 0, 0, last]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }
+}
+
+fn state(counting: &Counting, ttl: Duration) -> AppState {
+    *counting.table.lock().unwrap() = vec![entry("nas-pc", 10)];
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        not_found_ttl: ttl,
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(counting.clone())
+}
+
+async fn wake(app: &Router, body: &str) -> (StatusCode, String) {
+    let mut request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    request
+        .extensions_mut()
+        .insert(ConnectInfo("10.0.0.7:4000".parse::<SocketAddr>().unwrap()));
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let error =
+        serde_json::from_slice::<ErrorResponse>(&body).map_or_else(|_| String::new(), |e| e.error);
+    (status, error)
+}
+
+#[tokio::test]
+async fn unknown_names_are_not_looked_for_again() {
+    let counting = Counting::default();
+    let app = test_app(state(&counting, Duration::from_secs(60)));
+
+    for _ in 0..3 {
+        let (status, error) = wake(&app, r#"{"host": "gone", "dry_run": true}"#).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error, "host `gone` not found");
+    }
+    assert_eq!(counting.discovered.load(Ordering::SeqCst), 1);
+
+    // a refresh looks again
+    let (status, _) = wake(
+        &app,
+        r#"{"host": "gone", "dry_run": true, "refresh": true}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let after_refresh = counting.discovered.load(Ordering::SeqCst);
+    assert!(after_refresh > 1);
+
+    let request = Request::get("/api/v1/stats").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let stats: MemoryStats = serde_json::from_slice(&body).unwrap();
+    assert_eq!(stats.not_found.hits, 2);
+    assert_eq!(stats.not_found.names.len(), 1);
+    let name = &stats.not_found.names[0];
+    assert_eq!(name.name, "gone");
+    assert_eq!(name.hits, 2);
+    assert_eq!(name.last_requester, Some("10.0.0.7".parse().unwrap()));
+
+    // once it's discovered, it's found right away
+    counting.table.lock().unwrap().push(entry("gone", 11));
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    app.clone().oneshot(request).await.unwrap();
+    let (status, _) = wake(&app, r#"{"host": "gone", "dry_run": true}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+}
+
+#[tokio::test]
+async fn zero_looks_every_time() {
+    let counting = Counting::default();
+    let app = test_app(state(&counting, Duration::ZERO));
+    for _ in 0..3 {
+        let (status, _) = wake(&app, r#"{"host": "gone", "dry_run": true}"#).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+    assert_eq!(counting.discovered.load(Ordering::SeqCst), 3);
+}
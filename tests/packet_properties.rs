@@ -0,0 +1,139 @@
+//! Properties of building and parsing magic packets, checked with proptest on many generated
+//! inputs. A failure is shrunk to a small input, which is in the message.
+
+use proptest::prelude::*;
+use wakeonlan::{parse_magic_packet, MacAddress, MagicPacket, SecureOnPassword};
+
+/// What a magic packet is, written down apart from the parser: the header, 16 times the same MAC,
+/// then nothing or a 4 or 6 byte password.
+fn is_packet(bytes: &[u8]) -> bool {
+    matches!(bytes.len(), 102 | 106 | 108)
+        && bytes[..6] == [0xff; 6]
+        && bytes[6..102].chunks(6).all(|chunk| chunk == &bytes[6..12])
+}
+
+fn check_parse(bytes: &[u8]) {
+    let parsed = MagicPacket::parse(bytes);
+    assert_eq!(parsed.is_some(), is_packet(bytes), "{bytes:02x?}");
+    if let Some(packet) = parsed {
+        assert_eq!(packet.payload(), bytes);
+        assert_eq!(packet.mac().0, bytes[6..12]);
+        assert_eq!(parse_magic_packet(bytes), Some(packet.mac()));
+    }
+}
+
+/// A way of breaking a packet, at a position that's taken modulo its length.
+#[derive(Debug, Clone)]
+enum Break {
+    Change { at: usize, to: u8 },
+    Truncate(usize),
+    Remove(usize),
+    Insert { at: usize, byte: u8 },
+}
+
+fn breaks() -> impl Strategy<Value = Break> {
+    prop_oneof![
+        (any::<usize>(), any::<u8>()).prop_map(|(at, to)| Break::Change { at, to }),
+        any::<usize>().prop_map(Break::Truncate),
+        any::<usize>().prop_map(Break::Remove),
+        (any::<usize>(), any::<u8>()).prop_map(|(at, byte)| Break::Insert { at, byte }),
+    ]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(10_000))]
+
+    #[test]
+    fn round_trips(mac: [u8; 6]) {
+        let packet = MagicPacket::new(&mac);
+        let parsed = MagicPacket::parse(packet.magic_bytes()).expect("not parsed");
+        prop_assert_eq!(parsed.mac(), MacAddress(mac));
+        prop_assert_eq!(parsed.password_len(), None);
+        prop_assert_eq!(parsed.payload(), packet.magic_bytes());
+    }
+
+    #[test]
+    fn arbitrary_bytes(bytes in prop::collection::vec(any::<u8>(), ..300)) {
+        check_parse(&bytes);
+    }
+
+    /// Random bytes are hardly ever close to a packet, these are packets with one thing wrong.
+    #[test]
+    fn broken_packets(mac: [u8; 6], broken in breaks()) {
+        let mut packet = MagicPacket::new(&mac).magic_bytes().to_vec();
+        let len = packet.len();
+        match broken {
+            Break::Change { at, to } => packet[at % len] = to,
+            Break::Truncate(len) => packet.truncate(len % packet.len()),
+            Break::Remove(at) => {
+                packet.remove(at % len);
+            }
+            Break::Insert { at, byte } => packet.insert(at % (len + 1), byte),
+        }
+        check_parse(&packet);
+    }
+
+    #[test]
+    fn passwords(mac: [u8; 6], password in prop::collection::vec(any::<u8>(), ..=12)) {
+        let mut bytes = MagicPacket::new(&mac).magic_bytes().to_vec();
+        bytes.extend(&password);
+
+        let parsed = MagicPacket::parse(&bytes);
+        match password.len() {
+            0 => prop_assert_eq!(parsed.unwrap().password_len(), None),
+            len @ (4 | 6) => {
+                let parsed = parsed.expect("not parsed");
+                prop_assert_eq!(parsed.password_len(), Some(len));
+                prop_assert_eq!(parsed.mac(), MacAddress(mac));
+                let password = SecureOnPassword::new(&password).unwrap();
+                let built = MagicPacket::new(&mac).with_password(password);
+                prop_assert_eq!(parsed.payload(), built.payload());
+            }
+            _ => prop_assert!(parsed.is_none(), "{:02x?}", bytes),
+        }
+    }
+}
+
+/// Inputs right at the edges, kept so they're always checked whatever proptest generates.
+#[test]
+fn edge_cases() {
+    let packet = |mac: [u8; 6]| MagicPacket::new(&mac).magic_bytes().to_vec();
+    let with = |mut bytes: Vec<u8>, extra: &[u8]| {
+        bytes.extend(extra);
+        bytes
+    };
+    let changed = |mut bytes: Vec<u8>, at: usize| {
+        bytes[at] ^= 1;
+        bytes
+    };
+    let zeros = packet([0; 6]);
+    let cases = [
+        Vec::new(),
+        vec![0xff; 6],
+        vec![0xff; 101],
+        // a MAC of all 0xff is all 0xff, with or without a password of them
+        vec![0xff; 102],
+        vec![0xff; 103],
+        vec![0xff; 106],
+        vec![0xff; 107],
+        vec![0xff; 108],
+        vec![0xff; 109],
+        zeros.clone(),
+        zeros[..101].to_vec(),
+        changed(zeros.clone(), 0),
+        changed(zeros.clone(), 5),
+        changed(zeros.clone(), 6),
+        // the last byte of the last repetition
+        changed(zeros.clone(), 101),
+        with(zeros.clone(), &[1; 4]),
+        with(zeros.clone(), &[1; 5]),
+        with(zeros.clone(), &[1; 6]),
+        with(zeros.clone(), &[1; 7]),
+        // a password that looks like a 17th repetition is one, a 17th and 18th aren't
+        with(zeros.clone(), &[0; 6]),
+        with(zeros, &[0; 12]),
+    ];
+    for case in cases {
+        check_parse(&case);
+    }
+}
@@ -0,0 +1,150 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use common::{post_json, test_app, Recorder};
+use http_body_util::BodyExt;
+use std::collections::BTreeMap;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{BatchWakeResponse, Host, HostSource},
+    config::{self, Config},
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    MacAddress,
+};
+
+const NAS: MacAddress = MacAddress([0x02, 0, 0, 0, 0, 0x01]);
+const IMPOSTOR: MacAddress = MacAddress([0x02, 0, 0, 0, 0, 0x66]);
+const TV: MacAddress = MacAddress([0x02, 0, 0, 0, 0, 0x02]);
+
+fn entry(name: &str, mac: MacAddress) -> HostEntry {
+    HostEntry {
+        name: name.to_owned(),
+        ip: Some("192.168.1.20".parse().unwrap()),
+        mac,
+        named_by: None,
+        state: None,
+        vlan: None,
+    }
+}
+
+/// `nas` is discovered with another MAC than its pinned one, `tv` isn't discovered at all.
+fn state(sender: Recorder) -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        pinned_macs: BTreeMap::from([("nas".to_owned(), NAS), ("tv".to_owned(), TV)]),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(sender)
+    .with_discovery(StaticDiscovery(vec![entry("nas", IMPOSTOR)]))
+}
+
+#[tokio::test]
+async fn mismatch_is_refused() {
+    let sent = Recorder::default();
+    let app = test_app(state(sent.clone()));
+
+    let (status, body) = post_json(&app, "/api/v1/wake", r#"{"host": "nas"}"#).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(
+        body["error"],
+        "host `nas` is discovered with 02:00:00:00:00:66, but its MAC is pinned to \
+         02:00:00:00:00:01, wake with `force` to wake it anyway"
+    );
+    let (_, body) = post_json(&app, "/api/v1/wake/batch", r#"{"hosts": ["nas"]}"#).await;
+    let response: BatchWakeResponse = serde_json::from_value(body).unwrap();
+    assert!(!response.all_sent);
+    assert!(sent.0.lock().unwrap().is_empty());
+
+    let (status, _) = post_json(&app, "/api/v1/wake", r#"{"host": "nas", "force": true}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(*sent.0.lock().unwrap(), [IMPOSTOR]);
+}
+
+#[tokio::test]
+async fn pinned_mac_when_not_discovered() {
+    let sent = Recorder::default();
+    let app = test_app(state(sent.clone()));
+
+    let (status, body) = post_json(&app, "/api/v1/wake", r#"{"host": "tv"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(body["mac"], TV.to_string());
+    let (status, _) = post_json(&app, "/api/v1/wake/batch", r#"{"hosts": ["tv"]}"#).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(*sent.0.lock().unwrap(), [TV, TV]);
+}
+
+#[tokio::test]
+async fn flagged_in_hosts() {
+    let app = test_app(state(Recorder::default()));
+
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let hosts: Vec<Host> = serde_json::from_slice(&body).unwrap();
+    let summary = hosts
+        .iter()
+        .map(|host| {
+            (
+                host.name.as_str(),
+                host.mac.as_str(),
+                host.source,
+                host.pinned_mac.as_deref(),
+                host.mac_mismatch,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (
+                "nas",
+                "02:00:00:00:00:66",
+                HostSource::Discovered,
+                Some("02:00:00:00:00:01"),
+                true
+            ),
+            (
+                "tv",
+                "02:00:00:00:00:02",
+                HostSource::Pinned,
+                Some("02:00:00:00:00:02"),
+                false
+            ),
+        ]
+    );
+}
+
+#[test]
+fn problems() {
+    let path = std::env::temp_dir().join(format!("wakeonlan-pinning-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+[[hosts]]
+name = "nas"
+mac = "02:00:00:00:00:01"
+
+[pinned_macs]
+NAS = "02:00:00:00:00:01"
+"#,
+    )
+    .unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].key, "pinned_macs.NAS");
+    assert_eq!(
+        problems[0].message,
+        "a configured host, its MACs are used already"
+    );
+
+    std::fs::write(&path, "[pinned_macs]\nnas = \"nope\"\n").unwrap();
+    assert!(config::check_file(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,180 @@
+#![cfg(feature = "server")]
+
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use wakeonlan::{
+    config::{Config, ProxyConfig, ProxyWake, StaticHost},
+    server::{AppState, Proxy},
+};
+
+/// Starts a proxy for `media` in front of `upstream`, with the packets going to the returned
+/// socket.
+async fn start_proxy(upstream: SocketAddr, wake: ProxyWake, hold: bool) -> (SocketAddr, UdpSocket) {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let state = AppState::new(Config {
+        broadcast: receiver.local_addr().unwrap(),
+        hosts: vec![StaticHost::new("media", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        verify_timeout: Duration::from_millis(200),
+        ..Config::default()
+    })
+    .unwrap();
+    let proxy = Proxy::bind(ProxyConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        upstream: format!("http://{upstream}/media/"),
+        host: "media".to_owned(),
+        wake,
+        max_wait: 10,
+        hold,
+    })
+    .await
+    .unwrap();
+    let addr = proxy.local_addr().unwrap();
+    tokio::spawn(proxy.run(Arc::new(state)));
+    (addr, receiver)
+}
+
+/// An address nothing listens on (yet).
+fn free_addr() -> SocketAddr {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+/// Answers every request with its own request line and `X-Forwarded-For`, in a chunked body.
+async fn serve_upstream(listener: TcpListener) {
+    loop {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move {
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let len = stream.read(&mut buf).await.unwrap();
+                assert_ne!(len, 0);
+                request.extend_from_slice(&buf[..len]);
+            }
+            let request = String::from_utf8(request).unwrap();
+            let request_line = request.lines().next().unwrap();
+            let forwarded_for = request
+                .lines()
+                .find_map(|line| line.strip_prefix("X-Forwarded-For: "))
+                .unwrap();
+            let body = format!("{request_line}\n{forwarded_for}");
+            let (first, second) = body.split_at(5);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nX-Upstream: yes\r\n\r\n{:x}\r\n{first}\r\n{:x}\r\n{second}\r\n0\r\n\r\n",
+                first.len(),
+                second.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+    }
+}
+
+/// Returns the status, the headers and the body.
+async fn get(proxy: SocketAddr, path: &str, accept: &str) -> (u16, String, String) {
+    let mut stream = TcpStream::connect(proxy).await.unwrap();
+    let request = format!(
+        "GET {path} HTTP/1.1\r\nHost: media.example\r\nAccept: {accept}\r\nConnection: close\r\n\r\n"
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let head = head.to_ascii_lowercase();
+    let body = if head.contains("transfer-encoding: chunked") {
+        let mut decoded = String::new();
+        let mut rest = body;
+        loop {
+            let (size, after) = rest.split_once("\r\n").unwrap();
+            let size = usize::from_str_radix(size, 16).unwrap();
+            if size == 0 {
+                break decoded;
+            }
+            decoded.push_str(&after[..size]);
+            rest = &after[size + 2..];
+        }
+    } else {
+        body.to_owned()
+    };
+    (status, head, body)
+}
+
+fn received(receiver: &UdpSocket) -> usize {
+    let mut buf = [0; 200];
+    let mut count = 0;
+    while let Ok(len) = receiver.recv(&mut buf) {
+        assert_eq!(buf[6..12], [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+        assert_eq!(len, 102);
+        count += 1;
+    }
+    count
+}
+
+#[tokio::test]
+async fn wakes_then_forwards() {
+    let upstream = free_addr();
+    let (proxy, receiver) = start_proxy(upstream, ProxyWake::Any, false).await;
+
+    let (status, head, body) = get(proxy, "/web/index.html?x=1", "text/html").await;
+    assert_eq!(status, 503);
+    assert!(head.contains("retry-after: "), "{head}");
+    assert!(body.contains("Waking up media, retry in ~"), "{body}");
+    assert!(body.contains(r#"http-equiv="refresh""#), "{body}");
+    assert_eq!(received(&receiver), 1);
+
+    // asking again while it comes up doesn't wake it again
+    let (status, _, _) = get(proxy, "/", "*/*").await;
+    assert_eq!(status, 503);
+    assert_eq!(received(&receiver), 0);
+
+    tokio::spawn(serve_upstream(TcpListener::bind(upstream).await.unwrap()));
+    let (status, head, body) = get(proxy, "/web/index.html?x=1", "text/html").await;
+    assert_eq!(status, 200);
+    assert!(head.contains("x-upstream: yes"), "{head}");
+    assert_eq!(body, "GET /media/web/index.html?x=1 HTTP/1.1\n127.0.0.1");
+    assert_eq!(received(&receiver), 0);
+}
+
+#[tokio::test]
+async fn holds_until_upstream_answers() {
+    let upstream = free_addr();
+    let (proxy, receiver) = start_proxy(upstream, ProxyWake::Any, true).await;
+
+    let request = tokio::spawn(get(proxy, "/api/items", "application/json"));
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(!request.is_finished());
+    tokio::spawn(serve_upstream(TcpListener::bind(upstream).await.unwrap()));
+
+    let (status, _, body) = request.await.unwrap();
+    assert_eq!(status, 200);
+    assert_eq!(body, "GET /media/api/items HTTP/1.1\n127.0.0.1");
+    assert_eq!(received(&receiver), 1);
+}
+
+#[tokio::test]
+async fn only_pages_wake() {
+    let upstream = free_addr();
+    let (proxy, receiver) = start_proxy(upstream, ProxyWake::Pages, false).await;
+
+    let (status, _, body) = get(proxy, "/api/sessions", "application/json").await;
+    assert_eq!(status, 503);
+    assert!(body.contains("media is asleep."), "{body}");
+    assert_eq!(received(&receiver), 0);
+
+    let (status, _, body) = get(proxy, "/", "text/html,*/*;q=0.8").await;
+    assert_eq!(status, 503);
+    assert!(body.contains("Waking up media"), "{body}");
+    assert_eq!(received(&receiver), 1);
+}
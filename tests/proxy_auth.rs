@@ -0,0 +1,94 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::Arc,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::Host,
+    config::{Config, ProxyAuthConfig, StaticHost},
+    server::{self, AppState},
+};
+
+/// The proxy is at 10.0.0.1.
+fn app(token: Option<&str>, allowed_users: &[&str]) -> (Router, UdpSocket) {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let state = AppState::new(Config {
+        broadcast: receiver.local_addr().unwrap(),
+        token: token.map(Into::into),
+        hosts: vec![StaticHost::new("pc", ["00:d8:61:ca:3a:18"]).unwrap()],
+        trusted_proxies: vec!["10.0.0.1/32".parse().unwrap()],
+        proxy_auth: Some(ProxyAuthConfig {
+            header: "Remote-User".to_owned(),
+            allowed_users: allowed_users.iter().map(|user| user.to_string()).collect(),
+        }),
+        ..Config::default()
+    })
+    .unwrap();
+    (server::router(Arc::new(state)), receiver)
+}
+
+async fn wake(app: &Router, peer: &str, headers: &[(&str, &str)]) -> StatusCode {
+    let mut request = Request::post("/wake").header(header::CONTENT_TYPE, "application/json");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let mut request = request.body(Body::from(r#"{"host": "pc"}"#)).unwrap();
+    request
+        .extensions_mut()
+        .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+async fn last_principal(app: &Router) -> Option<String> {
+    let request = Request::get("/hosts").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let hosts: Vec<Host> = serde_json::from_slice(&body).unwrap();
+    hosts[0].last_wake.as_ref()?.principal.clone()
+}
+
+#[tokio::test]
+async fn user_from_proxy() {
+    let (app, _receiver) = app(None, &[]);
+    let status = wake(&app, "10.0.0.1:1234", &[("remote-user", "alice")]).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(last_principal(&app).await.as_deref(), Some("alice"));
+
+    let status = wake(&app, "10.0.0.1:1234", &[]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // the header means nothing from anyone else, and without a token there's no other way in
+    let status = wake(&app, "10.0.0.2:1234", &[("remote-user", "alice")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn token_and_allowed_users() {
+    let (app, _receiver) = app(Some("hunter2"), &["alice"]);
+    let status = wake(
+        &app,
+        "10.0.0.2:1234",
+        &[
+            ("remote-user", "alice"),
+            ("authorization", "Bearer hunter2"),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(last_principal(&app).await.as_deref(), Some("token"));
+
+    let status = wake(&app, "10.0.0.1:1234", &[("remote-user", "bob")]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let status = wake(&app, "10.0.0.1:1234", &[("remote-user", "alice")]).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(last_principal(&app).await.as_deref(), Some("alice"));
+}
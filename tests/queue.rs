@@ -0,0 +1,206 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use serde::de::DeserializeOwned;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1,
+    config::{Config, SendQueueConfig, StaticHost},
+    retry::RetryPolicy,
+    server::{self, AppState, PacketSender},
+    MagicPacket,
+};
+
+const NAS: &str = "a8:a1:59:0e:7b:02";
+const PC: &str = "00:d8:61:ca:3a:18";
+
+/// Fails like without a network while `down` is set.
+#[derive(Default)]
+struct Network {
+    down: AtomicBool,
+    sent: AtomicUsize,
+}
+
+struct Flaky(Arc<Network>);
+
+impl PacketSender for Flaky {
+    fn send(&self, _: &MagicPacket, _: SocketAddr, _: Option<&str>) -> io::Result<SocketAddr> {
+        if self.0.down.load(Ordering::SeqCst) {
+            return Err(io::Error::from_raw_os_error(libc::ENETUNREACH));
+        }
+        self.0.sent.fetch_add(1, Ordering::SeqCst);
+        Ok("127.0.0.1:40000".parse().unwrap())
+    }
+}
+
+async fn send<T: DeserializeOwned>(app: &Router, request: Request<Body>) -> (StatusCode, T) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+async fn wake(app: &Router, body: &str) -> (StatusCode, serde_json::Value) {
+    let request = Request::post("/api/v1/wake")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    send(app, request).await
+}
+
+async fn queue(app: &Router) -> Vec<v1::QueuedWake> {
+    let (status, queue) = send(
+        app,
+        Request::get("/api/v1/queue").body(Body::empty()).unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    queue
+}
+
+async fn last_wake(app: &Router, host: &str) -> v1::LastWake {
+    let (_, hosts): (_, Vec<v1::Host>) = send(
+        app,
+        Request::get("/api/v1/hosts").body(Body::empty()).unwrap(),
+    )
+    .await;
+    let host = hosts.into_iter().find(|found| found.name == host).unwrap();
+    host.last_wake.unwrap()
+}
+
+#[tokio::test]
+async fn sent_once_the_network_is_back() {
+    let network = Arc::new(Network::default());
+    network.down.store(true, Ordering::SeqCst);
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![
+            StaticHost::new("nas", [NAS]).unwrap(),
+            StaticHost::new("pc", [PC]).unwrap(),
+        ],
+        retry: RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        },
+        send_queue: Some(SendQueueConfig {
+            max_age: 60,
+            size: 1,
+        }),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(Flaky(network.clone()));
+    let state = Arc::new(state);
+    tokio::spawn(server::run_send_queue(state.clone()));
+    let app = server::router(state);
+
+    let (status, woken) = wake(&app, r#"{"host": "nas"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(woken["destinations"][0]["queued"], true);
+    assert_eq!(woken["destinations"][0]["sent"], false);
+    assert_eq!(
+        last_wake(&app, "nas").await.outcome,
+        v1::WakeOutcome::Queued
+    );
+
+    // the same MAC again is merged into it
+    let (status, again) = wake(&app, &format!(r#"{{"mac": "{NAS}"}}"#)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let [queued] = &queue(&app).await[..] else {
+        panic!("expected one queued wake");
+    };
+    assert_eq!(queued.id, again["id"]);
+    assert_eq!(queued.host.as_deref(), Some("nas"));
+    assert_eq!(queued.mac, NAS);
+    assert_eq!(queued.destinations, ["127.0.0.1:9".parse().unwrap()]);
+
+    // the queue is full
+    let (status, failed) = wake(&app, r#"{"host": "pc"}"#).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(failed["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("failed to send packet: 127.0.0.1:9: "));
+    assert_eq!(queue(&app).await.len(), 1);
+    assert_eq!(last_wake(&app, "pc").await.outcome, v1::WakeOutcome::Failed);
+
+    // tried again after a second, and the network is still down
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let [queued] = &queue(&app).await[..] else {
+        panic!("expected one queued wake");
+    };
+    assert_eq!(queued.attempts, 1);
+    assert!(queued.error.as_deref().unwrap().contains("unreachable"));
+
+    network.down.store(false, Ordering::SeqCst);
+    for _ in 0..50 {
+        if queue(&app).await.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(queue(&app).await.is_empty());
+    assert_eq!(network.sent.load(Ordering::SeqCst), 1);
+    let sent = last_wake(&app, "nas").await;
+    assert_eq!(sent.outcome, v1::WakeOutcome::Sent);
+    assert_eq!(sent.id, again["id"]);
+
+    // cancelled before the network is back
+    network.down.store(true, Ordering::SeqCst);
+    let (status, _) = wake(&app, r#"{"host": "pc"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let cancel = || {
+        Request::delete(format!("/api/v1/queue/{PC}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = app.clone().oneshot(cancel()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = app.clone().oneshot(cancel()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(queue(&app).await.is_empty());
+    assert_eq!(
+        last_wake(&app, "pc").await.outcome,
+        v1::WakeOutcome::Cancelled
+    );
+}
+
+#[tokio::test]
+async fn failing_without_a_queue() {
+    let network = Arc::new(Network::default());
+    network.down.store(true, Ordering::SeqCst);
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![StaticHost::new("nas", [NAS]).unwrap()],
+        retry: RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        },
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(Flaky(network));
+    let app = server::router(Arc::new(state));
+
+    let (status, failed) = wake(&app, r#"{"host": "nas"}"#).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(failed["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("failed to send packet: "));
+    assert!(queue(&app).await.is_empty());
+}
@@ -0,0 +1,247 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use chrono::{Local, NaiveTime, TimeDelta};
+use common::{post_json, test_app};
+use http_body_util::BodyExt;
+use std::{net::UdpSocket, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{BatchWakeResponse, HistoryEntry, Host, WakeOutcome},
+    config::{self, Config, ProxyConfig, ProxyWake, QuietHoursConfig, RelayConfig, StaticHost},
+    discovery::StaticDiscovery,
+    server::{AppState, Proxy, Relay},
+    MagicPacket,
+};
+
+fn time(time: &str) -> NaiveTime {
+    NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+}
+
+fn quiet(start: &str, end: &str) -> QuietHoursConfig {
+    QuietHoursConfig {
+        start: time(start),
+        end: time(end),
+        hosts: Vec::new(),
+        locations: Vec::new(),
+        require_force: false,
+    }
+}
+
+/// Quiet for an hour before and after now, so it's quiet whenever the test runs.
+fn quiet_now(require_force: bool) -> QuietHoursConfig {
+    let now = Local::now().time();
+    QuietHoursConfig {
+        require_force,
+        start: now - TimeDelta::hours(1),
+        end: now + TimeDelta::hours(1),
+        ..quiet("00:00", "00:00")
+    }
+}
+
+fn state(quiet_hours: QuietHoursConfig) -> Arc<AppState> {
+    let state = AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![StaticHost::new("nas", ["02:00:00:00:00:01"]).unwrap()],
+        quiet_hours: Some(quiet_hours),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(Vec::new()));
+    Arc::new(state)
+}
+
+async fn last_outcome(app: &Router) -> WakeOutcome {
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let hosts: Vec<Host> = serde_json::from_slice(&body).unwrap();
+    hosts[0].last_wake.as_ref().unwrap().outcome
+}
+
+#[test]
+fn window() {
+    let day = quiet("09:00", "17:00");
+    assert!(!day.contains(time("08:59")));
+    assert!(day.contains(time("09:00")));
+    assert!(day.contains(time("16:59")));
+    assert!(!day.contains(time("17:00")));
+
+    let night = quiet("22:00", "07:00");
+    assert!(!night.contains(time("21:59")));
+    assert!(night.contains(time("22:00")));
+    assert!(night.contains(time("00:00")));
+    assert!(night.contains(time("06:59")));
+    assert!(!night.contains(time("07:00")));
+    assert!(!night.contains(time("12:00")));
+}
+
+#[test]
+fn only_some_hosts() {
+    assert!(quiet("22:00", "07:00").applies_to(None, None));
+    let hosts = QuietHoursConfig {
+        hosts: vec!["NAS".to_owned()],
+        locations: vec!["bedroom".to_owned()],
+        ..quiet("22:00", "07:00")
+    };
+    assert!(hosts.applies_to(Some("nas"), None));
+    assert!(hosts.applies_to(Some("tv"), Some("Bedroom")));
+    assert!(!hosts.applies_to(Some("tv"), Some("office")));
+    assert!(!hosts.applies_to(None, None));
+}
+
+#[tokio::test]
+async fn manual_wakes_go_ahead() {
+    let app = test_app(state(quiet_now(false)));
+
+    let (status, _) = post_json(&app, "/api/v1/wake", r#"{"host": "nas"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(last_outcome(&app).await, WakeOutcome::Sent);
+}
+
+#[tokio::test]
+async fn forced_when_required() {
+    let app = test_app(state(quiet_now(true)));
+
+    let (status, body) = post_json(&app, "/api/v1/wake", r#"{"host": "nas"}"#).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let until = quiet_now(true).end.format("%H:%M");
+    assert_eq!(
+        body["error"],
+        format!("quiet hours until {until}, wake with `force` to wake anyway")
+    );
+    assert_eq!(last_outcome(&app).await, WakeOutcome::Suppressed);
+
+    let (status, body) = post_json(&app, "/api/v1/wake/batch", r#"{"hosts": ["nas"]}"#).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    let response: BatchWakeResponse = serde_json::from_value(body).unwrap();
+    assert!(response.results[0]
+        .error
+        .as_deref()
+        .unwrap()
+        .starts_with("quiet hours until "));
+
+    let (status, _) = post_json(&app, "/api/v1/wake", r#"{"host": "nas", "force": true}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(last_outcome(&app).await, WakeOutcome::Sent);
+    let (status, _) = post_json(
+        &app,
+        "/api/v1/wake/batch",
+        r#"{"hosts": ["nas"], "force": true}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let request = Request::get("/api/v1/history").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let history: Vec<HistoryEntry> = serde_json::from_slice(&body).unwrap();
+    let outcomes = history.iter().map(|entry| entry.wake.outcome);
+    assert_eq!(
+        outcomes.collect::<Vec<_>>(),
+        [
+            WakeOutcome::Sent,
+            WakeOutcome::Sent,
+            WakeOutcome::Suppressed,
+            WakeOutcome::Suppressed
+        ]
+    );
+}
+
+#[tokio::test]
+async fn relayed_packets_are_suppressed() {
+    let state = state(quiet_now(false));
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let relay = Relay::bind(RelayConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        destinations: vec![receiver.local_addr().unwrap()],
+        rate_limit: 10,
+    })
+    .await
+    .unwrap();
+    let relay_addr = relay.local_addr().unwrap();
+    tokio::spawn(relay.run(state.clone()));
+
+    let packet = MagicPacket::new(&[0x02, 0, 0, 0, 0, 0x01]);
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.send_to(packet.magic_bytes(), relay_addr).unwrap();
+    let relayed = tokio::task::spawn_blocking(move || receiver.recv(&mut [0; 200]).is_ok());
+    assert!(!relayed.await.unwrap(), "the packet was relayed");
+    assert_eq!(
+        last_outcome(&test_app(state)).await,
+        WakeOutcome::Suppressed
+    );
+}
+
+#[tokio::test]
+async fn proxied_requests_are_suppressed() {
+    let state = state(quiet_now(false));
+    let upstream = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let proxy = Proxy::bind(ProxyConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        upstream: format!("http://{upstream}/"),
+        host: "nas".to_owned(),
+        wake: ProxyWake::Any,
+        max_wait: 10,
+        hold: false,
+    })
+    .await
+    .unwrap();
+    let proxy_addr = proxy.local_addr().unwrap();
+    tokio::spawn(proxy.run(state.clone()));
+
+    let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: nas.example\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 502"), "{response}");
+    assert!(response.contains("quiet hours until "), "{response}");
+    assert_eq!(
+        last_outcome(&test_app(state)).await,
+        WakeOutcome::Suppressed
+    );
+}
+
+#[test]
+fn problems() {
+    let path = std::env::temp_dir().join(format!("wakeonlan-quiet-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+[quiet_hours]
+start = "22:00"
+end = "22:00"
+"#,
+    )
+    .unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].key, "quiet_hours.end");
+    assert_eq!(
+        problems[0].message,
+        "the same as `start`, it would never be quiet"
+    );
+
+    std::fs::write(&path, "[quiet_hours]\nstart = \"late\"\nend = \"07:00\"\n").unwrap();
+    assert!(config::check_file(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
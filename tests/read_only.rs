@@ -0,0 +1,94 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::ErrorResponse,
+    config::{Config, StaticHost},
+    discovery::StaticDiscovery,
+    server::AppState,
+};
+
+fn state() -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        token: Some("secret".into()),
+        read_only: true,
+        hosts: vec![StaticHost {
+            location: Some("Office".to_owned()),
+            ..StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()
+        }],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(Vec::new()))
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+#[tokio::test]
+async fn mutations_are_refused() {
+    let app = test_app(state());
+    let requests = [
+        ("/api/v1/wake", "application/json", r#"{"host": "nas"}"#),
+        ("/wake", "application/x-www-form-urlencoded", "host=nas"),
+        ("/wake/batch", "application/json", r#"{"hosts": ["nas"]}"#),
+        ("/hosts/import", "application/json", r#"{"hosts": []}"#),
+        (
+            "/api/v1/schedules",
+            "application/json",
+            r#"{"host": "nas", "cron": "0 7 * * *"}"#,
+        ),
+    ];
+    for (path, content_type, body) in requests {
+        // with the token and without it
+        for token in [Some("Bearer secret"), None] {
+            let mut request = Request::post(path).header(header::CONTENT_TYPE, content_type);
+            if let Some(token) = token {
+                request = request.header(header::AUTHORIZATION, token);
+            }
+            let request = request.body(Body::from(body)).unwrap();
+            let (status, body) = send(&app, request).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{path}");
+            let response: ErrorResponse = serde_json::from_slice(&body).unwrap();
+            assert_eq!(response.error, "server is in read-only mode");
+        }
+    }
+}
+
+#[tokio::test]
+async fn reads_work() {
+    let app = test_app(state());
+    for path in ["/api/v1/hosts", "/hosts/nas/status", "/metrics", "/healthz"] {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        let (status, _) = send(&app, request).await;
+        assert_eq!(status, StatusCode::OK, "{path}");
+    }
+
+    let request = Request::get("/").body(Body::empty()).unwrap();
+    let (status, page) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    let page = String::from_utf8(page).unwrap();
+    assert!(
+        page.contains(r#"<button class="wake-button" type="submit" disabled>"#),
+        "{page}"
+    );
+    assert!(
+        page.contains("read-only, nothing can be woken from here"),
+        "{page}"
+    );
+    assert!(!page.contains("Wake all"), "{page}");
+}
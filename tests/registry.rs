@@ -0,0 +1,285 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::ServiceExt;
+use wakeonlan::{
+    config::{Config, StaticHost},
+    server::{self, AppState},
+    MacAddress,
+};
+
+fn app(config: Config) -> Router {
+    server::router(Arc::new(
+        AppState::new(Config {
+            hosts: vec![
+                StaticHost {
+                    name: "pc".to_owned(),
+                    macs: vec![MacAddress([0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18])],
+                    interface: None,
+                    source: None,
+                    site: None,
+                    location: None,
+                    service_url: None,
+                    password: None,
+                    post_wake_commands: Vec::new(),
+                    strategies: Vec::new(),
+                    destinations: Vec::new(),
+                    require_confirmation: false,
+                },
+                StaticHost {
+                    name: "nas".to_owned(),
+                    macs: vec![MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02])],
+                    interface: None,
+                    source: None,
+                    site: None,
+                    location: None,
+                    service_url: None,
+                    password: None,
+                    post_wake_commands: Vec::new(),
+                    strategies: Vec::new(),
+                    destinations: Vec::new(),
+                    require_confirmation: false,
+                },
+            ],
+            ..config
+        })
+        .unwrap(),
+    ))
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+async fn export(app: &Router) -> Value {
+    let (status, body) = send(
+        app,
+        Request::get("/hosts/export").body(Body::empty()).unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    body
+}
+
+async fn import(app: &Router, mode: &str, document: Value) -> (StatusCode, Value) {
+    let request = Request::post(format!("/hosts/import?mode={mode}"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(document.to_string()))
+        .unwrap();
+    send(app, request).await
+}
+
+fn statuses(response: &Value) -> Vec<(&str, &str)> {
+    response["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| {
+            (
+                result["name"].as_str().unwrap(),
+                result["status"].as_str().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn export_then_import_is_unchanged() {
+    let app = app(Config::default());
+    let exported = export(&app).await;
+    assert_eq!(
+        exported,
+        json!({"hosts": [
+            {"name": "pc", "macs": ["00:d8:61:ca:3a:18"]},
+            {"name": "nas", "macs": ["a8:a1:59:0e:7b:02"]},
+        ]})
+    );
+
+    let (status, response) = import(&app, "replace", exported.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        statuses(&response),
+        [("pc", "unchanged"), ("nas", "unchanged")]
+    );
+    assert_eq!(export(&app).await, exported);
+}
+
+#[tokio::test]
+async fn merge_and_replace() {
+    let app = app(Config::default());
+    let document = json!({"hosts": [
+        {"name": "pc", "macs": ["00:11:22:33:44:55"]},
+        {"name": "laptop", "macs": ["3c:7c:3f:1d:aa:09"]},
+    ]});
+
+    let (status, response) = import(&app, "merge", document.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        statuses(&response),
+        [("pc", "updated"), ("laptop", "added")]
+    );
+    assert_eq!(
+        export(&app).await,
+        json!({"hosts": [
+            {"name": "pc", "macs": ["00:11:22:33:44:55"]},
+            {"name": "nas", "macs": ["a8:a1:59:0e:7b:02"]},
+            {"name": "laptop", "macs": ["3c:7c:3f:1d:aa:09"]},
+        ]})
+    );
+
+    let (status, response) = import(&app, "replace", document.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        statuses(&response),
+        [
+            ("pc", "unchanged"),
+            ("laptop", "unchanged"),
+            ("nas", "removed")
+        ]
+    );
+    assert_eq!(export(&app).await, document);
+}
+
+#[tokio::test]
+async fn imported_locations() {
+    let app = app(Config::default());
+    let document = json!({"hosts": [
+        {"name": "pc", "macs": ["00:d8:61:ca:3a:18"], "location": " Office "},
+        {"name": "nas", "macs": ["a8:a1:59:0e:7b:02"], "location": ""},
+    ]});
+
+    let (status, response) = import(&app, "replace", document).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        statuses(&response),
+        [("pc", "updated"), ("nas", "unchanged")]
+    );
+    assert_eq!(
+        export(&app).await,
+        json!({"hosts": [
+            {"name": "pc", "macs": ["00:d8:61:ca:3a:18"], "location": "Office"},
+            {"name": "nas", "macs": ["a8:a1:59:0e:7b:02"]},
+        ]})
+    );
+    let (_, hosts) = send(
+        &app,
+        Request::get("/hosts?location=office")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(hosts[0]["name"], "pc");
+    assert_eq!(hosts[0]["location"], "Office");
+    assert_eq!(hosts.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn passwords_are_not_exported() {
+    let app = app(Config::default());
+    let document = json!({"hosts": [
+        {"name": "pc", "macs": ["00:d8:61:ca:3a:18"], "password": "a1b2c3d4"},
+        {"name": "nas", "macs": ["a8:a1:59:0e:7b:02"], "password": "a1b2c3d4e5"},
+    ]});
+
+    let (status, response) = import(&app, "merge", document).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response["results"][1]["error"],
+        "invalid password for host `nas`: expected 4 or 6 bytes, got 5"
+    );
+
+    let document = json!({"hosts": [
+        {"name": "pc", "macs": ["00:d8:61:ca:3a:18"], "password": "a1b2c3d4"},
+    ]});
+    let (status, response) = import(&app, "merge", document).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(statuses(&response), [("pc", "updated")]);
+    assert_eq!(
+        export(&app).await["hosts"][0],
+        json!({"name": "pc", "macs": ["00:d8:61:ca:3a:18"]})
+    );
+}
+
+#[tokio::test]
+async fn invalid_import_changes_nothing() {
+    let path = std::env::temp_dir().join(format!("wakeonlan-registry-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let app = app(Config {
+        registry: Some(path.clone()),
+        ..Config::default()
+    });
+    let before = export(&app).await;
+
+    let (status, response) = import(
+        &app,
+        "replace",
+        json!({"hosts": [
+            {"name": "laptop", "macs": ["3c:7c:3f:1d:aa:09"]},
+            {"name": "broken", "macs": ["not a mac"]},
+            {"name": "LAPTOP", "macs": ["3c:7c:3f:1d:aa:0a"]},
+        ]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["applied"], false);
+    assert_eq!(
+        statuses(&response),
+        [
+            ("laptop", "added"),
+            ("broken", "invalid"),
+            ("LAPTOP", "invalid"),
+            ("pc", "removed"),
+            ("nas", "removed")
+        ]
+    );
+    assert_eq!(export(&app).await, before);
+    assert!(!path.exists());
+
+    let (status, _) = import(
+        &app,
+        "merge",
+        json!({"hosts": [{"name": "laptop", "macs": ["3c:7c:3f:1d:aa:09"]}]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let mut saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(
+        saved.as_object_mut().unwrap().remove("version"),
+        Some(2.into())
+    );
+    assert_eq!(saved, export(&app).await);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn hosts_with_several_macs() {
+    let app = app(Config::default());
+    let (status, response) = import(
+        &app,
+        "merge",
+        json!({"hosts": [
+            {"name": "workstation", "mac": "00:11:22:33:44:55", "macs": ["3c:7c:3f:1d:aa:09"]},
+            {"name": "nas", "macs": ["a8:a1:59:0e:7b:02", "a8:a1:59:0e:7b:02"]},
+        ]}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        statuses(&response),
+        [("workstation", "added"), ("nas", "unchanged")]
+    );
+    assert_eq!(
+        export(&app).await["hosts"][2],
+        json!({"name": "workstation", "macs": ["00:11:22:33:44:55", "3c:7c:3f:1d:aa:09"]})
+    );
+}
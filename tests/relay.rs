@@ -0,0 +1,61 @@
+#![cfg(feature = "server")]
+
+use std::{net::UdpSocket, sync::Arc, time::Duration};
+use wakeonlan::{
+    config::{Config, RelayConfig},
+    parse_magic_packet,
+    server::{AppState, Relay},
+    MacAddress, MagicPacket,
+};
+
+#[test]
+fn parse_magic_packets() {
+    let mac = [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18];
+    let packet = MagicPacket::new(&mac);
+    assert_eq!(
+        parse_magic_packet(packet.magic_bytes()),
+        Some(MacAddress(mac))
+    );
+
+    let mut broken = *packet.magic_bytes();
+    broken[101] ^= 1;
+    assert_eq!(parse_magic_packet(&broken), None);
+    assert_eq!(parse_magic_packet(&packet.magic_bytes()[..96]), None);
+    assert_eq!(parse_magic_packet(&[0; 102]), None);
+}
+
+#[tokio::test]
+async fn relays_valid_packets_once() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let relay = Relay::bind(RelayConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        destinations: vec![receiver.local_addr().unwrap()],
+        rate_limit: 10,
+    })
+    .await
+    .unwrap();
+    let relay_addr = relay.local_addr().unwrap();
+    let state = Arc::new(AppState::new(Config::default()).unwrap());
+    tokio::spawn(relay.run(state));
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.send_to(b"not a magic packet", relay_addr).unwrap();
+    let mac = [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18];
+    let packet = MagicPacket::new(&mac);
+    client.send_to(packet.magic_bytes(), relay_addr).unwrap();
+
+    let receive = tokio::task::spawn_blocking(move || {
+        let mut buf = [0; 200];
+        let len = receiver.recv(&mut buf).unwrap();
+        let first = buf[..len].to_vec();
+        // the same packet right after is what relaying back and forth would look like
+        client.send_to(packet.magic_bytes(), relay_addr).unwrap();
+        (first, receiver.recv(&mut buf).is_err())
+    });
+    let (first, nothing_else) = receive.await.unwrap();
+    assert_eq!(parse_magic_packet(&first), Some(MacAddress(mac)));
+    assert!(nothing_else, "the packet was relayed twice");
+}
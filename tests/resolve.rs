@@ -0,0 +1,165 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{Normalization, NotResolved, Resolution, ResolveSource, WakeResponse},
+    config::{Config, StaticHost},
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    MacAddress,
+};
+
+fn entry(name: &str, ip: &str, last: u8) -> HostEntry {
+    HostEntry {
+        name: name.to_owned(),
+        ip: Some(ip.parse().unwrap()),
+        mac: MacAddress([0x02, 0, 0, 0, 0, last]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }
+}
+
+fn state() -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![StaticHost::new("NAS", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![
+        entry("pc.fritz.box", "192.168.1.30", 1),
+        entry("tv-living", "192.168.1.40", 2),
+        entry("tv-bedroom", "192.168.1.41", 3),
+    ]))
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+async fn resolve(app: &Router, name: &str) -> (StatusCode, Vec<u8>) {
+    let request = Request::get(format!("/api/v1/resolve/{name}"))
+        .body(Body::empty())
+        .unwrap();
+    send(app, request).await
+}
+
+async fn dry_run(app: &Router, name: &str) -> Option<WakeResponse> {
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(
+            r#"{{"host": "{name}", "dry_run": true}}"#
+        )))
+        .unwrap();
+    let (status, body) = send(app, request).await;
+    (status == StatusCode::ACCEPTED).then(|| serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn resolves() {
+    let app = test_app(state());
+    let cases = [
+        (
+            "NAS",
+            "a8:a1:59:0e:7b:02",
+            ResolveSource::Static,
+            "NAS",
+            None,
+        ),
+        (
+            "nas",
+            "a8:a1:59:0e:7b:02",
+            ResolveSource::Static,
+            "NAS",
+            Some(Normalization::IgnoringCase),
+        ),
+        (
+            "pc",
+            "02:00:00:00:00:01",
+            ResolveSource::Discovered,
+            "pc.fritz.box",
+            Some(Normalization::Substring),
+        ),
+        (
+            "pc.fritz.box",
+            "02:00:00:00:00:01",
+            ResolveSource::Discovered,
+            "pc.fritz.box",
+            None,
+        ),
+    ];
+    for (name, mac, source, matched, normalization) in cases {
+        let (status, body) = resolve(&app, name).await;
+        assert_eq!(status, StatusCode::OK, "{name}");
+        let resolution: Resolution = serde_json::from_slice(&body).unwrap();
+        assert_eq!(resolution.name, name);
+        assert_eq!(resolution.mac, mac);
+        assert_eq!(resolution.source, source, "{name}");
+        assert_eq!(resolution.matched, matched);
+        assert_eq!(resolution.normalization, normalization, "{name}");
+    }
+
+    let (_, body) = resolve(&app, "pc").await;
+    let resolution: Resolution = serde_json::from_slice(&body).unwrap();
+    assert_eq!(resolution.ip, Some("192.168.1.30".parse().unwrap()));
+}
+
+#[tokio::test]
+async fn unknown_or_ambiguous() {
+    let app = test_app(state());
+
+    let (status, body) = resolve(&app, "nsa").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let not_resolved: NotResolved = serde_json::from_slice(&body).unwrap();
+    assert_eq!(not_resolved.error, "host `nsa` not found");
+    assert_eq!(not_resolved.candidates, ["NAS"]);
+
+    let (status, body) = resolve(&app, "tv").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let not_resolved: NotResolved = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        not_resolved.error,
+        "host `tv` is ambiguous, a wake would wake `tv-living`"
+    );
+    assert_eq!(not_resolved.candidates, ["tv-bedroom", "tv-living"]);
+}
+
+/// Whatever resolves is what a wake targets, and whatever doesn't isn't woken either.
+#[tokio::test]
+async fn same_as_a_wake() {
+    let app = test_app(state());
+    for name in [
+        "NAS",
+        "nas",
+        "pc",
+        "pc.fritz.box",
+        "tv-bedroom",
+        "nsa",
+        "printer",
+        "PC",
+    ] {
+        let (status, body) = resolve(&app, name).await;
+        let wake = dry_run(&app, name).await;
+        match status {
+            StatusCode::OK => {
+                let resolution: Resolution = serde_json::from_slice(&body).unwrap();
+                let wake = wake.unwrap_or_else(|| panic!("{name} resolves but isn't woken"));
+                assert_eq!(resolution.macs, wake.macs, "{name}");
+            }
+            _ => assert!(wake.is_none(), "{name} doesn't resolve but is woken"),
+        }
+    }
+}
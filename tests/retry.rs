@@ -0,0 +1,43 @@
+#![cfg(feature = "server")]
+
+use std::{io, time::Duration};
+use wakeonlan::retry::RetryPolicy;
+
+fn policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(2),
+    }
+}
+
+#[test]
+fn transient_errors_are_retried() {
+    let mut calls = 0;
+    let outcome = policy().run(|| {
+        calls += 1;
+        if calls < 3 {
+            Err(io::Error::from(io::ErrorKind::NetworkUnreachable))
+        } else {
+            Ok(calls)
+        }
+    });
+    assert_eq!(outcome.result.unwrap(), 3);
+    assert_eq!(outcome.attempts, 3);
+}
+
+#[test]
+fn gives_up_after_max_attempts() {
+    let outcome = policy().run(|| Err::<(), _>(io::Error::from(io::ErrorKind::NetworkDown)));
+    assert_eq!(
+        outcome.result.unwrap_err().kind(),
+        io::ErrorKind::NetworkDown
+    );
+    assert_eq!(outcome.attempts, 3);
+}
+
+#[test]
+fn permanent_errors_are_not_retried() {
+    let outcome = policy().run(|| Err::<(), _>(io::Error::from(io::ErrorKind::PermissionDenied)));
+    assert_eq!(outcome.attempts, 1);
+}
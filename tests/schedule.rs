@@ -0,0 +1,334 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use chrono::{DateTime, Local, TimeDelta, Timelike, Utc};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use std::{net::UdpSocket, sync::Arc, time::Duration};
+use tower::ServiceExt;
+use wakeonlan::{
+    config::{Config, StaticHost},
+    schedule::Cron,
+    server::{self, AppState},
+};
+
+#[test]
+fn parse_cron() {
+    for valid in [
+        "* * * * *",
+        "0 3 * * *",
+        "*/15 8-18 * * mon-fri",
+        "0 0 1,15 jan,jul *",
+        "5/10 * * * 7",
+    ] {
+        assert!(valid.parse::<Cron>().is_ok(), "{valid}");
+    }
+    for invalid in [
+        "* * * *",
+        "60 * * * *",
+        "* 24 * * *",
+        "* * 0 * *",
+        "*/0 * * * *",
+        "5-1 * * * *",
+        "* * * foo *",
+    ] {
+        assert!(invalid.parse::<Cron>().is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn next_cron_time() {
+    let after: DateTime<Utc> = "2026-03-10T12:34:56Z".parse().unwrap();
+
+    let every_minute: Cron = "* * * * *".parse().unwrap();
+    assert_eq!(
+        every_minute.next_after(after),
+        Some("2026-03-10T12:35:00Z".parse().unwrap())
+    );
+
+    let quarter_hours: Cron = "*/15 * * * *".parse().unwrap();
+    let next = quarter_hours.next_after(after).unwrap();
+    assert_eq!(next.minute() % 15, 0);
+    assert!(next > after && next - after <= TimeDelta::minutes(15));
+
+    let nightly: Cron = "30 3 * * *".parse().unwrap();
+    let next = nightly.next_after(after).unwrap();
+    let local = next.with_timezone(&Local);
+    assert_eq!((local.hour(), local.minute(), local.second()), (3, 30, 0));
+    assert!(next > after && next - after <= TimeDelta::hours(25));
+
+    let leap_day: Cron = "0 0 29 2 *".parse().unwrap();
+    let next = leap_day.next_after(after).unwrap();
+    assert_eq!(
+        next.with_timezone(&Local).date_naive().to_string(),
+        "2028-02-29"
+    );
+}
+
+fn app(config: Config) -> (Arc<AppState>, Router, UdpSocket) {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let state = Arc::new(
+        AppState::new(Config {
+            broadcast: receiver.local_addr().unwrap(),
+            hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+            ..config
+        })
+        .unwrap(),
+    );
+    (state.clone(), server::router(state), receiver)
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn create(app: &Router, schedule: Value) -> (StatusCode, Value) {
+    let request = Request::post("/schedules")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(schedule.to_string()))
+        .unwrap();
+    send(app, request).await
+}
+
+async fn list(app: &Router) -> Value {
+    let (status, body) = send(app, Request::get("/schedules").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    body
+}
+
+#[tokio::test]
+async fn manage_schedules() {
+    let path =
+        std::env::temp_dir().join(format!("wakeonlan-schedules-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let config = || Config {
+        schedules_file: Some(path.clone()),
+        ..Config::default()
+    };
+    let (_, app, _receiver) = app(config());
+
+    let (status, nightly) = create(&app, json!({"host": "nas", "cron": "0 3 * * *"})).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(nightly["host"], "nas");
+    assert_eq!(nightly["cron"], "0 3 * * *");
+    assert!(nightly["next"].is_string());
+
+    let (status, _) = create(&app, json!({"host": "nas", "at": "2000-01-01T00:00:00Z"})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = create(&app, json!({"host": "nas", "cron": "0 25 * * *"})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = create(&app, json!({"host": "nas"})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, once) = create(&app, json!({"host": "nas", "at": "2999-01-01T00:00:00Z"})).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(once["next"], "2999-01-01T00:00:00Z");
+    assert_eq!(list(&app).await, json!([nightly, once]));
+
+    // a restart keeps them
+    let (_, app, _receiver) = self::app(config());
+    assert_eq!(list(&app).await, json!([nightly, once]));
+
+    let id = nightly["id"].as_str().unwrap();
+    let delete = || {
+        Request::delete(format!("/schedules/{id}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+    assert_eq!(send(&app, delete()).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, delete()).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(list(&app).await, json!([once]));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn fires_one_shot_schedule() {
+    let (state, app, receiver) = app(Config::default());
+    tokio::spawn(server::run_scheduler(state));
+
+    let at = Utc::now() + TimeDelta::seconds(1);
+    let (status, _) = create(&app, json!({"host": "nas", "at": at})).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let receiver = tokio::task::spawn_blocking(move || {
+        let mut buf = [0; 200];
+        receiver.recv(&mut buf).unwrap()
+    });
+    assert_eq!(receiver.await.unwrap(), 102);
+
+    let mut last_wake = Value::Null;
+    for _ in 0..50 {
+        let (_, hosts) = send(&app, Request::get("/hosts").body(Body::empty()).unwrap()).await;
+        last_wake = hosts[0]["last_wake"].clone();
+        if !last_wake.is_null() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(last_wake["principal"], "scheduled");
+    assert_eq!(list(&app).await, json!([]));
+}
+
+async fn wake_later(app: &Router, query: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::post(format!("/wake?{query}"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    send(app, request).await
+}
+
+#[tokio::test]
+async fn delayed_wake() {
+    let path = std::env::temp_dir().join(format!(
+        "wakeonlan-delayed-wakes-{}.json",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let config = || Config {
+        schedules_file: Some(path.clone()),
+        default_host: Some("00:d8:61:ca:3a:18".to_owned()),
+        ..Config::default()
+    };
+    let (_, app, _receiver) = app(config());
+
+    let before = Utc::now();
+    let (status, later) = wake_later(&app, "delay=1200", json!({"host": "nas"})).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(later["host"], "nas");
+    let at: DateTime<Utc> = later["at"].as_str().unwrap().parse().unwrap();
+    assert!(at >= before + TimeDelta::seconds(1200));
+    assert!(at <= Utc::now() + TimeDelta::seconds(1200));
+    assert_eq!(later["next"], later["at"]);
+
+    let (status, at) = wake_later(&app, "at=2999-01-01T00:00:00Z&format=json", json!({})).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(at["host"], "00:d8:61:ca:3a:18");
+    assert_eq!(at["at"], "2999-01-01T00:00:00Z");
+
+    for (query, body) in [
+        ("at=2000-01-01T00:00:00Z", json!({})),
+        ("at=2999-01-01T00:00:00Z&delay=5", json!({})),
+        ("delay=18446744073709551615", json!({})),
+        ("delay=5", json!({"host": "nas", "wait_online": 60})),
+        ("delay=5", json!({"host": "nas", "dry_run": true})),
+        ("delay=5", json!({"host": "nas", "force": true})),
+        ("delay=5", json!({"host": "nas", "confirm": true})),
+        ("delay=5", json!({"host": "nas", "skip_if_online": false})),
+    ] {
+        let (status, _) = wake_later(&app, query, body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+    }
+    let (status, error) = wake_later(&app, "at=soon", json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{error}");
+    assert_eq!(list(&app).await, json!([later, at]));
+
+    // they're pending across a restart, and can be cancelled like any schedule
+    let (state, app, receiver) = self::app(config());
+    assert_eq!(list(&app).await, json!([later, at]));
+    let request = Request::delete(format!("/schedules/{}", later["id"].as_str().unwrap()))
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, request).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(list(&app).await, json!([at]));
+
+    // and woken like the host, or MAC, it's for
+    tokio::spawn(server::run_scheduler(state));
+    let (status, _) = wake_later(&app, "delay=1", json!({})).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let received = tokio::task::spawn_blocking(move || {
+        let mut buf = [0; 200];
+        let len = receiver.recv(&mut buf).unwrap();
+        (len, buf[6..12].to_vec())
+    });
+    assert_eq!(
+        received.await.unwrap(),
+        (102, vec![0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18])
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+async fn set_paused(app: &Router, id: &str, action: &str) -> (StatusCode, Value) {
+    let request = Request::post(format!("/schedules/{id}/{action}"))
+        .body(Body::empty())
+        .unwrap();
+    send(app, request).await
+}
+
+#[tokio::test]
+async fn pause_schedules() {
+    let path = std::env::temp_dir().join(format!(
+        "wakeonlan-paused-schedules-{}.json",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let config = || Config {
+        schedules_file: Some(path.clone()),
+        ..Config::default()
+    };
+    let (_, app, _receiver) = app(config());
+
+    let (_, nightly) = create(&app, json!({"host": "nas", "cron": "0 3 * * *"})).await;
+    assert_eq!(nightly["paused"], false);
+    let id = nightly["id"].as_str().unwrap();
+    let (status, paused) = set_paused(&app, id, "pause").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(paused["paused"], true);
+    assert_eq!(paused["next"], Value::Null);
+    assert_eq!(list(&app).await, json!([paused]));
+
+    // a restart keeps it paused
+    let (_, app, _receiver) = self::app(config());
+    assert_eq!(list(&app).await, json!([paused]));
+    let (status, resumed) = set_paused(&app, id, "resume").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(resumed["paused"], false);
+    assert!(resumed["next"].is_string());
+
+    assert_eq!(
+        set_paused(&app, "gone", "pause").await.0,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        set_paused(&app, "gone", "resume").await.0,
+        StatusCode::NOT_FOUND
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn skips_paused_schedule() {
+    let (state, app, receiver) = app(Config::default());
+    tokio::spawn(server::run_scheduler(state));
+
+    let at = Utc::now() + TimeDelta::seconds(1);
+    let (_, once) = create(&app, json!({"host": "nas", "at": at})).await;
+    let (status, _) = set_paused(&app, once["id"].as_str().unwrap(), "pause").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut last_wake = Value::Null;
+    for _ in 0..100 {
+        let (_, hosts) = send(&app, Request::get("/hosts").body(Body::empty()).unwrap()).await;
+        last_wake = hosts[0]["last_wake"].clone();
+        if !last_wake.is_null() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(last_wake["outcome"], "paused");
+    assert_eq!(last_wake["principal"], "scheduled");
+    assert_eq!(list(&app).await, json!([]));
+    receiver
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    assert!(receiver.recv(&mut [0; 200]).is_err());
+}
@@ -0,0 +1,130 @@
+#![cfg(feature = "server")]
+
+use std::collections::BTreeMap;
+use wakeonlan::{
+    config::{
+        self, Config, MqttConfig, RemoteSite, Site, SnmpConfig, Source, StaticHost, TelegramConfig,
+    },
+    secret::{Secret, REDACTED},
+};
+
+/// None of these may show up anywhere the config is formatted.
+const SENTINELS: [&str; 8] = [
+    "sentinel-token",
+    "sentinel-url-secret",
+    "sentinel-site-token",
+    "sentinel-community",
+    "sentinel-mqtt-password",
+    "sentinel-telegram-token",
+    "c0ffeec0ffee",
+    "c0:ff:ee:c0:ff:ee",
+];
+
+fn config() -> Config {
+    let host: StaticHost = serde_json::from_str(
+        r#"{"name": "nas", "mac": "a8:a1:59:0e:7b:02", "password": "c0:ff:ee:c0:ff:ee"}"#,
+    )
+    .unwrap();
+    assert!(host.password.is_some());
+    Config {
+        token: Some("sentinel-token".into()),
+        url_secret: Some("sentinel-url-secret".into()),
+        hosts: vec![host],
+        sites: vec![Site {
+            name: "parents".to_owned(),
+            broadcast: None,
+            interface: None,
+            source: None,
+            discovery: Vec::new(),
+            vlan: None,
+            remote: Some(RemoteSite {
+                url: "http://10.9.0.2:8090".to_owned(),
+                token: Some("sentinel-site-token".into()),
+            }),
+            ssh: None,
+        }],
+        snmp: Some(SnmpConfig {
+            switch: "192.0.2.1:161".parse().unwrap(),
+            community: "sentinel-community".into(),
+            timeout_ms: 1000,
+        }),
+        mqtt: Some(MqttConfig {
+            broker: "127.0.0.1:1883".to_owned(),
+            client_id: "wol".to_owned(),
+            username: Some("dashboard".to_owned()),
+            password: Some("sentinel-mqtt-password".into()),
+            prefix: "wol".to_owned(),
+            interval: 60,
+        }),
+        telegram: Some(TelegramConfig {
+            token: "sentinel-telegram-token".into(),
+            allowed_chats: vec![1],
+            api_url: "https://api.telegram.org".to_owned(),
+        }),
+        sources: BTreeMap::from([("token".to_owned(), Source::Env)]),
+        ..Config::default()
+    }
+}
+
+fn assert_no_sentinels(formatted: &str) {
+    for sentinel in SENTINELS {
+        assert!(!formatted.contains(sentinel), "`{sentinel}` in {formatted}");
+    }
+}
+
+#[test]
+fn debug_output() {
+    let config = config();
+    let formatted = format!("{config:?} {config:#?}");
+    assert_no_sentinels(&formatted);
+    assert!(formatted.contains(REDACTED));
+}
+
+#[test]
+fn effective_config() {
+    let effective = serde_json::to_string(&config().effective()).unwrap();
+    assert_no_sentinels(&effective);
+    assert!(effective.contains(REDACTED));
+    // the rest is still there
+    assert!(effective.contains("a8:a1:59:0e:7b:02"));
+}
+
+#[test]
+fn formatted_secret() {
+    let secret = Secret::new("sentinel-token".to_owned());
+    assert_eq!(format!("{secret}"), "<redacted>");
+    assert_eq!(format!("{secret:?}"), "<redacted>");
+    assert_eq!(format!("{:?}", Some(&secret)), "Some(<redacted>)");
+    assert_eq!(serde_json::to_string(&secret).unwrap(), r#""<redacted>""#);
+    assert_eq!(secret.expose(), "sentinel-token");
+}
+
+#[test]
+fn problems_with_the_config_file() {
+    let path = std::env::temp_dir().join(format!("wakeonlan-secrets-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+token = 1
+
+[[hosts]]
+name = "nas"
+mac = "not a mac"
+password = "c0:ff:ee:c0:ff:ee"
+
+[telegram]
+token = "sentinel-telegram-token"
+"#,
+    )
+    .unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    std::fs::remove_file(&path).unwrap();
+
+    let keys = (problems.iter())
+        .map(|problem| problem.key.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["hosts[0]", "telegram", "token"]);
+    let formatted = format!("{problems:?}");
+    assert_no_sentinels(&formatted);
+    assert!(formatted.contains(REDACTED));
+}
@@ -0,0 +1,95 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{ErrorResponse, Health},
+    config::{Config, SelfTestConfig},
+    discovery::{HostEntry, StaticDiscovery},
+    retry::RetryPolicy,
+    server::AppState,
+    MacAddress,
+};
+
+fn state(broadcast: &str) -> AppState {
+    AppState::new(Config {
+        broadcast: broadcast.parse().unwrap(),
+        self_test: Some(SelfTestConfig::default()),
+        retry: RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        },
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("192.168.1.20".parse().unwrap()),
+        mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]))
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+async fn health(app: &Router) -> Health {
+    let request = Request::get("/healthz").body(Body::empty()).unwrap();
+    let (status, body) = send(app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn passed() {
+    let app = test_app(state("127.0.0.1:9"));
+
+    let health = health(&app).await;
+    let self_test = health.self_test.unwrap();
+    assert!(self_test.passed);
+    assert_eq!(self_test.destination, "127.0.0.1:9".parse().unwrap());
+    assert_eq!(self_test.error, None);
+    assert!(health.problems.is_empty(), "{:?}", health.problems);
+}
+
+#[tokio::test]
+async fn failed() {
+    // the send socket is IPv4 only
+    let app = test_app(state("[::1]:9"));
+
+    let health = health(&app).await;
+    let self_test = health.self_test.unwrap();
+    assert!(!self_test.passed);
+    let error = self_test.error.unwrap();
+    assert!(error.starts_with("failed to send to [::1]:9: "), "{error}");
+    assert_eq!(health.problems, [format!("send self-test failed: {error}")]);
+
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"host": "nas"}"#))
+        .unwrap();
+    let (status, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let response: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert!(
+        response.error.ends_with(&format!(
+            "; the send self-test at startup failed already ({error}), see /healthz"
+        )),
+        "{}",
+        response.error
+    );
+}
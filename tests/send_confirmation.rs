@@ -0,0 +1,69 @@
+#![cfg(feature = "server")]
+#![cfg(target_os = "linux")]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use std::{net::UdpSocket, sync::Arc};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{Destination, WakeResponse},
+    config::{Config, SendConfirmationConfig},
+    server::{self, AppState},
+};
+
+/// Wakes a MAC with the real sockets, sending to `broadcast`.
+async fn wake(broadcast: &str, confirmation: bool) -> Destination {
+    let state = AppState::new(Config {
+        broadcast: broadcast.parse().unwrap(),
+        send_confirmation: confirmation.then_some(SendConfirmationConfig { wait_ms: 200 }),
+        ..Config::default()
+    })
+    .unwrap();
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"mac": "02:00:00:00:00:01"}"#))
+        .unwrap();
+    let response = server::router(Arc::new(state))
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let mut response: WakeResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response.destinations.len(), 1);
+    response.destinations.remove(0)
+}
+
+/// A port on localhost nothing listens on.
+fn closed_port() -> String {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.local_addr().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn reports_unreachable_ports() {
+    let destination = wake(&closed_port(), true).await;
+    assert!(destination.sent);
+    assert_eq!(
+        destination.reported_errors,
+        Some(vec!["port unreachable (from 127.0.0.1)".to_owned()])
+    );
+
+    let listening = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let destination = wake(&listening.local_addr().unwrap().to_string(), true).await;
+    assert_eq!(destination.reported_errors, Some(Vec::new()));
+}
+
+#[tokio::test]
+async fn only_when_asked_and_for_unicast() {
+    let destination = wake(&closed_port(), false).await;
+    assert!(destination.sent);
+    assert_eq!(destination.reported_errors, None);
+
+    // the directed broadcast of the loopback network
+    let destination = wake("127.255.255.255:9", true).await;
+    assert_eq!(destination.reported_errors, None);
+}
@@ -0,0 +1,189 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{receiving, test_app};
+use http_body_util::BodyExt;
+use std::{
+    net::{TcpListener, UdpSocket},
+    time::Duration,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{JobStatus, SequenceJob, StepState},
+    config::{Config, SequenceStep, StaticHost, WakeSequence},
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    verify::Strategy,
+    MacAddress,
+};
+
+const NAS: [u8; 6] = [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02];
+const PC: [u8; 6] = [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18];
+
+fn step(host: &str, ready: Strategy) -> SequenceStep {
+    SequenceStep {
+        host: host.to_owned(),
+        ready: Some(ready),
+        timeout: 5,
+    }
+}
+
+/// The `lab` sequence of the steps. Both hosts are at 127.0.0.1, so they're ready once `port` takes
+/// connections.
+fn lab(steps: Vec<SequenceStep>) -> (AppState, UdpSocket) {
+    let (state, receiver) = receiving(Config {
+        hosts: vec![
+            StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap(),
+            StaticHost::new("pc", ["00:d8:61:ca:3a:18"]).unwrap(),
+        ],
+        sequences: vec![WakeSequence {
+            name: "lab".to_owned(),
+            steps,
+            continue_on_failure: false,
+        }],
+        ..Config::default()
+    });
+    receiver
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let state = state.with_discovery(StaticDiscovery(
+        [NAS, PC]
+            .into_iter()
+            .map(|mac| HostEntry {
+                name: "localhost".to_owned(),
+                ip: Some("127.0.0.1".parse().unwrap()),
+                mac: MacAddress(mac),
+                named_by: None,
+                state: None,
+                vlan: None,
+            })
+            .collect(),
+    ));
+    (state, receiver)
+}
+
+async fn start(app: &Router, body: Option<&str>) -> (StatusCode, Option<SequenceJob>) {
+    let request = Request::post("/wake-sequence/lab");
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_owned())),
+        None => request.body(Body::empty()),
+    };
+    let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).ok())
+}
+
+async fn finished(app: &Router, id: &str) -> SequenceJob {
+    for _ in 0..100 {
+        let request = Request::get(format!("/jobs/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let job: SequenceJob = serde_json::from_slice(&body).unwrap();
+        if job.status != JobStatus::Running {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("sequence didn't finish");
+}
+
+/// The MACs of the packets that arrived, in order.
+fn received(receiver: &UdpSocket) -> Vec<[u8; 6]> {
+    let mut macs = Vec::new();
+    let mut buf = [0; 200];
+    while let Ok(len) = receiver.recv(&mut buf) {
+        assert_eq!(len, 102);
+        macs.push(buf[6..12].try_into().unwrap());
+    }
+    macs
+}
+
+#[tokio::test]
+async fn in_order() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (state, receiver) = lab(vec![
+        step("nas", Strategy::Tcp(port)),
+        step("pc", Strategy::Tcp(port)),
+    ]);
+    let app = test_app(state);
+
+    let (status, job) = start(&app, None).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let job = finished(&app, &job.unwrap().id).await;
+    assert_eq!(job.status, JobStatus::Succeeded);
+    assert_eq!(job.current_step, None);
+    assert!(job.steps.iter().all(|step| step.state == StepState::Ready));
+    assert_eq!(received(&receiver), [NAS, PC]);
+}
+
+#[tokio::test]
+async fn failed_step() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let steps = vec![
+        step("storage", Strategy::Tcp(port)),
+        step("pc", Strategy::Tcp(port)),
+    ];
+
+    // a failed step stops the sequence
+    let (state, receiver) = lab(steps.clone());
+    let app = test_app(state);
+    let (_, job) = start(&app, None).await;
+    let job = finished(&app, &job.unwrap().id).await;
+    assert_eq!(job.status, JobStatus::Failed);
+    assert_eq!(job.steps[0].state, StepState::Failed);
+    assert_eq!(
+        job.steps[0].error.as_deref(),
+        Some("host `storage` not found")
+    );
+    assert_eq!(job.steps[1].state, StepState::Skipped);
+    assert!(received(&receiver).is_empty());
+
+    // unless it's asked to go on
+    let (state, receiver) = lab(steps);
+    let app = test_app(state);
+    let (_, job) = start(&app, Some(r#"{"continue_on_failure": true}"#)).await;
+    let job = finished(&app, &job.unwrap().id).await;
+    assert_eq!(job.status, JobStatus::Failed);
+    assert_eq!(job.steps[0].state, StepState::Failed);
+    assert_eq!(job.steps[1].state, StepState::Ready);
+    assert_eq!(received(&receiver), [PC]);
+}
+
+#[tokio::test]
+async fn not_ready() {
+    // nothing listens there once the listener is gone
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let (state, _receiver) = lab(vec![SequenceStep {
+        timeout: 1,
+        ..step("nas", Strategy::Tcp(port))
+    }]);
+    let app = test_app(state);
+    let (_, job) = start(&app, None).await;
+    let job = finished(&app, &job.unwrap().id).await;
+    assert_eq!(job.steps[0].state, StepState::Failed);
+    assert_eq!(job.steps[0].error.as_deref(), Some("not ready after 1s"));
+
+    let request = Request::post("/wake-sequence/nope")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
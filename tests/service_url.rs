@@ -0,0 +1,154 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use std::net::TcpListener;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::WakeResponse,
+    config::{self, Config, StaticHost},
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    verify::Strategy,
+    MacAddress,
+};
+
+const JELLYFIN: &str = "http://192.168.1.20:8096/";
+
+/// `nas` is up at 127.0.0.1, `tv` isn't discovered so it's never up, and `pc` has no service.
+fn state(port: u16) -> AppState {
+    let with_service = |name, mac| StaticHost {
+        service_url: Some(JELLYFIN.to_owned()),
+        ..StaticHost::new(name, [mac]).unwrap()
+    };
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![
+            with_service("nas", "a8:a1:59:0e:7b:02"),
+            with_service("tv", "02:00:00:00:00:01"),
+            StaticHost::new("pc", ["00:d8:61:ca:3a:18"]).unwrap(),
+        ],
+        verify: vec![Strategy::Tcp(port)],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]))
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String, Option<String>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let location = response
+        .headers()
+        .get(header::LOCATION)
+        .map(|value| value.to_str().unwrap().to_owned());
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap(), location)
+}
+
+async fn wake(app: &Router, body: &str) -> WakeResponse {
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    let (status, body, _) = send(app, request).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{body}");
+    serde_json::from_str(&body).unwrap()
+}
+
+#[tokio::test]
+async fn in_the_response() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let app = test_app(state(listener.local_addr().unwrap().port()));
+
+    let woken = wake(&app, r#"{"host": "nas"}"#).await;
+    assert_eq!(woken.service_url.as_deref(), Some(JELLYFIN));
+    assert_eq!(woken.online, None);
+
+    let woken = wake(&app, r#"{"host": "nas", "wait_online": 5}"#).await;
+    assert_eq!(woken.online, Some(true));
+
+    let woken = wake(&app, r#"{"host": "pc"}"#).await;
+    assert_eq!(woken.service_url, None);
+}
+
+#[tokio::test]
+async fn page_opens_it() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let app = test_app(state(port));
+
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("host=nas"))
+        .unwrap();
+    let (status, page, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(
+        page.contains(r#"<meta http-equiv="refresh" content="2;url=hosts/nas/open" />"#),
+        "{page}"
+    );
+    assert!(
+        page.contains(&format!(r#"<a href="{JELLYFIN}">"#)),
+        "{page}"
+    );
+
+    let open = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+    let (status, _, location) = send(&app, open("/hosts/nas/open")).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert_eq!(location.as_deref(), Some(JELLYFIN));
+
+    let (status, page, _) = send(&app, open("/hosts/tv/open?attempt=3")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("url=open?attempt=4"), "{page}");
+    let (status, page, _) = send(&app, open("/hosts/tv/open?attempt=89")).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert!(page.contains("tv didn't come up"), "{page}");
+
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("host=pc"))
+        .unwrap();
+    let (status, page, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(!page.contains("refresh"), "{page}");
+    let (status, _, _) = send(&app, open("/hosts/pc/open")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn only_web_urls() {
+    let path =
+        std::env::temp_dir().join(format!("wakeonlan-service-url-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+[[hosts]]
+name = "nas"
+mac = "a8:a1:59:0e:7b:02"
+service_url = "javascript:alert(1)"
+"#,
+    )
+    .unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    assert_eq!(problems[0].key, "hosts[0]");
+    assert_eq!(
+        problems[0].message,
+        "service url `javascript:alert(1)` for host `nas` isn't an http:// or https:// url"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
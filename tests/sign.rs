@@ -0,0 +1,147 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{receiving, test_app};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use tower::ServiceExt;
+use wakeonlan::{
+    config::{Config, StaticHost},
+    sign::{hmac_sha256, sha256, sign_link, verify_link, CLOCK_SKEW},
+};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[test]
+fn sha256_vectors() {
+    assert_eq!(
+        hex(&sha256(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hex(&sha256(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        )),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+}
+
+#[test]
+fn hmac_vectors() {
+    // from RFC 4231
+    assert_eq!(
+        hex(&hmac_sha256(&[0x0b; 20], b"Hi There")),
+        "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+    );
+    assert_eq!(
+        hex(&hmac_sha256(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First"
+        )),
+        "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+    );
+}
+
+#[test]
+fn verify() {
+    let sig = sign_link(b"secret", "pc", 1000).unwrap();
+    assert!(verify_link(b"secret", "pc", 1000, &sig, 900));
+    assert!(verify_link(b"secret", "pc", 1000, &sig, 1000 + CLOCK_SKEW));
+    assert!(!verify_link(b"secret", "pc", 1000, &sig, 1001 + CLOCK_SKEW));
+    assert!(!verify_link(b"secret", "nas", 1000, &sig, 900));
+    assert!(!verify_link(b"secret", "pc", 2000, &sig, 900));
+    assert!(!verify_link(b"other", "pc", 1000, &sig, 900));
+    assert!(!verify_link(b"secret", "pc", 1000, &sig[1..], 900));
+    assert!(!verify_link(b"secret", "pc", 1000, &sig[2..], 900));
+    assert!(verify_link(b"secret", "pc", 1000, &sig.to_uppercase(), 900));
+}
+
+#[test]
+fn hosts_with_line_breaks_are_not_signed() {
+    assert_eq!(sign_link(b"secret", "pc\n1000", 2000), None);
+    let sig = sign_link(b"secret", "pc", 1000).unwrap();
+    assert!(!verify_link(b"secret", "pc\n1000", 1000, &sig, 900));
+}
+
+fn config() -> Config {
+    Config {
+        token: Some("hunter2".into()),
+        url_secret: Some("secret".into()),
+        hosts: vec![StaticHost::new("pc", ["00:11:22:33:44:55"]).unwrap()],
+        ..Config::default()
+    }
+}
+
+async fn get(app: &Router, uri: &str) -> StatusCode {
+    let request = Request::get(uri).body(Body::empty()).unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn mint_and_wake() {
+    let (state, receiver) = receiving(config());
+    let app = test_app(state);
+
+    let mint = |body: &'static str, token: &'static str| {
+        let request = Request::post("/wake/links")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::HOST, "wol.example")
+            .body(Body::from(body))
+            .unwrap();
+        app.clone().oneshot(request)
+    };
+    let response = mint(r#"{"host": "pc", "expires_in": 3600}"#, "wrong")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = mint(r#"{"host": "nas", "expires_in": 3600}"#, "hunter2")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = mint(r#"{"host": "pc", "expires_in": 3600}"#, "hunter2")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let link: Value = serde_json::from_slice(&body).unwrap();
+    let path = link["path"].as_str().unwrap();
+    assert_eq!(link["url"], format!("http://wol.example{path}"));
+    assert!(path.starts_with("/wake?host=pc&exp="));
+
+    assert_eq!(get(&app, path).await, StatusCode::ACCEPTED);
+    let mut buf = [0; 200];
+    assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+    assert_eq!(buf[6..12], [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+
+    let tampered = path.replace("host=pc", "host=nas");
+    assert_eq!(get(&app, &tampered).await, StatusCode::FORBIDDEN);
+    let (unsigned, _) = path.split_once("&sig=").unwrap();
+    assert_eq!(get(&app, unsigned).await, StatusCode::FORBIDDEN);
+    assert_eq!(get(&app, "/wake").await, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn expired_link() {
+    let (state, _receiver) = receiving(config());
+    let app = test_app(state);
+    let exp = chrono::Utc::now().timestamp() - CLOCK_SKEW - 10;
+    let sig = sign_link(b"secret", "pc", exp).unwrap();
+    let status = get(&app, &format!("/wake?host=pc&exp={exp}&sig={sig}")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // within the skew it's still fine
+    let exp = chrono::Utc::now().timestamp() - 10;
+    let sig = sign_link(b"secret", "pc", exp).unwrap();
+    let status = get(&app, &format!("/wake?host=pc&exp={exp}&sig={sig}")).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+}
@@ -0,0 +1,212 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+};
+use common::{post, test_app};
+use http_body_util::BodyExt;
+use std::{net::UdpSocket, time::Duration};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{ErrorResponse, SiteHosts, WakeResponse},
+    config::{Config, RemoteSite, Site, StaticHost},
+    discovery::StaticDiscovery,
+    server::AppState,
+};
+
+fn receiver() -> UdpSocket {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    receiver
+}
+
+fn in_site(site: &str, host: StaticHost) -> StaticHost {
+    StaticHost {
+        site: Some(site.to_owned()),
+        ..host
+    }
+}
+
+/// A server at the other site that knows `nas` and needs a token, at the returned URL.
+async fn remote_server(receiver: &UdpSocket) -> String {
+    let state = AppState::new(Config {
+        broadcast: receiver.local_addr().unwrap(),
+        token: Some("remote-secret".into()),
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        ..Config::default()
+    })
+    .unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        axum::serve(listener, test_app(state)).await.unwrap();
+    });
+    url
+}
+
+fn remote_site(name: &str, url: &str, token: &str) -> Site {
+    Site {
+        name: name.to_owned(),
+        broadcast: None,
+        interface: None,
+        source: None,
+        discovery: Vec::new(),
+        vlan: None,
+        remote: Some(RemoteSite {
+            url: url.to_owned(),
+            token: Some(token.into()),
+        }),
+        ssh: None,
+    }
+}
+
+fn assert_received(receiver: &UdpSocket, mac: [u8; 6]) {
+    let mut buf = [0; 200];
+    let len = receiver.recv(&mut buf).unwrap();
+    assert_eq!(len, 102);
+    assert_eq!(buf[6..12], mac);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn remote_and_local_sites() {
+    let remote_receiver = receiver();
+    let url = remote_server(&remote_receiver).await;
+    let here = receiver();
+    let lab = receiver();
+    let app = test_app(
+        AppState::new(Config {
+            broadcast: here.local_addr().unwrap(),
+            sites: vec![
+                remote_site("parents", &url, "remote-secret"),
+                remote_site("elsewhere", &url, "wrong"),
+                remote_site("gone", "http://127.0.0.1:1", "remote-secret"),
+                Site {
+                    name: "lab".to_owned(),
+                    broadcast: Some(lab.local_addr().unwrap()),
+                    interface: None,
+                    source: None,
+                    discovery: Vec::new(),
+                    vlan: None,
+                    remote: None,
+                    ssh: None,
+                },
+            ],
+            hosts: vec![
+                StaticHost::new("pc", ["00:d8:61:ca:3a:18"]).unwrap(),
+                in_site(
+                    "parents",
+                    StaticHost::new("nas", ["11:22:33:44:55:66"]).unwrap(),
+                ),
+                in_site(
+                    "elsewhere",
+                    StaticHost::new("laptop", ["11:22:33:44:55:77"]).unwrap(),
+                ),
+                in_site(
+                    "gone",
+                    StaticHost::new("old", ["11:22:33:44:55:88"]).unwrap(),
+                ),
+                in_site(
+                    "lab",
+                    StaticHost::new("rig", ["11:22:33:44:55:99"]).unwrap(),
+                ),
+            ],
+            ..Config::default()
+        })
+        .unwrap()
+        .with_discovery(StaticDiscovery(Vec::new())),
+    );
+
+    // the server there wakes it, with the MAC it knows
+    let (status, body) = post(&app, "/wake", r#"{"host": "nas"}"#).await;
+    assert_eq!(
+        status,
+        StatusCode::ACCEPTED,
+        "{}",
+        String::from_utf8_lossy(&body)
+    );
+    let response: WakeResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response.site.as_deref(), Some("parents"));
+    assert_eq!(response.macs, ["a8:a1:59:0e:7b:02"]);
+    assert!(response.destinations[0].sent);
+    assert_received(&remote_receiver, [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+
+    // what the server there answered is passed on, with the site it's from
+    let (status, body) = post(&app, "/api/v1/wake", r#"{"host": "laptop"}"#).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error.site.as_deref(), Some("elsewhere"));
+    assert!(
+        error.error.starts_with("site `elsewhere`: "),
+        "{}",
+        error.error
+    );
+
+    // not reaching it at all is a failure of its own
+    let (status, body) = post(&app, "/api/v1/wake", r#"{"host": "old"}"#).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error.site.as_deref(), Some("gone"));
+    assert!(
+        error
+            .error
+            .starts_with("can't reach the server of site `gone`"),
+        "{}",
+        error.error
+    );
+
+    // a local site has its own broadcast address
+    let (status, _) = post(&app, "/wake", r#"{"host": "rig"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_received(&lab, [0x11, 0x22, 0x33, 0x44, 0x55, 0x99]);
+    let (status, _) = post(&app, "/wake", r#"{"host": "pc"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_received(&here, [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]);
+
+    let (status, body) = post(&app, "/wake/batch", r#"{"hosts": ["nas", "old"]}"#).await;
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["results"][0]["site"], "parents");
+    assert_eq!(body["results"][0]["sent"], true);
+    assert_eq!(body["results"][1]["site"], "gone");
+    assert_eq!(body["results"][1]["sent"], false);
+    assert_received(&remote_receiver, [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+
+    let request = Request::get("/hosts?group=site")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let groups: Vec<SiteHosts> = serde_json::from_slice(&body).unwrap();
+    let summary = groups
+        .iter()
+        .map(|group| {
+            let hosts = group.hosts.iter().map(|host| host.name.as_str());
+            (
+                group.site.as_deref(),
+                group.remote,
+                hosts.collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        [
+            (None, false, vec!["pc"]),
+            (Some("parents"), true, vec!["nas"]),
+            (Some("elsewhere"), true, vec!["laptop"]),
+            (Some("gone"), true, vec!["old"]),
+            (Some("lab"), false, vec!["rig"]),
+        ]
+    );
+
+    let request = Request::get("/hosts?group=color")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,276 @@
+#![cfg(feature = "server")]
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    config::{Config, Site, SnmpConfig},
+    discovery::{
+        snmp::{BRIDGE_FDB_PORT, Q_BRIDGE_FDB_PORT},
+        Composite, HostDiscovery, HostEntry, Snmp, StaticDiscovery,
+    },
+    server::{self, AppState},
+    MacAddress,
+};
+
+const NAS: [u8; 6] = [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02];
+const PC: [u8; 6] = [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18];
+const SWITCH: [u8; 6] = [0x3c, 0x7c, 0x3f, 0x1d, 0xaa, 0x09];
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    assert!(content.len() < 0x10000);
+    let mut encoded = vec![tag];
+    if content.len() < 0x80 {
+        encoded.push(content.len() as u8);
+    } else {
+        encoded.extend([0x82, (content.len() >> 8) as u8, content.len() as u8]);
+    }
+    encoded.extend_from_slice(content);
+    encoded
+}
+
+fn integer(value: i32) -> Vec<u8> {
+    tlv(0x02, &value.to_be_bytes())
+}
+
+fn oid(arcs: &[u32]) -> Vec<u8> {
+    let mut content = vec![(arcs[0] * 40 + arcs[1]) as u8];
+    for &arc in &arcs[2..] {
+        for shift in [28, 21, 14, 7] {
+            if arc >> shift != 0 {
+                content.push((arc >> shift) as u8 & 0x7f | 0x80);
+            }
+        }
+        content.push(arc as u8 & 0x7f);
+    }
+    tlv(0x06, &content)
+}
+
+/// The tag, the content and what comes after it, for the short lengths the server sends.
+fn read(data: &[u8]) -> (u8, &[u8], &[u8]) {
+    assert!(data[1] < 0x80);
+    let len = usize::from(data[1]);
+    (data[0], &data[2..2 + len], &data[2 + len..])
+}
+
+fn read_oid(content: &[u8]) -> Vec<u32> {
+    let mut arcs = vec![u32::from(content[0] / 40), u32::from(content[0] % 40)];
+    let mut arc = 0;
+    for &byte in &content[1..] {
+        arc = arc << 7 | u32::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            arcs.push(arc);
+            arc = 0;
+        }
+    }
+    arcs
+}
+
+/// Answers GETBULKs with the community from the table, which has to be sorted.
+fn agent(community: &'static str, table: Vec<(Vec<u32>, i32)>) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    std::thread::spawn(move || loop {
+        let mut buf = [0; 1500];
+        let (len, peer) = socket.recv_from(&mut buf).unwrap();
+        let (_, message, _) = read(&buf[..len]);
+        let (_, _version, rest) = read(message);
+        let (_, sent_community, rest) = read(rest);
+        // like a switch, the wrong community isn't answered
+        if sent_community != community.as_bytes() {
+            continue;
+        }
+        let (pdu_tag, pdu, _) = read(rest);
+        assert_eq!(pdu_tag, 0xa5);
+        let (_, request_id, rest) = read(pdu);
+        let (_, _non_repeaters, rest) = read(rest);
+        let (_, repetitions, rest) = read(rest);
+        let (_, varbinds, _) = read(rest);
+        let (_, varbind, _) = read(varbinds);
+        let (_, after, _) = read(varbind);
+        let after = read_oid(after);
+
+        let mut answers = table
+            .iter()
+            .filter(|(oid, _)| *oid > after)
+            .take(usize::from(*repetitions.last().unwrap()))
+            .map(|(arcs, port)| tlv(0x30, &[oid(arcs), integer(*port)].concat()))
+            .collect::<Vec<_>>();
+        if answers.is_empty() {
+            answers.push(tlv(0x30, &[oid(&after), vec![0x82, 0]].concat()));
+        }
+        let pdu = [
+            tlv(0x02, request_id),
+            integer(0),
+            integer(0),
+            tlv(0x30, &answers.concat()),
+        ];
+        let response = tlv(
+            0x30,
+            &[
+                integer(1),
+                tlv(0x04, community.as_bytes()),
+                tlv(0xa2, &pdu.concat()),
+            ]
+            .concat(),
+        );
+        socket.send_to(&response, peer).unwrap();
+    });
+    addr
+}
+
+fn fdb_row(column: &[u32], vlan: Option<u32>, mac: [u8; 6], port: i32) -> (Vec<u32>, i32) {
+    let mut oid = column.to_vec();
+    oid.extend(vlan);
+    oid.extend(mac.map(u32::from));
+    (oid, port)
+}
+
+fn snmp(switch: SocketAddr, community: &str) -> Snmp {
+    Snmp::new(&SnmpConfig {
+        switch,
+        community: community.into(),
+        timeout_ms: 200,
+    })
+}
+
+fn entry(mac: [u8; 6], vlan: Option<u16>) -> HostEntry {
+    HostEntry {
+        name: MacAddress(mac).to_string(),
+        ip: None,
+        mac: MacAddress(mac),
+        named_by: None,
+        state: None,
+        vlan,
+    }
+}
+
+#[test]
+fn q_bridge() {
+    let mut table = (0..40)
+        .map(|i| fdb_row(Q_BRIDGE_FDB_PORT, Some(30), [2, 0, 0, 0, 0, i], 7))
+        .collect::<Vec<_>>();
+    table.extend([
+        // the switch itself
+        fdb_row(Q_BRIDGE_FDB_PORT, Some(1), SWITCH, 0),
+        fdb_row(Q_BRIDGE_FDB_PORT, Some(10), NAS, 3),
+        fdb_row(Q_BRIDGE_FDB_PORT, Some(20), PC, 5),
+        // the next column isn't part of it
+        fdb_row(&[1, 3, 6, 1, 2, 1, 17, 7, 1, 2, 2, 1, 3], Some(10), NAS, 3),
+    ]);
+    table.sort();
+    let entries = snmp(agent("secret", table), "secret").discover().unwrap();
+    // more than one GETBULK's worth
+    assert_eq!(entries.len(), 42);
+    assert_eq!(entries[0], entry(NAS, Some(10)));
+    assert_eq!(entries[1], entry(PC, Some(20)));
+    assert_eq!(entries[41], entry([2, 0, 0, 0, 0, 39], Some(30)));
+}
+
+#[test]
+fn bridge_without_vlans() {
+    let table = vec![
+        fdb_row(BRIDGE_FDB_PORT, None, PC, 5),
+        fdb_row(BRIDGE_FDB_PORT, None, NAS, 3),
+    ];
+    let entries = snmp(agent("public", table), "public").discover().unwrap();
+    assert_eq!(entries, [entry(PC, None), entry(NAS, None)]);
+}
+
+#[test]
+fn not_answering_breaks_nothing() {
+    let table = vec![fdb_row(BRIDGE_FDB_PORT, None, NAS, 3)];
+    let wrong = snmp(agent("secret", table), "public");
+    assert_eq!(wrong.discover().unwrap(), []);
+
+    let neighbor = HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("192.168.10.20".parse().unwrap()),
+        ..entry(NAS, None)
+    };
+    let composite = Composite(vec![
+        Box::new(StaticDiscovery(vec![neighbor.clone()])),
+        Box::new(wrong),
+    ]);
+    assert_eq!(composite.discover().unwrap(), [neighbor]);
+}
+
+#[test]
+fn merged_into_the_neighbors() {
+    let neighbor = HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("192.168.10.20".parse().unwrap()),
+        ..entry(NAS, None)
+    };
+    let composite = Composite(vec![
+        Box::new(StaticDiscovery(vec![neighbor.clone()])),
+        Box::new(StaticDiscovery(vec![
+            entry(NAS, Some(10)),
+            entry(PC, Some(20)),
+        ])),
+    ]);
+    let with_vlan = HostEntry {
+        vlan: Some(10),
+        ..neighbor
+    };
+    assert_eq!(
+        composite.discover().unwrap(),
+        [with_vlan.clone(), entry(PC, Some(20))]
+    );
+
+    // the same the other way around
+    let composite = Composite(vec![
+        Box::new(StaticDiscovery(vec![entry(NAS, Some(10))])),
+        Box::new(StaticDiscovery(vec![HostEntry {
+            vlan: None,
+            ..with_vlan.clone()
+        }])),
+    ]);
+    assert_eq!(composite.discover().unwrap(), [with_vlan]);
+}
+
+#[tokio::test]
+async fn woken_in_the_site_of_its_vlan() {
+    let lab = UdpSocket::bind("127.0.0.1:0").unwrap();
+    lab.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let state = AppState::new(Config {
+        // nothing should be sent here
+        broadcast: "127.0.0.1:1".parse().unwrap(),
+        sites: vec![Site {
+            name: "lab".to_owned(),
+            broadcast: Some(lab.local_addr().unwrap()),
+            interface: None,
+            source: None,
+            discovery: Vec::new(),
+            vlan: Some(10),
+            remote: None,
+            ssh: None,
+        }],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("192.168.10.20".parse().unwrap()),
+        ..entry(NAS, Some(10))
+    }]));
+    let app = server::router(Arc::new(state));
+
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"host": "nas"}"#))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let mut buf = [0; 200];
+    let len = lab.recv(&mut buf).unwrap();
+    assert_eq!(len, 102);
+    assert_eq!(buf[6..12], NAS);
+}
@@ -0,0 +1,222 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use std::os::unix::fs::PermissionsExt;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{ErrorResponse, Host, RelayFailure, WakeOutcome, WakeResponse},
+    config::{self, Config, Site, SshSite, StaticHost},
+    discovery::StaticDiscovery,
+    server::AppState,
+};
+
+/// Stands in for ssh: it notes its arguments in `args` next to it, and the host decides how it
+/// goes. Any other host runs the command here.
+const FAKE_SSH: &str = r#"#!/bin/sh
+echo "$@" >> "$(dirname "$0")/args"
+for command; do :; done
+host=""
+after=""
+for arg; do
+    if [ -n "$after" ] && [ -z "$host" ]; then host="$arg"; fi
+    if [ "$arg" = "--" ]; then after=1; fi
+done
+case "$host" in
+    denied) echo "pi@denied: Permission denied (publickey)." >&2; exit 255 ;;
+    gone) echo "ssh: connect to host gone port 22: Connection refused" >&2; exit 255 ;;
+    slow) sleep 10 ;;
+    *) exec sh -c "$command" ;;
+esac
+"#;
+
+fn ssh_site(name: &str, ssh: SshSite) -> Site {
+    Site {
+        name: name.to_owned(),
+        broadcast: None,
+        interface: None,
+        source: None,
+        discovery: Vec::new(),
+        vlan: None,
+        remote: None,
+        ssh: Some(ssh),
+    }
+}
+
+fn ssh(host: &str, command: &str) -> SshSite {
+    SshSite {
+        host: host.to_owned(),
+        port: None,
+        user: None,
+        key: None,
+        command: command.to_owned(),
+        timeout: 20,
+    }
+}
+
+fn state() -> AppState {
+    let sites = vec![
+        ssh_site(
+            "cabin",
+            SshSite {
+                port: Some(2222),
+                user: Some("pi".to_owned()),
+                key: Some("/etc/wakeonlan/id_ed25519".into()),
+                ..ssh("pi.cabin", "echo woke {mac}")
+            },
+        ),
+        ssh_site("broken", ssh("pi", "echo no {mac} >&2; exit 3")),
+        ssh_site("denied", ssh("denied", "wakeonlan wake --mac {mac}")),
+        ssh_site("gone", ssh("gone", "wakeonlan wake --mac {mac}")),
+        ssh_site(
+            "slow",
+            SshSite {
+                timeout: 1,
+                ..ssh("slow", "wakeonlan wake --mac {mac}")
+            },
+        ),
+    ];
+    let hosts = (sites.iter().enumerate())
+        .map(|(index, site)| StaticHost {
+            site: Some(site.name.clone()),
+            ..StaticHost::new(&site.name, [format!("02:00:00:00:00:0{index}").as_str()]).unwrap()
+        })
+        .collect();
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        sites,
+        hosts,
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(Vec::new()))
+}
+
+async fn wake(app: &Router, body: &str) -> (StatusCode, Vec<u8>) {
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+// all in one, since the fake ssh is found through `PATH`
+#[tokio::test(flavor = "multi_thread")]
+async fn wakes_over_ssh() {
+    let dir = std::env::temp_dir().join(format!("wakeonlan-ssh-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let fake = dir.join("ssh");
+    std::fs::write(&fake, FAKE_SSH).unwrap();
+    std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut paths = vec![dir.clone()];
+    paths.extend(std::env::split_paths(&path));
+    std::env::set_var("PATH", std::env::join_paths(paths).unwrap());
+    let args = || std::fs::read_to_string(dir.join("args")).unwrap_or_default();
+    let app = test_app(state());
+
+    let (status, body) = wake(&app, r#"{"host": "cabin", "dry_run": true}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let woken: WakeResponse = serde_json::from_slice(&body).unwrap();
+    assert!(woken.dry_run);
+    assert_eq!(args(), "");
+
+    let (status, body) = wake(&app, r#"{"host": "cabin"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let woken: WakeResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(woken.site.as_deref(), Some("cabin"));
+    assert_eq!(woken.macs, ["02:00:00:00:00:00"]);
+    assert!(woken.destinations.is_empty());
+    assert_eq!(
+        args(),
+        "-o BatchMode=yes -o ConnectTimeout=20 -p 2222 -l pi -i /etc/wakeonlan/id_ed25519 \
+         -o IdentitiesOnly=yes -- pi.cabin echo woke 02:00:00:00:00:00\n"
+    );
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let hosts = response.into_body().collect().await.unwrap().to_bytes();
+    let hosts: Vec<Host> = serde_json::from_slice(&hosts).unwrap();
+    let cabin = hosts.iter().find(|host| host.name == "cabin").unwrap();
+    assert_eq!(cabin.last_wake.as_ref().unwrap().outcome, WakeOutcome::Sent);
+
+    for (host, status, relay, message) in [
+        (
+            "broken",
+            StatusCode::BAD_GATEWAY,
+            RelayFailure::SshExit,
+            "the command of site `broken` exited with status 3: no 02:00:00:00:00:01",
+        ),
+        (
+            "denied",
+            StatusCode::BAD_GATEWAY,
+            RelayFailure::SshAuth,
+            "can't log in to site `denied` over ssh: pi@denied: Permission denied (publickey).",
+        ),
+        (
+            "gone",
+            StatusCode::BAD_GATEWAY,
+            RelayFailure::Unreachable,
+            "can't reach the server of site `gone`: ssh: connect to host gone port 22: Connection refused",
+        ),
+        (
+            "slow",
+            StatusCode::GATEWAY_TIMEOUT,
+            RelayFailure::SshTimeout,
+            "ssh to site `slow` didn't finish within 1s",
+        ),
+    ] {
+        let (got, body) = wake(&app, &format!(r#"{{"host": "{host}"}}"#)).await;
+        assert_eq!(got, status, "{host}");
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error.site.as_deref(), Some(host));
+        assert_eq!(error.relay, Some(relay), "{host}");
+        assert_eq!(error.error, message);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ssh_config_problems() {
+    let path = std::env::temp_dir().join(format!("wakeonlan-ssh-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+[[sites]]
+name = "cabin"
+ssh = { host = "-oProxyCommand=evil", user = "pi@cabin", command = "wake {host}", timeout = 0 }
+[[sites]]
+name = "both"
+broadcast = "10.0.0.255"
+remote = { url = "http://10.9.0.1:8090" }
+ssh = { host = "pi" }
+"#,
+    )
+    .unwrap();
+    let problems = config::check_file(&path).unwrap_err().to_string();
+    let lines = problems.lines().collect::<Vec<_>>();
+    let file = path.display();
+    assert_eq!(
+        lines,
+        [
+            "7 problems".to_owned(),
+            format!("  {file}: sites[0].ssh.host: not a host to log in to (found \"-oProxyCommand=evil\")"),
+            format!("  {file}: sites[0].ssh.user: not a user to log in as (found \"pi@cabin\")"),
+            format!("  {file}: sites[0].ssh.command: has to have `{{mac}}`, and nothing else is filled in (found \"wake {{host}}\")"),
+            format!("  {file}: sites[0].ssh.timeout: has to be at least a second (found 0)"),
+            format!("  {file}: sites[1].ssh.host: a site is woken either by its `remote` server or over `ssh`, not both (found \"pi\")"),
+            format!("  {file}: sites[1].ssh.host: the machine there sends the packets, a site woken over ssh can't have its own broadcast, interface or discovery (found \"pi\")"),
+            format!("  {file}: sites[1].remote.url: the server there sends the packets, a remote site can't have its own broadcast, interface or discovery (found \"http://10.9.0.1:8090\")"),
+        ]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
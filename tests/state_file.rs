@@ -0,0 +1,203 @@
+#![cfg(feature = "server")]
+
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use wakeonlan::{config::Config, server::AppState};
+
+/// An empty directory of its own for each test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wakeonlan-state-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Copies `fixtures/state/{fixture}` to `path`.
+fn copy_fixture(fixture: &str, path: &Path) -> String {
+    let contents = std::fs::read_to_string(format!(
+        "{}/tests/fixtures/state/{fixture}",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap();
+    std::fs::write(path, &contents).unwrap();
+    contents
+}
+
+fn read_json(path: &Path) -> Value {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn registry_from_version_1() {
+    let dir = temp_dir("registry-v1");
+    let path = dir.join("hosts.json");
+    let original = copy_fixture("registry_v1.json", &path);
+
+    AppState::new(Config {
+        registry: Some(path.clone()),
+        ..Config::default()
+    })
+    .unwrap();
+
+    assert_eq!(
+        read_json(&path),
+        json!({"version": 2, "hosts": [
+            {"name": "pc", "macs": ["00:d8:61:ca:3a:18"]},
+            {"name": "nas", "macs": ["a8:a1:59:0e:7b:02"]},
+        ]})
+    );
+    let backup = dir.join("hosts.json.v1.bak");
+    assert_eq!(std::fs::read_to_string(backup).unwrap(), original);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn registry_from_version_1_with_several_macs() {
+    let dir = temp_dir("registry-v1-macs");
+    let path = dir.join("hosts.json");
+    copy_fixture("registry_v1_macs.json", &path);
+
+    AppState::new(Config {
+        registry: Some(path.clone()),
+        ..Config::default()
+    })
+    .unwrap();
+
+    assert_eq!(
+        read_json(&path),
+        json!({"version": 2, "hosts": [{
+            "name": "workstation",
+            "macs": ["00:11:22:33:44:55", "3c:7c:3f:1d:aa:09"],
+            "location": "office",
+        }]})
+    );
+    assert!(dir.join("hosts.json.v1.bak").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn current_registry_is_left_alone() {
+    let dir = temp_dir("registry-v2");
+    let path = dir.join("hosts.json");
+    let original = copy_fixture("registry_v2.json", &path);
+
+    AppState::new(Config {
+        registry: Some(path.clone()),
+        ..Config::default()
+    })
+    .unwrap();
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn newer_registry_is_refused() {
+    let dir = temp_dir("registry-v3");
+    let path = dir.join("hosts.json");
+    let original = copy_fixture("registry_v3.json", &path);
+
+    let error = AppState::new(Config {
+        registry: Some(path.clone()),
+        ..Config::default()
+    })
+    .err()
+    .unwrap();
+
+    let message = format!("{error:#}");
+    assert!(message.contains("is version 3"), "{message}");
+    assert!(message.contains("newer than the version 2"), "{message}");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_version_is_refused() {
+    let dir = temp_dir("registry-invalid");
+    let path = dir.join("hosts.json");
+    std::fs::write(&path, r#"{"version": "two", "hosts": []}"#).unwrap();
+
+    let error = AppState::new(Config {
+        registry: Some(path.clone()),
+        ..Config::default()
+    })
+    .err()
+    .unwrap();
+
+    assert!(
+        format!("{error:#}").contains("invalid version"),
+        "{error:#}"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn schedules_from_version_1() {
+    let dir = temp_dir("schedules-v1");
+    let path = dir.join("schedules.json");
+    let original = copy_fixture("schedules_v1.json", &path);
+
+    AppState::new(Config {
+        schedules_file: Some(path.clone()),
+        ..Config::default()
+    })
+    .unwrap();
+
+    assert_eq!(
+        read_json(&path),
+        json!({"version": 2, "schedules": [
+            {"id": "0badf00d", "host": "nas", "cron": "0 7 * * 1-5"},
+            {"id": "deadbeef", "host": "pc", "at": "2999-01-01T00:00:00Z", "paused": true},
+        ]})
+    );
+    let backup = dir.join("schedules.json.v1.bak");
+    assert_eq!(std::fs::read_to_string(backup).unwrap(), original);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stats_from_version_1() {
+    let dir = temp_dir("stats-v1");
+    let path = dir.join("hosts.stats.json");
+    let original = copy_fixture("stats_v1.json", &path);
+
+    AppState::new(Config {
+        registry: Some(dir.join("hosts.json")),
+        ..Config::default()
+    })
+    .unwrap();
+
+    assert_eq!(
+        read_json(&path),
+        json!({"version": 2, "macs": {"a8:a1:59:0e:7b:02": {
+            "attempts": 3,
+            "verified": 2,
+            "timeouts": 1,
+            "boot_times": [41.5, 38.0],
+        }}})
+    );
+    let backup = dir.join("hosts.stats.json.v1.bak");
+    assert_eq!(std::fs::read_to_string(backup).unwrap(), original);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn tokens_from_version_1() {
+    let dir = temp_dir("tokens-v1");
+    let path = dir.join("tokens.json");
+    let original = copy_fixture("tokens_v1.json", &path);
+
+    AppState::new(Config {
+        wake_tokens_file: Some(path.clone()),
+        ..Config::default()
+    })
+    .unwrap();
+
+    let mut expected: Value = serde_json::from_str(&original).unwrap();
+    expected["version"] = json!(2);
+    assert_eq!(read_json(&path), expected);
+    let backup = dir.join("tokens.json.v1.bak");
+    assert_eq!(std::fs::read_to_string(backup).unwrap(), original);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
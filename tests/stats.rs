@@ -0,0 +1,174 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{receiving, test_app};
+use http_body_util::BodyExt;
+use serde::de::DeserializeOwned;
+use std::{
+    net::{TcpListener, UdpSocket},
+    path::Path,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{Host, HostStats},
+    config::{Config, StaticHost},
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    verify::Strategy,
+    MacAddress,
+};
+
+/// `nas` is up at 127.0.0.1 once something listens on `port`, `pc` is never discovered.
+fn nas_and_pc(registry: &Path, port: u16) -> (AppState, UdpSocket) {
+    let (state, receiver) = receiving(Config {
+        registry: Some(registry.to_owned()),
+        hosts: vec![
+            StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap(),
+            StaticHost::new("pc", ["00:d8:61:ca:3a:18"]).unwrap(),
+        ],
+        verify: vec![Strategy::Tcp(port)],
+        ..Config::default()
+    });
+    let state = state.with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]));
+    (state, receiver)
+}
+
+async fn wake(app: &Router, host: &str) {
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(r#"{{"host": "{host}"}}"#)))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+}
+
+async fn get<T: DeserializeOwned>(app: &Router, path: &str) -> (StatusCode, Option<T>) {
+    let request = Request::get(path).body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).ok())
+}
+
+#[tokio::test]
+async fn verified_and_timed_out() {
+    let dir = std::env::temp_dir();
+    let registry = dir.join(format!("wakeonlan-stats-{}.json", std::process::id()));
+    let stats_file = dir.join(format!("wakeonlan-stats-{}.stats.json", std::process::id()));
+    let _ = std::fs::remove_file(&stats_file);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (state, _receiver) = nas_and_pc(&registry, port);
+    let app = test_app(state);
+
+    let (status, stats) = get::<HostStats>(&app, "/hosts/nas/stats").await;
+    assert_eq!(status, StatusCode::OK);
+    let stats = stats.unwrap();
+    assert_eq!(stats.stats.attempts, 0);
+    assert!(stats.macs.is_empty());
+
+    wake(&app, "nas").await;
+    let (status, _) = get::<serde_json::Value>(&app, "/hosts/nas/wait-online?timeout=5").await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, stats) = get::<HostStats>(&app, "/hosts/nas/stats").await;
+    let stats = stats.unwrap();
+    assert_eq!(stats.stats.attempts, 1);
+    assert_eq!(stats.stats.verified, 1);
+    assert_eq!(stats.stats.success_rate, Some(1.0));
+    assert!(stats.stats.median_time_to_online.unwrap() < 5.0);
+    assert_eq!(stats.macs[0].mac, "a8:a1:59:0e:7b:02");
+
+    wake(&app, "pc").await;
+    let (status, _) = get::<serde_json::Value>(&app, "/hosts/pc/wait-online?timeout=1").await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    let (_, stats) = get::<HostStats>(&app, "/hosts/pc/stats").await;
+    let stats = stats.unwrap().stats;
+    assert_eq!((stats.attempts, stats.verified, stats.timeouts), (1, 0, 1));
+    assert_eq!(stats.success_rate, Some(0.0));
+    assert_eq!(stats.median_time_to_online, None);
+
+    // they're kept across restarts, and listed with the hosts
+    let (state, _receiver) = nas_and_pc(&registry, port);
+    let app = test_app(state);
+    let (_, hosts) = get::<Vec<Host>>(&app, "/hosts").await;
+    let hosts = hosts.unwrap();
+    let nas = hosts.iter().find(|host| host.name == "nas").unwrap();
+    assert_eq!(nas.stats.as_ref().unwrap().verified, 1);
+    let pc = hosts.iter().find(|host| host.name == "pc").unwrap();
+    assert_eq!(pc.stats.as_ref().unwrap().timeouts, 1);
+
+    let (status, _) = get::<HostStats>(&app, "/hosts/nope/stats").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    std::fs::remove_file(&stats_file).unwrap();
+}
+
+async fn metrics(app: &Router) -> String {
+    let request = Request::get("/metrics").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/plain; version=0.0.4"
+    );
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn time_to_online_metrics() {
+    let dir = std::env::temp_dir();
+    let registry = dir.join(format!("wakeonlan-metrics-{}.json", std::process::id()));
+    let stats_file = dir.join(format!(
+        "wakeonlan-metrics-{}.stats.json",
+        std::process::id()
+    ));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let (state, _receiver) = nas_and_pc(&registry, listener.local_addr().unwrap().port());
+    let app = test_app(state);
+
+    // waiting without a wake doesn't count
+    let (status, _) = get::<serde_json::Value>(&app, "/hosts/nas/wait-online?timeout=5").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!metrics(&app).await.contains("host=\"nas\""));
+
+    wake(&app, "nas").await;
+    let (status, _) = get::<serde_json::Value>(&app, "/hosts/nas/wait-online?timeout=5").await;
+    assert_eq!(status, StatusCode::OK);
+    wake(&app, "pc").await;
+    let (status, _) = get::<serde_json::Value>(&app, "/hosts/pc/wait-online?timeout=1").await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+
+    let metrics = metrics(&app).await;
+    let lines = metrics.lines().collect::<Vec<_>>();
+    for line in [
+        "# TYPE wol_time_to_online_seconds histogram",
+        r#"wol_time_to_online_seconds_bucket{host="nas",le="10"} 1"#,
+        r#"wol_time_to_online_seconds_bucket{host="nas",le="300"} 1"#,
+        r#"wol_time_to_online_seconds_bucket{host="nas",le="+Inf"} 1"#,
+        r#"wol_time_to_online_seconds_count{host="nas"} 1"#,
+        "# TYPE wol_verify_timeouts_total counter",
+        r#"wol_verify_timeouts_total{host="pc"} 1"#,
+    ] {
+        assert!(lines.contains(&line), "{line} missing in {metrics}");
+    }
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with(r#"wol_time_to_online_seconds_sum{host="nas"} "#)));
+    // pc never came up, nas never timed out
+    assert!(!metrics.contains(r#"wol_time_to_online_seconds_count{host="pc"}"#));
+    assert!(!metrics.contains(r#"wol_verify_timeouts_total{host="nas"}"#));
+    std::fs::remove_file(&stats_file).unwrap();
+}
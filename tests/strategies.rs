@@ -0,0 +1,247 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{ErrorResponse, Host, WakeResponse},
+    config::{self, Config, StaticHost, Via, WakeStrategy},
+    discovery::{HostDiscovery, HostEntry},
+    retry::RetryPolicy,
+    server::{AppState, PacketSender},
+    verify::Strategy,
+    MacAddress, MagicPacket,
+};
+
+const MAC: MacAddress = MacAddress([0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]);
+
+/// Ignores UDP, and only comes up (at 127.0.0.1) once it got a raw frame.
+#[derive(Clone)]
+struct Stubborn {
+    up: Arc<AtomicBool>,
+    /// Whether anything at all can be sent.
+    sends: bool,
+}
+
+impl PacketSender for Stubborn {
+    fn send(&self, _: &MagicPacket, _: SocketAddr, _: Option<&str>) -> io::Result<SocketAddr> {
+        if !self.sends {
+            return Err(io::Error::from_raw_os_error(libc::ENETUNREACH));
+        }
+        Ok("127.0.0.1:40000".parse().unwrap())
+    }
+
+    fn send_frame(&self, packet: &MagicPacket, _: &str) -> io::Result<()> {
+        if !self.sends {
+            return Err(io::Error::from_raw_os_error(libc::EPERM));
+        }
+        assert_eq!(packet.mac(), MAC);
+        self.up.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+impl HostDiscovery for Stubborn {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        if !self.up.load(Ordering::SeqCst) {
+            return Ok(Vec::new());
+        }
+        Ok(vec![HostEntry {
+            name: "127.0.0.1".to_owned(),
+            ip: Some("127.0.0.1".parse().unwrap()),
+            mac: MAC,
+            named_by: None,
+            state: None,
+            vlan: None,
+        }])
+    }
+}
+
+fn strategy(via: Via, destination: Option<&str>, interface: Option<&str>) -> WakeStrategy {
+    WakeStrategy {
+        via,
+        destination: destination.map(|destination| destination.parse().unwrap()),
+        interface: interface.map(str::to_owned),
+        ready: None,
+        timeout: 1,
+    }
+}
+
+fn state(sends: bool) -> AppState {
+    let stubborn = Stubborn {
+        up: Arc::new(AtomicBool::new(false)),
+        sends,
+    };
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![StaticHost {
+            strategies: vec![
+                strategy(Via::Udp, Some("192.168.1.255:9"), None),
+                strategy(Via::Udp, None, None),
+                WakeStrategy {
+                    timeout: 5,
+                    ..strategy(Via::Raw, None, Some("eth0"))
+                },
+            ],
+            ..StaticHost::new("workstation", ["00:d8:61:ca:3a:18"]).unwrap()
+        }],
+        // refused still means it's up
+        verify: vec![Strategy::Tcp(1)],
+        retry: RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        },
+        ..Config::default()
+    })
+    .unwrap()
+    .with_sender(stubborn.clone())
+    .with_discovery(stubborn)
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+fn wake() -> Request<Body> {
+    Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"host": "workstation"}"#))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn falls_back() {
+    let app = test_app(state(true));
+
+    let (status, body) = send(&app, wake()).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let response: WakeResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response.strategy.as_deref(), Some("raw on eth0"));
+    assert_eq!(response.online, Some(true));
+    let tried = response
+        .strategies
+        .iter()
+        .map(|attempt| {
+            let error = attempt.error.as_deref();
+            (
+                attempt.strategy.as_str(),
+                attempt.sent,
+                attempt.online,
+                error,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tried,
+        [
+            (
+                "udp to 192.168.1.255:9",
+                true,
+                false,
+                Some("not online after 1s")
+            ),
+            ("udp", true, false, Some("not online after 1s")),
+            ("raw on eth0", true, true, None),
+        ]
+    );
+    let addresses = response
+        .destinations
+        .iter()
+        .map(|destination| destination.address.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(addresses, ["192.168.1.255:9", "127.0.0.1:9"]);
+
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    let (_, body) = send(&app, request).await;
+    let hosts: Vec<Host> = serde_json::from_slice(&body).unwrap();
+    let last_wake = hosts[0].last_wake.clone().unwrap();
+    assert_eq!(last_wake.id, response.id);
+    assert_eq!(last_wake.strategy.as_deref(), Some("raw on eth0"));
+}
+
+#[tokio::test]
+async fn nothing_sent() {
+    let app = test_app(state(false));
+
+    let (status, body) = send(&app, wake()).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let response: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert!(
+        response
+            .error
+            .starts_with("no strategy got a packet out: udp to 192.168.1.255:9: failed to send"),
+        "{}",
+        response.error
+    );
+    assert!(
+        response.error.ends_with(
+            "raw on eth0: failed to send raw frame: 00:d8:61:ca:3a:18: Operation not permitted \
+             (os error 1)"
+        ),
+        "{}",
+        response.error
+    );
+}
+
+#[test]
+fn problems() {
+    let path =
+        std::env::temp_dir().join(format!("wakeonlan-strategies-{}.toml", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"
+verify = []
+
+[[hosts]]
+name = "workstation"
+mac = "00:d8:61:ca:3a:18"
+strategies = [
+    { via = "udp", destination = "192.168.1.255:9", timeout = 0 },
+    { via = "raw", destination = "192.168.1.255:9" },
+]
+"#,
+    )
+    .unwrap();
+    let problems = config::check_file(&path).unwrap_err().0;
+    let keys = problems
+        .iter()
+        .map(|problem| (problem.key.as_str(), problem.message.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        keys,
+        [
+            (
+                "hosts[0].strategies[0].timeout",
+                "no host comes up that fast"
+            ),
+            (
+                "hosts[0].strategies[1].destination",
+                "a raw frame goes to the broadcast MAC, not to an address"
+            ),
+            (
+                "verify",
+                "`workstation` has strategies, without it (or a `ready` for each) it can't be \
+                 told whether one woke the host"
+            ),
+        ]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
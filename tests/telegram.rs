@@ -0,0 +1,122 @@
+#![cfg(feature = "server")]
+
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, UdpSocket},
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+use wakeonlan::{
+    config::{Config, StaticHost, TelegramConfig},
+    server::{AppState, Telegram},
+};
+
+/// Reads one request, returning its path and body.
+fn read_request(reader: &mut impl BufRead) -> (String, Value) {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let path = request_line.split_whitespace().nth(1).unwrap().to_owned();
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+        if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+            content_length = value.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+    (path, serde_json::from_slice(&body).unwrap())
+}
+
+/// A Bot API server that has `updates` for the first poll and none after that,
+/// sending every request it gets to `requests`.
+fn fake_bot_api(listener: TcpListener, updates: Value, requests: mpsc::Sender<(String, Value)>) {
+    let mut updates = Some(updates);
+    for stream in listener.incoming() {
+        let mut reader = BufReader::new(stream.unwrap());
+        let (path, body) = read_request(&mut reader);
+        let result = if path.ends_with("/getUpdates") {
+            updates.take().unwrap_or_else(|| {
+                std::thread::sleep(Duration::from_millis(50));
+                json!([])
+            })
+        } else {
+            json!({})
+        };
+        if requests.send((path, body)).is_err() {
+            return;
+        }
+        let response = json!({"ok": true, "result": result}).to_string();
+        write!(
+            reader.into_inner(),
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{response}",
+            response.len()
+        )
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn wakes_for_allowed_chats() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let telegram = TelegramConfig {
+        token: "123:secret".into(),
+        allowed_chats: vec![1],
+        api_url: format!("http://{}", listener.local_addr().unwrap()),
+    };
+    let state = AppState::new(Config {
+        broadcast: receiver.local_addr().unwrap(),
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        ..Config::default()
+    })
+    .unwrap();
+
+    let (requests_tx, requests) = mpsc::channel();
+    let updates = json!([
+        {"update_id": 1, "message": {"chat": {"id": 2}, "text": "/wake nas"}},
+        {"update_id": 2, "message": {"chat": {"id": 1}, "text": "/wake@wol_bot nas"}},
+        {"update_id": 3, "message": {"chat": {"id": 1}, "text": "good morning"}},
+    ]);
+    std::thread::spawn(move || fake_bot_api(listener, updates, requests_tx));
+    tokio::spawn(Telegram::new(telegram).run(Arc::new(state)));
+
+    let requests = tokio::task::spawn_blocking(move || {
+        let timeout = Duration::from_secs(5);
+        let first_poll = requests.recv_timeout(timeout).unwrap();
+        let reply = requests.recv_timeout(timeout).unwrap();
+        let second_poll = requests.recv_timeout(timeout).unwrap();
+        (first_poll, reply, second_poll)
+    })
+    .await
+    .unwrap();
+    let ((first_path, _), (reply_path, reply), (_, second_poll)) = requests;
+
+    assert_eq!(first_path, "/bot123:secret/getUpdates");
+    assert_eq!(reply_path, "/bot123:secret/sendMessage");
+    assert_eq!(reply["chat_id"], 1);
+    let text = reply["text"].as_str().unwrap();
+    assert!(
+        text.starts_with("Sent magic packet to nas (a8:a1:59:0e:7b:02)"),
+        "{text}"
+    );
+    assert_eq!(second_poll["offset"], 4);
+
+    let mut buf = [0; 200];
+    assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+    receiver
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    assert!(
+        receiver.recv(&mut buf).is_err(),
+        "woke for a chat that isn't allowed"
+    );
+}
@@ -0,0 +1,217 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{receiving, test_app};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use std::path::Path;
+use tower::ServiceExt;
+use wakeonlan::{
+    config::{Config, StaticHost},
+    sign::sha256,
+};
+
+fn config(path: &Path) -> Config {
+    Config {
+        token: Some("hunter2".into()),
+        hosts: vec![StaticHost::new("pc", ["00:11:22:33:44:55"]).unwrap()],
+        wake_tokens_file: Some(path.to_owned()),
+        ..Config::default()
+    }
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn authorized(request: axum::http::request::Builder) -> axum::http::request::Builder {
+    request.header(header::AUTHORIZATION, "Bearer hunter2")
+}
+
+async fn create(app: &Router, host: &str) -> (StatusCode, Value) {
+    let request = authorized(Request::post("/tokens"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(format!(
+            r#"{{"host": "{host}", "expires_in": 3600}}"#
+        )))
+        .unwrap();
+    send(app, request).await
+}
+
+async fn redeem(app: &Router, path: &str) -> StatusCode {
+    let request = Request::get(path).body(Body::empty()).unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn single_use() {
+    let path = std::env::temp_dir().join(format!("wakeonlan-tokens-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (state, receiver) = receiving(config(&path));
+    let app = test_app(state);
+
+    assert_eq!(create(&app, "nas").await.0, StatusCode::NOT_FOUND);
+    let (status, created) = create(&app, "pc").await;
+    assert_eq!(status, StatusCode::CREATED);
+    let token = created["token"].as_str().unwrap();
+    let wake_path = created["path"].as_str().unwrap();
+    assert_eq!(wake_path, format!("/wake-token/{token}"));
+    // only the hash is saved
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(!saved.contains(token));
+
+    let (status, listed) = send(
+        &app,
+        authorized(Request::get("/tokens"))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert_eq!(listed[0]["id"], created["id"]);
+    assert!(listed[0].get("token").is_none());
+
+    // only one of the concurrent requests gets to wake
+    let statuses = redeem_concurrently(&app, wake_path).await;
+    assert_eq!(
+        statuses
+            .iter()
+            .filter(|&&status| status == StatusCode::ACCEPTED)
+            .count(),
+        1
+    );
+    assert!(statuses
+        .iter()
+        .all(|&status| status == StatusCode::ACCEPTED || status == StatusCode::GONE));
+    let mut buf = [0; 200];
+    assert_eq!(receiver.recv(&mut buf).unwrap(), 102);
+    assert_eq!(redeem(&app, wake_path).await, StatusCode::GONE);
+    assert_eq!(
+        redeem(&app, "/wake-token/0123456789abcdef").await,
+        StatusCode::NOT_FOUND
+    );
+
+    // it's still used up after a restart
+    let (state, _receiver) = receiving(config(&path));
+    let app = test_app(state);
+    assert_eq!(redeem(&app, wake_path).await, StatusCode::GONE);
+    std::fs::remove_file(&path).unwrap();
+}
+
+async fn redeem_concurrently(app: &Router, path: &str) -> Vec<StatusCode> {
+    let tasks = (0..8)
+        .map(|_| {
+            let app = app.clone();
+            let path = path.to_owned();
+            tokio::spawn(async move { redeem(&app, &path).await })
+        })
+        .collect::<Vec<_>>();
+    let mut statuses = Vec::new();
+    for task in tasks {
+        statuses.push(task.await.unwrap());
+    }
+    statuses
+}
+
+#[tokio::test]
+async fn revoke() {
+    let path = std::env::temp_dir().join(format!(
+        "wakeonlan-tokens-revoke-{}.json",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let (state, _receiver) = receiving(config(&path));
+    let app = test_app(state);
+
+    let (_, created) = create(&app, "pc").await;
+    let id = created["id"].as_str().unwrap();
+    let delete = |id: String| {
+        authorized(Request::delete(format!("/tokens/{id}")))
+            .body(Body::empty())
+            .unwrap()
+    };
+    assert_eq!(
+        send(&app, delete(id.to_owned())).await.0,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        send(&app, delete(id.to_owned())).await.0,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        redeem(&app, created["path"].as_str().unwrap()).await,
+        StatusCode::NOT_FOUND
+    );
+
+    let unauthorized = Request::get("/tokens").body(Body::empty()).unwrap();
+    assert_eq!(send(&app, unauthorized).await.0, StatusCode::UNAUTHORIZED);
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// A token file with one token for `pc`, `token`, that expired `ago`.
+fn expired_token(path: &Path, token: &str, ago: chrono::TimeDelta) {
+    let now = chrono::Utc::now();
+    let hash: String = sha256(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let document = json!({"version": 2, "tokens": [{
+        "id": "0123456789abcdef0123456789abcdef",
+        "hash": hash,
+        "host": "pc",
+        "created": now - ago - chrono::TimeDelta::hours(1),
+        "expires": now - ago,
+        "consumed": null,
+    }]});
+    std::fs::write(path, document.to_string()).unwrap();
+}
+
+#[tokio::test]
+async fn expired() {
+    let path = std::env::temp_dir().join(format!(
+        "wakeonlan-tokens-expired-{}.json",
+        std::process::id()
+    ));
+    let token = "00112233445566778899aabbccddeeff";
+
+    expired_token(&path, token, chrono::TimeDelta::hours(1));
+    let (state, _receiver) = receiving(config(&path));
+    let app = test_app(state);
+    let wake_path = format!("/wake-token/{token}");
+    assert_eq!(redeem(&app, &wake_path).await, StatusCode::GONE);
+
+    // long enough ago, it's forgotten
+    expired_token(&path, token, chrono::TimeDelta::days(8));
+    let (state, _receiver) = receiving(config(&path));
+    let app = test_app(state);
+    assert_eq!(redeem(&app, &wake_path).await, StatusCode::NOT_FOUND);
+    let saved: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved["tokens"], json!([]));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn ids_are_random() {
+    let path =
+        std::env::temp_dir().join(format!("wakeonlan-tokens-ids-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let (state, _receiver) = receiving(config(&path));
+    let app = test_app(state);
+
+    let (_, first) = create(&app, "pc").await;
+    let (_, second) = create(&app, "pc").await;
+    let first = first["id"].as_str().unwrap();
+    assert_eq!(first.len(), 32);
+    assert_ne!(first, second["id"].as_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+}
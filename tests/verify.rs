@@ -0,0 +1,13 @@
+#![cfg(feature = "server")]
+
+use wakeonlan::verify::Strategy;
+
+#[test]
+fn parse_strategies() {
+    assert_eq!("arp".parse(), Ok(Strategy::Arp));
+    assert_eq!("icmp".parse(), Ok(Strategy::Icmp));
+    assert_eq!("tcp:22".parse(), Ok(Strategy::Tcp(22)));
+    assert!("tcp".parse::<Strategy>().is_err());
+    assert!("tcp:ssh".parse::<Strategy>().is_err());
+    assert_eq!(Strategy::Tcp(3389).to_string(), "tcp:3389");
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use std::net::TcpListener;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{ErrorResponse, Host, WakeResponse},
+    config::Config,
+    discovery::{HostEntry, StaticDiscovery},
+    server::AppState,
+    verify::Strategy,
+    MacAddress,
+};
+
+/// `nas` is up at 127.0.0.1, but nothing configured can tell.
+fn state() -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        verify: Vec::new(),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]))
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+async fn wake(app: &Router, body: String) -> (StatusCode, Vec<u8>) {
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    send(app, request).await
+}
+
+#[tokio::test]
+async fn waits_with_it() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let app = test_app(state());
+
+    let body = format!(r#"{{"host": "nas", "verify": {{"tcp": {port}, "timeout": 5}}}}"#);
+    let (status, body) = wake(&app, body).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let response: WakeResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response.online, Some(true));
+    assert_eq!(response.probe, Some(Strategy::Tcp(port)));
+
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    let (_, body) = send(&app, request).await;
+    let hosts: Vec<Host> = serde_json::from_slice(&body).unwrap();
+    let last_wake = hosts[0].last_wake.clone().unwrap();
+    assert_eq!(last_wake.id, response.id);
+    assert_eq!(last_wake.probe, Some(Strategy::Tcp(port)));
+
+    // without it, the host can't be told to be up
+    let (status, body) = wake(&app, r#"{"host": "nas", "wait_online": 1}"#.to_owned()).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let response: WakeResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response.online, Some(false));
+    assert_eq!(response.probe, None);
+}
+
+#[tokio::test]
+async fn nonsense() {
+    let app = test_app(state());
+    let cases = [
+        (
+            r#"{"tcp": 22, "ping": true}"#,
+            Some("`verify` has either `tcp` or `ping`, not both"),
+        ),
+        (r#"{"timeout": 30}"#, Some("`verify` needs `tcp` or `ping`")),
+        (
+            r#"{"tcp": 0}"#,
+            Some("`verify.tcp` has to be a port, not 0"),
+        ),
+        (
+            r#"{"ping": true, "timeout": 0}"#,
+            Some("`verify.timeout` has to be between 1 and 600 seconds"),
+        ),
+        (
+            r#"{"tcp": 8006, "timeout": 601}"#,
+            Some("`verify.timeout` has to be between 1 and 600 seconds"),
+        ),
+        (r#"{"tcp": 65536}"#, None),
+        (r#"{"udp": 9}"#, None),
+    ];
+    for (verify, message) in cases {
+        let (status, body) = wake(&app, format!(r#"{{"host": "nas", "verify": {verify}}}"#)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{verify}");
+        if let Some(message) = message {
+            let response: ErrorResponse = serde_json::from_slice(&body).unwrap();
+            assert_eq!(response.error, message);
+        }
+    }
+}
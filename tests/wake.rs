@@ -0,0 +1,583 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::{receiving, test_app};
+use http_body_util::BodyExt;
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{ErrorResponse, MacSource, ResolvedName, WakeResponse},
+    config::{Config, StaticHost},
+    discovery::{HostDiscovery, HostEntry, StaticDiscovery},
+    retry::RetryPolicy,
+    server::{AppState, PacketSender},
+    MacAddress, MagicPacket,
+};
+
+async fn post_wake(app: Router, content_type: &str, body: &str) -> (StatusCode, String, String) {
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let content_type = response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (
+        status,
+        content_type,
+        String::from_utf8(body.to_vec()).unwrap(),
+    )
+}
+
+fn assert_received(receiver: &UdpSocket, mac: [u8; 6]) {
+    let mut buf = [0; 200];
+    let len = receiver.recv(&mut buf).unwrap();
+    assert_eq!(len, 102);
+    assert_eq!(buf[..6], [0xFF; 6]);
+    assert_eq!(buf[6..12], mac);
+}
+
+#[tokio::test]
+async fn json_mac() {
+    let (state, receiver) = receiving(Config::default());
+    let app = test_app(state);
+    let (status, content_type, body) =
+        post_wake(app, "application/json", r#"{"mac": "00:d8:61:ca:3a:18"}"#).await;
+
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(content_type, "application/json");
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["mac"], "00:d8:61:ca:3a:18");
+    assert_eq!(
+        body["destinations"][0]["address"],
+        receiver.local_addr().unwrap().to_string()
+    );
+    assert_eq!(body["destinations"][0]["sent"], true);
+    assert!(body["destinations"][0]["source"].is_string());
+    assert_received(&receiver, [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]);
+}
+
+#[tokio::test]
+async fn json_dry_run() {
+    let (state, receiver) = receiving(Config::default());
+    let app = test_app(state);
+    let (status, _, body) = post_wake(
+        app,
+        "application/json",
+        r#"{"mac": "00:d8:61:ca:3a:18", "dry_run": true}"#,
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["dry_run"], true);
+    assert_eq!(
+        body["destinations"][0]["address"],
+        receiver.local_addr().unwrap().to_string()
+    );
+    assert_eq!(body["destinations"][0]["sent"], false);
+
+    receiver.set_nonblocking(true).unwrap();
+    assert!(
+        receiver.recv(&mut [0; 200]).is_err(),
+        "dry run sent a packet"
+    );
+}
+
+#[tokio::test]
+async fn form_mac() {
+    let (state, receiver) = receiving(Config::default());
+    let app = test_app(state);
+    let (status, content_type, body) = post_wake(
+        app,
+        "application/x-www-form-urlencoded",
+        "host=&mac=00%3Ad8%3A61%3Aca%3A3a%3A18",
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert!(content_type.starts_with("text/html"), "{content_type}");
+    assert!(body.contains("00:d8:61:ca:3a:18"), "{body}");
+    assert!(body.contains(r#"<a href="/">"#), "{body}");
+    assert_received(&receiver, [0x00, 0xd8, 0x61, 0xca, 0x3a, 0x18]);
+}
+
+#[tokio::test]
+async fn json_invalid_mac() {
+    let (state, _receiver) = receiving(Config::default());
+    let app = test_app(state);
+    let (status, content_type, body) =
+        post_wake(app, "application/json", r#"{"mac": "not a mac"}"#).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(content_type, "application/json");
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert!(body["error"].as_str().unwrap().contains("not a mac"));
+}
+
+#[tokio::test]
+async fn form_invalid_mac() {
+    let (state, _receiver) = receiving(Config::default());
+    let app = test_app(state);
+    let (status, content_type, body) =
+        post_wake(app, "application/x-www-form-urlencoded", "mac=nope").await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(content_type.starts_with("text/html"), "{content_type}");
+    assert!(body.contains("nope"), "{body}");
+    assert!(body.contains(r#"<a href="/">"#), "{body}");
+}
+
+#[tokio::test]
+async fn unsupported_content_type() {
+    let (state, _receiver) = receiving(Config::default());
+    let app = test_app(state);
+    let (status, content_type, _) = post_wake(app, "text/plain", "mac=nope").await;
+
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(content_type, "application/json");
+}
+
+#[tokio::test]
+async fn host_with_several_macs() {
+    let (state, receiver) = receiving(Config {
+        hosts: vec![
+            StaticHost::new("workstation", ["00:11:22:33:44:55", "3c:7c:3f:1d:aa:09"]).unwrap(),
+        ],
+        ..Config::default()
+    });
+    let app = test_app(state);
+    let (status, _, body) = post_wake(app, "application/json", r#"{"host": "workstation"}"#).await;
+
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        body["macs"],
+        serde_json::json!(["00:11:22:33:44:55", "3c:7c:3f:1d:aa:09"])
+    );
+    assert_eq!(body["destinations"][0]["mac"], "00:11:22:33:44:55");
+    assert_eq!(body["destinations"][1]["mac"], "3c:7c:3f:1d:aa:09");
+    assert_received(&receiver, [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+    assert_received(&receiver, [0x3c, 0x7c, 0x3f, 0x1d, 0xaa, 0x09]);
+}
+
+#[tokio::test]
+async fn secure_on_password() {
+    let (state, receiver) = receiving(Config {
+        hosts: vec![StaticHost {
+            password: Some("a1:b2:c3:d4".parse().unwrap()),
+            ..StaticHost::new("old-nas", ["00:11:22:33:44:55"]).unwrap()
+        }],
+        ..Config::default()
+    });
+    let app = test_app(state);
+    let (status, _, _) = post_wake(app, "application/json", r#"{"host": "old-nas"}"#).await;
+
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let mut buf = [0; 200];
+    let len = receiver.recv(&mut buf).unwrap();
+    let packet = MagicPacket::parse(&buf[..len]).unwrap();
+    assert_eq!(
+        packet.mac(),
+        MacAddress([0x00, 0x11, 0x22, 0x33, 0x44, 0x55])
+    );
+    assert_eq!(packet.password_len(), Some(4));
+    assert_eq!(buf[102..len], [0xa1, 0xb2, 0xc3, 0xd4]);
+}
+
+#[tokio::test]
+async fn wait_online_times_out() {
+    let (state, _receiver) = receiving(Config {
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        ..Config::default()
+    });
+    let app = test_app(state);
+    let wait = |host: &str| {
+        let request = Request::get(format!("/hosts/{host}/wait-online?timeout=0"))
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request)
+    };
+    assert_eq!(
+        wait("laptop").await.unwrap().status(),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        wait("nas").await.unwrap().status(),
+        StatusCode::GATEWAY_TIMEOUT
+    );
+}
+
+#[tokio::test]
+async fn default_host() {
+    let (state, _receiver) = receiving(Config::default());
+    let app = test_app(state);
+    let (status, _, body) = post_wake(app, "application/x-www-form-urlencoded", "host=&mac=").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body.contains("no default_host configured"), "{body}");
+
+    let (state, receiver) = receiving(Config {
+        default_host: Some("nas".to_owned()),
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        ..Config::default()
+    });
+    let app = test_app(state);
+    let request = Request::get("/").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let page = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8(page.to_vec())
+        .unwrap()
+        .contains("wakes <b>nas</b>"));
+
+    let (status, _, _) = post_wake(app, "application/x-www-form-urlencoded", "host=&mac=").await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_received(&receiver, [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+}
+
+#[tokio::test]
+async fn host_interface() {
+    let (state, receiver) = receiving(Config {
+        interface: Some("nope0".to_owned()),
+        hosts: vec![
+            StaticHost {
+                interface: Some("lo".to_owned()),
+                ..StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()
+            },
+            StaticHost::new("pc", ["00:d8:61:ca:3a:18"]).unwrap(),
+        ],
+        ..Config::default()
+    });
+    let app = test_app(state);
+
+    let (status, _, body) = post_wake(app.clone(), "application/json", r#"{"host": "nas"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{body}");
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["destinations"][0]["interface"], "lo");
+    assert_received(&receiver, [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+
+    // everything else leaves on the configured one, which doesn't exist
+    let (status, _, body) = post_wake(app.clone(), "application/json", r#"{"host": "pc"}"#).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["error"], "no network interface named `nope0`");
+
+    let request = Request::get("/hosts").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let hosts = response.into_body().collect().await.unwrap().to_bytes();
+    let hosts: serde_json::Value = serde_json::from_slice(&hosts).unwrap();
+    assert_eq!(hosts[0]["interface"], "lo");
+    assert_eq!(hosts[1]["interface"], "nope0");
+}
+
+/// Fails every send with the OS error.
+struct FailingSender(i32);
+
+impl PacketSender for FailingSender {
+    fn send(&self, _: &MagicPacket, _: SocketAddr, _: Option<&str>) -> io::Result<SocketAddr> {
+        Err(io::Error::from_raw_os_error(self.0))
+    }
+
+    fn local_addr(&self, _: Option<&str>) -> Option<SocketAddr> {
+        Some("192.168.1.5:40000".parse().unwrap())
+    }
+}
+
+#[tokio::test]
+async fn send_error_hints() {
+    let cases = [
+        (libc::EPERM, "broadcast not permitted"),
+        (libc::EACCES, "broadcast not permitted"),
+        (libc::ENETUNREACH, "no route to 192.168.1.255:9"),
+        (
+            libc::EADDRNOTAVAIL,
+            "source address 192.168.1.5 is not on any interface",
+        ),
+    ];
+    for (errno, hint) in cases {
+        let state = AppState::new(Config {
+            broadcast: "192.168.1.255:9".parse().unwrap(),
+            retry: RetryPolicy {
+                max_attempts: 1,
+                ..RetryPolicy::default()
+            },
+            ..Config::default()
+        })
+        .unwrap()
+        .with_sender(FailingSender(errno));
+        let app = test_app(state);
+
+        let (status, _, body) =
+            post_wake(app, "application/json", r#"{"mac": "00:d8:61:ca:3a:18"}"#).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let error = body["error"].as_str().unwrap();
+        // both what the OS said and what to do about it
+        let os_error = io::Error::from_raw_os_error(errno).to_string();
+        assert!(error.contains(&os_error), "{error}");
+        assert!(error.contains(hint), "{error}");
+    }
+}
+
+/// Fails sends on the interfaces that are down, like a flapping NIC.
+struct FlappingSender(&'static [&'static str]);
+
+impl PacketSender for FlappingSender {
+    fn send(
+        &self,
+        _: &MagicPacket,
+        _: SocketAddr,
+        interface: Option<&str>,
+    ) -> io::Result<SocketAddr> {
+        if self.0.contains(&interface.unwrap()) {
+            return Err(io::Error::from_raw_os_error(libc::ENETDOWN));
+        }
+        Ok("192.168.1.5:40000".parse().unwrap())
+    }
+}
+
+#[tokio::test]
+async fn interface_failover() {
+    let config = Config {
+        broadcast: "192.168.1.255:9".parse().unwrap(),
+        failover: vec!["lo".to_owned(), "eth7".to_owned()],
+        retry: RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        },
+        ..Config::default()
+    };
+    let state = AppState::new(config.clone())
+        .unwrap()
+        .with_sender(FlappingSender(&["lo"]));
+    let app = test_app(state);
+    let down = io::Error::from_raw_os_error(libc::ENETDOWN).to_string();
+
+    let (status, _, body) = post_wake(
+        app.clone(),
+        "application/json",
+        r#"{"mac": "00:d8:61:ca:3a:18"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let destination = &body["destinations"][0];
+    assert_eq!(destination["sent"], true);
+    assert_eq!(destination["interface"], "eth7");
+    assert_eq!(destination["attempts"], 2);
+    assert_eq!(destination["skipped"][0]["interface"], "lo");
+    assert_eq!(destination["skipped"][0]["error"], *down);
+
+    // counted with the interface it failed on
+    let request = Request::get("/api/v1/network").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let network: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let lo = network["interfaces"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|interface| interface["name"] == "lo")
+        .unwrap();
+    assert_eq!(lo["failovers"], 1);
+
+    // failing on the last one fails the wake, with the ones before it
+    let state = AppState::new(config)
+        .unwrap()
+        .with_sender(FlappingSender(&["lo", "eth7"]));
+    let app = test_app(state);
+    let (status, _, body) =
+        post_wake(app, "application/json", r#"{"mac": "00:d8:61:ca:3a:18"}"#).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(body.contains(&down), "{body}");
+}
+
+async fn index_page(app: &Router) -> String {
+    let request = Request::get("/").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let page = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(page.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn custom_index_page() {
+    let path = std::env::temp_dir().join(format!("wakeonlan-index-{}.html", std::process::id()));
+    let (state, _receiver) = receiving(Config {
+        default_host: Some("nas".to_owned()),
+        hosts: vec![StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap()],
+        index_page: path.clone(),
+        ..Config::default()
+    });
+    let app = test_app(state);
+    let built_in = index_page(&app).await;
+    assert!(built_in.contains("<title>"), "{built_in}");
+
+    std::fs::write(&path, "<p>{{default_host}}</p><ul>{{hosts}}</ul>").unwrap();
+    let page = index_page(&app).await;
+    assert!(page.starts_with("<p>wakes <b>nas</b>"), "{page}");
+    assert!(page.contains("<li><b>nas</b>"), "{page}");
+
+    // it's read again once it changes
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_len(0).unwrap();
+    std::io::Write::write_all(&mut &file, b"<p>changed</p>").unwrap();
+    file.set_modified(std::time::SystemTime::now() + Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(index_page(&app).await, "<p>changed</p>");
+    std::fs::remove_file(&path).unwrap();
+
+    // one that can't be read doesn't break the page
+    std::fs::create_dir(&path).unwrap();
+    assert_eq!(index_page(&app).await, built_in);
+    std::fs::remove_dir(&path).unwrap();
+}
+
+#[tokio::test]
+async fn discovered_host() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let state = AppState::new(Config {
+        broadcast: receiver.local_addr().unwrap(),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "laptop.lan".to_owned(),
+        ip: None,
+        mac: MacAddress([0x3c, 0x7c, 0x3f, 0x1d, 0xaa, 0x09]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]));
+    let app = test_app(state);
+
+    let (status, _, _) = post_wake(app, "application/json", r#"{"host": "laptop"}"#).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_received(&receiver, [0x3c, 0x7c, 0x3f, 0x1d, 0xaa, 0x09]);
+}
+
+/// In the neighbor table from the second time it's read on, like a host that answered a poke.
+#[derive(Default)]
+struct Appearing(AtomicUsize);
+
+impl HostDiscovery for Appearing {
+    fn discover(&self) -> eyre::Result<Vec<HostEntry>> {
+        if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+            return Ok(Vec::new());
+        }
+        Ok(vec![HostEntry {
+            name: "nas".to_owned(),
+            ip: Some("127.0.0.1".parse().unwrap()),
+            mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+            named_by: None,
+            state: None,
+            vlan: None,
+        }])
+    }
+}
+
+fn app_discovering(receiver: &UdpSocket, discovery: impl HostDiscovery + 'static) -> Router {
+    let state = AppState::new(Config {
+        broadcast: receiver.local_addr().unwrap(),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(discovery);
+    test_app(state)
+}
+
+#[tokio::test]
+async fn host_by_dns_name() {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    receiver
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let nas = StaticDiscovery(vec![HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]);
+
+    // an address is a name that resolves to itself, and the table doesn't know it by that name
+    let (status, _, body) = post_wake(
+        app_discovering(&receiver, nas),
+        "application/json",
+        r#"{"host": "127.0.0.1"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{body}");
+    let response: WakeResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        response.resolved,
+        Some(ResolvedName {
+            ip: "127.0.0.1".parse().unwrap(),
+            mac_source: MacSource::NeighborTable,
+        })
+    );
+    assert_eq!(response.mac, "a8:a1:59:0e:7b:02");
+    assert_received(&receiver, [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+
+    let (status, _, body) = post_wake(
+        app_discovering(&receiver, Appearing::default()),
+        "application/json",
+        r#"{"host": "127.0.0.1"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{body}");
+    let response: WakeResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        response.resolved.unwrap().mac_source,
+        MacSource::NeighborRefresh
+    );
+    assert_received(&receiver, [0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]);
+
+    let (status, _, body) = post_wake(
+        app_discovering(&receiver, StaticDiscovery(Vec::new())),
+        "application/json",
+        r#"{"host": "127.0.0.2"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let error: ErrorResponse = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        error.error,
+        "host `127.0.0.2` resolves to 127.0.0.2, but no MAC is known for it, not even after \
+         asking the network"
+    );
+
+    let (status, _, body) = post_wake(
+        app_discovering(&receiver, StaticDiscovery(Vec::new())),
+        "application/json",
+        r#"{"host": "nope.invalid"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let error: ErrorResponse = serde_json::from_str(&body).unwrap();
+    assert!(
+        error
+            .error
+            .starts_with("host `nope.invalid` not found, and its name doesn't resolve: "),
+        "{}",
+        error.error
+    );
+}
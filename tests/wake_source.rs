@@ -0,0 +1,119 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use common::test_app;
+use http_body_util::BodyExt;
+use std::time::Duration;
+use tower::ServiceExt;
+use wakeonlan::{
+    api::v1::{BatchWakeResponse, Host, WakeResponse},
+    config::{Config, SequenceStep, StaticHost, WakeSequence},
+    discovery::StaticDiscovery,
+    server::AppState,
+};
+
+fn state() -> AppState {
+    AppState::new(Config {
+        broadcast: "127.0.0.1:9".parse().unwrap(),
+        hosts: vec![
+            StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap(),
+            StaticHost::new("pc", ["00:d8:61:ca:3a:18"]).unwrap(),
+        ],
+        sequences: vec![WakeSequence {
+            name: "lab".to_owned(),
+            steps: vec![SequenceStep {
+                host: "pc".to_owned(),
+                ready: None,
+                timeout: 5,
+            }],
+            continue_on_failure: false,
+        }],
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(Vec::new()))
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, body.to_vec())
+}
+
+async fn last_wake_source(app: &Router, host: &str) -> Option<String> {
+    let request = Request::get("/api/v1/hosts").body(Body::empty()).unwrap();
+    let (_, body) = send(app, request).await;
+    let hosts: Vec<Host> = serde_json::from_slice(&body).unwrap();
+    let host = hosts.into_iter().find(|entry| entry.name == host).unwrap();
+    Some(host.last_wake?.source)
+}
+
+#[tokio::test]
+async fn api_and_page() {
+    let app = test_app(state());
+
+    let request = Request::post("/api/v1/wake")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"host": "nas"}"#))
+        .unwrap();
+    let (status, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let response: WakeResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response.source, "api");
+    assert_eq!(last_wake_source(&app, "nas").await.as_deref(), Some("api"));
+
+    let request = Request::post("/wake")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("host=nas"))
+        .unwrap();
+    let (status, _) = send(&app, request).await;
+    assert!(status.is_success(), "{status}");
+    assert_eq!(last_wake_source(&app, "nas").await.as_deref(), Some("ui"));
+
+    let request = Request::post("/api/v1/wake/batch")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"hosts": ["nas", "pc"]}"#))
+        .unwrap();
+    let (status, body) = send(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    let response: BatchWakeResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(response.source, "api");
+
+    let request = Request::get("/metrics").body(Body::empty()).unwrap();
+    let (_, metrics) = send(&app, request).await;
+    let metrics = String::from_utf8(metrics).unwrap();
+    assert!(
+        metrics.contains("wol_wake_attempts_total{source=\"api\",outcome=\"sent\"} 3\n"),
+        "{metrics}"
+    );
+    assert!(
+        metrics.contains("wol_wake_attempts_total{source=\"ui\",outcome=\"sent\"} 1\n"),
+        "{metrics}"
+    );
+}
+
+#[tokio::test]
+async fn sequence() {
+    let app = test_app(state());
+
+    let request = Request::post("/wake-sequence/lab")
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = send(&app, request).await;
+    assert!(status.is_success(), "{status}");
+    for _ in 0..50 {
+        if let Some(source) = last_wake_source(&app, "pc").await {
+            assert_eq!(source, "sequence:lab");
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("the sequence didn't wake the host");
+}
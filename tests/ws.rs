@@ -0,0 +1,255 @@
+#![cfg(feature = "server")]
+
+use std::{
+    net::{SocketAddr, TcpListener as StdTcpListener, UdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use wakeonlan::{
+    config::{Config, StaticHost},
+    discovery::{HostEntry, StaticDiscovery},
+    server::{self, AppState},
+    verify::Strategy,
+    MacAddress,
+};
+
+/// `nas` is up at 127.0.0.1 once something listens on `port`, `pc` is never discovered.
+async fn start_server(port: u16, token: Option<&str>) -> (SocketAddr, Arc<AppState>, UdpSocket) {
+    let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let state = AppState::new(Config {
+        broadcast: receiver.local_addr().unwrap(),
+        hosts: vec![
+            StaticHost::new("nas", ["a8:a1:59:0e:7b:02"]).unwrap(),
+            StaticHost::new("pc", ["00:d8:61:ca:3a:18"]).unwrap(),
+        ],
+        verify: vec![Strategy::Tcp(port)],
+        token: token.map(Into::into),
+        ..Config::default()
+    })
+    .unwrap()
+    .with_discovery(StaticDiscovery(vec![HostEntry {
+        name: "nas".to_owned(),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        mac: MacAddress([0xa8, 0xa1, 0x59, 0x0e, 0x7b, 0x02]),
+        named_by: None,
+        state: None,
+        vlan: None,
+    }]));
+    let state = Arc::new(state);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = server::router(state.clone());
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+    });
+    (addr, state, receiver)
+}
+
+/// Returns the head of the response and the stream if it was upgraded.
+async fn connect(addr: SocketAddr, headers: &str) -> (String, TcpStream) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "GET /api/v1/ws HTTP/1.1\r\nHost: {addr}\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n{headers}\r\n"
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        head.push(stream.read_u8().await.unwrap());
+    }
+    (String::from_utf8(head).unwrap(), stream)
+}
+
+async fn send(stream: &mut TcpStream, opcode: u8, payload: &[u8]) {
+    assert!(payload.len() < 126);
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+    frame.extend_from_slice(&mask);
+    frame.extend(
+        payload
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4]),
+    );
+    stream.write_all(&frame).await.unwrap();
+}
+
+/// The opcode and payload of the next frame.
+async fn receive(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let read = async {
+        let head = [
+            stream.read_u8().await.unwrap(),
+            stream.read_u8().await.unwrap(),
+        ];
+        assert_eq!(head[0] & 0x80, 0x80, "fragmented");
+        assert_eq!(head[1] & 0x80, 0, "masked");
+        let len = match head[1] & 0x7f {
+            126 => usize::from(stream.read_u16().await.unwrap()),
+            127 => stream.read_u64().await.unwrap() as usize,
+            len => usize::from(len),
+        };
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload).await.unwrap();
+        (head[0] & 0x0f, payload)
+    };
+    tokio::time::timeout(Duration::from_secs(10), read)
+        .await
+        .unwrap()
+}
+
+async fn event(stream: &mut TcpStream) -> serde_json::Value {
+    let (opcode, payload) = receive(stream).await;
+    assert_eq!(opcode, 1);
+    serde_json::from_slice(&payload).unwrap()
+}
+
+#[tokio::test]
+async fn wake_and_watch() {
+    let up = StdTcpListener::bind("127.0.0.1:0").unwrap();
+    let (addr, state, _receiver) = start_server(up.local_addr().unwrap().port(), None).await;
+    let (head, mut ws) = connect(addr, "").await;
+    assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+    // the example from RFC 6455
+    assert!(
+        head.to_ascii_lowercase()
+            .contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo=\r\n"),
+        "{head}"
+    );
+
+    send(
+        &mut ws,
+        1,
+        br#"{"action":"wake","id":"a","host":"nas","wait_online":5}"#,
+    )
+    .await;
+    let sent = event(&mut ws).await;
+    assert_eq!(sent["event"], "sent");
+    assert_eq!(sent["id"], "a");
+    assert_eq!(sent["wake"]["host"], "nas");
+    let probing = event(&mut ws).await;
+    assert_eq!(
+        (&probing["event"], &probing["id"]),
+        (&"probing".into(), &"a".into())
+    );
+    assert_eq!(probing["timeout"], 5);
+    let online = event(&mut ws).await;
+    assert_eq!(
+        (&online["event"], &online["id"]),
+        (&"online".into(), &"a".into())
+    );
+    assert_eq!(
+        online["strategy"],
+        format!("tcp:{}", up.local_addr().unwrap().port())
+    );
+
+    // several at once, each with its own id
+    send(
+        &mut ws,
+        1,
+        br#"{"action":"wake","id":"b","host":"pc","wait_online":1}"#,
+    )
+    .await;
+    send(&mut ws, 1, br#"{"action":"wake","id":"c","host":"nope"}"#).await;
+    send(
+        &mut ws,
+        1,
+        br#"{"action":"wake","id":"d","host":"nas","dry_run":true}"#,
+    )
+    .await;
+    send(&mut ws, 9, b"hi").await;
+    let mut events = Vec::new();
+    while events.len() < 6 {
+        let (opcode, payload) = receive(&mut ws).await;
+        if opcode == 10 {
+            assert_eq!(payload, b"hi");
+            events.push(serde_json::json!({"event": "pong"}));
+            continue;
+        }
+        events.push(serde_json::from_slice(&payload).unwrap());
+    }
+    let of = |id: &str| {
+        events
+            .iter()
+            .filter(|event| event["id"] == id)
+            .map(|event| event["event"].as_str().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(of("b"), ["sent", "probing", "timeout"]);
+    assert_eq!(of("c"), ["error"]);
+    assert_eq!(of("d"), ["sent"]);
+    let error = events.iter().find(|event| event["id"] == "c").unwrap();
+    assert_eq!(error["status"], 404);
+    assert_eq!(error["error"], "host `nope` not found");
+
+    send(&mut ws, 1, br#"{"action":"cancel","id":"a"}"#).await;
+    let cancel = event(&mut ws).await;
+    assert_eq!(cancel["event"], "error");
+    assert_eq!(cancel["status"], 404);
+
+    // the server going away closes it
+    let shut_down = tokio::spawn(async move { state.shut_down().await });
+    let (opcode, payload) = receive(&mut ws).await;
+    assert_eq!(opcode, 8);
+    assert_eq!(payload[..2], 1001u16.to_be_bytes());
+    send(&mut ws, 8, &payload[..2]).await;
+    tokio::time::timeout(Duration::from_millis(500), shut_down)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn cancel() {
+    let (addr, _state, _receiver) = start_server(1, None).await;
+    let (_, mut ws) = connect(addr, "").await;
+    send(&mut ws, 1, br#"{"action":"wake","id":"a","host":"pc"}"#).await;
+    assert_eq!(event(&mut ws).await["event"], "sent");
+    assert_eq!(event(&mut ws).await["event"], "probing");
+    send(&mut ws, 1, br#"{"action":"wake","id":"a","host":"pc"}"#).await;
+    let conflict = event(&mut ws).await;
+    assert_eq!(conflict["status"], 409);
+    send(&mut ws, 1, br#"{"action":"cancel","id":"a"}"#).await;
+    let cancelled = event(&mut ws).await;
+    assert_eq!(
+        (&cancelled["event"], &cancelled["id"]),
+        (&"cancelled".into(), &"a".into())
+    );
+
+    send(&mut ws, 1, b"{").await;
+    let invalid = event(&mut ws).await;
+    assert_eq!(invalid["status"], 400);
+    assert!(invalid["id"].is_null());
+
+    send(&mut ws, 8, &1000u16.to_be_bytes()).await;
+    let (opcode, payload) = receive(&mut ws).await;
+    assert_eq!((opcode, &payload[..]), (8, &1000u16.to_be_bytes()[..]));
+}
+
+#[tokio::test]
+async fn authenticated_like_the_api() {
+    let (addr, _state, _receiver) = start_server(1, Some("secret")).await;
+    let (head, _) = connect(addr, "").await;
+    assert!(head.starts_with("HTTP/1.1 401"), "{head}");
+    let (head, _) = connect(addr, "Authorization: Bearer secret\r\n").await;
+    assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+
+    let (head, _) = connect(
+        addr,
+        "Authorization: Bearer secret\r\nOrigin: http://evil.example\r\n",
+    )
+    .await;
+    assert!(head.starts_with("HTTP/1.1 403"), "{head}");
+    let (head, _) = connect(
+        addr,
+        &format!("Authorization: Bearer secret\r\nOrigin: http://{addr}\r\n"),
+    )
+    .await;
+    assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+}